@@ -49,6 +49,30 @@ fn bench_decode(c: &mut Criterion) {
     group.finish();
 }
 
+/// Benchmark borrowed-slice decoding (one copy) against owned-buffer decoding (no copy)
+fn bench_decode_copy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode_16k");
+
+    let message = Message::new(MessageType::Call, vec![0u8; 16384]);
+    let bytes = bytes::Bytes::from(mxp::protocol::encode(&message));
+
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function("slice", |b| {
+        b.iter(|| {
+            let decoded = black_box(Message::decode(black_box(&bytes)).unwrap());
+            black_box(decoded);
+        });
+    });
+    group.bench_function("bytes", |b| {
+        b.iter(|| {
+            let decoded = black_box(Message::decode_bytes(black_box(bytes.clone())).unwrap());
+            black_box(decoded);
+        });
+    });
+
+    group.finish();
+}
+
 /// Benchmark full roundtrip (encode + decode)
 fn bench_roundtrip(c: &mut Criterion) {
     let mut group = c.benchmark_group("roundtrip");
@@ -101,7 +125,7 @@ fn bench_header_decode(c: &mut Criterion) {
 
 /// Benchmark checksum calculation
 fn bench_checksum(c: &mut Criterion) {
-    use xxhash_rust::xxh3::xxh3_64;
+    use mxp::protocol::checksum;
 
     let mut group = c.benchmark_group("checksum");
 
@@ -111,12 +135,21 @@ fn bench_checksum(c: &mut Criterion) {
         group.throughput(Throughput::Bytes(size as u64));
         group.bench_with_input(BenchmarkId::from_parameter(size), &data, |b, d| {
             b.iter(|| {
-                let checksum = black_box(xxh3_64(d));
+                let checksum = black_box(checksum(d));
                 black_box(checksum);
             });
         });
     }
 
+    for size in [64, 1024, 16384] {
+        let data = vec![0u8; size];
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("crc32c", size), &data, |b, d| {
+            b.iter(|| black_box(mxp::protocol::crc32c(d)));
+        });
+    }
+
     group.finish();
 }
 
@@ -158,6 +191,7 @@ criterion_group!(
     benches,
     bench_encode,
     bench_decode,
+    bench_decode_copy,
     bench_roundtrip,
     bench_header_encode,
     bench_header_decode,
@@ -1,19 +1,380 @@
 //! Transport layer performance benchmarks
 //!
-//! NOTE: Currently disabled as transport APIs are not fully public yet.
-//! Will be enabled in a future release when transport modules are stabilized.
+//! Covers the per-packet hot paths: flow-control accounting, congestion control updates,
+//! ChaCha20-Poly1305 sealing/opening and its comparison with AES-256-GCM, buffer pool acquire/release under contention, handing
+//! received datagrams to the message decoder, moving a megabyte through a stream and
+//! reading it back, and ACK processing with a large number of packets in flight.
 
-use criterion::{Criterion, criterion_group, criterion_main};
+use std::sync::Barrier;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
-/// Placeholder benchmark - transport benchmarks coming soon
-fn bench_placeholder(c: &mut Criterion) {
-    c.bench_function("transport_placeholder", |b| {
+use criterion::{
+    BatchSize, BenchmarkId, Criterion, Throughput, black_box, criterion_group, criterion_main,
+};
+use mxp::transport::{
+    AckFrame, AckRange, AeadCipher, AeadEncryptor, AeadKey, AeadNonce, BufferPool,
+    CongestionControl, CongestionController, EndpointRole, FlowController, HashAlgorithm,
+    LossConfig, LossManager, StreamId, StreamKind, StreamManager, chacha20_poly1305_open,
+    chacha20_poly1305_seal,
+};
+use mxp::{Message, MessageType};
+
+/// Benchmark consuming and releasing flow-control credit
+fn bench_flow_control(c: &mut Criterion) {
+    let mut controller = FlowController::with_stream_default(1_000_000, 100_000);
+    let stream = StreamId::from_raw(0);
+
+    c.bench_function("flow_control_consume_release", |b| {
+        b.iter(|| {
+            controller
+                .consume(stream, black_box(1200))
+                .expect("window available");
+            controller.release(stream, black_box(1200));
+        });
+    });
+}
+
+/// Benchmark per-packet congestion controller updates
+fn bench_congestion_control(c: &mut Criterion) {
+    let mut controller = CongestionController::default();
+    let rtt = Duration::from_millis(20);
+
+    c.bench_function("congestion_send_ack", |b| {
+        b.iter(|| {
+            controller.on_packet_sent(black_box(1200));
+            controller.on_ack(black_box(1200), SystemTime::now(), rtt);
+            black_box(controller.can_send(1200));
+        });
+    });
+
+    c.bench_function("congestion_send_loss", |b| {
+        b.iter(|| {
+            controller.on_packet_sent(black_box(1200));
+            controller.on_loss(black_box(1200));
+        });
+    });
+}
+
+/// Benchmark AEAD sealing and opening of packet payloads
+fn bench_aead(c: &mut Criterion) {
+    let key = AeadKey::from_array([0x42; 32]);
+    let nonce = AeadNonce::from_array([0x24; 12]);
+    let aad = [0u8; 16];
+    let mut group = c.benchmark_group("chacha20_poly1305");
+
+    for size in [64, 512, 1200, 1500] {
+        let payload = vec![0xAB; size];
+        let sealed = chacha20_poly1305_seal(&key, &nonce, &payload, &aad);
+
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::new("seal", size), &payload, |b, payload| {
+            b.iter(|| black_box(chacha20_poly1305_seal(&key, &nonce, payload, &aad)));
+        });
+        group.bench_with_input(BenchmarkId::new("open", size), &sealed, |b, sealed| {
+            b.iter(|| black_box(chacha20_poly1305_open(&key, &nonce, sealed, &aad).expect("open")));
+        });
+    }
+
+    // Allocation-free baseline: encrypt a full-MTU packet in place, as the packet path can.
+    let mut packet = vec![0xAB; 1500];
+    group.throughput(Throughput::Bytes(1500));
+    group.bench_function(BenchmarkId::new("seal_in_place", 1500), |b| {
         b.iter(|| {
-            // Placeholder - will add real transport benchmarks when APIs are public
-            1 + 1
+            let mut encryptor = AeadEncryptor::new(&key, &nonce, &aad);
+            encryptor.update(black_box(&mut packet));
+            black_box(encryptor.finalize())
         });
     });
+
+    group.finish();
+}
+
+/// Benchmark each negotiable AEAD suite on the same packet sizes
+fn bench_aead_suites(c: &mut Criterion) {
+    let key = AeadKey::from_array([0x42; 32]);
+    let nonce = AeadNonce::from_array([0x24; 12]);
+    let aad = [0u8; 16];
+    let mut group = c.benchmark_group("aead_suites");
+
+    for (name, suite) in [
+        ("chacha20_poly1305", AeadCipher::ChaCha20Poly1305),
+        ("aes_256_gcm", AeadCipher::Aes256Gcm),
+    ] {
+        for size in [64, 1200] {
+            let payload = vec![0xAB; size];
+            let (ciphertext, tag) = suite.seal(&key, &nonce, &payload, &aad);
+
+            group.throughput(Throughput::Bytes(size as u64));
+            group.bench_with_input(
+                BenchmarkId::new(format!("{name}/seal"), size),
+                &payload,
+                |b, payload| b.iter(|| black_box(suite.seal(&key, &nonce, payload, &aad))),
+            );
+            group.bench_with_input(
+                BenchmarkId::new(format!("{name}/open"), size),
+                &ciphertext,
+                |b, ciphertext| {
+                    b.iter(|| {
+                        black_box(
+                            suite
+                                .open(&key, &nonce, ciphertext, &aad, &tag)
+                                .expect("open"),
+                        )
+                    });
+                },
+            );
+        }
+    }
+
+    group.finish();
+}
+
+/// Benchmark transcript/KDF hash throughput for each supported algorithm
+fn bench_hash(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hash");
+
+    for size in [64, 1024, 16384] {
+        let data = vec![0x5A; size];
+        group.throughput(Throughput::Bytes(size as u64));
+        for (name, hash) in [
+            ("sha256", HashAlgorithm::Sha256),
+            ("blake3", HashAlgorithm::Blake3),
+        ] {
+            group.bench_with_input(BenchmarkId::new(name, size), &data, |b, data| {
+                b.iter(|| black_box(hash.digest(data)));
+            });
+        }
+    }
+
+    group.finish();
+}
+
+/// Time `iters` acquire/release cycles on each of `threads` threads sharing `pool`.
+fn contended_cycles(pool: &BufferPool, threads: usize, iters: u64) -> Duration {
+    let barrier = Barrier::new(threads);
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    barrier.wait();
+                    let start = Instant::now();
+                    for _ in 0..iters {
+                        black_box(pool.acquire());
+                    }
+                    start.elapsed()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("worker panicked"))
+            .max()
+            .unwrap_or_default()
+    })
+}
+
+/// Benchmark contended buffer acquire/release with and without thread-local caching
+fn bench_buffer_pool(c: &mut Criterion) {
+    let threads = thread::available_parallelism().map_or(4, |n| n.get().min(8));
+    let mut group = c.benchmark_group("buffer_pool_contended");
+
+    for (name, local_cache) in [("shared", 0), ("local_cache", 16)] {
+        let pool = BufferPool::new(1500, 256).with_local_cache(local_cache);
+        group.bench_function(BenchmarkId::new(name, threads), |b| {
+            b.iter_custom(|iters| contended_cycles(&pool, threads, iters));
+        });
+    }
+
+    group.finish();
+}
+
+/// Benchmark decoding a received 16 KB datagram by copying it out of the pooled buffer
+/// versus freezing the buffer into `Bytes`
+fn bench_buffer_handoff(c: &mut Criterion) {
+    let frame = Message::new(MessageType::Call, vec![0x5A; 16 * 1024]).encode();
+    let pool = BufferPool::new(frame.len(), 4);
+    let receive = || {
+        // Stands in for the socket writing the datagram into a fresh lease.
+        let mut buffer = pool.acquire();
+        buffer.as_mut_slice()[..frame.len()].copy_from_slice(&frame);
+        buffer.set_len(frame.len());
+        buffer
+    };
+    let mut group = c.benchmark_group("buffer_handoff_16k");
+    group.throughput(Throughput::Bytes(frame.len() as u64));
+
+    group.bench_function("copy", |b| {
+        b.iter(|| {
+            let buffer = receive();
+            black_box(Message::decode(buffer.as_slice()).expect("valid frame"))
+        });
+    });
+    group.bench_function("freeze", |b| {
+        b.iter(|| black_box(Message::decode_bytes(receive().freeze()).expect("valid frame")));
+    });
+
+    group.finish();
+}
+
+/// Benchmark a 1 MB transfer through a stream: queue, cut into 1200-byte chunks,
+/// acknowledge, ingest on the peer and read back
+fn bench_stream_transfer(c: &mut Criterion) {
+    const TRANSFER: usize = 1024 * 1024;
+    const CHUNK: usize = 1200;
+    let payload = vec![0xA5; TRANSFER];
+    let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+    let mut group = c.benchmark_group("stream_transfer_1m");
+    group.throughput(Throughput::Bytes(TRANSFER as u64));
+
+    group.bench_function("send_ingest_read", |b| {
+        b.iter(|| {
+            let mut sender = StreamManager::new(EndpointRole::Client);
+            let mut receiver = StreamManager::new(EndpointRole::Server);
+            sender.get_or_create(id);
+            sender.set_connection_limit(u64::MAX / 2);
+            sender.set_stream_limit(id, u64::MAX / 2);
+            sender.queue_send(id, &payload).expect("queue");
+            sender.finish(id).expect("finish");
+            while let Some(chunk) = sender.poll_send_chunk(id, CHUNK).expect("flow") {
+                let len = chunk.payload.len() as u64;
+                receiver
+                    .ingest(id, chunk.offset, &chunk.payload, chunk.fin)
+                    .expect("ingest");
+                sender
+                    .on_chunk_acked(id, chunk.offset, len, chunk.fin)
+                    .expect("ack");
+            }
+            let mut read = 0;
+            while read < TRANSFER {
+                read += receiver.read(id, 64 * 1024).expect("read").len();
+            }
+            black_box(read)
+        });
+    });
+
+    group.finish();
+}
+
+/// Benchmark draining 1 MB of received stream data: copied into a `Vec` per read, copied
+/// into a reused buffer, or handed out as `Bytes` views of the received chunks
+fn bench_stream_read(c: &mut Criterion) {
+    const TRANSFER: usize = 1024 * 1024;
+    const CHUNK: usize = 1200;
+    const READ: usize = 64 * 1024;
+    let payload = vec![0x5A; TRANSFER];
+    let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+    let filled = || {
+        let mut receiver = StreamManager::new(EndpointRole::Server);
+        for (index, chunk) in payload.chunks(CHUNK).enumerate() {
+            receiver
+                .ingest(id, (index * CHUNK) as u64, chunk, false)
+                .expect("ingest");
+        }
+        receiver
+    };
+    let mut group = c.benchmark_group("stream_read_1m");
+    group.throughput(Throughput::Bytes(TRANSFER as u64));
+
+    group.bench_function("read_vec", |b| {
+        b.iter_batched(
+            filled,
+            |mut receiver| {
+                let mut read = 0;
+                while read < TRANSFER {
+                    read += black_box(receiver.read(id, READ).expect("read")).len();
+                }
+                receiver
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
+    group.bench_function("read_into", |b| {
+        let mut scratch = vec![0u8; READ];
+        b.iter_batched(
+            filled,
+            |mut receiver| {
+                let mut read = 0;
+                while read < TRANSFER {
+                    read += receiver.read_into(id, &mut scratch).expect("read");
+                    black_box(&scratch);
+                }
+                receiver
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
+    group.bench_function("read_bytes", |b| {
+        b.iter_batched(
+            filled,
+            |mut receiver| {
+                let mut read = 0;
+                while read < TRANSFER {
+                    read += black_box(receiver.read_bytes(id, READ).expect("read")).len();
+                }
+                receiver
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
+    group.finish();
+}
+
+/// Benchmark ACK processing with 100k packets outstanding: a run of cumulative ACKs, each
+/// acknowledging the next 64 packets, as at a high bandwidth-delay product
+fn bench_loss_ack(c: &mut Criterion) {
+    const OUTSTANDING: u64 = 100_000;
+    const ACKS: u64 = 16;
+    const PER_ACK: u64 = 64;
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+    let filled = || {
+        let mut manager = LossManager::new(LossConfig::default());
+        for packet_number in 0..OUTSTANDING {
+            let sent = start + Duration::from_micros(packet_number);
+            manager.on_packet_sent(packet_number, sent, 1200, true);
+        }
+        manager
+    };
+    let frames: Vec<AckFrame> = (1..=ACKS)
+        .map(|idx| {
+            let largest = idx * PER_ACK - 1;
+            let range = AckRange::new(0, largest).expect("range");
+            AckFrame::new(largest, Duration::ZERO, vec![range]).expect("ack frame")
+        })
+        .collect();
+    let now = start + Duration::from_millis(150);
+
+    let mut group = c.benchmark_group("loss_ack_100k_outstanding");
+    group.throughput(Throughput::Elements(ACKS));
+    group.bench_function("cumulative_acks", |b| {
+        b.iter_batched(
+            filled,
+            |mut manager| {
+                for frame in &frames {
+                    black_box(manager.on_ack_frame(frame, now));
+                }
+                manager
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    group.finish();
 }
 
-criterion_group!(benches, bench_placeholder);
+criterion_group!(
+    benches,
+    bench_flow_control,
+    bench_congestion_control,
+    bench_aead,
+    bench_aead_suites,
+    bench_hash,
+    bench_buffer_pool,
+    bench_buffer_handoff,
+    bench_stream_transfer,
+    bench_stream_read,
+    bench_loss_ack
+);
 criterion_main!(benches);
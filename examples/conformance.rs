@@ -0,0 +1,331 @@
+//! MXP conformance runner.
+//!
+//! Sends a scripted series of messages to a peer speaking plaintext MXP over UDP (one encoded
+//! message per datagram) and checks how it answers. Decode failures must come back as `Error`
+//! messages whose payload starts with the little-endian `ErrorCode`, and the peer must keep
+//! serving afterwards.
+//!
+//! Run against a peer with `cargo run --example conformance -- <addr>`, or with
+//! `-- --self-test` to check the in-tree echo responder on a loopback port. Prints one PASS or
+//! FAIL line per check and exits non-zero if any check fails.
+
+use std::env;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mxp::protocol::{HEADER_SIZE, checksum};
+use mxp::transport::Buffer;
+use mxp::{
+    ErrorCode, Flags, MAX_PAYLOAD_SIZE, Message, MessageHeader, MessageType, TransportHandle,
+};
+
+#[path = "support/responder.rs"]
+mod responder;
+
+/// How long to wait for an expected reply
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long the peer must stay quiet when no reply is expected
+const SILENCE_WINDOW: Duration = Duration::from_millis(200);
+/// Socket read timeout; bounds how often deadlines are checked
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+type Outcome = Result<(), String>;
+type Check = fn(&mut Client) -> Outcome;
+
+const CHECKS: &[(&str, Check)] = &[
+    ("magic-number-rejection", check_bad_magic),
+    ("checksum-corruption", check_corrupt_checksum),
+    ("oversized-payload", check_oversized_payload),
+    ("unknown-message-type", check_unknown_type),
+    ("call-response-correlation", check_call_response),
+    ("heartbeat-ack", check_heartbeat_ack),
+    ("requires-ack-flag", check_requires_ack),
+];
+
+fn main() -> ExitCode {
+    let target = match env::args().nth(1).as_deref() {
+        Some("--self-test") => spawn_echo(),
+        Some(addr) => addr.parse::<SocketAddr>().map_err(|err| err.to_string()),
+        None => Err("usage: conformance <addr> | --self-test".to_string()),
+    };
+    let target = match target {
+        Ok(target) => target,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::from(2);
+        }
+    };
+    let mut client = match Client::connect(target) {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("failed to bind client socket: {err}");
+            return ExitCode::from(2);
+        }
+    };
+
+    println!("MXP conformance against {target}");
+    let mut failed = 0;
+    for (name, check) in CHECKS {
+        match check(&mut client) {
+            Ok(()) => println!("PASS  {name}"),
+            Err(reason) => {
+                println!("FAIL  {name}: {reason}");
+                failed += 1;
+            }
+        }
+    }
+    println!("{}/{} checks passed", CHECKS.len() - failed, CHECKS.len());
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Start the echo responder on a loopback port and return its address
+fn spawn_echo() -> Result<SocketAddr, String> {
+    let loopback = SocketAddr::from(([127, 0, 0, 1], 0));
+    let handle = responder::bind(loopback, None).map_err(|err| err.to_string())?;
+    let addr = handle
+        .local_addr()
+        .map_err(|err| responder::into_io(err).to_string())?;
+    thread::spawn(move || {
+        if let Err(err) = responder::serve(&handle) {
+            eprintln!("echo responder stopped: {err}");
+        }
+    });
+    Ok(addr)
+}
+
+/// Client socket talking to the peer under test
+struct Client {
+    handle: TransportHandle,
+    target: SocketAddr,
+    buffer: Buffer,
+}
+
+impl Client {
+    fn connect(target: SocketAddr) -> std::io::Result<Self> {
+        let local = if target.is_ipv4() {
+            SocketAddr::from(([0, 0, 0, 0], 0))
+        } else {
+            SocketAddr::from(([0u16; 8], 0))
+        };
+        let handle = responder::bind(local, Some(POLL_INTERVAL))?;
+        let buffer = handle.acquire_buffer();
+        Ok(Self {
+            handle,
+            target,
+            buffer,
+        })
+    }
+
+    fn send_raw(&self, datagram: &[u8]) -> Outcome {
+        self.handle
+            .send(datagram, self.target)
+            .map(drop)
+            .map_err(|err| format!("send failed: {}", responder::into_io(err)))
+    }
+
+    fn send(&self, message: &Message) -> Outcome {
+        self.send_raw(&message.encode())
+    }
+
+    /// Wait up to `timeout` for a reply from the target matching `wanted`
+    ///
+    /// Replies that do not match are skipped, so stragglers from earlier checks cannot
+    /// satisfy later ones; replies that fail to decode fail the check.
+    fn wait_for(
+        &mut self,
+        timeout: Duration,
+        wanted: impl Fn(&Message) -> bool,
+    ) -> Result<Option<Message>, String> {
+        let deadline = Instant::now() + timeout;
+        while Instant::now() < deadline {
+            let peer = match self.handle.receive(&mut self.buffer) {
+                Ok((_, peer)) => peer,
+                Err(err) => {
+                    let err = responder::into_io(err);
+                    if responder::is_timeout(&err) {
+                        continue;
+                    }
+                    return Err(format!("receive failed: {err}"));
+                }
+            };
+            if peer != self.target {
+                continue;
+            }
+            let reply = Message::decode(self.buffer.as_slice())
+                .map_err(|err| format!("peer sent an undecodable reply: {err}"))?;
+            if wanted(&reply) {
+                return Ok(Some(reply));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Wait for a reply matching `wanted`, failing the check on timeout
+    fn expect(&mut self, what: &str, wanted: impl Fn(&Message) -> bool) -> Result<Message, String> {
+        self.wait_for(REPLY_TIMEOUT, wanted)?
+            .ok_or_else(|| format!("no {what} within {REPLY_TIMEOUT:?}"))
+    }
+
+    /// Wait for an `Error` reply and check its code
+    fn expect_error(&mut self, code: ErrorCode) -> Outcome {
+        let reply = self.expect("Error reply", |msg| {
+            msg.message_type() == Some(MessageType::Error)
+        })?;
+        let payload = reply.payload();
+        let Some(raw) = payload.get(..2) else {
+            return Err(format!("Error payload too short ({} bytes)", payload.len()));
+        };
+        let found = u16::from_le_bytes([raw[0], raw[1]]);
+        if found == code.as_u16() {
+            Ok(())
+        } else {
+            Err(format!(
+                "expected error code {:#06x} ({code:?}), got {found:#06x} ({:?})",
+                code.as_u16(),
+                ErrorCode::from_u16(found)
+            ))
+        }
+    }
+
+    /// Check that a `Call` still gets its `Response`
+    fn expect_alive(&mut self, message_id: u64) -> Outcome {
+        self.send(&Message::with_ids(
+            MessageType::Call,
+            message_id,
+            message_id,
+            b"still there?".to_vec(),
+        ))?;
+        self.expect("Response after the rejected datagram", |msg| {
+            msg.message_type() == Some(MessageType::Response) && msg.message_id() == message_id
+        })
+        .map(drop)
+    }
+}
+
+/// Replace the trailer with a valid XXH3 checksum over the rest of `frame`
+fn reseal(frame: &mut [u8]) {
+    let body = frame.len() - 8;
+    let sum = checksum(&frame[..body]);
+    frame[body..].copy_from_slice(&sum.to_le_bytes());
+}
+
+fn check_bad_magic(client: &mut Client) -> Outcome {
+    client.send_raw(&[0xAB; 48])?;
+    client.expect_error(ErrorCode::InvalidMagic)?;
+    client.expect_alive(1_001)
+}
+
+fn check_corrupt_checksum(client: &mut Client) -> Outcome {
+    let call = Message::with_ids(MessageType::Call, 2_001, 2_001, b"corrupt me".to_vec());
+    let mut frame = call.encode();
+    frame[HEADER_SIZE] ^= 0xFF;
+    client.send_raw(&frame)?;
+    client.expect_error(ErrorCode::ChecksumMismatch)?;
+    if client
+        .wait_for(SILENCE_WINDOW, |msg| msg.message_id() == 2_001)?
+        .is_some()
+    {
+        return Err("peer answered a corrupted Call".to_string());
+    }
+    client.expect_alive(2_002)
+}
+
+fn check_oversized_payload(client: &mut Client) -> Outcome {
+    // The header alone claims more than MAX_PAYLOAD_SIZE; a datagram could not carry it.
+    let header = MessageHeader::new(MessageType::Call, 3_001, 3_001, MAX_PAYLOAD_SIZE as u64 + 1);
+    let mut frame = header.to_bytes().to_vec();
+    frame.extend_from_slice(&[0; 8]);
+    client.send_raw(&frame)?;
+    client.expect_error(ErrorCode::PayloadTooLarge)?;
+    client.expect_alive(3_002)
+}
+
+fn check_unknown_type(client: &mut Client) -> Outcome {
+    let event = Message::with_ids(MessageType::Event, 4_001, 4_001, b"mystery".to_vec());
+    let mut frame = event.encode();
+    frame[4] = 0x7E;
+    reseal(&mut frame);
+    client.send_raw(&frame)?;
+    client.expect_error(ErrorCode::InvalidMessageType)?;
+    client.expect_alive(4_002)
+}
+
+fn check_call_response(client: &mut Client) -> Outcome {
+    let calls: Vec<Message> = (0..3u64)
+        .map(|i| {
+            Message::with_ids(
+                MessageType::Call,
+                5_001 + i,
+                0xC0FF_EE00 + i,
+                format!("call #{i}").into_bytes(),
+            )
+        })
+        .collect();
+    for call in &calls {
+        client.send(call)?;
+    }
+    for call in &calls {
+        let id = call.message_id();
+        let response = client.expect("Response", |msg| {
+            msg.message_type() == Some(MessageType::Response) && msg.message_id() == id
+        })?;
+        if response.trace_id() != call.trace_id() {
+            return Err(format!(
+                "Response {id} carried trace ID {:#x}, expected {:#x}",
+                response.trace_id(),
+                call.trace_id()
+            ));
+        }
+        if response.payload() != call.payload() {
+            return Err(format!("Response {id} did not echo the Call payload"));
+        }
+    }
+    Ok(())
+}
+
+fn check_heartbeat_ack(client: &mut Client) -> Outcome {
+    let sequence = 6_001u64;
+    let heartbeat = Message::with_ids(
+        MessageType::AgentHeartbeat,
+        sequence,
+        sequence,
+        sequence.to_le_bytes().to_vec(),
+    );
+    client.send(&heartbeat)?;
+    let ack = client.expect("heartbeat Ack", |msg| {
+        msg.message_type() == Some(MessageType::Ack) && msg.message_id() == sequence
+    })?;
+    if ack.payload() != heartbeat.payload() {
+        return Err("heartbeat Ack did not echo the sequence payload".to_string());
+    }
+    Ok(())
+}
+
+fn check_requires_ack(client: &mut Client) -> Outcome {
+    let flagged = Message::builder(MessageType::Event)
+        .message_id(7_001)
+        .trace_id(7_001)
+        .flags(Flags::new().with(Flags::REQUIRES_ACK))
+        .payload(&b"ack me"[..])
+        .build();
+    client.send(&flagged)?;
+    client.expect("Ack for a REQUIRES_ACK Event", |msg| {
+        msg.message_type() == Some(MessageType::Ack) && msg.message_id() == 7_001
+    })?;
+
+    let unflagged = Message::with_ids(MessageType::Event, 7_002, 7_002, b"no ack".to_vec());
+    client.send(&unflagged)?;
+    if client
+        .wait_for(SILENCE_WINDOW, |msg| msg.message_id() == 7_002)?
+        .is_some()
+    {
+        return Err("peer answered an Event without REQUIRES_ACK".to_string());
+    }
+    Ok(())
+}
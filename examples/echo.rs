@@ -0,0 +1,28 @@
+//! MXP echo responder over UDP.
+//!
+//! Run with `cargo run --example echo [-- <bind-addr>]` (default `127.0.0.1:7700`) and point
+//! `cargo run --example conformance -- <addr>` at it. See `support/responder.rs` for the
+//! replies it sends.
+
+use std::env;
+use std::net::SocketAddr;
+
+#[path = "support/responder.rs"]
+mod responder;
+
+const DEFAULT_ADDR: &str = "127.0.0.1:7700";
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let addr: SocketAddr = env::args()
+        .nth(1)
+        .as_deref()
+        .unwrap_or(DEFAULT_ADDR)
+        .parse()?;
+    let handle = responder::bind(addr, None)?;
+    println!(
+        "MXP echo listening on {}",
+        handle.local_addr().map_err(responder::into_io)?
+    );
+    responder::serve(&handle)?;
+    Ok(())
+}
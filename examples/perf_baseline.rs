@@ -6,11 +6,11 @@
 use std::env;
 use std::time::{Duration, Instant};
 
-use mxp::transport::AeadKey;
+use mxp::transport::{AeadKey, AeadNonce};
 use mxp::transport::{
-    AmplificationConfig, AntiAmplificationGuard, BufferPool, DatagramConfig, DatagramQueue,
-    EndpointRole, HeaderProtectionKey, PacketCipher, PacketFlags, PriorityClass, Scheduler,
-    SessionKeys, StreamId, StreamKind, StreamManager,
+    AmplificationConfig, AntiAmplificationGuard, BufferPool, ConnectionId, DatagramConfig,
+    DatagramQueue, EndpointRole, HeaderProtectionKey, PacketCipher, PacketFlags, PriorityClass,
+    Scheduler, SessionKeys, StreamId, StreamKind, StreamManager,
 };
 
 const DEFAULT_ITERATIONS: usize = 100_000;
@@ -70,12 +70,16 @@ fn bench_packet_path(iterations: usize) {
         AeadKey::from_array([0x22; mxp::transport::AEAD_KEY_LEN]),
         HeaderProtectionKey::from_array([0x33; mxp::transport::HEADER_PROTECTION_KEY_LEN]),
         HeaderProtectionKey::from_array([0x44; mxp::transport::HEADER_PROTECTION_KEY_LEN]),
+        AeadNonce::from_array([0x11; mxp::transport::AEAD_NONCE_LEN]),
+        AeadNonce::from_array([0x22; mxp::transport::AEAD_NONCE_LEN]),
     );
     let responder_keys = SessionKeys::new(
         AeadKey::from_array([0x22; mxp::transport::AEAD_KEY_LEN]),
         AeadKey::from_array([0x11; mxp::transport::AEAD_KEY_LEN]),
         HeaderProtectionKey::from_array([0x44; mxp::transport::HEADER_PROTECTION_KEY_LEN]),
         HeaderProtectionKey::from_array([0x33; mxp::transport::HEADER_PROTECTION_KEY_LEN]),
+        AeadNonce::from_array([0x22; mxp::transport::AEAD_NONCE_LEN]),
+        AeadNonce::from_array([0x11; mxp::transport::AEAD_NONCE_LEN]),
     );
 
     let mut sender = PacketCipher::new(initiator_keys);
@@ -85,7 +89,7 @@ fn bench_packet_path(iterations: usize) {
         buffer.reset();
         let (_, written) = sender
             .seal_into(
-                0x4D58_5031,
+                &ConnectionId::from_u64(0x4D58_5031),
                 PacketFlags::default(),
                 &payload,
                 buffer.as_mut_slice(),
@@ -22,7 +22,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("Encoded to {} bytes", encoded.len());
 
     // Decode
-    let decoded = Message::decode(encoded.clone())?;
+    let decoded = Message::decode(&encoded)?;
     println!(
         "Decoded: payload={:?}",
         std::str::from_utf8(decoded.payload()).unwrap()
@@ -0,0 +1,98 @@
+//! Reference responder shared by the `echo` and `conformance` examples
+//!
+//! Speaks plaintext MXP messages over UDP, one encoded message per datagram:
+//! - `Call` is answered with a `Response` echoing the message ID, trace ID and payload
+//! - `AgentHeartbeat` is answered with its `Ack`
+//! - any other message flagged `REQUIRES_ACK` is answered with an empty `Ack`
+//! - a datagram that fails to decode is answered with an `Error` whose payload starts with
+//!   the little-endian `ErrorCode`; the responder keeps serving
+
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use bytes::Bytes;
+use mxp::protocol::heartbeat_ack;
+use mxp::transport::SocketError;
+use mxp::{Error, Message, MessageType, Transport, TransportConfig, TransportHandle};
+
+/// Reply to one inbound datagram
+pub fn respond(datagram: Bytes) -> Vec<Message> {
+    let message = match Message::decode_bytes(datagram) {
+        Ok(message) => message,
+        Err(err) => return vec![error_reply(&err)],
+    };
+    if let Some(ack) = heartbeat_ack(&message) {
+        return vec![ack];
+    }
+
+    let mut replies = Vec::new();
+    if message.flags().requires_ack() {
+        replies.push(Message::with_ids(
+            MessageType::Ack,
+            message.message_id(),
+            message.trace_id(),
+            Vec::new(),
+        ));
+    }
+    if message.message_type() == Some(MessageType::Call) {
+        replies.push(Message::with_ids(
+            MessageType::Response,
+            message.message_id(),
+            message.trace_id(),
+            message.payload().clone(),
+        ));
+    }
+    replies
+}
+
+/// `Error` message reporting a decode failure
+fn error_reply(err: &Error) -> Message {
+    let mut payload = err.code().as_u16().to_le_bytes().to_vec();
+    payload.extend_from_slice(err.to_string().as_bytes());
+    Message::with_ids(MessageType::Error, 0, 0, payload)
+}
+
+/// Bind a transport on `addr`, with reads giving up after `read_timeout` if set
+pub fn bind(addr: SocketAddr, read_timeout: Option<Duration>) -> io::Result<TransportHandle> {
+    let config = TransportConfig {
+        read_timeout,
+        ..TransportConfig::default()
+    };
+    Transport::new(config).bind(addr).map_err(into_io)
+}
+
+/// Answer datagrams on `handle` until a socket error other than a read timeout occurs
+pub fn serve(handle: &TransportHandle) -> io::Result<()> {
+    loop {
+        // Each datagram gets its own lease so the message can borrow it without a copy.
+        let mut buffer = handle.acquire_buffer();
+        let (_, peer) = match handle.receive(&mut buffer) {
+            Ok(received) => received,
+            Err(err) => {
+                let err = into_io(err);
+                if is_timeout(&err) {
+                    continue;
+                }
+                return Err(err);
+            }
+        };
+        for reply in respond(buffer.freeze()) {
+            handle.send(&reply.encode(), peer).map_err(into_io)?;
+        }
+    }
+}
+
+/// Unwrap the I/O error behind a socket failure
+pub fn into_io(err: SocketError) -> io::Error {
+    let SocketError::Io(err) = err;
+    err
+}
+
+/// Whether `err` is a socket read timing out (`WouldBlock` on Unix, `TimedOut` on Windows)
+pub fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
@@ -14,8 +14,8 @@
 //! // Encode to bytes (zero-copy)
 //! let bytes = msg.encode();
 //!
-//! // Decode from bytes
-//! let decoded = Message::decode(bytes.clone())?;
+//! // Decode from bytes (takes ownership of the buffer, no copy)
+//! let decoded = Message::decode_bytes(bytes.into())?;
 //! # Ok::<(), mxp::Error>(())
 //! ```
 //!
@@ -26,23 +26,40 @@
 //! - **Built-in checksums** - `XXHash3` for fast validation
 //! - **Custom transport** - UDP carrier with MXP-native reliability and security
 //!
+//! # `no_std`
+//!
+//! With `default-features = false` the crate is `no_std + alloc` and exposes only
+//! [`protocol`]: headers, message types, messages, and the codec. The `std` feature
+//! (on by default) adds the transport, tracing, random message IDs, heartbeats, and
+//! request tracking; `xxhash` swaps the in-tree checksum for the `xxhash-rust` crate.
+//! The `testing` feature adds [`testing`], a deterministic network simulator for exercising
+//! agents and transport drivers without real sockets.
+//!
 //! # Protocol Specification
 //!
 //! See [SPEC.md](https://github.com/yourusername/mxp-protocol/blob/main/SPEC.md)
 //! or visit [getmxp.xyz](https://getmxp.xyz) for the complete protocol specification.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![warn(missing_docs)]
 #![warn(clippy::all)]
 #![warn(clippy::pedantic)]
 #![allow(clippy::missing_errors_doc)]
 #![allow(clippy::missing_panics_doc)]
 
+extern crate alloc;
+
 pub mod protocol;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "std")]
 pub mod transport;
 
 pub use protocol::{
-    Error, Flags, MAGIC_NUMBER, MAX_PAYLOAD_SIZE, Message, MessageHeader, MessageType, Result,
+    Error, ErrorCode, Flags, MAGIC_NUMBER, MAX_PAYLOAD_SIZE, Message, MessageBuilder,
+    MessageHeader, MessageType, Result,
 };
+#[cfg(feature = "std")]
 pub use transport::{BufferPool, Transport, TransportConfig, TransportHandle};
 
 /// MXP protocol version
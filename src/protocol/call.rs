@@ -0,0 +1,513 @@
+//! Call retry policy and server-side duplicate suppression
+//!
+//! [`call_with`] drives one logical call over any [`CallTransport`]: every attempt reuses
+//! the request's `message_id`, failed attempts back off exponentially with jitter, and the
+//! whole call is bounded by one deadline. Idempotent calls carry `Flags::REQUIRES_ACK` so
+//! a server can run them through a [`DedupCache`] and answer repeats from the cache.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, SystemTime};
+
+use tracing::debug;
+
+use super::{Error, Flags, Message, Result};
+
+/// Default overall deadline for a call
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default delay before the first retry
+pub const DEFAULT_CALL_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Upper bound on a single backoff delay
+pub const MAX_CALL_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Retry and deadline settings for [`call_with`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallOptions {
+    /// Deadline for the whole call, across all attempts
+    pub timeout: Duration,
+    /// Retries allowed after the first attempt
+    pub retries: u32,
+    /// Delay before the first retry; doubles for each later one
+    pub backoff: Duration,
+    /// Whether executing the request twice is harmless
+    ///
+    /// Non-idempotent calls are retried only when the request provably never left.
+    pub idempotent: bool,
+}
+
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_CALL_TIMEOUT,
+            retries: 0,
+            backoff: DEFAULT_CALL_BACKOFF,
+            idempotent: false,
+        }
+    }
+}
+
+impl CallOptions {
+    /// Delay before retry number `retry` (starting at 0) of the call `message_id`
+    ///
+    /// The base delay doubles per retry up to [`MAX_CALL_BACKOFF`]; jitter then picks a
+    /// point in the upper half of it, so delays never shrink from one retry to the next.
+    #[must_use]
+    pub fn backoff_delay(&self, message_id: u64, retry: u32) -> Duration {
+        let base = self
+            .backoff
+            .saturating_mul(1 << retry.min(16))
+            .min(MAX_CALL_BACKOFF.max(self.backoff));
+        let jitter = splitmix(message_id ^ u64::from(retry));
+        // Scale into [base / 2, base] using the top 16 bits of the jitter.
+        let fraction = u32::try_from(jitter >> 48).expect("16-bit value");
+        base / 2 + (base / 2) * fraction / 0xFFFF
+    }
+}
+
+/// Whether a failed request may have reached the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The request was never handed to the network
+    NotSent,
+    /// The request may have been received and executed
+    Unknown,
+}
+
+/// Failure of a single attempt reported by a [`CallTransport`]
+#[derive(Debug)]
+pub struct AttemptError {
+    /// What went wrong
+    pub error: Error,
+    /// Whether the peer may have seen the request
+    pub delivery: Delivery,
+}
+
+impl AttemptError {
+    /// Failure before the request was sent
+    #[must_use]
+    pub const fn not_sent(error: Error) -> Self {
+        Self {
+            error,
+            delivery: Delivery::NotSent,
+        }
+    }
+
+    /// Failure after the request may have been delivered
+    #[must_use]
+    pub const fn unknown(error: Error) -> Self {
+        Self {
+            error,
+            delivery: Delivery::Unknown,
+        }
+    }
+}
+
+/// Carries call attempts for [`call_with`]
+pub trait CallTransport {
+    /// Send `request` and wait for its reply until `deadline`
+    ///
+    /// # Errors
+    ///
+    /// Reports the failure and whether the request may have been delivered.
+    fn attempt(
+        &mut self,
+        request: &Message,
+        deadline: SystemTime,
+    ) -> core::result::Result<Message, AttemptError>;
+
+    /// Current time
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+
+    /// Wait `delay` before the next attempt
+    fn sleep(&mut self, delay: Duration) {
+        std::thread::sleep(delay);
+    }
+}
+
+/// Whether an error is a transient transport failure worth retrying
+#[must_use]
+pub const fn is_retryable(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::Io(_) | Error::Connection(_) | Error::Stream(_)
+    )
+}
+
+/// Perform a call, retrying transient failures as `options` allow
+///
+/// Every attempt sends the same request, so the `message_id` never changes; idempotent
+/// requests are marked `REQUIRES_ACK` first. A retry happens only for a retryable error,
+/// while retries remain, when the backoff still fits before the deadline, and, for
+/// non-idempotent calls, only if the failed attempt was [`Delivery::NotSent`].
+///
+/// # Errors
+///
+/// Returns the last attempt's error when no retry is allowed, or
+/// [`Error::DeadlineExceeded`] once the overall deadline passes.
+pub fn call_with<T: CallTransport + ?Sized>(
+    transport: &mut T,
+    mut request: Message,
+    options: CallOptions,
+) -> Result<Message> {
+    if options.idempotent {
+        request.set_flags(request.flags().with(Flags::REQUIRES_ACK));
+    }
+    let message_id = request.message_id();
+    let deadline = transport.now() + options.timeout;
+
+    let mut retry = 0;
+    loop {
+        if transport.now() >= deadline {
+            return Err(Error::DeadlineExceeded { message_id });
+        }
+        let failure = match transport.attempt(&request, deadline) {
+            Ok(reply) => return Ok(reply),
+            Err(failure) => failure,
+        };
+        let may_retry = is_retryable(&failure.error)
+            && retry < options.retries
+            && (options.idempotent || failure.delivery == Delivery::NotSent);
+        if !may_retry {
+            return Err(failure.error);
+        }
+        let delay = options.backoff_delay(message_id, retry);
+        if transport.now() + delay >= deadline {
+            return Err(Error::DeadlineExceeded { message_id });
+        }
+        debug!(
+            message_id,
+            retry,
+            delay_ms = delay.as_millis(),
+            error = %failure.error,
+            "retrying call"
+        );
+        transport.sleep(delay);
+        retry += 1;
+    }
+}
+
+/// What a server should do with an incoming request, from [`DedupCache::check`]
+#[derive(Debug, Clone)]
+pub enum DedupStatus {
+    /// First sighting: execute it, then call [`DedupCache::complete`]
+    New,
+    /// Already executing; drop the duplicate
+    InProgress,
+    /// Already answered; resend this reply instead of executing again
+    Completed(Message),
+}
+
+#[derive(Debug)]
+struct DedupEntry {
+    seen: SystemTime,
+    reply: Option<Message>,
+}
+
+/// Server-side cache suppressing duplicate execution of retried idempotent calls
+///
+/// Only requests flagged `REQUIRES_ACK` are tracked. Entries are keyed by `message_id`,
+/// expire after `ttl` and are evicted oldest-first beyond `capacity`.
+#[derive(Debug)]
+pub struct DedupCache {
+    entries: HashMap<u64, DedupEntry>,
+    order: VecDeque<u64>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl DedupCache {
+    /// Create a cache remembering at most `capacity` calls for `ttl` each
+    #[must_use]
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            capacity: capacity.max(1),
+            ttl,
+        }
+    }
+
+    /// Classify `request` received at `now`, remembering it if new
+    pub fn check(&mut self, request: &Message, now: SystemTime) -> DedupStatus {
+        if !request.flags().requires_ack() {
+            return DedupStatus::New;
+        }
+        self.expire(now);
+        let message_id = request.message_id();
+        if let Some(entry) = self.entries.get(&message_id) {
+            return entry
+                .reply
+                .clone()
+                .map_or(DedupStatus::InProgress, DedupStatus::Completed);
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            message_id,
+            DedupEntry {
+                seen: now,
+                reply: None,
+            },
+        );
+        self.order.push_back(message_id);
+        DedupStatus::New
+    }
+
+    /// Record the reply sent for `message_id` so duplicates get the same answer
+    pub fn complete(&mut self, message_id: u64, reply: &Message) {
+        if let Some(entry) = self.entries.get_mut(&message_id) {
+            entry.reply = Some(reply.clone());
+        }
+    }
+
+    /// Number of calls remembered
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Check whether no calls are remembered
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn expire(&mut self, now: SystemTime) {
+        while let Some(&oldest) = self.order.front() {
+            let expired = self
+                .entries
+                .get(&oldest)
+                .is_none_or(|entry| now.duration_since(entry.seen).unwrap_or_default() >= self.ttl);
+            if !expired {
+                break;
+            }
+            self.order.pop_front();
+            self.entries.remove(&oldest);
+        }
+    }
+}
+
+/// `SplitMix64` finaliser, used as a cheap deterministic jitter source
+const fn splitmix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MessageType;
+
+    /// Transport that fails scripted attempts, then answers via a server-side dedup cache.
+    struct FlakyTransport {
+        failures: VecDeque<AttemptError>,
+        now: SystemTime,
+        sleeps: Vec<Duration>,
+        sent: Vec<(u64, Flags)>,
+        server: DedupCache,
+        executions: u32,
+    }
+
+    impl FlakyTransport {
+        fn new(failures: Vec<AttemptError>) -> Self {
+            Self {
+                failures: failures.into(),
+                now: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000),
+                sleeps: Vec::new(),
+                sent: Vec::new(),
+                server: DedupCache::new(16, Duration::from_secs(60)),
+                executions: 0,
+            }
+        }
+
+        fn reply_to(&mut self, request: &Message) -> Message {
+            match self.server.check(request, self.now) {
+                DedupStatus::Completed(reply) => reply,
+                DedupStatus::InProgress => unreachable!("attempts are sequential"),
+                DedupStatus::New => {
+                    self.executions += 1;
+                    let reply = Message::with_ids(
+                        MessageType::Response,
+                        request.message_id(),
+                        request.trace_id(),
+                        b"done".to_vec(),
+                    );
+                    self.server.complete(request.message_id(), &reply);
+                    reply
+                }
+            }
+        }
+    }
+
+    impl CallTransport for FlakyTransport {
+        fn attempt(
+            &mut self,
+            request: &Message,
+            _deadline: SystemTime,
+        ) -> core::result::Result<Message, AttemptError> {
+            self.sent.push((request.message_id(), request.flags()));
+            match self.failures.pop_front() {
+                // The reply was lost after the server executed the call.
+                Some(failure) if failure.delivery == Delivery::Unknown => {
+                    self.reply_to(request);
+                    Err(failure)
+                }
+                Some(failure) => Err(failure),
+                None => Ok(self.reply_to(request)),
+            }
+        }
+
+        fn now(&self) -> SystemTime {
+            self.now
+        }
+
+        fn sleep(&mut self, delay: Duration) {
+            self.sleeps.push(delay);
+            self.now += delay;
+        }
+    }
+
+    fn stream_reset() -> Error {
+        Error::Stream("reset".into())
+    }
+
+    fn request() -> Message {
+        Message::with_ids(MessageType::Call, 0xCA11, 1, b"work".to_vec())
+    }
+
+    #[test]
+    fn idempotent_call_retries_with_growing_backoff_and_single_execution() {
+        let mut transport = FlakyTransport::new(vec![
+            AttemptError::not_sent(stream_reset()),
+            AttemptError::unknown(stream_reset()),
+            AttemptError::unknown(Error::Connection("lost".into())),
+        ]);
+        let options = CallOptions {
+            retries: 3,
+            idempotent: true,
+            ..CallOptions::default()
+        };
+        let reply = call_with(&mut transport, request(), options).unwrap();
+        assert_eq!(reply.payload().as_ref(), b"done");
+
+        assert_eq!(transport.sent.len(), 4);
+        for (message_id, flags) in &transport.sent {
+            assert_eq!(*message_id, 0xCA11);
+            assert!(flags.requires_ack());
+        }
+        assert_eq!(transport.sleeps.len(), 3);
+        assert!(transport.sleeps.windows(2).all(|pair| pair[0] <= pair[1]));
+        for (retry, delay) in (0u32..).zip(&transport.sleeps) {
+            let base = DEFAULT_CALL_BACKOFF * (1 << retry);
+            assert!(
+                *delay >= base / 2 && *delay <= base,
+                "{delay:?} vs {base:?}"
+            );
+            assert_eq!(*delay, options.backoff_delay(0xCA11, retry));
+        }
+        // The server ran the call once despite seeing it three times.
+        assert_eq!(transport.executions, 1);
+    }
+
+    #[test]
+    fn non_idempotent_call_retries_only_unsent_requests() {
+        let options = CallOptions {
+            retries: 5,
+            ..CallOptions::default()
+        };
+        let mut transport = FlakyTransport::new(vec![
+            AttemptError::not_sent(stream_reset()),
+            AttemptError::unknown(stream_reset()),
+        ]);
+        let err = call_with(&mut transport, request(), options).unwrap_err();
+        assert!(matches!(err, Error::Stream(_)));
+        assert_eq!(transport.sent.len(), 2);
+        assert!(!transport.sent[0].1.requires_ack());
+        assert_eq!(transport.executions, 1);
+    }
+
+    #[test]
+    fn retries_stop_at_limit_fatal_errors_and_deadline() {
+        let failures = || {
+            (0..4)
+                .map(|_| AttemptError::not_sent(stream_reset()))
+                .collect()
+        };
+        let mut limited = FlakyTransport::new(failures());
+        let options = CallOptions {
+            retries: 2,
+            idempotent: true,
+            ..CallOptions::default()
+        };
+        assert!(matches!(
+            call_with(&mut limited, request(), options),
+            Err(Error::Stream(_))
+        ));
+        assert_eq!(limited.sent.len(), 3);
+
+        let mut fatal = FlakyTransport::new(vec![AttemptError::not_sent(Error::InvalidFlags {
+            flags: 0xFF,
+        })]);
+        assert!(call_with(&mut fatal, request(), options).is_err());
+        assert_eq!(fatal.sent.len(), 1);
+
+        let mut slow = FlakyTransport::new(failures());
+        let tight = CallOptions {
+            timeout: Duration::from_millis(120),
+            retries: 10,
+            idempotent: true,
+            ..CallOptions::default()
+        };
+        assert!(matches!(
+            call_with(&mut slow, request(), tight),
+            Err(Error::DeadlineExceeded { message_id: 0xCA11 })
+        ));
+        assert!(slow.sleeps.iter().sum::<Duration>() < tight.timeout);
+    }
+
+    #[test]
+    fn dedup_cache_expires_evicts_and_ignores_unflagged_requests() {
+        let start = SystemTime::UNIX_EPOCH;
+        let mut cache = DedupCache::new(2, Duration::from_secs(10));
+        let flagged = |id| {
+            let mut message = Message::with_ids(MessageType::Call, id, 0, Vec::new());
+            message.set_flags(Flags::new().with(Flags::REQUIRES_ACK));
+            message
+        };
+
+        assert!(matches!(cache.check(&request(), start), DedupStatus::New));
+        assert!(matches!(cache.check(&request(), start), DedupStatus::New));
+        assert!(cache.is_empty());
+
+        assert!(matches!(cache.check(&flagged(1), start), DedupStatus::New));
+        assert!(matches!(
+            cache.check(&flagged(1), start),
+            DedupStatus::InProgress
+        ));
+        cache.complete(
+            1,
+            &Message::with_ids(MessageType::Response, 1, 0, Vec::new()),
+        );
+        assert!(matches!(
+            cache.check(&flagged(1), start),
+            DedupStatus::Completed(reply) if reply.message_id() == 1
+        ));
+
+        cache.check(&flagged(2), start);
+        cache.check(&flagged(3), start);
+        assert_eq!(cache.len(), 2);
+        assert!(matches!(cache.check(&flagged(1), start), DedupStatus::New));
+
+        let later = start + Duration::from_secs(10);
+        assert!(matches!(cache.check(&flagged(3), later), DedupStatus::New));
+        assert_eq!(cache.len(), 1);
+    }
+}
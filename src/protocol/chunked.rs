@@ -0,0 +1,325 @@
+//! Chunked framing for logical messages larger than [`MAX_PAYLOAD_SIZE`]
+//!
+//! A logical message is sent as `StreamChunk` messages followed by one `StreamClose`
+//! message carrying the last piece, all sharing the logical message ID. Each body is
+//! `index: u32 (LE) | data`, with indexes counting up from zero.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use bytes::{Bytes, BytesMut};
+
+use super::{Error, MAX_PAYLOAD_SIZE, Message, MessageType, Result};
+
+/// Size of the chunk index prefixed to every body
+const INDEX_LEN: usize = 4;
+
+/// Largest amount of logical data a single chunk can carry
+pub const MAX_CHUNK_DATA: usize = MAX_PAYLOAD_SIZE - INDEX_LEN;
+
+/// Default amount of logical data per chunk (1 MB)
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Default cap on bytes buffered across all partially reassembled messages (256 MB)
+pub const DEFAULT_MAX_REASSEMBLED_SIZE: usize = 256 * 1024 * 1024;
+
+/// Split `payload` into chunk messages of at most `chunk_size` data bytes each
+///
+/// `chunk_size` is clamped to `1..=`[`MAX_CHUNK_DATA`]. An empty payload yields a single
+/// empty `StreamClose` chunk. Chunks are produced lazily, so only one is held at a time.
+#[must_use]
+pub fn split_chunks(
+    message_id: u64,
+    trace_id: u64,
+    payload: &[u8],
+    chunk_size: usize,
+) -> Chunks<'_> {
+    Chunks {
+        message_id,
+        trace_id,
+        remaining: payload,
+        chunk_size: chunk_size.clamp(1, MAX_CHUNK_DATA),
+        index: 0,
+        done: false,
+    }
+}
+
+/// Iterator over the chunk messages of one logical message, from [`split_chunks`]
+#[derive(Debug, Clone)]
+pub struct Chunks<'a> {
+    message_id: u64,
+    trace_id: u64,
+    remaining: &'a [u8],
+    chunk_size: usize,
+    index: u32,
+    done: bool,
+}
+
+impl Iterator for Chunks<'_> {
+    type Item = Message;
+
+    fn next(&mut self) -> Option<Message> {
+        if self.done {
+            return None;
+        }
+        let take = self.remaining.len().min(self.chunk_size);
+        let (data, rest) = self.remaining.split_at(take);
+        self.remaining = rest;
+        self.done = rest.is_empty();
+
+        let msg_type = if self.done {
+            MessageType::StreamClose
+        } else {
+            MessageType::StreamChunk
+        };
+        let mut body = Vec::with_capacity(INDEX_LEN + data.len());
+        body.extend_from_slice(&self.index.to_le_bytes());
+        body.extend_from_slice(data);
+        self.index = self.index.wrapping_add(1);
+        Some(Message::with_ids(
+            msg_type,
+            self.message_id,
+            self.trace_id,
+            body,
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let left = if self.done {
+            0
+        } else {
+            self.remaining.len().div_ceil(self.chunk_size).max(1)
+        };
+        (left, Some(left))
+    }
+}
+
+impl ExactSizeIterator for Chunks<'_> {}
+
+/// Logical message whose chunks are still arriving
+#[derive(Debug)]
+struct Partial {
+    next_index: u32,
+    data: BytesMut,
+}
+
+/// Reassembles chunked logical messages, keyed by message ID
+///
+/// Chunks of one message must arrive in order, as they do on a single stream; chunks of
+/// different messages may interleave. A gap or reordering fails that message with
+/// [`Error::ChunkOutOfOrder`] and discards what was buffered for it.
+#[derive(Debug)]
+pub struct ChunkReassembler {
+    max_size: usize,
+    buffered: usize,
+    partials: BTreeMap<u64, Partial>,
+}
+
+impl Default for ChunkReassembler {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_REASSEMBLED_SIZE)
+    }
+}
+
+impl ChunkReassembler {
+    /// Create a reassembler that buffers at most `max_size` bytes across all messages
+    #[must_use]
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            buffered: 0,
+            partials: BTreeMap::new(),
+        }
+    }
+
+    /// Bytes buffered for messages that have not completed yet
+    #[must_use]
+    pub const fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    /// Number of messages with chunks outstanding
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.partials.len()
+    }
+
+    /// Feed one chunk, returning the full payload once its final chunk arrives
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidMessageType`] for messages other than `StreamChunk` and
+    /// `StreamClose`, [`Error::BufferTooSmall`] if the body lacks its index,
+    /// [`Error::ChunkOutOfOrder`] on a missing or repeated chunk, and
+    /// [`Error::PayloadTooLarge`] if buffering the chunk would exceed the size limit. The
+    /// affected message is discarded in the last two cases.
+    pub fn push(&mut self, chunk: &Message) -> Result<Option<Bytes>> {
+        let last = match chunk.message_type() {
+            Some(MessageType::StreamChunk) => false,
+            Some(MessageType::StreamClose) => true,
+            _ => {
+                return Err(Error::InvalidMessageType {
+                    type_byte: chunk.header().msg_type_byte(),
+                });
+            }
+        };
+        let body = chunk.payload();
+        if body.len() < INDEX_LEN {
+            return Err(Error::BufferTooSmall {
+                needed: INDEX_LEN,
+                got: body.len(),
+            });
+        }
+        let index = u32::from_le_bytes(body[..INDEX_LEN].try_into().unwrap());
+        let data = &body[INDEX_LEN..];
+        let message_id = chunk.message_id();
+
+        let expected = self.partials.get(&message_id).map_or(0, |p| p.next_index);
+        if index != expected {
+            self.discard(message_id);
+            return Err(Error::ChunkOutOfOrder {
+                message_id,
+                expected,
+                found: index,
+            });
+        }
+        if self.buffered + data.len() > self.max_size {
+            let size = self.partials.get(&message_id).map_or(0, |p| p.data.len()) + data.len();
+            self.discard(message_id);
+            return Err(Error::PayloadTooLarge {
+                size,
+                max: self.max_size,
+            });
+        }
+
+        if last && index == 0 {
+            // Single-chunk message: hand back a slice of the body without buffering.
+            return Ok(Some(body.slice(INDEX_LEN..)));
+        }
+        let partial = self.partials.entry(message_id).or_insert_with(|| Partial {
+            next_index: 0,
+            data: BytesMut::new(),
+        });
+        partial.data.extend_from_slice(data);
+        partial.next_index += 1;
+        self.buffered += data.len();
+        if !last {
+            return Ok(None);
+        }
+        let partial = self.partials.remove(&message_id).expect("inserted above");
+        self.buffered -= partial.data.len();
+        Ok(Some(partial.data.freeze()))
+    }
+
+    fn discard(&mut self, message_id: u64) {
+        if let Some(partial) = self.partials.remove(&message_id) {
+            self.buffered -= partial.data.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pattern(len: usize) -> Vec<u8> {
+        (0..len).map(|i| u8::try_from(i % 251).unwrap()).collect()
+    }
+
+    #[test]
+    fn reassembles_message_larger_than_max_payload() {
+        let payload = pattern(40 * 1024 * 1024);
+        assert!(payload.len() > MAX_PAYLOAD_SIZE);
+        let chunks = split_chunks(7, 9, &payload, DEFAULT_CHUNK_SIZE);
+        assert_eq!(chunks.len(), 40);
+
+        let mut reassembler = ChunkReassembler::default();
+        let mut complete = None;
+        for (i, chunk) in chunks.enumerate() {
+            assert_eq!(chunk.message_id(), 7);
+            assert_eq!(chunk.trace_id(), 9);
+            // Every chunk survives the wire codec on its own.
+            let chunk = Message::decode(&chunk.encode()).unwrap();
+            let out = reassembler.push(&chunk).unwrap();
+            if i < 39 {
+                assert_eq!(chunk.message_type(), Some(MessageType::StreamChunk));
+                assert!(out.is_none());
+            } else {
+                assert_eq!(chunk.message_type(), Some(MessageType::StreamClose));
+                complete = out;
+            }
+        }
+        assert_eq!(complete.as_deref(), Some(payload.as_slice()));
+        assert_eq!(reassembler.buffered(), 0);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn missing_middle_chunk_errors_and_discards() {
+        let payload = pattern(10_000);
+        let mut chunks: Vec<Message> = split_chunks(1, 1, &payload, 1000).collect();
+        chunks.remove(4);
+
+        let mut reassembler = ChunkReassembler::default();
+        for chunk in &chunks[..4] {
+            assert!(reassembler.push(chunk).unwrap().is_none());
+        }
+        assert_eq!(reassembler.buffered(), 4000);
+        assert!(matches!(
+            reassembler.push(&chunks[4]),
+            Err(Error::ChunkOutOfOrder {
+                message_id: 1,
+                expected: 4,
+                found: 5
+            })
+        ));
+        assert_eq!(reassembler.buffered(), 0);
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn interleaved_messages_and_single_chunk_payloads() {
+        let first = pattern(2500);
+        let second = pattern(1800);
+        let mut reassembler = ChunkReassembler::default();
+        let mut a = split_chunks(1, 0, &first, 1000);
+        let mut b = split_chunks(2, 0, &second, 1000);
+        assert!(reassembler.push(&a.next().unwrap()).unwrap().is_none());
+        assert!(reassembler.push(&b.next().unwrap()).unwrap().is_none());
+        assert!(reassembler.push(&a.next().unwrap()).unwrap().is_none());
+        let done_b = reassembler.push(&b.next().unwrap()).unwrap();
+        assert_eq!(done_b.as_deref(), Some(second.as_slice()));
+        let done_a = reassembler.push(&a.next().unwrap()).unwrap();
+        assert_eq!(done_a.as_deref(), Some(first.as_slice()));
+
+        let mut empty = split_chunks(3, 0, &[], 1000);
+        assert_eq!(empty.len(), 1);
+        let out = reassembler.push(&empty.next().unwrap()).unwrap();
+        assert_eq!(out.as_deref(), Some(&[][..]));
+        assert!(empty.next().is_none());
+    }
+
+    #[test]
+    fn size_limit_rejects_oversized_messages() {
+        let payload = pattern(5000);
+        let mut reassembler = ChunkReassembler::new(3000);
+        let mut chunks = split_chunks(4, 0, &payload, 1000);
+        for _ in 0..3 {
+            assert!(reassembler.push(&chunks.next().unwrap()).unwrap().is_none());
+        }
+        assert!(matches!(
+            reassembler.push(&chunks.next().unwrap()),
+            Err(Error::PayloadTooLarge {
+                size: 4000,
+                max: 3000
+            })
+        ));
+        assert_eq!(reassembler.buffered(), 0);
+
+        let other = Message::new(MessageType::Call, b"not a chunk");
+        assert!(matches!(
+            reassembler.push(&other),
+            Err(Error::InvalidMessageType { type_byte: 0x10 })
+        ));
+    }
+}
@@ -2,17 +2,22 @@
 //!
 //! This module provides zero-copy encoding and decoding of MXP messages.
 
-use bytes::Bytes;
-use xxhash_rust::xxh3::xxh3_64;
+use alloc::vec::Vec;
+use core::ops::Range;
 
-use super::{CHECKSUM_SIZE, Error, HEADER_SIZE, MIN_MESSAGE_SIZE, Message, MessageHeader, Result};
+use bytes::{Bytes, BytesMut};
+
+use super::{
+    CHECKSUM_SIZE, DEADLINE_SIZE, Error, HEADER_SIZE, MIN_MESSAGE_SIZE, Message, MessageHeader,
+    Result,
+};
 
 /// Encode a message to bytes
 ///
 /// # Format
 ///
 /// ```text
-/// [HEADER (32 bytes)] [PAYLOAD (variable)] [CHECKSUM (8 bytes)]
+/// [HEADER (32 bytes)] [DEADLINE (8 bytes, optional)] [PAYLOAD (variable)] [CHECKSUM (8 bytes)]
 /// ```
 ///
 /// # Performance
@@ -24,17 +29,23 @@ pub fn encode(message: &Message) -> Vec<u8> {
     let payload = message.payload();
 
     // Calculate total size
-    let total_size = HEADER_SIZE + payload.len() + CHECKSUM_SIZE;
+    let deadline_size = message.deadline_micros().map_or(0, |_| DEADLINE_SIZE);
+    let total_size = HEADER_SIZE + deadline_size + payload.len() + CHECKSUM_SIZE;
     let mut bytes = Vec::with_capacity(total_size);
 
     // Write header
     bytes.extend_from_slice(&header.to_bytes());
 
+    // Write deadline prefix
+    if let Some(deadline) = message.deadline_micros() {
+        bytes.extend_from_slice(&deadline.to_le_bytes());
+    }
+
     // Write payload
     bytes.extend_from_slice(payload);
 
-    // Calculate checksum (header + payload)
-    let checksum = xxh3_64(&bytes);
+    // Calculate checksum (header + payload) with the algorithm the flags select
+    let checksum = header.flags().checksum_algorithm().compute(&bytes);
 
     // Write checksum
     bytes.extend_from_slice(&checksum.to_le_bytes());
@@ -47,7 +58,7 @@ pub fn encode(message: &Message) -> Vec<u8> {
 /// # Format
 ///
 /// ```text
-/// [HEADER (32 bytes)] [PAYLOAD (variable)] [CHECKSUM (8 bytes)]
+/// [HEADER (32 bytes)] [DEADLINE (8 bytes, optional)] [PAYLOAD (variable)] [CHECKSUM (8 bytes)]
 /// ```
 ///
 /// # Performance
@@ -63,6 +74,120 @@ pub fn encode(message: &Message) -> Vec<u8> {
 /// - Checksum doesn't match
 /// - Payload is too large
 pub fn decode(bytes: Bytes) -> Result<Message> {
+    let (header, deadline, payload) = parse(&bytes)?;
+    Ok(Message::from_parts(header, bytes.slice(payload), deadline))
+}
+
+/// Decode a message from a borrowed slice
+///
+/// Validates the frame in place and copies only the payload into the returned message, so
+/// callers holding a `&[u8]` need not build a [`Bytes`] first. Accepts and rejects exactly
+/// the same inputs as [`decode`].
+///
+/// # Errors
+///
+/// Returns the same errors as [`decode`].
+pub fn decode_ref(bytes: &[u8]) -> Result<Message> {
+    let (header, deadline, payload) = parse(bytes)?;
+    Ok(Message::from_parts(
+        header,
+        Bytes::copy_from_slice(&bytes[payload]),
+        deadline,
+    ))
+}
+
+/// Decode the message at the start of `bytes`, returning it with the number of bytes it
+/// occupied
+///
+/// Bytes after the message are ignored, so a buffer holding several back-to-back messages
+/// can be walked by slicing off the consumed length; [`MessageIter`] does exactly that. Like
+/// [`decode_ref`], only the payload is copied.
+///
+/// # Errors
+///
+/// Returns [`Error::Incomplete`] if `bytes` ends before the message does. `needed` is exact
+/// once the header is complete and a lower bound (the smallest possible message) before
+/// then. Otherwise returns the same errors as [`decode`].
+pub fn decode_prefix(bytes: &[u8]) -> Result<(Message, usize)> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(Error::Incomplete {
+            needed: MIN_MESSAGE_SIZE - bytes.len(),
+        });
+    }
+    let header = MessageHeader::from_bytes(&bytes[..HEADER_SIZE])?;
+    let payload_len =
+        usize::try_from(header.payload_len()).expect("validated payload length fits usize");
+    let total_size = HEADER_SIZE + payload_len + CHECKSUM_SIZE;
+    if bytes.len() < total_size {
+        return Err(Error::Incomplete {
+            needed: total_size - bytes.len(),
+        });
+    }
+    let message = decode_ref(&bytes[..total_size])?;
+    Ok((message, total_size))
+}
+
+/// Iterator over messages written back-to-back in a byte slice
+///
+/// Yields one `Result<Message>` per message until the slice is exhausted. A trailing
+/// partial message yields [`Error::Incomplete`]; any other error means the bytes at
+/// [`position`](Self::position) are not a valid message. Either way iteration stops after
+/// the first error, leaving `position` at the start of the offending message.
+#[derive(Debug, Clone)]
+pub struct MessageIter<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    failed: bool,
+}
+
+impl<'a> MessageIter<'a> {
+    /// Iterate over the messages in `bytes`
+    #[must_use]
+    pub const fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            position: 0,
+            failed: false,
+        }
+    }
+
+    /// Offset of the next message to decode, or of the one that failed
+    #[must_use]
+    pub const fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Bytes not consumed yet, such as a partial message to keep for the next read
+    #[must_use]
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.bytes[self.position..]
+    }
+}
+
+impl Iterator for MessageIter<'_> {
+    type Item = Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.position == self.bytes.len() {
+            return None;
+        }
+        match decode_prefix(self.remaining()) {
+            Ok((message, consumed)) => {
+                self.position += consumed;
+                Some(Ok(message))
+            }
+            Err(err) => {
+                self.failed = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl core::iter::FusedIterator for MessageIter<'_> {}
+
+/// Validate a framed message, returning its header, deadline and payload range
+fn parse(bytes: &[u8]) -> Result<(MessageHeader, Option<u64>, Range<usize>)> {
     let total_available = bytes.len();
 
     // Check minimum size
@@ -87,32 +212,163 @@ pub fn decode(bytes: Bytes) -> Result<Message> {
         });
     }
 
-    // Extract payload
-    let payload = bytes.slice(HEADER_SIZE..HEADER_SIZE + payload_len);
+    // Extract deadline prefix and payload
+    let mut payload_start = HEADER_SIZE;
+    let deadline = if header.flags().has_deadline() {
+        if payload_len < DEADLINE_SIZE {
+            return Err(Error::BufferTooSmall {
+                needed: DEADLINE_SIZE,
+                got: payload_len,
+            });
+        }
+        payload_start += DEADLINE_SIZE;
+        Some(u64::from_le_bytes(
+            bytes[HEADER_SIZE..payload_start].try_into().unwrap(),
+        ))
+    } else {
+        None
+    };
 
     // Extract checksum
     let checksum_offset = HEADER_SIZE + payload_len;
     let checksum_slice = &bytes[checksum_offset..checksum_offset + CHECKSUM_SIZE];
     let stored_checksum = u64::from_le_bytes(checksum_slice.try_into().unwrap());
 
-    // Verify checksum
-    let calculated_checksum = xxh3_64(&bytes[0..checksum_offset]);
+    // Verify checksum, unless the sender opted out
+    let algorithm = header.flags().checksum_algorithm();
+    if algorithm.verifies() {
+        let calculated_checksum = algorithm.compute(&bytes[0..checksum_offset]);
+        if stored_checksum != calculated_checksum {
+            return Err(Error::ChecksumMismatch {
+                expected: calculated_checksum,
+                found: stored_checksum,
+            });
+        }
+    }
 
-    if stored_checksum != calculated_checksum {
-        return Err(Error::ChecksumMismatch {
-            expected: calculated_checksum,
-            found: stored_checksum,
-        });
+    Ok((header, deadline, payload_start..checksum_offset))
+}
+
+/// Incremental decoder for messages written back-to-back on a byte stream
+///
+/// Bytes may arrive in arbitrary fragments; each message is framed by the
+/// `payload_len` in its header, so no extra length prefix is needed.
+#[derive(Debug, Default)]
+pub struct StreamingDecoder {
+    buffer: BytesMut,
+}
+
+impl StreamingDecoder {
+    /// Create an empty decoder
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    // Create message
-    Ok(Message::from_parts(header, payload))
+    /// Append received bytes
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Append an owned buffer, adopting it without copying when nothing is buffered
+    pub fn push_owned(&mut self, bytes: Vec<u8>) {
+        if self.buffer.is_empty() {
+            self.buffer = BytesMut::from(Bytes::from(bytes));
+        } else {
+            self.buffer.extend_from_slice(&bytes);
+        }
+    }
+
+    /// Number of buffered bytes not yet decoded
+    #[must_use]
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Decode the next complete message, or `None` if more bytes are needed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a header or checksum is invalid. The stream cannot
+    /// be resynchronised after an error.
+    pub fn next_message(&mut self) -> Result<Option<Message>> {
+        if self.buffer.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+        let header = MessageHeader::from_bytes(&self.buffer[..HEADER_SIZE])?;
+        let payload_len =
+            usize::try_from(header.payload_len()).expect("validated payload length fits usize");
+        let total_size = HEADER_SIZE + payload_len + CHECKSUM_SIZE;
+        if self.buffer.len() < total_size {
+            return Ok(None);
+        }
+        decode(self.buffer.split_to(total_size).freeze()).map(Some)
+    }
+}
+
+/// Read exactly one message from an async byte stream
+///
+/// Reads the 32-byte header, validates it before allocating, then reads exactly
+/// `payload_len + CHECKSUM_SIZE` more bytes and decodes them like [`decode`]. Nothing past
+/// the message is consumed, so consecutive calls read consecutive messages.
+///
+/// # Errors
+///
+/// Returns the header and checksum errors of [`decode`], and [`Error::Io`] for read
+/// failures. A stream that ends before the message does fails with
+/// [`std::io::ErrorKind::UnexpectedEof`]; the message tells a clean close (no bytes read)
+/// apart from one mid-message.
+#[cfg(feature = "tokio")]
+pub async fn read_async<R>(reader: &mut R) -> Result<Message>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let mut header = [0u8; HEADER_SIZE];
+    read_full(reader, &mut header, 0, HEADER_SIZE).await?;
+    let payload_len = usize::try_from(MessageHeader::from_bytes(&header)?.payload_len())
+        .expect("validated payload length fits usize");
+    let total_size = HEADER_SIZE + payload_len + CHECKSUM_SIZE;
+
+    let mut frame = BytesMut::zeroed(total_size);
+    frame[..HEADER_SIZE].copy_from_slice(&header);
+    read_full(reader, &mut frame[HEADER_SIZE..], HEADER_SIZE, total_size).await?;
+    decode(frame.freeze())
+}
+
+/// Fill `buf`, which starts `offset` bytes into a message of `total` bytes
+#[cfg(feature = "tokio")]
+async fn read_full<R>(reader: &mut R, buf: &mut [u8], offset: usize, total: usize) -> Result<()>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    use std::io::{Error as IoError, ErrorKind};
+    use tokio::io::AsyncReadExt;
+
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = reader.read(&mut buf[filled..]).await?;
+        if read == 0 {
+            let got = offset + filled;
+            let err = if got == 0 {
+                IoError::new(ErrorKind::UnexpectedEof, "stream closed before a message")
+            } else {
+                IoError::new(
+                    ErrorKind::UnexpectedEof,
+                    alloc::format!("stream closed mid-message after {got} of {total} bytes"),
+                )
+            };
+            return Err(err.into());
+        }
+        filled += read;
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::MessageType;
+    use crate::protocol::{CUSTOM_TYPE_MAX, CUSTOM_TYPE_MIN, ChecksumAlgorithm, Flags};
     use bytes::Bytes;
 
     #[test]
@@ -127,6 +383,25 @@ mod tests {
         assert_eq!(decoded.trace_id(), original.trace_id());
     }
 
+    #[test]
+    fn test_custom_type_roundtrip_with_flags() {
+        let mut original = Message::new(MessageType::custom(CUSTOM_TYPE_MIN).unwrap(), b"custom");
+        original.set_flags(
+            Flags::new()
+                .with(Flags::REQUIRES_ACK)
+                .with(Flags::FINAL)
+                .with_checksum_algorithm(ChecksumAlgorithm::Crc32c),
+        );
+        let decoded = decode(Bytes::from(encode(&original))).unwrap();
+
+        assert_eq!(
+            decoded.message_type(),
+            Some(MessageType::custom(CUSTOM_TYPE_MIN).unwrap())
+        );
+        assert_eq!(decoded.flags(), original.flags());
+        assert_eq!(decoded.payload().as_ref(), b"custom");
+    }
+
     #[test]
     fn test_decode_invalid_magic() {
         let mut bytes = vec![0u8; MIN_MESSAGE_SIZE];
@@ -149,6 +424,163 @@ mod tests {
         assert!(matches!(result, Err(Error::ChecksumMismatch { .. })));
     }
 
+    #[test]
+    fn test_checksum_algorithms_roundtrip_and_detect_corruption() {
+        for algorithm in [ChecksumAlgorithm::Xxh3, ChecksumAlgorithm::Crc32c] {
+            let original = Message::builder(MessageType::Event)
+                .payload(&b"checksummed payload"[..])
+                .checksum_algorithm(algorithm)
+                .build();
+            let encoded = encode(&original);
+            let trailer = u64::from_le_bytes(encoded[encoded.len() - 8..].try_into().unwrap());
+            assert_eq!(
+                trailer,
+                algorithm.compute(&encoded[..encoded.len() - 8]),
+                "{algorithm}"
+            );
+
+            let decoded = decode_ref(&encoded).unwrap();
+            assert_eq!(decoded.checksum_algorithm(), algorithm);
+            assert_eq!(decoded.payload().as_ref(), b"checksummed payload");
+
+            for offset in [HEADER_SIZE + 3, encoded.len() - 1] {
+                let mut corrupted = encoded.clone();
+                corrupted[offset] ^= 0x01;
+                assert!(
+                    matches!(decode_ref(&corrupted), Err(Error::ChecksumMismatch { .. })),
+                    "{algorithm} at {offset}"
+                );
+            }
+        }
+
+        // CRC32C frames carry a 32-bit value in the 64-bit trailer.
+        let msg = Message::builder(MessageType::Call)
+            .checksum_algorithm(ChecksumAlgorithm::Crc32c)
+            .build();
+        let encoded = encode(&msg);
+        assert_eq!(&encoded[encoded.len() - 4..], &[0; 4]);
+    }
+
+    #[test]
+    fn test_checksum_none_skips_verification() {
+        let msg = Message::builder(MessageType::Event)
+            .payload(&b"trusted link"[..])
+            .checksum_algorithm(ChecksumAlgorithm::None)
+            .build();
+        let mut encoded = encode(&msg);
+        let len = encoded.len();
+        assert_eq!(&encoded[len - 8..], &[0; 8]);
+
+        // Payload and trailer corruption pass unnoticed: nothing is verified.
+        encoded[HEADER_SIZE] ^= 0xFF;
+        encoded[len - 1] = 0xAB;
+        let decoded = decode(Bytes::from(encoded)).unwrap();
+        assert_eq!(decoded.checksum_algorithm(), ChecksumAlgorithm::None);
+        assert_eq!(decoded.payload()[0], b't' ^ 0xFF);
+    }
+
+    #[test]
+    fn test_reserved_checksum_bits_rejected() {
+        let msg = Message::new(MessageType::Call, b"x");
+        let mut encoded = encode(&msg);
+        encoded[5] |= Flags::CHECKSUM_MASK;
+        assert!(matches!(
+            decode_ref(&encoded),
+            Err(Error::InvalidFlags { .. })
+        ));
+        assert_eq!(
+            Flags::new()
+                .with(Flags::FINAL)
+                .with_checksum_algorithm(ChecksumAlgorithm::Crc32c)
+                .with_checksum_algorithm(ChecksumAlgorithm::None)
+                .as_u8(),
+            Flags::FINAL | (2 << 5)
+        );
+    }
+
+    #[test]
+    fn decode_prefix_reports_consumed_length() {
+        let first = Message::new(MessageType::Call, b"first");
+        let mut bytes = encode(&first);
+        let first_len = bytes.len();
+        bytes.extend_from_slice(&encode(&Message::new(MessageType::Event, b"second")));
+
+        let (decoded, consumed) = decode_prefix(&bytes).unwrap();
+        assert_eq!(consumed, first_len);
+        assert_eq!(decoded.payload().as_ref(), b"first");
+        let (decoded, consumed) = decode_prefix(&bytes[first_len..]).unwrap();
+        assert_eq!(consumed, bytes.len() - first_len);
+        assert_eq!(decoded.payload().as_ref(), b"second");
+    }
+
+    #[test]
+    fn message_iter_walks_concatenated_messages() {
+        let messages = [
+            Message::new(MessageType::Call, b"one"),
+            Message::new(MessageType::Event, b""),
+            Message::new(MessageType::Response, &[7u8; 300][..]),
+        ];
+        let bytes: Vec<u8> = messages.iter().flat_map(encode).collect();
+
+        let mut iter = MessageIter::new(&bytes);
+        for expected in &messages {
+            let decoded = iter.next().unwrap().unwrap();
+            assert_eq!(decoded.message_id(), expected.message_id());
+            assert_eq!(decoded.payload(), expected.payload());
+        }
+        assert!(iter.next().is_none());
+        assert_eq!(iter.position(), bytes.len());
+        assert!(MessageIter::new(&[]).next().is_none());
+    }
+
+    #[test]
+    fn message_iter_reports_bytes_needed_for_truncated_tail() {
+        let first = encode(&Message::new(MessageType::Call, b"complete"));
+        let second = encode(&Message::new(MessageType::Call, b"cut short"));
+        let mut bytes = first.clone();
+        bytes.extend_from_slice(&second[..second.len() - 5]);
+
+        let mut iter = MessageIter::new(&bytes);
+        assert!(iter.next().unwrap().is_ok());
+        assert!(matches!(
+            iter.next(),
+            Some(Err(Error::Incomplete { needed: 5 }))
+        ));
+        assert!(iter.next().is_none());
+        assert_eq!(iter.position(), first.len());
+        assert_eq!(iter.remaining().len(), second.len() - 5);
+
+        // Before the header is complete only the minimum is known.
+        assert!(matches!(
+            decode_prefix(&second[..10]),
+            Err(Error::Incomplete { needed }) if needed == MIN_MESSAGE_SIZE - 10
+        ));
+    }
+
+    #[test]
+    fn message_iter_surfaces_garbage_and_its_position() {
+        let first = encode(&Message::new(MessageType::Call, b"good"));
+        let mut bytes = first.clone();
+        bytes.extend_from_slice(&[0xAB; MIN_MESSAGE_SIZE]);
+        bytes.extend_from_slice(&encode(&Message::new(MessageType::Call, b"unreached")));
+
+        let mut iter = MessageIter::new(&bytes);
+        assert!(iter.next().unwrap().is_ok());
+        assert!(matches!(
+            iter.next(),
+            Some(Err(Error::InvalidMagic { found: 0xABAB_ABAB }))
+        ));
+        assert!(iter.next().is_none());
+        assert_eq!(iter.position(), first.len());
+
+        let mut corrupted = first.clone();
+        corrupted.extend_from_slice(&first);
+        corrupted[first.len() + HEADER_SIZE] ^= 0xFF;
+        let results: Vec<_> = MessageIter::new(&corrupted).collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[1], Err(Error::ChecksumMismatch { .. })));
+    }
+
     #[test]
     fn test_decode_buffer_too_small() {
         let bytes = vec![0u8; 10]; // Too small
@@ -156,6 +588,102 @@ mod tests {
         assert!(matches!(result, Err(Error::BufferTooSmall { .. })));
     }
 
+    #[test]
+    fn test_deadline_roundtrip() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let deadline = UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456);
+        let original = Message::builder(MessageType::Call)
+            .payload(&b"rpc"[..])
+            .deadline(deadline)
+            .build();
+        let encoded = encode(&original);
+        assert_eq!(encoded.len(), MIN_MESSAGE_SIZE + DEADLINE_SIZE + 3);
+
+        let decoded = decode(Bytes::from(encoded)).unwrap();
+        assert!(decoded.flags().has_deadline());
+        assert_eq!(decoded.deadline(), Some(deadline));
+        assert_eq!(decoded.payload().as_ref(), b"rpc");
+        assert_eq!(decoded.header().payload_len(), (DEADLINE_SIZE + 3) as u64);
+    }
+
+    #[test]
+    fn test_decode_truncated_deadline() {
+        let mut original = Message::new(MessageType::Call, b"abc");
+        original
+            .header_mut()
+            .set_flags(crate::Flags::new().with(crate::Flags::HAS_DEADLINE));
+        let encoded = encode(&original);
+
+        let result = decode(Bytes::from(encoded));
+        assert!(matches!(result, Err(Error::BufferTooSmall { .. })));
+    }
+
+    #[test]
+    fn test_decode_ref_matches_decode() {
+        let plain = Message::new(MessageType::Call, b"borrowed payload");
+        let with_deadline = Message::builder(MessageType::Response)
+            .payload(vec![9u8; 300])
+            .deadline(std::time::UNIX_EPOCH + std::time::Duration::from_secs(5))
+            .build();
+        for original in [plain, with_deadline] {
+            let encoded = encode(&original);
+            let owned = decode(Bytes::from(encoded.clone())).unwrap();
+            let borrowed = decode_ref(&encoded).unwrap();
+            assert_eq!(borrowed.header().to_bytes(), owned.header().to_bytes());
+            assert_eq!(borrowed.payload(), owned.payload());
+            assert_eq!(borrowed.deadline(), owned.deadline());
+        }
+    }
+
+    #[test]
+    fn test_try_from_slice_surfaces_decode_errors() {
+        let original = Message::new(MessageType::Call, b"test");
+        let mut corrupt = encode(&original);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xFF;
+        let mut bad_magic = encode(&original);
+        bad_magic[0] ^= 0xFF;
+
+        for bytes in [corrupt, bad_magic, vec![0u8; 10]] {
+            assert_eq!(
+                format!("{:?}", Message::try_from(bytes.as_slice()).unwrap_err()),
+                format!("{:?}", decode(Bytes::from(bytes)).unwrap_err())
+            );
+        }
+
+        let encoded = encode(&original);
+        let message = Message::try_from(encoded.as_slice()).unwrap();
+        assert_eq!(Bytes::from(message), Bytes::from(encoded));
+    }
+
+    #[test]
+    fn test_streaming_decoder_reassembles_fragments() {
+        let first = Message::new(MessageType::Event, b"first");
+        let second = Message::new(MessageType::Event, vec![7u8; 300]);
+        let mut bytes = encode(&first);
+        bytes.extend_from_slice(&encode(&second));
+
+        let mut decoder = StreamingDecoder::new();
+        let mut messages = Vec::new();
+        for fragment in bytes.chunks(7) {
+            decoder.push(fragment);
+            while let Some(message) = decoder.next_message().unwrap() {
+                messages.push(message);
+            }
+        }
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].payload().as_ref(), b"first");
+        assert_eq!(messages[1].payload().len(), 300);
+        assert_eq!(decoder.buffered(), 0);
+
+        decoder.push(&[0u8; HEADER_SIZE]);
+        assert!(matches!(
+            decoder.next_message(),
+            Err(Error::InvalidMagic { .. })
+        ));
+    }
+
     #[test]
     fn test_encode_performance() {
         use std::time::Instant;
@@ -200,6 +728,7 @@ mod tests {
     #[cfg(test)]
     mod proptests {
         use super::*;
+        use crate::protocol::checksum;
         use crate::{MAGIC_NUMBER, MAX_PAYLOAD_SIZE};
         use proptest::prelude::*;
 
@@ -217,6 +746,8 @@ mod tests {
                 Just(MessageType::StreamClose),
                 Just(MessageType::Ack),
                 Just(MessageType::Error),
+                (CUSTOM_TYPE_MIN..=CUSTOM_TYPE_MAX)
+                    .prop_filter_map("custom range", MessageType::custom),
             ]
         }
 
@@ -319,7 +850,7 @@ mod tests {
 
                 // Recalculate checksum for the modified header
                 let checksum_offset = HEADER_SIZE + 1024;
-                let checksum = xxh3_64(&encoded[0..checksum_offset]);
+                let checksum = checksum(&encoded[0..checksum_offset]);
                 encoded[checksum_offset..checksum_offset + 8].copy_from_slice(&checksum.to_le_bytes());
 
                 let result = decode(Bytes::from(encoded));
@@ -1,5 +1,7 @@
 //! MXP error types
 
+use alloc::string::{FromUtf8Error, String};
+
 use thiserror::Error;
 
 /// MXP protocol errors
@@ -46,6 +48,13 @@ pub enum Error {
         got: usize,
     },
 
+    /// Buffer ends partway through a message; read more bytes and retry
+    #[error("incomplete message: need {needed} more bytes")]
+    Incomplete {
+        /// Additional bytes required (a lower bound while the header is still partial)
+        needed: usize,
+    },
+
     /// Reserved bits must be zero
     #[error("reserved field {field} must be zero (found {value})")]
     ReservedFieldNonZero {
@@ -55,6 +64,13 @@ pub enum Error {
         value: u64,
     },
 
+    /// Invalid priority bits in the header
+    #[error("invalid message priority: {value}")]
+    InvalidPriority {
+        /// Priority bits
+        value: u8,
+    },
+
     /// Invalid flags value
     #[error("invalid flags value: {flags:#010b}")]
     InvalidFlags {
@@ -62,7 +78,47 @@ pub enum Error {
         flags: u8,
     },
 
+    /// Chunk of a chunked message arrived out of sequence
+    #[error("chunk {found} of message {message_id:#x} out of order (expected {expected})")]
+    ChunkOutOfOrder {
+        /// Logical message ID shared by the chunks
+        message_id: u64,
+        /// Index the reassembler was waiting for
+        expected: u32,
+        /// Index that arrived
+        found: u32,
+    },
+
+    /// Encrypted payload failed authentication (wrong key or tampered message)
+    #[error("payload decryption failed for message {message_id:#x}")]
+    DecryptionFailed {
+        /// ID of the message that failed to decrypt
+        message_id: u64,
+    },
+
+    /// Message deadline passed before it could be sent or answered
+    #[error("deadline exceeded for message {message_id:#x}")]
+    DeadlineExceeded {
+        /// ID of the expired message
+        message_id: u64,
+    },
+
+    /// Too many requests awaiting a reply
+    #[error("too many pending requests (limit {limit})")]
+    TooManyPending {
+        /// Maximum number of pending requests
+        limit: usize,
+    },
+
+    /// A request with this message ID is already pending
+    #[error("request {message_id:#x} is already pending")]
+    DuplicateRequest {
+        /// Duplicated message ID
+        message_id: u64,
+    },
+
     /// IO error
+    #[cfg(feature = "std")]
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
@@ -76,7 +132,7 @@ pub enum Error {
 
     /// Invalid UTF-8
     #[error("invalid UTF-8: {0}")]
-    InvalidUtf8(#[from] std::string::FromUtf8Error),
+    InvalidUtf8(#[from] FromUtf8Error),
 
     /// Other error
     #[error("{0}")]
@@ -84,4 +140,206 @@ pub enum Error {
 }
 
 /// Result type alias
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = core::result::Result<T, Error>;
+
+/// Stable numeric error codes carried in `Error` messages on the wire.
+///
+/// `0x00xx` codes are framing/decoding failures, `0x01xx` request lifecycle failures and
+/// `0x02xx` transport failures. Values never change once assigned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u16)]
+pub enum ErrorCode {
+    /// Invalid magic number
+    InvalidMagic = 0x0001,
+    /// Invalid message type
+    InvalidMessageType = 0x0002,
+    /// Checksum mismatch
+    ChecksumMismatch = 0x0003,
+    /// Payload too large
+    PayloadTooLarge = 0x0004,
+    /// Buffer too small
+    BufferTooSmall = 0x0005,
+    /// Reserved bits set
+    ReservedFieldNonZero = 0x0006,
+    /// Invalid priority bits
+    InvalidPriority = 0x0007,
+    /// Invalid flags value
+    InvalidFlags = 0x0008,
+    /// Invalid UTF-8
+    InvalidUtf8 = 0x0009,
+    /// Chunk out of order
+    ChunkOutOfOrder = 0x000A,
+    /// Payload decryption failed
+    DecryptionFailed = 0x000B,
+    /// Message incomplete
+    Incomplete = 0x000C,
+    /// Deadline exceeded
+    DeadlineExceeded = 0x0100,
+    /// Too many pending requests
+    TooManyPending = 0x0101,
+    /// Duplicate request ID
+    DuplicateRequest = 0x0102,
+    /// IO error
+    Io = 0x0200,
+    /// Transport connection error
+    Connection = 0x0201,
+    /// Transport stream error
+    Stream = 0x0202,
+    /// Unclassified error
+    Other = 0xFFFF,
+}
+
+impl ErrorCode {
+    const ALL: [Self; 19] = [
+        Self::InvalidMagic,
+        Self::InvalidMessageType,
+        Self::ChecksumMismatch,
+        Self::PayloadTooLarge,
+        Self::BufferTooSmall,
+        Self::ReservedFieldNonZero,
+        Self::InvalidPriority,
+        Self::InvalidFlags,
+        Self::InvalidUtf8,
+        Self::ChunkOutOfOrder,
+        Self::DecryptionFailed,
+        Self::Incomplete,
+        Self::DeadlineExceeded,
+        Self::TooManyPending,
+        Self::DuplicateRequest,
+        Self::Io,
+        Self::Connection,
+        Self::Stream,
+        Self::Other,
+    ];
+
+    /// Wire value of the code.
+    #[must_use]
+    pub const fn as_u16(self) -> u16 {
+        self as u16
+    }
+
+    /// Parse a wire value, returning `None` for unassigned codes.
+    #[must_use]
+    pub fn from_u16(value: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|code| code.as_u16() == value)
+    }
+}
+
+impl Error {
+    /// Stable code identifying the error kind, for `Error` messages.
+    #[must_use]
+    pub const fn code(&self) -> ErrorCode {
+        match self {
+            Self::InvalidMagic { .. } => ErrorCode::InvalidMagic,
+            Self::InvalidMessageType { .. } => ErrorCode::InvalidMessageType,
+            Self::ChecksumMismatch { .. } => ErrorCode::ChecksumMismatch,
+            Self::PayloadTooLarge { .. } => ErrorCode::PayloadTooLarge,
+            Self::BufferTooSmall { .. } => ErrorCode::BufferTooSmall,
+            Self::Incomplete { .. } => ErrorCode::Incomplete,
+            Self::ReservedFieldNonZero { .. } => ErrorCode::ReservedFieldNonZero,
+            Self::InvalidPriority { .. } => ErrorCode::InvalidPriority,
+            Self::InvalidFlags { .. } => ErrorCode::InvalidFlags,
+            Self::ChunkOutOfOrder { .. } => ErrorCode::ChunkOutOfOrder,
+            Self::DecryptionFailed { .. } => ErrorCode::DecryptionFailed,
+            Self::DeadlineExceeded { .. } => ErrorCode::DeadlineExceeded,
+            Self::TooManyPending { .. } => ErrorCode::TooManyPending,
+            Self::DuplicateRequest { .. } => ErrorCode::DuplicateRequest,
+            #[cfg(feature = "std")]
+            Self::Io(_) => ErrorCode::Io,
+            Self::Connection(_) => ErrorCode::Connection,
+            Self::Stream(_) => ErrorCode::Stream,
+            Self::InvalidUtf8(_) => ErrorCode::InvalidUtf8,
+            Self::Other(_) => ErrorCode::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::transport::{CryptoError, HandshakeError};
+
+    fn all_errors() -> Vec<Error> {
+        vec![
+            Error::InvalidMagic { found: 0 },
+            Error::InvalidMessageType { type_byte: 0x7F },
+            Error::ChecksumMismatch {
+                expected: 1,
+                found: 2,
+            },
+            Error::PayloadTooLarge { size: 2, max: 1 },
+            Error::BufferTooSmall { needed: 2, got: 1 },
+            Error::Incomplete { needed: 1 },
+            Error::ReservedFieldNonZero {
+                field: "reserved",
+                value: 1,
+            },
+            Error::InvalidPriority { value: 3 },
+            Error::InvalidFlags { flags: 0xFF },
+            Error::ChunkOutOfOrder {
+                message_id: 1,
+                expected: 0,
+                found: 2,
+            },
+            Error::DecryptionFailed { message_id: 1 },
+            Error::DeadlineExceeded { message_id: 1 },
+            Error::TooManyPending { limit: 1 },
+            Error::DuplicateRequest { message_id: 1 },
+            Error::Io(std::io::Error::other("io")),
+            Error::Connection("closed".into()),
+            Error::Stream("reset".into()),
+            Error::InvalidUtf8(String::from_utf8(vec![0xFF]).unwrap_err()),
+            Error::Other("other".into()),
+        ]
+    }
+
+    #[test]
+    fn every_error_has_distinct_code_and_message() {
+        let errors = all_errors();
+        let codes: HashSet<ErrorCode> = errors.iter().map(Error::code).collect();
+        assert_eq!(codes.len(), errors.len());
+        assert_eq!(codes.len(), ErrorCode::ALL.len());
+        for err in &errors {
+            assert!(!err.to_string().is_empty(), "{err:?}");
+            assert_eq!(ErrorCode::from_u16(err.code().as_u16()), Some(err.code()));
+        }
+        assert_eq!(ErrorCode::from_u16(0), None);
+    }
+
+    #[test]
+    fn transport_errors_display_and_chain() {
+        let crypto = [
+            CryptoError::InvalidKeyLength,
+            CryptoError::InvalidNonceLength,
+            CryptoError::InvalidTagLength,
+            CryptoError::AuthenticationFailed,
+            CryptoError::KeyDerivationFailed,
+        ];
+        for err in &crypto {
+            assert!(!err.to_string().is_empty());
+        }
+
+        let handshake = [
+            HandshakeError::UnexpectedMessage,
+            HandshakeError::MalformedMessage,
+            HandshakeError::MissingKeyMaterial,
+            HandshakeError::Crypto(CryptoError::AuthenticationFailed),
+            HandshakeError::ReplayDetected,
+            HandshakeError::InvalidTicket,
+            HandshakeError::Timeout,
+            HandshakeError::RetryRequired,
+            HandshakeError::InvalidRetryToken,
+        ];
+        for err in &handshake {
+            assert!(!err.to_string().is_empty());
+        }
+
+        let err: Box<dyn std::error::Error> = Box::new(crate::transport::TransportError::from(
+            HandshakeError::Crypto(CryptoError::AuthenticationFailed),
+        ));
+        assert!(err.to_string().contains("authentication failed"));
+        assert!(err.source().is_some());
+    }
+}
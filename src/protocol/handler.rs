@@ -0,0 +1,280 @@
+//! Per-type message handlers
+//!
+//! A [`HandlerRegistry`] maps message type bytes, built-in or in the custom range, to the
+//! closures answering them. The serve loop (`AsyncConnection::serve` with the `tokio`
+//! feature) hands each received message to the handler for its type and sends back
+//! whatever it returns.
+//!
+//! Registration also records whether a type expects a reply and whether it is one. Built-in
+//! types default to [`MessageType::requires_response`] and [`MessageType::is_response`];
+//! custom types default to neither, and either can be overridden per type. Replies to
+//! responses are never sent, so two peers serving each other cannot answer back and forth
+//! forever.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use tracing::{debug, trace};
+
+use super::{Message, MessageType};
+
+type Handler = dyn Fn(&Message) -> Option<Message> + Send + Sync;
+
+/// Reply semantics of a registered message type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HandlerOptions {
+    /// Messages of this type expect a reply
+    pub requires_response: bool,
+    /// Messages of this type answer an earlier message, so replies to them are dropped
+    pub is_response: bool,
+}
+
+impl HandlerOptions {
+    /// Options matching the type's own [`requires_response`](MessageType::requires_response)
+    /// and [`is_response`](MessageType::is_response)
+    #[must_use]
+    pub const fn for_type(msg_type: MessageType) -> Self {
+        Self {
+            requires_response: msg_type.requires_response(),
+            is_response: msg_type.is_response(),
+        }
+    }
+}
+
+struct Registration {
+    handler: Box<Handler>,
+    options: HandlerOptions,
+}
+
+/// Handlers keyed by message type byte
+#[derive(Default)]
+pub struct HandlerRegistry {
+    handlers: HashMap<u8, Registration>,
+}
+
+impl fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut types: Vec<u8> = self.handlers.keys().copied().collect();
+        types.sort_unstable();
+        f.debug_struct("HandlerRegistry")
+            .field("types", &types)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HandlerRegistry {
+    /// Create an empty registry
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle `msg_type` with `handler`, keeping the type's default reply semantics
+    ///
+    /// The handler returns the reply to send, if any. Registering a type again replaces
+    /// its handler.
+    pub fn register(
+        &mut self,
+        msg_type: MessageType,
+        handler: impl Fn(&Message) -> Option<Message> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.register_with(msg_type, HandlerOptions::for_type(msg_type), handler)
+    }
+
+    /// [`register`](Self::register) with explicit reply semantics for the type
+    pub fn register_with(
+        &mut self,
+        msg_type: MessageType,
+        options: HandlerOptions,
+        handler: impl Fn(&Message) -> Option<Message> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.handlers.insert(
+            msg_type.as_u8(),
+            Registration {
+                handler: Box::new(handler),
+                options,
+            },
+        );
+        self
+    }
+
+    /// Remove the handler for `msg_type`, returning whether there was one
+    pub fn unregister(&mut self, msg_type: MessageType) -> bool {
+        self.handlers.remove(&msg_type.as_u8()).is_some()
+    }
+
+    /// Whether a handler is registered for `msg_type`
+    #[must_use]
+    pub fn handles(&self, msg_type: MessageType) -> bool {
+        self.handlers.contains_key(&msg_type.as_u8())
+    }
+
+    /// Whether `msg_type` expects a reply, as registered or by the type's default
+    #[must_use]
+    pub fn requires_response(&self, msg_type: MessageType) -> bool {
+        self.options(msg_type).requires_response
+    }
+
+    /// Whether `msg_type` answers an earlier message, as registered or by the type's default
+    #[must_use]
+    pub fn is_response(&self, msg_type: MessageType) -> bool {
+        self.options(msg_type).is_response
+    }
+
+    fn options(&self, msg_type: MessageType) -> HandlerOptions {
+        self.handlers
+            .get(&msg_type.as_u8())
+            .map_or_else(|| HandlerOptions::for_type(msg_type), |entry| entry.options)
+    }
+
+    /// Run the handler for `message`'s type, returning the reply to send
+    ///
+    /// Returns `None` when no handler is registered or the handler produced no reply. The
+    /// handler still runs for responses, but whatever it returns is dropped.
+    #[must_use]
+    pub fn dispatch(&self, message: &Message) -> Option<Message> {
+        let type_byte = message.header().msg_type_byte();
+        let Some(entry) = self.handlers.get(&type_byte) else {
+            trace!(type_byte, "no handler for message type");
+            return None;
+        };
+        let reply = (entry.handler)(message);
+        match reply {
+            Some(_) if entry.options.is_response => {
+                debug!(type_byte, "dropping reply to a response");
+                None
+            }
+            None if entry.options.requires_response => {
+                debug!(
+                    type_byte,
+                    message_id = message.message_id(),
+                    "no reply to a message that requires one"
+                );
+                None
+            }
+            reply => reply,
+        }
+    }
+
+    /// Number of registered handlers
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    /// Whether no handler is registered
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::heartbeat_ack;
+
+    fn custom(byte: u8) -> MessageType {
+        MessageType::custom(byte).expect("custom range")
+    }
+
+    fn echo(reply_type: MessageType) -> impl Fn(&Message) -> Option<Message> + Send + Sync {
+        move |message| {
+            Some(Message::with_ids(
+                reply_type,
+                message.message_id(),
+                message.trace_id(),
+                message.payload().clone(),
+            ))
+        }
+    }
+
+    #[test]
+    fn dispatches_by_type_including_custom_types() {
+        let mut registry = HandlerRegistry::new();
+        registry
+            .register(MessageType::Call, echo(MessageType::Response))
+            .register(custom(0x90), echo(custom(0x91)))
+            .register(MessageType::AgentHeartbeat, heartbeat_ack);
+        assert_eq!(registry.len(), 3);
+
+        let call = Message::new(MessageType::Call, b"call".to_vec());
+        let reply = registry.dispatch(&call).unwrap();
+        assert_eq!(reply.message_type(), Some(MessageType::Response));
+        assert_eq!(reply.message_id(), call.message_id());
+
+        let request = Message::new(custom(0x90), b"custom".to_vec());
+        let reply = registry.dispatch(&request).unwrap();
+        assert_eq!(reply.message_type(), Some(custom(0x91)));
+        assert_eq!(reply.payload().as_ref(), b"custom");
+
+        let heartbeat = Message::new(MessageType::AgentHeartbeat, Vec::new());
+        let reply = registry.dispatch(&heartbeat).unwrap();
+        assert_eq!(reply.message_type(), Some(MessageType::Ack));
+
+        let unhandled = Message::new(custom(0x91), Vec::new());
+        assert!(registry.dispatch(&unhandled).is_none());
+    }
+
+    #[test]
+    fn reply_semantics_default_per_type_and_can_be_overridden() {
+        let mut registry = HandlerRegistry::new();
+        let query = custom(0xA0);
+        let answer = custom(0xA1);
+        assert!(!registry.requires_response(query));
+        assert!(registry.requires_response(MessageType::Call));
+        assert!(registry.is_response(MessageType::Ack));
+
+        registry
+            .register_with(
+                query,
+                HandlerOptions {
+                    requires_response: true,
+                    is_response: false,
+                },
+                echo(answer),
+            )
+            .register_with(
+                answer,
+                HandlerOptions {
+                    requires_response: false,
+                    is_response: true,
+                },
+                |_| None,
+            )
+            .register(MessageType::Call, |_| None);
+        assert!(registry.requires_response(query));
+        assert!(!registry.is_response(query));
+        assert!(registry.is_response(answer));
+        assert!(registry.requires_response(MessageType::Call));
+
+        // The answer type is a response, so its handler's reply is dropped.
+        registry.register_with(
+            answer,
+            HandlerOptions {
+                requires_response: false,
+                is_response: true,
+            },
+            echo(query),
+        );
+        let request = Message::new(query, Vec::new());
+        let reply = registry.dispatch(&request).expect("query is answered");
+        assert_eq!(reply.message_type(), Some(answer));
+        assert!(registry.dispatch(&reply).is_none());
+
+        assert!(registry.unregister(query));
+        assert!(!registry.unregister(query));
+        assert!(!registry.handles(query));
+        assert!(!registry.requires_response(query));
+    }
+
+    #[test]
+    fn registering_a_type_again_replaces_its_handler() {
+        let mut registry = HandlerRegistry::new();
+        registry.register(MessageType::Event, |_| None);
+        registry.register(MessageType::Event, echo(MessageType::Ack));
+        assert_eq!(registry.len(), 1);
+        let event = Message::new(MessageType::Event, Vec::new());
+        assert!(registry.dispatch(&event).is_some());
+    }
+}
@@ -2,7 +2,10 @@
 //!
 //! The header is 32 bytes and cache-aligned for performance.
 
-use super::{Flags, MAGIC_NUMBER, MessageType};
+use super::{Flags, MAGIC_NUMBER, MessageType, Priority};
+
+/// Bits of the reserved field carrying the message priority
+const PRIORITY_MASK: u16 = 0b11;
 
 /// MXP message header (32 bytes, cache-aligned)
 ///
@@ -14,7 +17,7 @@ use super::{Flags, MAGIC_NUMBER, MessageType};
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 /// |                        Magic Number (4)                       |
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-/// | Message Type  |     Flags     |          Reserved             |
+/// | Message Type  |     Flags     |Pri|       Reserved (14)       |
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 /// |                                                               |
 /// +                      Message ID (8)                           +
@@ -29,6 +32,8 @@ use super::{Flags, MAGIC_NUMBER, MessageType};
 /// |                                                               |
 /// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 /// ```
+///
+/// The low two bits of the reserved field carry the [`Priority`]; the rest must be zero.
 #[repr(C, align(32))]
 #[derive(Debug, Clone, Copy)]
 pub struct MessageHeader {
@@ -91,6 +96,24 @@ impl MessageHeader {
         self.flags = flags.as_u8();
     }
 
+    /// Set payload length (deadline prefix included)
+    #[cfg(feature = "std")]
+    pub(super) const fn set_payload_len(&mut self, payload_len: u64) {
+        self.payload_len = payload_len;
+    }
+
+    /// Get priority
+    #[must_use]
+    pub fn priority(&self) -> Priority {
+        Priority::from_u8((self.reserved & PRIORITY_MASK) as u8)
+            .expect("priority validated during parsing")
+    }
+
+    /// Set priority
+    pub fn set_priority(&mut self, priority: Priority) {
+        self.reserved = (self.reserved & !PRIORITY_MASK) | u16::from(priority.as_u8());
+    }
+
     /// Get message ID
     #[must_use]
     pub const fn message_id(&self) -> u64 {
@@ -116,14 +139,21 @@ impl MessageHeader {
             return Err(super::Error::InvalidMagic { found: self.magic });
         }
 
-        // Check reserved bits
-        if self.reserved != 0 {
+        // Check reserved bits (the low two carry the priority)
+        let reserved = self.reserved & !PRIORITY_MASK;
+        if reserved != 0 {
             return Err(super::Error::ReservedFieldNonZero {
                 field: "header.reserved",
-                value: u64::from(self.reserved),
+                value: u64::from(reserved),
             });
         }
 
+        // Check priority
+        let priority = (self.reserved & PRIORITY_MASK) as u8;
+        if Priority::from_u8(priority).is_none() {
+            return Err(super::Error::InvalidPriority { value: priority });
+        }
+
         // Check message type
         if self.message_type().is_none() {
             return Err(super::Error::InvalidMessageType {
@@ -228,6 +258,71 @@ mod tests {
         assert_eq!(decoded.payload_len(), 789);
     }
 
+    #[test]
+    fn test_header_accepts_only_known_and_custom_types() {
+        let mut bytes = MessageHeader::new(MessageType::Call, 1, 2, 3).to_bytes();
+        for type_byte in [0x80, 0xC3, 0xEF] {
+            bytes[4] = type_byte;
+            let decoded = MessageHeader::from_bytes(&bytes).unwrap();
+            assert_eq!(
+                decoded.message_type(),
+                Some(MessageType::custom(type_byte).unwrap())
+            );
+        }
+        for type_byte in [0x00, 0x7F, 0xF2] {
+            bytes[4] = type_byte;
+            assert!(matches!(
+                MessageHeader::from_bytes(&bytes),
+                Err(super::super::Error::InvalidMessageType { type_byte: found }) if found == type_byte
+            ));
+        }
+    }
+
+    #[test]
+    fn test_header_priority_roundtrip() {
+        for priority in [Priority::Interactive, Priority::Control, Priority::Bulk] {
+            let mut header = MessageHeader::new(MessageType::Event, 1, 2, 3);
+            header.set_priority(priority);
+            let decoded = MessageHeader::from_bytes(&header.to_bytes()).unwrap();
+            assert_eq!(decoded.priority(), priority);
+            assert_eq!(decoded.message_id(), 1);
+        }
+    }
+
+    #[test]
+    fn test_header_without_priority_defaults_to_interactive() {
+        let bytes = MessageHeader::new(MessageType::Call, 1, 2, 3).to_bytes();
+        assert_eq!(&bytes[6..8], &[0, 0]);
+        let decoded = MessageHeader::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.priority(), Priority::Interactive);
+    }
+
+    #[test]
+    fn test_header_rejects_reserved_bits() {
+        let mut header = MessageHeader::new(MessageType::Call, 1, 2, 3);
+        header.set_priority(Priority::Bulk);
+        let mut bytes = header.to_bytes();
+        bytes[6] |= 0b100;
+        assert!(matches!(
+            MessageHeader::from_bytes(&bytes),
+            Err(super::super::Error::ReservedFieldNonZero { value: 0b100, .. })
+        ));
+
+        let mut bytes = header.to_bytes();
+        bytes[7] = 0x80;
+        assert!(matches!(
+            MessageHeader::from_bytes(&bytes),
+            Err(super::super::Error::ReservedFieldNonZero { .. })
+        ));
+
+        let mut bytes = header.to_bytes();
+        bytes[6] = 0b11;
+        assert!(matches!(
+            MessageHeader::from_bytes(&bytes),
+            Err(super::super::Error::InvalidPriority { value: 3 })
+        ));
+    }
+
     #[test]
     fn test_invalid_magic() {
         let mut bytes = [0u8; 32];
@@ -0,0 +1,682 @@
+//! Liveness checks with `AgentHeartbeat` messages
+//!
+//! The initiator sends a heartbeat every interval, carrying an increasing
+//! sequence number as its `message_id` and as an 8-byte little-endian payload,
+//! or as the `seq` of a [`HeartbeatBody`] with load metadata. The peer answers
+//! with an `Ack` echoing both (see [`heartbeat_ack`]). A
+//! heartbeat not acknowledged within the timeout counts as a miss; after
+//! `max_misses` consecutive misses the peer is reported unhealthy until the
+//! next acknowledged heartbeat.
+//!
+//! [`HeartbeatMonitor`] is the transport-agnostic state machine. With the
+//! `tokio` feature, [`HeartbeatHandle`] drives one on a background task and
+//! [`answer_heartbeats`] acknowledges heartbeats on the receiving side.
+//! [`HealthTable`] keeps the latest [`HeartbeatBody`] per agent for load-aware
+//! selection.
+//!
+//! RTTs are measured with a monotonic [`Instant`] rather than wall-clock time.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use tracing::debug;
+
+use super::{HeartbeatBody, Message, MessageType};
+
+/// Default interval between heartbeats
+pub const DEFAULT_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Default time to wait for a heartbeat to be acknowledged
+pub const DEFAULT_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Heartbeat timing configuration
+#[derive(Debug, Clone)]
+pub struct HeartbeatConfig {
+    /// Time between consecutive heartbeats
+    pub interval: Duration,
+    /// Time a heartbeat may go unacknowledged before it counts as missed
+    pub timeout: Duration,
+    /// Consecutive misses after which the peer is considered unhealthy
+    pub max_misses: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_HEARTBEAT_INTERVAL,
+            timeout: DEFAULT_HEARTBEAT_TIMEOUT,
+            max_misses: 3,
+        }
+    }
+}
+
+/// Build the `Ack` answering a heartbeat
+///
+/// Returns `None` if `message` is not an `AgentHeartbeat`.
+#[must_use]
+pub fn heartbeat_ack(message: &Message) -> Option<Message> {
+    if message.message_type() != Some(MessageType::AgentHeartbeat) {
+        return None;
+    }
+    Some(Message::with_ids(
+        MessageType::Ack,
+        message.message_id(),
+        message.trace_id(),
+        message.payload().clone(),
+    ))
+}
+
+/// Heartbeat state machine for one peer
+#[derive(Debug)]
+pub struct HeartbeatMonitor {
+    config: HeartbeatConfig,
+    next_sequence: u64,
+    next_send: Option<Instant>,
+    outstanding: VecDeque<(u64, Instant)>,
+    consecutive_misses: u32,
+    healthy: bool,
+    last_rtt: Option<Duration>,
+}
+
+impl Default for HeartbeatMonitor {
+    fn default() -> Self {
+        Self::new(HeartbeatConfig::default())
+    }
+}
+
+impl HeartbeatMonitor {
+    /// Create a monitor; the first heartbeat is due immediately
+    #[must_use]
+    pub fn new(config: HeartbeatConfig) -> Self {
+        Self {
+            config,
+            next_sequence: 1,
+            next_send: None,
+            outstanding: VecDeque::new(),
+            consecutive_misses: 0,
+            healthy: true,
+            last_rtt: None,
+        }
+    }
+
+    /// Return the heartbeat to send at `now`, if one is due
+    pub fn poll_send(&mut self, now: Instant) -> Option<Message> {
+        let sequence = self.next_due(now)?;
+        Some(Message::with_ids(
+            MessageType::AgentHeartbeat,
+            sequence,
+            sequence,
+            sequence.to_le_bytes().to_vec(),
+        ))
+    }
+
+    /// Like [`Self::poll_send`], but carry `body` with its `seq` set to the
+    /// heartbeat's sequence number
+    ///
+    /// A body over the custom entry or key caps is sent as a bare heartbeat.
+    pub fn poll_send_with(&mut self, now: Instant, mut body: HeartbeatBody) -> Option<Message> {
+        let sequence = self.next_due(now)?;
+        body.seq = sequence;
+        let payload = body
+            .encode()
+            .unwrap_or_else(|_| sequence.to_le_bytes().to_vec());
+        Some(Message::with_ids(
+            MessageType::AgentHeartbeat,
+            sequence,
+            sequence,
+            payload,
+        ))
+    }
+
+    /// Claim the next sequence number if a heartbeat is due at `now`
+    fn next_due(&mut self, now: Instant) -> Option<u64> {
+        if self.next_send.is_some_and(|due| due > now) {
+            return None;
+        }
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        self.next_send = Some(now + self.config.interval);
+        self.outstanding.push_back((sequence, now));
+        Some(sequence)
+    }
+
+    /// Handle an inbound message
+    ///
+    /// Returns `true` if it acknowledged an outstanding heartbeat. Acks
+    /// arriving after their heartbeat timed out are ignored.
+    pub fn on_message(&mut self, message: &Message, now: Instant) -> bool {
+        if message.message_type() != Some(MessageType::Ack) {
+            return false;
+        }
+        let sequence = message.message_id();
+        let payload = message.payload().as_ref();
+        let echoed = <[u8; 8]>::try_from(payload)
+            .ok()
+            .map(u64::from_le_bytes)
+            .filter(|&echoed| echoed == sequence)
+            .or_else(|| HeartbeatBody::decode(payload).ok().map(|body| body.seq));
+        if echoed != Some(sequence) {
+            return false;
+        }
+        let Some(index) = self
+            .outstanding
+            .iter()
+            .position(|(seq, _)| *seq == sequence)
+        else {
+            return false;
+        };
+        let (_, sent) = self.outstanding.remove(index).expect("index in range");
+        self.last_rtt = Some(now.saturating_duration_since(sent));
+        self.consecutive_misses = 0;
+        if !self.healthy {
+            debug!(sequence, "heartbeat acknowledged, peer healthy again");
+        }
+        self.healthy = true;
+        true
+    }
+
+    /// Count heartbeats whose timeout elapsed at `now` as missed
+    ///
+    /// Returns the number of newly missed heartbeats.
+    pub fn poll_timeout(&mut self, now: Instant) -> u32 {
+        let mut missed = 0;
+        while let Some(&(sequence, sent)) = self.outstanding.front() {
+            if sent + self.config.timeout > now {
+                break;
+            }
+            self.outstanding.pop_front();
+            missed += 1;
+            self.consecutive_misses = self.consecutive_misses.saturating_add(1);
+            debug!(
+                sequence,
+                misses = self.consecutive_misses,
+                "heartbeat timed out"
+            );
+        }
+        if self.healthy && self.consecutive_misses >= self.config.max_misses {
+            debug!(misses = self.consecutive_misses, "peer unhealthy");
+            self.healthy = false;
+        }
+        missed
+    }
+
+    /// Earliest instant at which [`Self::poll_send`] or [`Self::poll_timeout`]
+    /// has work to do
+    #[must_use]
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let timeout = self
+            .outstanding
+            .front()
+            .map(|(_, sent)| *sent + self.config.timeout);
+        match (self.next_send, timeout) {
+            (Some(send), Some(timeout)) => Some(send.min(timeout)),
+            (send, timeout) => send.or(timeout),
+        }
+    }
+
+    /// Round-trip time of the most recently acknowledged heartbeat
+    #[must_use]
+    pub const fn last_rtt(&self) -> Option<Duration> {
+        self.last_rtt
+    }
+
+    /// Whether fewer than `max_misses` heartbeats in a row went unanswered
+    #[must_use]
+    pub const fn is_healthy(&self) -> bool {
+        self.healthy
+    }
+
+    /// Heartbeats missed since the last acknowledgement
+    #[must_use]
+    pub const fn consecutive_misses(&self) -> u32 {
+        self.consecutive_misses
+    }
+
+    /// Heartbeats sent and still awaiting an acknowledgement
+    #[must_use]
+    pub fn outstanding(&self) -> usize {
+        self.outstanding.len()
+    }
+}
+
+/// Latest heartbeat state of one agent in a [`HealthTable`]
+#[derive(Debug, Clone, Default)]
+pub struct AgentHealth {
+    capabilities: HashSet<String>,
+    last_seen: Option<Instant>,
+    snapshot: Option<HeartbeatBody>,
+}
+
+impl AgentHealth {
+    /// Capabilities the agent was registered with
+    #[must_use]
+    pub fn capabilities(&self) -> &HashSet<String> {
+        &self.capabilities
+    }
+
+    /// When the agent's most recent heartbeat was recorded
+    #[must_use]
+    pub const fn last_seen(&self) -> Option<Instant> {
+        self.last_seen
+    }
+
+    /// Load metadata from the agent's most recent heartbeat, if it carried a
+    /// valid body
+    #[must_use]
+    pub const fn snapshot(&self) -> Option<&HeartbeatBody> {
+        self.snapshot.as_ref()
+    }
+}
+
+/// Latest health snapshot per agent, for picking the least loaded one
+///
+/// Heartbeats without a valid [`HeartbeatBody`] still refresh the agent's
+/// liveness but clear its snapshot, so stale load figures are never used.
+#[derive(Debug, Clone, Default)]
+pub struct HealthTable {
+    agents: HashMap<String, AgentHealth>,
+}
+
+impl HealthTable {
+    /// Create an empty table
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `agent` or replace its capabilities, keeping any recorded health
+    pub fn register<I, C>(&mut self, agent: impl Into<String>, capabilities: I)
+    where
+        I: IntoIterator<Item = C>,
+        C: Into<String>,
+    {
+        self.agents.entry(agent.into()).or_default().capabilities =
+            capabilities.into_iter().map(Into::into).collect();
+    }
+
+    /// Forget `agent`, returning its last state
+    pub fn remove(&mut self, agent: &str) -> Option<AgentHealth> {
+        self.agents.remove(agent)
+    }
+
+    /// State recorded for `agent`
+    #[must_use]
+    pub fn get(&self, agent: &str) -> Option<&AgentHealth> {
+        self.agents.get(agent)
+    }
+
+    /// Record a heartbeat from `agent` received at `now`
+    ///
+    /// Returns `false` if `message` is not an `AgentHeartbeat`. Unknown agents
+    /// are added without capabilities. A body older than the stored snapshot
+    /// (by `seq`) only refreshes liveness.
+    pub fn record(&mut self, agent: &str, message: &Message, now: Instant) -> bool {
+        if message.message_type() != Some(MessageType::AgentHeartbeat) {
+            return false;
+        }
+        let entry = self.agents.entry(agent.to_owned()).or_default();
+        entry.last_seen = Some(now);
+        if let Some(body) = message.heartbeat_body() {
+            if entry
+                .snapshot
+                .as_ref()
+                .is_none_or(|current| body.seq >= current.seq)
+            {
+                entry.snapshot = Some(body);
+            }
+        } else {
+            debug!(
+                agent,
+                "heartbeat without a valid body, keeping liveness only"
+            );
+            entry.snapshot = None;
+        }
+        true
+    }
+
+    /// Least loaded agent offering `capability`
+    ///
+    /// Agents are ranked by [`HeartbeatBody::load_permille`], then by in-flight
+    /// requests, then by name; agents without a snapshot rank last.
+    #[must_use]
+    pub fn healthiest(&self, capability: &str) -> Option<&str> {
+        self.agents
+            .iter()
+            .filter(|(_, health)| health.capabilities.contains(capability))
+            .min_by_key(|(name, health)| {
+                let load = health
+                    .snapshot
+                    .as_ref()
+                    .map(|body| (body.load_permille(), body.inflight_requests));
+                (load.is_none(), load, name.as_str())
+            })
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Number of agents in the table
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.agents.len()
+    }
+
+    /// Whether the table is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use driver::{HeartbeatHandle, answer_heartbeats};
+
+#[cfg(feature = "tokio")]
+mod driver {
+    use std::sync::{Arc, Mutex, MutexGuard};
+    use std::time::Duration;
+
+    use tokio::sync::{mpsc, watch};
+    use tokio::task::JoinHandle;
+    use tokio::time::Instant;
+    use tracing::trace;
+
+    use super::{HeartbeatConfig, HeartbeatMonitor, heartbeat_ack};
+    use crate::protocol::Message;
+
+    #[derive(Debug)]
+    struct Shared {
+        monitor: Mutex<HeartbeatMonitor>,
+        health: watch::Sender<bool>,
+    }
+
+    impl Shared {
+        fn monitor(&self) -> MutexGuard<'_, HeartbeatMonitor> {
+            self.monitor
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner)
+        }
+
+        /// Send any due heartbeat and expire timed out ones, returning the
+        /// heartbeat and the next wake-up time.
+        fn tick(&self) -> (Option<Message>, Option<std::time::Instant>) {
+            let now = Instant::now().into_std();
+            let mut monitor = self.monitor();
+            let heartbeat = monitor.poll_send(now);
+            monitor.poll_timeout(now);
+            let healthy = monitor.is_healthy();
+            let deadline = monitor.next_deadline();
+            drop(monitor);
+            self.publish(healthy);
+            (heartbeat, deadline)
+        }
+
+        fn publish(&self, healthy: bool) {
+            self.health.send_if_modified(|current| {
+                let changed = *current != healthy;
+                *current = healthy;
+                changed
+            });
+        }
+    }
+
+    /// Heartbeats sent by a background tokio task
+    ///
+    /// Outbound heartbeats go to the `outbound` channel given to
+    /// [`HeartbeatHandle::start`]; the caller writes them to the connection
+    /// and feeds inbound acks back through [`HeartbeatHandle::on_message`].
+    /// The task stops when the handle is dropped or the channel closes.
+    #[derive(Debug)]
+    pub struct HeartbeatHandle {
+        shared: Arc<Shared>,
+        health: watch::Receiver<bool>,
+        task: JoinHandle<()>,
+    }
+
+    impl HeartbeatHandle {
+        /// Start sending heartbeats; must be called within a tokio runtime
+        #[must_use]
+        pub fn start(config: HeartbeatConfig, outbound: mpsc::Sender<Message>) -> Self {
+            let (health_tx, health) = watch::channel(true);
+            let shared = Arc::new(Shared {
+                monitor: Mutex::new(HeartbeatMonitor::new(config)),
+                health: health_tx,
+            });
+            let task = tokio::spawn(run(Arc::clone(&shared), outbound));
+            Self {
+                shared,
+                health,
+                task,
+            }
+        }
+
+        /// Pass an inbound message to the monitor
+        ///
+        /// Returns `true` if it acknowledged an outstanding heartbeat.
+        pub fn on_message(&self, message: &Message) -> bool {
+            let mut monitor = self.shared.monitor();
+            let acked = monitor.on_message(message, Instant::now().into_std());
+            let healthy = monitor.is_healthy();
+            drop(monitor);
+            self.shared.publish(healthy);
+            acked
+        }
+
+        /// Round-trip time of the most recently acknowledged heartbeat
+        #[must_use]
+        pub fn last_rtt(&self) -> Option<Duration> {
+            self.shared.monitor().last_rtt()
+        }
+
+        /// Whether the peer is currently answering heartbeats
+        #[must_use]
+        pub fn is_healthy(&self) -> bool {
+            *self.health.borrow()
+        }
+
+        /// Watch channel that changes whenever the peer's health flips
+        #[must_use]
+        pub fn health(&self) -> watch::Receiver<bool> {
+            self.health.clone()
+        }
+    }
+
+    impl Drop for HeartbeatHandle {
+        fn drop(&mut self) {
+            self.task.abort();
+        }
+    }
+
+    async fn run(shared: Arc<Shared>, outbound: mpsc::Sender<Message>) {
+        loop {
+            let (heartbeat, deadline) = shared.tick();
+
+            if let Some(heartbeat) = heartbeat {
+                trace!(sequence = heartbeat.message_id(), "sending heartbeat");
+                if outbound.send(heartbeat).await.is_err() {
+                    return;
+                }
+            }
+            if let Some(deadline) = deadline {
+                tokio::time::sleep_until(Instant::from_std(deadline)).await;
+            }
+        }
+    }
+
+    /// Acknowledge every heartbeat received on `inbound` by sending an `Ack`
+    /// on `outbound`
+    ///
+    /// Other message types are ignored. The task ends when either channel
+    /// closes.
+    #[must_use]
+    pub fn answer_heartbeats(
+        mut inbound: mpsc::Receiver<Message>,
+        outbound: mpsc::Sender<Message>,
+    ) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            while let Some(message) = inbound.recv().await {
+                let Some(ack) = heartbeat_ack(&message) else {
+                    trace!(message_id = message.message_id(), "ignoring non-heartbeat");
+                    continue;
+                };
+                if outbound.send(ack).await.is_err() {
+                    return;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> HeartbeatConfig {
+        HeartbeatConfig {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_millis(500),
+            max_misses: 2,
+        }
+    }
+
+    #[test]
+    fn test_ack_records_rtt() {
+        let start = Instant::now();
+        let mut monitor = HeartbeatMonitor::new(config());
+        let heartbeat = monitor.poll_send(start).expect("first heartbeat is due");
+        assert_eq!(heartbeat.message_type(), Some(MessageType::AgentHeartbeat));
+        assert!(
+            monitor
+                .poll_send(start + Duration::from_millis(999))
+                .is_none()
+        );
+
+        let ack = heartbeat_ack(&heartbeat).expect("ack");
+        assert_eq!(ack.message_id(), heartbeat.message_id());
+        assert!(monitor.on_message(&ack, start + Duration::from_millis(40)));
+        assert!(!monitor.on_message(&ack, start + Duration::from_millis(41)));
+        assert_eq!(monitor.last_rtt(), Some(Duration::from_millis(40)));
+        assert_eq!(monitor.outstanding(), 0);
+        assert!(heartbeat_ack(&ack).is_none());
+    }
+
+    #[test]
+    fn test_misses_flip_health_until_next_ack() {
+        let start = Instant::now();
+        let mut monitor = HeartbeatMonitor::new(config());
+        let second = Duration::from_secs(1);
+
+        monitor.poll_send(start).unwrap();
+        assert_eq!(monitor.poll_timeout(start + second / 2), 1);
+        assert!(monitor.is_healthy());
+        let late = monitor.poll_send(start + second).unwrap();
+        assert_eq!(monitor.poll_timeout(start + second * 3 / 2), 1);
+        assert!(!monitor.is_healthy());
+        assert_eq!(monitor.consecutive_misses(), 2);
+
+        // An ack for a heartbeat that already timed out does not count.
+        assert!(!monitor.on_message(&heartbeat_ack(&late).unwrap(), start + second * 2));
+        let third = monitor.poll_send(start + second * 2).unwrap();
+        assert_eq!(
+            monitor.next_deadline(),
+            Some(start + second * 2 + second / 2)
+        );
+        assert!(monitor.on_message(&heartbeat_ack(&third).unwrap(), start + second * 2));
+        assert!(monitor.is_healthy());
+        assert_eq!(monitor.consecutive_misses(), 0);
+    }
+
+    #[test]
+    fn test_mismatched_payload_ignored() {
+        let start = Instant::now();
+        let mut monitor = HeartbeatMonitor::default();
+        let heartbeat = monitor.poll_send(start).unwrap();
+        let forged = Message::with_ids(
+            MessageType::Ack,
+            heartbeat.message_id(),
+            0,
+            99u64.to_le_bytes().to_vec(),
+        );
+        assert!(!monitor.on_message(&forged, start));
+        assert_eq!(monitor.outstanding(), 1);
+    }
+
+    #[test]
+    fn test_body_heartbeat_acked_by_echo() {
+        let start = Instant::now();
+        let mut monitor = HeartbeatMonitor::new(config());
+        monitor.poll_send(start).unwrap();
+        let body = HeartbeatBody {
+            seq: 99,
+            cpu_permille: 250,
+            ..HeartbeatBody::default()
+        };
+        let heartbeat = monitor
+            .poll_send_with(start + Duration::from_secs(1), body)
+            .unwrap();
+        let sent = heartbeat.heartbeat_body().expect("body");
+        assert_eq!((sent.seq, sent.cpu_permille), (2, 250));
+
+        assert!(monitor.on_message(
+            &heartbeat_ack(&heartbeat).unwrap(),
+            start + Duration::from_millis(1_010)
+        ));
+        assert_eq!(monitor.last_rtt(), Some(Duration::from_millis(10)));
+        assert_eq!(monitor.outstanding(), 1);
+    }
+
+    fn report(seq: u64, cpu: u16, inflight: u32) -> Message {
+        Message::heartbeat(&HeartbeatBody {
+            seq,
+            cpu_permille: cpu,
+            inflight_requests: inflight,
+            ..HeartbeatBody::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_health_table_picks_least_loaded() {
+        let now = Instant::now();
+        let mut table = HealthTable::new();
+        table.register("alpha", ["search", "summarize"]);
+        table.register("beta", ["search"]);
+        table.register("gamma", ["search"]);
+        assert_eq!(table.healthiest("search"), Some("alpha"));
+        assert_eq!(table.healthiest("translate"), None);
+
+        assert!(table.record("alpha", &report(1, 900, 3), now));
+        assert!(table.record("beta", &report(1, 300, 8), now));
+        assert!(table.record("gamma", &report(1, 300, 2), now));
+        assert_eq!(table.healthiest("search"), Some("gamma"));
+        assert_eq!(table.healthiest("summarize"), Some("alpha"));
+
+        // A stale body does not overwrite a newer snapshot.
+        assert!(table.record("gamma", &report(4, 100, 0), now));
+        assert!(table.record("gamma", &report(3, 999, 99), now));
+        assert_eq!(table.get("gamma").unwrap().snapshot().unwrap().seq, 4);
+        assert!(!table.record("gamma", &Message::new(MessageType::Event, b"x"), now));
+    }
+
+    #[test]
+    fn test_health_table_malformed_body_keeps_liveness() {
+        let start = Instant::now();
+        let later = start + Duration::from_secs(1);
+        let mut table = HealthTable::new();
+        table.register("alpha", ["search"]);
+        table.register("beta", ["search"]);
+        table.record("alpha", &report(1, 100, 0), start);
+        table.record("beta", &report(1, 500, 0), start);
+
+        let garbage = Message::with_ids(MessageType::AgentHeartbeat, 2, 2, vec![0xFF; 3]);
+        assert!(table.record("alpha", &garbage, later));
+        let alpha = table.get("alpha").unwrap();
+        assert_eq!(alpha.last_seen(), Some(later));
+        assert!(alpha.snapshot().is_none());
+        assert_eq!(table.healthiest("search"), Some("beta"));
+
+        let bare = HeartbeatMonitor::default().poll_send(later).unwrap();
+        assert!(table.record("delta", &bare, later));
+        assert!(table.get("delta").unwrap().capabilities().is_empty());
+        assert_eq!(table.len(), 3);
+        assert!(table.remove("delta").is_some());
+    }
+}
@@ -0,0 +1,341 @@
+//! `AgentHeartbeat` message body carrying load and health metadata
+//!
+//! Layout: `seq | uptime_s | cpu_permille | mem_permille | inflight_requests` as LEB128
+//! varints, then `custom_len: u8` and per entry `key_len: u8 | key: [u8; key_len] | value`
+//! with the value as a varint.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{Error, Message, MessageType, Result};
+
+/// Maximum number of custom entries in a heartbeat body
+pub const MAX_HEARTBEAT_CUSTOM_ENTRIES: usize = 16;
+
+/// Maximum length of a custom entry key in bytes
+pub const MAX_HEARTBEAT_KEY_LEN: usize = 32;
+
+/// Longest LEB128 encoding of a `u64`
+const MAX_VARINT_LEN: usize = 10;
+
+/// Load and health snapshot sent in an `AgentHeartbeat`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeartbeatBody {
+    /// Heartbeat sequence number, matching the message ID
+    pub seq: u64,
+    /// Seconds since the agent started
+    pub uptime_s: u32,
+    /// CPU utilisation in thousandths
+    pub cpu_permille: u16,
+    /// Memory utilisation in thousandths
+    pub mem_permille: u16,
+    /// Requests the agent is currently serving
+    pub inflight_requests: u32,
+    /// Application-defined counters, at most [`MAX_HEARTBEAT_CUSTOM_ENTRIES`]
+    pub custom: Vec<(String, u64)>,
+}
+
+impl HeartbeatBody {
+    /// Create a body with only a sequence number
+    #[must_use]
+    pub fn new(seq: u64) -> Self {
+        Self {
+            seq,
+            ..Self::default()
+        }
+    }
+
+    /// Load used to rank agents: the busier of CPU and memory, in thousandths
+    #[must_use]
+    pub fn load_permille(&self) -> u16 {
+        self.cpu_permille.max(self.mem_permille)
+    }
+
+    /// Look up a custom entry by key
+    #[must_use]
+    pub fn custom(&self, key: &str) -> Option<u64> {
+        self.custom
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|&(_, value)| value)
+    }
+
+    /// Encode the body
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PayloadTooLarge`] if there are more than
+    /// [`MAX_HEARTBEAT_CUSTOM_ENTRIES`] custom entries or a key exceeds
+    /// [`MAX_HEARTBEAT_KEY_LEN`] bytes.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        if self.custom.len() > MAX_HEARTBEAT_CUSTOM_ENTRIES {
+            return Err(Error::PayloadTooLarge {
+                size: self.custom.len(),
+                max: MAX_HEARTBEAT_CUSTOM_ENTRIES,
+            });
+        }
+        let mut bytes = Vec::with_capacity(16);
+        put_varint(&mut bytes, self.seq);
+        put_varint(&mut bytes, u64::from(self.uptime_s));
+        put_varint(&mut bytes, u64::from(self.cpu_permille));
+        put_varint(&mut bytes, u64::from(self.mem_permille));
+        put_varint(&mut bytes, u64::from(self.inflight_requests));
+        #[allow(clippy::cast_possible_truncation)] // checked above
+        bytes.push(self.custom.len() as u8);
+        for (key, value) in &self.custom {
+            if key.len() > MAX_HEARTBEAT_KEY_LEN {
+                return Err(Error::PayloadTooLarge {
+                    size: key.len(),
+                    max: MAX_HEARTBEAT_KEY_LEN,
+                });
+            }
+            #[allow(clippy::cast_possible_truncation)] // checked above
+            bytes.push(key.len() as u8);
+            bytes.extend_from_slice(key.as_bytes());
+            put_varint(&mut bytes, *value);
+        }
+        Ok(bytes)
+    }
+
+    /// Decode a body
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BufferTooSmall`] if the body is truncated,
+    /// [`Error::PayloadTooLarge`] if it exceeds the entry or key caps,
+    /// [`Error::InvalidUtf8`] for a non-UTF-8 key and [`Error::Other`] for an overlong
+    /// varint, an out-of-range field or trailing bytes.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader { bytes, pos: 0 };
+        let seq = reader.varint()?;
+        let uptime_s = reader.narrow(|v| u32::try_from(v).ok(), "uptime_s")?;
+        let cpu_permille = reader.narrow(|v| u16::try_from(v).ok(), "cpu_permille")?;
+        let mem_permille = reader.narrow(|v| u16::try_from(v).ok(), "mem_permille")?;
+        let inflight_requests = reader.narrow(|v| u32::try_from(v).ok(), "inflight_requests")?;
+        let entries = usize::from(reader.byte()?);
+        if entries > MAX_HEARTBEAT_CUSTOM_ENTRIES {
+            return Err(Error::PayloadTooLarge {
+                size: entries,
+                max: MAX_HEARTBEAT_CUSTOM_ENTRIES,
+            });
+        }
+        let mut custom = Vec::with_capacity(entries);
+        for _ in 0..entries {
+            let key_len = usize::from(reader.byte()?);
+            if key_len > MAX_HEARTBEAT_KEY_LEN {
+                return Err(Error::PayloadTooLarge {
+                    size: key_len,
+                    max: MAX_HEARTBEAT_KEY_LEN,
+                });
+            }
+            let key = String::from_utf8(reader.take(key_len)?.to_vec())?;
+            custom.push((key, reader.varint()?));
+        }
+        if reader.pos != bytes.len() {
+            return Err(Error::Other(
+                "trailing bytes after heartbeat body".to_string(),
+            ));
+        }
+        Ok(Self {
+            seq,
+            uptime_s,
+            cpu_permille,
+            mem_permille,
+            inflight_requests,
+            custom,
+        })
+    }
+
+    /// Wrap the body in an `AgentHeartbeat` whose message and trace IDs are the sequence
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PayloadTooLarge`] if the body exceeds the entry or key caps.
+    pub fn to_message(&self) -> Result<Message> {
+        Ok(Message::with_ids(
+            MessageType::AgentHeartbeat,
+            self.seq,
+            self.seq,
+            self.encode()?,
+        ))
+    }
+
+    /// Parse the body of an `AgentHeartbeat` message
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidMessageType`] for other message types and the
+    /// [`decode`](Self::decode) errors for a malformed body.
+    pub fn from_message(message: &Message) -> Result<Self> {
+        if message.message_type() != Some(MessageType::AgentHeartbeat) {
+            return Err(Error::InvalidMessageType {
+                type_byte: message.header().msg_type_byte(),
+            });
+        }
+        Self::decode(message.payload())
+    }
+}
+
+impl Message {
+    /// Build an `AgentHeartbeat` carrying `body`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PayloadTooLarge`] if the body exceeds the entry or key caps.
+    pub fn heartbeat(body: &HeartbeatBody) -> Result<Self> {
+        body.to_message()
+    }
+
+    /// Health metadata carried by an `AgentHeartbeat`
+    ///
+    /// Returns `None` for other message types and for heartbeats without a valid body,
+    /// such as the bare 8-byte sequence of older senders; those still count as liveness.
+    #[must_use]
+    pub fn heartbeat_body(&self) -> Option<HeartbeatBody> {
+        HeartbeatBody::from_message(self).ok()
+    }
+}
+
+fn put_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        #[allow(clippy::cast_possible_truncation)] // low seven bits
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    #[allow(clippy::cast_possible_truncation)] // below 0x80
+    bytes.push(value as u8);
+}
+
+/// Cursor over a body being decoded
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos + len;
+        let Some(slice) = self.bytes.get(self.pos..end) else {
+            return Err(Error::BufferTooSmall {
+                needed: end,
+                got: self.bytes.len(),
+            });
+        };
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for index in 0..MAX_VARINT_LEN {
+            let byte = self.byte()?;
+            let bits = u64::from(byte & 0x7F);
+            if index == MAX_VARINT_LEN - 1 && bits > 1 {
+                break;
+            }
+            value |= bits << (7 * index);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(Error::Other(
+            "overlong varint in heartbeat body".to_string(),
+        ))
+    }
+
+    fn narrow<T>(&mut self, convert: impl FnOnce(u64) -> Option<T>, field: &str) -> Result<T> {
+        let value = self.varint()?;
+        convert(value).ok_or_else(|| {
+            Error::Other(alloc::format!(
+                "heartbeat field {field} out of range: {value}"
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body() -> HeartbeatBody {
+        HeartbeatBody {
+            seq: 300,
+            uptime_s: 86_400,
+            cpu_permille: 412,
+            mem_permille: 733,
+            inflight_requests: 17,
+            custom: alloc::vec![("queue_depth".into(), 9), ("gpu_mem".into(), u64::MAX)],
+        }
+    }
+
+    #[test]
+    fn roundtrip_through_message() {
+        let body = body();
+        let message = Message::heartbeat(&body).unwrap();
+        assert_eq!(message.message_type(), Some(MessageType::AgentHeartbeat));
+        assert_eq!(message.message_id(), 300);
+        assert_eq!(message.heartbeat_body(), Some(body.clone()));
+        assert_eq!(body.custom("gpu_mem"), Some(u64::MAX));
+        assert_eq!(body.load_permille(), 733);
+
+        // A bare heartbeat fits in a handful of bytes.
+        assert_eq!(HeartbeatBody::new(5).encode().unwrap(), [5, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn caps_are_enforced_both_ways() {
+        let mut crowded = HeartbeatBody::new(1);
+        crowded.custom = (0..=MAX_HEARTBEAT_CUSTOM_ENTRIES as u64)
+            .map(|i| (alloc::format!("k{i}"), i))
+            .collect();
+        assert!(matches!(
+            crowded.encode(),
+            Err(Error::PayloadTooLarge { size: 17, max: 16 })
+        ));
+        crowded.custom.pop();
+        let mut bytes = crowded.encode().unwrap();
+        assert_eq!(HeartbeatBody::decode(&bytes).unwrap(), crowded);
+        // Claim one entry more than allowed.
+        bytes[5] += 1;
+        assert!(matches!(
+            HeartbeatBody::decode(&bytes),
+            Err(Error::PayloadTooLarge { size: 17, .. })
+        ));
+
+        let mut long_key = HeartbeatBody::new(1);
+        long_key.custom = alloc::vec![("k".repeat(MAX_HEARTBEAT_KEY_LEN + 1), 0)];
+        assert!(matches!(
+            long_key.encode(),
+            Err(Error::PayloadTooLarge { size: 33, max: 32 })
+        ));
+    }
+
+    #[test]
+    fn malformed_bodies_degrade_to_liveness() {
+        let legacy = Message::with_ids(
+            MessageType::AgentHeartbeat,
+            7,
+            7,
+            7u64.to_le_bytes().to_vec(),
+        );
+        assert_eq!(legacy.heartbeat_body(), None);
+
+        let bytes = body().encode().unwrap();
+        let truncated = Message::with_ids(MessageType::AgentHeartbeat, 1, 1, bytes[..4].to_vec());
+        assert_eq!(truncated.heartbeat_body(), None);
+        assert!(matches!(
+            HeartbeatBody::decode(&bytes[..bytes.len() - 1]),
+            Err(Error::BufferTooSmall { .. })
+        ));
+        assert!(HeartbeatBody::decode(&[0xFF; 11]).is_err());
+        // cpu_permille does not fit in a u16.
+        assert!(HeartbeatBody::decode(&[1, 0, 0x80, 0x80, 0x04, 0, 0, 0]).is_err());
+
+        let event = Message::with_ids(MessageType::Event, 1, 1, bytes);
+        assert_eq!(event.heartbeat_body(), None);
+    }
+}
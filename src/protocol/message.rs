@@ -1,21 +1,39 @@
 //! MXP message implementation
 
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use core::time::Duration;
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use bytes::Bytes;
+#[cfg(feature = "std")]
 use uuid::Uuid;
 
-use super::{Flags, MessageHeader, MessageType};
+#[cfg(feature = "std")]
+use crate::transport::{
+    AEAD_NONCE_LEN, AeadKey, AeadNonce, chacha20_poly1305_open, chacha20_poly1305_seal,
+};
+
+use super::{ChecksumAlgorithm, DEADLINE_SIZE, Flags, MessageHeader, MessageType, Priority};
 
 /// MXP message
 #[derive(Debug, Clone)]
 pub struct Message {
     /// Message header
     header: MessageHeader,
-    /// Message payload
+    /// Message payload (excluding any deadline prefix)
     payload: Bytes,
+    /// Deadline in microseconds since the unix epoch
+    deadline: Option<u64>,
 }
 
 impl Message {
-    /// Create a new message
+    /// Create a new message with generated message and trace IDs
+    ///
+    /// Without the `std` feature there is no entropy source, so generated IDs are a
+    /// scrambled per-process sequence; set IDs explicitly when they must be unique across
+    /// devices.
     pub fn new(msg_type: MessageType, payload: impl Into<Vec<u8>>) -> Self {
         let payload = Bytes::from(payload.into());
         let message_id = Self::generate_id();
@@ -23,13 +41,28 @@ impl Message {
 
         let header = MessageHeader::new(msg_type, message_id, trace_id, payload.len() as u64);
 
-        Self { header, payload }
+        Self {
+            header,
+            payload,
+            deadline: None,
+        }
+    }
+
+    /// Start building a message of the given type
+    #[must_use]
+    pub fn builder(msg_type: MessageType) -> MessageBuilder {
+        MessageBuilder::new(msg_type)
     }
 
     /// Create a message from raw parts without copying payload bytes
-    pub(super) fn from_parts(header: MessageHeader, payload: Bytes) -> Self {
-        debug_assert_eq!(header.payload_len(), payload.len() as u64);
-        Self { header, payload }
+    pub(super) fn from_parts(header: MessageHeader, payload: Bytes, deadline: Option<u64>) -> Self {
+        let prefix = if deadline.is_some() { DEADLINE_SIZE } else { 0 };
+        debug_assert_eq!(header.payload_len(), (prefix + payload.len()) as u64);
+        Self {
+            header,
+            payload,
+            deadline,
+        }
     }
 
     /// Create a new message with explicit IDs
@@ -42,7 +75,11 @@ impl Message {
         let payload = payload.into();
         let header = MessageHeader::new(msg_type, message_id, trace_id, payload.len() as u64);
 
-        Self { header, payload }
+        Self {
+            header,
+            payload,
+            deadline: None,
+        }
     }
 
     /// Get message type
@@ -69,17 +106,84 @@ impl Message {
         &self.payload
     }
 
+    /// Get a shared handle to the payload (reference-counted, no copy)
+    #[must_use]
+    pub fn payload_bytes(&self) -> Bytes {
+        self.payload.clone()
+    }
+
     /// Get flags
     #[must_use]
     pub fn flags(&self) -> Flags {
         self.header.flags()
     }
 
-    /// Set flags
+    /// Set flags (`HAS_DEADLINE` always mirrors whether a deadline is present)
     pub fn set_flags(&mut self, flags: Flags) {
+        let flags = if self.deadline.is_some() {
+            flags.with(Flags::HAS_DEADLINE)
+        } else {
+            flags.without(Flags::HAS_DEADLINE)
+        };
         self.header.set_flags(flags);
     }
 
+    /// Checksum algorithm used for the trailer when encoding
+    #[must_use]
+    pub fn checksum_algorithm(&self) -> ChecksumAlgorithm {
+        self.flags().checksum_algorithm()
+    }
+
+    /// Select the checksum algorithm used for the trailer when encoding
+    pub fn set_checksum_algorithm(&mut self, algorithm: ChecksumAlgorithm) {
+        self.set_flags(self.flags().with_checksum_algorithm(algorithm));
+    }
+
+    /// Get priority
+    #[must_use]
+    pub fn priority(&self) -> Priority {
+        self.header.priority()
+    }
+
+    /// Set priority
+    pub fn set_priority(&mut self, priority: Priority) {
+        self.header.set_priority(priority);
+    }
+
+    /// Get deadline, if any
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn deadline(&self) -> Option<SystemTime> {
+        self.deadline
+            .map(|micros| UNIX_EPOCH + Duration::from_micros(micros))
+    }
+
+    /// Get raw deadline (microseconds since the unix epoch)
+    #[must_use]
+    pub const fn deadline_micros(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Check whether the deadline has passed
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(SystemTime::now())
+    }
+
+    /// Check whether the deadline has passed at `now`
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn is_expired_at(&self, now: SystemTime) -> bool {
+        self.deadline().is_some_and(|deadline| deadline <= now)
+    }
+
+    /// Check whether the deadline has passed at `now_micros` (microseconds since the unix epoch)
+    #[must_use]
+    pub fn is_expired_at_micros(&self, now_micros: u64) -> bool {
+        self.deadline.is_some_and(|deadline| deadline <= now_micros)
+    }
+
     /// Get header
     #[must_use]
     pub const fn header(&self) -> &MessageHeader {
@@ -92,6 +196,7 @@ impl Message {
     }
 
     /// Generate a random message/trace ID
+    #[cfg(feature = "std")]
     fn generate_id() -> u64 {
         let uuid = Uuid::new_v4();
         let bytes = uuid.as_bytes();
@@ -100,18 +205,264 @@ impl Message {
         ])
     }
 
+    /// Generate a message/trace ID from a sequence number scrambled with the `SplitMix64`
+    /// finalizer
+    #[cfg(not(feature = "std"))]
+    fn generate_id() -> u64 {
+        use core::sync::atomic::{AtomicU32, Ordering};
+
+        static NEXT: AtomicU32 = AtomicU32::new(1);
+        let mut id =
+            u64::from(NEXT.fetch_add(1, Ordering::Relaxed)).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        id = (id ^ (id >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        id = (id ^ (id >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        id ^ (id >> 31)
+    }
+
     /// Encode message to bytes
     #[must_use]
     pub fn encode(&self) -> Vec<u8> {
         super::encode(self)
     }
 
-    /// Decode message from bytes
-    pub fn decode<B>(bytes: B) -> super::Result<Self>
+    /// Decode message from an owned buffer without copying
+    ///
+    /// The header is parsed in place and the payload is a slice of `bytes`, so the message
+    /// keeps the buffer alive for as long as the payload is referenced.
+    pub fn decode_bytes(bytes: Bytes) -> super::Result<Self> {
+        super::decode(bytes)
+    }
+
+    /// Decode message from a borrowed slice
+    ///
+    /// Copies only the payload into a new buffer owned by the message; use
+    /// [`decode_bytes`](Self::decode_bytes) when the caller already owns the buffer.
+    pub fn decode(bytes: &[u8]) -> super::Result<Self> {
+        super::decode_ref(bytes)
+    }
+
+    /// Decode the message at the start of `bytes` and report how many bytes it used
+    ///
+    /// See [`decode_prefix`](super::decode_prefix); use [`MessageIter`](super::MessageIter)
+    /// to walk a buffer of back-to-back messages.
+    pub fn decode_prefix(bytes: &[u8]) -> super::Result<(Self, usize)> {
+        super::decode_prefix(bytes)
+    }
+
+    /// Read exactly one message from an async byte stream, such as a pipe or TCP socket
+    ///
+    /// See [`read_async`](super::read_async) for the framing and errors.
+    #[cfg(feature = "tokio")]
+    pub async fn read_async<R>(reader: &mut R) -> super::Result<Self>
     where
-        B: Into<Bytes>,
+        R: tokio::io::AsyncRead + Unpin,
     {
-        super::decode(bytes.into())
+        super::read_async(reader).await
+    }
+}
+
+/// End-to-end payload encryption, independent of transport encryption
+///
+/// The encrypted payload is `nonce (12) || ciphertext || tag (16)` under ChaCha20-Poly1305,
+/// with the message type, message ID and trace ID as associated data. The header, deadline
+/// and checksum stay readable, so relays can route and verify messages they cannot read.
+#[cfg(feature = "std")]
+impl Message {
+    /// Encrypt the payload under `key` and set [`Flags::ENCRYPTED`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidFlags`](super::Error::InvalidFlags) if the payload is
+    /// already encrypted.
+    pub fn encrypt(&mut self, key: &AeadKey) -> super::Result<()> {
+        let flags = self.flags();
+        if flags.is_encrypted() {
+            return Err(super::Error::InvalidFlags {
+                flags: flags.as_u8(),
+            });
+        }
+        let nonce = AeadNonce::random();
+        let sealed = chacha20_poly1305_seal(key, &nonce, &self.payload, &self.payload_aad());
+        let mut payload = Vec::with_capacity(AEAD_NONCE_LEN + sealed.len());
+        payload.extend_from_slice(nonce.as_bytes());
+        payload.extend_from_slice(&sealed);
+        self.replace_payload(Bytes::from(payload));
+        self.set_flags(flags.with(Flags::ENCRYPTED));
+        Ok(())
+    }
+
+    /// Decrypt a payload produced by [`encrypt`](Self::encrypt) and clear
+    /// [`Flags::ENCRYPTED`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidFlags`](super::Error::InvalidFlags) if the payload is not
+    /// encrypted and [`Error::DecryptionFailed`](super::Error::DecryptionFailed) if the key
+    /// is wrong or the message was altered; the message is left unchanged on error.
+    pub fn decrypt(&mut self, key: &AeadKey) -> super::Result<()> {
+        let flags = self.flags();
+        if !flags.is_encrypted() {
+            return Err(super::Error::InvalidFlags {
+                flags: flags.as_u8(),
+            });
+        }
+        let failed = super::Error::DecryptionFailed {
+            message_id: self.message_id(),
+        };
+        if self.payload.len() < AEAD_NONCE_LEN {
+            return Err(failed);
+        }
+        let (nonce, sealed) = self.payload.split_at(AEAD_NONCE_LEN);
+        let nonce = AeadNonce::from_bytes(nonce).expect("nonce length");
+        let plaintext =
+            chacha20_poly1305_open(key, &nonce, sealed, &self.payload_aad()).map_err(|_| failed)?;
+        self.replace_payload(Bytes::from(plaintext));
+        self.set_flags(flags.without(Flags::ENCRYPTED));
+        Ok(())
+    }
+
+    fn payload_aad(&self) -> [u8; 17] {
+        let mut aad = [0u8; 17];
+        aad[0] = self.header.msg_type_byte();
+        aad[1..9].copy_from_slice(&self.message_id().to_le_bytes());
+        aad[9..].copy_from_slice(&self.trace_id().to_le_bytes());
+        aad
+    }
+
+    fn replace_payload(&mut self, payload: Bytes) {
+        let prefix = if self.deadline.is_some() {
+            DEADLINE_SIZE
+        } else {
+            0
+        };
+        self.header.set_payload_len((prefix + payload.len()) as u64);
+        self.payload = payload;
+    }
+}
+
+impl TryFrom<&[u8]> for Message {
+    type Error = super::Error;
+
+    fn try_from(bytes: &[u8]) -> super::Result<Self> {
+        super::decode_ref(bytes)
+    }
+}
+
+impl From<Message> for Bytes {
+    fn from(message: Message) -> Self {
+        Self::from(message.encode())
+    }
+}
+
+/// Builder for messages with explicit IDs, flags, or a deadline
+#[derive(Debug, Clone)]
+pub struct MessageBuilder {
+    msg_type: MessageType,
+    message_id: Option<u64>,
+    trace_id: Option<u64>,
+    flags: Flags,
+    priority: Priority,
+    deadline: Option<u64>,
+    payload: Bytes,
+}
+
+impl MessageBuilder {
+    /// Create a builder for the given message type
+    #[must_use]
+    pub fn new(msg_type: MessageType) -> Self {
+        Self {
+            msg_type,
+            message_id: None,
+            trace_id: None,
+            flags: Flags::new(),
+            priority: Priority::Interactive,
+            deadline: None,
+            payload: Bytes::new(),
+        }
+    }
+
+    /// Set payload
+    #[must_use]
+    pub fn payload(mut self, payload: impl Into<Bytes>) -> Self {
+        self.payload = payload.into();
+        self
+    }
+
+    /// Set message ID (random if unset)
+    #[must_use]
+    pub const fn message_id(mut self, message_id: u64) -> Self {
+        self.message_id = Some(message_id);
+        self
+    }
+
+    /// Set trace ID (random if unset)
+    #[must_use]
+    pub const fn trace_id(mut self, trace_id: u64) -> Self {
+        self.trace_id = Some(trace_id);
+        self
+    }
+
+    /// Set flags (including the checksum algorithm bits)
+    #[must_use]
+    pub const fn flags(mut self, flags: Flags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Set the checksum algorithm (XXH3 if unset)
+    #[must_use]
+    pub const fn checksum_algorithm(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.flags = self.flags.with_checksum_algorithm(algorithm);
+        self
+    }
+
+    /// Set priority used when scheduling the message for transmission
+    #[must_use]
+    pub const fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Set deadline after which the message should be dropped rather than processed
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn deadline(self, deadline: SystemTime) -> Self {
+        let micros = deadline
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros();
+        self.deadline_micros(u64::try_from(micros).unwrap_or(u64::MAX))
+    }
+
+    /// Set deadline in microseconds since the unix epoch
+    #[must_use]
+    pub const fn deadline_micros(mut self, deadline_micros: u64) -> Self {
+        self.deadline = Some(deadline_micros);
+        self
+    }
+
+    /// Build the message
+    #[must_use]
+    pub fn build(self) -> Message {
+        let message_id = self.message_id.unwrap_or_else(Message::generate_id);
+        let trace_id = self.trace_id.unwrap_or_else(Message::generate_id);
+        let deadline = self.deadline;
+        let prefix = if deadline.is_some() { DEADLINE_SIZE } else { 0 };
+
+        let header = MessageHeader::new(
+            self.msg_type,
+            message_id,
+            trace_id,
+            (prefix + self.payload.len()) as u64,
+        );
+        let mut message = Message {
+            header,
+            payload: self.payload,
+            deadline,
+        };
+        message.set_flags(self.flags);
+        message.set_priority(self.priority);
+        message
     }
 }
 
@@ -132,10 +483,135 @@ mod tests {
     fn test_message_roundtrip() {
         let original = Message::new(MessageType::Event, b"hello world");
         let encoded = original.encode();
-        let decoded = Message::decode(encoded).unwrap();
+        let decoded = Message::decode(&encoded).unwrap();
 
         assert_eq!(decoded.message_type(), original.message_type());
         assert_eq!(decoded.payload().as_ref(), original.payload().as_ref());
         assert_eq!(decoded.message_id(), original.message_id());
     }
+
+    #[test]
+    fn decode_bytes_shares_the_input_buffer() {
+        let original = Message::new(MessageType::Call, vec![0xA5; 16 * 1024]);
+        let wire = Bytes::from(original.encode());
+        let wire_range = wire.as_ptr_range();
+
+        let decoded = Message::decode_bytes(wire.clone()).unwrap();
+        let payload = decoded.payload_bytes();
+        assert_eq!(payload, original.payload());
+        assert!(wire_range.contains(&payload.as_ptr()));
+        assert_eq!(
+            payload.as_ptr(),
+            wire[crate::protocol::HEADER_SIZE..].as_ptr()
+        );
+
+        // Decoding from a borrowed slice copies into a buffer of its own.
+        let copied = Message::decode(&wire).unwrap();
+        assert!(!wire_range.contains(&copied.payload().as_ptr()));
+    }
+
+    #[test]
+    fn test_message_expiry() {
+        let now = SystemTime::now();
+        let msg = Message::builder(MessageType::Call)
+            .payload(&b"work"[..])
+            .deadline(now + Duration::from_secs(5))
+            .build();
+
+        assert!(msg.flags().has_deadline());
+        assert!(!msg.is_expired_at(now));
+        assert!(msg.is_expired_at(now + Duration::from_secs(5)));
+        assert!(!Message::new(MessageType::Call, b"work").is_expired());
+    }
+
+    #[test]
+    fn test_message_priority_roundtrip() {
+        let msg = Message::builder(MessageType::AgentHeartbeat)
+            .priority(Priority::Control)
+            .build();
+        let decoded = Message::decode_bytes(msg.encode().into()).unwrap();
+
+        assert_eq!(decoded.priority(), Priority::Control);
+        assert_eq!(
+            Message::new(MessageType::Call, b"x").priority(),
+            Priority::Interactive
+        );
+    }
+
+    #[test]
+    fn test_payload_encryption_roundtrip_through_codec() {
+        let key = AeadKey::from_array([7; 32]);
+        let mut msg = Message::builder(MessageType::Call)
+            .payload(&b"secret plan"[..])
+            .deadline_micros(1_700_000_000_000_000)
+            .build();
+        msg.encrypt(&key).unwrap();
+        assert!(msg.flags().is_encrypted());
+        assert!(msg.flags().has_deadline());
+        assert_eq!(msg.payload().len(), AEAD_NONCE_LEN + 11 + 16);
+        assert!(!msg.payload().windows(6).any(|window| window == b"secret"));
+
+        // A relay decodes and re-encodes without the key; the checksum covers ciphertext.
+        let mut relayed = Message::decode(&msg.encode()).unwrap();
+        assert!(relayed.flags().is_encrypted());
+        assert_eq!(relayed.payload(), msg.payload());
+
+        relayed.decrypt(&key).unwrap();
+        assert!(!relayed.flags().is_encrypted());
+        assert_eq!(relayed.payload().as_ref(), b"secret plan");
+        assert_eq!(relayed.deadline_micros(), Some(1_700_000_000_000_000));
+        let reencoded = Message::decode(&relayed.encode()).unwrap();
+        assert_eq!(reencoded.payload().as_ref(), b"secret plan");
+
+        assert!(matches!(
+            relayed.decrypt(&key),
+            Err(crate::Error::InvalidFlags { .. })
+        ));
+        msg.clone().encrypt(&key).unwrap_err();
+    }
+
+    #[test]
+    fn test_payload_nonce_has_no_fixed_bits() {
+        let key = AeadKey::from_array([7; 32]);
+        let mut seen_ones = [0u8; AEAD_NONCE_LEN];
+        let mut seen_zeros = [0u8; AEAD_NONCE_LEN];
+        for _ in 0..64 {
+            let mut msg = Message::new(MessageType::Call, b"x");
+            msg.encrypt(&key).unwrap();
+            for (i, byte) in msg.payload()[..AEAD_NONCE_LEN].iter().enumerate() {
+                seen_ones[i] |= byte;
+                seen_zeros[i] |= !byte;
+            }
+        }
+        assert_eq!(seen_ones, [0xff; AEAD_NONCE_LEN]);
+        assert_eq!(seen_zeros, [0xff; AEAD_NONCE_LEN]);
+    }
+
+    #[test]
+    fn test_payload_decryption_rejects_wrong_key_and_tampering() {
+        let key = AeadKey::from_array([7; 32]);
+        let mut msg = Message::with_ids(MessageType::Event, 5, 6, b"payload".to_vec());
+        msg.encrypt(&key).unwrap();
+        let sealed = msg.payload_bytes();
+
+        let wrong = AeadKey::from_array([8; 32]);
+        assert!(matches!(
+            msg.decrypt(&wrong),
+            Err(crate::Error::DecryptionFailed { message_id: 5 })
+        ));
+        assert!(msg.flags().is_encrypted());
+        assert_eq!(msg.payload_bytes(), sealed);
+
+        // The IDs are authenticated, so a relay cannot splice the payload onto another call.
+        let mut spliced = Message::with_ids(MessageType::Event, 99, 6, sealed.clone());
+        spliced.set_flags(Flags::new().with(Flags::ENCRYPTED));
+        assert!(spliced.decrypt(&key).is_err());
+
+        let mut truncated = Message::with_ids(MessageType::Event, 5, 6, sealed.slice(..4));
+        truncated.set_flags(Flags::new().with(Flags::ENCRYPTED));
+        assert!(truncated.decrypt(&key).is_err());
+
+        msg.decrypt(&key).unwrap();
+        assert_eq!(msg.payload().as_ref(), b"payload");
+    }
 }
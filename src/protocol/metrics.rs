@@ -23,6 +23,9 @@ static DATAGRAM_ENQUEUED: AtomicU64 = AtomicU64::new(0);
 static DATAGRAM_ENQUEUED_BYTES: AtomicU64 = AtomicU64::new(0);
 static DATAGRAM_SENT: AtomicU64 = AtomicU64::new(0);
 static DATAGRAM_SENT_BYTES: AtomicU64 = AtomicU64::new(0);
+static DATAGRAM_RECEIVED: AtomicU64 = AtomicU64::new(0);
+static DATAGRAM_RECEIVED_BYTES: AtomicU64 = AtomicU64::new(0);
+static DATAGRAM_RECV_DROPPED: AtomicU64 = AtomicU64::new(0);
 
 static FLOW_BYTES_CONSUMED: AtomicU64 = AtomicU64::new(0);
 static FLOW_CONNECTION_UPDATES: AtomicU64 = AtomicU64::new(0);
@@ -35,6 +38,11 @@ static SCHEDULER_INTERACTIVE_DEQUEUED: AtomicU64 = AtomicU64::new(0);
 static SCHEDULER_BULK_ENQUEUED: AtomicU64 = AtomicU64::new(0);
 static SCHEDULER_BULK_DEQUEUED: AtomicU64 = AtomicU64::new(0);
 
+static HANDSHAKES_REJECTED: AtomicU64 = AtomicU64::new(0);
+static DUPLICATE_PACKETS: AtomicU64 = AtomicU64::new(0);
+static FOREIGN_DATAGRAMS_DROPPED: AtomicU64 = AtomicU64::new(0);
+static INFLIGHT_UNDERFLOWS: AtomicU64 = AtomicU64::new(0);
+
 const NANOSECONDS_PER_MICROSECOND: u128 = 1_000;
 
 struct MessageTypeCounters {
@@ -49,6 +57,7 @@ struct MessageTypeCounters {
     stream_close: AtomicU64,
     ack: AtomicU64,
     error: AtomicU64,
+    custom: AtomicU64,
 }
 
 static MESSAGE_COUNTERS: MessageTypeCounters = MessageTypeCounters::new();
@@ -67,13 +76,14 @@ impl MessageTypeCounters {
             stream_close: AtomicU64::new(0),
             ack: AtomicU64::new(0),
             error: AtomicU64::new(0),
+            custom: AtomicU64::new(0),
         }
     }
 
     fn increment(&self, msg_type: MessageType) {
         use MessageType::{
-            Ack, AgentDiscover, AgentHeartbeat, AgentRegister, Call, Event, Response, StreamChunk,
-            StreamClose, StreamOpen,
+            Ack, AgentDiscover, AgentHeartbeat, AgentRegister, Call, Custom, Event, Response,
+            StreamChunk, StreamClose, StreamOpen,
         };
 
         match msg_type {
@@ -88,6 +98,7 @@ impl MessageTypeCounters {
             StreamClose => self.stream_close.fetch_add(1, Ordering::Relaxed),
             Ack => self.ack.fetch_add(1, Ordering::Relaxed),
             MessageType::Error => self.error.fetch_add(1, Ordering::Relaxed),
+            Custom(_) => self.custom.fetch_add(1, Ordering::Relaxed),
         };
     }
 }
@@ -186,6 +197,17 @@ impl Metrics {
         DATAGRAM_SENT_BYTES.fetch_add(len as u64, Ordering::Relaxed);
     }
 
+    #[inline]
+    pub(crate) fn record_datagram_received(len: usize) {
+        DATAGRAM_RECEIVED.fetch_add(1, Ordering::Relaxed);
+        DATAGRAM_RECEIVED_BYTES.fetch_add(len as u64, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn record_datagram_recv_dropped() {
+        DATAGRAM_RECV_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+
     #[inline]
     pub(crate) fn record_flow_consumed(bytes: u64) {
         FLOW_BYTES_CONSUMED.fetch_add(bytes, Ordering::Relaxed);
@@ -231,6 +253,26 @@ impl Metrics {
         }
     }
 
+    #[inline]
+    pub(crate) fn record_handshake_rejected() {
+        HANDSHAKES_REJECTED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn record_duplicate_packet() {
+        DUPLICATE_PACKETS.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn record_foreign_datagram_dropped() {
+        FOREIGN_DATAGRAMS_DROPPED.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn record_inflight_underflow() {
+        INFLIGHT_UNDERFLOWS.fetch_add(1, Ordering::Relaxed);
+    }
+
     #[inline]
     pub(crate) fn totals() -> MetricsSnapshot {
         MetricsSnapshot {
@@ -248,6 +290,9 @@ impl Metrics {
             datagram_enqueued_bytes: DATAGRAM_ENQUEUED_BYTES.load(Ordering::Relaxed),
             datagram_sent: DATAGRAM_SENT.load(Ordering::Relaxed),
             datagram_sent_bytes: DATAGRAM_SENT_BYTES.load(Ordering::Relaxed),
+            datagram_received: DATAGRAM_RECEIVED.load(Ordering::Relaxed),
+            datagram_received_bytes: DATAGRAM_RECEIVED_BYTES.load(Ordering::Relaxed),
+            datagram_recv_dropped: DATAGRAM_RECV_DROPPED.load(Ordering::Relaxed),
             scheduler_control_enqueued: SCHEDULER_CONTROL_ENQUEUED.load(Ordering::Relaxed),
             scheduler_control_dequeued: SCHEDULER_CONTROL_DEQUEUED.load(Ordering::Relaxed),
             scheduler_interactive_enqueued: SCHEDULER_INTERACTIVE_ENQUEUED.load(Ordering::Relaxed),
@@ -257,6 +302,10 @@ impl Metrics {
             flow_bytes_consumed: FLOW_BYTES_CONSUMED.load(Ordering::Relaxed),
             flow_connection_updates: FLOW_CONNECTION_UPDATES.load(Ordering::Relaxed),
             flow_stream_updates: FLOW_STREAM_UPDATES.load(Ordering::Relaxed),
+            handshakes_rejected: HANDSHAKES_REJECTED.load(Ordering::Relaxed),
+            duplicate_packets: DUPLICATE_PACKETS.load(Ordering::Relaxed),
+            foreign_datagrams_dropped: FOREIGN_DATAGRAMS_DROPPED.load(Ordering::Relaxed),
+            inflight_underflows: INFLIGHT_UNDERFLOWS.load(Ordering::Relaxed),
         }
     }
 }
@@ -289,6 +338,9 @@ pub struct MetricsSnapshot {
     pub datagram_enqueued_bytes: u64,
     pub datagram_sent: u64,
     pub datagram_sent_bytes: u64,
+    pub datagram_received: u64,
+    pub datagram_received_bytes: u64,
+    pub datagram_recv_dropped: u64,
     pub scheduler_control_enqueued: u64,
     pub scheduler_control_dequeued: u64,
     pub scheduler_interactive_enqueued: u64,
@@ -298,6 +350,10 @@ pub struct MetricsSnapshot {
     pub flow_bytes_consumed: u64,
     pub flow_connection_updates: u64,
     pub flow_stream_updates: u64,
+    pub handshakes_rejected: u64,
+    pub duplicate_packets: u64,
+    pub foreign_datagrams_dropped: u64,
+    pub inflight_underflows: u64,
 }
 
 impl MetricsSnapshot {
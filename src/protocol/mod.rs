@@ -2,18 +2,63 @@
 //!
 //! This module provides the wire format, message types, and codec for MXP.
 
+#[cfg(feature = "std")]
+mod call;
+mod chunked;
 mod codec;
+mod crc32c;
 mod error;
+#[cfg(feature = "std")]
+mod handler;
 mod header;
+#[cfg(feature = "std")]
+mod heartbeat;
+mod heartbeat_body;
 mod message;
+#[cfg(feature = "std")]
 pub(crate) mod metrics;
+mod stream_open;
+#[cfg(feature = "std")]
+mod tracker;
 mod types;
+#[cfg_attr(feature = "xxhash", allow(dead_code))]
+mod xxh3;
 
-pub use codec::{decode, encode};
-pub use error::{Error, Result};
+#[cfg(feature = "std")]
+pub use call::{
+    AttemptError, CallOptions, CallTransport, DEFAULT_CALL_BACKOFF, DEFAULT_CALL_TIMEOUT,
+    DedupCache, DedupStatus, Delivery, MAX_CALL_BACKOFF, call_with, is_retryable,
+};
+pub use chunked::{
+    ChunkReassembler, Chunks, DEFAULT_CHUNK_SIZE, DEFAULT_MAX_REASSEMBLED_SIZE, MAX_CHUNK_DATA,
+    split_chunks,
+};
+#[cfg(feature = "tokio")]
+pub use codec::read_async;
+pub use codec::{MessageIter, StreamingDecoder, decode, decode_prefix, decode_ref, encode};
+pub use crc32c::crc32c;
+pub use error::{Error, ErrorCode, Result};
+#[cfg(feature = "std")]
+pub use handler::{HandlerOptions, HandlerRegistry};
 pub use header::MessageHeader;
-pub use message::Message;
-pub use types::{Flags, MessageType};
+#[cfg(feature = "std")]
+pub use heartbeat::{
+    AgentHealth, DEFAULT_HEARTBEAT_INTERVAL, DEFAULT_HEARTBEAT_TIMEOUT, HealthTable,
+    HeartbeatConfig, HeartbeatMonitor, heartbeat_ack,
+};
+#[cfg(feature = "tokio")]
+pub use heartbeat::{HeartbeatHandle, answer_heartbeats};
+pub use heartbeat_body::{HeartbeatBody, MAX_HEARTBEAT_CUSTOM_ENTRIES, MAX_HEARTBEAT_KEY_LEN};
+pub use message::{Message, MessageBuilder};
+pub use stream_open::{MAX_STREAM_NAME_LEN, StreamOpen};
+#[cfg(feature = "std")]
+pub use tracker::{
+    DEFAULT_MAX_PENDING, DEFAULT_REQUEST_TIMEOUT, RequestStatus, RequestTracker, delivery_ack,
+    is_delivery_ack,
+};
+pub use types::{
+    CUSTOM_TYPE_MAX, CUSTOM_TYPE_MIN, ChecksumAlgorithm, CustomType, Flags, MessageType, Priority,
+};
 
 /// MXP magic number: "MXP1" in ASCII
 pub const MAGIC_NUMBER: u32 = 0x4D58_5031;
@@ -29,3 +74,25 @@ pub const CHECKSUM_SIZE: usize = 8;
 
 /// Minimum message size (header + checksum)
 pub const MIN_MESSAGE_SIZE: usize = HEADER_SIZE + CHECKSUM_SIZE;
+
+/// Deadline prefix size in bytes (present when `Flags::HAS_DEADLINE` is set)
+pub const DEADLINE_SIZE: usize = 8;
+
+/// Compute the XXH3-64 checksum carried in the message trailer by default
+///
+/// See [`ChecksumAlgorithm`] for the alternatives selectable per message.
+///
+/// Uses the `xxhash-rust` crate with the `xxhash` feature and the in-tree XXH3 otherwise;
+/// both produce identical values.
+#[must_use]
+#[inline]
+pub fn checksum(bytes: &[u8]) -> u64 {
+    #[cfg(feature = "xxhash")]
+    {
+        xxhash_rust::xxh3::xxh3_64(bytes)
+    }
+    #[cfg(not(feature = "xxhash"))]
+    {
+        xxh3::xxh3_64(bytes)
+    }
+}
@@ -0,0 +1,175 @@
+//! `StreamOpen` message body
+//!
+//! Layout: `stream_id: u64 (LE) | priority: u8 | name_len: u8 | name: [u8; name_len]`.
+
+use alloc::vec::Vec;
+
+use super::{Error, Message, MessageType, Priority, Result};
+
+/// Fixed-size prefix of the body (stream ID, priority, name length)
+const FIXED_LEN: usize = 8 + 1 + 1;
+
+/// Maximum length of a stream name in bytes
+pub const MAX_STREAM_NAME_LEN: usize = u8::MAX as usize;
+
+/// Body of a `StreamOpen` message announcing a new stream and its priority
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamOpen {
+    stream_id: u64,
+    priority: Priority,
+    name: Vec<u8>,
+}
+
+impl StreamOpen {
+    /// Create a body for `stream_id`
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::PayloadTooLarge`] if `name` exceeds [`MAX_STREAM_NAME_LEN`] bytes.
+    pub fn new(stream_id: u64, priority: Priority, name: impl Into<Vec<u8>>) -> Result<Self> {
+        let name = name.into();
+        if name.len() > MAX_STREAM_NAME_LEN {
+            return Err(Error::PayloadTooLarge {
+                size: name.len(),
+                max: MAX_STREAM_NAME_LEN,
+            });
+        }
+        Ok(Self {
+            stream_id,
+            priority,
+            name,
+        })
+    }
+
+    /// Get the raw transport stream ID
+    #[must_use]
+    pub const fn stream_id(&self) -> u64 {
+        self.stream_id
+    }
+
+    /// Get the stream priority
+    #[must_use]
+    pub const fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Get the stream name
+    #[must_use]
+    pub fn name(&self) -> &[u8] {
+        &self.name
+    }
+
+    /// Encode the body
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(FIXED_LEN + self.name.len());
+        bytes.extend_from_slice(&self.stream_id.to_le_bytes());
+        bytes.push(self.priority.as_u8());
+        #[allow(clippy::cast_possible_truncation)] // checked in `new`
+        bytes.push(self.name.len() as u8);
+        bytes.extend_from_slice(&self.name);
+        bytes
+    }
+
+    /// Decode a body; unknown priority values clamp to [`Priority::Bulk`]
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::BufferTooSmall`] if the body is truncated.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() < FIXED_LEN {
+            return Err(Error::BufferTooSmall {
+                needed: FIXED_LEN,
+                got: bytes.len(),
+            });
+        }
+        let stream_id = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
+        let priority = Priority::from_u8(bytes[8]).unwrap_or(Priority::Bulk);
+        let needed = FIXED_LEN + usize::from(bytes[9]);
+        if bytes.len() < needed {
+            return Err(Error::BufferTooSmall {
+                needed,
+                got: bytes.len(),
+            });
+        }
+        Ok(Self {
+            stream_id,
+            priority,
+            name: bytes[FIXED_LEN..needed].to_vec(),
+        })
+    }
+
+    /// Wrap the body in a `StreamOpen` message whose header carries the same priority
+    #[must_use]
+    pub fn to_message(&self) -> Message {
+        let mut message = Message::new(MessageType::StreamOpen, self.encode());
+        message.set_priority(self.priority);
+        message
+    }
+
+    /// Parse the body of a `StreamOpen` message
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Error::InvalidMessageType`] for other message types and
+    /// [`Error::BufferTooSmall`] if the body is truncated.
+    pub fn from_message(message: &Message) -> Result<Self> {
+        if message.message_type() != Some(MessageType::StreamOpen) {
+            return Err(Error::InvalidMessageType {
+                type_byte: message.header().msg_type_byte(),
+            });
+        }
+        Self::decode(message.payload())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_through_message() {
+        let open = StreamOpen::new(42, Priority::Control, "control").unwrap();
+        let message = open.to_message();
+        assert_eq!(message.priority(), Priority::Control);
+        assert_eq!(message.payload().len(), FIXED_LEN + 7);
+
+        let decoded = StreamOpen::from_message(&message).unwrap();
+        assert_eq!(decoded, open);
+        assert_eq!(decoded.name(), b"control");
+    }
+
+    #[test]
+    fn unknown_priority_clamps_to_bulk() {
+        let mut bytes = StreamOpen::new(7, Priority::Interactive, "")
+            .unwrap()
+            .encode();
+        bytes[8] = 0xFF;
+        let decoded = StreamOpen::decode(&bytes).unwrap();
+        assert_eq!(decoded.priority(), Priority::Bulk);
+        assert_eq!(decoded.stream_id(), 7);
+    }
+
+    #[test]
+    fn rejects_truncated_body_and_long_names() {
+        let bytes = StreamOpen::new(1, Priority::Bulk, "file").unwrap().encode();
+        assert!(matches!(
+            StreamOpen::decode(&bytes[..bytes.len() - 1]),
+            Err(Error::BufferTooSmall {
+                needed: 14,
+                got: 13
+            })
+        ));
+        assert!(matches!(
+            StreamOpen::decode(&bytes[..4]),
+            Err(Error::BufferTooSmall { needed: 10, got: 4 })
+        ));
+        assert!(matches!(
+            StreamOpen::new(1, Priority::Bulk, vec![b'a'; 256]),
+            Err(Error::PayloadTooLarge {
+                size: 256,
+                max: 255
+            })
+        ));
+    }
+}
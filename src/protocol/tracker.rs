@@ -0,0 +1,348 @@
+//! Correlation of responses with pending calls
+//!
+//! Responses, acks, and errors echo the `message_id` of the call they answer.
+//! [`RequestTracker`] keeps the table of outstanding calls so a single connection
+//! can multiplex many requests regardless of the transport underneath.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use tracing::trace;
+
+use super::{Error, Message, MessageType, Result};
+
+/// Build the Ack a receiver owes for a message flagged `REQUIRES_ACK`
+///
+/// The Ack echoes the `message_id` and `trace_id` and carries the `message_id` again as
+/// an 8-byte little-endian payload. Returns `None` when the flag is clear or the message
+/// type already implies a reply (Call, heartbeat) or is itself an Ack.
+#[must_use]
+pub fn delivery_ack(message: &Message) -> Option<Message> {
+    if !message.flags().requires_ack() {
+        return None;
+    }
+    match message.message_type() {
+        Some(MessageType::Call | MessageType::AgentHeartbeat | MessageType::Ack) | None => None,
+        Some(_) => Some(Message::with_ids(
+            MessageType::Ack,
+            message.message_id(),
+            message.trace_id(),
+            message.message_id().to_le_bytes().to_vec(),
+        )),
+    }
+}
+
+/// Check whether `message` is an Ack built by [`delivery_ack`]
+#[must_use]
+pub fn is_delivery_ack(message: &Message) -> bool {
+    message.message_type() == Some(MessageType::Ack)
+        && message.payload().as_ref() == message.message_id().to_le_bytes()
+}
+
+/// Default number of in-flight requests
+pub const DEFAULT_MAX_PENDING: usize = 1024;
+
+/// Default per-request timeout
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// State of a tracked request
+#[derive(Debug, Clone)]
+pub enum RequestStatus {
+    /// No reply yet and the deadline has not passed
+    Pending,
+    /// Reply received (Response, Ack, or Error message)
+    Complete(Message),
+    /// Deadline passed without a reply
+    TimedOut,
+}
+
+#[derive(Debug)]
+struct PendingRequest {
+    deadline: SystemTime,
+    reply: Option<Message>,
+}
+
+/// Table of pending calls keyed by `message_id`
+#[derive(Debug)]
+pub struct RequestTracker {
+    pending: HashMap<u64, PendingRequest>,
+    max_pending: usize,
+    timeout: Duration,
+    unknown_replies: u64,
+    timed_out: u64,
+}
+
+impl Default for RequestTracker {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PENDING, DEFAULT_REQUEST_TIMEOUT)
+    }
+}
+
+impl RequestTracker {
+    /// Create a tracker holding at most `max_pending` requests
+    #[must_use]
+    pub fn new(max_pending: usize, timeout: Duration) -> Self {
+        Self {
+            pending: HashMap::new(),
+            max_pending,
+            timeout,
+            unknown_replies: 0,
+            timed_out: 0,
+        }
+    }
+
+    /// Register a call using the default timeout
+    pub fn register(&mut self, message_id: u64) -> Result<()> {
+        self.register_with_timeout(message_id, self.timeout)
+    }
+
+    /// Register a call that times out after `timeout`
+    pub fn register_with_timeout(&mut self, message_id: u64, timeout: Duration) -> Result<()> {
+        self.register_until(message_id, SystemTime::now() + timeout)
+    }
+
+    /// Register a call that times out at `deadline`
+    pub fn register_until(&mut self, message_id: u64, deadline: SystemTime) -> Result<()> {
+        if self.pending.len() >= self.max_pending {
+            return Err(Error::TooManyPending {
+                limit: self.max_pending,
+            });
+        }
+        if self.pending.contains_key(&message_id) {
+            return Err(Error::DuplicateRequest { message_id });
+        }
+        self.pending.insert(
+            message_id,
+            PendingRequest {
+                deadline,
+                reply: None,
+            },
+        );
+        Ok(())
+    }
+
+    /// Route a reply to its pending call
+    ///
+    /// Returns `false` (and drops the message) if it is not a Response, Ack,
+    /// or Error, or if no call with its `message_id` is pending.
+    pub fn complete(&mut self, message: Message) -> bool {
+        if !matches!(
+            message.message_type(),
+            Some(MessageType::Response | MessageType::Ack | MessageType::Error)
+        ) {
+            return false;
+        }
+        if let Some(request) = self.pending.get_mut(&message.message_id()) {
+            if request.reply.is_none() {
+                request.reply = Some(message);
+                return true;
+            }
+        }
+        trace!(
+            message_id = message.message_id(),
+            "dropping reply for unknown request"
+        );
+        self.unknown_replies += 1;
+        false
+    }
+
+    /// Check a call's status at `now`
+    ///
+    /// Completed and timed out requests are removed; `None` means the
+    /// `message_id` is not tracked.
+    pub fn poll(&mut self, message_id: u64, now: SystemTime) -> Option<RequestStatus> {
+        let request = self.pending.get(&message_id)?;
+        if request.reply.is_none() && request.deadline > now {
+            return Some(RequestStatus::Pending);
+        }
+        let request = self.pending.remove(&message_id)?;
+        let Some(reply) = request.reply else {
+            self.timed_out += 1;
+            return Some(RequestStatus::TimedOut);
+        };
+        Some(RequestStatus::Complete(reply))
+    }
+
+    /// Stop tracking a call (e.g. the caller gave up)
+    pub fn cancel(&mut self, message_id: u64) -> bool {
+        self.pending.remove(&message_id).is_some()
+    }
+
+    /// Remove every unanswered call whose deadline passed, returning their IDs
+    pub fn expire(&mut self, now: SystemTime) -> Vec<u64> {
+        let expired: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, request)| request.reply.is_none() && request.deadline <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &expired {
+            self.pending.remove(id);
+        }
+        self.timed_out += expired.len() as u64;
+        expired
+    }
+
+    /// Number of tracked calls (pending or completed but not yet polled)
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Check whether no calls are tracked
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Replies dropped because no matching call was pending
+    #[must_use]
+    pub const fn unknown_replies(&self) -> u64 {
+        self.unknown_replies
+    }
+
+    /// Calls that timed out without a reply
+    #[must_use]
+    pub const fn timed_out(&self) -> u64 {
+        self.timed_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Flags;
+
+    fn reply(msg_type: MessageType, message_id: u64) -> Message {
+        Message::with_ids(msg_type, message_id, 7, b"ok".to_vec())
+    }
+
+    #[test]
+    fn test_out_of_order_replies() {
+        let mut tracker = RequestTracker::default();
+        tracker.register(1).unwrap();
+        tracker.register(2).unwrap();
+        tracker.register(3).unwrap();
+
+        assert!(tracker.complete(reply(MessageType::Error, 3)));
+        assert!(tracker.complete(reply(MessageType::Response, 1)));
+
+        let now = SystemTime::now();
+        assert!(matches!(tracker.poll(2, now), Some(RequestStatus::Pending)));
+        let Some(RequestStatus::Complete(msg)) = tracker.poll(3, now) else {
+            panic!("request 3 should be complete");
+        };
+        assert_eq!(msg.message_type(), Some(MessageType::Error));
+        assert!(tracker.complete(reply(MessageType::Ack, 2)));
+        for id in [1, 2] {
+            let Some(RequestStatus::Complete(msg)) = tracker.poll(id, now) else {
+                panic!("request {id} should be complete");
+            };
+            assert_eq!(msg.message_id(), id);
+        }
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut tracker = RequestTracker::default();
+        tracker
+            .register_with_timeout(1, Duration::from_millis(10))
+            .unwrap();
+        tracker
+            .register_with_timeout(2, Duration::from_millis(10))
+            .unwrap();
+
+        let later = SystemTime::now() + Duration::from_secs(1);
+        assert!(matches!(
+            tracker.poll(1, later),
+            Some(RequestStatus::TimedOut)
+        ));
+        assert_eq!(tracker.expire(later), vec![2]);
+        assert_eq!(tracker.timed_out(), 2);
+
+        // A late reply no longer has a waiter.
+        assert!(!tracker.complete(reply(MessageType::Response, 1)));
+        assert!(tracker.poll(1, later).is_none());
+    }
+
+    #[test]
+    fn test_unknown_replies_dropped() {
+        let mut tracker = RequestTracker::default();
+        tracker.register(1).unwrap();
+
+        assert!(!tracker.complete(reply(MessageType::Response, 99)));
+        assert!(!tracker.complete(reply(MessageType::Event, 1)));
+        assert!(tracker.complete(reply(MessageType::Response, 1)));
+        assert!(!tracker.complete(reply(MessageType::Response, 1)));
+        assert_eq!(tracker.unknown_replies(), 2);
+    }
+
+    #[test]
+    fn test_delivery_ack() {
+        let mut event = Message::with_ids(MessageType::Event, 0x0102, 9, b"evt".to_vec());
+        assert!(delivery_ack(&event).is_none());
+
+        event.set_flags(Flags::new().with(Flags::REQUIRES_ACK));
+        let ack = delivery_ack(&event).expect("flagged event is acked");
+        assert_eq!(ack.message_type(), Some(MessageType::Ack));
+        assert_eq!(ack.message_id(), 0x0102);
+        assert_eq!(ack.trace_id(), 9);
+        assert_eq!(ack.payload().as_ref(), 0x0102u64.to_le_bytes());
+        assert!(is_delivery_ack(&ack));
+        assert!(!is_delivery_ack(&event));
+
+        for msg_type in [
+            MessageType::Call,
+            MessageType::AgentHeartbeat,
+            MessageType::Ack,
+        ] {
+            let mut message = Message::with_ids(msg_type, 5, 0, Vec::new());
+            message.set_flags(Flags::new().with(Flags::REQUIRES_ACK));
+            assert!(delivery_ack(&message).is_none(), "{msg_type:?}");
+        }
+    }
+
+    #[test]
+    fn test_register_until_deadline() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut tracker = RequestTracker::default();
+        tracker
+            .register_until(1, now + Duration::from_secs(1))
+            .unwrap();
+
+        assert!(matches!(tracker.poll(1, now), Some(RequestStatus::Pending)));
+        assert!(matches!(
+            tracker.poll(1, now + Duration::from_secs(1)),
+            Some(RequestStatus::TimedOut)
+        ));
+    }
+
+    #[test]
+    fn test_capacity_enforced() {
+        let mut tracker = RequestTracker::new(2, DEFAULT_REQUEST_TIMEOUT);
+        tracker.register(1).unwrap();
+        tracker.register(2).unwrap();
+
+        assert!(matches!(
+            tracker.register(3),
+            Err(Error::TooManyPending { limit: 2 })
+        ));
+        assert!(matches!(
+            tracker.register(1),
+            Err(Error::TooManyPending { .. })
+        ));
+
+        assert!(tracker.cancel(1));
+        tracker.register(3).unwrap();
+        assert!(matches!(
+            tracker.register(2),
+            Err(Error::TooManyPending { .. })
+        ));
+        assert!(tracker.cancel(2));
+        assert!(matches!(
+            tracker.register(3),
+            Err(Error::DuplicateRequest { message_id: 3 })
+        ));
+    }
+}
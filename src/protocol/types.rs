@@ -1,36 +1,79 @@
 //! MXP message types and flags
 
-use std::fmt;
+use core::fmt;
+
+/// First type byte reserved for user-defined message types
+pub const CUSTOM_TYPE_MIN: u8 = 0x80;
+
+/// Last type byte reserved for user-defined message types
+pub const CUSTOM_TYPE_MAX: u8 = 0xEF;
+
+/// Type byte of a user-defined message, always within
+/// [`CUSTOM_TYPE_MIN`]`..=`[`CUSTOM_TYPE_MAX`]
+///
+/// Built through [`CustomType::new`] or [`MessageType::custom`], so a custom type can never
+/// encode as a built-in type or as an undecodable byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CustomType(u8);
+
+impl CustomType {
+    /// Custom type for `value`, or `None` outside the reserved range
+    #[must_use]
+    pub const fn new(value: u8) -> Option<Self> {
+        if value >= CUSTOM_TYPE_MIN && value <= CUSTOM_TYPE_MAX {
+            Some(Self(value))
+        } else {
+            None
+        }
+    }
+
+    /// The type byte
+    #[must_use]
+    pub const fn as_u8(self) -> u8 {
+        self.0
+    }
+}
+
+impl fmt::Display for CustomType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04x}", self.0)
+    }
+}
 
 /// MXP message types
+///
+/// Type bytes [`CUSTOM_TYPE_MIN`]`..=`[`CUSTOM_TYPE_MAX`] are left to applications and
+/// decode as [`MessageType::Custom`]; the codec treats them like any built-in type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-#[repr(u8)]
 pub enum MessageType {
     /// Register agent with mesh
-    AgentRegister = 0x01,
+    AgentRegister,
     /// Discover agents by capability
-    AgentDiscover = 0x02,
+    AgentDiscover,
     /// Keep-alive / health check
-    AgentHeartbeat = 0x03,
+    AgentHeartbeat,
 
     /// Synchronous RPC call
-    Call = 0x10,
+    Call,
     /// Response to Call
-    Response = 0x11,
+    Response,
     /// Async event (fire-and-forget)
-    Event = 0x12,
+    Event,
 
     /// Open new stream
-    StreamOpen = 0x20,
+    StreamOpen,
     /// Stream data chunk
-    StreamChunk = 0x21,
+    StreamChunk,
     /// Close stream
-    StreamClose = 0x22,
+    StreamClose,
 
     /// Acknowledgment
-    Ack = 0xF0,
+    Ack,
     /// Error response
-    Error = 0xF1,
+    Error,
+
+    /// User-defined type in the reserved custom range
+    Custom(CustomType),
 }
 
 impl MessageType {
@@ -49,23 +92,56 @@ impl MessageType {
             0x22 => Some(Self::StreamClose),
             0xF0 => Some(Self::Ack),
             0xF1 => Some(Self::Error),
-            _ => None,
+            _ => Self::custom(value),
+        }
+    }
+
+    /// User-defined type for `value`, or `None` outside the custom range
+    #[must_use]
+    pub const fn custom(value: u8) -> Option<Self> {
+        match CustomType::new(value) {
+            Some(custom) => Some(Self::Custom(custom)),
+            None => None,
         }
     }
 
     /// Convert to byte
     #[must_use]
     pub const fn as_u8(self) -> u8 {
-        self as u8
+        match self {
+            Self::AgentRegister => 0x01,
+            Self::AgentDiscover => 0x02,
+            Self::AgentHeartbeat => 0x03,
+            Self::Call => 0x10,
+            Self::Response => 0x11,
+            Self::Event => 0x12,
+            Self::StreamOpen => 0x20,
+            Self::StreamChunk => 0x21,
+            Self::StreamClose => 0x22,
+            Self::Ack => 0xF0,
+            Self::Error => 0xF1,
+            Self::Custom(custom) => custom.as_u8(),
+        }
+    }
+
+    /// Whether this is a user-defined type
+    #[must_use]
+    pub const fn is_custom(self) -> bool {
+        matches!(self, Self::Custom(_))
     }
 
     /// Check if this message type requires a response
+    ///
+    /// Always `false` for custom types; a
+    /// [`HandlerRegistry`](super::HandlerRegistry) can say otherwise per type.
     #[must_use]
     pub const fn requires_response(self) -> bool {
         matches!(self, Self::Call | Self::AgentRegister | Self::AgentDiscover)
     }
 
     /// Check if this message type is a response
+    ///
+    /// Always `false` for custom types, as for [`requires_response`](Self::requires_response).
     #[must_use]
     pub const fn is_response(self) -> bool {
         matches!(self, Self::Response | Self::Ack | Self::Error)
@@ -86,6 +162,106 @@ impl fmt::Display for MessageType {
             Self::StreamClose => "StreamClose",
             Self::Ack => "Ack",
             Self::Error => "Error",
+            Self::Custom(custom) => return write!(f, "Custom({custom})"),
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Message priority carried in the low two bits of the header's reserved field
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Priority {
+    /// Latency-sensitive request/response traffic (default)
+    #[default]
+    Interactive = 0,
+    /// Control traffic such as heartbeats and acks
+    Control = 1,
+    /// Background or bulk transfers
+    Bulk = 2,
+}
+
+impl Priority {
+    /// Convert from the 2-bit wire value
+    #[must_use]
+    pub const fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Interactive),
+            1 => Some(Self::Control),
+            2 => Some(Self::Bulk),
+            _ => None,
+        }
+    }
+
+    /// Convert to the 2-bit wire value
+    #[must_use]
+    pub const fn as_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Interactive => "Interactive",
+            Self::Control => "Control",
+            Self::Bulk => "Bulk",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Algorithm for the 8-byte checksum trailer, recorded in the message flags
+///
+/// Decoders read the algorithm from the header, so frames using different algorithms can
+/// share a connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum ChecksumAlgorithm {
+    /// XXH3-64 over header, deadline and payload
+    #[default]
+    Xxh3 = 0,
+    /// CRC-32C (Castagnoli), zero-extended to 64 bits
+    Crc32c = 1,
+    /// No checksum: the trailer is zero and never verified, for transports that already
+    /// guarantee integrity
+    None = 2,
+}
+
+impl ChecksumAlgorithm {
+    /// Compute the trailer value for `bytes` (zero for [`ChecksumAlgorithm::None`])
+    #[must_use]
+    #[inline]
+    pub fn compute(self, bytes: &[u8]) -> u64 {
+        match self {
+            Self::Xxh3 => super::checksum(bytes),
+            Self::Crc32c => u64::from(super::crc32c::crc32c(bytes)),
+            Self::None => 0,
+        }
+    }
+
+    /// Whether decoders verify the trailer
+    #[must_use]
+    pub const fn verifies(self) -> bool {
+        !matches!(self, Self::None)
+    }
+
+    const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(Self::Xxh3),
+            1 => Some(Self::Crc32c),
+            2 => Some(Self::None),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ChecksumAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Xxh3 => "XXH3",
+            Self::Crc32c => "CRC32C",
+            Self::None => "NONE",
         };
         write!(f, "{name}")
     }
@@ -97,8 +273,12 @@ pub struct Flags(u8);
 
 impl Flags {
     /// Valid flag bits mask
-    pub const VALID_MASK: u8 =
-        Self::COMPRESSED | Self::ENCRYPTED | Self::REQUIRES_ACK | Self::FINAL;
+    pub const VALID_MASK: u8 = Self::COMPRESSED
+        | Self::ENCRYPTED
+        | Self::REQUIRES_ACK
+        | Self::FINAL
+        | Self::HAS_DEADLINE
+        | Self::CHECKSUM_MASK;
     /// Payload is compressed (zstd)
     pub const COMPRESSED: u8 = 1 << 0;
     /// Payload is encrypted (E2E)
@@ -107,6 +287,10 @@ impl Flags {
     pub const REQUIRES_ACK: u8 = 1 << 2;
     /// Last message in sequence
     pub const FINAL: u8 = 1 << 3;
+    /// Payload is prefixed with an 8-byte deadline (unix micros)
+    pub const HAS_DEADLINE: u8 = 1 << 4;
+    /// Two-bit [`ChecksumAlgorithm`] field (`00` XXH3, `01` CRC32C, `10` none)
+    pub const CHECKSUM_MASK: u8 = 0b11 << CHECKSUM_SHIFT;
 
     /// Create empty flags
     #[must_use]
@@ -117,7 +301,10 @@ impl Flags {
     /// Create from byte
     #[must_use]
     pub const fn from_u8(value: u8) -> Option<Self> {
-        if value & !Self::VALID_MASK == 0 {
+        if value & !Self::VALID_MASK == 0
+            && ChecksumAlgorithm::from_bits((value & Self::CHECKSUM_MASK) >> CHECKSUM_SHIFT)
+                .is_some()
+        {
             Some(Self(value))
         } else {
             None
@@ -167,28 +354,66 @@ impl Flags {
     pub const fn is_final(self) -> bool {
         self.has(Self::FINAL)
     }
+
+    /// Check if the payload carries a deadline prefix
+    #[must_use]
+    pub const fn has_deadline(self) -> bool {
+        self.has(Self::HAS_DEADLINE)
+    }
+
+    /// Clear a flag
+    #[must_use]
+    pub const fn without(mut self, flag: u8) -> Self {
+        self.0 &= !flag;
+        self
+    }
+
+    /// Checksum algorithm used for the message trailer
+    #[must_use]
+    pub const fn checksum_algorithm(self) -> ChecksumAlgorithm {
+        match ChecksumAlgorithm::from_bits((self.0 & Self::CHECKSUM_MASK) >> CHECKSUM_SHIFT) {
+            Some(algorithm) => algorithm,
+            None => ChecksumAlgorithm::Xxh3,
+        }
+    }
+
+    /// Select the checksum algorithm used for the message trailer
+    #[must_use]
+    pub const fn with_checksum_algorithm(mut self, algorithm: ChecksumAlgorithm) -> Self {
+        self.0 = (self.0 & !Self::CHECKSUM_MASK) | ((algorithm as u8) << CHECKSUM_SHIFT);
+        self
+    }
 }
 
+/// Bit offset of [`Flags::CHECKSUM_MASK`]
+const CHECKSUM_SHIFT: u8 = 5;
+
 impl fmt::Display for Flags {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut parts = Vec::new();
-        if self.is_compressed() {
-            parts.push("COMPRESSED");
-        }
-        if self.is_encrypted() {
-            parts.push("ENCRYPTED");
-        }
-        if self.requires_ack() {
-            parts.push("REQUIRES_ACK");
+        let parts = [
+            (self.is_compressed(), "COMPRESSED"),
+            (self.is_encrypted(), "ENCRYPTED"),
+            (self.requires_ack(), "REQUIRES_ACK"),
+            (self.is_final(), "FINAL"),
+            (self.has_deadline(), "HAS_DEADLINE"),
+        ];
+        let mut written = false;
+        for (_, name) in parts.iter().filter(|(set, _)| *set) {
+            if written {
+                f.write_str(" | ")?;
+            }
+            f.write_str(name)?;
+            written = true;
         }
-        if self.is_final() {
-            parts.push("FINAL");
-        }
-        if parts.is_empty() {
-            write!(f, "NONE")
-        } else {
-            write!(f, "{}", parts.join(" | "))
+        let checksum = self.checksum_algorithm();
+        if checksum != ChecksumAlgorithm::Xxh3 {
+            if written {
+                f.write_str(" | ")?;
+            }
+            write!(f, "CHECKSUM_{checksum}")?;
+            written = true;
         }
+        if written { Ok(()) } else { f.write_str("NONE") }
     }
 }
 
@@ -211,6 +436,59 @@ mod tests {
         }
     }
 
+    #[test]
+    fn custom_range_decodes_as_custom() {
+        for byte in CUSTOM_TYPE_MIN..=CUSTOM_TYPE_MAX {
+            let msg_type = MessageType::from_u8(byte).unwrap();
+            assert_eq!(
+                msg_type,
+                MessageType::Custom(CustomType::new(byte).unwrap())
+            );
+            assert_eq!(msg_type.as_u8(), byte);
+            assert_eq!(MessageType::custom(byte), Some(msg_type));
+            assert!(msg_type.is_custom());
+            assert!(!msg_type.requires_response());
+            assert!(!msg_type.is_response());
+        }
+        assert_eq!(MessageType::from_u8(CUSTOM_TYPE_MIN - 1), None);
+        assert_eq!(
+            MessageType::from_u8(CUSTOM_TYPE_MAX + 1),
+            Some(MessageType::Ack)
+        );
+        assert_eq!(MessageType::custom(0xF0), None);
+        assert_eq!(MessageType::custom(0x10), None);
+        assert!(!MessageType::Call.is_custom());
+        assert_eq!(
+            MessageType::custom(0x80).unwrap().to_string(),
+            "Custom(0x80)"
+        );
+    }
+
+    #[test]
+    fn custom_types_cannot_alias_other_bytes() {
+        for byte in 0..=u8::MAX {
+            let in_range = (CUSTOM_TYPE_MIN..=CUSTOM_TYPE_MAX).contains(&byte);
+            assert_eq!(CustomType::new(byte).is_some(), in_range, "{byte:#04x}");
+            assert_eq!(MessageType::custom(byte).is_some(), in_range, "{byte:#04x}");
+            if let Some(custom) = CustomType::new(byte) {
+                assert_eq!(custom.as_u8(), byte);
+                assert_eq!(
+                    MessageType::from_u8(byte),
+                    Some(MessageType::Custom(custom))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_priority_roundtrip() {
+        for priority in [Priority::Interactive, Priority::Control, Priority::Bulk] {
+            assert_eq!(Priority::from_u8(priority.as_u8()), Some(priority));
+        }
+        assert_eq!(Priority::default(), Priority::Interactive);
+        assert_eq!(Priority::from_u8(3), None);
+    }
+
     #[test]
     fn test_flags() {
         let flags = Flags::new()
@@ -0,0 +1,280 @@
+//! XXH3 64-bit hash (default secret, seed 0), implemented from the reference design.
+//!
+//! Produces the same values as the reference `XXH3_64bits`; kept in-tree so the wire
+//! format builds without external hashing crates.
+
+const STRIPE_LEN: usize = 64;
+const SECRET_CONSUME_RATE: usize = 8;
+const ACC_NB: usize = STRIPE_LEN / 8;
+const SECRET_MERGEACCS_START: usize = 11;
+const SECRET_LASTACC_START: usize = 7;
+const MIDSIZE_STARTOFFSET: usize = 3;
+const MIDSIZE_LASTOFFSET: usize = 17;
+const MID_SIZE_MAX: usize = 240;
+const SECRET_SIZE_MIN: usize = 136;
+const SECRET_LEN: usize = 192;
+const STRIPES_PER_BLOCK: usize = (SECRET_LEN - STRIPE_LEN) / SECRET_CONSUME_RATE;
+const BLOCK_LEN: usize = STRIPE_LEN * STRIPES_PER_BLOCK;
+
+const PRIME32_1: u64 = 0x9E37_79B1;
+const PRIME32_2: u64 = 0x85EB_CA77;
+const PRIME32_3: u64 = 0xC2B2_AE3D;
+const PRIME64_1: u64 = 0x9E37_79B1_85EB_CA87;
+const PRIME64_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const PRIME64_3: u64 = 0x1656_67B1_9E37_79F9;
+const PRIME64_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const PRIME64_5: u64 = 0x27D4_EB2F_1656_67C5;
+const PRIME_MX1: u64 = 0x1656_6791_9E37_79F9;
+const PRIME_MX2: u64 = 0x9FB2_1C65_1E98_DF25;
+
+const SECRET: [u8; SECRET_LEN] = [
+    0xb8, 0xfe, 0x6c, 0x39, 0x23, 0xa4, 0x4b, 0xbe, 0x7c, 0x01, 0x81, 0x2c, 0xf7, 0x21, 0xad, 0x1c,
+    0xde, 0xd4, 0x6d, 0xe9, 0x83, 0x90, 0x97, 0xdb, 0x72, 0x40, 0xa4, 0xa4, 0xb7, 0xb3, 0x67, 0x1f,
+    0xcb, 0x79, 0xe6, 0x4e, 0xcc, 0xc0, 0xe5, 0x78, 0x82, 0x5a, 0xd0, 0x7d, 0xcc, 0xff, 0x72, 0x21,
+    0xb8, 0x08, 0x46, 0x74, 0xf7, 0x43, 0x24, 0x8e, 0xe0, 0x35, 0x90, 0xe6, 0x81, 0x3a, 0x26, 0x4c,
+    0x3c, 0x28, 0x52, 0xbb, 0x91, 0xc3, 0x00, 0xcb, 0x88, 0xd0, 0x65, 0x8b, 0x1b, 0x53, 0x2e, 0xa3,
+    0x71, 0x64, 0x48, 0x97, 0xa2, 0x0d, 0xf9, 0x4e, 0x38, 0x19, 0xef, 0x46, 0xa9, 0xde, 0xac, 0xd8,
+    0xa8, 0xfa, 0x76, 0x3f, 0xe3, 0x9c, 0x34, 0x3f, 0xf9, 0xdc, 0xbb, 0xc7, 0xc7, 0x0b, 0x4f, 0x1d,
+    0x8a, 0x51, 0xe0, 0x4b, 0xcd, 0xb4, 0x59, 0x31, 0xc8, 0x9f, 0x7e, 0xc9, 0xd9, 0x78, 0x73, 0x64,
+    0xea, 0xc5, 0xac, 0x83, 0x34, 0xd3, 0xeb, 0xc3, 0xc5, 0x81, 0xa0, 0xff, 0xfa, 0x13, 0x63, 0xeb,
+    0x17, 0x0d, 0xdd, 0x51, 0xb7, 0xf0, 0xda, 0x49, 0xd3, 0x16, 0x55, 0x26, 0x29, 0xd4, 0x68, 0x9e,
+    0x2b, 0x16, 0xbe, 0x58, 0x7d, 0x47, 0xa1, 0xfc, 0x8f, 0xf8, 0xb8, 0xd1, 0x7a, 0xd0, 0x31, 0xce,
+    0x45, 0xcb, 0x3a, 0x8f, 0x95, 0x16, 0x04, 0x28, 0xaf, 0xd7, 0xfb, 0xca, 0xbb, 0x4b, 0x40, 0x7e,
+];
+
+#[inline]
+fn read32(bytes: &[u8], offset: usize) -> u64 {
+    u64::from(u32::from_le_bytes(
+        bytes[offset..offset + 4].try_into().expect("four bytes"),
+    ))
+}
+
+#[inline]
+fn read64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().expect("eight bytes"))
+}
+
+#[inline]
+fn mul128_fold64(left: u64, right: u64) -> u64 {
+    let product = u128::from(left) * u128::from(right);
+    #[allow(clippy::cast_possible_truncation)] // folding the two halves
+    let folded = (product as u64) ^ ((product >> 64) as u64);
+    folded
+}
+
+const fn avalanche(mut value: u64) -> u64 {
+    value ^= value >> 37;
+    value = value.wrapping_mul(PRIME_MX1);
+    value ^ (value >> 32)
+}
+
+/// The XXH64 finalizer, used for the shortest inputs.
+const fn xxh64_avalanche(mut value: u64) -> u64 {
+    value ^= value >> 33;
+    value = value.wrapping_mul(PRIME64_2);
+    value ^= value >> 29;
+    value = value.wrapping_mul(PRIME64_3);
+    value ^ (value >> 32)
+}
+
+const fn rrmxmx(mut value: u64, len: u64) -> u64 {
+    value ^= value.rotate_left(49) ^ value.rotate_left(24);
+    value = value.wrapping_mul(PRIME_MX2);
+    value ^= (value >> 35).wrapping_add(len);
+    value = value.wrapping_mul(PRIME_MX2);
+    value ^ (value >> 28)
+}
+
+#[inline]
+fn mix16(input: &[u8], input_offset: usize, secret_offset: usize) -> u64 {
+    mul128_fold64(
+        read64(input, input_offset) ^ read64(&SECRET, secret_offset),
+        read64(input, input_offset + 8) ^ read64(&SECRET, secret_offset + 8),
+    )
+}
+
+fn hash_1_to_3(input: &[u8]) -> u64 {
+    let len = input.len();
+    let combined = (u32::from(input[0]) << 16)
+        | (u32::from(input[len >> 1]) << 24)
+        | u32::from(input[len - 1])
+        | (u32::try_from(len).expect("at most three bytes") << 8);
+    let bitflip = read32(&SECRET, 0) ^ read32(&SECRET, 4);
+    xxh64_avalanche(u64::from(combined) ^ bitflip)
+}
+
+fn hash_4_to_8(input: &[u8]) -> u64 {
+    let len = input.len();
+    let bitflip = read64(&SECRET, 8) ^ read64(&SECRET, 16);
+    let combined = read32(input, len - 4) | (read32(input, 0) << 32);
+    rrmxmx(combined ^ bitflip, len as u64)
+}
+
+fn hash_9_to_16(input: &[u8]) -> u64 {
+    let len = input.len();
+    let low = read64(input, 0) ^ (read64(&SECRET, 24) ^ read64(&SECRET, 32));
+    let high = read64(input, len - 8) ^ (read64(&SECRET, 40) ^ read64(&SECRET, 48));
+    let acc = (len as u64)
+        .wrapping_add(low.swap_bytes())
+        .wrapping_add(high)
+        .wrapping_add(mul128_fold64(low, high));
+    avalanche(acc)
+}
+
+fn hash_17_to_128(input: &[u8]) -> u64 {
+    let len = input.len();
+    let mut acc = (len as u64).wrapping_mul(PRIME64_1);
+    if len > 32 {
+        if len > 64 {
+            if len > 96 {
+                acc = acc.wrapping_add(mix16(input, 48, 96));
+                acc = acc.wrapping_add(mix16(input, len - 64, 112));
+            }
+            acc = acc.wrapping_add(mix16(input, 32, 64));
+            acc = acc.wrapping_add(mix16(input, len - 48, 80));
+        }
+        acc = acc.wrapping_add(mix16(input, 16, 32));
+        acc = acc.wrapping_add(mix16(input, len - 32, 48));
+    }
+    acc = acc.wrapping_add(mix16(input, 0, 0));
+    acc = acc.wrapping_add(mix16(input, len - 16, 16));
+    avalanche(acc)
+}
+
+fn hash_129_to_240(input: &[u8]) -> u64 {
+    let len = input.len();
+    let mut acc = (len as u64).wrapping_mul(PRIME64_1);
+    let rounds = len / 16;
+    for round in 0..8 {
+        acc = acc.wrapping_add(mix16(input, 16 * round, 16 * round));
+    }
+    acc = avalanche(acc);
+    for round in 8..rounds {
+        acc = acc.wrapping_add(mix16(
+            input,
+            16 * round,
+            16 * (round - 8) + MIDSIZE_STARTOFFSET,
+        ));
+    }
+    acc = acc.wrapping_add(mix16(input, len - 16, SECRET_SIZE_MIN - MIDSIZE_LASTOFFSET));
+    avalanche(acc)
+}
+
+#[inline]
+fn accumulate_stripe(acc: &mut [u64; ACC_NB], stripe: &[u8], secret_offset: usize) {
+    let stripe: &[u8; STRIPE_LEN] = stripe[..STRIPE_LEN].try_into().expect("stripe length");
+    let secret: &[u8; STRIPE_LEN] = SECRET[secret_offset..secret_offset + STRIPE_LEN]
+        .try_into()
+        .expect("secret length");
+    let mut data = [0u64; ACC_NB];
+    let mut keys = [0u64; ACC_NB];
+    for lane in 0..ACC_NB {
+        data[lane] = u64::from_le_bytes(stripe[8 * lane..8 * lane + 8].try_into().unwrap());
+        keys[lane] =
+            data[lane] ^ u64::from_le_bytes(secret[8 * lane..8 * lane + 8].try_into().unwrap());
+    }
+    for lane in 0..ACC_NB {
+        acc[lane ^ 1] = acc[lane ^ 1].wrapping_add(data[lane]);
+        acc[lane] =
+            acc[lane].wrapping_add((keys[lane] & 0xFFFF_FFFF).wrapping_mul(keys[lane] >> 32));
+    }
+}
+
+fn accumulate(acc: &mut [u64; ACC_NB], input: &[u8], stripes: usize) {
+    for (stripe, chunk) in input.chunks_exact(STRIPE_LEN).take(stripes).enumerate() {
+        accumulate_stripe(acc, chunk, stripe * SECRET_CONSUME_RATE);
+    }
+}
+
+fn scramble(acc: &mut [u64; ACC_NB]) {
+    let secret_offset = SECRET_LEN - STRIPE_LEN;
+    for (lane, value) in acc.iter_mut().enumerate() {
+        let key = read64(&SECRET, secret_offset + 8 * lane);
+        *value = (*value ^ (*value >> 47) ^ key).wrapping_mul(PRIME32_1);
+    }
+}
+
+fn hash_long(input: &[u8]) -> u64 {
+    let len = input.len();
+    let mut acc = [
+        PRIME32_3, PRIME64_1, PRIME64_2, PRIME64_3, PRIME64_4, PRIME32_2, PRIME64_5, PRIME32_1,
+    ];
+
+    let blocks = (len - 1) / BLOCK_LEN;
+    for block in 0..blocks {
+        accumulate(&mut acc, &input[block * BLOCK_LEN..], STRIPES_PER_BLOCK);
+        scramble(&mut acc);
+    }
+    let tail_stripes = ((len - 1) - BLOCK_LEN * blocks) / STRIPE_LEN;
+    accumulate(&mut acc, &input[blocks * BLOCK_LEN..], tail_stripes);
+    accumulate_stripe(
+        &mut acc,
+        &input[len - STRIPE_LEN..],
+        SECRET_LEN - STRIPE_LEN - SECRET_LASTACC_START,
+    );
+
+    let mut result = (len as u64).wrapping_mul(PRIME64_1);
+    for pair in 0..ACC_NB / 2 {
+        let secret_offset = SECRET_MERGEACCS_START + 16 * pair;
+        result = result.wrapping_add(mul128_fold64(
+            acc[2 * pair] ^ read64(&SECRET, secret_offset),
+            acc[2 * pair + 1] ^ read64(&SECRET, secret_offset + 8),
+        ));
+    }
+    avalanche(result)
+}
+
+/// Compute the 64-bit XXH3 hash of `input`.
+#[must_use]
+pub fn xxh3_64(input: &[u8]) -> u64 {
+    match input.len() {
+        0 => xxh64_avalanche(read64(&SECRET, 56) ^ read64(&SECRET, 64)),
+        1..=3 => hash_1_to_3(input),
+        4..=8 => hash_4_to_8(input),
+        9..=16 => hash_9_to_16(input),
+        17..=128 => hash_17_to_128(input),
+        129..=MID_SIZE_MAX => hash_129_to_240(input),
+        _ => hash_long(input),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    /// Deterministic input covering every length class and block boundary.
+    fn input(len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|idx| u8::try_from((idx * 7 + idx / 251) % 256).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn known_values() {
+        assert_eq!(xxh3_64(b""), 0x2D06_8005_38D3_94C2);
+        assert_eq!(xxh3_64(b"a"), 0xE6C6_32B6_1E96_4E1F);
+        assert_eq!(xxh3_64(b"abc"), 0x78AF_5F94_892F_3950);
+    }
+
+    #[test]
+    fn matches_reference_across_length_classes() {
+        for len in (0..=300).chain([
+            1023, 1024, 1025, 1087, 1088, 1089, 2048, 4096, 16_384, 65_537,
+        ]) {
+            let data = input(len);
+            assert_eq!(
+                xxh3_64(&data),
+                xxhash_rust::xxh3::xxh3_64(&data),
+                "len {len}"
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn matches_reference(data in proptest::collection::vec(any::<u8>(), 0..4096)) {
+            prop_assert_eq!(xxh3_64(&data), xxhash_rust::xxh3::xxh3_64(&data));
+        }
+    }
+}
@@ -0,0 +1,41 @@
+//! Shared simulated clock.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::transport::{Clock, MockClock};
+
+/// [`Clock`] shared by a [`SimNetwork`](super::SimNetwork) and everything driven by it.
+///
+/// Clones observe the same time, so advancing any of them releases datagrams whose
+/// simulated delivery time has come.
+#[derive(Debug, Clone, Default)]
+pub struct SimClock {
+    inner: Arc<MockClock>,
+}
+
+impl SimClock {
+    /// Create a clock reading `start`.
+    #[must_use]
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            inner: Arc::new(MockClock::new(start)),
+        }
+    }
+
+    /// Move the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        self.inner.advance(by);
+    }
+
+    /// Set the clock to `now`.
+    pub fn set(&self, now: SystemTime) {
+        self.inner.set(now);
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> SystemTime {
+        self.inner.now()
+    }
+}
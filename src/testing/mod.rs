@@ -0,0 +1,41 @@
+//! Deterministic network simulation for testing agents and transport drivers.
+//!
+//! A [`SimNetwork`] connects any number of [`MemorySocket`]s through simulated links with
+//! configurable loss, latency, reordering, bandwidth and MTU. Everything is driven by a
+//! seeded generator and a [`SimClock`] that only moves when told to, so a run with the same
+//! seed and the same sequence of calls always delivers the same datagrams at the same times.
+//!
+//! [`MemorySocket`] implements [`DatagramSocket`](crate::transport::DatagramSocket), so it
+//! can back a [`TransportHandle`](crate::transport::TransportHandle) via
+//! [`Transport::bind_socket`](crate::transport::Transport::bind_socket):
+//!
+//! ```rust
+//! use std::time::Duration;
+//!
+//! use mxp::testing::{Latency, LinkConfig, SimNetwork};
+//! use mxp::transport::Transport;
+//!
+//! let network = SimNetwork::new(7);
+//! network.set_default_link(LinkConfig {
+//!     latency: Latency::Fixed(Duration::from_millis(20)),
+//!     ..LinkConfig::default()
+//! });
+//! let (a, b) = network.socket_pair();
+//! let transport = Transport::default();
+//! let (a, b) = (transport.bind_socket(a)?, transport.bind_socket(b)?);
+//!
+//! a.send(b"ping", b.local_addr()?)?;
+//! let mut buffer = b.acquire_buffer();
+//! // Nothing arrives until simulated time passes.
+//! assert!(b.receive(&mut buffer).is_err());
+//! network.clock().advance(Duration::from_millis(20));
+//! let (len, from) = b.receive(&mut buffer)?;
+//! assert_eq!((&buffer.as_slice()[..len], from), (&b"ping"[..], a.local_addr()?));
+//! # Ok::<(), mxp::transport::SocketError>(())
+//! ```
+
+mod clock;
+mod network;
+
+pub use clock::SimClock;
+pub use network::{Latency, LinkConfig, MemorySocket, SimNetwork};
@@ -0,0 +1,460 @@
+//! Simulated links and in-memory sockets.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use super::SimClock;
+use crate::transport::{Clock, DatagramSocket, EcnCodepoint, RecvMeta, SocketError};
+
+/// First port handed out to sockets bound on port 0.
+const FIRST_EPHEMERAL_PORT: u16 = 49_152;
+
+/// Distribution of one-way link latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Latency {
+    /// Every datagram takes exactly this long.
+    Fixed(Duration),
+    /// Latency drawn uniformly from `min..=max`.
+    Uniform {
+        /// Shortest latency.
+        min: Duration,
+        /// Longest latency.
+        max: Duration,
+    },
+}
+
+impl Latency {
+    fn sample(self, rng: &mut SimRng) -> Duration {
+        match self {
+            Self::Fixed(latency) => latency,
+            Self::Uniform { min, max } => min + max.saturating_sub(min).mul_f64(rng.next_f64()),
+        }
+    }
+}
+
+/// Behaviour of a one-way link between two sockets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkConfig {
+    /// Probability in `0.0..=1.0` that a datagram is dropped.
+    pub loss_rate: f64,
+    /// One-way latency, added after any queueing behind the bandwidth cap.
+    pub latency: Latency,
+    /// Probability that a datagram is held back for a second latency sample, letting later
+    /// ones overtake it.
+    pub reorder_rate: f64,
+    /// Capacity in bytes per second; datagrams queue behind each other when set.
+    pub bandwidth: Option<u64>,
+    /// Largest datagram the link carries; longer ones are dropped, as on a path with a
+    /// smaller MTU.
+    pub max_datagram_size: Option<usize>,
+}
+
+impl Default for LinkConfig {
+    /// A perfect link: no loss, no latency, no reordering and unlimited capacity.
+    fn default() -> Self {
+        Self {
+            loss_rate: 0.0,
+            latency: Latency::Fixed(Duration::ZERO),
+            reorder_rate: 0.0,
+            bandwidth: None,
+            max_datagram_size: None,
+        }
+    }
+}
+
+/// Linear congruential generator; reproducible across platforms, which is all the
+/// simulation needs.
+#[derive(Debug)]
+struct SimRng(u64);
+
+impl SimRng {
+    fn next_u64(&mut self) -> u64 {
+        const A: u64 = 6_364_136_223_846_793_005;
+        const C: u64 = 1_442_695_040_888_963_407;
+        self.0 = self.0.wrapping_mul(A).wrapping_add(C);
+        self.0
+    }
+
+    /// Uniform value in `0.0..1.0`.
+    fn next_f64(&mut self) -> f64 {
+        #[allow(clippy::cast_precision_loss)] // 53-bit values are exact in an f64
+        let value = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        value
+    }
+}
+
+#[derive(Debug)]
+struct Datagram {
+    from: SocketAddr,
+    bytes: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Inbox {
+    /// Datagrams keyed by delivery time, then send order.
+    queue: BTreeMap<(SystemTime, u64), Datagram>,
+    /// Peer set by `connect`; datagrams from anyone else are discarded.
+    peer: Option<SocketAddr>,
+}
+
+type Link = (SocketAddr, SocketAddr);
+
+#[derive(Debug)]
+struct State {
+    rng: SimRng,
+    default_link: LinkConfig,
+    links: HashMap<Link, LinkConfig>,
+    /// When each bandwidth-capped link finishes sending what it has already accepted.
+    busy_until: HashMap<Link, SystemTime>,
+    forced_drops: HashMap<Link, u64>,
+    inboxes: HashMap<SocketAddr, Inbox>,
+    sequence: u64,
+    next_port: u16,
+}
+
+impl State {
+    fn transmit(&mut self, now: SystemTime, link: Link, bytes: &[u8]) {
+        let config = self.links.get(&link).copied().unwrap_or(self.default_link);
+        if let Some(remaining) = self.forced_drops.get_mut(&link).filter(|left| **left > 0) {
+            *remaining -= 1;
+            return;
+        }
+        if config
+            .max_datagram_size
+            .is_some_and(|max| bytes.len() > max)
+        {
+            return;
+        }
+        if self.rng.next_f64() < config.loss_rate {
+            return;
+        }
+        let mut departs = now;
+        if let Some(bandwidth) = config.bandwidth.filter(|rate| *rate > 0) {
+            let start = self
+                .busy_until
+                .get(&link)
+                .map_or(now, |busy| (*busy).max(now));
+            departs = start + transmission_time(bytes.len(), bandwidth);
+            self.busy_until.insert(link, departs);
+        }
+        let mut deliver_at = departs + config.latency.sample(&mut self.rng);
+        if self.rng.next_f64() < config.reorder_rate {
+            deliver_at += config.latency.sample(&mut self.rng);
+        }
+        self.sequence += 1;
+        // Datagrams to unbound addresses vanish, as they would over UDP.
+        if let Some(inbox) = self.inboxes.get_mut(&link.1) {
+            inbox.queue.insert(
+                (deliver_at, self.sequence),
+                Datagram {
+                    from: link.0,
+                    bytes: bytes.to_vec(),
+                },
+            );
+        }
+    }
+
+    fn ephemeral_port(&mut self, ip: IpAddr) -> Option<u16> {
+        for _ in FIRST_EPHEMERAL_PORT..=u16::MAX {
+            let port = self.next_port;
+            self.next_port = port.checked_add(1).unwrap_or(FIRST_EPHEMERAL_PORT);
+            if !self.inboxes.contains_key(&SocketAddr::new(ip, port)) {
+                return Some(port);
+            }
+        }
+        None
+    }
+}
+
+/// Time to clock `len` bytes onto a link of `bandwidth` bytes per second, rounded up.
+fn transmission_time(len: usize, bandwidth: u64) -> Duration {
+    let nanos = (len as u128 * 1_000_000_000).div_ceil(u128::from(bandwidth));
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+/// Deterministic in-memory network connecting [`MemorySocket`]s.
+///
+/// Links are one-way and keyed by sender and receiver address; those without a
+/// [`set_link`](Self::set_link) override use the default [`LinkConfig`]. Loss, latency and
+/// reordering are drawn from a generator seeded at construction, and a datagram becomes
+/// receivable once the [`SimClock`] reaches its delivery time. Clones share the network.
+#[derive(Debug, Clone)]
+pub struct SimNetwork {
+    state: Arc<Mutex<State>>,
+    clock: SimClock,
+}
+
+impl SimNetwork {
+    /// Create an empty network with perfect links and a clock at the unix epoch.
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self::with_clock(seed, SimClock::default())
+    }
+
+    /// Create an empty network driven by `clock`.
+    #[must_use]
+    pub fn with_clock(seed: u64, clock: SimClock) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                rng: SimRng(seed),
+                default_link: LinkConfig::default(),
+                links: HashMap::new(),
+                busy_until: HashMap::new(),
+                forced_drops: HashMap::new(),
+                inboxes: HashMap::new(),
+                sequence: 0,
+                next_port: FIRST_EPHEMERAL_PORT,
+            })),
+            clock,
+        }
+    }
+
+    /// Clock deciding when datagrams arrive.
+    #[must_use]
+    pub fn clock(&self) -> &SimClock {
+        &self.clock
+    }
+
+    /// Configure every link without its own override.
+    pub fn set_default_link(&self, config: LinkConfig) {
+        self.lock().default_link = config;
+    }
+
+    /// Configure the link carrying datagrams from `from` to `to`.
+    pub fn set_link(&self, from: SocketAddr, to: SocketAddr, config: LinkConfig) {
+        self.lock().links.insert((from, to), config);
+    }
+
+    /// Drop the next `count` datagrams sent from `from` to `to`, before random loss applies.
+    pub fn drop_next(&self, from: SocketAddr, to: SocketAddr, count: u64) {
+        *self.lock().forced_drops.entry((from, to)).or_default() += count;
+    }
+
+    /// Bind a socket at `addr`; port 0 picks an unused port.
+    ///
+    /// Addresses only identify sockets within this network; no real interface is involved.
+    pub fn bind(&self, addr: SocketAddr) -> Result<MemorySocket, SocketError> {
+        let mut state = self.lock();
+        let mut addr = addr;
+        if addr.port() == 0 {
+            let port = state
+                .ephemeral_port(addr.ip())
+                .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?;
+            addr.set_port(port);
+        }
+        if state.inboxes.contains_key(&addr) {
+            return Err(io::Error::from(io::ErrorKind::AddrInUse).into());
+        }
+        state.inboxes.insert(addr, Inbox::default());
+        Ok(MemorySocket {
+            network: self.clone(),
+            addr,
+        })
+    }
+
+    /// Bind two sockets on loopback ports, ready to talk to each other.
+    #[must_use]
+    pub fn socket_pair(&self) -> (MemorySocket, MemorySocket) {
+        let loopback = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+        let first = self.bind(loopback).expect("ephemeral port available");
+        let second = self.bind(loopback).expect("ephemeral port available");
+        (first, second)
+    }
+
+    /// Earliest time a datagram still in flight becomes receivable.
+    ///
+    /// Drivers with nothing else to do can advance the clock straight to it.
+    #[must_use]
+    pub fn next_delivery(&self) -> Option<SystemTime> {
+        self.lock()
+            .inboxes
+            .values()
+            .filter_map(|inbox| inbox.queue.keys().next().map(|(at, _)| *at))
+            .min()
+    }
+
+    /// Datagrams sent but not yet received, delivered or not.
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        self.lock()
+            .inboxes
+            .values()
+            .map(|inbox| inbox.queue.len())
+            .sum()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Socket attached to a [`SimNetwork`].
+///
+/// Receives never block: with nothing deliverable at the current simulated time they fail
+/// with [`io::ErrorKind::WouldBlock`], because simulated time cannot pass while waiting.
+/// Dropping the socket unbinds its address and discards its queued datagrams.
+#[derive(Debug)]
+pub struct MemorySocket {
+    network: SimNetwork,
+    addr: SocketAddr,
+}
+
+impl DatagramSocket for MemorySocket {
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, SocketError> {
+        let now = self.network.clock.now();
+        self.network.lock().transmit(now, (self.addr, addr), buf);
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<RecvMeta, SocketError> {
+        let now = self.network.clock.now();
+        let mut state = self.network.lock();
+        let inbox = state
+            .inboxes
+            .get_mut(&self.addr)
+            .expect("socket stays bound until dropped");
+        while let Some(entry) = inbox.queue.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            let datagram = entry.remove();
+            if inbox.peer.is_some_and(|peer| peer != datagram.from) {
+                continue;
+            }
+            // Like UDP, a datagram longer than the buffer is truncated.
+            let len = datagram.bytes.len().min(buf.len());
+            buf[..len].copy_from_slice(&datagram.bytes[..len]);
+            return Ok(RecvMeta {
+                len,
+                addr: datagram.from,
+                ecn: EcnCodepoint::NotEct,
+            });
+        }
+        Err(io::Error::from(io::ErrorKind::WouldBlock).into())
+    }
+
+    fn recv_batch(
+        &self,
+        buffers: &mut [&mut [u8]],
+    ) -> Result<Vec<(usize, SocketAddr)>, SocketError> {
+        let mut received = Vec::new();
+        for buf in buffers.iter_mut() {
+            match self.recv_from(buf) {
+                Ok(meta) => received.push((meta.len, meta.addr)),
+                Err(err) if received.is_empty() => return Err(err),
+                Err(_) => break,
+            }
+        }
+        Ok(received)
+    }
+
+    fn connect(&self, addr: SocketAddr) -> Result<(), SocketError> {
+        if let Some(inbox) = self.network.lock().inboxes.get_mut(&self.addr) {
+            inbox.peer = Some(addr);
+        }
+        Ok(())
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr, SocketError> {
+        self.network
+            .lock()
+            .inboxes
+            .get(&self.addr)
+            .and_then(|inbox| inbox.peer)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotConnected).into())
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, SocketError> {
+        Ok(self.addr)
+    }
+}
+
+impl Drop for MemorySocket {
+    fn drop(&mut self) {
+        self.network.lock().inboxes.remove(&self.addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn receive_all(socket: &MemorySocket) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 1];
+        while let Ok(meta) = socket.recv_from(&mut buf) {
+            received.push(buf[..meta.len][0]);
+        }
+        received
+    }
+
+    fn lossy_run(seed: u64) -> Vec<u8> {
+        let network = SimNetwork::new(seed);
+        network.set_default_link(LinkConfig {
+            loss_rate: 0.25,
+            latency: Latency::Uniform {
+                min: Duration::from_millis(10),
+                max: Duration::from_millis(30),
+            },
+            reorder_rate: 0.2,
+            bandwidth: None,
+            max_datagram_size: None,
+        });
+        let (a, b) = network.socket_pair();
+        let to = b.local_addr().unwrap();
+        for byte in 0..200u8 {
+            a.send_to(&[byte], to).unwrap();
+        }
+        assert!(receive_all(&b).is_empty());
+        let first = network.next_delivery().expect("datagrams in flight");
+        assert!(first >= network.clock().now() + Duration::from_millis(10));
+        network.clock().advance(Duration::from_millis(60));
+        let received = receive_all(&b);
+        assert_eq!(network.in_flight(), 0);
+        received
+    }
+
+    #[test]
+    fn runs_are_reproducible_lossy_and_reordered() {
+        let received = lossy_run(42);
+        assert_eq!(received, lossy_run(42));
+        assert_ne!(received, lossy_run(43));
+        assert!(
+            received.len() > 120 && received.len() < 180,
+            "{} of 200 delivered",
+            received.len()
+        );
+        assert!(received.windows(2).any(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn forced_drops_connect_and_unbound_addresses() {
+        let network = SimNetwork::new(1);
+        let (a, b) = network.socket_pair();
+        let c = network.bind("10.0.0.1:5000".parse().unwrap()).unwrap();
+        let (a_addr, b_addr) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+        assert!(matches!(
+            network.bind(c.local_addr().unwrap()),
+            Err(SocketError::Io(err)) if err.kind() == io::ErrorKind::AddrInUse
+        ));
+
+        network.drop_next(a_addr, b_addr, 1);
+        a.send_to(&[1], b_addr).unwrap();
+        a.send_to(&[2], b_addr).unwrap();
+        assert_eq!(receive_all(&b), [2]);
+
+        assert!(b.peer_addr().is_err());
+        b.connect(a_addr).unwrap();
+        assert_eq!(b.peer_addr().unwrap(), a_addr);
+        c.send_to(&[3], b_addr).unwrap();
+        a.send_to(&[4], b_addr).unwrap();
+        assert_eq!(receive_all(&b), [4]);
+
+        drop(c);
+        a.send_to(&[5], "10.0.0.1:5000".parse().unwrap()).unwrap();
+        assert_eq!(network.in_flight(), 0);
+    }
+}
@@ -1,11 +1,37 @@
 //! ACK frame encoding, decoding, and receive history tracking for MXP transport.
 
 use core::fmt;
+use core::ops::RangeInclusive;
 use std::cmp::{max, min};
 use std::time::{Duration, SystemTime};
 
+use super::ecn::{EcnCodepoint, EcnCounts};
+use crate::protocol::metrics::Metrics;
+
 /// Maximum number of ACK ranges tracked by default.
 pub const DEFAULT_MAX_ACK_RANGES: usize = 32;
+/// Ack-eliciting packets received before an ACK is sent without waiting for the delay timer.
+pub const DEFAULT_ACK_ELICITING_THRESHOLD: usize = 2;
+
+/// When [`ReceiveHistory`] asks for an ACK ahead of its delay timer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AckFrequencyConfig {
+    /// Acknowledge once this many ack-eliciting packets arrived since the last ACK; `0` or
+    /// `1` acknowledges every ack-eliciting packet.
+    pub ack_eliciting_threshold: usize,
+    /// Acknowledge immediately when an ack-eliciting packet arrives out of order, i.e. below
+    /// the largest packet number seen or past a gap, so the sender learns of loss quickly.
+    pub immediate_on_reorder: bool,
+}
+
+impl Default for AckFrequencyConfig {
+    fn default() -> Self {
+        Self {
+            ack_eliciting_threshold: DEFAULT_ACK_ELICITING_THRESHOLD,
+            immediate_on_reorder: true,
+        }
+    }
+}
 
 /// Error type for ACK frame processing.
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -79,6 +105,18 @@ impl AckRange {
         self.end
     }
 
+    /// Whether `packet_number` falls within the range.
+    #[must_use]
+    pub const fn contains(&self, packet_number: u64) -> bool {
+        self.start <= packet_number && packet_number <= self.end
+    }
+
+    /// Iterate the packet numbers in the range, lowest first.
+    #[must_use]
+    pub const fn iter(&self) -> RangeInclusive<u64> {
+        self.start..=self.end
+    }
+
     fn overlaps_or_adjacent(&self, other: &Self) -> bool {
         !(self.end.saturating_add(1) < other.start || other.end.saturating_add(1) < self.start)
     }
@@ -91,12 +129,28 @@ impl AckRange {
     }
 }
 
+impl IntoIterator for &AckRange {
+    type Item = u64;
+    type IntoIter = RangeInclusive<u64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Encoded length of the optional ECN counters (ECT(0), ECT(1), CE).
+const ECN_COUNTS_LEN: usize = 3 * 8;
+
 /// Encoded ACK frame containing the largest acknowledged packet and ranges.
+///
+/// When the receiver tracks ECN marks, the cumulative ECT(0)/ECT(1)/CE counts are
+/// appended after the ranges; decoders treat their absence as "no ECN feedback".
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AckFrame {
     largest: u64,
     ack_delay_micros: u64,
     ranges: Vec<AckRange>,
+    ecn: Option<EcnCounts>,
 }
 
 impl AckFrame {
@@ -122,9 +176,23 @@ impl AckFrame {
             largest,
             ack_delay_micros,
             ranges,
+            ecn: None,
         })
     }
 
+    /// Attach cumulative ECN counts observed by the receiver.
+    #[must_use]
+    pub const fn with_ecn_counts(mut self, counts: EcnCounts) -> Self {
+        self.ecn = Some(counts);
+        self
+    }
+
+    /// Cumulative ECN counts reported by the peer, if any.
+    #[must_use]
+    pub const fn ecn_counts(&self) -> Option<EcnCounts> {
+        self.ecn
+    }
+
     /// Largest acknowledged packet number.
     #[must_use]
     pub const fn largest(&self) -> u64 {
@@ -143,20 +211,61 @@ impl AckFrame {
         &self.ranges
     }
 
+    /// Whether `packet_number` is acknowledged by any range.
+    ///
+    /// Checks each range's bounds, so the cost does not depend on how many packets the
+    /// ranges cover.
+    #[must_use]
+    pub fn contains(&self, packet_number: u64) -> bool {
+        packet_number <= self.largest
+            && self
+                .ranges
+                .iter()
+                .any(|range| range.contains(packet_number))
+    }
+
+    /// Iterate every acknowledged packet number, largest first.
+    ///
+    /// Lazy, but yields one item per packet; prefer [`ranges`](Self::ranges) or
+    /// [`contains`](Self::contains) when ranges may be wide.
+    pub fn acked_numbers(&self) -> impl Iterator<Item = u64> + '_ {
+        self.ranges.iter().flat_map(|range| range.iter().rev())
+    }
+
     /// Encode into the provided buffer, appending bytes.
     pub fn encode(&self, out: &mut Vec<u8>) {
+        self.encode_with_exponent(out, 0);
+    }
+
+    /// Encode with the ACK delay scaled down by `2^ack_delay_exponent` (negotiated via
+    /// [`TransportParameters`](super::TransportParameters)).
+    pub fn encode_with_exponent(&self, out: &mut Vec<u8>, ack_delay_exponent: u8) {
+        let scaled_delay = self
+            .ack_delay_micros
+            .checked_shr(u32::from(ack_delay_exponent))
+            .unwrap_or(0);
         out.extend_from_slice(&self.largest.to_le_bytes());
-        out.extend_from_slice(&self.ack_delay_micros.to_le_bytes());
+        out.extend_from_slice(&scaled_delay.to_le_bytes());
         let range_count = u16::try_from(self.ranges.len()).unwrap_or(u16::MAX);
         out.extend_from_slice(&range_count.to_le_bytes());
         for range in &self.ranges {
             out.extend_from_slice(&range.start.to_le_bytes());
             out.extend_from_slice(&range.end.to_le_bytes());
         }
+        if let Some(ecn) = self.ecn {
+            out.extend_from_slice(&ecn.ect0.to_le_bytes());
+            out.extend_from_slice(&ecn.ect1.to_le_bytes());
+            out.extend_from_slice(&ecn.ce.to_le_bytes());
+        }
     }
 
     /// Decode an ACK frame from bytes.
     pub fn decode(bytes: &[u8]) -> Result<Self, AckError> {
+        Self::decode_with_exponent(bytes, 0)
+    }
+
+    /// Decode an ACK frame whose delay was scaled by the peer's `ack_delay_exponent`.
+    pub fn decode_with_exponent(bytes: &[u8], ack_delay_exponent: u8) -> Result<Self, AckError> {
         const HEADER_LEN: usize = 8 + 8 + 2;
         if bytes.len() < HEADER_LEN {
             return Err(AckError::BufferTooSmall {
@@ -165,7 +274,8 @@ impl AckFrame {
             });
         }
         let largest = u64::from_le_bytes(bytes[0..8].try_into().unwrap());
-        let ack_delay_micros = u64::from_le_bytes(bytes[8..16].try_into().unwrap());
+        let ack_delay_micros = u64::from_le_bytes(bytes[8..16].try_into().unwrap())
+            .saturating_mul(1u64 << ack_delay_exponent.min(63));
         let range_count = u16::from_le_bytes(bytes[16..18].try_into().unwrap()) as usize;
 
         let mut offset = HEADER_LEN;
@@ -181,6 +291,24 @@ impl AckFrame {
             ranges.push(AckRange::new(start, end)?);
         }
 
+        let ecn = match bytes.len() - offset {
+            0 => None,
+            remaining if remaining < ECN_COUNTS_LEN => {
+                return Err(AckError::BufferTooSmall {
+                    expected: offset + ECN_COUNTS_LEN,
+                    actual: bytes.len(),
+                });
+            }
+            _ => {
+                let counter = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+                Some(EcnCounts {
+                    ect0: counter(offset),
+                    ect1: counter(offset + 8),
+                    ce: counter(offset + 16),
+                })
+            }
+        };
+
         if ranges.is_empty() {
             return Err(AckError::EmptyHistory);
         }
@@ -196,35 +324,109 @@ impl AckFrame {
             largest,
             ack_delay_micros,
             ranges,
+            ecn,
         })
     }
 }
 
 /// Receive history used to build ACK frames for packets observed from the peer.
+///
+/// At most `max_ranges` disjoint ranges are kept. Adjacent packets are merged first; when
+/// a new gap would exceed the limit, the lowest range is evicted so the most recent packets
+/// are always acknowledged. Evicted packets were already covered by earlier ACK frames
+/// unless the history overflowed between frames, and packets at or below the eviction
+/// horizon are treated as duplicates rather than re-entering the history.
 #[derive(Debug)]
 pub struct ReceiveHistory {
     ranges: Vec<AckRange>,
     max_ranges: usize,
+    /// Largest packet number evicted from the history, if any.
+    evicted_through: Option<u64>,
     ack_delay: Duration,
-    last_ack_time: Option<SystemTime>,
+    /// When the largest packet number so far arrived; ACK delay is measured from here.
+    largest_received_time: Option<SystemTime>,
     ack_request_time: Option<SystemTime>,
+    ecn_counts: EcnCounts,
+    frequency: AckFrequencyConfig,
+    /// Ack-eliciting packets received since the last ACK was built.
+    unacked_eliciting: usize,
+    /// An ack-eliciting packet arrived out of order since the last ACK was built.
+    reordered: bool,
+    /// Packets received again after they were already recorded.
+    duplicates: u64,
 }
 
 impl ReceiveHistory {
-    /// Create a new history with configurable capacity and ACK delay target.
+    /// Create a new history with configurable capacity and ACK delay target, using the
+    /// default [`AckFrequencyConfig`].
     #[must_use]
     pub fn new(max_ranges: usize, ack_delay: Duration) -> Self {
         Self {
             ranges: Vec::with_capacity(max_ranges),
             max_ranges: max_ranges.max(1),
+            evicted_through: None,
             ack_delay,
-            last_ack_time: None,
+            largest_received_time: None,
             ack_request_time: None,
+            ecn_counts: EcnCounts::default(),
+            frequency: AckFrequencyConfig::default(),
+            unacked_eliciting: 0,
+            reordered: false,
+            duplicates: 0,
         }
     }
 
-    /// Observation of a packet number; returns true when an immediate ACK is suggested.
+    /// Replace the ACK frequency policy.
+    #[must_use]
+    pub const fn with_ack_frequency(mut self, frequency: AckFrequencyConfig) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    /// ACK frequency policy in use.
+    #[must_use]
+    pub const fn ack_frequency(&self) -> AckFrequencyConfig {
+        self.frequency
+    }
+
+    /// Observation of a packet number; returns true when an ACK should be sent now.
+    ///
+    /// That is the case once the ACK delay has elapsed for the oldest unacknowledged
+    /// ack-eliciting packet, once the [`AckFrequencyConfig`] threshold of ack-eliciting
+    /// packets is reached, or when an ack-eliciting packet arrived out of order.
     pub fn record(&mut self, packet_number: u64, ack_eliciting: bool, now: SystemTime) -> bool {
+        self.record_with_ecn(packet_number, ack_eliciting, EcnCodepoint::NotEct, now)
+    }
+
+    /// Observation of a packet number received with the given ECN codepoint.
+    ///
+    /// Duplicate packets are not counted again.
+    pub fn record_with_ecn(
+        &mut self,
+        packet_number: u64,
+        ack_eliciting: bool,
+        ecn: EcnCodepoint,
+        now: SystemTime,
+    ) -> bool {
+        let duplicate = self.contains(packet_number);
+        if duplicate {
+            self.duplicates += 1;
+            Metrics::record_duplicate_packet();
+        } else {
+            self.ecn_counts.record(ecn);
+        }
+        let largest = self.ranges.first().map(AckRange::end);
+        if largest.is_none_or(|largest| packet_number > largest) {
+            self.largest_received_time = Some(now);
+        }
+        if ack_eliciting && !duplicate {
+            self.unacked_eliciting += 1;
+            // Below the largest fills a gap; beyond largest + 1 opens one.
+            let out_of_order = largest.is_some_and(|largest| {
+                packet_number < largest || packet_number > largest.saturating_add(1)
+            });
+            self.reordered |= out_of_order && self.frequency.immediate_on_reorder;
+        }
         self.insert_packet(packet_number);
         if ack_eliciting && self.ack_request_time.is_none() {
             self.ack_request_time = Some(now);
@@ -241,35 +443,82 @@ impl ReceiveHistory {
 
         let largest = self.ranges[0].end();
         let ack_delay = self
-            .last_ack_time
-            .map(|sent| {
-                now.duration_since(sent)
-                    .unwrap_or_else(|_| Duration::default())
-            })
+            .largest_received_time
+            .and_then(|received| now.duration_since(received).ok())
             .unwrap_or_default();
         let ranges = self.ranges.clone();
-        let frame = AckFrame::new(largest, ack_delay, ranges)?;
-        self.last_ack_time = Some(now);
+        let mut frame = AckFrame::new(largest, ack_delay, ranges)?;
+        if self.ecn_counts != EcnCounts::default() {
+            frame = frame.with_ecn_counts(self.ecn_counts);
+        }
         self.ack_request_time = None;
+        self.unacked_eliciting = 0;
+        self.reordered = false;
         Ok(Some(frame))
     }
 
+    /// Time by which an ACK should be sent for the ack-eliciting packets received so far.
+    #[must_use]
+    pub fn ack_deadline(&self) -> Option<SystemTime> {
+        self.ack_request_time
+            .map(|requested| requested + self.ack_delay)
+    }
+
     /// Expose current ranges for inspection/testing.
     #[must_use]
     pub fn ranges(&self) -> &[AckRange] {
         &self.ranges
     }
 
+    /// Packets recorded more than once, a sign of spurious retransmission or replay.
+    ///
+    /// Packets at or below the [`eviction_horizon`](Self::eviction_horizon) count too.
+    #[must_use]
+    pub const fn duplicate_count(&self) -> u64 {
+        self.duplicates
+    }
+
+    /// ECN marks observed on received packets.
+    #[must_use]
+    pub const fn ecn_counts(&self) -> EcnCounts {
+        self.ecn_counts
+    }
+
+    /// Largest packet number dropped to respect the range limit, if any.
+    ///
+    /// Packets at or below it are no longer tracked and are not acknowledged again.
+    #[must_use]
+    pub const fn eviction_horizon(&self) -> Option<u64> {
+        self.evicted_through
+    }
+
+    fn contains(&self, packet_number: u64) -> bool {
+        self.evicted_through
+            .is_some_and(|horizon| packet_number <= horizon)
+            || self
+                .ranges
+                .iter()
+                .any(|range| range.contains(packet_number))
+    }
+
     fn should_ack_immediately(&self, now: SystemTime) -> bool {
-        if let Some(requested) = self.ack_request_time {
-            if let Ok(elapsed) = now.duration_since(requested) {
-                return elapsed >= self.ack_delay;
-            }
-        }
-        false
+        let Some(requested) = self.ack_request_time else {
+            return false;
+        };
+        self.reordered
+            || self.unacked_eliciting >= self.frequency.ack_eliciting_threshold
+            || now
+                .duration_since(requested)
+                .is_ok_and(|elapsed| elapsed >= self.ack_delay)
     }
 
     fn insert_packet(&mut self, packet_number: u64) {
+        if self
+            .evicted_through
+            .is_some_and(|horizon| packet_number <= horizon)
+        {
+            return;
+        }
         let mut inserted = false;
         for idx in 0..self.ranges.len() {
             let range = self.ranges[idx];
@@ -304,7 +553,7 @@ impl ReceiveHistory {
                 .push(AckRange::new(packet_number, packet_number).unwrap());
         }
 
-        self.truncate_to_capacity();
+        self.evict_oldest();
     }
 
     fn compress_around(&mut self, idx: usize) {
@@ -332,17 +581,24 @@ impl ReceiveHistory {
         }
     }
 
-    fn truncate_to_capacity(&mut self) {
-        if self.ranges.len() <= self.max_ranges {
-            return;
+    /// Drop the lowest ranges (stored last) until the limit is respected.
+    fn evict_oldest(&mut self) {
+        while self.ranges.len() > self.max_ranges {
+            let Some(oldest) = self.ranges.pop() else {
+                break;
+            };
+            self.evicted_through = Some(
+                self.evicted_through
+                    .map_or(oldest.end, |horizon| horizon.max(oldest.end)),
+            );
         }
-        self.ranges.truncate(self.max_ranges);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transport::time::{Clock, MockClock};
 
     #[test]
     fn ack_range_new_validates_ordering() {
@@ -353,6 +609,49 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn ack_range_iterates_each_packet_number() {
+        let range = AckRange::new(7, 10).unwrap();
+        assert_eq!(range.iter().collect::<Vec<_>>(), [7, 8, 9, 10]);
+        assert_eq!(AckRange::new(4, 4).unwrap().iter().count(), 1);
+        assert!(range.contains(7) && range.contains(10));
+        assert!(!range.contains(6) && !range.contains(11));
+    }
+
+    #[test]
+    fn ack_frame_contains_across_ranges() {
+        let ranges = vec![
+            AckRange::new(3, 5).unwrap(),
+            AckRange::new(20, 22).unwrap(),
+            AckRange::new(10, 12).unwrap(),
+        ];
+        let frame = AckFrame::new(22, Duration::ZERO, ranges).unwrap();
+        for acked in [3, 5, 10, 11, 12, 20, 22] {
+            assert!(frame.contains(acked), "{acked} should be acked");
+        }
+        for gap in [0, 2, 6, 9, 13, 19, 23, u64::MAX] {
+            assert!(!frame.contains(gap), "{gap} should not be acked");
+        }
+        assert_eq!(
+            frame.acked_numbers().collect::<Vec<_>>(),
+            [22, 21, 20, 12, 11, 10, 5, 4, 3]
+        );
+
+        // Membership stays cheap for ranges far too wide to enumerate.
+        let wide = AckFrame::new(
+            u64::MAX,
+            Duration::ZERO,
+            vec![AckRange::new(1, u64::MAX).unwrap()],
+        )
+        .unwrap();
+        assert!(wide.contains(u64::MAX / 2));
+        assert!(!wide.contains(0));
+        assert_eq!(
+            wide.acked_numbers().take(2).collect::<Vec<_>>(),
+            [u64::MAX, u64::MAX - 1]
+        );
+    }
+
     #[test]
     fn ack_frame_encode_decode_roundtrip() {
         let ranges = vec![AckRange::new(10, 15).unwrap(), AckRange::new(3, 5).unwrap()];
@@ -366,10 +665,25 @@ mod tests {
         assert_eq!(decoded.ranges()[0], AckRange::new(10, 15).unwrap());
     }
 
+    #[test]
+    fn ack_delay_scaled_by_exponent() {
+        let frame = AckFrame::new(
+            3,
+            Duration::from_millis(1),
+            vec![AckRange::new(1, 3).unwrap()],
+        )
+        .unwrap();
+        let mut buf = Vec::new();
+        frame.encode_with_exponent(&mut buf, 3);
+        assert_eq!(u64::from_le_bytes(buf[8..16].try_into().unwrap()), 125);
+        let decoded = AckFrame::decode_with_exponent(&buf, 3).unwrap();
+        assert_eq!(decoded.ack_delay_micros(), 1000);
+    }
+
     #[test]
     fn receive_history_merges_adjacent_packets() {
         let mut history = ReceiveHistory::new(8, Duration::from_millis(1));
-        let now = SystemTime::now();
+        let now = SystemTime::UNIX_EPOCH;
         history.record(5, true, now);
         history.record(4, true, now);
         history.record(7, true, now);
@@ -381,17 +695,142 @@ mod tests {
     #[test]
     fn receive_history_limits_range_count() {
         let mut history = ReceiveHistory::new(2, Duration::from_millis(1));
-        let now = SystemTime::now();
+        let now = SystemTime::UNIX_EPOCH;
         history.record(10, true, now);
         history.record(8, true, now);
         history.record(6, true, now);
         assert!(history.ranges().len() <= 2);
     }
 
+    #[test]
+    fn duplicate_packets_are_counted_once_each() {
+        let before = Metrics::totals().duplicate_packets;
+        let mut history = ReceiveHistory::new(8, Duration::from_millis(1));
+        let now = SystemTime::UNIX_EPOCH;
+        history.record(3, true, now);
+        history.record(4, false, now);
+        assert_eq!(history.duplicate_count(), 0);
+        history.record(3, true, now);
+        assert_eq!(history.duplicate_count(), 1);
+        assert_eq!(history.ranges(), &[AckRange::new(3, 4).unwrap()]);
+        assert!(Metrics::totals().duplicate_packets > before);
+    }
+
+    #[test]
+    fn eviction_keeps_the_most_recent_ranges() {
+        let mut history = ReceiveHistory::new(4, Duration::from_millis(1));
+        let now = SystemTime::UNIX_EPOCH;
+        // Every other packet: 0, 2, 4, ... 38 form twenty disjoint ranges.
+        for pn in (0..40).step_by(2) {
+            history.record(pn, true, now);
+            let frame = history.build_frame(now).unwrap().unwrap();
+            assert_eq!(frame.largest(), pn);
+            assert!(frame.contains(pn));
+            assert!(frame.ranges().len() <= 4);
+        }
+        let retained: Vec<u64> = history.ranges().iter().map(AckRange::end).collect();
+        assert_eq!(retained, vec![38, 36, 34, 32]);
+        assert_eq!(history.eviction_horizon(), Some(30));
+
+        // A late packet below the horizon neither re-enters nor displaces recent ranges.
+        assert!(!history.record(5, true, now));
+        assert_eq!(history.ranges().len(), 4);
+        assert_eq!(history.ranges()[3].end(), 32);
+
+        // Filling a gap merges first, freeing a slot instead of evicting.
+        history.record(37, true, now);
+        history.record(39, true, now);
+        let frame = history.build_frame(now).unwrap().unwrap();
+        assert_eq!(frame.largest(), 39);
+        assert_eq!(frame.ranges()[0], AckRange::new(36, 39).unwrap());
+        assert_eq!(history.eviction_horizon(), Some(30));
+    }
+
+    #[test]
+    fn ack_delay_measured_from_largest_packet_arrival() {
+        let mut history = ReceiveHistory::new(8, Duration::from_millis(25));
+        let start = SystemTime::UNIX_EPOCH;
+        history.record(1, true, start);
+        history.record(3, true, start + Duration::from_millis(2));
+        let frame = history
+            .build_frame(start + Duration::from_millis(5))
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.ack_delay_micros(), 3_000);
+
+        // A reordered packet below the largest does not restart the delay.
+        history.record(2, true, start + Duration::from_millis(6));
+        let frame = history
+            .build_frame(start + Duration::from_millis(7))
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.ack_delay_micros(), 5_000);
+    }
+
+    #[test]
+    fn receive_history_counts_ecn_marks_once() {
+        let mut history = ReceiveHistory::new(8, Duration::from_millis(1));
+        let now = SystemTime::UNIX_EPOCH;
+        history.record_with_ecn(1, true, EcnCodepoint::Ect0, now);
+        history.record_with_ecn(2, true, EcnCodepoint::Ce, now);
+        history.record_with_ecn(2, true, EcnCodepoint::Ce, now);
+        history.record(3, true, now);
+        assert_eq!(
+            history.ecn_counts(),
+            EcnCounts {
+                ect0: 1,
+                ect1: 0,
+                ce: 1
+            }
+        );
+    }
+
+    #[test]
+    fn ack_frame_ecn_counts_roundtrip() {
+        let counts = EcnCounts {
+            ect0: 40,
+            ect1: 2,
+            ce: 7,
+        };
+        let frame = AckFrame::new(9, Duration::ZERO, vec![AckRange::new(3, 9).unwrap()])
+            .unwrap()
+            .with_ecn_counts(counts);
+        let mut buf = Vec::new();
+        frame.encode(&mut buf);
+        assert_eq!(buf.len(), 8 + 8 + 2 + 16 + ECN_COUNTS_LEN);
+        let decoded = AckFrame::decode(&buf).unwrap();
+        assert_eq!(decoded.ecn_counts(), Some(counts));
+        assert_eq!(decoded, frame);
+
+        let plain = AckFrame::new(9, Duration::ZERO, vec![AckRange::new(3, 9).unwrap()]).unwrap();
+        buf.clear();
+        plain.encode(&mut buf);
+        assert_eq!(AckFrame::decode(&buf).unwrap().ecn_counts(), None);
+
+        buf.push(0);
+        assert!(matches!(
+            AckFrame::decode(&buf),
+            Err(AckError::BufferTooSmall { .. })
+        ));
+    }
+
+    #[test]
+    fn receive_history_attaches_ecn_counts_to_frame() {
+        let mut history = ReceiveHistory::new(8, Duration::from_millis(1));
+        let now = SystemTime::UNIX_EPOCH;
+        history.record(1, true, now);
+        let frame = history.build_frame(now).unwrap().unwrap();
+        assert_eq!(frame.ecn_counts(), None);
+
+        history.record_with_ecn(2, true, EcnCodepoint::Ce, now);
+        let frame = history.build_frame(now).unwrap().unwrap();
+        assert_eq!(frame.ecn_counts().map(|counts| counts.ce), Some(1));
+    }
+
     #[test]
     fn receive_history_builds_ack_frame() {
         let mut history = ReceiveHistory::new(8, Duration::from_millis(0));
-        let now = SystemTime::now();
+        let now = SystemTime::UNIX_EPOCH;
         history.record(10, true, now);
         history.record(9, true, now);
         history.record(7, false, now);
@@ -400,4 +839,93 @@ mod tests {
         assert_eq!(frame.ranges().len(), 2);
         assert_eq!(frame.ranges()[0], AckRange::new(9, 10).unwrap());
     }
+
+    #[test]
+    fn second_ack_eliciting_packet_triggers_ack() {
+        let now = SystemTime::UNIX_EPOCH;
+        let mut history = ReceiveHistory::new(8, Duration::from_millis(25));
+        assert_eq!(history.ack_frequency(), AckFrequencyConfig::default());
+        // Non-eliciting packets never count towards the threshold.
+        assert!(!history.record(0, false, now));
+        assert!(!history.record(1, true, now));
+        assert!(history.record(2, true, now));
+        history.build_frame(now).unwrap();
+
+        // The count restarts after each ACK; a repeated packet is not counted again.
+        assert!(!history.record(3, true, now));
+        assert!(!history.record(3, true, now));
+        assert!(history.record(4, true, now));
+        history.build_frame(now).unwrap();
+
+        let mut every_fourth = ReceiveHistory::new(8, Duration::from_millis(25))
+            .with_ack_frequency(AckFrequencyConfig {
+                ack_eliciting_threshold: 4,
+                immediate_on_reorder: true,
+            });
+        let decisions: Vec<bool> = (0..8)
+            .map(|pn| {
+                let ack = every_fourth.record(pn, true, now);
+                if ack {
+                    every_fourth.build_frame(now).unwrap();
+                }
+                ack
+            })
+            .collect();
+        assert_eq!(
+            decisions,
+            [false, false, false, true, false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn out_of_order_packet_triggers_immediate_ack() {
+        let now = SystemTime::UNIX_EPOCH;
+        let lenient = AckFrequencyConfig {
+            ack_eliciting_threshold: 10,
+            immediate_on_reorder: true,
+        };
+
+        // A gap opening (5 after 3) is reported right away...
+        let mut history =
+            ReceiveHistory::new(8, Duration::from_millis(25)).with_ack_frequency(lenient);
+        assert!(!history.record(3, true, now));
+        assert!(history.record(5, true, now));
+        history.build_frame(now).unwrap();
+        // ...and so is the late packet filling it.
+        assert!(history.record(4, true, now));
+        history.build_frame(now).unwrap();
+        assert!(!history.record(6, true, now));
+
+        // Non-eliciting packets out of order do not force an ACK.
+        assert!(!history.record(9, false, now));
+
+        // With reordering tolerated, the same gap waits for the threshold or timer.
+        let mut tolerant = ReceiveHistory::new(8, Duration::from_millis(25)).with_ack_frequency(
+            AckFrequencyConfig {
+                immediate_on_reorder: false,
+                ..lenient
+            },
+        );
+        assert!(!tolerant.record(3, true, now));
+        assert!(!tolerant.record(5, true, now));
+        assert!(tolerant.record(4, true, now + Duration::from_millis(25)));
+    }
+
+    #[test]
+    fn receive_history_ack_deadline_follows_delay() {
+        let clock = MockClock::default();
+        let mut history = ReceiveHistory::new(8, Duration::from_millis(25));
+        assert!(!history.record(1, false, clock.now()));
+        assert_eq!(history.ack_deadline(), None);
+
+        clock.advance(Duration::from_millis(5));
+        assert!(!history.record(2, true, clock.now()));
+        let deadline = history.ack_deadline().expect("ack requested");
+        assert_eq!(deadline, clock.now() + Duration::from_millis(25));
+
+        clock.set(deadline);
+        assert!(history.record(3, true, clock.now()));
+        history.build_frame(clock.now()).unwrap();
+        assert_eq!(history.ack_deadline(), None);
+    }
 }
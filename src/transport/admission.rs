@@ -0,0 +1,462 @@
+//! Per-peer rate limiting and connection admission for servers.
+//!
+//! Anti-amplification bounds what a server sends to an unvalidated address; admission
+//! control bounds what a peer may make it do. A [`RateLimiter`] keeps a token bucket per peer
+//! IP, and [`AdmissionControl`] combines a handshake-rate and a byte-rate limiter with caps
+//! on open connections, overall and per IP. Refused handshakes are answered statelessly (see
+//! [`HandshakeServer::refuse`](super::HandshakeServer::refuse)) and counted in the metrics
+//! snapshot.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime};
+
+use tracing::{debug, trace};
+
+use crate::protocol::metrics::Metrics;
+use crate::transport::congestion::duration_to_secs;
+
+/// Default cap on connections (and handshakes in progress) across all peers.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 10_000;
+/// Default cap on connections (and handshakes in progress) from one IP address.
+pub const DEFAULT_MAX_CONNECTIONS_PER_IP: usize = 64;
+/// Default sustained handshake rate allowed per IP address, in handshakes per second.
+pub const DEFAULT_HANDSHAKE_RATE: f64 = 10.0;
+/// Default number of handshakes an idle IP address may start back to back.
+pub const DEFAULT_HANDSHAKE_BURST: f64 = 20.0;
+/// Default sustained receive rate allowed per IP address, in bytes per second.
+pub const DEFAULT_BYTE_RATE: f64 = 8.0 * 1024.0 * 1024.0;
+/// Default number of bytes an idle IP address may send back to back.
+pub const DEFAULT_BYTE_BURST: f64 = 1024.0 * 1024.0;
+/// Default number of IP addresses a [`RateLimiter`] tracks before evicting idle ones.
+pub const DEFAULT_MAX_TRACKED_PEERS: usize = 4096;
+
+/// Sustained rate and burst allowance of a token bucket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Tokens credited per second.
+    pub per_second: f64,
+    /// Bucket size: tokens an idle peer may spend back to back.
+    pub burst: f64,
+}
+
+impl RateLimit {
+    /// Create a limit of `per_second` tokens with room for `burst` at once.
+    #[must_use]
+    pub const fn new(per_second: f64, burst: f64) -> Self {
+        Self { per_second, burst }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    tokens: f64,
+    updated: SystemTime,
+    /// Position in [`RateLimiter::eviction_order`].
+    key: EvictionKey,
+}
+
+/// Orders buckets for eviction: those that refill at all first, then by the time they are
+/// full again, then least recently used first.
+type EvictionKey = (bool, SystemTime, u64);
+
+/// Where a bucket holding `tokens` at `updated` sorts for eviction after use number `seq`.
+fn eviction_key(limit: RateLimit, tokens: f64, updated: SystemTime, seq: u64) -> EvictionKey {
+    let deficit = (limit.burst - tokens).max(0.0);
+    let full_at = if deficit == 0.0 {
+        Some(updated)
+    } else {
+        Duration::try_from_secs_f64(deficit / limit.per_second)
+            .ok()
+            .and_then(|wait| updated.checked_add(wait))
+    };
+    match full_at {
+        Some(at) => (false, at, seq),
+        None => (true, updated, seq),
+    }
+}
+
+/// Token buckets keyed by peer IP address.
+///
+/// Each address starts with a full bucket of `burst` tokens that refills at `per_second`.
+/// Once `max_peers` addresses are tracked, the bucket that is (or soonest will be) full again
+/// is evicted, the least recently used first among equals, and starts over with a full
+/// bucket if it returns. A throttled peer thus keeps its debt while any peer closer to a
+/// full bucket can make room.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    buckets: HashMap<IpAddr, Bucket>,
+    eviction_order: BTreeMap<EvictionKey, IpAddr>,
+    max_peers: usize,
+    clock: u64,
+}
+
+impl RateLimiter {
+    /// Create a limiter applying `limit` to each of at most `max_peers` addresses.
+    #[must_use]
+    pub fn new(limit: RateLimit, max_peers: usize) -> Self {
+        Self {
+            limit,
+            buckets: HashMap::new(),
+            eviction_order: BTreeMap::new(),
+            max_peers: max_peers.max(1),
+            clock: 0,
+        }
+    }
+
+    /// Limit applied to each address.
+    #[must_use]
+    pub const fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    /// Spend `cost` tokens from the bucket of `ip` at `now`. Returns `true` if permitted.
+    ///
+    /// A refused attempt spends nothing, so a peer that backs off recovers at the full rate.
+    pub fn try_acquire(&mut self, ip: IpAddr, cost: f64, now: SystemTime) -> bool {
+        let limit = self.limit;
+        self.clock += 1;
+        if !self.buckets.contains_key(&ip) && self.buckets.len() >= self.max_peers {
+            self.evict_fullest();
+        }
+        let bucket = self.buckets.entry(ip).or_insert_with(|| Bucket {
+            tokens: limit.burst,
+            updated: now,
+            key: eviction_key(limit, limit.burst, now, 0),
+        });
+        self.eviction_order.remove(&bucket.key);
+        let elapsed = now.duration_since(bucket.updated).unwrap_or(Duration::ZERO);
+        bucket.tokens =
+            (bucket.tokens + duration_to_secs(elapsed) * limit.per_second).min(limit.burst);
+        if now > bucket.updated {
+            bucket.updated = now;
+        }
+        let permitted = bucket.tokens >= cost;
+        if permitted {
+            bucket.tokens -= cost;
+        }
+        bucket.key = eviction_key(limit, bucket.tokens, bucket.updated, self.clock);
+        self.eviction_order.insert(bucket.key, ip);
+        permitted
+    }
+
+    /// Number of tracked addresses.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Determine whether no addresses are tracked.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    fn evict_fullest(&mut self) {
+        if let Some((_, ip)) = self.eviction_order.pop_first() {
+            trace!(%ip, "evicting rate limit bucket for idle peer");
+            self.buckets.remove(&ip);
+        }
+    }
+}
+
+/// Limits a server enforces before committing state to a peer.
+#[derive(Debug, Clone)]
+pub struct ConnectionLimits {
+    /// Connections, including handshakes in progress, allowed across all peers.
+    pub max_connections: usize,
+    /// Connections, including handshakes in progress, allowed from one IP address.
+    pub max_per_ip: usize,
+    /// Handshakes each IP address may start.
+    pub handshake_rate: RateLimit,
+    /// Bytes each IP address may send, checked with [`AdmissionControl::admit_bytes`].
+    pub byte_rate: RateLimit,
+    /// Addresses each rate limiter tracks before evicting idle ones.
+    pub max_tracked_peers: usize,
+}
+
+impl Default for ConnectionLimits {
+    fn default() -> Self {
+        Self {
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            max_per_ip: DEFAULT_MAX_CONNECTIONS_PER_IP,
+            handshake_rate: RateLimit::new(DEFAULT_HANDSHAKE_RATE, DEFAULT_HANDSHAKE_BURST),
+            byte_rate: RateLimit::new(DEFAULT_BYTE_RATE, DEFAULT_BYTE_BURST),
+            max_tracked_peers: DEFAULT_MAX_TRACKED_PEERS,
+        }
+    }
+}
+
+/// Why a handshake was refused; carried as one byte in the refusal message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Refusal {
+    /// The server is at [`ConnectionLimits::max_connections`].
+    ServerFull = 0x01,
+    /// The peer's address is at [`ConnectionLimits::max_per_ip`].
+    TooManyFromPeer = 0x02,
+    /// The peer's address exceeded [`ConnectionLimits::handshake_rate`].
+    RateLimited = 0x03,
+}
+
+impl Refusal {
+    /// Parse a wire value; unknown reasons are reported as `None`.
+    #[must_use]
+    pub const fn from_byte(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(Self::ServerFull),
+            0x02 => Some(Self::TooManyFromPeer),
+            0x03 => Some(Self::RateLimited),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Refusal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ServerFull => write!(f, "server at connection limit"),
+            Self::TooManyFromPeer => write!(f, "too many connections from this address"),
+            Self::RateLimited => write!(f, "handshake rate exceeded"),
+        }
+    }
+}
+
+/// Connection admission for a server: handshake and byte rates per IP plus connection caps.
+///
+/// Every admitted handshake occupies a connection slot for its address until
+/// [`connection_closed`](Self::connection_closed) releases it, whether the handshake fails
+/// or the connection later ends.
+#[derive(Debug, Clone)]
+pub struct AdmissionControl {
+    limits: ConnectionLimits,
+    handshakes: RateLimiter,
+    bytes: RateLimiter,
+    per_ip: HashMap<IpAddr, usize>,
+    connections: usize,
+}
+
+impl AdmissionControl {
+    /// Create admission control enforcing `limits`.
+    #[must_use]
+    pub fn new(limits: ConnectionLimits) -> Self {
+        Self {
+            handshakes: RateLimiter::new(limits.handshake_rate, limits.max_tracked_peers),
+            bytes: RateLimiter::new(limits.byte_rate, limits.max_tracked_peers),
+            per_ip: HashMap::new(),
+            connections: 0,
+            limits,
+        }
+    }
+
+    /// Limits being enforced.
+    #[must_use]
+    pub const fn limits(&self) -> &ConnectionLimits {
+        &self.limits
+    }
+
+    /// Decide whether `ip` may start a handshake at `now`, reserving a connection slot if so.
+    ///
+    /// Connection caps are checked before the rate, so a peer turned away for a full server
+    /// keeps its handshake tokens.
+    ///
+    /// # Errors
+    ///
+    /// Returns the [`Refusal`] reason; the refusal is counted in the metrics snapshot.
+    pub fn admit_handshake(&mut self, ip: IpAddr, now: SystemTime) -> Result<(), Refusal> {
+        let from_peer = self.per_ip.get(&ip).copied().unwrap_or(0);
+        let refusal = if self.connections >= self.limits.max_connections {
+            Some(Refusal::ServerFull)
+        } else if from_peer >= self.limits.max_per_ip {
+            Some(Refusal::TooManyFromPeer)
+        } else if !self.handshakes.try_acquire(ip, 1.0, now) {
+            Some(Refusal::RateLimited)
+        } else {
+            None
+        };
+        if let Some(refusal) = refusal {
+            debug!(%ip, %refusal, "refusing handshake");
+            Metrics::record_handshake_rejected();
+            return Err(refusal);
+        }
+        *self.per_ip.entry(ip).or_insert(0) += 1;
+        self.connections += 1;
+        Ok(())
+    }
+
+    /// Charge `bytes` received from `ip` at `now` against its byte rate. Returns `true` if
+    /// the datagram should be processed.
+    pub fn admit_bytes(&mut self, ip: IpAddr, bytes: usize, now: SystemTime) -> bool {
+        #[allow(clippy::cast_precision_loss)] // datagram sizes are far below 2^52
+        let cost = bytes as f64;
+        self.bytes.try_acquire(ip, cost, now)
+    }
+
+    /// Release the connection slot held by a handshake or connection from `ip`.
+    pub fn connection_closed(&mut self, ip: IpAddr) {
+        if let Some(count) = self.per_ip.get_mut(&ip) {
+            *count -= 1;
+            if *count == 0 {
+                self.per_ip.remove(&ip);
+            }
+            self.connections -= 1;
+        }
+    }
+
+    /// Connections and in-progress handshakes currently holding a slot.
+    #[must_use]
+    pub const fn connections(&self) -> usize {
+        self.connections
+    }
+
+    /// Slots held by `ip`.
+    #[must_use]
+    pub fn connections_from(&self, ip: IpAddr) -> usize {
+        self.per_ip.get(&ip).copied().unwrap_or(0)
+    }
+}
+
+impl Default for AdmissionControl {
+    fn default() -> Self {
+        Self::new(ConnectionLimits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::time::UNIX_EPOCH;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(198, 51, 100, last))
+    }
+
+    fn start() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_000)
+    }
+
+    #[test]
+    fn hammered_address_tapers_to_the_configured_rate() {
+        let mut limiter = RateLimiter::new(RateLimit::new(10.0, 5.0), 16);
+        let attacker = ip(1);
+        let bystander = ip(2);
+
+        // One attempt per millisecond for two seconds.
+        let mut accepted = 0;
+        for ms in 0..2_000 {
+            let now = start() + Duration::from_millis(ms);
+            if limiter.try_acquire(attacker, 1.0, now) {
+                accepted += 1;
+            }
+        }
+        // The burst, then ten per second.
+        assert!((24..=26).contains(&accepted), "accepted {accepted}");
+
+        // In the last second acceptance is spread out at the rate, not bunched.
+        let mut last_second = Vec::new();
+        for ms in 2_000..3_000 {
+            let now = start() + Duration::from_millis(ms);
+            if limiter.try_acquire(attacker, 1.0, now) {
+                last_second.push(ms);
+            }
+        }
+        assert_eq!(last_second.len(), 10);
+        assert!(last_second.windows(2).all(|pair| pair[1] - pair[0] >= 99));
+
+        // Another address still gets its full burst.
+        let now = start() + Duration::from_secs(3);
+        for _ in 0..5 {
+            assert!(limiter.try_acquire(bystander, 1.0, now));
+        }
+        assert!(!limiter.try_acquire(bystander, 1.0, now));
+    }
+
+    #[test]
+    fn limiter_evicts_idle_peers() {
+        let mut limiter = RateLimiter::new(RateLimit::new(1.0, 1.0), 2);
+        assert!(limiter.try_acquire(ip(1), 1.0, start()));
+        assert!(limiter.try_acquire(ip(2), 1.0, start()));
+        assert!(limiter.try_acquire(ip(3), 1.0, start()));
+        assert_eq!(limiter.len(), 2);
+        // ip(1) was evicted and starts over with a full bucket.
+        assert!(limiter.try_acquire(ip(1), 1.0, start()));
+        assert!(!limiter.try_acquire(ip(3), 1.0, start()));
+    }
+
+    #[test]
+    fn limiter_keeps_throttled_peers_over_recently_used_ones() {
+        let mut limiter = RateLimiter::new(RateLimit::new(1.0, 5.0), 2);
+        let attacker = ip(1);
+        for _ in 0..5 {
+            assert!(limiter.try_acquire(attacker, 1.0, start()));
+        }
+        assert!(!limiter.try_acquire(attacker, 1.0, start()));
+
+        // The bystander is used more recently but is closer to a full bucket.
+        let later = start() + Duration::from_millis(10);
+        assert!(limiter.try_acquire(ip(2), 1.0, later));
+        assert!(limiter.try_acquire(ip(3), 1.0, later));
+        assert_eq!(limiter.len(), 2);
+
+        // The attacker's debt survived the eviction.
+        assert!(!limiter.try_acquire(attacker, 1.0, later));
+        assert_eq!(limiter.len(), 2);
+    }
+
+    #[test]
+    fn admission_caps_connections_per_ip_and_overall() {
+        let before = Metrics::totals().handshakes_rejected;
+        let mut admission = AdmissionControl::new(ConnectionLimits {
+            max_connections: 3,
+            max_per_ip: 2,
+            ..ConnectionLimits::default()
+        });
+        assert_eq!(admission.admit_handshake(ip(1), start()), Ok(()));
+        assert_eq!(admission.admit_handshake(ip(1), start()), Ok(()));
+        assert_eq!(
+            admission.admit_handshake(ip(1), start()),
+            Err(Refusal::TooManyFromPeer)
+        );
+        assert_eq!(admission.admit_handshake(ip(2), start()), Ok(()));
+        assert_eq!(
+            admission.admit_handshake(ip(3), start()),
+            Err(Refusal::ServerFull)
+        );
+        assert_eq!(admission.connections(), 3);
+
+        admission.connection_closed(ip(1));
+        assert_eq!(admission.connections_from(ip(1)), 1);
+        assert_eq!(admission.admit_handshake(ip(3), start()), Ok(()));
+        // Closing an address that holds no slot changes nothing.
+        admission.connection_closed(ip(9));
+        assert_eq!(admission.connections(), 3);
+        assert!(Metrics::totals().handshakes_rejected >= before + 2);
+    }
+
+    #[test]
+    fn admission_limits_handshake_and_byte_rates() {
+        let mut admission = AdmissionControl::new(ConnectionLimits {
+            handshake_rate: RateLimit::new(1.0, 2.0),
+            byte_rate: RateLimit::new(1_000.0, 3_000.0),
+            ..ConnectionLimits::default()
+        });
+        assert!(admission.admit_handshake(ip(1), start()).is_ok());
+        assert!(admission.admit_handshake(ip(1), start()).is_ok());
+        assert_eq!(
+            admission.admit_handshake(ip(1), start()),
+            Err(Refusal::RateLimited)
+        );
+        assert!(admission.admit_handshake(ip(2), start()).is_ok());
+        let later = start() + Duration::from_secs(1);
+        assert!(admission.admit_handshake(ip(1), later).is_ok());
+
+        assert!(admission.admit_bytes(ip(1), 1_200, start()));
+        assert!(admission.admit_bytes(ip(1), 1_200, start()));
+        assert!(!admission.admit_bytes(ip(1), 1_200, start()));
+        assert!(admission.admit_bytes(ip(2), 1_200, start()));
+        assert!(admission.admit_bytes(ip(1), 1_200, later));
+    }
+}
@@ -1,7 +1,14 @@
 //! Anti-amplification budget tracking for MXP transport handshakes.
 
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+use tracing::trace;
+
 /// Default amplification limit multiplier (3x per QUIC guidance).
 pub const DEFAULT_AMPLIFICATION_FACTOR: usize = 3;
+/// Default number of remote paths tracked by a [`PerPathAmplificationTracker`].
+pub const DEFAULT_MAX_TRACKED_PATHS: usize = 4096;
 
 /// Configuration for the amplification guard.
 #[derive(Debug, Clone)]
@@ -101,10 +108,129 @@ impl AntiAmplificationGuard {
     }
 }
 
+#[derive(Debug, Clone)]
+struct TrackedPath {
+    guard: AntiAmplificationGuard,
+    last_used: u64,
+}
+
+/// Independent amplification budgets for each remote address.
+///
+/// A server talking to many unvalidated peers keeps one [`AntiAmplificationGuard`] per
+/// address so traffic from one peer never raises the allowance of another. Paths are
+/// created on first use; once `max_paths` are tracked, the least recently used path is
+/// evicted and starts over with a fresh budget if it returns.
+#[derive(Debug, Clone)]
+pub struct PerPathAmplificationTracker {
+    config: AmplificationConfig,
+    paths: HashMap<SocketAddr, TrackedPath>,
+    max_paths: usize,
+    clock: u64,
+}
+
+impl PerPathAmplificationTracker {
+    /// Construct a tracker creating guards with `config` for at most `max_paths` addresses.
+    #[must_use]
+    pub fn new(config: AmplificationConfig, max_paths: usize) -> Self {
+        Self {
+            config,
+            paths: HashMap::new(),
+            max_paths: max_paths.max(1),
+            clock: 0,
+        }
+    }
+
+    /// Record bytes received from `addr`.
+    pub fn on_receive(&mut self, addr: SocketAddr, bytes: usize) {
+        self.path(addr).on_receive(bytes);
+    }
+
+    /// Attempt to reserve capacity for sending `bytes` to `addr`. Returns `true` if permitted.
+    pub fn try_consume(&mut self, addr: SocketAddr, bytes: usize) -> bool {
+        self.path(addr).try_consume(bytes)
+    }
+
+    /// Mark `addr` as validated, lifting its restriction and clearing its byte counts.
+    pub fn mark_validated(&mut self, addr: SocketAddr) {
+        let guard = self.path(addr);
+        *guard = AntiAmplificationGuard::new(guard.config.clone());
+        guard.mark_verified();
+    }
+
+    /// Additional bytes that may be sent to `addr` under its current budget.
+    #[must_use]
+    pub fn available_budget(&self, addr: &SocketAddr) -> usize {
+        self.paths
+            .get(addr)
+            .map_or(self.config.initial_allowance, |path| {
+                path.guard.available_budget()
+            })
+    }
+
+    /// Guard tracking `addr`, if the path is known.
+    #[must_use]
+    pub fn guard(&self, addr: &SocketAddr) -> Option<&AntiAmplificationGuard> {
+        self.paths.get(addr).map(|path| &path.guard)
+    }
+
+    /// Stop tracking `addr`; returns `true` if it was tracked.
+    pub fn remove(&mut self, addr: &SocketAddr) -> bool {
+        self.paths.remove(addr).is_some()
+    }
+
+    /// Number of tracked paths.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+
+    /// Determine whether no paths are tracked.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    fn path(&mut self, addr: SocketAddr) -> &mut AntiAmplificationGuard {
+        self.clock += 1;
+        if !self.paths.contains_key(&addr) && self.paths.len() >= self.max_paths {
+            self.evict_least_recent();
+        }
+        let config = &self.config;
+        let path = self.paths.entry(addr).or_insert_with(|| TrackedPath {
+            guard: AntiAmplificationGuard::new(config.clone()),
+            last_used: 0,
+        });
+        path.last_used = self.clock;
+        &mut path.guard
+    }
+
+    fn evict_least_recent(&mut self) {
+        let oldest = self
+            .paths
+            .iter()
+            .min_by_key(|(_, path)| path.last_used)
+            .map(|(addr, _)| *addr);
+        if let Some(addr) = oldest {
+            trace!(%addr, "evicting amplification budget for idle path");
+            self.paths.remove(&addr);
+        }
+    }
+}
+
+impl Default for PerPathAmplificationTracker {
+    fn default() -> Self {
+        Self::new(AmplificationConfig::default(), DEFAULT_MAX_TRACKED_PATHS)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], port))
+    }
+
     #[test]
     fn guard_blocks_over_budget_sends() {
         let mut guard = AntiAmplificationGuard::new(AmplificationConfig::default());
@@ -136,4 +262,56 @@ mod tests {
         assert!(guard.try_consume(2999));
         assert!(!guard.try_consume(2));
     }
+
+    #[test]
+    fn paths_have_independent_budgets() {
+        let config = AmplificationConfig {
+            initial_allowance: 0,
+            ..Default::default()
+        };
+        let mut tracker = PerPathAmplificationTracker::new(config, 8);
+        tracker.on_receive(addr(1), 1000);
+        assert!(tracker.try_consume(addr(1), 3000));
+        assert!(!tracker.try_consume(addr(1), 1));
+        assert!(!tracker.try_consume(addr(2), 1));
+
+        tracker.on_receive(addr(2), 10);
+        assert!(tracker.try_consume(addr(2), 30));
+        assert_eq!(tracker.available_budget(&addr(1)), 0);
+        assert_eq!(tracker.len(), 2);
+    }
+
+    #[test]
+    fn validation_resets_path_budget() {
+        let mut tracker = PerPathAmplificationTracker::default();
+        assert!(tracker.try_consume(addr(1), 3600));
+        assert!(!tracker.try_consume(addr(1), 1));
+
+        tracker.mark_validated(addr(1));
+        let guard = tracker.guard(&addr(1)).expect("tracked");
+        assert!(!guard.is_restricted());
+        assert_eq!(guard.sent(), 0);
+        assert!(tracker.try_consume(addr(1), 1_000_000));
+        assert!(!tracker.try_consume(addr(2), 3601));
+    }
+
+    #[test]
+    fn least_recently_used_path_is_evicted() {
+        let mut tracker = PerPathAmplificationTracker::new(AmplificationConfig::default(), 2);
+        tracker.on_receive(addr(1), 100);
+        tracker.on_receive(addr(2), 100);
+        tracker.on_receive(addr(1), 100);
+        tracker.on_receive(addr(3), 100);
+
+        assert_eq!(tracker.len(), 2);
+        assert!(tracker.guard(&addr(2)).is_none());
+        assert_eq!(
+            tracker
+                .guard(&addr(1))
+                .map(AntiAmplificationGuard::received),
+            Some(200)
+        );
+        assert!(tracker.remove(&addr(3)));
+        assert!(!tracker.remove(&addr(3)));
+    }
 }
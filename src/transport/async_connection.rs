@@ -0,0 +1,234 @@
+//! Tokio driver for a [`Connection`].
+//!
+//! [`AsyncConnection`] pairs a sans-IO [`Connection`] with an [`AsyncTransportHandle`]: it
+//! sends what the connection has queued, feeds it datagrams from the peer, and runs its
+//! timers against the wall clock while waiting for messages.
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use tracing::trace;
+
+use crate::protocol::{HandlerRegistry, Message};
+
+use super::buffer::Buffer;
+use super::connection::{
+    Connection, ConnectionClose, ConnectionError, ConnectionState, IDLE_TIMEOUT_REASON,
+};
+use super::socket::SocketError;
+use super::transport::AsyncTransportHandle;
+
+/// A [`Connection`] driven over a tokio socket.
+pub struct AsyncConnection {
+    connection: Connection,
+    handle: AsyncTransportHandle,
+    peer: SocketAddr,
+    buffer: Buffer,
+}
+
+impl fmt::Debug for AsyncConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncConnection")
+            .field("connection", &self.connection)
+            .field("peer", &self.peer)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncConnection {
+    /// Drive `connection` over `handle`, exchanging datagrams with `peer`.
+    ///
+    /// Datagrams from other addresses are discarded.
+    #[must_use]
+    pub fn new(connection: Connection, handle: AsyncTransportHandle, peer: SocketAddr) -> Self {
+        let buffer = handle.acquire_buffer();
+        Self {
+            connection,
+            handle,
+            peer,
+            buffer,
+        }
+    }
+
+    /// The underlying connection, for sending messages, opening streams, or closing.
+    #[must_use]
+    pub const fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    /// Mutable access to the underlying connection.
+    ///
+    /// Anything queued goes out on the next [`flush`](Self::flush), or while waiting in
+    /// [`recv`](Self::recv) or [`closed`](Self::closed).
+    pub const fn connection_mut(&mut self) -> &mut Connection {
+        &mut self.connection
+    }
+
+    /// Address of the peer.
+    #[must_use]
+    pub const fn peer_addr(&self) -> SocketAddr {
+        self.peer
+    }
+
+    /// Send every datagram the connection has ready, returning how many went out.
+    pub async fn flush(&mut self) -> Result<usize, SocketError> {
+        let now = SystemTime::now();
+        let mut sent = 0;
+        while let Some(datagram) = self.connection.poll_transmit(now) {
+            self.handle.send(&datagram, self.peer).await?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+
+    /// Wait for the next message from the peer.
+    ///
+    /// Messages already received are returned even after the connection started closing.
+    /// Once none are left, a graceful close (error code zero, from either side) yields
+    /// `Ok(None)`, so a receive loop can simply end; a close with an error code or an idle
+    /// timeout yields [`ConnectionError::Closed`].
+    pub async fn recv(&mut self) -> Result<Option<Message>, ConnectionError> {
+        loop {
+            if let Some(message) = self.connection.recv_message() {
+                return Ok(Some(message));
+            }
+            if let Some(close) = self.connection.close_reason() {
+                let outcome = close_outcome(close);
+                // Our own CONNECTION_CLOSE may still be queued.
+                self.flush().await.map_err(ConnectionError::Socket)?;
+                return outcome;
+            }
+            self.drive().await?;
+        }
+    }
+
+    /// [`recv`](Self::recv), giving up with [`ConnectionError::Timeout`] after `timeout`.
+    ///
+    /// The connection is unaffected by the timeout and can be received from again.
+    pub async fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<Message>, ConnectionError> {
+        tokio::time::timeout(timeout, self.recv())
+            .await
+            .unwrap_or(Err(ConnectionError::Timeout))
+    }
+
+    /// Answer messages from the peer with `handlers` until the connection closes.
+    ///
+    /// Each message goes through [`HandlerRegistry::dispatch`], and the reply it returns,
+    /// if any, is sent back. Messages without a handler are dropped. Ends as
+    /// [`recv`](Self::recv) does: `Ok(())` after a graceful close, an error otherwise.
+    pub async fn serve(&mut self, handlers: &HandlerRegistry) -> Result<(), ConnectionError> {
+        while let Some(message) = self.recv().await? {
+            if let Some(reply) = handlers.dispatch(&message) {
+                self.connection.send_message(&reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Keep the connection running until it is closed, returning why it closed.
+    ///
+    /// Messages arriving meanwhile stay queued for [`recv`](Self::recv). Errors are those
+    /// that end the connection without a close, such as a failed handshake.
+    pub async fn closed(&mut self) -> Result<ConnectionClose, ConnectionError> {
+        while self.connection.state() != ConnectionState::Closed {
+            self.drive().await?;
+        }
+        Ok(self
+            .connection
+            .close_reason()
+            .cloned()
+            .expect("closed connections record a reason"))
+    }
+
+    /// Run due timers and flush, then wait for one datagram or the next timer, whichever
+    /// comes first. Closed connections do not wait.
+    async fn drive(&mut self) -> Result<(), ConnectionError> {
+        if self
+            .connection
+            .poll_timeout()
+            .is_some_and(|deadline| deadline <= SystemTime::now())
+        {
+            self.connection.handle_timeout(SystemTime::now())?;
+        }
+        self.flush().await.map_err(ConnectionError::Socket)?;
+        if self.connection.state() == ConnectionState::Closed {
+            return Ok(());
+        }
+
+        let receive = self.handle.receive(&mut self.buffer);
+        let received = match self.connection.poll_timeout() {
+            Some(deadline) => {
+                let wait = deadline
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default();
+                tokio::time::timeout(wait, receive).await.ok()
+            }
+            None => Some(receive.await),
+        };
+        let Some(received) = received else {
+            return Ok(());
+        };
+        let (_, from) = received.map_err(ConnectionError::Socket)?;
+        if from != self.peer {
+            trace!(%from, "dropping datagram from another address");
+            return Ok(());
+        }
+        match self
+            .connection
+            .handle_datagram(self.buffer.as_slice(), SystemTime::now())
+        {
+            Err(ConnectionError::Packet(err)) => {
+                trace!(%err, "dropping packet");
+                Ok(())
+            }
+            result => result,
+        }
+    }
+}
+
+/// What [`AsyncConnection::recv`] reports once a drained connection is closing.
+fn close_outcome(close: &ConnectionClose) -> Result<Option<Message>, ConnectionError> {
+    if close.error_code == 0 && close.reason != IDLE_TIMEOUT_REASON {
+        Ok(None)
+    } else {
+        Err(ConnectionError::Closed(close.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(error_code: u64, reason: &str, remote: bool) -> ConnectionClose {
+        ConnectionClose {
+            error_code,
+            reason: reason.to_owned(),
+            remote,
+        }
+    }
+
+    #[test]
+    fn graceful_closes_end_the_receive_loop() {
+        for remote in [false, true] {
+            assert!(matches!(close_outcome(&close(0, "done", remote)), Ok(None)));
+        }
+    }
+
+    #[test]
+    fn error_closes_and_idle_timeouts_are_errors() {
+        let failed = close(7, "bad request", true);
+        assert!(matches!(
+            close_outcome(&failed),
+            Err(ConnectionError::Closed(info)) if info == failed
+        ));
+        let idle = close(0, IDLE_TIMEOUT_REASON, false);
+        assert!(matches!(
+            close_outcome(&idle),
+            Err(ConnectionError::Closed(info)) if info == idle
+        ));
+    }
+}
@@ -0,0 +1,90 @@
+//! Batched MXP message framing over a single long-lived stream.
+//!
+//! Opening a stream per message makes stream setup and teardown dominate when sending many
+//! small events. A [`MessageSink`] instead writes encoded messages back-to-back onto one
+//! unidirectional stream; the header's `payload_len` frames each message, so the receiving
+//! [`BatchedReceiver`] re-frames them with a [`StreamingDecoder`]. Backpressure comes from the
+//! stream's flow-control window like any other stream data.
+
+use tracing::trace;
+
+use crate::protocol::{self, Message, StreamingDecoder};
+
+use super::stream::{StreamError, StreamId, StreamManager};
+
+/// Handle writing MXP messages onto one long-lived unidirectional stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageSink {
+    stream: StreamId,
+}
+
+impl MessageSink {
+    pub(crate) const fn new(stream: StreamId) -> Self {
+        Self { stream }
+    }
+
+    /// Stream carrying the batched messages.
+    #[must_use]
+    pub const fn stream_id(&self) -> StreamId {
+        self.stream
+    }
+
+    /// Encode `message` and queue it on the sink's stream.
+    pub fn send(&self, streams: &mut StreamManager, message: &Message) -> Result<(), StreamError> {
+        streams.queue_send(self.stream, &protocol::encode(message))
+    }
+
+    /// Close the sink; the peer's receiver finishes once all queued messages are read.
+    pub fn finish(&self, streams: &mut StreamManager) -> Result<(), StreamError> {
+        streams.finish(self.stream)
+    }
+}
+
+/// Receiver yielding messages as they decode from a batched stream.
+#[derive(Debug)]
+pub struct BatchedReceiver {
+    stream: StreamId,
+    decoder: StreamingDecoder,
+}
+
+impl BatchedReceiver {
+    pub(crate) fn new(stream: StreamId) -> Self {
+        Self {
+            stream,
+            decoder: StreamingDecoder::new(),
+        }
+    }
+
+    /// Stream the messages are read from.
+    #[must_use]
+    pub const fn stream_id(&self) -> StreamId {
+        self.stream
+    }
+
+    /// Read all contiguous stream data and return every complete message decoded so far.
+    ///
+    /// A message split across stream chunks is held back until the rest arrives.
+    pub fn poll(&mut self, streams: &mut StreamManager) -> protocol::Result<Vec<Message>> {
+        let data = streams
+            .read(self.stream, usize::MAX)
+            .map_err(|err| protocol::Error::Stream(err.to_string()))?;
+        self.decoder.push_owned(data);
+
+        let mut messages = Vec::new();
+        while let Some(message) = self.decoder.next_message()? {
+            messages.push(message);
+        }
+        trace!(
+            stream = self.stream.as_u64(),
+            decoded = messages.len(),
+            pending = self.decoder.buffered(),
+            "decoded batched messages"
+        );
+        Ok(messages)
+    }
+
+    /// Whether the sender finished the stream and every message has been consumed.
+    pub fn is_finished(&self, streams: &StreamManager) -> Result<bool, StreamError> {
+        Ok(streams.is_receive_finished(self.stream)? && self.decoder.buffered() == 0)
+    }
+}
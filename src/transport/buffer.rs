@@ -1,9 +1,31 @@
 //! Zero-copy buffer pool for MXP transport packets.
 
-use std::collections::VecDeque;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering, compiler_fence};
 use std::sync::{Arc, Mutex};
 
+use bytes::buf::UninitSlice;
+use bytes::{Buf, BufMut, Bytes};
+
+/// Source of pool identities for the thread-local caches; never reused.
+static NEXT_POOL_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Per-thread free lists, keyed by pool ID.
+    static LOCAL_CACHES: RefCell<HashMap<u64, Vec<Vec<u8>>>> = RefCell::new(HashMap::new());
+}
+
 /// Shared pool of reusable byte buffers.
+///
+/// By default released buffers go back to the pool as-is, so a later caller may see data
+/// left by the previous holder. Pools created with [`BufferPool::with_zero_on_release`]
+/// scrub every buffer when it is released instead.
+///
+/// The shared free list sits behind a mutex. [`BufferPool::with_local_cache`] puts a small
+/// per-thread free list in front of it, so a thread that releases and re-acquires buffers
+/// does not touch the lock until its cache runs empty or full.
 #[derive(Clone, Debug)]
 pub struct BufferPool {
     inner: Arc<PoolInner>,
@@ -11,15 +33,34 @@ pub struct BufferPool {
 
 #[derive(Debug)]
 struct PoolInner {
+    id: u64,
     buffers: Mutex<VecDeque<Vec<u8>>>,
+    /// Buffers each thread may keep in its local cache (0 disables caching).
+    local_cache_size: usize,
     buffer_size: usize,
     max_buffers: usize,
+    zero_on_release: bool,
+    /// Buffers currently leased out.
+    outstanding: AtomicUsize,
+    /// Most buffers ever leased out at once.
+    high_water: AtomicUsize,
 }
 
 impl BufferPool {
     /// Create a new buffer pool.
     #[must_use]
     pub fn new(buffer_size: usize, max_buffers: usize) -> Self {
+        Self::build(buffer_size, max_buffers, false)
+    }
+
+    /// Create a pool that zeroes each buffer when it is released, for buffers that may hold
+    /// plaintext or key material.
+    #[must_use]
+    pub fn with_zero_on_release(buffer_size: usize, max_buffers: usize) -> Self {
+        Self::build(buffer_size, max_buffers, true)
+    }
+
+    fn build(buffer_size: usize, max_buffers: usize, zero_on_release: bool) -> Self {
         assert!(buffer_size > 0, "buffer_size must be positive");
         assert!(max_buffers > 0, "max_buffers must be positive");
 
@@ -30,29 +71,61 @@ impl BufferPool {
 
         Self {
             inner: Arc::new(PoolInner {
+                id: NEXT_POOL_ID.fetch_add(1, Ordering::Relaxed),
                 buffers: Mutex::new(deque),
+                local_cache_size: 0,
                 buffer_size,
                 max_buffers,
+                zero_on_release,
+                outstanding: AtomicUsize::new(0),
+                high_water: AtomicUsize::new(0),
             }),
         }
     }
 
+    /// Let each thread keep up to `size` released buffers for itself.
+    ///
+    /// Cached buffers are not counted by [`available`](Self::available), and a thread's cache
+    /// is only freed when the thread exits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pool has already been cloned.
+    #[must_use]
+    pub fn with_local_cache(mut self, size: usize) -> Self {
+        Arc::get_mut(&mut self.inner)
+            .expect("configure the local cache before sharing the pool")
+            .local_cache_size = size;
+        self
+    }
+
+    /// Buffers each thread may cache locally.
+    #[must_use]
+    pub fn local_cache_size(&self) -> usize {
+        self.inner.local_cache_size
+    }
+
     /// Acquire a buffer from the pool.
     #[must_use]
     pub fn acquire(&self) -> Buffer {
-        let mut guard = self
-            .inner
-            .buffers
-            .lock()
-            .expect("buffer pool mutex poisoned");
+        let buffer = self.inner.take_local().unwrap_or_else(|| {
+            self.inner
+                .buffers
+                .lock()
+                .expect("buffer pool mutex poisoned")
+                .pop_front()
+                .unwrap_or_else(|| vec![0u8; self.inner.buffer_size])
+        });
 
-        let buffer = guard
-            .pop_front()
-            .unwrap_or_else(|| vec![0u8; self.inner.buffer_size]);
+        let outstanding = self.inner.outstanding.fetch_add(1, Ordering::Relaxed) + 1;
+        self.inner
+            .high_water
+            .fetch_max(outstanding, Ordering::Relaxed);
 
         Buffer {
             data: Some(buffer),
             pool: Arc::clone(&self.inner),
+            start: 0,
             len: 0,
         }
     }
@@ -68,18 +141,189 @@ impl BufferPool {
     pub fn max_buffers(&self) -> usize {
         self.inner.max_buffers
     }
+
+    /// Whether released buffers are zeroed before reuse.
+    #[must_use]
+    pub fn zero_on_release(&self) -> bool {
+        self.inner.zero_on_release
+    }
+
+    /// Number of idle buffers ready to be acquired without allocating.
+    #[must_use]
+    pub fn available(&self) -> usize {
+        self.inner
+            .buffers
+            .lock()
+            .expect("buffer pool mutex poisoned")
+            .len()
+    }
+
+    /// Number of buffers currently leased out.
+    #[must_use]
+    pub fn outstanding(&self) -> usize {
+        self.inner.outstanding.load(Ordering::Relaxed)
+    }
+
+    /// Most buffers that have been leased out at the same time.
+    #[must_use]
+    pub fn high_water_mark(&self) -> usize {
+        self.inner.high_water.load(Ordering::Relaxed)
+    }
+}
+
+/// Buffer sizes used by [`SizeClassedPool::default`]: ACK/control, small messages, one
+/// Ethernet MTU, and jumbo-ish datagrams.
+pub const DEFAULT_SIZE_CLASSES: [usize; 4] = [128, 512, 1500, 2048];
+
+/// Buffers kept per class by [`SizeClassedPool::default`].
+pub const DEFAULT_BUFFERS_PER_CLASS: usize = 32;
+
+/// Set of [`BufferPool`]s of increasing size; requests draw from the smallest that fits.
+///
+/// Buffers go back to the class they were taken from when dropped.
+#[derive(Clone, Debug)]
+pub struct SizeClassedPool {
+    /// Pools sorted by ascending buffer size.
+    classes: Vec<BufferPool>,
+}
+
+impl SizeClassedPool {
+    /// Create one pool of `buffers_per_class` buffers for each size in `sizes`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sizes` is empty or contains zero, or if `buffers_per_class` is zero.
+    #[must_use]
+    pub fn new(sizes: &[usize], buffers_per_class: usize) -> Self {
+        Self::build(sizes, buffers_per_class, false)
+    }
+
+    /// Like [`new`](Self::new), but every class zeroes buffers on release.
+    #[must_use]
+    pub fn with_zero_on_release(sizes: &[usize], buffers_per_class: usize) -> Self {
+        Self::build(sizes, buffers_per_class, true)
+    }
+
+    fn build(sizes: &[usize], buffers_per_class: usize, zero_on_release: bool) -> Self {
+        assert!(!sizes.is_empty(), "at least one size class is required");
+        let mut sizes = sizes.to_vec();
+        sizes.sort_unstable();
+        sizes.dedup();
+        let classes = sizes
+            .into_iter()
+            .map(|size| BufferPool::build(size, buffers_per_class, zero_on_release))
+            .collect();
+        Self { classes }
+    }
+
+    /// Give every class a per-thread cache of `size` buffers (see
+    /// [`BufferPool::with_local_cache`]).
+    #[must_use]
+    pub fn with_local_cache(self, size: usize) -> Self {
+        Self {
+            classes: self
+                .classes
+                .into_iter()
+                .map(|pool| pool.with_local_cache(size))
+                .collect(),
+        }
+    }
+
+    /// Acquire the smallest buffer with capacity for at least `len` bytes.
+    ///
+    /// Returns `None` if `len` exceeds the largest class.
+    #[must_use]
+    pub fn acquire_at_least(&self, len: usize) -> Option<Buffer> {
+        self.class_for(len).map(BufferPool::acquire)
+    }
+
+    /// Pool that serves requests for `len` bytes.
+    #[must_use]
+    pub fn class_for(&self, len: usize) -> Option<&BufferPool> {
+        self.classes.iter().find(|pool| pool.buffer_size() >= len)
+    }
+
+    /// Pools in ascending size order.
+    #[must_use]
+    pub fn classes(&self) -> &[BufferPool] {
+        &self.classes
+    }
+
+    /// Largest buffer the pool can hand out.
+    #[must_use]
+    pub fn max_buffer_size(&self) -> usize {
+        self.classes.last().map_or(0, BufferPool::buffer_size)
+    }
+
+    /// `(buffer_size, high_water_mark)` for each class, in ascending size order.
+    #[must_use]
+    pub fn high_water_marks(&self) -> Vec<(usize, usize)> {
+        self.classes
+            .iter()
+            .map(|pool| (pool.buffer_size(), pool.high_water_mark()))
+            .collect()
+    }
+}
+
+impl Default for SizeClassedPool {
+    fn default() -> Self {
+        Self::new(&DEFAULT_SIZE_CLASSES, DEFAULT_BUFFERS_PER_CLASS)
+    }
+}
+
+impl PoolInner {
+    fn take_local(&self) -> Option<Vec<u8>> {
+        if self.local_cache_size == 0 {
+            return None;
+        }
+        LOCAL_CACHES
+            .try_with(|caches| caches.borrow_mut().get_mut(&self.id).and_then(Vec::pop))
+            .ok()
+            .flatten()
+    }
+
+    /// Keep `data` in this thread's cache, handing it back if the cache is full.
+    fn put_local(&self, data: Vec<u8>) -> Option<Vec<u8>> {
+        if self.local_cache_size == 0 {
+            return Some(data);
+        }
+        let mut data = Some(data);
+        // Fails only while the thread is exiting, in which case `data` is returned as-is.
+        let _ = LOCAL_CACHES.try_with(|caches| {
+            let mut caches = caches.borrow_mut();
+            let cache = caches.entry(self.id).or_default();
+            if cache.len() < self.local_cache_size {
+                cache.extend(data.take());
+            }
+        });
+        data
+    }
+}
+
+/// Overwrite `data` with zeros in a way the optimizer will not elide.
+fn scrub(data: &mut [u8]) {
+    data.fill(0);
+    compiler_fence(Ordering::SeqCst);
 }
 
 /// Buffer leased from the pool.
+///
+/// The filled region is readable through [`Deref`], [`AsRef`] and [`Buf`] (which consumes it
+/// from the front), and [`BufMut`] appends after it up to the fixed capacity.
+/// [`Buffer::freeze`] hands the filled region to [`Bytes`] without copying.
 pub struct Buffer {
     data: Option<Vec<u8>>,
     pool: Arc<PoolInner>,
+    /// Start of the filled region; advanced as [`Buf`] consumes it.
+    start: usize,
+    /// End of the filled region.
     len: usize,
 }
 
 impl Buffer {
     /// Reset the logical length of the buffer.
     pub fn reset(&mut self) {
+        self.start = 0;
         self.len = 0;
         if let Some(data) = self.data.as_mut() {
             data.fill(0);
@@ -97,25 +341,29 @@ impl Buffer {
     #[must_use]
     pub fn as_slice(&self) -> &[u8] {
         let data = self.data.as_ref().expect("buffer already returned to pool");
-        &data[..self.len]
+        &data[self.start..self.len]
     }
 
     /// Current logical length of the buffer.
     #[must_use]
     pub fn len(&self) -> usize {
-        self.len
+        self.len - self.start
     }
 
     /// Check whether the buffer contains no data.
     #[must_use]
     pub fn is_empty(&self) -> bool {
-        self.len == 0
+        self.len == self.start
     }
 
     /// Set the length of meaningful data within the buffer.
+    ///
+    /// The filled region becomes the first `len` bytes of [`as_mut_slice`](Self::as_mut_slice),
+    /// discarding any read position.
     pub fn set_len(&mut self, len: usize) {
         let capacity = self.capacity();
         assert!(len <= capacity, "buffer length exceeds capacity");
+        self.start = 0;
         self.len = len;
     }
 
@@ -124,20 +372,241 @@ impl Buffer {
     pub fn capacity(&self) -> usize {
         self.data.as_ref().map_or(0, Vec::len)
     }
+
+    /// Hand the filled region to [`Bytes`] without copying.
+    ///
+    /// The storage returns to the pool (scrubbed, if the pool zeroes on release) once the
+    /// last clone of the returned `Bytes` is dropped.
+    #[must_use]
+    pub fn freeze(self) -> Bytes {
+        Bytes::from_owner(self)
+    }
+}
+
+impl Deref for Buffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl AsRef<[u8]> for Buffer {
+    fn as_ref(&self) -> &[u8] {
+        self.as_slice()
+    }
+}
+
+impl Buf for Buffer {
+    fn remaining(&self) -> usize {
+        self.len()
+    }
+
+    fn chunk(&self) -> &[u8] {
+        self.as_slice()
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        assert!(cnt <= self.len(), "cannot advance past the filled region");
+        self.start += cnt;
+    }
+}
+
+// SAFETY: `chunk_mut` exposes the initialized storage after the filled region, and
+// `advance_mut` only extends the filled region within it.
+unsafe impl BufMut for Buffer {
+    fn remaining_mut(&self) -> usize {
+        self.capacity() - self.len
+    }
+
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        assert!(
+            cnt <= self.remaining_mut(),
+            "buffer length exceeds capacity"
+        );
+        self.len += cnt;
+    }
+
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        let len = self.len;
+        UninitSlice::new(&mut self.as_mut_slice()[len..])
+    }
 }
 
 impl Drop for Buffer {
     fn drop(&mut self) {
         if let Some(mut data) = self.data.take() {
-            data.fill(0);
-            let mut guard = self
-                .pool
-                .buffers
-                .lock()
-                .expect("buffer pool mutex poisoned");
-            if guard.len() < self.pool.max_buffers {
-                guard.push_back(data);
+            if self.pool.zero_on_release {
+                scrub(&mut data);
+            }
+            self.pool.outstanding.fetch_sub(1, Ordering::Relaxed);
+            if let Some(data) = self.pool.put_local(data) {
+                let mut guard = self
+                    .pool
+                    .buffers
+                    .lock()
+                    .expect("buffer pool mutex poisoned");
+                if guard.len() < self.pool.max_buffers {
+                    guard.push_back(data);
+                }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release_secret(pool: &BufferPool) {
+        let mut buffer = pool.acquire();
+        buffer.as_mut_slice()[..6].copy_from_slice(b"secret");
+        buffer.set_len(6);
+    }
+
+    #[test]
+    fn zeroing_pool_returns_cleared_buffer() {
+        let pool = BufferPool::with_zero_on_release(64, 1);
+        assert!(pool.zero_on_release());
+        release_secret(&pool);
+        let reused = pool.acquire();
+        assert!(reused.is_empty());
+        assert!(reused.data.as_ref().unwrap().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn default_pool_skips_scrubbing() {
+        let pool = BufferPool::new(64, 1);
+        assert!(!pool.zero_on_release());
+        release_secret(&pool);
+        let mut reused = pool.acquire();
+        assert!(reused.is_empty());
+        assert_eq!(&reused.as_mut_slice()[..6], b"secret");
+    }
+
+    #[test]
+    fn buf_traits_write_then_consume_the_filled_region() {
+        let pool = BufferPool::new(16, 1);
+        let mut buffer = pool.acquire();
+        buffer.put_u32_le(0xDEAD_BEEF);
+        buffer.put_slice(b"payload");
+        assert_eq!(buffer.len(), 11);
+        assert_eq!(buffer.remaining_mut(), 5);
+        assert_eq!(&buffer[4..], b"payload");
+
+        assert_eq!(buffer.get_u32_le(), 0xDEAD_BEEF);
+        assert_eq!(buffer.as_ref(), b"payload");
+        assert_eq!(buffer.remaining(), 7);
+
+        // Receiving into the storage again starts a fresh filled region.
+        buffer.as_mut_slice()[..3].copy_from_slice(b"new");
+        buffer.set_len(3);
+        assert_eq!(&*buffer, b"new");
+    }
+
+    #[test]
+    fn freeze_shares_storage_and_returns_it_on_last_drop() {
+        let pool = BufferPool::with_zero_on_release(64, 1);
+        let mut buffer = pool.acquire();
+        buffer.put_slice(b"header|body");
+        buffer.advance(7);
+        let storage = buffer.as_slice().as_ptr();
+
+        let frozen = buffer.freeze();
+        assert_eq!(frozen, &b"body"[..]);
+        assert_eq!(frozen.as_ptr(), storage, "freeze must not copy");
+        let clone = frozen.slice(1..);
+        assert_eq!(pool.outstanding(), 1);
+        assert_eq!(pool.available(), 0);
+
+        drop(frozen);
+        assert_eq!(pool.outstanding(), 1, "a clone still holds the storage");
+        drop(clone);
+        assert_eq!(pool.outstanding(), 0);
+        assert_eq!(pool.available(), 1);
+
+        // The same allocation comes back, scrubbed, for the next lease.
+        let reused = pool.acquire();
+        assert!(reused.is_empty());
+        assert_eq!(
+            reused.data.as_ref().unwrap().as_ptr(),
+            storage.wrapping_sub(7)
+        );
+        assert!(reused.data.as_ref().unwrap().iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn size_classes_serve_smallest_fitting_buffer() {
+        let pool = SizeClassedPool::new(&[1500, 128, 512], 2);
+        assert_eq!(pool.max_buffer_size(), 1500);
+        for (len, capacity) in [(0, 128), (64, 128), (128, 128), (129, 512), (600, 1500)] {
+            let buffer = pool.acquire_at_least(len).expect("fits");
+            assert_eq!(buffer.capacity(), capacity, "request for {len} bytes");
+        }
+        assert!(pool.acquire_at_least(1501).is_none());
+    }
+
+    #[test]
+    fn released_buffers_return_to_their_class() {
+        let pool = SizeClassedPool::default();
+        let data_class = pool.class_for(1200).unwrap().clone();
+        assert_eq!(data_class.buffer_size(), 1500);
+
+        let held: Vec<_> = (0..3)
+            .map(|_| pool.acquire_at_least(1200).unwrap())
+            .collect();
+        let ack = pool.acquire_at_least(64).unwrap();
+        assert_eq!(data_class.outstanding(), 3);
+        assert_eq!(data_class.available(), DEFAULT_BUFFERS_PER_CLASS - 3);
+
+        drop(held);
+        drop(ack);
+        assert_eq!(data_class.outstanding(), 0);
+        assert_eq!(data_class.available(), DEFAULT_BUFFERS_PER_CLASS);
+        assert_eq!(pool.classes()[0].available(), DEFAULT_BUFFERS_PER_CLASS);
+        assert_eq!(
+            pool.high_water_marks(),
+            [(128, 1), (512, 0), (1500, 3), (2048, 0)]
+        );
+    }
+
+    #[test]
+    fn local_cache_absorbs_release_and_spills_when_full() {
+        let pool = BufferPool::new(64, 4).with_local_cache(2);
+        assert_eq!(pool.local_cache_size(), 2);
+        let held: Vec<_> = (0..4).map(|_| pool.acquire()).collect();
+        assert_eq!(pool.available(), 0);
+
+        // Two releases fill this thread's cache; the rest spill to the shared list.
+        drop(held);
+        assert_eq!(pool.available(), 2);
+        assert_eq!(pool.outstanding(), 0);
+
+        // Acquiring drains the local cache before touching the shared list.
+        let first = pool.acquire();
+        let second = pool.acquire();
+        assert_eq!(pool.available(), 2);
+        let third = pool.acquire();
+        assert_eq!(pool.available(), 1);
+        drop((first, second, third));
+    }
+
+    #[test]
+    fn local_caches_are_per_thread_and_per_pool() {
+        let pool = BufferPool::new(64, 2).with_local_cache(4);
+        let other = BufferPool::new(128, 1).with_local_cache(4);
+        drop(pool.acquire());
+        assert_eq!(other.acquire().capacity(), 128);
+
+        // Another thread cannot see this thread's cached buffer.
+        let shared = pool.clone();
+        std::thread::spawn(move || {
+            let buffer = shared.acquire();
+            assert_eq!(shared.available(), 0);
+            drop(buffer);
+        })
+        .join()
+        .unwrap();
+        assert_eq!(pool.available(), 0);
+    }
+}
@@ -1,14 +1,76 @@
-//! Congestion control primitives for MXP transport (BBR-inspired).
+//! Congestion control primitives for MXP transport.
+//!
+//! Algorithms implement [`CongestionControl`]; [`CongestionConfig::algorithm`] selects between
+//! the BBR-inspired [`CongestionController`] and [`CubicController`].
 
+use crate::protocol::metrics::Metrics;
+use crate::transport::cubic::CubicController;
 use crate::transport::loss::{AckOutcome, SentPacketInfo};
+#[cfg(feature = "qlog")]
+use crate::transport::qlog::{LoggedCongestionControl, SharedEventLogger};
 use core::fmt;
 use std::time::{Duration, SystemTime};
 /// Gain cycle used by the pacing model (similar to BBR's 8-phase cycle).
 const PACING_GAINS: [f64; 8] = [1.25, 1.0, 1.0, 1.0, 1.0, 1.0, 0.75, 1.0];
 
+/// Congestion control algorithm selection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CongestionAlgorithm {
+    /// BBR-inspired bandwidth probing with a pacing gain cycle.
+    #[default]
+    Bbr,
+    /// CUBIC window growth with multiplicative decrease on loss.
+    Cubic,
+}
+
+/// Behaviour shared by congestion control algorithms.
+pub trait CongestionControl: fmt::Debug + Send {
+    /// Called when a packet is sent.
+    fn on_packet_sent(&mut self, size: usize);
+
+    /// Called when ACK/loss info is available.
+    fn on_ack_outcome(&mut self, outcome: &AckOutcome, now: SystemTime);
+
+    /// Bytes currently permitted in flight.
+    fn window(&self) -> usize;
+
+    /// Suggested pacing rate in bytes per second.
+    fn pacing_rate(&self) -> f64;
+
+    /// Called when packets are declared lost outside an ACK, such as by
+    /// [`LossManager::on_loss_timeout`](crate::transport::LossManager::on_loss_timeout).
+    ///
+    /// Releases their bytes from flight and reacts as to losses reported with an ACK.
+    fn on_packets_lost(&mut self, lost: &[SentPacketInfo], now: SystemTime) {
+        if lost.is_empty() {
+            return;
+        }
+        let outcome = AckOutcome {
+            lost: lost.to_vec(),
+            ..AckOutcome::default()
+        };
+        self.on_ack_outcome(&outcome, now);
+    }
+
+    /// Bytes sent but not yet acknowledged or declared lost.
+    fn bytes_in_flight(&self) -> usize;
+
+    /// Bytes that may still be sent before the window is full.
+    fn available_window(&self) -> usize {
+        self.window().saturating_sub(self.bytes_in_flight())
+    }
+
+    /// Whether a packet of `size` bytes fits in the congestion window.
+    fn can_send(&self, size: usize) -> bool {
+        self.bytes_in_flight().saturating_add(size) <= self.window()
+    }
+}
+
 /// Configurable parameters for congestion control.
 #[derive(Debug, Clone)]
 pub struct CongestionConfig {
+    /// Algorithm built by [`CongestionConfig::build`].
+    pub algorithm: CongestionAlgorithm,
     /// Initial congestion window in bytes.
     pub initial_window: usize,
     /// Minimum congestion window in bytes.
@@ -24,6 +86,7 @@ pub struct CongestionConfig {
 impl Default for CongestionConfig {
     fn default() -> Self {
         Self {
+            algorithm: CongestionAlgorithm::default(),
             initial_window: 32 * 1024,
             min_window: 4 * 1024,
             max_window: 4 * 1024 * 1024,
@@ -33,7 +96,25 @@ impl Default for CongestionConfig {
     }
 }
 
-/// Congestion control state machine.
+impl CongestionConfig {
+    /// Construct the configured algorithm.
+    #[must_use]
+    pub fn build(self) -> Box<dyn CongestionControl> {
+        match self.algorithm {
+            CongestionAlgorithm::Bbr => Box::new(CongestionController::new(self)),
+            CongestionAlgorithm::Cubic => Box::new(CubicController::new(self)),
+        }
+    }
+
+    /// Construct the configured algorithm, reporting congestion window changes to `logger`.
+    #[cfg(feature = "qlog")]
+    #[must_use]
+    pub fn build_with_event_logger(self, logger: SharedEventLogger) -> Box<dyn CongestionControl> {
+        Box::new(LoggedCongestionControl::new(self.build(), logger))
+    }
+}
+
+/// BBR-inspired congestion control state machine.
 #[derive(Debug)]
 pub struct CongestionController {
     config: CongestionConfig,
@@ -64,49 +145,20 @@ impl CongestionController {
         controller
     }
 
-    /// Called when a packet is sent.
-    pub fn on_packet_sent(&mut self, size: usize) {
-        self.inflight_bytes = self.inflight_bytes.saturating_add(size);
-        self.max_inflight = self.max_inflight.max(self.inflight_bytes);
-    }
-
-    /// Called when ACK/loss info is available.
-    pub fn on_ack_outcome(&mut self, outcome: &AckOutcome, now: SystemTime) {
-        for pkt in &outcome.acknowledged {
-            self.inflight_bytes = self.inflight_bytes.saturating_sub(pkt.size());
-        }
-
-        if !outcome.acknowledged.is_empty() {
-            if let Some(rtt) = outcome.rtt_sample {
-                if rtt > Duration::from_micros(0) {
-                    let delivered: usize =
-                        outcome.acknowledged.iter().map(SentPacketInfo::size).sum();
-                    let seconds = duration_to_secs(rtt);
-                    let bw = delivered as f64 / seconds.max(1e-9);
-                    self.bandwidth_estimate = self.bandwidth_estimate.max(bw);
-                }
-            }
-            self.increase_window();
-        }
-
-        if !outcome.lost.is_empty() {
-            self.reduce_window();
-        }
-
+    /// Record `bytes` acknowledged at `now` with an RTT sample of `rtt`.
+    ///
+    /// Convenience for callers that track bytes rather than [`AckOutcome`]s.
+    pub fn on_ack(&mut self, bytes: usize, now: SystemTime, rtt: Duration) {
+        self.on_delivered(bytes, Some(rtt));
         self.advance_pacing_cycle(now);
         self.recompute_pacing();
     }
 
-    /// Bytes currently permitted in flight.
-    #[must_use]
-    pub fn window(&self) -> usize {
-        self.congestion_window
-    }
-
-    /// Suggested pacing rate in bytes per second.
-    #[must_use]
-    pub fn pacing_rate(&self) -> f64 {
-        self.pacing_rate
+    /// Record `bytes` declared lost, shrinking the window.
+    pub fn on_loss(&mut self, bytes: usize) {
+        release_inflight(&mut self.inflight_bytes, bytes);
+        self.reduce_window();
+        self.recompute_pacing();
     }
 
     /// Maximum number of bytes considered safely in-flight.
@@ -115,13 +167,26 @@ impl CongestionController {
         self.max_inflight
     }
 
+    fn on_delivered(&mut self, delivered: usize, rtt: Option<Duration>) {
+        release_inflight(&mut self.inflight_bytes, delivered);
+        if let Some(rtt) = rtt {
+            if rtt > Duration::from_micros(0) {
+                let seconds = duration_to_secs(rtt);
+                let bw = delivered as f64 / seconds.max(1e-9);
+                self.bandwidth_estimate = self.bandwidth_estimate.max(bw);
+            }
+        }
+        self.increase_window();
+    }
+
     fn increase_window(&mut self) {
         self.congestion_window = (self.congestion_window + 1500).min(self.config.max_window);
     }
 
+    /// Halve the window after a congestion signal. In-flight bytes are released only
+    /// for packets actually acknowledged or lost, never by clamping to the window.
     fn reduce_window(&mut self) {
         self.congestion_window = (self.congestion_window / 2).max(self.config.min_window);
-        self.inflight_bytes = self.inflight_bytes.min(self.congestion_window);
     }
 
     fn advance_pacing_cycle(&mut self, now: SystemTime) {
@@ -150,10 +215,69 @@ impl CongestionController {
     }
 }
 
-fn duration_to_secs(d: Duration) -> f64 {
+impl CongestionControl for CongestionController {
+    fn on_packet_sent(&mut self, size: usize) {
+        self.inflight_bytes = self.inflight_bytes.saturating_add(size);
+        self.max_inflight = self.max_inflight.max(self.inflight_bytes);
+    }
+
+    fn on_ack_outcome(&mut self, outcome: &AckOutcome, now: SystemTime) {
+        if !outcome.acknowledged.is_empty() {
+            let delivered = outcome.acknowledged.iter().map(SentPacketInfo::size).sum();
+            self.on_delivered(delivered, outcome.rtt_sample);
+        }
+
+        if !outcome.lost.is_empty() {
+            let lost = outcome.lost.iter().map(SentPacketInfo::size).sum();
+            release_inflight(&mut self.inflight_bytes, lost);
+        }
+        if !outcome.lost.is_empty() || outcome.ecn_ce_increase > 0 {
+            self.reduce_window();
+        }
+
+        self.advance_pacing_cycle(now);
+        self.recompute_pacing();
+    }
+
+    fn window(&self) -> usize {
+        self.congestion_window
+    }
+
+    fn pacing_rate(&self) -> f64 {
+        self.pacing_rate
+    }
+
+    fn bytes_in_flight(&self) -> usize {
+        self.inflight_bytes
+    }
+}
+
+/// Take `bytes` out of `inflight`, which must cover them.
+///
+/// Releasing more than is in flight means a packet was counted twice or never sent; it
+/// trips a debug assertion, is counted in the `inflight_underflows` metric and clamps to
+/// zero in release builds.
+pub(crate) fn release_inflight(inflight: &mut usize, bytes: usize) {
+    if bytes > *inflight {
+        Metrics::record_inflight_underflow();
+        debug_assert!(
+            bytes <= *inflight,
+            "releasing {bytes} bytes with only {inflight} in flight"
+        );
+    }
+    *inflight = inflight.saturating_sub(bytes);
+}
+
+pub(crate) fn duration_to_secs(d: Duration) -> f64 {
     d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1_000_000_000.0
 }
 
+impl Default for CongestionController {
+    fn default() -> Self {
+        Self::new(CongestionConfig::default())
+    }
+}
+
 impl fmt::Display for CongestionController {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
@@ -167,6 +291,8 @@ impl fmt::Display for CongestionController {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::transport::ack::{AckFrame, AckRange};
+    use crate::transport::loss::{LossConfig, LossManager};
 
     fn ack_pkt(number: u64, size: usize, sent: SystemTime) -> SentPacketInfo {
         SentPacketInfo::new(number, sent, size, true)
@@ -182,6 +308,7 @@ mod tests {
             acknowledged: vec![ack_pkt(1, 1200, now - Duration::from_millis(10))],
             lost: Vec::new(),
             rtt_sample: Some(Duration::from_millis(10)),
+            ecn_ce_increase: 0,
         };
         cc.on_ack_outcome(&ack, now);
         assert!(cc.window() > config.initial_window);
@@ -200,6 +327,7 @@ mod tests {
             acknowledged: Vec::new(),
             lost: vec![ack_pkt(1, 1200, now - Duration::from_millis(5))],
             rtt_sample: None,
+            ecn_ce_increase: 0,
         };
         let prev_window = cc.window();
         cc.on_ack_outcome(&loss, now);
@@ -207,6 +335,26 @@ mod tests {
         assert!(cc.window() >= config.min_window);
     }
 
+    #[test]
+    fn controller_reduces_window_on_ce_without_loss() {
+        let config = CongestionConfig::default();
+        let mut cc = CongestionController::new(config.clone());
+        cc.on_packet_sent(1200);
+        cc.on_packet_sent(1200);
+        let now = SystemTime::now();
+        let prev_window = cc.window();
+        let ce = AckOutcome {
+            acknowledged: vec![ack_pkt(1, 1200, now - Duration::from_millis(10))],
+            lost: Vec::new(),
+            rtt_sample: Some(Duration::from_millis(10)),
+            ecn_ce_increase: 1,
+        };
+        cc.on_ack_outcome(&ce, now);
+        assert!(cc.window() < prev_window);
+        assert!(cc.window() >= config.min_window);
+        assert_eq!(cc.bytes_in_flight(), 1200);
+    }
+
     #[test]
     fn pacing_cycle_advances_over_time() {
         let config = CongestionConfig::default();
@@ -216,11 +364,109 @@ mod tests {
             acknowledged: vec![ack_pkt(1, 1200, base - Duration::from_millis(10))],
             lost: Vec::new(),
             rtt_sample: Some(Duration::from_millis(10)),
+            ecn_ce_increase: 0,
         };
+        cc.on_packet_sent(2400);
         cc.on_ack_outcome(&ack, base);
         let first_rate = cc.pacing_rate();
         cc.on_ack_outcome(&ack, base + Duration::from_millis(60));
         let second_rate = cc.pacing_rate();
         assert_ne!(first_rate, second_rate);
     }
+
+    /// Run random sends, ACKs and loss timeouts through a [`LossManager`] and `cc`,
+    /// checking after every event that the controller's in-flight bytes match the
+    /// packets the loss manager still tracks.
+    fn check_inflight_tracks_loss_manager(mut cc: Box<dyn CongestionControl>, seed: u64) {
+        let mut state = seed;
+        let mut next = move |bound: u64| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+            (state >> 33) % bound
+        };
+        let mut loss = LossManager::new(LossConfig::default());
+        let mut now = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut packet_number = 0;
+        let underflows = Metrics::totals().inflight_underflows;
+
+        for _ in 0..5_000 {
+            match next(10) {
+                0..=4 => {
+                    let size = 200 + usize::try_from(next(1_300)).unwrap();
+                    loss.on_packet_sent(packet_number, now, size, next(8) != 0);
+                    cc.on_packet_sent(size);
+                    packet_number += 1;
+                }
+                5..=7 => {
+                    let ranges: Vec<AckRange> = loss
+                        .outstanding()
+                        .map(SentPacketInfo::packet_number)
+                        .filter(|_| next(3) == 0)
+                        .map(|number| AckRange::new(number, number).unwrap())
+                        .collect();
+                    let Some(largest) = ranges.iter().map(AckRange::end).max() else {
+                        continue;
+                    };
+                    let frame = AckFrame::new(largest, Duration::ZERO, ranges).unwrap();
+                    cc.on_ack_outcome(&loss.on_ack_frame(&frame, now), now);
+                }
+                _ => {
+                    now += Duration::from_millis(next(300));
+                    let timed_out = loss.on_loss_timeout(now);
+                    cc.on_packets_lost(&timed_out, now);
+                }
+            }
+            assert_eq!(cc.bytes_in_flight(), loss.bytes_in_flight());
+            assert_eq!(
+                cc.available_window(),
+                cc.window().saturating_sub(loss.bytes_in_flight())
+            );
+        }
+        assert!(packet_number > 1_000);
+        assert_eq!(Metrics::totals().inflight_underflows, underflows);
+    }
+
+    #[test]
+    fn inflight_matches_outstanding_packets_under_random_events() {
+        for seed in 1..=4 {
+            check_inflight_tracks_loss_manager(CongestionConfig::default().build(), seed);
+            let cubic = CongestionConfig {
+                algorithm: CongestionAlgorithm::Cubic,
+                ..CongestionConfig::default()
+            };
+            check_inflight_tracks_loss_manager(cubic.build(), seed);
+        }
+    }
+
+    #[test]
+    fn timeout_losses_release_inflight() {
+        let mut cc = CongestionController::default();
+        let now = SystemTime::now();
+        for _ in 0..4 {
+            cc.on_packet_sent(1200);
+        }
+        let window = cc.window();
+        assert_eq!(cc.available_window(), window - 4800);
+        cc.on_packets_lost(&[ack_pkt(1, 1200, now), ack_pkt(2, 1200, now)], now);
+        assert_eq!(cc.bytes_in_flight(), 2400);
+        assert_eq!(cc.window(), window / 2);
+        cc.on_packets_lost(&[], now);
+        assert_eq!(cc.window(), window / 2);
+    }
+
+    #[test]
+    fn byte_level_ack_and_loss_match_outcomes() {
+        let mut cc = CongestionController::default();
+        let initial = CongestionConfig::default().initial_window;
+        cc.on_packet_sent(2400);
+        cc.on_ack(1200, SystemTime::now(), Duration::from_millis(10));
+        assert_eq!(cc.bytes_in_flight(), 1200);
+        assert!(cc.window() > initial);
+
+        let grown = cc.window();
+        cc.on_loss(1200);
+        assert_eq!(cc.bytes_in_flight(), 0);
+        assert_eq!(cc.window(), grown / 2);
+    }
 }
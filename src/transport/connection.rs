@@ -0,0 +1,1175 @@
+//! Connection state machine tying the handshake, packet protection, loss recovery,
+//! congestion control, and streams together.
+//!
+//! A [`Connection`] performs no I/O: feed it received datagrams with
+//! [`handle_datagram`](Connection::handle_datagram), drain outgoing ones with
+//! [`poll_transmit`](Connection::poll_transmit), and call
+//! [`handle_timeout`](Connection::handle_timeout) once
+//! [`poll_timeout`](Connection::poll_timeout) passes.
+//!
+//! Ack-eliciting packets are paced at the congestion controller's
+//! [`pacing_rate`](CongestionControl::pacing_rate): once the [`Pacer`]'s burst allowance is
+//! spent, `poll_transmit` holds them back and `poll_timeout` reports when the next may go.
+//!
+//! Once established, a connection with [`pmtu`](ConnectionConfig::pmtu) set probes the path
+//! with padded packets and grows its datagrams to the largest size acknowledged; see
+//! [`current_mtu`](Connection::current_mtu).
+//!
+//! Every datagram starts with one type byte: `0x00` for a plaintext handshake message,
+//! `0x01` for a protected packet whose payload is a sequence of [`Frame`]s.
+//!
+//! MXP messages travel batched on unidirectional streams, one per direction. Messages
+//! flagged `REQUIRES_ACK` are acknowledged automatically; see
+//! [`send_acked`](Connection::send_acked).
+
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use tracing::{debug, trace};
+
+use crate::protocol::{self, Message, RequestStatus, RequestTracker};
+
+use super::ack::{DEFAULT_MAX_ACK_RANGES, ReceiveHistory};
+use super::batch::{BatchedReceiver, MessageSink};
+use super::congestion::{CongestionConfig, CongestionControl};
+use super::crypto::{AEAD_TAG_LEN, PublicKey, SessionKeys};
+use super::error::TransportError;
+use super::handshake::{
+    HandshakeError, HandshakeMessage, HandshakeMessageKind, HandshakeServer, Initiator,
+    PendingHandshake,
+};
+use super::loss::{LossConfig, LossManager, SentPacketInfo};
+use super::mtu::{PmtuConfig, PmtuDiscovery};
+use super::pacer::{Pacer, PacerConfig};
+use super::packet::{
+    ConnectionId, FRAME_HEADER_LEN, Frame, FrameType, PacketFlags, PacketHeader,
+    STREAM_DATA_OVERHEAD,
+};
+use super::packet_crypto::PacketCipher;
+use super::params::TransportParameters;
+use super::socket::SocketError;
+use super::stream::{EndpointRole, SendChunk, StreamError, StreamId, StreamKind, StreamManager};
+
+/// Default largest datagram a connection emits, matching
+/// [`MIN_UDP_PAYLOAD_SIZE`](super::MIN_UDP_PAYLOAD_SIZE).
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1200;
+
+/// Default idle timeout, matching the transport parameter default.
+pub const DEFAULT_MAX_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Reason recorded by [`Connection::close_reason`] when the idle timeout closes a connection.
+pub const IDLE_TIMEOUT_REASON: &str = "idle timeout";
+
+/// Datagram type byte for a plaintext handshake message.
+pub(crate) const HANDSHAKE_DATAGRAM: u8 = 0x00;
+/// Datagram type byte for a protected packet.
+const PACKET_DATAGRAM: u8 = 0x01;
+
+/// Connection ID carried by a packet datagram, readable before the packet is opened.
+///
+/// `None` for handshake datagrams and truncated headers.
+pub(crate) fn packet_connection_id(datagram: &[u8]) -> Option<ConnectionId> {
+    let Some((&PACKET_DATAGRAM, packet)) = datagram.split_first() else {
+        return None;
+    };
+    let len = usize::from(PacketHeader::peek_conn_id_len(packet).ok()?);
+    ConnectionId::new(packet.get(1..=len)?).ok()
+}
+
+/// Settings for a [`Connection`].
+///
+/// Handshake options (keys, identities, transport parameters, timeouts) are configured on
+/// the [`Initiator`] or [`HandshakeServer`] instead.
+#[derive(Debug, Clone)]
+pub struct ConnectionConfig {
+    /// Congestion controller to run.
+    pub congestion: CongestionConfig,
+    /// Loss detection settings.
+    pub loss: LossConfig,
+    /// Largest datagram returned by [`Connection::poll_transmit`], type byte included, until
+    /// path MTU discovery confirms a larger one.
+    pub max_datagram_size: usize,
+    /// Connection ID stamped on outgoing packets.
+    pub connection_id: ConnectionId,
+    /// How long an ACK for ack-eliciting packets may be delayed.
+    pub ack_delay: Duration,
+    /// Close the connection silently after this long without activity; `None` leaves it
+    /// to the negotiated `max_idle_timeout`.
+    pub max_idle_timeout: Option<Duration>,
+    /// Send a `PING` after this long without sending an ack-eliciting packet, so the peer's
+    /// ACK keeps both idle timers fresh. `None` disables keepalives.
+    pub keep_alive_interval: Option<Duration>,
+    /// Take part in the spin bit so on-path observers can measure the round-trip time;
+    /// when disabled every packet carries a zero spin bit.
+    pub spin_bit: bool,
+    /// Space ack-eliciting packets at the congestion controller's pacing rate; `None` sends
+    /// whatever the congestion window allows back to back.
+    pub pacing: Option<PacerConfig>,
+    /// Search for a larger path MTU once established, never beyond the negotiated
+    /// `max_udp_payload_size`. The default `None` keeps every datagram within
+    /// `max_datagram_size`.
+    pub pmtu: Option<PmtuConfig>,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        let loss = LossConfig::default();
+        Self {
+            congestion: CongestionConfig::default(),
+            ack_delay: loss.max_ack_delay,
+            loss,
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            connection_id: ConnectionId::default(),
+            max_idle_timeout: Some(DEFAULT_MAX_IDLE_TIMEOUT),
+            keep_alive_interval: None,
+            spin_bit: true,
+            pacing: Some(PacerConfig::default()),
+            pmtu: None,
+        }
+    }
+}
+
+/// Errors surfaced by a [`Connection`].
+#[derive(Debug, thiserror::Error)]
+pub enum ConnectionError {
+    /// The handshake failed or timed out; the connection cannot carry data.
+    #[error("handshake failed: {0}")]
+    Handshake(#[from] HandshakeError),
+    /// A protected packet failed to open and was dropped; the connection is unaffected.
+    #[error("packet dropped: {0}")]
+    Packet(#[from] TransportError),
+    /// A frame from the peer broke stream rules.
+    #[error("stream error: {0}")]
+    Stream(#[from] StreamError),
+    /// The datagram or a frame inside it could not be parsed.
+    #[error("malformed datagram")]
+    Malformed,
+    /// A message could not be decoded, tracked, or was not acknowledged in time.
+    #[error("message error: {0}")]
+    Message(#[from] protocol::Error),
+    /// No message with this ID is awaiting an ack.
+    #[error("message {0} is not awaiting an ack")]
+    NotAwaitingAck(u64),
+    /// The shared socket failed to receive or send a datagram.
+    #[error("socket error: {0:?}")]
+    Socket(SocketError),
+    /// The connection closed with an error code or timed out.
+    #[error("connection closed with code {}: {}", .0.error_code, .0.reason)]
+    Closed(ConnectionClose),
+    /// No message arrived in time.
+    #[error("timed out waiting for a message")]
+    Timeout,
+}
+
+/// Lifecycle of a [`Connection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Keys are not yet available; only handshake messages are exchanged.
+    Handshaking,
+    /// Packets carry stream data in both directions.
+    Established,
+    /// A close was sent or received; incoming packets are still processed until the drain
+    /// period ends, but no new data is sent.
+    Draining,
+    /// The connection is finished and ignores all input.
+    Closed,
+}
+
+/// Close reported by [`Connection::close_reason`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionClose {
+    /// Application error code; zero conventionally means no error.
+    pub error_code: u64,
+    /// Human-readable reason, possibly truncated to fit one packet.
+    pub reason: String,
+    /// Whether the peer closed the connection rather than the local endpoint.
+    pub remote: bool,
+}
+
+/// Close in progress: what was reported and when draining ends.
+#[derive(Debug)]
+struct Closing {
+    info: ConnectionClose,
+    /// When the connection moves from draining to closed.
+    drain_until: SystemTime,
+    /// Our `CONNECTION_CLOSE` still has to go out (again).
+    frame_pending: bool,
+    closed: bool,
+}
+
+/// Activity tracking behind the idle timeout and keepalives, armed once established.
+#[derive(Debug)]
+struct IdleTimer {
+    /// Last receive, or first ack-eliciting send after it; the idle timeout counts from here.
+    since: SystemTime,
+    /// An ack-eliciting packet went out since the last receive.
+    sent_since_receive: bool,
+    /// Last ack-eliciting send; keepalives count from here.
+    last_sent: SystemTime,
+}
+
+impl IdleTimer {
+    const fn new(now: SystemTime) -> Self {
+        Self {
+            since: now,
+            sent_since_receive: false,
+            last_sent: now,
+        }
+    }
+
+    const fn on_receive(&mut self, now: SystemTime) {
+        self.since = now;
+        self.sent_since_receive = false;
+    }
+
+    /// Only the first send after a receive refreshes the timer, so retransmitting to a
+    /// silent peer cannot keep the connection open.
+    const fn on_ack_eliciting_sent(&mut self, now: SystemTime) {
+        if !self.sent_since_receive {
+            self.since = now;
+            self.sent_since_receive = true;
+        }
+        self.last_sent = now;
+    }
+}
+
+/// Handshake side of a connection, kept after completion to answer retransmitted flights.
+#[derive(Debug)]
+enum Handshake {
+    Client {
+        initiator: Box<Initiator>,
+        /// Finish message, resent if the responder retransmits its hello.
+        finish: Option<HandshakeMessage>,
+    },
+    Server {
+        server: Arc<Mutex<HandshakeServer>>,
+        pending: Box<PendingHandshake>,
+    },
+}
+
+/// One MXP connection: handshake, packet protection, loss recovery, congestion control,
+/// ACK generation, and streams.
+///
+/// Stream data queued before the handshake completes is sent once keys are available.
+/// Flow-control credit is not exchanged yet, so send windows stay unlimited regardless of
+/// the negotiated transport parameters.
+#[derive(Debug)]
+pub struct Connection {
+    config: ConnectionConfig,
+    handshake: Handshake,
+    cipher: Option<PacketCipher>,
+    parameters: Option<TransportParameters>,
+    loss: LossManager,
+    cc: Box<dyn CongestionControl>,
+    streams: StreamManager,
+    recv_history: ReceiveHistory,
+    /// Handshake messages waiting to be sent, oldest first.
+    handshake_queue: VecDeque<HandshakeMessage>,
+    /// Control frames waiting to be sent.
+    control: VecDeque<Frame>,
+    /// Control frames carried by each outstanding packet, queued again if it is lost.
+    sent_control: HashMap<u64, Vec<Frame>>,
+    /// The receive history asked for an ACK without waiting for the delay.
+    ack_now: bool,
+    closing: Option<Closing>,
+    idle: Option<IdleTimer>,
+    /// Stream carrying outgoing messages, opened on first use.
+    message_sink: Option<MessageSink>,
+    /// Decoders for the peer's message streams.
+    message_streams: HashMap<StreamId, BatchedReceiver>,
+    /// Received messages not yet taken by the application.
+    inbox: VecDeque<Message>,
+    /// Messages sent with [`send_acked`](Self::send_acked) awaiting the peer's ack.
+    awaiting_ack: RequestTracker,
+    /// Spin bit value for outgoing packets.
+    spin: bool,
+    pacer: Option<Pacer>,
+    /// When the pacer releases the ack-eliciting packet it is holding back.
+    paced_until: Option<SystemTime>,
+    /// Path MTU search, started once the handshake completes.
+    pmtu: Option<PmtuDiscovery>,
+    /// Largest datagram currently sent, type byte included.
+    max_datagram_size: usize,
+}
+
+impl Connection {
+    /// Start a client connection at `now`; its hello is the first datagram from
+    /// [`poll_transmit`](Self::poll_transmit).
+    pub fn connect(
+        config: ConnectionConfig,
+        mut initiator: Initiator,
+        now: SystemTime,
+    ) -> Result<Self, ConnectionError> {
+        let hello = initiator.initiate(now)?;
+        let mut connection = Self::new(
+            config,
+            EndpointRole::Client,
+            Handshake::Client {
+                initiator: Box::new(initiator),
+                finish: None,
+            },
+        );
+        connection.handshake_queue.push_back(hello);
+        Ok(connection)
+    }
+
+    /// Accept a client at `peer`, holding `peer_static`, whose first datagram is `datagram`,
+    /// received at `now`.
+    ///
+    /// `server` is shared by every connection it accepts, so session tickets, anti-replay,
+    /// retry, and admission state span them all. Errors such as
+    /// [`HandshakeError::RetryRequired`] leave no connection behind.
+    pub fn accept(
+        config: ConnectionConfig,
+        server: Arc<Mutex<HandshakeServer>>,
+        peer_static: &PublicKey,
+        peer: SocketAddr,
+        datagram: &[u8],
+        now: SystemTime,
+    ) -> Result<Self, ConnectionError> {
+        let hello = match datagram.split_first() {
+            Some((&HANDSHAKE_DATAGRAM, body)) => HandshakeMessage::decode(body)?,
+            _ => return Err(ConnectionError::Malformed),
+        };
+        let pending = lock(&server).accept_from(&hello, peer_static, peer, now)?;
+        let response = pending.hello().clone();
+        let mut connection = Self::new(
+            config,
+            EndpointRole::Server,
+            Handshake::Server {
+                server,
+                pending: Box::new(pending),
+            },
+        );
+        connection.handshake_queue.push_back(response);
+        Ok(connection)
+    }
+
+    fn new(config: ConnectionConfig, role: EndpointRole, handshake: Handshake) -> Self {
+        Self {
+            handshake,
+            cipher: None,
+            parameters: None,
+            loss: LossManager::new(config.loss.clone()),
+            cc: config.congestion.clone().build(),
+            streams: StreamManager::new(role),
+            recv_history: ReceiveHistory::new(DEFAULT_MAX_ACK_RANGES, config.ack_delay),
+            handshake_queue: VecDeque::new(),
+            control: VecDeque::new(),
+            sent_control: HashMap::new(),
+            ack_now: false,
+            closing: None,
+            idle: None,
+            message_sink: None,
+            message_streams: HashMap::new(),
+            inbox: VecDeque::new(),
+            awaiting_ack: RequestTracker::default(),
+            spin: false,
+            pacer: config.pacing.clone().map(Pacer::new),
+            paced_until: None,
+            pmtu: None,
+            max_datagram_size: config.max_datagram_size,
+            config,
+        }
+    }
+
+    /// Whether the handshake completed and packets can be protected.
+    #[must_use]
+    pub const fn is_established(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Current lifecycle stage.
+    #[must_use]
+    pub const fn state(&self) -> ConnectionState {
+        match &self.closing {
+            Some(closing) if closing.closed => ConnectionState::Closed,
+            Some(_) => ConnectionState::Draining,
+            None if self.cipher.is_some() => ConnectionState::Established,
+            None => ConnectionState::Handshaking,
+        }
+    }
+
+    /// Why the connection is closing or closed, if it is.
+    #[must_use]
+    pub fn close_reason(&self) -> Option<&ConnectionClose> {
+        self.closing.as_ref().map(|closing| &closing.info)
+    }
+
+    /// Close the connection with an application `error_code` and `reason`.
+    ///
+    /// A `CONNECTION_CLOSE` frame carrying both goes out on the next
+    /// [`poll_transmit`](Self::poll_transmit), and is repeated if the peer keeps sending;
+    /// queued stream data is abandoned and new streams are refused. The connection drains
+    /// for three probe timeouts before it is closed. Before the handshake completes there
+    /// are no keys to protect the frame, so the connection closes at once and the peer
+    /// times out. Closing twice keeps the first reason.
+    pub fn close(&mut self, error_code: u64, reason: &str, now: SystemTime) {
+        if self.closing.is_some() {
+            return;
+        }
+        let budget = self.payload_budget().saturating_sub(FRAME_HEADER_LEN + 8);
+        let mut end = reason.len().min(budget);
+        while !reason.is_char_boundary(end) {
+            end -= 1;
+        }
+        debug!(error_code, reason, "closing connection");
+        self.enter_draining(
+            ConnectionClose {
+                error_code,
+                reason: reason[..end].to_owned(),
+                remote: false,
+            },
+            now,
+        );
+    }
+
+    fn enter_draining(&mut self, info: ConnectionClose, now: SystemTime) {
+        let established = self.is_established();
+        self.closing = Some(Closing {
+            frame_pending: !info.remote && established,
+            closed: !established,
+            drain_until: now + self.drain_period(),
+            info,
+        });
+        self.handshake_queue.clear();
+        self.control.clear();
+        self.streams.begin_drain();
+    }
+
+    /// Idle timeout in force: the smaller of the configured and negotiated values, ignoring
+    /// a zero negotiated value, and never under three probe timeouts.
+    fn idle_timeout(&self) -> Option<Duration> {
+        let negotiated = self
+            .parameters
+            .as_ref()
+            .map(|parameters| parameters.max_idle_timeout)
+            .filter(|timeout| !timeout.is_zero());
+        let timeout = match (self.config.max_idle_timeout, negotiated) {
+            (Some(local), Some(negotiated)) => Some(local.min(negotiated)),
+            (local, negotiated) => local.or(negotiated),
+        }?;
+        Some(timeout.max(self.drain_period()))
+    }
+
+    fn idle_deadline(&self) -> Option<SystemTime> {
+        Some(self.idle.as_ref()?.since + self.idle_timeout()?)
+    }
+
+    /// When a keepalive `PING` is due; `None` while one is already queued.
+    fn keep_alive_deadline(&self) -> Option<SystemTime> {
+        let interval = self.config.keep_alive_interval?;
+        let idle = self.idle.as_ref()?;
+        let queued = self
+            .control
+            .iter()
+            .any(|frame| frame.frame_type() == FrameType::Ping);
+        (!queued).then_some(idle.last_sent + interval)
+    }
+
+    /// Three probe timeouts, the time the peer may keep retransmitting before it notices.
+    fn drain_period(&self) -> Duration {
+        let rtt = self
+            .loss
+            .smoothed_rtt()
+            .unwrap_or(self.config.loss.initial_rtt);
+        let variance = self.loss.rtt_variance().unwrap_or(rtt / 2);
+        (rtt + variance * 4 + self.config.loss.max_ack_delay) * 3
+    }
+
+    /// Transport parameters agreed during the handshake.
+    #[must_use]
+    pub const fn negotiated_parameters(&self) -> Option<&TransportParameters> {
+        self.parameters.as_ref()
+    }
+
+    /// Open the next locally initiated bidirectional stream.
+    pub fn open_stream(&mut self) -> Result<StreamId, StreamError> {
+        self.streams.open_bidirectional()
+    }
+
+    /// Queue `data` on stream `id`.
+    pub fn send(&mut self, id: StreamId, data: &[u8]) -> Result<(), StreamError> {
+        self.streams.queue_send(id, data)
+    }
+
+    /// Queue the FIN on stream `id` after the data already sent.
+    pub fn finish(&mut self, id: StreamId) -> Result<(), StreamError> {
+        self.streams.finish(id)
+    }
+
+    /// Read up to `max_len` in-order bytes received on stream `id`.
+    pub fn read(&mut self, id: StreamId, max_len: usize) -> Result<Vec<u8>, StreamError> {
+        self.streams.read(id, max_len)
+    }
+
+    /// Queue `message` on this side's message stream.
+    pub fn send_message(&mut self, message: &Message) -> Result<(), StreamError> {
+        let sink = match self.message_sink {
+            Some(sink) => sink,
+            None => *self.message_sink.insert(self.streams.open_message_sink()?),
+        };
+        sink.send(&mut self.streams, message)
+    }
+
+    /// Send `message` flagged `REQUIRES_ACK` and track the peer's ack until `now + timeout`.
+    ///
+    /// Returns the `message_id` to pass to [`poll_ack`](Self::poll_ack).
+    pub fn send_acked(
+        &mut self,
+        mut message: Message,
+        timeout: Duration,
+        now: SystemTime,
+    ) -> Result<u64, ConnectionError> {
+        let message_id = message.message_id();
+        message.set_flags(message.flags().with(protocol::Flags::REQUIRES_ACK));
+        self.awaiting_ack
+            .register_until(message_id, now + timeout)?;
+        if let Err(err) = self.send_message(&message) {
+            self.awaiting_ack.cancel(message_id);
+            return Err(err.into());
+        }
+        Ok(message_id)
+    }
+
+    /// Check whether the peer acknowledged a message sent with
+    /// [`send_acked`](Self::send_acked).
+    ///
+    /// Returns `Ok(false)` while waiting and `Ok(true)` once acked; a missed deadline yields
+    /// [`protocol::Error::DeadlineExceeded`]. Either outcome stops tracking the message.
+    pub fn poll_ack(&mut self, message_id: u64, now: SystemTime) -> Result<bool, ConnectionError> {
+        match self.awaiting_ack.poll(message_id, now) {
+            Some(RequestStatus::Pending) => Ok(false),
+            Some(RequestStatus::Complete(_)) => Ok(true),
+            Some(RequestStatus::TimedOut) => {
+                Err(protocol::Error::DeadlineExceeded { message_id }.into())
+            }
+            None => Err(ConnectionError::NotAwaitingAck(message_id)),
+        }
+    }
+
+    /// Next message received from the peer, oldest first.
+    pub fn recv_message(&mut self) -> Option<Message> {
+        self.inbox.pop_front()
+    }
+
+    /// Streams backing this connection, for operations not wrapped here.
+    #[must_use]
+    pub const fn streams(&self) -> &StreamManager {
+        &self.streams
+    }
+
+    /// Mutable access to the streams backing this connection.
+    ///
+    /// Frames returned by stream operations (such as
+    /// [`StreamManager::stop_sending`]) must be passed to
+    /// [`queue_frame`](Self::queue_frame) to reach the peer.
+    pub const fn streams_mut(&mut self) -> &mut StreamManager {
+        &mut self.streams
+    }
+
+    /// Queue a `PING`, unless one is already waiting; the peer's ACK counts as activity
+    /// for both idle timers.
+    pub fn ping(&mut self) {
+        if !self
+            .control
+            .iter()
+            .any(|frame| frame.frame_type() == FrameType::Ping)
+        {
+            self.control.push_back(Frame::ping());
+        }
+    }
+
+    /// Send a control frame, resending it if the packet carrying it is lost.
+    pub fn queue_frame(&mut self, frame: Frame) {
+        self.control.push_back(frame);
+    }
+
+    /// Loss detector and RTT estimates.
+    #[must_use]
+    pub const fn loss(&self) -> &LossManager {
+        &self.loss
+    }
+
+    /// Largest datagram [`poll_transmit`](Self::poll_transmit) currently returns: the
+    /// configured `max_datagram_size`, raised by each acknowledged path MTU probe.
+    #[must_use]
+    pub const fn current_mtu(&self) -> usize {
+        self.max_datagram_size
+    }
+
+    /// Congestion controller in use.
+    #[must_use]
+    pub fn congestion(&self) -> &dyn CongestionControl {
+        self.cc.as_ref()
+    }
+
+    /// Earliest time [`handle_timeout`](Self::handle_timeout) has work to do: a handshake
+    /// retransmission, the loss timer, a delayed ACK, the idle timeout, or a keepalive. Also
+    /// the time the pacer releases a held-back packet for
+    /// [`poll_transmit`](Self::poll_transmit).
+    #[must_use]
+    pub fn poll_timeout(&self) -> Option<SystemTime> {
+        if let Some(closing) = &self.closing {
+            return (!closing.closed).then_some(closing.drain_until);
+        }
+        let handshake = match &self.handshake {
+            Handshake::Client { initiator, .. } => initiator.deadline(),
+            Handshake::Server { pending, .. } => pending.deadline(),
+        };
+        [
+            handshake,
+            self.loss.loss_time(),
+            self.recv_history.ack_deadline(),
+            self.idle_deadline(),
+            self.keep_alive_deadline(),
+            self.paced_until,
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+    }
+
+    /// Run expired timers: retransmit the last handshake flight, declare timed-out packets
+    /// lost, queue a keepalive `PING`, or close an idle connection. Delayed ACKs go out on
+    /// the next [`poll_transmit`](Self::poll_transmit). A draining connection only waits
+    /// for the end of its drain period.
+    pub fn handle_timeout(&mut self, now: SystemTime) -> Result<(), ConnectionError> {
+        if let Some(closing) = &mut self.closing {
+            if !closing.closed && closing.drain_until <= now {
+                debug!("connection closed");
+                closing.closed = true;
+            }
+            return Ok(());
+        }
+        if self.idle_deadline().is_some_and(|deadline| deadline <= now) {
+            debug!("idle timeout expired, closing connection");
+            self.enter_draining(
+                ConnectionClose {
+                    error_code: 0,
+                    reason: IDLE_TIMEOUT_REASON.to_owned(),
+                    remote: false,
+                },
+                now,
+            );
+            if let Some(closing) = &mut self.closing {
+                // The peer's idle timer expires too; nothing is sent.
+                closing.frame_pending = false;
+                closing.closed = true;
+            }
+            return Ok(());
+        }
+        if self
+            .keep_alive_deadline()
+            .is_some_and(|deadline| deadline <= now)
+        {
+            self.ping();
+        }
+        let flight = match &mut self.handshake {
+            Handshake::Client { initiator, .. } => initiator.poll_timeout(now)?,
+            Handshake::Server { pending, .. } => pending.poll_timeout(now)?,
+        };
+        if let Some(flight) = flight {
+            debug!(kind = ?flight.kind(), "retransmitting handshake flight");
+            self.handshake_queue.push_back(flight);
+        }
+
+        let mut lost = self.loss.on_loss_timeout(now);
+        if !lost.is_empty() {
+            self.on_packets_lost(&lost);
+            self.settle_probes(&mut Vec::new(), &mut lost);
+            self.cc.on_packets_lost(&lost, now);
+        }
+        Ok(())
+    }
+
+    /// Spin bit of a packet datagram, readable without keys as an on-path observer would.
+    ///
+    /// `None` for handshake datagrams and truncated headers.
+    #[must_use]
+    pub fn observed_spin(datagram: &[u8]) -> Option<bool> {
+        let Some((&PACKET_DATAGRAM, packet)) = datagram.split_first() else {
+            return None;
+        };
+        PacketHeader::peek_spin(packet).ok()
+    }
+
+    /// Process one datagram received from the peer.
+    ///
+    /// Protected packets arriving before the handshake completes are dropped; the peer
+    /// resends their contents once they are declared lost. Closed connections ignore
+    /// every datagram.
+    pub fn handle_datagram(
+        &mut self,
+        datagram: &[u8],
+        now: SystemTime,
+    ) -> Result<(), ConnectionError> {
+        if self.state() == ConnectionState::Closed {
+            return Ok(());
+        }
+        match datagram.split_first() {
+            Some((&HANDSHAKE_DATAGRAM, body)) => {
+                let message = HandshakeMessage::decode(body)?;
+                self.on_handshake_message(&message, now)
+            }
+            Some((&PACKET_DATAGRAM, body)) => self.on_packet(body, now),
+            _ => Err(ConnectionError::Malformed),
+        }
+    }
+
+    fn on_handshake_message(
+        &mut self,
+        message: &HandshakeMessage,
+        now: SystemTime,
+    ) -> Result<(), ConnectionError> {
+        let keys = match &mut self.handshake {
+            Handshake::Client { initiator, finish } => match message.kind() {
+                HandshakeMessageKind::ResponderHello | HandshakeMessageKind::Refused
+                    if finish.is_none() =>
+                {
+                    let (message, keys) = initiator.handle_response(message, now)?;
+                    self.handshake_queue.push_back(message.clone());
+                    *finish = Some(message);
+                    Some((keys, initiator.negotiated_parameters().cloned()))
+                }
+                HandshakeMessageKind::ResponderHello => {
+                    // Our finish was lost and the responder is retransmitting its hello.
+                    self.handshake_queue.extend(finish.clone());
+                    None
+                }
+                HandshakeMessageKind::Retry if finish.is_none() => {
+                    let hello = initiator.handle_retry(message, now)?;
+                    self.handshake_queue.push_back(hello);
+                    None
+                }
+                kind => {
+                    trace!(?kind, "ignoring handshake message");
+                    None
+                }
+            },
+            Handshake::Server { server, pending } => match message.kind() {
+                _ if self.cipher.is_some() => None,
+                HandshakeMessageKind::InitiatorHello => {
+                    // Our hello was lost and the initiator is retransmitting its own.
+                    self.handshake_queue.push_back(pending.hello().clone());
+                    None
+                }
+                HandshakeMessageKind::InitiatorFinish => {
+                    let outcome =
+                        pending.handle_initiator_finish(&mut lock(server), message, now)?;
+                    Some((outcome.session_keys, Some(outcome.transport_parameters)))
+                }
+                kind => {
+                    trace!(?kind, "ignoring handshake message");
+                    None
+                }
+            },
+        };
+        if let Some((keys, parameters)) = keys {
+            self.establish(keys, parameters, now);
+        }
+        Ok(())
+    }
+
+    fn establish(
+        &mut self,
+        keys: SessionKeys,
+        parameters: Option<TransportParameters>,
+        now: SystemTime,
+    ) {
+        let mut cipher = PacketCipher::new(keys);
+        if let Some(parameters) = &parameters {
+            cipher = cipher.with_aead_cipher(parameters.aead_cipher());
+        }
+        debug!(aead = ?cipher.aead_cipher(), "connection established");
+        self.cipher = Some(cipher);
+        self.pmtu = self.config.pmtu.clone().map(|mut pmtu| {
+            pmtu.base_mtu = pmtu.base_mtu.max(self.config.max_datagram_size);
+            if let Some(parameters) = &parameters {
+                let peer_max =
+                    usize::try_from(parameters.max_udp_payload_size).unwrap_or(usize::MAX);
+                pmtu.max_mtu = pmtu.max_mtu.min(peer_max);
+            }
+            PmtuDiscovery::new(pmtu)
+        });
+        self.parameters = parameters;
+        self.idle = Some(IdleTimer::new(now));
+    }
+
+    fn on_packet(&mut self, body: &[u8], now: SystemTime) -> Result<(), ConnectionError> {
+        let Some(cipher) = self.cipher.as_mut() else {
+            trace!("dropping protected packet received before the handshake completed");
+            return Ok(());
+        };
+        let packet = cipher.open(body)?;
+        if let Some(idle) = &mut self.idle {
+            idle.on_receive(now);
+        }
+        let frames = Frame::decode_all(packet.payload()).map_err(|_| ConnectionError::Malformed)?;
+        let header = packet.header();
+        // The cipher only opens packets above the highest number seen, so every packet
+        // here is the newest: the server echoes its spin bit and the client inverts it.
+        self.spin = match self.role() {
+            EndpointRole::Client => !header.spin(),
+            EndpointRole::Server => header.spin(),
+        };
+        let ack_eliciting = header.flags().contains(PacketFlags::ACK_ELICITING);
+        self.ack_now |= self
+            .recv_history
+            .record(header.packet_number(), ack_eliciting, now);
+        if let Some(closing) = &mut self.closing {
+            // The peer has not seen our close yet; repeat it.
+            closing.frame_pending |= ack_eliciting && !closing.info.remote;
+        }
+        for frame in &frames {
+            self.on_frame(frame, now)?;
+        }
+        self.read_messages()
+    }
+
+    /// Decode messages arriving on the peer's unidirectional streams.
+    fn read_messages(&mut self) -> Result<(), ConnectionError> {
+        let role = self.role();
+        for id in self.streams.readable_streams() {
+            if id.kind() != StreamKind::Unidirectional || id.is_local_initiated(role) {
+                continue;
+            }
+            let receiver = match self.message_streams.entry(id) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(self.streams.recv_batched(id)?),
+            };
+            for message in receiver.poll(&mut self.streams)? {
+                self.on_message(message)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_message(&mut self, message: Message) -> Result<(), StreamError> {
+        if protocol::is_delivery_ack(&message) {
+            let message_id = message.message_id();
+            if !self.awaiting_ack.complete(message) {
+                trace!(message_id, "ignoring duplicate or unexpected ack");
+            }
+            return Ok(());
+        }
+        if let Some(ack) = protocol::delivery_ack(&message) {
+            self.send_message(&ack)?;
+        }
+        self.inbox.push_back(message);
+        Ok(())
+    }
+
+    const fn role(&self) -> EndpointRole {
+        match self.handshake {
+            Handshake::Client { .. } => EndpointRole::Client,
+            Handshake::Server { .. } => EndpointRole::Server,
+        }
+    }
+
+    fn on_frame(&mut self, frame: &Frame, now: SystemTime) -> Result<(), ConnectionError> {
+        match frame.frame_type() {
+            FrameType::Ack => {
+                let ack = frame.decode_ack().map_err(|_| ConnectionError::Malformed)?;
+                let mut outcome = self.loss.on_ack_frame(&ack, now);
+                for info in &outcome.acknowledged {
+                    self.streams.on_packet_acked(info.packet_number());
+                    self.sent_control.remove(&info.packet_number());
+                }
+                self.on_packets_lost(&outcome.lost);
+                self.settle_probes(&mut outcome.acknowledged, &mut outcome.lost);
+                self.cc.on_ack_outcome(&outcome, now);
+            }
+            FrameType::ConnectionClose => {
+                let (error_code, reason) = frame
+                    .decode_connection_close()
+                    .map_err(|_| ConnectionError::Malformed)?;
+                debug!(error_code, reason, "peer closed connection");
+                if self.closing.is_none() {
+                    self.enter_draining(
+                        ConnectionClose {
+                            error_code,
+                            reason: reason.to_owned(),
+                            remote: true,
+                        },
+                        now,
+                    );
+                }
+            }
+            FrameType::StreamData => {
+                let (id, offset, data, fin) = frame
+                    .decode_stream_data()
+                    .map_err(|_| ConnectionError::Malformed)?;
+                self.streams.ingest(id, offset, data, fin)?;
+            }
+            FrameType::StreamExpired => {
+                let (id, offset) = frame
+                    .decode_stream_expired()
+                    .map_err(|_| ConnectionError::Malformed)?;
+                self.streams.on_stream_expired(id, offset)?;
+            }
+            FrameType::StopSending => {
+                let (id, code) = frame
+                    .decode_stop_sending()
+                    .map_err(|_| ConnectionError::Malformed)?;
+                self.streams.on_stop_sending(id, code)?;
+            }
+            FrameType::StreamMaxData => {
+                let (id, limit) = frame
+                    .decode_stream_max_data()
+                    .map_err(|_| ConnectionError::Malformed)?;
+                self.streams.set_stream_limit(id, limit);
+            }
+            FrameType::ConnectionMaxData => {
+                let limit = frame
+                    .decode_connection_max_data()
+                    .map_err(|_| ConnectionError::Malformed)?;
+                self.streams.set_connection_limit(limit);
+            }
+            FrameType::Ping => trace!("ping received"),
+            frame_type => trace!(?frame_type, "ignoring frame"),
+        }
+        Ok(())
+    }
+
+    fn on_packets_lost(&mut self, lost: &[SentPacketInfo]) {
+        for info in lost {
+            self.streams.on_packet_lost(info.packet_number());
+            if let Some(frames) = self.sent_control.remove(&info.packet_number()) {
+                self.control.extend(frames);
+            }
+        }
+    }
+
+    /// Pass acknowledged and lost path MTU probes to the search, removing them from
+    /// `acknowledged` and `lost`: the congestion controller never counted them, and a lost
+    /// probe says nothing about congestion.
+    fn settle_probes(
+        &mut self,
+        acknowledged: &mut Vec<SentPacketInfo>,
+        lost: &mut Vec<SentPacketInfo>,
+    ) {
+        let Some(pmtu) = &mut self.pmtu else {
+            return;
+        };
+        acknowledged.retain(|info| !pmtu.on_packet_acked(info.packet_number()));
+        lost.retain(|info| !pmtu.on_packet_lost(info.packet_number()));
+        self.max_datagram_size = self.max_datagram_size.max(pmtu.current_mtu());
+    }
+
+    /// Next datagram to send, if any.
+    ///
+    /// Handshake messages go first. Once established, a due path MTU probe goes next as a
+    /// packet of its own; otherwise a packet carries a due ACK, then control frames and
+    /// stream data as far as the congestion window allows. A draining connection only sends
+    /// its `CONNECTION_CLOSE`.
+    pub fn poll_transmit(&mut self, now: SystemTime) -> Option<Vec<u8>> {
+        if let Some(closing) = &mut self.closing {
+            if !closing.frame_pending || closing.closed {
+                return None;
+            }
+            closing.frame_pending = false;
+            let mut payload = Vec::new();
+            Frame::connection_close(closing.info.error_code, &closing.info.reason)
+                .encode(&mut payload)
+                .ok()?;
+            let (_, datagram) = self.seal(PacketFlags::default(), &payload)?;
+            return Some(datagram);
+        }
+        if let Some(message) = self.handshake_queue.pop_front() {
+            let mut datagram = vec![HANDSHAKE_DATAGRAM];
+            datagram.extend_from_slice(&message.encode());
+            return Some(datagram);
+        }
+        self.cipher.as_ref()?;
+        if let Some(probe) = self.poll_probe(now) {
+            return Some(probe);
+        }
+
+        let budget = self.payload_budget();
+        let mut payload = Vec::new();
+        let ack_due = self.ack_now
+            || self
+                .recv_history
+                .ack_deadline()
+                .is_some_and(|deadline| deadline <= now);
+        if ack_due {
+            if let Ok(Some(ack)) = self.recv_history.build_frame(now) {
+                let frame = Frame::from_ack(&ack);
+                if frame.encoded_len() <= budget {
+                    frame.encode(&mut payload).ok()?;
+                }
+            }
+            self.ack_now = false;
+        }
+
+        let mut window = if self.pacing_holds_back(now) {
+            0
+        } else {
+            self.cc.available_window()
+        };
+        if let Some(frame) = self.streams.poll_streams_blocked() {
+            self.control.push_back(frame);
+        }
+        let mut control = Vec::new();
+        while let Some(frame) = self.control.front() {
+            let len = frame.encoded_len();
+            if payload.len() + len > budget || len > window {
+                break;
+            }
+            window -= len;
+            let frame = self.control.pop_front()?;
+            frame.encode(&mut payload).ok()?;
+            control.push(frame);
+        }
+
+        let mut chunks: Vec<(StreamId, SendChunk)> = Vec::new();
+        loop {
+            let room = budget
+                .saturating_sub(payload.len() + FRAME_HEADER_LEN + STREAM_DATA_OVERHEAD)
+                .min(window);
+            if room == 0 {
+                break;
+            }
+            let (id, chunk) = match self.streams.poll_any_send_chunk(room) {
+                Ok(Some(next)) => next,
+                Ok(None) => break,
+                Err(err) => {
+                    debug!(%err, "stream data blocked by flow control");
+                    break;
+                }
+            };
+            let frame = Frame::stream_data(id, &chunk);
+            window = window.saturating_sub(frame.encoded_len());
+            frame.encode(&mut payload).ok()?;
+            chunks.push((id, chunk));
+        }
+
+        if payload.is_empty() {
+            return None;
+        }
+        let ack_eliciting = !control.is_empty() || !chunks.is_empty();
+        let flags = PacketFlags::from_bits(if ack_eliciting {
+            PacketFlags::ACK_ELICITING
+        } else {
+            PacketFlags::ACK
+        });
+        let (packet_number, datagram) = self.seal(flags, &payload)?;
+        let len = datagram.len() - 1;
+
+        if ack_eliciting {
+            self.on_ack_eliciting_sent(packet_number, len, &chunks, control, now);
+        }
+        trace!(packet_number, len, ack_eliciting, "packet sent");
+        Some(datagram)
+    }
+
+    /// A padded probe of the next size path MTU discovery wants to try, if the congestion
+    /// window and pacer leave room for it.
+    fn poll_probe(&mut self, now: SystemTime) -> Option<Vec<u8>> {
+        let size = self.pmtu.as_ref()?.next_probe_size()?;
+        if self.pacing_holds_back(now) || size > self.cc.available_window() {
+            return None;
+        }
+        let overhead = 1
+            + PacketHeader::max_len(self.config.connection_id.len())
+            + AEAD_TAG_LEN
+            + FRAME_HEADER_LEN;
+        let mut payload = Vec::new();
+        PmtuDiscovery::probe_frame(size, overhead)
+            .encode(&mut payload)
+            .ok()?;
+        let flags = PacketFlags::from_bits(PacketFlags::ACK_ELICITING);
+        let (packet_number, datagram) = self.seal(flags, &payload)?;
+        let len = datagram.len() - 1;
+
+        // Loss recovery tracks the probe so its fate reaches the search, but the congestion
+        // controller never sees it.
+        if let Some(idle) = &mut self.idle {
+            idle.on_ack_eliciting_sent(now);
+        }
+        self.loss.on_packet_sent(packet_number, now, len, true);
+        if let Some(pacer) = &mut self.pacer {
+            pacer.on_packet_sent(len, now);
+        }
+        if let Some(pmtu) = &mut self.pmtu {
+            pmtu.on_probe_sent(packet_number, datagram.len());
+        }
+        trace!(packet_number, size = datagram.len(), "path MTU probe sent");
+        Some(datagram)
+    }
+
+    /// Account for an ack-eliciting packet of `len` bytes carrying `chunks` and `control`.
+    fn on_ack_eliciting_sent(
+        &mut self,
+        packet_number: u64,
+        len: usize,
+        chunks: &[(StreamId, SendChunk)],
+        control: Vec<Frame>,
+        now: SystemTime,
+    ) {
+        if let Some(idle) = &mut self.idle {
+            idle.on_ack_eliciting_sent(now);
+        }
+        self.loss.on_packet_sent(packet_number, now, len, true);
+        self.cc.on_packet_sent(len);
+        if let Some(pacer) = &mut self.pacer {
+            pacer.on_packet_sent(len, now);
+        }
+        for (id, chunk) in chunks {
+            // The stream handed out this chunk just now, so it exists.
+            let _ = self.streams.on_chunk_sent(*id, chunk, packet_number);
+        }
+        if !control.is_empty() {
+            self.sent_control.insert(packet_number, control);
+        }
+    }
+
+    /// Whether the pacer keeps ack-eliciting frames out of a packet sent at `now`, recording
+    /// when it will let them go if any are waiting.
+    fn pacing_holds_back(&mut self, now: SystemTime) -> bool {
+        self.paced_until = None;
+        let Some(pacer) = &mut self.pacer else {
+            return false;
+        };
+        pacer.set_rate(self.cc.pacing_rate(), now);
+        let Some(release) = pacer.next_send_time(self.max_datagram_size, now) else {
+            return false;
+        };
+        if !self.control.is_empty() || self.streams.has_pending_data() {
+            trace!(?release, "pacing holds back ack-eliciting frames");
+            self.paced_until = Some(release);
+        }
+        true
+    }
+
+    /// Frame bytes that fit in one packet.
+    fn payload_budget(&self) -> usize {
+        self.max_datagram_size.saturating_sub(
+            1 + PacketHeader::max_len(self.config.connection_id.len()) + AEAD_TAG_LEN,
+        )
+    }
+
+    /// Protect `payload` into a packet datagram, returning its packet number.
+    fn seal(&mut self, flags: PacketFlags, payload: &[u8]) -> Option<(u64, Vec<u8>)> {
+        let cipher = self.cipher.as_mut()?;
+        cipher.set_spin(self.spin && self.config.spin_bit);
+        cipher.set_largest_acked(self.loss.largest_acked());
+        let conn_id = &self.config.connection_id;
+        let mut datagram =
+            vec![0u8; 1 + PacketHeader::max_len(conn_id.len()) + payload.len() + AEAD_TAG_LEN];
+        datagram[0] = PACKET_DATAGRAM;
+        let (packet_number, len) = cipher
+            .seal_into(conn_id, flags, payload, &mut datagram[1..])
+            .ok()?;
+        datagram.truncate(1 + len);
+        Some((packet_number, datagram))
+    }
+}
+
+fn lock(server: &Mutex<HandshakeServer>) -> MutexGuard<'_, HandshakeServer> {
+    server.lock().unwrap_or_else(PoisonError::into_inner)
+}
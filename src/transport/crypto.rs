@@ -18,28 +18,260 @@ pub const HEADER_PROTECTION_KEY_LEN: usize = 32;
 pub const HEADER_PROTECTION_SAMPLE_LEN: usize = 16;
 /// Length of the derived header protection mask (1 byte for flags, 8 for packet number).
 pub const HEADER_PROTECTION_MASK_LEN: usize = 9;
+/// Length of the handshake transcript hash (SHA-256 or BLAKE3) in bytes.
+pub const TRANSCRIPT_HASH_LEN: usize = 32;
+/// Length of an HKDF pseudorandom key in bytes.
+pub const HKDF_PRK_LEN: usize = 32;
+/// Length of identity public keys (Ed25519) in bytes.
+pub const IDENTITY_KEY_LEN: usize = ed25519::PUBLIC_KEY_LEN;
+/// Length of identity seeds (Ed25519 secret keys) in bytes.
+pub const IDENTITY_SEED_LEN: usize = ed25519::SEED_LEN;
+/// Length of identity signatures (Ed25519) in bytes.
+pub const SIGNATURE_LEN: usize = ed25519::SIGNATURE_LEN;
+
+/// Protocol labels absorbed as the initial transcript hash, one per hash algorithm.
+const PROTOCOL_NAME: &[u8] = b"MXP_IK_25519_ChaChaPoly_SHA256";
+const PROTOCOL_NAME_BLAKE3: &[u8] = b"MXP_IK_25519_ChaChaPoly_BLAKE3";
+/// HKDF info labels for the per-direction traffic secrets (initiator-to-responder and back).
+const TRAFFIC_I2R_INFO: &[u8] = b"mxp traffic i2r";
+const TRAFFIC_R2I_INFO: &[u8] = b"mxp traffic r2i";
+/// HKDF info labels expanding a traffic secret into its packet protection keys.
+const KEY_INFO: &[u8] = b"mxp key";
+const IV_INFO: &[u8] = b"mxp iv";
+const HP_INFO: &[u8] = b"mxp hp";
+/// HKDF info label used when expanding 0-RTT keys from a resumption secret.
+const EARLY_DATA_INFO: &[u8] = b"mxp early data";
 
 /// Error type for cryptographic operations.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
 pub enum CryptoError {
     /// Key material of unexpected length.
+    #[error("invalid key length")]
     InvalidKeyLength,
     /// Nonce value has invalid length.
+    #[error("invalid nonce length")]
     InvalidNonceLength,
     /// Authentication tag has invalid length.
+    #[error("invalid authentication tag length")]
     InvalidTagLength,
     /// Authentication failure during decryption.
+    #[error("authentication failed")]
     AuthenticationFailed,
     /// HKDF expansion failure.
+    #[error("key derivation failed")]
     KeyDerivationFailed,
+    /// Signature did not verify against the claimed identity key.
+    #[error("invalid signature")]
+    InvalidSignature,
 }
 
 mod aead;
+mod aes_gcm;
+mod blake3;
 mod chacha20;
+mod ed25519;
 mod hkdf;
 mod hmac;
 mod poly1305;
 mod sha256;
+mod sha512;
+
+pub use aead::{AeadDecryptor, AeadEncryptor};
+
+/// Hash function used for the handshake transcript and HKDF key schedule.
+///
+/// Both peers must select the same algorithm; the protocol label differs per algorithm, so a
+/// mismatch fails the handshake with [`CryptoError::AuthenticationFailed`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// SHA-256 with HKDF-SHA256.
+    #[default]
+    Sha256,
+    /// BLAKE3 (32-byte output) with HKDF over HMAC-BLAKE3.
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// Hash `data` with this algorithm.
+    #[must_use]
+    pub fn digest(self, data: &[u8]) -> [u8; TRANSCRIPT_HASH_LEN] {
+        match self {
+            Self::Sha256 => sha256::Sha256::digest(data),
+            Self::Blake3 => blake3::Blake3::digest(data),
+        }
+    }
+
+    const fn protocol_name(self) -> &'static [u8] {
+        match self {
+            Self::Sha256 => PROTOCOL_NAME,
+            Self::Blake3 => PROTOCOL_NAME_BLAKE3,
+        }
+    }
+}
+
+/// AEAD cipher suite protecting packet payloads.
+///
+/// Both suites use 32-byte keys, 12-byte nonces, and 16-byte tags, so session keys are
+/// derived identically; the suite is agreed through the transport parameters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum AeadCipher {
+    /// ChaCha20-Poly1305 (RFC 8439); mandatory for every endpoint.
+    #[default]
+    ChaCha20Poly1305,
+    /// AES-256-GCM (NIST SP 800-38D).
+    ///
+    /// Portable software AES without AES-NI. Its S-box is a lookup table indexed by secret
+    /// bytes, so it is **not constant-time** and can leak key bits to cache-timing attacks
+    /// from code sharing the CPU; prefer [`ChaCha20Poly1305`](Self::ChaCha20Poly1305) where
+    /// that threat applies.
+    Aes256Gcm,
+}
+
+impl AeadCipher {
+    const fn bit(self) -> u8 {
+        match self {
+            Self::ChaCha20Poly1305 => 1 << 0,
+            Self::Aes256Gcm => 1 << 1,
+        }
+    }
+
+    /// Encrypt `plaintext`, authenticating `aad`.
+    #[must_use]
+    pub fn seal(
+        self,
+        key: &AeadKey,
+        nonce: &AeadNonce,
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> (Vec<u8>, AeadTag) {
+        match self {
+            Self::ChaCha20Poly1305 => aead::seal(key, nonce, plaintext, aad),
+            Self::Aes256Gcm => aes_gcm::seal(key, nonce, plaintext, aad),
+        }
+    }
+
+    /// Decrypt `ciphertext`, verifying `tag` over it and `aad`.
+    pub fn open(
+        self,
+        key: &AeadKey,
+        nonce: &AeadNonce,
+        ciphertext: &[u8],
+        aad: &[u8],
+        tag: &AeadTag,
+    ) -> Result<Vec<u8>, CryptoError> {
+        match self {
+            Self::ChaCha20Poly1305 => aead::open(key, nonce, ciphertext, aad, tag),
+            Self::Aes256Gcm => aes_gcm::open(key, nonce, ciphertext, aad, tag),
+        }
+    }
+}
+
+/// Set of AEAD cipher suites an endpoint supports.
+///
+/// The default set holds ChaCha20-Poly1305 only. Endpoints bound to a compliance regime may
+/// advertise [`only`](Self::only) AES-256-GCM; a handshake with a peer sharing no suite
+/// fails with [`HandshakeError::NoCommonCipherSuite`](super::HandshakeError::NoCommonCipherSuite).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AeadCipherSet(u8);
+
+impl AeadCipherSet {
+    /// Every suite this implementation supports.
+    pub const ALL: Self = Self(AeadCipher::ChaCha20Poly1305.bit() | AeadCipher::Aes256Gcm.bit());
+
+    /// Construct from a raw bitmask; unknown bits are dropped.
+    #[must_use]
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits & Self::ALL.0)
+    }
+
+    /// Set holding just `cipher`.
+    #[must_use]
+    pub const fn only(cipher: AeadCipher) -> Self {
+        Self(cipher.bit())
+    }
+
+    /// Raw bitmask.
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Add `cipher` to the set.
+    #[must_use]
+    pub const fn with(self, cipher: AeadCipher) -> Self {
+        Self(self.0 | cipher.bit())
+    }
+
+    /// Whether the set holds no suite.
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Whether `cipher` is a member.
+    #[must_use]
+    pub const fn contains(self, cipher: AeadCipher) -> bool {
+        self.0 & cipher.bit() != 0
+    }
+
+    /// Suites supported by both sets.
+    #[must_use]
+    pub const fn intersection(self, other: Self) -> Self {
+        Self::from_bits(self.0 & other.0)
+    }
+
+    /// Suite to use for a negotiated set: AES-256-GCM when available, otherwise
+    /// ChaCha20-Poly1305 (also returned for an empty set, which handshakes reject).
+    ///
+    /// Endpoints without AES hardware acceleration should leave AES-256-GCM out of the set
+    /// they advertise.
+    #[must_use]
+    pub const fn preferred(self) -> AeadCipher {
+        if self.contains(AeadCipher::Aes256Gcm) {
+            AeadCipher::Aes256Gcm
+        } else {
+            AeadCipher::ChaCha20Poly1305
+        }
+    }
+}
+
+impl Default for AeadCipherSet {
+    fn default() -> Self {
+        Self::only(AeadCipher::ChaCha20Poly1305)
+    }
+}
+
+/// Incremental hash with a 32-byte output and 64-byte blocks, as used by HMAC.
+trait Digest: Clone + Default {
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> [u8; TRANSCRIPT_HASH_LEN];
+
+    fn digest(data: &[u8]) -> [u8; TRANSCRIPT_HASH_LEN] {
+        let mut hasher = Self::default();
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
+
+impl Digest for sha256::Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        Self::update(self, data);
+    }
+
+    fn finalize(self) -> [u8; TRANSCRIPT_HASH_LEN] {
+        Self::finalize(self)
+    }
+}
+
+impl Digest for blake3::Blake3 {
+    fn update(&mut self, data: &[u8]) {
+        Self::update(self, data);
+    }
+
+    fn finalize(self) -> [u8; TRANSCRIPT_HASH_LEN] {
+        Self::finalize(self)
+    }
+}
 
 fn copy_checked<const N: usize>(bytes: &[u8], on_err: CryptoError) -> Result<[u8; N], CryptoError> {
     if bytes.len() != N {
@@ -208,12 +440,35 @@ impl AeadNonce {
         Ok(Self(copy_checked(bytes, CryptoError::InvalidNonceLength)?))
     }
 
+    /// A nonce of 96 random bits: the first six bytes of two v4 UUIDs, which hold no
+    /// version or variant bits.
+    pub(crate) fn random() -> Self {
+        let mut bytes = [0u8; AEAD_NONCE_LEN];
+        bytes[..6].copy_from_slice(&uuid::Uuid::new_v4().as_bytes()[..6]);
+        bytes[6..].copy_from_slice(&uuid::Uuid::new_v4().as_bytes()[..6]);
+        Self(bytes)
+    }
+
     /// Borrow as bytes.
     #[must_use]
     pub fn as_bytes(&self) -> &[u8; AEAD_NONCE_LEN] {
         &self.0
     }
 
+    /// Per-packet nonce: this value (used as an IV) XOR the little-endian packet number
+    /// in its last 8 bytes.
+    #[must_use]
+    pub fn for_packet(&self, packet_number: u64) -> Self {
+        let mut bytes = self.0;
+        for (byte, pn) in bytes[AEAD_NONCE_LEN - 8..]
+            .iter_mut()
+            .zip(packet_number.to_le_bytes())
+        {
+            *byte ^= pn;
+        }
+        Self(bytes)
+    }
+
     /// Increment nonce in place (little-endian).
     pub fn increment(&mut self) {
         for byte in &mut self.0 {
@@ -249,6 +504,110 @@ impl AeadTag {
     }
 }
 
+/// Ed25519 key pair identifying an endpoint, used to sign handshake transcripts.
+#[derive(Clone)]
+pub struct Identity {
+    seed: [u8; IDENTITY_SEED_LEN],
+    public: IdentityKey,
+}
+
+impl Identity {
+    /// Construct from a 32-byte Ed25519 secret seed.
+    #[must_use]
+    pub fn from_seed(seed: [u8; IDENTITY_SEED_LEN]) -> Self {
+        let public = IdentityKey(ed25519::public_key(&seed));
+        Self { seed, public }
+    }
+
+    /// Construct from a raw seed slice.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        Ok(Self::from_seed(copy_checked(
+            bytes,
+            CryptoError::InvalidKeyLength,
+        )?))
+    }
+
+    /// Public half of the key pair.
+    #[must_use]
+    pub fn public_key(&self) -> &IdentityKey {
+        &self.public
+    }
+
+    /// Sign `message`.
+    #[must_use]
+    pub fn sign(&self, message: &[u8]) -> Signature {
+        Signature(ed25519::sign(&self.seed, &self.public.0, message))
+    }
+
+    /// Verify a signature made by this identity.
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), CryptoError> {
+        self.public.verify(message, signature)
+    }
+}
+
+impl std::fmt::Debug for Identity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Identity")
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Ed25519 public key of an endpoint identity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IdentityKey([u8; IDENTITY_KEY_LEN]);
+
+impl IdentityKey {
+    /// Construct from a fixed-size array.
+    #[must_use]
+    pub const fn from_array(bytes: [u8; IDENTITY_KEY_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// Construct from raw byte slice.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        Ok(Self(copy_checked(bytes, CryptoError::InvalidKeyLength)?))
+    }
+
+    /// Borrow as bytes.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8; IDENTITY_KEY_LEN] {
+        &self.0
+    }
+
+    /// Verify `signature` over `message` against this key.
+    pub fn verify(&self, message: &[u8], signature: &Signature) -> Result<(), CryptoError> {
+        if ed25519::verify(&self.0, message, &signature.0) {
+            Ok(())
+        } else {
+            Err(CryptoError::InvalidSignature)
+        }
+    }
+}
+
+/// Ed25519 signature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Signature([u8; SIGNATURE_LEN]);
+
+impl Signature {
+    /// Construct from a fixed-size array.
+    #[must_use]
+    pub const fn from_array(bytes: [u8; SIGNATURE_LEN]) -> Self {
+        Self(bytes)
+    }
+
+    /// Construct from raw byte slice.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CryptoError> {
+        Ok(Self(copy_checked(bytes, CryptoError::InvalidSignature)?))
+    }
+
+    /// Borrow as bytes.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8; SIGNATURE_LEN] {
+        &self.0
+    }
+}
+
 /// Noise protocol handshake state (simplified placeholder).
 #[derive(Debug, Clone)]
 pub struct HandshakeState {
@@ -258,12 +617,21 @@ pub struct HandshakeState {
     remote_ephemeral: Option<PublicKey>,
     chaining_key: [u8; SHARED_SECRET_LEN],
     temp_key: [u8; AEAD_KEY_LEN],
+    handshake_hash: [u8; TRANSCRIPT_HASH_LEN],
+    nonce: u64,
+    hash: HashAlgorithm,
 }
 
 impl HandshakeState {
-    /// Initialize a new handshake with the local static key.
+    /// Initialize a new handshake with the local static key, hashing with SHA-256.
     #[must_use]
     pub fn new(local_static: PrivateKey) -> Self {
+        Self::with_hash_algorithm(local_static, HashAlgorithm::Sha256)
+    }
+
+    /// Initialize a new handshake whose transcript and key schedule use `hash`.
+    #[must_use]
+    pub fn with_hash_algorithm(local_static: PrivateKey, hash: HashAlgorithm) -> Self {
         Self {
             local_static,
             local_ephemeral: None,
@@ -271,9 +639,18 @@ impl HandshakeState {
             remote_ephemeral: None,
             chaining_key: [0u8; SHARED_SECRET_LEN],
             temp_key: [0u8; AEAD_KEY_LEN],
+            handshake_hash: hash.digest(hash.protocol_name()),
+            nonce: 0,
+            hash,
         }
     }
 
+    /// Hash algorithm used for the transcript and key schedule.
+    #[must_use]
+    pub const fn hash_algorithm(&self) -> HashAlgorithm {
+        self.hash
+    }
+
     /// Set the remote static key (when known).
     pub fn set_remote_static(&mut self, key: PublicKey) {
         self.remote_static = Some(key);
@@ -325,18 +702,75 @@ impl HandshakeState {
         &self.temp_key
     }
 
-    /// Inject new key material via HKDF (placeholder implementation).
+    /// Access the running transcript hash.
+    #[must_use]
+    pub fn handshake_hash(&self) -> &[u8; TRANSCRIPT_HASH_LEN] {
+        &self.handshake_hash
+    }
+
+    /// Absorb handshake bytes into the transcript hash (`h = HASH(h || data)`).
+    pub fn mix_hash(&mut self, data: &[u8]) {
+        self.handshake_hash = match self.hash {
+            HashAlgorithm::Sha256 => mix(sha256::Sha256::new(), &self.handshake_hash, data),
+            HashAlgorithm::Blake3 => mix(blake3::Blake3::new(), &self.handshake_hash, data),
+        };
+    }
+
+    /// Inject DH output into the chaining key via HKDF-extract/expand.
     pub fn mix_key(&mut self, material: &[u8]) -> Result<(), CryptoError> {
-        let prk = hkdf::extract(&self.chaining_key, material);
+        let prk = hkdf::extract_with(self.hash, &self.chaining_key, material);
 
         let mut okm = [0u8; SHARED_SECRET_LEN + AEAD_KEY_LEN];
-        hkdf::expand(&prk, &[], &mut okm)?;
+        hkdf::expand_with(self.hash, &prk, &[], &mut okm)?;
 
         self.chaining_key.copy_from_slice(&okm[..SHARED_SECRET_LEN]);
         self.temp_key
             .copy_from_slice(&okm[SHARED_SECRET_LEN..SHARED_SECRET_LEN + AEAD_KEY_LEN]);
+        self.nonce = 0;
         Ok(())
     }
+
+    /// Seal a handshake payload under the temp key, authenticating the transcript hash.
+    ///
+    /// Output is `ciphertext || tag`; the nonce counter advances with every call and is
+    /// reset by [`mix_key`](Self::mix_key).
+    pub fn encrypt_payload(&mut self, plaintext: &[u8]) -> Vec<u8> {
+        let key = AeadKey::from_array(self.temp_key);
+        let nonce = self.next_nonce();
+        let (mut sealed, tag) = aead::seal(&key, &nonce, plaintext, &self.handshake_hash);
+        sealed.extend_from_slice(tag.as_bytes());
+        sealed
+    }
+
+    /// Open a payload produced by the peer's [`encrypt_payload`](Self::encrypt_payload).
+    pub fn decrypt_payload(&mut self, sealed: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        if sealed.len() < AEAD_TAG_LEN {
+            return Err(CryptoError::AuthenticationFailed);
+        }
+        let (ciphertext, tag) = sealed.split_at(sealed.len() - AEAD_TAG_LEN);
+        let key = AeadKey::from_array(self.temp_key);
+        let nonce = self.next_nonce();
+        aead::open(
+            &key,
+            &nonce,
+            ciphertext,
+            &self.handshake_hash,
+            &AeadTag::from_bytes(tag)?,
+        )
+    }
+
+    fn next_nonce(&mut self) -> AeadNonce {
+        let mut bytes = [0u8; AEAD_NONCE_LEN];
+        bytes[AEAD_NONCE_LEN - 8..].copy_from_slice(&self.nonce.to_le_bytes());
+        self.nonce += 1;
+        AeadNonce::from_array(bytes)
+    }
+}
+
+fn mix<D: Digest>(mut hasher: D, hash: &[u8], data: &[u8]) -> [u8; TRANSCRIPT_HASH_LEN] {
+    hasher.update(hash);
+    hasher.update(data);
+    hasher.finalize()
 }
 
 /// Session keys derived at the end of the handshake.
@@ -346,6 +780,8 @@ pub struct SessionKeys {
     receive: AeadKey,
     send_hp: HeaderProtectionKey,
     receive_hp: HeaderProtectionKey,
+    send_iv: AeadNonce,
+    receive_iv: AeadNonce,
 }
 
 impl SessionKeys {
@@ -356,12 +792,16 @@ impl SessionKeys {
         receive: AeadKey,
         send_hp: HeaderProtectionKey,
         receive_hp: HeaderProtectionKey,
+        send_iv: AeadNonce,
+        receive_iv: AeadNonce,
     ) -> Self {
         Self {
             send,
             receive,
             send_hp,
             receive_hp,
+            send_iv,
+            receive_iv,
         }
     }
 
@@ -388,62 +828,146 @@ impl SessionKeys {
     pub fn receive_hp(&self) -> &HeaderProtectionKey {
         &self.receive_hp
     }
+
+    /// Access the IV combined with packet numbers to form outbound nonces.
+    #[must_use]
+    pub fn send_iv(&self) -> &AeadNonce {
+        &self.send_iv
+    }
+
+    /// Access the IV combined with packet numbers to form inbound nonces.
+    #[must_use]
+    pub fn receive_iv(&self) -> &AeadNonce {
+        &self.receive_iv
+    }
 }
 
-/// Derive session keys based on the chaining key and temp key.
-pub fn derive_session_keys(
-    state: &HandshakeState,
-    initiator: bool,
-) -> Result<SessionKeys, CryptoError> {
-    let mut okm = [0u8; AEAD_KEY_LEN * 2 + HEADER_PROTECTION_KEY_LEN * 2];
-    hkdf::expand(state.chaining_key(), &[], &mut okm)?;
+/// HKDF-SHA256 extract (RFC 5869): condense `ikm` into a pseudorandom key.
+///
+/// An empty `salt` is treated as [`HKDF_PRK_LEN`] zero bytes.
+#[must_use]
+pub fn hkdf_extract(salt: &[u8], ikm: &[u8]) -> [u8; HKDF_PRK_LEN] {
+    hkdf::extract(salt, ikm)
+}
+
+/// HKDF-SHA256 expand (RFC 5869): derive `len` bytes bound to the `info` label.
+///
+/// Fails with [`CryptoError::KeyDerivationFailed`] if `len` exceeds `255 * HKDF_PRK_LEN`.
+pub fn hkdf_expand(
+    prk: &[u8; HKDF_PRK_LEN],
+    info: &[u8],
+    len: usize,
+) -> Result<Vec<u8>, CryptoError> {
+    let mut okm = vec![0u8; len];
+    hkdf::expand(prk, info, &mut okm)?;
+    Ok(okm)
+}
 
-    let mut offset = 0;
+fn expand_array<const N: usize>(
+    prk: &[u8; HKDF_PRK_LEN],
+    info: &[u8],
+) -> Result<[u8; N], CryptoError> {
+    expand_array_with(HashAlgorithm::Sha256, prk, info)
+}
+
+fn expand_array_with<const N: usize>(
+    hash: HashAlgorithm,
+    prk: &[u8; HKDF_PRK_LEN],
+    info: &[u8],
+) -> Result<[u8; N], CryptoError> {
+    let mut okm = [0u8; N];
+    hkdf::expand_with(hash, prk, info, &mut okm)?;
+    Ok(okm)
+}
 
-    let mut first_aead = [0u8; AEAD_KEY_LEN];
-    first_aead.copy_from_slice(&okm[offset..offset + AEAD_KEY_LEN]);
-    offset += AEAD_KEY_LEN;
+/// Derive the header protection key from a direction's traffic secret.
+pub fn derive_header_protection_key(
+    hash: HashAlgorithm,
+    traffic_secret: &[u8; HKDF_PRK_LEN],
+) -> Result<HeaderProtectionKey, CryptoError> {
+    Ok(HeaderProtectionKey::from_array(expand_array_with(
+        hash,
+        traffic_secret,
+        HP_INFO,
+    )?))
+}
 
-    let mut second_aead = [0u8; AEAD_KEY_LEN];
-    second_aead.copy_from_slice(&okm[offset..offset + AEAD_KEY_LEN]);
-    offset += AEAD_KEY_LEN;
+/// Expand a traffic secret into its AEAD key, IV, and header protection key.
+fn derive_packet_protection(
+    hash: HashAlgorithm,
+    traffic_secret: &[u8; HKDF_PRK_LEN],
+) -> Result<(AeadKey, AeadNonce, HeaderProtectionKey), CryptoError> {
+    Ok((
+        AeadKey::from_array(expand_array_with(hash, traffic_secret, KEY_INFO)?),
+        AeadNonce::from_array(expand_array_with(hash, traffic_secret, IV_INFO)?),
+        derive_header_protection_key(hash, traffic_secret)?,
+    ))
+}
 
-    let mut first_hp = [0u8; HEADER_PROTECTION_KEY_LEN];
-    first_hp.copy_from_slice(&okm[offset..offset + HEADER_PROTECTION_KEY_LEN]);
-    offset += HEADER_PROTECTION_KEY_LEN;
+/// Derive session keys as `HKDF(chaining_key, transcript_hash)`, with HKDF over `hash`.
+///
+/// Each direction gets its own traffic secret, which is then expanded into that direction's
+/// AEAD key, IV, and header protection key. Any difference in the transcripts observed by the
+/// two peers yields unrelated keys.
+pub fn derive_session_keys(
+    hash: HashAlgorithm,
+    chaining_key: &[u8; SHARED_SECRET_LEN],
+    transcript_hash: &[u8; TRANSCRIPT_HASH_LEN],
+    initiator: bool,
+) -> Result<SessionKeys, CryptoError> {
+    let prk = hkdf::extract_with(hash, chaining_key, transcript_hash);
 
-    let mut second_hp = [0u8; HEADER_PROTECTION_KEY_LEN];
-    second_hp.copy_from_slice(&okm[offset..offset + HEADER_PROTECTION_KEY_LEN]);
+    let secret_i2r = expand_array_with(hash, &prk, TRAFFIC_I2R_INFO)?;
+    let secret_r2i = expand_array_with(hash, &prk, TRAFFIC_R2I_INFO)?;
+    let (key_i2r, iv_i2r, hp_i2r) = derive_packet_protection(hash, &secret_i2r)?;
+    let (key_r2i, iv_r2i, hp_r2i) = derive_packet_protection(hash, &secret_r2i)?;
 
     if initiator {
         Ok(SessionKeys::new(
-            AeadKey::from_array(first_aead),
-            AeadKey::from_array(second_aead),
-            HeaderProtectionKey::from_array(first_hp),
-            HeaderProtectionKey::from_array(second_hp),
+            key_i2r, key_r2i, hp_i2r, hp_r2i, iv_i2r, iv_r2i,
         ))
     } else {
         Ok(SessionKeys::new(
-            AeadKey::from_array(second_aead),
-            AeadKey::from_array(first_aead),
-            HeaderProtectionKey::from_array(second_hp),
-            HeaderProtectionKey::from_array(first_hp),
+            key_r2i, key_i2r, hp_r2i, hp_i2r, iv_r2i, iv_i2r,
         ))
     }
 }
 
+/// Derive the 0-RTT key from a resumption secret and the initiator's ephemeral key.
+pub fn derive_early_data_key(
+    resumption_secret: &[u8],
+    ephemeral: &PublicKey,
+) -> Result<AeadKey, CryptoError> {
+    let prk = hkdf::extract(resumption_secret, ephemeral.as_bytes());
+    Ok(AeadKey::from_array(expand_array(&prk, EARLY_DATA_INFO)?))
+}
+
+/// Compute the SHA-256 digest of `data`.
+#[must_use]
+pub(crate) fn sha256(data: &[u8]) -> [u8; 32] {
+    sha256::Sha256::digest(data)
+}
+
+/// Compute HMAC-SHA256 of `data` under `key`.
+#[must_use]
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> [u8; TRANSCRIPT_HASH_LEN] {
+    hmac::HmacSha256::compute(key, data)
+}
+
 /// Derive a header protection mask from sampled ciphertext bytes.
+///
+/// As in RFC 9001 section 5.4.4, the first four sample bytes are the little-endian `ChaCha20` block
+/// counter and the remaining twelve the nonce; the mask is the start of that keystream block.
 #[must_use]
 pub fn header_protection_mask(
     key: &HeaderProtectionKey,
     sample: &[u8; HEADER_PROTECTION_SAMPLE_LEN],
 ) -> [u8; HEADER_PROTECTION_MASK_LEN] {
-    let mut nonce = [0u8; AEAD_NONCE_LEN];
-    nonce.copy_from_slice(
-        &sample[HEADER_PROTECTION_SAMPLE_LEN - AEAD_NONCE_LEN..HEADER_PROTECTION_SAMPLE_LEN],
-    );
+    let (counter, nonce) = sample.split_at(HEADER_PROTECTION_SAMPLE_LEN - AEAD_NONCE_LEN);
+    let counter = u32::from_le_bytes(counter.try_into().expect("four counter bytes"));
+    let nonce: &[u8; AEAD_NONCE_LEN] = nonce.try_into().expect("twelve nonce bytes");
 
-    let block = chacha20::chacha20_block(key.as_bytes(), 0, &nonce);
+    let block = chacha20::chacha20_block(key.as_bytes(), counter, nonce);
     let mut mask = [0u8; HEADER_PROTECTION_MASK_LEN];
     mask.copy_from_slice(&block[..HEADER_PROTECTION_MASK_LEN]);
     mask
@@ -471,6 +995,33 @@ pub fn decrypt(
     aead::open(key, nonce, ciphertext, aad, tag)
 }
 
+/// Seal `plaintext` with ChaCha20-Poly1305, returning the ciphertext with the tag appended.
+#[must_use]
+pub fn chacha20_poly1305_seal(
+    key: &AeadKey,
+    nonce: &AeadNonce,
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Vec<u8> {
+    let (mut sealed, tag) = aead::seal(key, nonce, plaintext, aad);
+    sealed.extend_from_slice(tag.as_bytes());
+    sealed
+}
+
+/// Open a buffer produced by [`chacha20_poly1305_seal`] (ciphertext followed by the tag).
+pub fn chacha20_poly1305_open(
+    key: &AeadKey,
+    nonce: &AeadNonce,
+    sealed: &[u8],
+    aad: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    if sealed.len() < AEAD_TAG_LEN {
+        return Err(CryptoError::AuthenticationFailed);
+    }
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - AEAD_TAG_LEN);
+    aead::open(key, nonce, ciphertext, aad, &AeadTag::from_bytes(tag)?)
+}
+
 /// Perform a dummy X25519 key agreement (placeholder).
 /// To simulate the commutative property of real DH (DH(a,B) = DH(b,A)),
 /// we derive the private key's corresponding public key, then combine both
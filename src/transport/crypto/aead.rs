@@ -1,7 +1,7 @@
 //! ChaCha20-Poly1305 AEAD per RFC 8439 using the local primitives.
 
-use super::chacha20::{chacha20_block, chacha20_xor};
-use super::poly1305::poly1305_tag;
+use super::chacha20::{ChaCha20Stream, chacha20_block, chacha20_xor};
+use super::poly1305::Poly1305;
 use super::{AeadKey, AeadNonce, AeadTag, CryptoError};
 
 fn poly_key(key: &AeadKey, nonce: &AeadNonce) -> [u8; 32] {
@@ -11,24 +11,132 @@ fn poly_key(key: &AeadKey, nonce: &AeadNonce) -> [u8; 32] {
     poly
 }
 
+/// RFC 8439 tag over `aad || pad || ciphertext || pad || lengths`, fed straight into Poly1305
+/// so the padded MAC input is never materialised.
 fn compute_mac(poly_key: &[u8; 32], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
-    let mut mac_data =
-        Vec::with_capacity(aad.len().div_ceil(16) * 16 + ciphertext.len().div_ceil(16) * 16 + 16);
+    let mut mac = Poly1305::new(poly_key);
+    mac.update(aad);
+    mac.pad();
+    mac.update(ciphertext);
+    mac.pad();
+    mac.update(&length_block(aad.len() as u64, ciphertext.len() as u64));
+    mac.finalize()
+}
+
+/// Final Poly1305 block: AAD and ciphertext lengths as little-endian u64s.
+fn length_block(aad_len: u64, ciphertext_len: u64) -> [u8; 16] {
+    let mut block = [0u8; 16];
+    block[..8].copy_from_slice(&aad_len.to_le_bytes());
+    block[8..].copy_from_slice(&ciphertext_len.to_le_bytes());
+    block
+}
+
+/// Keystream and MAC state shared by the incremental encryptor and decryptor.
+#[derive(Clone)]
+struct StreamingState {
+    keystream: ChaCha20Stream,
+    mac: Poly1305,
+    aad_len: u64,
+    ciphertext_len: u64,
+}
+
+impl StreamingState {
+    fn new(key: &AeadKey, nonce: &AeadNonce, aad: &[u8]) -> Self {
+        let mut mac = Poly1305::new(&poly_key(key, nonce));
+        mac.update(aad);
+        mac.pad();
+        Self {
+            keystream: ChaCha20Stream::new(key.as_bytes(), 1, nonce.as_bytes()),
+            mac,
+            aad_len: aad.len() as u64,
+            ciphertext_len: 0,
+        }
+    }
+
+    fn absorb_ciphertext(&mut self, ciphertext: &[u8]) {
+        self.mac.update(ciphertext);
+        self.ciphertext_len += ciphertext.len() as u64;
+    }
+
+    fn tag(mut self) -> [u8; 16] {
+        self.mac.pad();
+        self.mac
+            .update(&length_block(self.aad_len, self.ciphertext_len));
+        self.mac.finalize()
+    }
+}
 
-    mac_data.extend_from_slice(aad);
-    if aad.len() % 16 != 0 {
-        mac_data.resize(aad.len().div_ceil(16) * 16, 0);
+/// Incremental ChaCha20-Poly1305 encryption for payloads too large to hold twice in memory.
+///
+/// Chunks may have any size; the concatenated output and the final tag equal what
+/// [`chacha20_poly1305_seal`](super::chacha20_poly1305_seal) produces for the whole message.
+#[derive(Clone)]
+pub struct AeadEncryptor {
+    state: StreamingState,
+}
+
+impl AeadEncryptor {
+    /// Start encrypting a message authenticated together with `aad`.
+    #[must_use]
+    pub fn new(key: &AeadKey, nonce: &AeadNonce, aad: &[u8]) -> Self {
+        Self {
+            state: StreamingState::new(key, nonce, aad),
+        }
+    }
+
+    /// Encrypt the next chunk of plaintext in place.
+    pub fn update(&mut self, chunk: &mut [u8]) {
+        self.state.keystream.apply(chunk);
+        self.state.absorb_ciphertext(chunk);
+    }
+
+    /// Finish the message and return its tag.
+    #[must_use]
+    pub fn finalize(self) -> AeadTag {
+        AeadTag::from_array(self.state.tag())
     }
+}
+
+/// Incremental ChaCha20-Poly1305 decryption, the counterpart of [`AeadEncryptor`].
+///
+/// Plaintext returned by [`update`](Self::update) is unauthenticated until
+/// [`finalize`](Self::finalize) succeeds and must not be acted on before then.
+#[derive(Clone)]
+pub struct AeadDecryptor {
+    state: StreamingState,
+}
 
-    mac_data.extend_from_slice(ciphertext);
-    if ciphertext.len() % 16 != 0 {
-        mac_data.resize(mac_data.len() + (16 - (ciphertext.len() % 16)) % 16, 0);
+impl AeadDecryptor {
+    /// Start decrypting a message authenticated together with `aad`.
+    #[must_use]
+    pub fn new(key: &AeadKey, nonce: &AeadNonce, aad: &[u8]) -> Self {
+        Self {
+            state: StreamingState::new(key, nonce, aad),
+        }
     }
 
-    mac_data.extend_from_slice(&(aad.len() as u64).to_le_bytes());
-    mac_data.extend_from_slice(&(ciphertext.len() as u64).to_le_bytes());
+    /// Decrypt the next chunk of ciphertext in place.
+    pub fn update(&mut self, chunk: &mut [u8]) {
+        self.state.absorb_ciphertext(chunk);
+        self.state.keystream.apply(chunk);
+    }
+
+    /// Check the message against `tag`.
+    pub fn finalize(self, tag: &AeadTag) -> Result<(), CryptoError> {
+        if tags_equal(&self.state.tag(), tag.as_bytes()) {
+            Ok(())
+        } else {
+            Err(CryptoError::AuthenticationFailed)
+        }
+    }
+}
 
-    poly1305_tag(&mac_data, poly_key)
+fn tags_equal(expected: &[u8; 16], actual: &[u8; 16]) -> bool {
+    let mut diff = 0u8;
+    for (a, b) in expected.iter().zip(actual) {
+        diff |= a ^ b;
+    }
+    diff == 0
 }
 
 pub fn seal(key: &AeadKey, nonce: &AeadNonce, plaintext: &[u8], aad: &[u8]) -> (Vec<u8>, AeadTag) {
@@ -50,12 +158,7 @@ pub fn open(
 ) -> Result<Vec<u8>, CryptoError> {
     let poly = poly_key(key, nonce);
     let expected = compute_mac(&poly, aad, ciphertext);
-
-    let mut diff = 0u8;
-    for (a, b) in expected.iter().zip(tag.as_bytes()) {
-        diff |= a ^ b;
-    }
-    if diff != 0 {
+    if !tags_equal(&expected, tag.as_bytes()) {
         return Err(CryptoError::AuthenticationFailed);
     }
 
@@ -113,4 +216,72 @@ mod tests {
         let err = open(&key, &nonce, &tampered, &aad, &tag).unwrap_err();
         assert!(matches!(err, CryptoError::AuthenticationFailed));
     }
+
+    #[test]
+    fn chunked_encryption_matches_one_shot() {
+        let key = AeadKey::from_array([0x42; 32]);
+        let nonce = AeadNonce::from_array([0x24; 12]);
+        let aad = b"stream 7";
+        let plaintext: Vec<u8> = (0..(1usize << 20) + 13)
+            .map(|idx| u8::try_from((idx.wrapping_mul(31) >> 3) & 0xff).unwrap())
+            .collect();
+        let (expected, expected_tag) = seal(&key, &nonce, &plaintext, aad);
+
+        // Irregular chunk sizes straddle keystream and Poly1305 block boundaries.
+        let sizes = [1, 63, 64, 65, 15, 4096 + 7, 256, 17];
+        let mut encryptor = AeadEncryptor::new(&key, &nonce, aad);
+        let mut ciphertext = plaintext.clone();
+        let mut rest = ciphertext.as_mut_slice();
+        for size in sizes.iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (chunk, tail) = rest.split_at_mut((*size).min(rest.len()));
+            encryptor.update(chunk);
+            rest = tail;
+        }
+        assert!(ciphertext == expected);
+        assert_eq!(encryptor.finalize(), expected_tag);
+
+        let mut decryptor = AeadDecryptor::new(&key, &nonce, aad);
+        for chunk in ciphertext.chunks_mut(1000) {
+            decryptor.update(chunk);
+        }
+        decryptor.finalize(&expected_tag).expect("authentic");
+        assert!(ciphertext == plaintext);
+    }
+
+    #[test]
+    fn streaming_decryptor_rejects_tampering() {
+        let key = AeadKey::from_array([1; 32]);
+        let nonce = AeadNonce::from_array([2; 12]);
+        let (mut ciphertext, tag) = seal(&key, &nonce, &[0x77; 200], b"aad");
+        ciphertext[150] ^= 0x80;
+
+        let mut decryptor = AeadDecryptor::new(&key, &nonce, b"aad");
+        decryptor.update(&mut ciphertext[..100]);
+        decryptor.update(&mut ciphertext[100..]);
+        assert_eq!(
+            decryptor.finalize(&tag),
+            Err(CryptoError::AuthenticationFailed)
+        );
+    }
+
+    #[test]
+    fn public_wrappers_append_tag() {
+        use super::super::{AEAD_TAG_LEN, chacha20_poly1305_open, chacha20_poly1305_seal};
+
+        let key = AeadKey::from_array([7; 32]);
+        let nonce = AeadNonce::from_array([9; 12]);
+        let sealed = chacha20_poly1305_seal(&key, &nonce, b"payload", b"aad");
+        let (cipher, tag) = seal(&key, &nonce, b"payload", b"aad");
+        assert_eq!(sealed[..7], cipher[..]);
+        assert_eq!(&sealed[7..], tag.as_bytes());
+        assert_eq!(sealed.len(), 7 + AEAD_TAG_LEN);
+
+        let opened = chacha20_poly1305_open(&key, &nonce, &sealed, b"aad").expect("open");
+        assert_eq!(opened, b"payload");
+        assert!(chacha20_poly1305_open(&key, &nonce, &sealed, b"other").is_err());
+        assert!(chacha20_poly1305_open(&key, &nonce, &sealed[..4], b"aad").is_err());
+    }
 }
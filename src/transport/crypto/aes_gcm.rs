@@ -0,0 +1,322 @@
+//! AES-256-GCM AEAD (FIPS 197, NIST SP 800-38D) using the local primitives.
+//!
+//! Portable implementation; it does not use AES-NI/PCLMULQDQ. GHASH and `MixColumns` run
+//! without secret-dependent branches, but `SubBytes` indexes a 256-byte S-box table with key-
+//! and data-dependent bytes, so the cipher is **not constant-time**: an attacker sharing the
+//! CPU cache can recover key bits from lookup timing.
+
+use super::{AeadKey, AeadNonce, AeadTag, CryptoError};
+
+const BLOCK_LEN: usize = 16;
+const ROUNDS: usize = 14;
+const KEY_WORDS: usize = 8;
+
+const SBOX: [u8; 256] = build_sbox();
+const RCON: [u8; 7] = [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40];
+/// GHASH reduction constant for the bit-reflected polynomial x^128 + x^7 + x^2 + x + 1.
+const GHASH_R: u128 = 0xE1 << 120;
+
+/// Multiply by x in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1, without branching on `a`.
+const fn xtime(a: u8) -> u8 {
+    (a << 1) ^ (0x1B & 0u8.wrapping_sub(a >> 7))
+}
+
+/// Multiply in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1; only used to build the S-box.
+const fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1B;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// S-box: multiplicative inverse followed by the FIPS 197 affine transform.
+#[allow(clippy::cast_possible_truncation)] // x < 256
+const fn build_sbox() -> [u8; 256] {
+    let mut sbox = [0u8; 256];
+    let mut x = 0usize;
+    while x < 256 {
+        // x^254 is the inverse of x (and maps 0 to 0).
+        let mut inverse = 1u8;
+        let mut power = 0;
+        while power < 254 {
+            inverse = gf_mul(inverse, x as u8);
+            power += 1;
+        }
+        if x == 0 {
+            inverse = 0;
+        }
+        sbox[x] = inverse
+            ^ inverse.rotate_left(1)
+            ^ inverse.rotate_left(2)
+            ^ inverse.rotate_left(3)
+            ^ inverse.rotate_left(4)
+            ^ 0x63;
+        x += 1;
+    }
+    sbox
+}
+
+/// Expanded AES-256 encryption key.
+struct Aes256 {
+    round_keys: [[u8; BLOCK_LEN]; ROUNDS + 1],
+}
+
+impl Aes256 {
+    fn new(key: &[u8; 32]) -> Self {
+        let mut words = [[0u8; 4]; 4 * (ROUNDS + 1)];
+        for (word, bytes) in words.iter_mut().zip(key.chunks_exact(4)) {
+            word.copy_from_slice(bytes);
+        }
+        for idx in KEY_WORDS..words.len() {
+            let mut temp = words[idx - 1];
+            if idx % KEY_WORDS == 0 {
+                temp.rotate_left(1);
+                temp = temp.map(|byte| SBOX[usize::from(byte)]);
+                temp[0] ^= RCON[idx / KEY_WORDS - 1];
+            } else if idx % KEY_WORDS == 4 {
+                temp = temp.map(|byte| SBOX[usize::from(byte)]);
+            }
+            for (byte, prev) in temp.iter_mut().zip(words[idx - KEY_WORDS]) {
+                *byte ^= prev;
+            }
+            words[idx] = temp;
+        }
+
+        let mut round_keys = [[0u8; BLOCK_LEN]; ROUNDS + 1];
+        for (round_key, group) in round_keys.iter_mut().zip(words.chunks_exact(4)) {
+            for (bytes, word) in round_key.chunks_exact_mut(4).zip(group) {
+                bytes.copy_from_slice(word);
+            }
+        }
+        Self { round_keys }
+    }
+
+    fn encrypt_block(&self, block: &mut [u8; BLOCK_LEN]) {
+        add_round_key(block, &self.round_keys[0]);
+        for round_key in &self.round_keys[1..ROUNDS] {
+            sub_bytes(block);
+            shift_rows(block);
+            mix_columns(block);
+            add_round_key(block, round_key);
+        }
+        sub_bytes(block);
+        shift_rows(block);
+        add_round_key(block, &self.round_keys[ROUNDS]);
+    }
+}
+
+fn add_round_key(state: &mut [u8; BLOCK_LEN], round_key: &[u8; BLOCK_LEN]) {
+    for (byte, key) in state.iter_mut().zip(round_key) {
+        *byte ^= key;
+    }
+}
+
+fn sub_bytes(state: &mut [u8; BLOCK_LEN]) {
+    for byte in state.iter_mut() {
+        *byte = SBOX[usize::from(*byte)];
+    }
+}
+
+/// State bytes are column-major: `state[4 * column + row]`.
+fn shift_rows(state: &mut [u8; BLOCK_LEN]) {
+    let original = *state;
+    for column in 0..4 {
+        for row in 1..4 {
+            state[4 * column + row] = original[4 * ((column + row) % 4) + row];
+        }
+    }
+}
+
+fn mix_columns(state: &mut [u8; BLOCK_LEN]) {
+    for column in state.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [column[0], column[1], column[2], column[3]];
+        let [b0, b1, b2, b3] = [xtime(a0), xtime(a1), xtime(a2), xtime(a3)];
+        column[0] = b0 ^ (b1 ^ a1) ^ a2 ^ a3;
+        column[1] = a0 ^ b1 ^ (b2 ^ a2) ^ a3;
+        column[2] = a0 ^ a1 ^ b2 ^ (b3 ^ a3);
+        column[3] = (b0 ^ a0) ^ a1 ^ a2 ^ b3;
+    }
+}
+
+/// Multiply two field elements in GCM's bit-reflected GF(2^128).
+///
+/// Both the hash key `h` and the accumulator `x` are secret, so each step selects with an
+/// all-ones or all-zero mask instead of branching on their bits.
+fn ghash_mul(x: u128, h: u128) -> u128 {
+    let mut product = 0u128;
+    let mut v = h;
+    for bit in (0..128).rev() {
+        product ^= v & 0u128.wrapping_sub((x >> bit) & 1);
+        v = (v >> 1) ^ (GHASH_R & 0u128.wrapping_sub(v & 1));
+    }
+    product
+}
+
+fn ghash(h: u128, aad: &[u8], ciphertext: &[u8]) -> u128 {
+    let mut acc = 0u128;
+    for data in [aad, ciphertext] {
+        for chunk in data.chunks(BLOCK_LEN) {
+            let mut block = [0u8; BLOCK_LEN];
+            block[..chunk.len()].copy_from_slice(chunk);
+            acc = ghash_mul(acc ^ u128::from_be_bytes(block), h);
+        }
+    }
+    let lengths =
+        (u128::from(aad.len() as u64 * 8) << 64) | u128::from(ciphertext.len() as u64 * 8);
+    ghash_mul(acc ^ lengths, h)
+}
+
+/// Counter block for a 96-bit nonce: `nonce || counter (32-bit BE)`.
+fn counter_block(nonce: &AeadNonce, counter: u32) -> [u8; BLOCK_LEN] {
+    let mut block = [0u8; BLOCK_LEN];
+    block[..12].copy_from_slice(nonce.as_bytes());
+    block[12..].copy_from_slice(&counter.to_be_bytes());
+    block
+}
+
+/// XOR `data` with the keystream starting at counter 2 (counter 1 masks the tag).
+fn apply_keystream(cipher: &Aes256, nonce: &AeadNonce, data: &mut [u8]) {
+    for (idx, chunk) in data.chunks_mut(BLOCK_LEN).enumerate() {
+        #[allow(clippy::cast_possible_truncation)] // packets are far below 2^36 bytes
+        let mut keystream = counter_block(nonce, 2u32.wrapping_add(idx as u32));
+        cipher.encrypt_block(&mut keystream);
+        for (byte, key) in chunk.iter_mut().zip(keystream) {
+            *byte ^= key;
+        }
+    }
+}
+
+fn compute_tag(cipher: &Aes256, nonce: &AeadNonce, aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+    let mut h = [0u8; BLOCK_LEN];
+    cipher.encrypt_block(&mut h);
+    let mut tag_mask = counter_block(nonce, 1);
+    cipher.encrypt_block(&mut tag_mask);
+    let tag = ghash(u128::from_be_bytes(h), aad, ciphertext) ^ u128::from_be_bytes(tag_mask);
+    tag.to_be_bytes()
+}
+
+pub fn seal(key: &AeadKey, nonce: &AeadNonce, plaintext: &[u8], aad: &[u8]) -> (Vec<u8>, AeadTag) {
+    let cipher = Aes256::new(key.as_bytes());
+
+    let mut ciphertext = plaintext.to_vec();
+    apply_keystream(&cipher, nonce, &mut ciphertext);
+
+    let tag_bytes = compute_tag(&cipher, nonce, aad, &ciphertext);
+    (ciphertext, AeadTag::from_array(tag_bytes))
+}
+
+pub fn open(
+    key: &AeadKey,
+    nonce: &AeadNonce,
+    ciphertext: &[u8],
+    aad: &[u8],
+    tag: &AeadTag,
+) -> Result<Vec<u8>, CryptoError> {
+    let cipher = Aes256::new(key.as_bytes());
+    let expected = compute_tag(&cipher, nonce, aad, ciphertext);
+
+    let mut diff = 0u8;
+    for (a, b) in expected.iter().zip(tag.as_bytes()) {
+        diff |= a ^ b;
+    }
+    if diff != 0 {
+        return Err(CryptoError::AuthenticationFailed);
+    }
+
+    let mut plaintext = ciphertext.to_vec();
+    apply_keystream(&cipher, nonce, &mut plaintext);
+    Ok(plaintext)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn fips_197_aes_256_block() {
+        let key: [u8; 32] =
+            unhex("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")
+                .try_into()
+                .unwrap();
+        let mut block: [u8; 16] = unhex("00112233445566778899aabbccddeeff")
+            .try_into()
+            .unwrap();
+        Aes256::new(&key).encrypt_block(&mut block);
+        assert_eq!(block.to_vec(), unhex("8ea2b7ca516745bfeafc49904b496089"));
+    }
+
+    #[test]
+    fn gcm_spec_zero_key_vectors() {
+        // Test cases 13 and 14 of the GCM specification.
+        let key = AeadKey::from_array([0u8; 32]);
+        let nonce = AeadNonce::from_array([0u8; 12]);
+
+        let (ciphertext, tag) = seal(&key, &nonce, &[], &[]);
+        assert!(ciphertext.is_empty());
+        assert_eq!(
+            tag.as_bytes().to_vec(),
+            unhex("530f8afbc74536b9a963b4f1c4cb738b")
+        );
+
+        let (ciphertext, tag) = seal(&key, &nonce, &[0u8; 16], &[]);
+        assert_eq!(ciphertext, unhex("cea7403d4d606b6e074ec5d3baf39d18"));
+        assert_eq!(
+            tag.as_bytes().to_vec(),
+            unhex("d0d1c8a799996bf0265b98b5d48ab919")
+        );
+    }
+
+    #[test]
+    fn gcm_spec_seal_open_with_aad() {
+        // Test case 16 of the GCM specification.
+        let key = AeadKey::from_bytes(&unhex(
+            "feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308",
+        ))
+        .unwrap();
+        let nonce = AeadNonce::from_bytes(&unhex("cafebabefacedbaddecaf888")).unwrap();
+        let plaintext = unhex(
+            "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72\
+             1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39",
+        );
+        let aad = unhex("feedfacedeadbeeffeedfacedeadbeefabaddad2");
+
+        let (ciphertext, tag) = seal(&key, &nonce, &plaintext, &aad);
+        assert_eq!(
+            ciphertext,
+            unhex(
+                "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa\
+                 8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662"
+            )
+        );
+        assert_eq!(
+            tag.as_bytes().to_vec(),
+            unhex("76fc6ece0f4e1768cddf8853bb2d551b")
+        );
+
+        let opened = open(&key, &nonce, &ciphertext, &aad, &tag).unwrap();
+        assert_eq!(opened, plaintext);
+
+        let mut tampered = ciphertext;
+        tampered[0] ^= 1;
+        assert_eq!(
+            open(&key, &nonce, &tampered, &aad, &tag),
+            Err(CryptoError::AuthenticationFailed)
+        );
+    }
+}
@@ -0,0 +1,578 @@
+//! Ed25519 signatures (RFC 8032) over the local SHA-512.
+//!
+//! Field elements use five 51-bit limbs and points extended twisted Edwards coordinates.
+//! Secret-dependent scalar multiplication and reduction avoid data-dependent branches, but
+//! the code has not been audited for side channels.
+
+use super::sha512::Sha512;
+
+pub const PUBLIC_KEY_LEN: usize = 32;
+pub const SEED_LEN: usize = 32;
+pub const SIGNATURE_LEN: usize = 64;
+
+const LIMB_MASK: u64 = (1 << 51) - 1;
+
+/// Curve constant `d = -121665 / 121666`, little-endian.
+const D_BYTES: [u8; 32] = [
+    0xa3, 0x78, 0x59, 0x13, 0xca, 0x4d, 0xeb, 0x75, 0xab, 0xd8, 0x41, 0x41, 0x4d, 0x0a, 0x70, 0x00,
+    0x98, 0xe8, 0x79, 0x77, 0x79, 0x40, 0xc7, 0x8c, 0x73, 0xfe, 0x6f, 0x2b, 0xee, 0x6c, 0x03, 0x52,
+];
+/// `sqrt(-1) = 2^((p - 1) / 4)`, little-endian.
+const SQRT_M1_BYTES: [u8; 32] = [
+    0xb0, 0xa0, 0x0e, 0x4a, 0x27, 0x1b, 0xee, 0xc4, 0x78, 0xe4, 0x2f, 0xad, 0x06, 0x18, 0x43, 0x2f,
+    0xa7, 0xd7, 0xfb, 0x3d, 0x99, 0x00, 0x4d, 0x2b, 0x0b, 0xdf, 0xc1, 0x4f, 0x80, 0x24, 0x83, 0x2b,
+];
+/// Compressed base point (`y = 4/5`, even `x`).
+const BASE_BYTES: [u8; 32] = [
+    0x58, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+    0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66, 0x66,
+];
+/// `p - 2`, the inversion exponent, little-endian.
+const P_MINUS_2: [u8; 32] = {
+    let mut bytes = [0xff; 32];
+    bytes[0] = 0xeb;
+    bytes[31] = 0x7f;
+    bytes
+};
+/// `(p - 5) / 8`, the square-root exponent, little-endian.
+const P_MINUS_5_DIV_8: [u8; 32] = {
+    let mut bytes = [0xff; 32];
+    bytes[0] = 0xfd;
+    bytes[31] = 0x0f;
+    bytes
+};
+
+/// Group order `L = 2^252 + 27742317777372353535851937790883648493`, little-endian limbs.
+const ORDER: [u64; 4] = [
+    0x5812_631a_5cf5_d3ed,
+    0x14de_f9de_a2f7_9cd6,
+    0,
+    0x1000_0000_0000_0000,
+];
+
+/// Element of GF(2^255 - 19).
+#[derive(Clone, Copy, Debug)]
+struct Fe([u64; 5]);
+
+impl Fe {
+    const ZERO: Self = Self([0; 5]);
+    const ONE: Self = Self([1, 0, 0, 0, 0]);
+
+    fn from_bytes(bytes: &[u8; 32]) -> Self {
+        let load = |offset: usize| {
+            u64::from_le_bytes(bytes[offset..offset + 8].try_into().expect("eight bytes"))
+        };
+        Self([
+            load(0) & LIMB_MASK,
+            (load(6) >> 3) & LIMB_MASK,
+            (load(12) >> 6) & LIMB_MASK,
+            (load(19) >> 1) & LIMB_MASK,
+            (load(24) >> 12) & LIMB_MASK,
+        ])
+    }
+
+    /// Carry each limb into the next, folding the top carry back in times 19.
+    fn carry(mut limbs: [u64; 5]) -> Self {
+        for idx in 0..4 {
+            limbs[idx + 1] += limbs[idx] >> 51;
+            limbs[idx] &= LIMB_MASK;
+        }
+        limbs[0] += 19 * (limbs[4] >> 51);
+        limbs[4] &= LIMB_MASK;
+        Self(limbs)
+    }
+
+    /// Canonical little-endian encoding.
+    fn to_bytes(self) -> [u8; 32] {
+        let mut limbs = Self::carry(Self::carry(self.0).0).0;
+        // Add 19 and see whether it carries out of bit 255, i.e. whether the value is >= p.
+        let mut overflow = (limbs[0] + 19) >> 51;
+        for limb in &limbs[1..] {
+            overflow = (limb + overflow) >> 51;
+        }
+        limbs[0] += 19 * overflow;
+        for idx in 0..4 {
+            limbs[idx + 1] += limbs[idx] >> 51;
+            limbs[idx] &= LIMB_MASK;
+        }
+        limbs[4] &= LIMB_MASK;
+
+        let mut out = [0u8; 32];
+        let mut acc = 0u128;
+        let mut acc_bits = 0;
+        let mut idx = 0;
+        for limb in limbs {
+            acc |= u128::from(limb) << acc_bits;
+            acc_bits += 51;
+            while acc_bits >= 8 {
+                out[idx] = acc.to_le_bytes()[0];
+                acc >>= 8;
+                acc_bits -= 8;
+                idx += 1;
+            }
+        }
+        out[idx] = acc.to_le_bytes()[0];
+        out
+    }
+
+    fn add(self, other: Self) -> Self {
+        let mut limbs = self.0;
+        for (limb, rhs) in limbs.iter_mut().zip(other.0) {
+            *limb += rhs;
+        }
+        Self::carry(limbs)
+    }
+
+    fn sub(self, other: Self) -> Self {
+        // Add 16p so every limb stays positive.
+        const SIXTEEN_P: [u64; 5] = [
+            (LIMB_MASK - 18) * 16,
+            LIMB_MASK * 16,
+            LIMB_MASK * 16,
+            LIMB_MASK * 16,
+            LIMB_MASK * 16,
+        ];
+        let mut limbs = [0u64; 5];
+        for idx in 0..5 {
+            limbs[idx] = self.0[idx] + SIXTEEN_P[idx] - other.0[idx];
+        }
+        Self::carry(limbs)
+    }
+
+    fn neg(self) -> Self {
+        Self::ZERO.sub(self)
+    }
+
+    #[allow(clippy::cast_possible_truncation)] // carries are split into 51-bit limbs
+    fn mul(self, other: Self) -> Self {
+        let [a0, a1, a2, a3, a4] = self.0.map(u128::from);
+        let [b0, b1, b2, b3, b4] = other.0.map(u128::from);
+        let (b1_19, b2_19, b3_19, b4_19) = (b1 * 19, b2 * 19, b3 * 19, b4 * 19);
+
+        let mut wide = [
+            a0 * b0 + a1 * b4_19 + a2 * b3_19 + a3 * b2_19 + a4 * b1_19,
+            a0 * b1 + a1 * b0 + a2 * b4_19 + a3 * b3_19 + a4 * b2_19,
+            a0 * b2 + a1 * b1 + a2 * b0 + a3 * b4_19 + a4 * b3_19,
+            a0 * b3 + a1 * b2 + a2 * b1 + a3 * b0 + a4 * b4_19,
+            a0 * b4 + a1 * b3 + a2 * b2 + a3 * b1 + a4 * b0,
+        ];
+        for idx in 0..4 {
+            wide[idx + 1] += wide[idx] >> 51;
+            wide[idx] &= u128::from(LIMB_MASK);
+        }
+        wide[0] += 19 * (wide[4] >> 51);
+        wide[4] &= u128::from(LIMB_MASK);
+        Self::carry(wide.map(|limb| limb as u64))
+    }
+
+    fn square(self) -> Self {
+        self.mul(self)
+    }
+
+    /// Raise to a public little-endian exponent.
+    fn pow(self, exponent: &[u8; 32]) -> Self {
+        let mut result = Self::ONE;
+        for bit in (0..256).rev() {
+            result = result.square();
+            if (exponent[bit / 8] >> (bit % 8)) & 1 == 1 {
+                result = result.mul(self);
+            }
+        }
+        result
+    }
+
+    fn invert(self) -> Self {
+        self.pow(&P_MINUS_2)
+    }
+
+    fn is_negative(self) -> bool {
+        self.to_bytes()[0] & 1 == 1
+    }
+
+    fn ct_eq(self, other: Self) -> bool {
+        let (lhs, rhs) = (self.to_bytes(), other.to_bytes());
+        lhs.iter().zip(rhs).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+
+    /// `choice` must be 0 or 1; picks `other` when it is 1 without branching.
+    fn select(self, other: Self, choice: u64) -> Self {
+        let mask = choice.wrapping_neg();
+        let mut limbs = self.0;
+        for (limb, rhs) in limbs.iter_mut().zip(other.0) {
+            *limb ^= (*limb ^ rhs) & mask;
+        }
+        Self(limbs)
+    }
+}
+
+/// Point in extended coordinates: `x = X/Z`, `y = Y/Z`, `xy = T/Z`.
+#[derive(Clone, Copy, Debug)]
+struct Point {
+    x: Fe,
+    y: Fe,
+    z: Fe,
+    t: Fe,
+}
+
+impl Point {
+    const IDENTITY: Self = Self {
+        x: Fe::ZERO,
+        y: Fe::ONE,
+        z: Fe::ONE,
+        t: Fe::ZERO,
+    };
+
+    fn base() -> Self {
+        Self::decompress(&BASE_BYTES).expect("base point decodes")
+    }
+
+    /// Decode a compressed point (RFC 8032 section 5.1.3), rejecting non-canonical `y`.
+    #[allow(clippy::many_single_char_names)]
+    fn decompress(bytes: &[u8; 32]) -> Option<Self> {
+        let sign = bytes[31] >> 7;
+        let mut y_bytes = *bytes;
+        y_bytes[31] &= 0x7f;
+        let y = Fe::from_bytes(&y_bytes);
+        if y.to_bytes() != y_bytes {
+            return None;
+        }
+
+        let d = Fe::from_bytes(&D_BYTES);
+        let y2 = y.square();
+        let u = y2.sub(Fe::ONE);
+        let v = d.mul(y2).add(Fe::ONE);
+        let v3 = v.square().mul(v);
+        let v7 = v3.square().mul(v);
+        let mut x = u.mul(v3).mul(u.mul(v7).pow(&P_MINUS_5_DIV_8));
+
+        let vx2 = v.mul(x.square());
+        if !vx2.ct_eq(u) {
+            if vx2.ct_eq(u.neg()) {
+                x = x.mul(Fe::from_bytes(&SQRT_M1_BYTES));
+            } else {
+                return None;
+            }
+        }
+        if x.ct_eq(Fe::ZERO) && sign == 1 {
+            return None;
+        }
+        if u8::from(x.is_negative()) != sign {
+            x = x.neg();
+        }
+        Some(Self {
+            x,
+            y,
+            z: Fe::ONE,
+            t: x.mul(y),
+        })
+    }
+
+    fn compress(self) -> [u8; 32] {
+        let z_inv = self.z.invert();
+        let x = self.x.mul(z_inv);
+        let mut bytes = self.y.mul(z_inv).to_bytes();
+        bytes[31] ^= u8::from(x.is_negative()) << 7;
+        bytes
+    }
+
+    /// Unified addition (add-2008-hwcd-3); also valid for doubling.
+    #[allow(clippy::many_single_char_names)]
+    fn add(self, other: Self) -> Self {
+        let d2 = Fe::from_bytes(&D_BYTES).add(Fe::from_bytes(&D_BYTES));
+        let a = self.y.sub(self.x).mul(other.y.sub(other.x));
+        let b = self.y.add(self.x).mul(other.y.add(other.x));
+        let c = self.t.mul(d2).mul(other.t);
+        let d = self.z.add(self.z).mul(other.z);
+        let (e, f, g, h) = (b.sub(a), d.sub(c), d.add(c), b.add(a));
+        Self {
+            x: e.mul(f),
+            y: g.mul(h),
+            z: f.mul(g),
+            t: e.mul(h),
+        }
+    }
+
+    fn neg(self) -> Self {
+        Self {
+            x: self.x.neg(),
+            y: self.y,
+            z: self.z,
+            t: self.t.neg(),
+        }
+    }
+
+    fn select(self, other: Self, choice: u64) -> Self {
+        Self {
+            x: self.x.select(other.x, choice),
+            y: self.y.select(other.y, choice),
+            z: self.z.select(other.z, choice),
+            t: self.t.select(other.t, choice),
+        }
+    }
+
+    /// `[scalar]self` for a little-endian scalar, always doing the same work per bit.
+    fn mul(self, scalar: &[u8; 32]) -> Self {
+        let mut acc = Self::IDENTITY;
+        for bit in (0..256).rev() {
+            acc = acc.add(acc);
+            let with = acc.add(self);
+            acc = acc.select(with, u64::from((scalar[bit / 8] >> (bit % 8)) & 1));
+        }
+        acc
+    }
+}
+
+/// Subtract `L` when `value >= L`, without branching.
+fn reduce_once(value: [u64; 4]) -> [u64; 4] {
+    let mut diff = [0u64; 4];
+    let mut borrow = 0u64;
+    for idx in 0..4 {
+        let (step, under1) = value[idx].overflowing_sub(ORDER[idx]);
+        let (step, under2) = step.overflowing_sub(borrow);
+        diff[idx] = step;
+        borrow = u64::from(under1 | under2);
+    }
+    // `borrow` is 1 when value < L: keep the original.
+    let keep = borrow.wrapping_neg();
+    let mut out = [0u64; 4];
+    for idx in 0..4 {
+        out[idx] = (value[idx] & keep) | (diff[idx] & !keep);
+    }
+    out
+}
+
+/// Reduce a little-endian multi-limb integer modulo `L`, one bit at a time.
+fn reduce_wide(wide: &[u64]) -> [u64; 4] {
+    let mut acc = [0u64; 4];
+    for bit in (0..wide.len() * 64).rev() {
+        let mut carry = (wide[bit / 64] >> (bit % 64)) & 1;
+        for limb in &mut acc {
+            let next = *limb >> 63;
+            *limb = (*limb << 1) | carry;
+            carry = next;
+        }
+        acc = reduce_once(acc);
+    }
+    acc
+}
+
+fn scalar_limbs(bytes: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    bytes
+        .chunks_exact(8)
+        .map(|chunk| u64::from_le_bytes(chunk.try_into().expect("eight bytes")))
+}
+
+fn scalar_bytes(limbs: [u64; 4]) -> [u8; 32] {
+    let mut out = [0u8; 32];
+    for (chunk, limb) in out.chunks_exact_mut(8).zip(limbs) {
+        chunk.copy_from_slice(&limb.to_le_bytes());
+    }
+    out
+}
+
+/// Reduce a 64-byte hash output modulo `L`.
+fn reduce_hash(hash: &[u8; 64]) -> [u8; 32] {
+    let limbs: Vec<u64> = scalar_limbs(hash).collect();
+    scalar_bytes(reduce_wide(&limbs))
+}
+
+/// `(a * b + c) mod L`.
+#[allow(clippy::cast_possible_truncation)] // splitting 128-bit products into limbs
+fn mul_add(a: &[u8; 32], b: &[u8; 32], c: &[u8; 32]) -> [u8; 32] {
+    let a: Vec<u64> = scalar_limbs(a).collect();
+    let b: Vec<u64> = scalar_limbs(b).collect();
+    let mut wide = [0u64; 9];
+    for (idx, limb) in scalar_limbs(c).enumerate() {
+        wide[idx] = limb;
+    }
+    for (i, &lhs) in a.iter().enumerate() {
+        let mut carry = 0u128;
+        for (j, &rhs) in b.iter().enumerate() {
+            let sum = u128::from(wide[i + j]) + u128::from(lhs) * u128::from(rhs) + carry;
+            wide[i + j] = sum as u64;
+            carry = sum >> 64;
+        }
+        let mut idx = i + b.len();
+        while carry != 0 {
+            let sum = u128::from(wide[idx]) + carry;
+            wide[idx] = sum as u64;
+            carry = sum >> 64;
+            idx += 1;
+        }
+    }
+    scalar_bytes(reduce_wide(&wide))
+}
+
+fn is_canonical_scalar(bytes: &[u8; 32]) -> bool {
+    let limbs: Vec<u64> = scalar_limbs(bytes).collect();
+    let mut borrow = false;
+    for (limb, order) in limbs.iter().zip(ORDER) {
+        let (step, under1) = limb.overflowing_sub(order);
+        let (_, under2) = step.overflowing_sub(u64::from(borrow));
+        borrow = under1 | under2;
+    }
+    borrow
+}
+
+/// Clamped secret scalar and nonce prefix expanded from a seed.
+fn expand_seed(seed: &[u8; SEED_LEN]) -> ([u8; 32], [u8; 32]) {
+    let hash = Sha512::digest(seed);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&hash[..32]);
+    scalar[0] &= 0xf8;
+    scalar[31] &= 0x7f;
+    scalar[31] |= 0x40;
+    let mut prefix = [0u8; 32];
+    prefix.copy_from_slice(&hash[32..]);
+    (scalar, prefix)
+}
+
+fn challenge(r: &[u8; 32], public: &[u8; PUBLIC_KEY_LEN], message: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha512::new();
+    hasher.update(r);
+    hasher.update(public);
+    hasher.update(message);
+    reduce_hash(&hasher.finalize())
+}
+
+/// Derive the public key for `seed`.
+#[must_use]
+pub fn public_key(seed: &[u8; SEED_LEN]) -> [u8; PUBLIC_KEY_LEN] {
+    let (scalar, _) = expand_seed(seed);
+    Point::base().mul(&scalar).compress()
+}
+
+/// Sign `message` with the key pair (`seed`, `public`).
+#[must_use]
+pub fn sign(
+    seed: &[u8; SEED_LEN],
+    public: &[u8; PUBLIC_KEY_LEN],
+    message: &[u8],
+) -> [u8; SIGNATURE_LEN] {
+    let (scalar, prefix) = expand_seed(seed);
+    let mut hasher = Sha512::new();
+    hasher.update(&prefix);
+    hasher.update(message);
+    let nonce = reduce_hash(&hasher.finalize());
+
+    let r = Point::base().mul(&nonce).compress();
+    let k = challenge(&r, public, message);
+    let s = mul_add(&k, &scalar, &nonce);
+
+    let mut signature = [0u8; SIGNATURE_LEN];
+    signature[..32].copy_from_slice(&r);
+    signature[32..].copy_from_slice(&s);
+    signature
+}
+
+/// Check `signature` over `message` against `public` (`[S]B == R + [k]A`).
+#[must_use]
+pub fn verify(
+    public: &[u8; PUBLIC_KEY_LEN],
+    message: &[u8],
+    signature: &[u8; SIGNATURE_LEN],
+) -> bool {
+    let (r, s) = signature.split_at(32);
+    let r: &[u8; 32] = r.try_into().expect("32 bytes");
+    let s: &[u8; 32] = s.try_into().expect("32 bytes");
+    if !is_canonical_scalar(s) {
+        return false;
+    }
+    let Some(a) = Point::decompress(public) else {
+        return false;
+    };
+    let k = challenge(r, public, message);
+    let check = Point::base().mul(s).add(a.neg().mul(&k));
+    check.compress() == *r
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unhex<const N: usize>(hex: &str) -> [u8; N] {
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap())
+            .collect();
+        bytes.try_into().unwrap()
+    }
+
+    #[test]
+    fn curve_constants_are_consistent() {
+        let d = Fe::from_bytes(&D_BYTES);
+        let lhs = d.mul(Fe([121_666, 0, 0, 0, 0]));
+        assert!(lhs.ct_eq(Fe([121_665, 0, 0, 0, 0]).neg()));
+        let i = Fe::from_bytes(&SQRT_M1_BYTES);
+        assert!(i.square().ct_eq(Fe::ONE.neg()));
+        assert_eq!(Point::base().compress(), BASE_BYTES);
+    }
+
+    #[test]
+    fn rfc8032_test_vectors() {
+        // RFC 8032 section 7.1, tests 1-3.
+        let vectors: [(&str, &str, &str, &str); 3] = [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            (
+                "4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb",
+                "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c",
+                "72",
+                "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00",
+            ),
+            (
+                "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+                "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+                "af82",
+                "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+            ),
+        ];
+        for (seed, public, message, signature) in vectors {
+            let seed: [u8; 32] = unhex(seed);
+            let public: [u8; 32] = unhex(public);
+            let message: Vec<u8> = (0..message.len())
+                .step_by(2)
+                .map(|idx| u8::from_str_radix(&message[idx..idx + 2], 16).unwrap())
+                .collect();
+            let signature: [u8; 64] = unhex(signature);
+
+            assert_eq!(public_key(&seed), public);
+            assert_eq!(sign(&seed, &public, &message), signature);
+            assert!(verify(&public, &message, &signature));
+        }
+    }
+
+    #[test]
+    fn forged_or_mismatched_signatures_rejected() {
+        let seed = [7u8; 32];
+        let public = public_key(&seed);
+        let signature = sign(&seed, &public, b"transcript");
+        assert!(verify(&public, b"transcript", &signature));
+
+        assert!(!verify(&public, b"transcript!", &signature));
+        let other = public_key(&[8u8; 32]);
+        assert!(!verify(&other, b"transcript", &signature));
+        for idx in [0, 31, 32, 63] {
+            let mut forged = signature;
+            forged[idx] ^= 0x01;
+            assert!(!verify(&public, b"transcript", &forged), "byte {idx}");
+        }
+
+        // S + L verifies mathematically but is non-canonical and must be rejected.
+        let mut malleated = signature;
+        let s: [u8; 32] = signature[32..].try_into().unwrap();
+        let mut carry = 0u128;
+        let mut bumped = [0u64; 4];
+        for (idx, (limb, order)) in scalar_limbs(&s).zip(ORDER).enumerate() {
+            let sum = u128::from(limb) + u128::from(order) + carry;
+            bumped[idx] = u64::try_from(sum & u128::from(u64::MAX)).unwrap();
+            carry = sum >> 64;
+        }
+        malleated[32..].copy_from_slice(&scalar_bytes(bumped));
+        assert!(!verify(&public, b"transcript", &malleated));
+    }
+}
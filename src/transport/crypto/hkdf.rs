@@ -1,19 +1,53 @@
-//! HKDF extract/expand helpers based on HMAC-SHA256.
+//! HKDF extract/expand helpers based on HMAC-SHA256 or HMAC-BLAKE3.
 
-use super::CryptoError;
-use super::hmac::HmacSha256;
+use super::blake3::Blake3;
+use super::hmac::Hmac;
+use super::sha256::Sha256;
+use super::{CryptoError, Digest, HashAlgorithm};
 
 const HASH_LEN: usize = 32;
 const MAX_BLOCKS: usize = 255;
 
 #[must_use]
 pub fn extract(salt: &[u8], ikm: &[u8]) -> [u8; HASH_LEN] {
+    extract_generic::<Sha256>(salt, ikm)
+}
+
+pub fn expand(prk: &[u8; HASH_LEN], info: &[u8], okm: &mut [u8]) -> Result<(), CryptoError> {
+    expand_generic::<Sha256>(prk, info, okm)
+}
+
+#[must_use]
+pub fn extract_with(hash: HashAlgorithm, salt: &[u8], ikm: &[u8]) -> [u8; HASH_LEN] {
+    match hash {
+        HashAlgorithm::Sha256 => extract_generic::<Sha256>(salt, ikm),
+        HashAlgorithm::Blake3 => extract_generic::<Blake3>(salt, ikm),
+    }
+}
+
+pub fn expand_with(
+    hash: HashAlgorithm,
+    prk: &[u8; HASH_LEN],
+    info: &[u8],
+    okm: &mut [u8],
+) -> Result<(), CryptoError> {
+    match hash {
+        HashAlgorithm::Sha256 => expand_generic::<Sha256>(prk, info, okm),
+        HashAlgorithm::Blake3 => expand_generic::<Blake3>(prk, info, okm),
+    }
+}
+
+fn extract_generic<D: Digest>(salt: &[u8], ikm: &[u8]) -> [u8; HASH_LEN] {
     let default_salt = [0u8; HASH_LEN];
     let key = if salt.is_empty() { &default_salt } else { salt };
-    HmacSha256::compute(key, ikm)
+    Hmac::<D>::compute(key, ikm)
 }
 
-pub fn expand(prk: &[u8; HASH_LEN], info: &[u8], okm: &mut [u8]) -> Result<(), CryptoError> {
+fn expand_generic<D: Digest>(
+    prk: &[u8; HASH_LEN],
+    info: &[u8],
+    okm: &mut [u8],
+) -> Result<(), CryptoError> {
     if okm.is_empty() {
         return Ok(());
     }
@@ -27,7 +61,7 @@ pub fn expand(prk: &[u8; HASH_LEN], info: &[u8], okm: &mut [u8]) -> Result<(), C
     let mut generated = 0;
 
     for counter in 1..=blocks {
-        let mut hmac = HmacSha256::new(prk);
+        let mut hmac = Hmac::<D>::new(prk);
         if generated > 0 {
             hmac.update(&prev);
         }
@@ -126,6 +160,29 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rfc_5869_test_case_3_via_public_api() {
+        // Zero-length salt and info.
+        let prk = super::super::hkdf_extract(&[], &[0x0b; 22]);
+        assert_eq!(
+            hex(&prk),
+            "19ef24a32c717b167f33a91d6f648bdf96596776afdb6377ac434c1c293ccb04"
+        );
+        let okm = super::super::hkdf_expand(&prk, &[], 42).expect("hkdf expand");
+        let expected_okm = "
+            8da4e775a563c18f715f802a063c5a31
+            b8a11f5c5ee1879ec3454e5f3c738d2d
+            9d201395faa4b61a96c8";
+        assert_eq!(
+            hex(&okm),
+            expected_okm.split_whitespace().collect::<String>()
+        );
+        assert_eq!(
+            super::super::hkdf_expand(&prk, &[], HASH_LEN * MAX_BLOCKS + 1),
+            Err(CryptoError::KeyDerivationFailed)
+        );
+    }
+
     #[test]
     fn expand_rejects_oversized_okm() {
         let prk = [0u8; HASH_LEN];
@@ -1,22 +1,26 @@
-//! HMAC-SHA256 implementation built on the in-house SHA-256.
+//! HMAC (RFC 2104) over the in-house hash functions.
 
+use super::Digest;
 use super::sha256::Sha256;
 
+/// Input block size shared by SHA-256 and BLAKE3.
 const BLOCK_SIZE: usize = 64;
 
+pub type HmacSha256 = Hmac<Sha256>;
+
 #[derive(Clone)]
-pub struct HmacSha256 {
-    inner: Sha256,
-    outer: Sha256,
+pub struct Hmac<D> {
+    inner: D,
+    outer: D,
 }
 
-impl HmacSha256 {
+impl<D: Digest> Hmac<D> {
     #[must_use]
     pub fn new(key: &[u8]) -> Self {
         let mut key_block = [0u8; BLOCK_SIZE];
 
         if key.len() > BLOCK_SIZE {
-            let hashed = Sha256::digest(key);
+            let hashed = D::digest(key);
             key_block[..hashed.len()].copy_from_slice(&hashed);
         } else {
             key_block[..key.len()].copy_from_slice(key);
@@ -29,10 +33,10 @@ impl HmacSha256 {
             i_key_pad[idx] = byte ^ 0x36;
         }
 
-        let mut inner = Sha256::new();
+        let mut inner = D::default();
         inner.update(&i_key_pad);
 
-        let mut outer = Sha256::new();
+        let mut outer = D::default();
         outer.update(&o_key_pad);
 
         Self { inner, outer }
@@ -89,4 +93,44 @@ mod tests {
         let expected = "773ea91e36800e46854db8ebd09181a72959098b3ef8c122d9635514ced565fe";
         assert_eq!(hex(&HmacSha256::compute(&key, &data)), expected);
     }
+
+    #[test]
+    fn rfc_4231_case_4() {
+        let key: Vec<u8> = (0x01..=0x19).collect();
+        let data = [0xcdu8; 50];
+        let expected = "82558a389a443c0ea4cc819899f2083a85f0faa3e578f8077a2e3ff46729665b";
+        assert_eq!(hex(&HmacSha256::compute(&key, &data)), expected);
+    }
+
+    #[test]
+    fn rfc_4231_case_5_truncated() {
+        let key = [0x0cu8; 20];
+        let data = b"Test With Truncation";
+        let expected = "a3b6167473100ee06e0c796c2955552b";
+        assert_eq!(hex(&HmacSha256::compute(&key, data)[..16]), expected);
+    }
+
+    #[test]
+    fn rfc_4231_cases_6_and_7_hash_long_keys() {
+        let key = [0xaau8; 131];
+        let data = b"Test Using Larger Than Block-Size Key - Hash Key First";
+        let expected = "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54";
+        assert_eq!(hex(&HmacSha256::compute(&key, data)), expected);
+
+        let data = b"This is a test using a larger than block-size key and a larger than \
+            block-size data. The key needs to be hashed before being used by the HMAC algorithm.";
+        let expected = "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2";
+        assert_eq!(hex(&HmacSha256::compute(&key, data)), expected);
+    }
+
+    #[test]
+    fn incremental_updates_match_compute() {
+        let mut hmac = HmacSha256::new(b"key");
+        hmac.update(b"The quick brown fox ");
+        hmac.update(b"jumps over the lazy dog");
+        assert_eq!(
+            hex(&hmac.finalize()),
+            "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
 }
@@ -2,41 +2,90 @@
 //!
 //! Clean implementation using straightforward 130-bit arithmetic.
 
-/// Compute Poly1305 MAC tag.
-pub fn poly1305_tag(msg: &[u8], key: &[u8; 32]) -> [u8; 16] {
-    // Parse and clamp r
-    let mut r_bytes = [0u8; 16];
-    r_bytes.copy_from_slice(&key[0..16]);
-    r_bytes[3] &= 15;
-    r_bytes[7] &= 15;
-    r_bytes[11] &= 15;
-    r_bytes[15] &= 15;
-    r_bytes[4] &= 252;
-    r_bytes[8] &= 252;
-    r_bytes[12] &= 252;
-    let r = u128::from_le_bytes(r_bytes);
-
-    // Parse s
-    let s = u128::from_le_bytes(key[16..32].try_into().unwrap());
-
-    // Process message blocks
-    let mut accumulator = U130::zero();
-
-    for chunk in msg.chunks(16) {
+/// Incremental Poly1305: input may be fed in pieces of any size.
+#[derive(Clone)]
+pub struct Poly1305 {
+    r: u128,
+    s: u128,
+    accumulator: U130,
+    buffer: [u8; 16],
+    buffered: usize,
+}
+
+impl Poly1305 {
+    pub fn new(key: &[u8; 32]) -> Self {
+        // Parse and clamp r
+        let mut r_bytes = [0u8; 16];
+        r_bytes.copy_from_slice(&key[0..16]);
+        r_bytes[3] &= 15;
+        r_bytes[7] &= 15;
+        r_bytes[11] &= 15;
+        r_bytes[15] &= 15;
+        r_bytes[4] &= 252;
+        r_bytes[8] &= 252;
+        r_bytes[12] &= 252;
+
+        Self {
+            r: u128::from_le_bytes(r_bytes),
+            s: u128::from_le_bytes(key[16..32].try_into().unwrap()),
+            accumulator: U130::zero(),
+            buffer: [0u8; 16],
+            buffered: 0,
+        }
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.buffered > 0 {
+            let take = (16 - self.buffered).min(data.len());
+            self.buffer[self.buffered..self.buffered + take].copy_from_slice(&data[..take]);
+            self.buffered += take;
+            data = &data[take..];
+            if self.buffered < 16 {
+                return;
+            }
+            let block = self.buffer;
+            self.process_block(&block);
+            self.buffered = 0;
+        }
+
+        let mut blocks = data.chunks_exact(16);
+        for block in &mut blocks {
+            self.process_block(block);
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffered = rest.len();
+    }
+
+    /// Zero-pad buffered input up to the next 16-byte boundary (RFC 8439 AEAD padding).
+    pub fn pad(&mut self) {
+        if self.buffered > 0 {
+            self.buffer[self.buffered..].fill(0);
+            let block = self.buffer;
+            self.process_block(&block);
+            self.buffered = 0;
+        }
+    }
+
+    pub fn finalize(mut self) -> [u8; 16] {
+        if self.buffered > 0 {
+            let block = self.buffer;
+            self.process_block(&block[..self.buffered]);
+        }
+        // Add s and return lower 128 bits
+        self.accumulator.add_u128(self.s).to_bytes()
+    }
+
+    /// accumulator = (accumulator + block) * r mod P, for a block of at most 16 bytes.
+    fn process_block(&mut self, chunk: &[u8]) {
         // Create block with padding
         let mut block_bytes = [0u8; 17];
         block_bytes[..chunk.len()].copy_from_slice(chunk);
         block_bytes[chunk.len()] = 1;
 
-        // Convert to U130
         let block = U130::from_bytes(&block_bytes);
-
-        // accumulator = (accumulator + block) * r mod P
-        accumulator = accumulator.add(block).mul_mod_p(r);
+        self.accumulator = self.accumulator.add(block).mul_mod_p(self.r);
     }
-
-    // Add s and return lower 128 bits
-    accumulator.add_u128(s).to_bytes()
 }
 
 // 130-bit unsigned integer (for poly1305 arithmetic)
@@ -162,6 +211,12 @@ fn mul_u128(a: u128, b: u128) -> (u128, u128) {
 mod tests {
     use super::*;
 
+    fn poly1305_tag(msg: &[u8], key: &[u8; 32]) -> [u8; 16] {
+        let mut mac = Poly1305::new(key);
+        mac.update(msg);
+        mac.finalize()
+    }
+
     fn hex(bytes: &[u8]) -> String {
         bytes.iter().map(|b| format!("{b:02x}")).collect()
     }
@@ -180,6 +235,19 @@ mod tests {
         assert_eq!(hex(&tag), expected);
     }
 
+    #[test]
+    fn incremental_matches_one_shot() {
+        let key: [u8; 32] = core::array::from_fn(|idx| u8::try_from(idx * 7).unwrap());
+        let msg: Vec<u8> = (0..300u16).map(|idx| (idx % 251) as u8).collect();
+        for split in [1, 15, 16, 17, 100] {
+            let mut mac = Poly1305::new(&key);
+            for piece in msg.chunks(split) {
+                mac.update(piece);
+            }
+            assert_eq!(mac.finalize(), poly1305_tag(&msg, &key), "split {split}");
+        }
+    }
+
     #[test]
     fn empty_message_returns_s() {
         let key = [0u8; 32];
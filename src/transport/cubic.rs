@@ -0,0 +1,384 @@
+//! CUBIC congestion control (RFC 8312 window growth).
+//!
+//! After a loss the window drops by [`CUBIC_BETA`] and regrows along a cubic curve centred on
+//! the window at which the loss occurred: quickly at first (concave region), flattening out
+//! near the previous maximum, then probing beyond it (convex region).
+
+// Window arithmetic is done in floating point; windows are far below 2^52 bytes.
+#![allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+
+use std::time::{Duration, SystemTime};
+
+use tracing::debug;
+
+use super::congestion::{CongestionConfig, CongestionControl, duration_to_secs, release_inflight};
+use super::loss::{AckOutcome, SentPacketInfo};
+
+/// Cubic scaling constant (segments per second cubed).
+const CUBIC_C: f64 = 0.4;
+/// Multiplicative decrease applied to the window on loss.
+pub const CUBIC_BETA: f64 = 0.7;
+/// Segment size used to scale the cubic function.
+const SEGMENT_SIZE: f64 = 1200.0;
+/// Gain applied to `cwnd / srtt` when deriving the pacing rate.
+const PACING_GAIN: f64 = 1.25;
+/// RTT assumed until the first sample arrives.
+const INITIAL_RTT: Duration = Duration::from_millis(100);
+
+/// CUBIC congestion control state machine.
+#[derive(Debug)]
+pub struct CubicController {
+    config: CongestionConfig,
+    inflight_bytes: usize,
+    congestion_window: usize,
+    slow_start_threshold: usize,
+    /// Window (bytes) at the last congestion event.
+    w_max: f64,
+    /// Seconds from epoch start until the cubic curve reaches `w_max`.
+    k: f64,
+    epoch_start: Option<SystemTime>,
+    recovery_start: Option<SystemTime>,
+    /// Reno-equivalent window used for the TCP-friendly region.
+    w_est: f64,
+    smoothed_rtt: Option<Duration>,
+    pacing_rate: f64,
+}
+
+impl CubicController {
+    /// Create a controller starting in slow start.
+    #[must_use]
+    pub fn new(config: CongestionConfig) -> Self {
+        let mut controller = Self {
+            inflight_bytes: 0,
+            congestion_window: config.initial_window,
+            slow_start_threshold: usize::MAX,
+            w_max: 0.0,
+            k: 0.0,
+            epoch_start: None,
+            recovery_start: None,
+            w_est: 0.0,
+            smoothed_rtt: None,
+            pacing_rate: config.min_pacing_rate,
+            config,
+        };
+        controller.recompute_pacing();
+        controller
+    }
+
+    /// Window below which the controller is in slow start.
+    #[must_use]
+    pub fn slow_start_threshold(&self) -> usize {
+        self.slow_start_threshold
+    }
+
+    /// Cubic window (bytes) `elapsed` seconds into the current epoch.
+    fn cubic_window(&self, elapsed: f64) -> f64 {
+        CUBIC_C * (elapsed - self.k).powi(3) * SEGMENT_SIZE + self.w_max
+    }
+
+    fn start_epoch(&mut self, now: SystemTime) -> SystemTime {
+        let cwnd = self.congestion_window as f64;
+        if self.w_max < cwnd {
+            // No loss yet (or the window already passed the old maximum): probe convexly.
+            self.w_max = cwnd;
+            self.k = 0.0;
+        }
+        self.w_est = cwnd;
+        self.epoch_start = Some(now);
+        now
+    }
+
+    fn on_ack(&mut self, acked: usize, now: SystemTime) {
+        if self.congestion_window < self.slow_start_threshold {
+            self.congestion_window = self
+                .congestion_window
+                .saturating_add(acked)
+                .min(self.config.max_window);
+            return;
+        }
+
+        let epoch = match self.epoch_start {
+            Some(epoch) => epoch,
+            None => self.start_epoch(now),
+        };
+        let rtt = self.smoothed_rtt.unwrap_or(INITIAL_RTT);
+        let elapsed = duration_to_secs(now.duration_since(epoch).unwrap_or_default() + rtt);
+
+        let cwnd = self.congestion_window as f64;
+        let target = self.cubic_window(elapsed).clamp(cwnd, 1.5 * cwnd);
+        self.w_est +=
+            3.0 * (1.0 - CUBIC_BETA) / (1.0 + CUBIC_BETA) * SEGMENT_SIZE * acked as f64 / cwnd;
+
+        let next = if self.w_est > target {
+            self.w_est
+        } else {
+            cwnd + (target - cwnd) * acked as f64 / cwnd
+        };
+        self.congestion_window = (next as usize)
+            .max(self.congestion_window)
+            .min(self.config.max_window);
+    }
+
+    fn on_congestion_event(&mut self, now: SystemTime) {
+        let cwnd = self.congestion_window as f64;
+        // Fast convergence: release bandwidth when losses arrive below the previous maximum.
+        self.w_max = if cwnd < self.w_max {
+            cwnd * (1.0 + CUBIC_BETA) / 2.0
+        } else {
+            cwnd
+        };
+        self.congestion_window = ((cwnd * CUBIC_BETA) as usize).max(self.config.min_window);
+        self.slow_start_threshold = self.congestion_window;
+        let shortfall = (self.w_max - self.congestion_window as f64).max(0.0);
+        self.k = (shortfall / SEGMENT_SIZE / CUBIC_C).cbrt();
+        self.epoch_start = None;
+        self.recovery_start = Some(now);
+        debug!(
+            cwnd = self.congestion_window,
+            w_max = self.w_max,
+            k = self.k,
+            "cubic congestion event"
+        );
+    }
+
+    fn in_recovery(&self, time_sent: SystemTime) -> bool {
+        self.recovery_start.is_some_and(|start| time_sent <= start)
+    }
+
+    fn recompute_pacing(&mut self) {
+        let rtt = duration_to_secs(self.smoothed_rtt.unwrap_or(INITIAL_RTT)).max(1e-6);
+        self.pacing_rate = (PACING_GAIN * self.congestion_window as f64 / rtt)
+            .min(self.config.max_pacing_rate)
+            .max(self.config.min_pacing_rate);
+    }
+}
+
+impl CongestionControl for CubicController {
+    fn on_packet_sent(&mut self, size: usize) {
+        self.inflight_bytes = self.inflight_bytes.saturating_add(size);
+    }
+
+    fn on_ack_outcome(&mut self, outcome: &AckOutcome, now: SystemTime) {
+        if let Some(sample) = outcome.rtt_sample {
+            self.smoothed_rtt = Some(match self.smoothed_rtt {
+                Some(srtt) => (srtt * 7 + sample) / 8,
+                None => sample,
+            });
+        }
+
+        for pkt in &outcome.acknowledged {
+            release_inflight(&mut self.inflight_bytes, pkt.size());
+            if !self.in_recovery(pkt.time_sent()) {
+                self.on_ack(pkt.size(), now);
+            }
+        }
+
+        let mut congestion = false;
+        for pkt in &outcome.lost {
+            release_inflight(&mut self.inflight_bytes, pkt.size());
+            congestion |= !self.in_recovery(pkt.time_sent());
+        }
+        if outcome.ecn_ce_increase > 0 {
+            // CE marks count as a congestion event once per recovery period, keyed on
+            // the newest packet the ACK covers; the marked packets were still delivered.
+            let newest = outcome
+                .acknowledged
+                .iter()
+                .map(SentPacketInfo::time_sent)
+                .max();
+            congestion |= newest.is_some_and(|sent| !self.in_recovery(sent));
+        }
+        if congestion {
+            self.on_congestion_event(now);
+        }
+
+        self.recompute_pacing();
+    }
+
+    fn window(&self) -> usize {
+        self.congestion_window
+    }
+
+    fn pacing_rate(&self) -> f64 {
+        self.pacing_rate
+    }
+
+    fn bytes_in_flight(&self) -> usize {
+        self.inflight_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RTT: Duration = Duration::from_millis(100);
+
+    fn pkt(number: u64, sent: SystemTime) -> SentPacketInfo {
+        SentPacketInfo::new(number, sent, 1200, true)
+    }
+
+    /// Controller that has just reduced from a 100-segment window.
+    fn after_loss(base: SystemTime) -> CubicController {
+        let mut cc = CubicController::new(CongestionConfig {
+            initial_window: 100 * 1200,
+            ..CongestionConfig::default()
+        });
+        cc.on_packet_sent(1200);
+        cc.on_ack_outcome(
+            &AckOutcome {
+                acknowledged: Vec::new(),
+                lost: vec![pkt(0, base - RTT)],
+                rtt_sample: None,
+                ecn_ce_increase: 0,
+            },
+            base,
+        );
+        cc
+    }
+
+    /// Ack one full window per RTT, returning the window after each round.
+    fn run_rounds(cc: &mut CubicController, start: SystemTime, rounds: u32) -> Vec<usize> {
+        let mut windows = Vec::new();
+        let mut number = 1;
+        for round in 1..=rounds {
+            let now = start + RTT * round;
+            let acknowledged: Vec<_> = (0..cc.window() / 1200)
+                .map(|_| {
+                    number += 1;
+                    pkt(number, now - RTT)
+                })
+                .collect();
+            for packet in &acknowledged {
+                cc.on_packet_sent(packet.size());
+            }
+            cc.on_ack_outcome(
+                &AckOutcome {
+                    acknowledged,
+                    lost: Vec::new(),
+                    rtt_sample: Some(RTT),
+                    ecn_ce_increase: 0,
+                },
+                now,
+            );
+            windows.push(cc.window());
+        }
+        windows
+    }
+
+    #[test]
+    fn loss_reduces_by_beta() {
+        let cc = after_loss(SystemTime::now());
+        assert_eq!(cc.window(), 84_000);
+        assert_eq!(cc.slow_start_threshold(), 84_000);
+        assert!((cc.w_max - 120_000.0).abs() < f64::EPSILON);
+        assert!((cc.cubic_window(0.0) - 84_000.0).abs() < 1.0);
+        assert!((cc.cubic_window(cc.k) - cc.w_max).abs() < 1.0);
+    }
+
+    #[test]
+    fn growth_is_concave_then_convex() {
+        let base = SystemTime::now();
+        let mut cc = after_loss(base);
+        let k_rounds = (cc.k / duration_to_secs(RTT)).round() as usize;
+        let windows = run_rounds(&mut cc, base, u32::try_from(k_rounds * 2).unwrap());
+        let growth: Vec<usize> = windows.windows(2).map(|pair| pair[1] - pair[0]).collect();
+
+        // Concave: growth slows as the window approaches the previous maximum...
+        let early = growth[1];
+        let plateau = growth[k_rounds - 2..k_rounds + 2]
+            .iter()
+            .copied()
+            .min()
+            .unwrap();
+        assert!(plateau < early / 4, "early {early}, plateau {plateau}");
+        assert!(windows[k_rounds].abs_diff(120_000) < 4 * 1200);
+        // ...then convex: it accelerates again while probing beyond it.
+        let late = *growth.last().unwrap();
+        assert!(late > plateau * 4, "late {late}, plateau {plateau}");
+        assert!(*windows.last().unwrap() > 120_000);
+    }
+
+    #[test]
+    fn losses_within_recovery_reduce_once() {
+        let base = SystemTime::now();
+        let mut cc = after_loss(base);
+        let reduced = cc.window();
+        cc.on_packet_sent(2400);
+        cc.on_ack_outcome(
+            &AckOutcome {
+                acknowledged: Vec::new(),
+                lost: vec![pkt(1, base - RTT / 2)],
+                rtt_sample: None,
+                ecn_ce_increase: 0,
+            },
+            base + RTT / 2,
+        );
+        assert_eq!(cc.window(), reduced);
+
+        cc.on_ack_outcome(
+            &AckOutcome {
+                acknowledged: Vec::new(),
+                lost: vec![pkt(2, base + RTT / 4)],
+                rtt_sample: None,
+                ecn_ce_increase: 0,
+            },
+            base + RTT,
+        );
+        assert!(cc.window() < reduced);
+    }
+
+    #[test]
+    fn ce_marks_reduce_window_once_per_recovery() {
+        let base = SystemTime::now();
+        let mut cc = CubicController::new(CongestionConfig::default());
+        cc.on_packet_sent(3600);
+        let initial = cc.window();
+        let ce = |number, sent| AckOutcome {
+            acknowledged: vec![pkt(number, sent)],
+            lost: Vec::new(),
+            rtt_sample: Some(RTT),
+            ecn_ce_increase: 1,
+        };
+
+        cc.on_ack_outcome(&ce(1, base - RTT), base);
+        let reduced = cc.window();
+        assert!(reduced < initial);
+        assert_eq!(cc.bytes_in_flight(), 2400);
+
+        // Marks on packets sent before the reduction belong to the same event.
+        cc.on_ack_outcome(&ce(2, base - RTT / 2), base + RTT / 2);
+        assert_eq!(cc.window(), reduced);
+
+        cc.on_ack_outcome(&ce(3, base + RTT / 4), base + RTT);
+        assert!(cc.window() < reduced);
+        assert_eq!(cc.bytes_in_flight(), 0);
+    }
+
+    #[test]
+    fn slow_start_grows_by_acked_bytes() {
+        let mut cc = CubicController::new(CongestionConfig::default());
+        let now = SystemTime::now();
+        cc.on_packet_sent(2400);
+        assert!(cc.can_send(1200));
+        cc.on_ack_outcome(
+            &AckOutcome {
+                acknowledged: vec![pkt(1, now - RTT), pkt(2, now - RTT)],
+                lost: Vec::new(),
+                rtt_sample: Some(RTT),
+                ecn_ce_increase: 0,
+            },
+            now,
+        );
+        assert_eq!(
+            cc.window(),
+            CongestionConfig::default().initial_window + 2400
+        );
+        assert_eq!(cc.bytes_in_flight(), 0);
+        assert!(cc.pacing_rate() > CongestionConfig::default().min_pacing_rate);
+    }
+}
@@ -1,8 +1,16 @@
-//! Unreliable datagram queue with amplification guard integration.
+//! Datagram queues: outbound with amplification guard integration, and a bounded inbound
+//! queue for received DATAGRAM frames.
+//!
+//! Datagrams are unreliable by default. Payloads queued with
+//! [`DatagramQueue::enqueue_reliable`] are remembered per packet number and requeued when
+//! the loss manager declares their packet lost, giving at-least-once delivery without the
+//! head-of-line blocking of a stream. The receiver may see such a datagram more than once.
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 
 use super::anti_amplification::AntiAmplificationGuard;
+use super::loss::{AckOutcome, SentPacketInfo};
+use super::packet::{Frame, FrameType};
 use crate::protocol::metrics::Metrics;
 
 #[cfg(test)]
@@ -49,13 +57,32 @@ pub enum DatagramError {
         /// Configured maximum number of queued datagrams.
         capacity: usize,
     },
+    /// No received datagram is waiting.
+    #[error("no datagram received")]
+    Empty,
+    /// Datagram fragment header is truncated or inconsistent.
+    #[error("malformed datagram fragment")]
+    MalformedFragment,
+}
+
+#[derive(Debug, Clone)]
+struct QueuedDatagram {
+    payload: Vec<u8>,
+    /// Sequence number of a reliable datagram; `None` for unreliable ones.
+    sequence: Option<u64>,
 }
 
 /// Manage outbound datagram payloads with amplification awareness.
 #[derive(Debug)]
 pub struct DatagramQueue {
     config: DatagramConfig,
-    queue: VecDeque<Vec<u8>>,
+    queue: VecDeque<QueuedDatagram>,
+    next_sequence: u64,
+    /// Reliable datagrams dequeued since the last [`DatagramQueue::on_packet_sent`].
+    unsent: Vec<QueuedDatagram>,
+    /// Reliable datagrams awaiting acknowledgement, keyed by carrying packet number.
+    in_flight: HashMap<u64, Vec<QueuedDatagram>>,
+    retransmitted: u64,
 }
 
 impl DatagramQueue {
@@ -65,11 +92,30 @@ impl DatagramQueue {
         Self {
             queue: VecDeque::with_capacity(config.max_queue.min(64)),
             config,
+            next_sequence: 0,
+            unsent: Vec::new(),
+            in_flight: HashMap::new(),
+            retransmitted: 0,
         }
     }
 
     /// Enqueue a datagram payload.
     pub fn enqueue(&mut self, payload: Vec<u8>) -> Result<(), DatagramError> {
+        self.push(payload, None)
+    }
+
+    /// Enqueue a datagram that is retransmitted until its packet is acknowledged.
+    ///
+    /// Returns the datagram's sequence number. After sending the packet that carries it,
+    /// report the packet number with [`on_packet_sent`](Self::on_packet_sent).
+    pub fn enqueue_reliable(&mut self, payload: Vec<u8>) -> Result<u64, DatagramError> {
+        let sequence = self.next_sequence;
+        self.push(payload, Some(sequence))?;
+        self.next_sequence += 1;
+        Ok(sequence)
+    }
+
+    fn push(&mut self, payload: Vec<u8>, sequence: Option<u64>) -> Result<(), DatagramError> {
         if payload.len() > self.config.max_payload {
             return Err(DatagramError::PayloadTooLarge {
                 len: payload.len(),
@@ -84,10 +130,11 @@ impl DatagramQueue {
         trace!(
             len = payload.len(),
             queued = self.queue.len(),
+            ?sequence,
             "enqueue datagram payload"
         );
         Metrics::record_datagram_enqueued(payload.len());
-        self.queue.push_back(payload);
+        self.queue.push_back(QueuedDatagram { payload, sequence });
         Ok(())
     }
 
@@ -105,14 +152,180 @@ impl DatagramQueue {
 
     /// Attempt to dequeue a datagram when amplification budget permits.
     pub fn dequeue_with_guard(&mut self, guard: &mut AntiAmplificationGuard) -> Option<Vec<u8>> {
-        let payload = self.queue.front()?;
-        if guard.try_consume(payload.len()) {
-            trace!(len = payload.len(), "dequeue datagram payload");
-            Metrics::record_datagram_sent(payload.len());
-            self.queue.pop_front()
-        } else {
-            None
+        let len = self.queue.front()?.payload.len();
+        if !guard.try_consume(len) {
+            return None;
+        }
+        trace!(len, "dequeue datagram payload");
+        Metrics::record_datagram_sent(len);
+        let datagram = self.queue.pop_front()?;
+        if datagram.sequence.is_none() {
+            return Some(datagram.payload);
+        }
+        let payload = datagram.payload.clone();
+        self.unsent.push(datagram);
+        Some(payload)
+    }
+
+    /// Associate reliable datagrams dequeued since the previous call with `packet_number`.
+    pub fn on_packet_sent(&mut self, packet_number: u64) {
+        if self.unsent.is_empty() {
+            return;
+        }
+        let datagrams = std::mem::take(&mut self.unsent);
+        self.in_flight.insert(packet_number, datagrams);
+    }
+
+    /// Stop tracking acknowledged reliable datagrams and requeue those in lost packets.
+    ///
+    /// Returns the number of datagrams requeued.
+    pub fn on_ack_outcome(&mut self, outcome: &AckOutcome) -> usize {
+        for packet in &outcome.acknowledged {
+            self.in_flight.remove(&packet.packet_number());
+        }
+        self.on_packets_lost(&outcome.lost)
+    }
+
+    /// Requeue reliable datagrams carried by `lost` packets ahead of new datagrams.
+    ///
+    /// Returns the number of datagrams requeued.
+    pub fn on_packets_lost(&mut self, lost: &[SentPacketInfo]) -> usize {
+        let mut requeued = 0;
+        for packet in lost {
+            let Some(datagrams) = self.in_flight.remove(&packet.packet_number()) else {
+                continue;
+            };
+            requeued += datagrams.len();
+            for datagram in datagrams.into_iter().rev() {
+                trace!(
+                    sequence = datagram.sequence,
+                    packet_number = packet.packet_number(),
+                    "requeue lost reliable datagram"
+                );
+                self.queue.push_front(datagram);
+            }
+        }
+        self.retransmitted += requeued as u64;
+        requeued
+    }
+
+    /// Reliable datagrams sent and awaiting acknowledgement.
+    #[must_use]
+    pub fn reliable_in_flight(&self) -> usize {
+        self.in_flight.values().map(Vec::len).sum::<usize>() + self.unsent.len()
+    }
+
+    /// Total reliable datagrams requeued after loss.
+    #[must_use]
+    pub const fn retransmitted(&self) -> u64 {
+        self.retransmitted
+    }
+}
+
+/// Which datagram the receive queue discards when it is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DatagramDropPolicy {
+    /// Discard the oldest queued datagram to make room; favours fresh data.
+    #[default]
+    DropOldest,
+    /// Discard the datagram that just arrived.
+    DropNewest,
+}
+
+/// Bounded queue of received datagram payloads awaiting the application.
+#[derive(Debug)]
+pub struct DatagramReceiveQueue {
+    config: DatagramConfig,
+    policy: DatagramDropPolicy,
+    queue: VecDeque<Vec<u8>>,
+    dropped: u64,
+}
+
+impl DatagramReceiveQueue {
+    /// Construct a queue bounded by `config.max_queue`; payloads over `config.max_payload`
+    /// are dropped on arrival.
+    #[must_use]
+    pub fn new(config: DatagramConfig, policy: DatagramDropPolicy) -> Self {
+        Self {
+            queue: VecDeque::with_capacity(config.max_queue.min(64)),
+            config,
+            policy,
+            dropped: 0,
+        }
+    }
+
+    /// Queue a received payload, dropping one datagram per the policy when full.
+    ///
+    /// Returns `false` if `payload` itself was dropped.
+    pub fn push_received(&mut self, payload: Vec<u8>) -> bool {
+        Metrics::record_datagram_received(payload.len());
+        if payload.len() > self.config.max_payload || self.config.max_queue == 0 {
+            self.record_drop(payload.len());
+            return false;
+        }
+        if self.queue.len() >= self.config.max_queue {
+            match self.policy {
+                DatagramDropPolicy::DropNewest => {
+                    self.record_drop(payload.len());
+                    return false;
+                }
+                DatagramDropPolicy::DropOldest => {
+                    if let Some(oldest) = self.queue.pop_front() {
+                        self.record_drop(oldest.len());
+                    }
+                }
+            }
+        }
+        trace!(
+            len = payload.len(),
+            queued = self.queue.len(),
+            "queue received datagram"
+        );
+        self.queue.push_back(payload);
+        true
+    }
+
+    /// Route a decrypted frame: DATAGRAM payloads are queued, other frames are returned.
+    pub fn deliver_frame(&mut self, frame: Frame) -> Option<Frame> {
+        if frame.frame_type() != FrameType::Datagram {
+            return Some(frame);
         }
+        self.push_received(frame.into_payload());
+        None
+    }
+
+    /// Pop the oldest received datagram.
+    pub fn pop_received(&mut self) -> Option<Vec<u8>> {
+        self.queue.pop_front()
+    }
+
+    /// Like [`pop_received`](Self::pop_received), but reports an empty queue as an error.
+    pub fn try_recv(&mut self) -> Result<Vec<u8>, DatagramError> {
+        self.pop_received().ok_or(DatagramError::Empty)
+    }
+
+    /// Number of received datagrams waiting.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Determine whether no received datagrams are waiting.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    /// Total datagrams dropped because the queue was full or the payload too large.
+    #[must_use]
+    pub const fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    fn record_drop(&mut self, len: usize) {
+        trace!(len, policy = ?self.policy, "drop received datagram");
+        self.dropped += 1;
+        Metrics::record_datagram_recv_dropped();
     }
 }
 
@@ -157,4 +370,97 @@ mod tests {
         });
         assert!(queue.dequeue_with_guard(&mut guard).is_none());
     }
+
+    #[test]
+    fn lost_reliable_datagrams_are_requeued_in_order() {
+        let now = std::time::SystemTime::now();
+        let mut queue = DatagramQueue::new(DatagramConfig::default());
+        let mut guard = AntiAmplificationGuard::new(AmplificationConfig::default());
+        guard.mark_verified();
+        assert_eq!(queue.enqueue_reliable(vec![1]), Ok(0));
+        assert_eq!(queue.enqueue_reliable(vec![2]), Ok(1));
+        queue.enqueue(vec![3]).unwrap();
+
+        assert_eq!(queue.dequeue_with_guard(&mut guard), Some(vec![1]));
+        assert_eq!(queue.dequeue_with_guard(&mut guard), Some(vec![2]));
+        queue.on_packet_sent(10);
+        assert_eq!(queue.dequeue_with_guard(&mut guard), Some(vec![3]));
+        queue.on_packet_sent(11);
+        assert_eq!(queue.reliable_in_flight(), 2);
+        assert!(queue.is_empty());
+
+        let lost = SentPacketInfo::new(10, now, 100, true);
+        let unreliable = SentPacketInfo::new(11, now, 100, true);
+        assert_eq!(queue.on_packets_lost(&[lost, unreliable]), 2);
+        assert_eq!(queue.retransmitted(), 2);
+        assert_eq!(queue.dequeue_with_guard(&mut guard), Some(vec![1]));
+        assert_eq!(queue.dequeue_with_guard(&mut guard), Some(vec![2]));
+        queue.on_packet_sent(12);
+
+        let outcome = AckOutcome {
+            acknowledged: vec![SentPacketInfo::new(12, now, 100, true)],
+            ..AckOutcome::default()
+        };
+        assert_eq!(queue.on_ack_outcome(&outcome), 0);
+        assert_eq!(queue.reliable_in_flight(), 0);
+    }
+
+    fn receive_queue(max_queue: usize, policy: DatagramDropPolicy) -> DatagramReceiveQueue {
+        DatagramReceiveQueue::new(
+            DatagramConfig {
+                max_payload: 16,
+                max_queue,
+            },
+            policy,
+        )
+    }
+
+    #[test]
+    fn receive_queue_is_fifo() {
+        let mut queue = receive_queue(4, DatagramDropPolicy::default());
+        assert!(queue.push_received(vec![1]));
+        assert!(queue.push_received(vec![2]));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop_received(), Some(vec![1]));
+        assert_eq!(queue.try_recv(), Ok(vec![2]));
+        assert_eq!(queue.try_recv(), Err(DatagramError::Empty));
+        assert!(queue.is_empty());
+        assert_eq!(queue.dropped(), 0);
+    }
+
+    #[test]
+    fn receive_queue_overflow_follows_policy() {
+        let mut oldest = receive_queue(2, DatagramDropPolicy::DropOldest);
+        let mut newest = receive_queue(2, DatagramDropPolicy::DropNewest);
+        for byte in 1..=3 {
+            oldest.push_received(vec![byte]);
+            newest.push_received(vec![byte]);
+        }
+
+        assert_eq!(oldest.dropped(), 1);
+        assert_eq!(oldest.pop_received(), Some(vec![2]));
+        assert_eq!(oldest.pop_received(), Some(vec![3]));
+
+        assert_eq!(newest.dropped(), 1);
+        assert_eq!(newest.pop_received(), Some(vec![1]));
+        assert_eq!(newest.pop_received(), Some(vec![2]));
+    }
+
+    #[test]
+    fn receive_queue_counts_drops_and_routes_frames() {
+        let before = Metrics::totals().datagram_recv_dropped;
+        let mut queue = receive_queue(1, DatagramDropPolicy::DropNewest);
+
+        assert!(queue.deliver_frame(Frame::datagram(vec![7])).is_none());
+        assert!(queue.deliver_frame(Frame::datagram(vec![8])).is_none());
+        assert!(!queue.push_received(vec![0; 17]));
+        assert_eq!(queue.dropped(), 2);
+        assert!(Metrics::totals().datagram_recv_dropped >= before + 2);
+
+        let other = queue
+            .deliver_frame(Frame::padding(3))
+            .expect("non-datagram frame returned");
+        assert_eq!(other.frame_type(), FrameType::Padding);
+        assert_eq!(queue.try_recv(), Ok(vec![7]));
+    }
 }
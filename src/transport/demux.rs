@@ -0,0 +1,393 @@
+//! Many connections sharing one socket.
+//!
+//! A [`ConnectionTable`] routes each inbound datagram to its [`Connection`]: protected
+//! packets by the connection ID in their header, falling back to the sender address, and
+//! handshake messages by the sender address. A handshake from an unknown address starts a
+//! new server-side connection, subject to the table's connection limit and the
+//! [`HandshakeServer`]'s admission control. Outbound datagrams come back paired with the
+//! peer address they go to, so one socket serves every connection.
+//!
+//! Connection IDs are chosen by the clients, so the table never lets one connection take
+//! over another's ID or address: a client whose ID is already in use stays routed by its
+//! address. Datagrams that fail to open on the connection they route to are dropped.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::net::SocketAddr;
+use std::ops::Bound;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::SystemTime;
+
+use tracing::{debug, trace};
+
+use super::admission::DEFAULT_MAX_CONNECTIONS;
+use super::connection::{
+    Connection, ConnectionConfig, ConnectionError, ConnectionState, HANDSHAKE_DATAGRAM,
+    packet_connection_id,
+};
+use super::crypto::PublicKey;
+use super::handshake::{HandshakeError, HandshakeMessage, HandshakeServer};
+use super::packet::ConnectionId;
+
+/// Refusal and retry replies queued at once; hellos beyond it go unanswered.
+const MAX_STATELESS_REPLIES: usize = 64;
+
+/// Identifies one connection within a [`ConnectionTable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ConnectionHandle(u64);
+
+type PeerKeyResolver = dyn Fn(SocketAddr) -> Option<PublicKey> + Send + Sync;
+
+#[derive(Debug)]
+struct Entry {
+    connection: Connection,
+    peer: SocketAddr,
+    connection_id: Option<ConnectionId>,
+}
+
+/// Server-side set of connections demultiplexed from one socket.
+pub struct ConnectionTable {
+    config: ConnectionConfig,
+    server: Arc<Mutex<HandshakeServer>>,
+    peer_key: Box<PeerKeyResolver>,
+    max_connections: usize,
+    next_handle: u64,
+    /// Handle of the connection [`poll_transmit`](Self::poll_transmit) served last, so
+    /// every connection gets a turn.
+    last_polled: Option<ConnectionHandle>,
+    connections: BTreeMap<ConnectionHandle, Entry>,
+    by_addr: HashMap<SocketAddr, ConnectionHandle>,
+    by_id: HashMap<ConnectionId, ConnectionHandle>,
+    /// Replies to hellos that did not start a connection, sent before connection traffic.
+    stateless: VecDeque<(SocketAddr, Vec<u8>)>,
+}
+
+impl fmt::Debug for ConnectionTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionTable")
+            .field("max_connections", &self.max_connections)
+            .field("connections", &self.connections.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ConnectionTable {
+    /// Create a table accepting handshakes through `server`.
+    ///
+    /// `peer_key` returns the static public key expected from a peer address; handshakes
+    /// from addresses it returns `None` for are dropped. Accepted connections use `config`.
+    pub fn new(
+        config: ConnectionConfig,
+        server: Arc<Mutex<HandshakeServer>>,
+        peer_key: impl Fn(SocketAddr) -> Option<PublicKey> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            config,
+            server,
+            peer_key: Box::new(peer_key),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            next_handle: 0,
+            last_polled: None,
+            connections: BTreeMap::new(),
+            by_addr: HashMap::new(),
+            by_id: HashMap::new(),
+            stateless: VecDeque::new(),
+        }
+    }
+
+    /// Cap the number of connections; handshakes beyond it are dropped.
+    #[must_use]
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Route one datagram received from `from`.
+    ///
+    /// Returns the connection it reached, or `None` when it was dropped: a packet for no
+    /// known connection or one that fails to open, a malformed datagram, or a handshake
+    /// over the connection limit, from a peer without a known key, or refused by the
+    /// [`HandshakeServer`]. Refusals and retry requests are answered through
+    /// [`poll_transmit`](Self::poll_transmit). Errors come from a connection whose peer
+    /// broke protocol rules in an authentic packet.
+    pub fn handle_datagram(
+        &mut self,
+        datagram: &[u8],
+        from: SocketAddr,
+        now: SystemTime,
+    ) -> Result<Option<ConnectionHandle>, ConnectionError> {
+        let connection_id = packet_connection_id(datagram).filter(|id| !id.is_empty());
+        let by_id = connection_id.and_then(|id| self.by_id.get(&id)).copied();
+        let by_addr = self.by_addr.get(&from).copied();
+        let routes = [by_id, by_addr.filter(|handle| Some(*handle) != by_id)];
+        if routes.iter().all(Option::is_none) {
+            if datagram.first() != Some(&HANDSHAKE_DATAGRAM) {
+                trace!(%from, "dropping packet for unknown connection");
+                return Ok(None);
+            }
+            return Ok(self.accept(datagram, from, now));
+        }
+
+        // A client may have picked an ID that is already in use, so a packet that does not
+        // open on the ID's connection gets a second chance on the address's.
+        for handle in routes.into_iter().flatten() {
+            let entry = self
+                .connections
+                .get_mut(&handle)
+                .expect("routing tables only name live connections");
+            // Packets reaching a connection still in its handshake are dropped unopened.
+            let established = entry.connection.is_established();
+            match entry.connection.handle_datagram(datagram, now) {
+                Ok(()) => {}
+                Err(
+                    err @ (ConnectionError::Packet(_)
+                    | ConnectionError::Malformed
+                    | ConnectionError::Handshake(_)),
+                ) => {
+                    trace!(%from, ?handle, %err, "datagram rejected by connection");
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+            if let Some(id) = connection_id.filter(|_| established) {
+                self.learn(handle, id, from);
+            }
+            return Ok(Some(handle));
+        }
+        trace!(%from, "dropping datagram that no connection accepted");
+        Ok(None)
+    }
+
+    /// A packet carrying `id` from `from` opened on `handle`, so it is authentic: learn
+    /// the ID and follow an address change, unless another connection holds either.
+    fn learn(&mut self, handle: ConnectionHandle, id: ConnectionId, from: SocketAddr) {
+        let entry = self
+            .connections
+            .get_mut(&handle)
+            .expect("routing tables only name live connections");
+        if entry.connection_id.is_none() {
+            match self.by_id.get(&id) {
+                None => {
+                    entry.connection_id = Some(id);
+                    self.by_id.insert(id, handle);
+                }
+                Some(owner) if *owner != handle => {
+                    debug!(
+                        ?handle,
+                        ?owner,
+                        "connection ID already in use; routing by address"
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+        // Only a connection routed by its ID can be recognised at a new address.
+        if entry.peer != from && entry.connection_id == Some(id) {
+            if let Some(owner) = self.by_addr.get(&from) {
+                debug!(%from, ?handle, ?owner, "not migrating onto another connection's address");
+                return;
+            }
+            debug!(old = %entry.peer, new = %from, "connection migrated");
+            self.by_addr.remove(&entry.peer);
+            self.by_addr.insert(from, handle);
+            entry.peer = from;
+        }
+    }
+
+    fn accept(
+        &mut self,
+        datagram: &[u8],
+        from: SocketAddr,
+        now: SystemTime,
+    ) -> Option<ConnectionHandle> {
+        if self.connections.len() >= self.max_connections {
+            debug!(%from, limit = self.max_connections, "connection table full");
+            return None;
+        }
+        let Some(peer_key) = (self.peer_key)(from) else {
+            debug!(%from, "dropping handshake from peer without a known key");
+            return None;
+        };
+        let connection = match Connection::accept(
+            self.config.clone(),
+            Arc::clone(&self.server),
+            &peer_key,
+            from,
+            datagram,
+            now,
+        ) {
+            Ok(connection) => connection,
+            Err(ConnectionError::Handshake(err)) => {
+                self.answer_refused_hello(&err, from, now);
+                return None;
+            }
+            Err(err) => {
+                debug!(%from, %err, "dropping handshake");
+                return None;
+            }
+        };
+
+        let handle = ConnectionHandle(self.next_handle);
+        self.next_handle += 1;
+        debug!(%from, ?handle, "accepted connection");
+        self.connections.insert(
+            handle,
+            Entry {
+                connection,
+                peer: from,
+                connection_id: None,
+            },
+        );
+        self.by_addr.insert(from, handle);
+        Some(handle)
+    }
+
+    /// Queue the server's stateless reply to a hello that failed with `err`, if it has one.
+    fn answer_refused_hello(&mut self, err: &HandshakeError, from: SocketAddr, now: SystemTime) {
+        let server = self.server.lock().unwrap_or_else(PoisonError::into_inner);
+        let reply = match err {
+            HandshakeError::Refused(refusal) => server.refuse(*refusal),
+            HandshakeError::RetryRequired => match server.issue_retry(from, now) {
+                Ok(retry) => retry,
+                Err(err) => {
+                    debug!(%from, %err, "cannot ask for a retry");
+                    return;
+                }
+            },
+            err => {
+                debug!(%from, %err, "dropping handshake");
+                return;
+            }
+        };
+        drop(server);
+        if self.stateless.len() >= MAX_STATELESS_REPLIES {
+            debug!(%from, "too many queued handshake replies; dropping");
+            return;
+        }
+        debug!(%from, kind = ?reply.kind(), "answering hello without a connection");
+        self.stateless.push_back((from, handshake_datagram(&reply)));
+    }
+
+    /// Next datagram to send and the address it goes to, taking connections in turn.
+    ///
+    /// Queued refusal and retry replies go first.
+    pub fn poll_transmit(&mut self, now: SystemTime) -> Option<(SocketAddr, Vec<u8>)> {
+        if let Some(reply) = self.stateless.pop_front() {
+            return Some(reply);
+        }
+        let (after, through) = match self.last_polled {
+            Some(last) => (Bound::Excluded(last), Bound::Included(last)),
+            None => (Bound::Unbounded, Bound::Excluded(ConnectionHandle(0))),
+        };
+        let order: Vec<ConnectionHandle> = self
+            .connections
+            .range((after, Bound::Unbounded))
+            .chain(self.connections.range((Bound::Unbounded, through)))
+            .map(|(handle, _)| *handle)
+            .collect();
+        for handle in order {
+            let entry = self.connections.get_mut(&handle)?;
+            if let Some(datagram) = entry.connection.poll_transmit(now) {
+                self.last_polled = Some(handle);
+                return Some((entry.peer, datagram));
+            }
+        }
+        None
+    }
+
+    /// Earliest deadline across every connection.
+    #[must_use]
+    pub fn poll_timeout(&self) -> Option<SystemTime> {
+        self.connections
+            .values()
+            .filter_map(|entry| entry.connection.poll_timeout())
+            .min()
+    }
+
+    /// Run expired timers on every connection, then remove closed connections and those
+    /// whose handshake failed.
+    pub fn handle_timeout(&mut self, now: SystemTime) {
+        let mut finished = Vec::new();
+        for (handle, entry) in &mut self.connections {
+            if entry
+                .connection
+                .poll_timeout()
+                .is_some_and(|deadline| deadline <= now)
+            {
+                if let Err(err) = entry.connection.handle_timeout(now) {
+                    debug!(?handle, %err, "connection failed");
+                    finished.push(*handle);
+                    continue;
+                }
+            }
+            if entry.connection.state() == ConnectionState::Closed {
+                finished.push(*handle);
+            }
+        }
+        for handle in finished {
+            self.remove(handle);
+        }
+    }
+
+    /// Drop a connection, releasing its admission slot.
+    pub fn remove(&mut self, handle: ConnectionHandle) -> Option<Connection> {
+        let entry = self.connections.remove(&handle)?;
+        if self.by_addr.get(&entry.peer) == Some(&handle) {
+            self.by_addr.remove(&entry.peer);
+        }
+        if let Some(id) = entry.connection_id {
+            self.by_id.remove(&id);
+        }
+        if let Some(admission) = self
+            .server
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .admission_mut()
+        {
+            admission.connection_closed(entry.peer.ip());
+        }
+        Some(entry.connection)
+    }
+
+    /// Connection behind `handle`.
+    #[must_use]
+    pub fn get(&self, handle: ConnectionHandle) -> Option<&Connection> {
+        self.connections.get(&handle).map(|entry| &entry.connection)
+    }
+
+    /// Mutable access to the connection behind `handle`.
+    pub fn get_mut(&mut self, handle: ConnectionHandle) -> Option<&mut Connection> {
+        self.connections
+            .get_mut(&handle)
+            .map(|entry| &mut entry.connection)
+    }
+
+    /// Current address of the peer behind `handle`.
+    #[must_use]
+    pub fn peer_addr(&self, handle: ConnectionHandle) -> Option<SocketAddr> {
+        self.connections.get(&handle).map(|entry| entry.peer)
+    }
+
+    /// Handles of every connection, oldest first.
+    pub fn handles(&self) -> impl Iterator<Item = ConnectionHandle> + '_ {
+        self.connections.keys().copied()
+    }
+
+    /// Number of connections, including handshakes in progress.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Whether the table holds no connections.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+}
+
+fn handshake_datagram(message: &HandshakeMessage) -> Vec<u8> {
+    let mut datagram = vec![HANDSHAKE_DATAGRAM];
+    datagram.extend_from_slice(&message.encode());
+    datagram
+}
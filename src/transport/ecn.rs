@@ -0,0 +1,58 @@
+//! Explicit Congestion Notification (ECN) codepoints and receive counters.
+
+/// ECN codepoint carried in the low two bits of the IP TOS / traffic class byte.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum EcnCodepoint {
+    /// Not ECN-capable transport.
+    #[default]
+    NotEct = 0b00,
+    /// ECN-capable transport, ECT(1).
+    Ect1 = 0b01,
+    /// ECN-capable transport, ECT(0).
+    Ect0 = 0b10,
+    /// Congestion experienced.
+    Ce = 0b11,
+}
+
+impl EcnCodepoint {
+    /// Extract the codepoint from a TOS / traffic class byte.
+    #[must_use]
+    pub const fn from_tos(tos: u8) -> Self {
+        match tos & 0b11 {
+            0b01 => Self::Ect1,
+            0b10 => Self::Ect0,
+            0b11 => Self::Ce,
+            _ => Self::NotEct,
+        }
+    }
+
+    /// Two-bit value placed in the TOS / traffic class byte.
+    #[must_use]
+    pub const fn bits(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Per-codepoint counts of packets received with ECN marks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EcnCounts {
+    /// Packets received with ECT(0).
+    pub ect0: u64,
+    /// Packets received with ECT(1).
+    pub ect1: u64,
+    /// Packets received with CE.
+    pub ce: u64,
+}
+
+impl EcnCounts {
+    /// Count one packet received with `codepoint`.
+    pub fn record(&mut self, codepoint: EcnCodepoint) {
+        match codepoint {
+            EcnCodepoint::NotEct => {}
+            EcnCodepoint::Ect1 => self.ect1 += 1,
+            EcnCodepoint::Ect0 => self.ect0 += 1,
+            EcnCodepoint::Ce => self.ce += 1,
+        }
+    }
+}
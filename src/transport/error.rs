@@ -1,6 +1,7 @@
 //! Transport-level error types covering socket, packet, and crypto failures.
 
 use super::crypto::CryptoError;
+use super::handshake::HandshakeError;
 use super::packet::PacketError;
 use super::socket::SocketError;
 use core::fmt;
@@ -14,6 +15,8 @@ pub enum TransportError {
     Packet(PacketError),
     /// Cryptographic failure (AEAD, HKDF, etc.).
     Crypto(CryptoError),
+    /// Handshake failure.
+    Handshake(HandshakeError),
     /// Provided buffer was not large enough to hold the encoded packet.
     BufferTooSmall {
         /// Number of bytes required to encode the packet.
@@ -42,7 +45,8 @@ impl fmt::Display for TransportError {
         match self {
             Self::Socket(err) => write!(f, "socket error: {err:?}"),
             Self::Packet(err) => write!(f, "packet error: {err}"),
-            Self::Crypto(err) => write!(f, "crypto error: {err:?}"),
+            Self::Crypto(err) => write!(f, "crypto error: {err}"),
+            Self::Handshake(err) => write!(f, "handshake error: {err}"),
             Self::BufferTooSmall {
                 required,
                 available,
@@ -64,7 +68,16 @@ impl fmt::Display for TransportError {
     }
 }
 
-impl std::error::Error for TransportError {}
+impl std::error::Error for TransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Packet(err) => Some(err),
+            Self::Crypto(err) => Some(err),
+            Self::Handshake(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
 impl From<SocketError> for TransportError {
     fn from(err: SocketError) -> Self {
@@ -83,3 +96,9 @@ impl From<CryptoError> for TransportError {
         Self::Crypto(err)
     }
 }
+
+impl From<HandshakeError> for TransportError {
+    fn from(err: HandshakeError) -> Self {
+        Self::Handshake(err)
+    }
+}
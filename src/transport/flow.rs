@@ -2,6 +2,9 @@
 
 use std::collections::HashMap;
 
+use super::params::TransportParameters;
+#[cfg(feature = "qlog")]
+use super::qlog::{QlogEvent, SharedEventLogger};
 use super::stream::StreamId;
 use crate::protocol::metrics::Metrics;
 
@@ -64,6 +67,11 @@ impl FlowWindow {
         Ok(())
     }
 
+    /// Return previously consumed bytes to the window.
+    pub fn release(&mut self, amount: u64) {
+        self.consumed = self.consumed.saturating_sub(amount);
+    }
+
     /// Returns total bytes consumed so far.
     #[must_use]
     pub const fn consumed(&self) -> u64 {
@@ -82,6 +90,9 @@ impl FlowWindow {
 pub struct FlowController {
     connection: FlowWindow,
     streams: HashMap<StreamId, FlowWindow>,
+    default_stream_limit: Option<u64>,
+    #[cfg(feature = "qlog")]
+    event_logger: Option<SharedEventLogger>,
 }
 
 impl FlowController {
@@ -91,9 +102,40 @@ impl FlowController {
         Self {
             connection: FlowWindow::new(connection_limit),
             streams: HashMap::new(),
+            default_stream_limit: None,
+            #[cfg(feature = "qlog")]
+            event_logger: None,
+        }
+    }
+
+    /// Report sends refused by flow control to `logger`.
+    #[cfg(feature = "qlog")]
+    #[must_use]
+    pub fn with_event_logger(mut self, logger: SharedEventLogger) -> Self {
+        self.event_logger = Some(logger);
+        self
+    }
+
+    /// Create a controller whose new streams start with `stream_default_limit` bytes of credit.
+    #[must_use]
+    pub fn with_stream_default(connection_limit: u64, stream_default_limit: u64) -> Self {
+        Self {
+            default_stream_limit: Some(stream_default_limit),
+            ..Self::new(connection_limit)
         }
     }
 
+    /// Apply negotiated limits: the connection window and the initial window of new streams.
+    pub fn apply_transport_parameters(&mut self, params: &TransportParameters) {
+        self.update_connection_limit(params.initial_max_data);
+        self.default_stream_limit = Some(params.initial_max_stream_data);
+    }
+
+    fn initial_stream_limit(&self) -> u64 {
+        self.default_stream_limit
+            .unwrap_or_else(|| self.connection.limit())
+    }
+
     /// Update the connection-wide limit.
     pub fn update_connection_limit(&mut self, new_limit: u64) {
         if new_limit != self.connection.limit() {
@@ -104,9 +146,10 @@ impl FlowController {
 
     /// Acquire mutable reference to a stream-specific window, creating if absent.
     fn stream_window_mut(&mut self, id: StreamId) -> &mut FlowWindow {
+        let initial = self.initial_stream_limit();
         self.streams
             .entry(id)
-            .or_insert_with(|| FlowWindow::new(self.connection.limit()))
+            .or_insert_with(|| FlowWindow::new(initial))
     }
 
     /// Update the limit for a specific stream.
@@ -122,18 +165,12 @@ impl FlowController {
     pub fn consume(&mut self, id: StreamId, amount: u64) -> Result<(), FlowControlError> {
         let conn_available = self.connection.available();
         if amount > conn_available {
-            return Err(FlowControlError::SendWindowExceeded {
-                available: conn_available,
-                attempted: amount,
-            });
+            return Err(self.blocked(id, conn_available, amount));
         }
 
         let stream_available = self.stream_window_mut(id).available();
         if amount > stream_available {
-            return Err(FlowControlError::SendWindowExceeded {
-                available: stream_available,
-                attempted: amount,
-            });
+            return Err(self.blocked(id, stream_available, amount));
         }
 
         self.connection.consume(amount).expect("bounds checked");
@@ -144,6 +181,27 @@ impl FlowController {
         Ok(())
     }
 
+    #[cfg_attr(not(feature = "qlog"), allow(clippy::unused_self))]
+    fn blocked(&self, id: StreamId, available: u64, attempted: u64) -> FlowControlError {
+        #[cfg(feature = "qlog")]
+        if let Some(logger) = &self.event_logger {
+            logger.log(
+                std::time::SystemTime::now(),
+                &QlogEvent::FlowBlocked {
+                    stream_id: id.as_u64(),
+                    available,
+                    attempted,
+                },
+            );
+        }
+        #[cfg(not(feature = "qlog"))]
+        let _ = id;
+        FlowControlError::SendWindowExceeded {
+            available,
+            attempted,
+        }
+    }
+
     /// Determine connection-level send availability.
     #[must_use]
     pub fn connection_available(&self) -> u64 {
@@ -153,9 +211,10 @@ impl FlowController {
     /// Determine per-stream send availability.
     #[must_use]
     pub fn stream_available(&self, id: StreamId) -> u64 {
-        self.streams
-            .get(&id)
-            .map_or(self.connection.available(), FlowWindow::available)
+        self.streams.get(&id).map_or_else(
+            || self.initial_stream_limit().min(self.connection.available()),
+            FlowWindow::available,
+        )
     }
 
     /// Access the current connection limit.
@@ -166,7 +225,16 @@ impl FlowController {
 
     /// Reset consumption counters (e.g., after receiving `MAX_DATA` that surpasses current total consumption).
     pub fn retire_connection_consumed(&mut self, amount: u64) {
-        self.connection.consumed = self.connection.consumed.saturating_sub(amount);
+        self.connection.release(amount);
+    }
+
+    /// Return `amount` bytes of credit to both the connection and the stream window, e.g. once
+    /// the application has read received data and the window can be retired.
+    pub fn release(&mut self, id: StreamId, amount: u64) {
+        self.connection.release(amount);
+        if let Some(window) = self.streams.get_mut(&id) {
+            window.release(amount);
+        }
     }
 }
 
@@ -205,4 +273,36 @@ mod tests {
         assert_eq!(controller.connection_available(), 100);
         assert_eq!(controller.stream_available(stream), 20);
     }
+
+    #[test]
+    fn release_returns_credit_to_both_windows() {
+        let mut controller = FlowController::with_stream_default(1000, 100);
+        let stream = StreamId::from_raw(0);
+        assert_eq!(controller.stream_available(stream), 100);
+        controller.consume(stream, 100).unwrap();
+        assert!(controller.consume(stream, 1).is_err());
+
+        controller.release(stream, 60);
+        assert_eq!(controller.stream_available(stream), 60);
+        assert_eq!(controller.connection_available(), 960);
+        controller.release(stream, 500);
+        assert_eq!(controller.stream_available(stream), 100);
+        assert_eq!(controller.connection_available(), 1000);
+    }
+
+    #[test]
+    fn transport_parameters_set_initial_windows() {
+        let mut controller = FlowController::new(u64::MAX);
+        controller.apply_transport_parameters(&TransportParameters {
+            initial_max_data: 1000,
+            initial_max_stream_data: 300,
+            ..TransportParameters::default()
+        });
+        let stream = StreamId::from_raw(4);
+        assert_eq!(controller.connection_limit(), 1000);
+        assert_eq!(controller.stream_available(stream), 300);
+        controller.consume(stream, 300).unwrap();
+        assert!(controller.consume(stream, 1).is_err());
+        assert_eq!(controller.connection_available(), 700);
+    }
 }
@@ -0,0 +1,510 @@
+//! Fragmentation of datagrams larger than one packet.
+//!
+//! A datagram that fits in a single packet is sent as a plain [`FrameType::Datagram`] frame
+//! with no extra bytes. Larger datagrams are split into [`FrameType::DatagramFragment`]
+//! frames, each prefixed with `[datagram_id (4)][index (2)][count (2)]`. The receiver buffers
+//! fragments until the set is complete; sets still incomplete after the reassembly timeout
+//! are discarded, since a lost fragment is never retransmitted. Reassembled datagrams are
+//! handed to a [`DatagramReceiveQueue`](super::DatagramReceiveQueue) like unfragmented ones.
+//!
+//! Fragment headers come from the peer, so the receiver bounds what it buffers: sets larger
+//! than the biggest datagram it accepts are rejected, and beyond the number of pending sets
+//! or the byte budget of [`ReassemblyConfig`] the oldest sets are evicted.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, SystemTime};
+
+use tracing::{debug, trace};
+
+use super::datagram::DatagramError;
+use super::packet::{Frame, FrameType};
+
+/// Size of the header prefixed to each fragment.
+pub const FRAGMENT_HEADER_LEN: usize = 8;
+/// Default time an incomplete fragment set is kept before being discarded.
+pub const DEFAULT_REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(1);
+/// Default largest datagram accepted for reassembly.
+pub const DEFAULT_MAX_REASSEMBLED_DATAGRAM: usize = 64 * 1024;
+/// Default number of incomplete fragment sets buffered at once.
+pub const DEFAULT_MAX_PENDING_DATAGRAMS: usize = 64;
+/// Default budget for fragment bytes buffered across all incomplete sets.
+pub const DEFAULT_REASSEMBLY_BUFFER: usize = 1024 * 1024;
+
+/// Limits on the fragments a [`DatagramReassembler`] buffers.
+#[derive(Debug, Clone)]
+pub struct ReassemblyConfig {
+    /// Time an incomplete set is kept before [`expire`](DatagramReassembler::expire)
+    /// discards it.
+    pub timeout: Duration,
+    /// Largest reassembled datagram accepted; fragments of larger sets are rejected.
+    pub max_datagram_len: usize,
+    /// Incomplete sets kept at once; a new set beyond this evicts the oldest.
+    pub max_pending: usize,
+    /// Fragment bytes buffered across all incomplete sets; exceeding it evicts the oldest
+    /// sets.
+    pub max_buffered_bytes: usize,
+}
+
+impl Default for ReassemblyConfig {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_REASSEMBLY_TIMEOUT,
+            max_datagram_len: DEFAULT_MAX_REASSEMBLED_DATAGRAM,
+            max_pending: DEFAULT_MAX_PENDING_DATAGRAMS,
+            max_buffered_bytes: DEFAULT_REASSEMBLY_BUFFER,
+        }
+    }
+}
+
+/// Splits outbound datagrams into frames that fit the path MTU.
+#[derive(Debug, Default)]
+pub struct DatagramFragmenter {
+    next_id: u32,
+}
+
+impl DatagramFragmenter {
+    /// Create a fragmenter.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the frames carrying `payload`, each with at most `max_frame_payload` bytes.
+    ///
+    /// Returns a single DATAGRAM frame when `payload` fits, otherwise one fragment frame per
+    /// `max_frame_payload - FRAGMENT_HEADER_LEN` bytes.
+    pub fn fragment(
+        &mut self,
+        payload: Vec<u8>,
+        max_frame_payload: usize,
+    ) -> Result<Vec<Frame>, DatagramError> {
+        if payload.len() <= max_frame_payload {
+            return Ok(vec![Frame::datagram(payload)]);
+        }
+
+        let chunk_len = max_frame_payload.saturating_sub(FRAGMENT_HEADER_LEN);
+        let max_len = chunk_len.saturating_mul(usize::from(u16::MAX));
+        if chunk_len == 0 || payload.len() > max_len {
+            return Err(DatagramError::PayloadTooLarge {
+                len: payload.len(),
+                max: max_len,
+            });
+        }
+        let count = u16::try_from(payload.len().div_ceil(chunk_len)).expect("bounded above");
+
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        trace!(id, count, len = payload.len(), "fragment datagram");
+
+        Ok((0..count)
+            .zip(payload.chunks(chunk_len))
+            .map(|(index, chunk)| {
+                let mut bytes = Vec::with_capacity(FRAGMENT_HEADER_LEN + chunk.len());
+                bytes.extend_from_slice(&id.to_le_bytes());
+                bytes.extend_from_slice(&index.to_le_bytes());
+                bytes.extend_from_slice(&count.to_le_bytes());
+                bytes.extend_from_slice(chunk);
+                Frame::new(FrameType::DatagramFragment, bytes)
+            })
+            .collect())
+    }
+}
+
+#[derive(Debug)]
+struct PartialDatagram {
+    count: usize,
+    /// Fragments received so far, by index; only what arrived is allocated.
+    fragments: BTreeMap<usize, Vec<u8>>,
+    /// Length of every fragment but the last, learned from the first one seen.
+    chunk_len: Option<usize>,
+    bytes: usize,
+    first_seen: SystemTime,
+}
+
+/// Reassembles fragmented datagrams, discarding sets that stay incomplete too long.
+#[derive(Debug)]
+pub struct DatagramReassembler {
+    config: ReassemblyConfig,
+    partial: HashMap<u32, PartialDatagram>,
+    buffered_bytes: usize,
+    expired: u64,
+    evicted: u64,
+}
+
+impl Default for DatagramReassembler {
+    fn default() -> Self {
+        Self::with_config(ReassemblyConfig::default())
+    }
+}
+
+impl DatagramReassembler {
+    /// Create a reassembler that keeps incomplete sets for `timeout`, with default limits.
+    #[must_use]
+    pub fn new(timeout: Duration) -> Self {
+        Self::with_config(ReassemblyConfig {
+            timeout,
+            ..ReassemblyConfig::default()
+        })
+    }
+
+    /// Create a reassembler bounded by `config`.
+    #[must_use]
+    pub fn with_config(config: ReassemblyConfig) -> Self {
+        Self {
+            config,
+            partial: HashMap::new(),
+            buffered_bytes: 0,
+            expired: 0,
+            evicted: 0,
+        }
+    }
+
+    /// Process a received datagram or datagram fragment frame.
+    ///
+    /// Returns the complete datagram once every fragment has arrived; plain DATAGRAM frames
+    /// are returned immediately.
+    pub fn on_frame(
+        &mut self,
+        frame: Frame,
+        now: SystemTime,
+    ) -> Result<Option<Vec<u8>>, DatagramError> {
+        match frame.frame_type() {
+            FrameType::Datagram => Ok(Some(frame.into_payload())),
+            FrameType::DatagramFragment => self.on_fragment(frame.payload(), now),
+            _ => Err(DatagramError::MalformedFragment),
+        }
+    }
+
+    fn on_fragment(
+        &mut self,
+        bytes: &[u8],
+        now: SystemTime,
+    ) -> Result<Option<Vec<u8>>, DatagramError> {
+        let Some((header, data)) = bytes.split_first_chunk::<FRAGMENT_HEADER_LEN>() else {
+            return Err(DatagramError::MalformedFragment);
+        };
+        let id = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let index = usize::from(u16::from_le_bytes([header[4], header[5]]));
+        let count = usize::from(u16::from_le_bytes([header[6], header[7]]));
+        if index >= count || data.is_empty() {
+            return Err(DatagramError::MalformedFragment);
+        }
+        // Every fragment carries at least one byte and all but the last carry the same
+        // amount, which bounds the size of the set before any of it is buffered.
+        let is_last = index + 1 == count;
+        let max = self.config.max_datagram_len;
+        let min_len = if is_last {
+            count
+        } else {
+            data.len().saturating_mul(count - 1).saturating_add(1)
+        };
+        if min_len > max {
+            return Err(DatagramError::PayloadTooLarge { len: min_len, max });
+        }
+
+        if !self.partial.contains_key(&id) {
+            while self.partial.len() >= self.config.max_pending.max(1) {
+                self.evict_oldest(None);
+            }
+            self.partial.insert(
+                id,
+                PartialDatagram {
+                    count,
+                    fragments: BTreeMap::new(),
+                    chunk_len: None,
+                    bytes: 0,
+                    first_seen: now,
+                },
+            );
+        }
+        let partial = self.partial.get_mut(&id).expect("entry present");
+        if partial.count != count {
+            return Err(DatagramError::MalformedFragment);
+        }
+        if partial.fragments.contains_key(&index) {
+            return Ok(None);
+        }
+        if !is_last {
+            match partial.chunk_len {
+                Some(chunk_len) if chunk_len != data.len() => {
+                    return Err(DatagramError::MalformedFragment);
+                }
+                Some(_) => {}
+                None => partial.chunk_len = Some(data.len()),
+            }
+        }
+        let set_len = partial.bytes + data.len();
+        if set_len > max {
+            self.discard(id);
+            return Err(DatagramError::PayloadTooLarge { len: set_len, max });
+        }
+
+        while self.buffered_bytes + data.len() > self.config.max_buffered_bytes {
+            if !self.evict_oldest(Some(id)) {
+                // This set alone exceeds the budget.
+                self.discard(id);
+                self.evicted += 1;
+                return Ok(None);
+            }
+        }
+        let partial = self.partial.get_mut(&id).expect("entry present");
+        partial.fragments.insert(index, data.to_vec());
+        partial.bytes += data.len();
+        self.buffered_bytes += data.len();
+        if partial.fragments.len() < count {
+            return Ok(None);
+        }
+
+        let partial = self.discard(id).expect("entry present");
+        trace!(id, count, "reassembled datagram");
+        Ok(Some(partial.fragments.into_values().flatten().collect()))
+    }
+
+    /// Evict the oldest incomplete set other than `keep`, returning whether one was evicted.
+    fn evict_oldest(&mut self, keep: Option<u32>) -> bool {
+        let oldest = self
+            .partial
+            .iter()
+            .filter(|(id, _)| Some(**id) != keep)
+            .min_by_key(|(_, partial)| partial.first_seen)
+            .map(|(id, _)| *id);
+        let Some(id) = oldest else {
+            return false;
+        };
+        debug!(id, "evicting incomplete datagram fragments");
+        self.discard(id);
+        self.evicted += 1;
+        true
+    }
+
+    fn discard(&mut self, id: u32) -> Option<PartialDatagram> {
+        let partial = self.partial.remove(&id)?;
+        self.buffered_bytes -= partial.bytes;
+        Some(partial)
+    }
+
+    /// Discard fragment sets first seen more than the timeout before `now`.
+    ///
+    /// Returns the number of sets discarded.
+    pub fn expire(&mut self, now: SystemTime) -> usize {
+        let timeout = self.config.timeout;
+        let before = self.partial.len();
+        let mut released = 0;
+        self.partial.retain(|_, partial| {
+            let keep = now
+                .duration_since(partial.first_seen)
+                .map_or(true, |age| age < timeout);
+            if !keep {
+                released += partial.bytes;
+            }
+            keep
+        });
+        self.buffered_bytes -= released;
+        let discarded = before - self.partial.len();
+        if discarded > 0 {
+            debug!(discarded, "discarded incomplete datagram fragments");
+            self.expired += discarded as u64;
+        }
+        discarded
+    }
+
+    /// Number of fragment sets awaiting more fragments.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+
+    /// Fragment bytes buffered across incomplete sets.
+    #[must_use]
+    pub const fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    /// Total fragment sets discarded by [`expire`](Self::expire).
+    #[must_use]
+    pub const fn expired(&self) -> u64 {
+        self.expired
+    }
+
+    /// Total incomplete sets evicted to stay within the configured limits.
+    #[must_use]
+    pub const fn evicted(&self) -> u64 {
+        self.evicted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn three_fragments() -> (Vec<u8>, Vec<Frame>) {
+        let payload: Vec<u8> = (0..250u8).collect();
+        let frames = DatagramFragmenter::new()
+            .fragment(payload.clone(), 100)
+            .expect("fragment");
+        (payload, frames)
+    }
+
+    #[test]
+    fn small_datagram_is_not_fragmented() {
+        let frames = DatagramFragmenter::new()
+            .fragment(vec![1; 100], 100)
+            .expect("fragment");
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].frame_type(), FrameType::Datagram);
+        assert_eq!(frames[0].payload().len(), 100);
+    }
+
+    #[test]
+    fn three_fragments_reassemble_in_order() {
+        let (payload, frames) = three_fragments();
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|frame| frame.payload().len() <= 100));
+
+        let now = SystemTime::now();
+        let mut reassembler = DatagramReassembler::default();
+        let mut outputs: Vec<_> = frames
+            .into_iter()
+            .map(|frame| reassembler.on_frame(frame, now).expect("fragment"))
+            .collect();
+        assert_eq!(outputs.pop().unwrap(), Some(payload));
+        assert!(outputs.iter().all(Option::is_none));
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn three_fragments_reassemble_out_of_order() {
+        let (payload, frames) = three_fragments();
+        let [first, second, third] = <[Frame; 3]>::try_from(frames).expect("three fragments");
+
+        let now = SystemTime::now();
+        let mut reassembler = DatagramReassembler::default();
+        assert_eq!(reassembler.on_frame(second.clone(), now), Ok(None));
+        // A duplicated fragment is ignored.
+        assert_eq!(reassembler.on_frame(second, now), Ok(None));
+        assert_eq!(reassembler.on_frame(third, now), Ok(None));
+        assert_eq!(reassembler.on_frame(first, now), Ok(Some(payload)));
+        assert_eq!(reassembler.pending(), 0);
+    }
+
+    #[test]
+    fn timeout_discards_incomplete_set() {
+        let (_, mut frames) = three_fragments();
+        frames.remove(1);
+
+        let start = SystemTime::now();
+        let mut reassembler = DatagramReassembler::new(Duration::from_millis(500));
+        for frame in frames {
+            assert_eq!(reassembler.on_frame(frame, start), Ok(None));
+        }
+        assert_eq!(reassembler.expire(start + Duration::from_millis(100)), 0);
+        assert_eq!(reassembler.pending(), 1);
+        assert_eq!(reassembler.expire(start + Duration::from_millis(500)), 1);
+        assert_eq!(reassembler.pending(), 0);
+        assert_eq!(reassembler.expired(), 1);
+    }
+
+    #[test]
+    fn malformed_fragments_rejected() {
+        let mut reassembler = DatagramReassembler::default();
+        let now = SystemTime::now();
+        let short = Frame::new(FrameType::DatagramFragment, vec![0; 4]);
+        assert_eq!(
+            reassembler.on_frame(short, now),
+            Err(DatagramError::MalformedFragment)
+        );
+        let bad_index = Frame::new(FrameType::DatagramFragment, vec![0, 0, 0, 0, 2, 0, 2, 0]);
+        assert_eq!(
+            reassembler.on_frame(bad_index, now),
+            Err(DatagramError::MalformedFragment)
+        );
+        let empty = Frame::new(FrameType::DatagramFragment, vec![0, 0, 0, 0, 0, 0, 2, 0]);
+        assert_eq!(
+            reassembler.on_frame(empty, now),
+            Err(DatagramError::MalformedFragment)
+        );
+    }
+
+    fn fragment(id: u32, index: u16, count: u16, data: &[u8]) -> Frame {
+        let mut bytes = id.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&index.to_le_bytes());
+        bytes.extend_from_slice(&count.to_le_bytes());
+        bytes.extend_from_slice(data);
+        Frame::new(FrameType::DatagramFragment, bytes)
+    }
+
+    #[test]
+    fn flood_of_distinct_ids_stays_within_limits() {
+        let config = ReassemblyConfig {
+            max_pending: 16,
+            max_buffered_bytes: 4096,
+            ..ReassemblyConfig::default()
+        };
+        let mut reassembler = DatagramReassembler::with_config(config);
+        let now = SystemTime::now();
+        for id in 0..10_000 {
+            let frame = fragment(id, 0, 2, &[0xAB; 200]);
+            assert_eq!(reassembler.on_frame(frame, now), Ok(None));
+            assert!(reassembler.pending() <= 16);
+            assert!(reassembler.buffered_bytes() <= 4096);
+        }
+        assert_eq!(reassembler.pending(), 16);
+        assert_eq!(reassembler.evicted(), 10_000 - 16);
+
+        // The newest sets survived and still complete.
+        let last = fragment(9_999, 1, 2, &[0xCD; 10]);
+        let datagram = reassembler.on_frame(last, now).expect("fragment").unwrap();
+        assert_eq!(datagram.len(), 210);
+        assert_eq!(reassembler.buffered_bytes(), 15 * 200);
+    }
+
+    #[test]
+    fn sets_larger_than_the_largest_datagram_are_rejected() {
+        let config = ReassemblyConfig {
+            max_datagram_len: 1000,
+            ..ReassemblyConfig::default()
+        };
+        let mut reassembler = DatagramReassembler::with_config(config);
+        let now = SystemTime::now();
+        // A huge count is refused before anything is buffered.
+        assert_eq!(
+            reassembler.on_frame(fragment(1, u16::MAX - 1, u16::MAX, &[0]), now),
+            Err(DatagramError::PayloadTooLarge {
+                len: usize::from(u16::MAX),
+                max: 1000
+            })
+        );
+        assert_eq!(
+            reassembler.on_frame(fragment(2, 0, 3, &[0; 600]), now),
+            Err(DatagramError::PayloadTooLarge {
+                len: 1201,
+                max: 1000
+            })
+        );
+        assert_eq!(reassembler.pending(), 0);
+
+        // Fragments of one set must agree on their size.
+        assert_eq!(
+            reassembler.on_frame(fragment(3, 0, 3, &[0; 100]), now),
+            Ok(None)
+        );
+        assert_eq!(
+            reassembler.on_frame(fragment(3, 1, 3, &[0; 400]), now),
+            Err(DatagramError::MalformedFragment)
+        );
+    }
+
+    #[test]
+    fn expiry_releases_the_byte_budget() {
+        let (_, mut frames) = three_fragments();
+        frames.remove(1);
+        let start = SystemTime::now();
+        let mut reassembler = DatagramReassembler::default();
+        for frame in frames {
+            assert_eq!(reassembler.on_frame(frame, start), Ok(None));
+        }
+        assert_eq!(reassembler.buffered_bytes(), 92 + 66);
+        reassembler.expire(start + DEFAULT_REASSEMBLY_TIMEOUT);
+        assert_eq!(reassembler.buffered_bytes(), 0);
+    }
+}
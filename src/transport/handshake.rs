@@ -1,13 +1,43 @@
 //! Handshake state machines for the MXP custom transport.
 
-use std::collections::{HashSet, VecDeque};
+use std::collections::HashSet;
+use std::net::SocketAddr;
 use std::time::{Duration, SystemTime};
 
+use super::admission::{AdmissionControl, ConnectionLimits, Refusal};
 use super::crypto::{
-    AEAD_NONCE_LEN, AeadNonce, CryptoError, HandshakeState, PUBLIC_KEY_LEN, PrivateKey, PublicKey,
-    SHARED_SECRET_LEN, SessionKeys, derive_session_keys, x25519_diffie_hellman,
+    AEAD_NONCE_LEN, AEAD_TAG_LEN, AeadCipherSet, AeadNonce, AeadTag, CryptoError, HandshakeState,
+    HashAlgorithm, IDENTITY_KEY_LEN, Identity, IdentityKey, PUBLIC_KEY_LEN, PrivateKey, PublicKey,
+    SIGNATURE_LEN, SessionKeys, Signature, TRANSCRIPT_HASH_LEN, decrypt, derive_early_data_key,
+    derive_session_keys, encrypt, sha256, x25519_diffie_hellman,
 };
-use super::session::{SessionTicket, SessionTicketManager};
+use super::params::TransportParameters;
+use super::retry::{RetryConfig, RetryToken};
+use super::session::{
+    ClientTicketCache, SEALED_TICKET_LEN, SessionTicket, SessionTicketManager, TICKET_ID_LEN,
+};
+
+/// Handshake protocol version spoken by this implementation.
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Protocol versions this implementation can negotiate, highest first.
+pub const SUPPORTED_VERSIONS: &[u8] = &[PROTOCOL_VERSION];
+
+/// Default number of entries an [`AntiReplayStore`] holds per time window.
+pub const DEFAULT_ANTI_REPLAY_CAPACITY: usize = 1 << 17;
+/// Length of the truncated SHA-256 digest stored per anti-replay entry.
+const REPLAY_DIGEST_LEN: usize = 16;
+
+/// Bytes preceding the sealed early data in a resumption hello: sealed ticket and nonce.
+const EARLY_DATA_HEADER_LEN: usize = SEALED_TICKET_LEN + AEAD_NONCE_LEN;
+/// Most 0-RTT early data a resumption hello carries: what keeps the sealed payload within
+/// the hello's `u16` payload length.
+pub const MAX_EARLY_DATA_LEN: usize = u16::MAX as usize - EARLY_DATA_HEADER_LEN - AEAD_TAG_LEN;
+/// Longest retry token a hello can echo, as its length is one byte.
+pub const MAX_RETRY_TOKEN_LEN: usize = u8::MAX as usize;
+
+/// Context strings prefixed to the transcript hash before it is signed, one per role.
+const RESPONDER_IDENTITY_CONTEXT: &[u8] = b"mxp responder identity";
+const INITIATOR_IDENTITY_CONTEXT: &[u8] = b"mxp initiator identity";
 
 /// Different handshake messages exchanged between peers.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -18,6 +48,10 @@ pub enum HandshakeMessageKind {
     ResponderHello = 0x02,
     /// Initiator finish (confirms key material and completes handshake).
     InitiatorFinish = 0x03,
+    /// Responder retry (carries an address validation token to echo in the next hello).
+    Retry = 0x04,
+    /// Responder refusal (carries a one-byte [`Refusal`] reason; no state was kept).
+    Refused = 0x05,
 }
 
 impl HandshakeMessageKind {
@@ -27,84 +61,216 @@ impl HandshakeMessageKind {
             0x01 => Some(Self::InitiatorHello),
             0x02 => Some(Self::ResponderHello),
             0x03 => Some(Self::InitiatorFinish),
+            0x04 => Some(Self::Retry),
+            0x05 => Some(Self::Refused),
             _ => None,
         }
     }
 }
 
 /// Errors produced by handshake processing.
-#[derive(Debug)]
+#[derive(Debug, thiserror::Error)]
 pub enum HandshakeError {
     /// Unexpected message type for the current stage.
+    #[error("unexpected handshake message for the current stage")]
     UnexpectedMessage,
     /// Message payload malformed.
+    #[error("malformed handshake message")]
     MalformedMessage,
     /// Required key material missing.
+    #[error("required key material missing")]
     MissingKeyMaterial,
     /// Cryptographic failure.
-    Crypto(CryptoError),
+    #[error("handshake crypto error: {0}")]
+    Crypto(#[from] CryptoError),
     /// Anti-replay filter rejected the message.
+    #[error("handshake message replayed")]
     ReplayDetected,
-}
-
-impl From<CryptoError> for HandshakeError {
-    fn from(err: CryptoError) -> Self {
-        Self::Crypto(err)
-    }
+    /// Session ticket is expired or otherwise unusable for resumption.
+    #[error("session ticket invalid or expired")]
+    InvalidTicket,
+    /// Early data is longer than a hello can carry.
+    #[error("early data of {len} bytes exceeds the limit of {MAX_EARLY_DATA_LEN}")]
+    EarlyDataTooLarge {
+        /// Length of the early data offered.
+        len: usize,
+    },
+    /// Peer did not respond before the handshake deadline and retransmissions were exhausted.
+    #[error("handshake timed out")]
+    Timeout,
+    /// Responder requires address validation; answer with [`HandshakeServer::issue_retry`].
+    #[error("address validation required")]
+    RetryRequired,
+    /// Retry token is stale, malformed, or was not issued for this peer.
+    #[error("invalid retry token")]
+    InvalidRetryToken,
+    /// Retry token is longer than a hello can echo.
+    #[error("retry token of {len} bytes exceeds the limit of {MAX_RETRY_TOKEN_LEN}")]
+    RetryTokenTooLong {
+        /// Length of the token received.
+        len: usize,
+    },
+    /// Peer presented no identity, or one that is not the expected/trusted key.
+    #[error("peer identity missing or not trusted")]
+    UntrustedIdentity,
+    /// Responder turned the handshake away under its admission limits.
+    #[error("handshake refused: {0}")]
+    Refused(Refusal),
+    /// The peers share no AEAD cipher suite.
+    #[error("no common AEAD cipher suite (local {local:?}, peer {peer:?})")]
+    NoCommonCipherSuite {
+        /// Suites the rejecting side supports.
+        local: AeadCipherSet,
+        /// Suites the peer advertised.
+        peer: AeadCipherSet,
+    },
+    /// The peer lays out packets in a different wire version, so none of its packets
+    /// would open.
+    #[error("packet wire version mismatch (local {local}, peer {peer})")]
+    WireVersionMismatch {
+        /// Wire version of the rejecting side.
+        local: u8,
+        /// Wire version the peer advertised.
+        peer: u8,
+    },
+    /// The peers share no protocol version.
+    #[error("no mutual protocol version (offered {offered:?}, supported {supported:?})")]
+    VersionMismatch {
+        /// Versions offered by the initiator.
+        offered: Vec<u8>,
+        /// Versions the rejecting side supports (or the one the responder selected).
+        supported: Vec<u8>,
+    },
 }
 
 /// Serialized handshake message.
 #[derive(Debug, Clone)]
 pub struct HandshakeMessage {
     kind: HandshakeMessageKind,
+    versions: Vec<u8>,
     ephemeral: PublicKey,
     payload: Vec<u8>,
+    retry_token: Option<Vec<u8>>,
 }
 
 impl HandshakeMessage {
-    /// Create a new handshake message.
+    /// Create a new handshake message carrying [`PROTOCOL_VERSION`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `payload` is longer than its `u16` length can encode.
     #[must_use]
     pub fn new(kind: HandshakeMessageKind, ephemeral: PublicKey, payload: Vec<u8>) -> Self {
+        assert!(
+            u16::try_from(payload.len()).is_ok(),
+            "a handshake payload carries at most 65535 bytes, got {}",
+            payload.len()
+        );
         Self {
             kind,
+            versions: vec![PROTOCOL_VERSION],
             ephemeral,
             payload,
+            retry_token: None,
+        }
+    }
+
+    /// Replace the version field: the offered versions in an initiator hello, the selected
+    /// version in every later message.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `versions` holds 1 to 255 entries, the range its one-byte count can
+    /// encode and [`decode`](Self::decode) accepts.
+    #[must_use]
+    pub fn with_versions(mut self, versions: Vec<u8>) -> Self {
+        check_version_count(&versions);
+        self.versions = versions;
+        self
+    }
+
+    /// Attach a retry token echoed back to the responder.
+    ///
+    /// Tokens longer than [`MAX_RETRY_TOKEN_LEN`] fail with
+    /// [`HandshakeError::RetryTokenTooLong`].
+    pub fn with_retry_token(mut self, token: Vec<u8>) -> Result<Self, HandshakeError> {
+        if token.len() > MAX_RETRY_TOKEN_LEN {
+            return Err(HandshakeError::RetryTokenTooLong { len: token.len() });
         }
+        self.retry_token = Some(token);
+        Ok(self)
     }
 
-    /// Encode a message into bytes. Format: [kind (1)][ephemeral (32)][len (u16 LE)][payload].
+    /// Encode a message into bytes. Format: [kind (1)][version count (1)][versions]
+    /// [ephemeral (32)][len (u16 LE)][payload], followed by [token len (1)][token] when a retry
+    /// token is attached.
     #[must_use]
     pub fn encode(&self) -> Vec<u8> {
-        let mut out = Vec::with_capacity(1 + PUBLIC_KEY_LEN + 2 + self.payload.len());
+        let token_len = self.retry_token.as_ref().map_or(0, |token| 1 + token.len());
+        let mut out = Vec::with_capacity(
+            2 + self.versions.len() + PUBLIC_KEY_LEN + 2 + self.payload.len() + token_len,
+        );
         out.push(self.kind as u8);
+        let version_count = u8::try_from(self.versions.len()).expect("checked by with_versions");
+        out.push(version_count);
+        out.extend_from_slice(&self.versions);
         out.extend_from_slice(self.ephemeral.as_bytes());
-        let len = u16::try_from(self.payload.len()).unwrap_or(0);
+        let len = u16::try_from(self.payload.len()).expect("checked by new");
         out.extend_from_slice(&len.to_le_bytes());
         out.extend_from_slice(&self.payload);
+        if let Some(token) = &self.retry_token {
+            out.push(u8::try_from(token.len()).expect("checked by with_retry_token"));
+            out.extend_from_slice(token);
+        }
         out
     }
 
     /// Decode message from bytes.
     pub fn decode(bytes: &[u8]) -> Result<Self, HandshakeError> {
-        if bytes.len() < 1 + PUBLIC_KEY_LEN + 2 {
+        let (&kind, rest) = bytes
+            .split_first()
+            .ok_or(HandshakeError::MalformedMessage)?;
+        let kind = HandshakeMessageKind::from_byte(kind).ok_or(HandshakeError::MalformedMessage)?;
+        let (&version_count, rest) = rest.split_first().ok_or(HandshakeError::MalformedMessage)?;
+        let versions = rest
+            .get(..usize::from(version_count))
+            .filter(|versions| !versions.is_empty())
+            .ok_or(HandshakeError::MalformedMessage)?
+            .to_vec();
+        let header_start = 2 + versions.len();
+        let bytes = &bytes[header_start..];
+
+        if bytes.len() < PUBLIC_KEY_LEN + 2 {
             return Err(HandshakeError::MalformedMessage);
         }
-        let kind =
-            HandshakeMessageKind::from_byte(bytes[0]).ok_or(HandshakeError::MalformedMessage)?;
         let mut key_bytes = [0u8; PUBLIC_KEY_LEN];
-        key_bytes.copy_from_slice(&bytes[1..=PUBLIC_KEY_LEN]);
+        key_bytes.copy_from_slice(&bytes[..PUBLIC_KEY_LEN]);
         let payload_len =
-            u16::from_le_bytes([bytes[1 + PUBLIC_KEY_LEN], bytes[1 + PUBLIC_KEY_LEN + 1]]) as usize;
-        if bytes.len() < 1 + PUBLIC_KEY_LEN + 2 + payload_len {
+            u16::from_le_bytes([bytes[PUBLIC_KEY_LEN], bytes[PUBLIC_KEY_LEN + 1]]) as usize;
+        if bytes.len() < PUBLIC_KEY_LEN + 2 + payload_len {
             return Err(HandshakeError::MalformedMessage);
         }
-        let payload_start = 1 + PUBLIC_KEY_LEN + 2;
-        let payload = bytes[payload_start..payload_start + payload_len].to_vec();
+        let payload_start = PUBLIC_KEY_LEN + 2;
+        let payload_end = payload_start + payload_len;
+        let payload = bytes[payload_start..payload_end].to_vec();
+
+        let retry_token = match bytes.get(payload_end) {
+            Some(&token_len) => {
+                let token_start = payload_end + 1;
+                let token = bytes
+                    .get(token_start..token_start + usize::from(token_len))
+                    .ok_or(HandshakeError::MalformedMessage)?;
+                Some(token.to_vec())
+            }
+            None => None,
+        };
 
         Ok(Self {
             kind,
+            versions,
             ephemeral: PublicKey::from_array(key_bytes),
             payload,
+            retry_token,
         })
     }
 
@@ -114,6 +280,12 @@ impl HandshakeMessage {
         self.kind
     }
 
+    /// Versions offered (initiator hello) or selected (every later message).
+    #[must_use]
+    pub fn versions(&self) -> &[u8] {
+        &self.versions
+    }
+
     /// Access the ephemeral public key.
     #[must_use]
     pub fn ephemeral(&self) -> &PublicKey {
@@ -125,6 +297,51 @@ impl HandshakeMessage {
     pub fn payload(&self) -> &[u8] {
         &self.payload
     }
+
+    /// Retry token echoed in an initiator hello, if any.
+    #[must_use]
+    pub fn retry_token(&self) -> Option<&[u8]> {
+        self.retry_token.as_deref()
+    }
+
+    /// Whether this is a resumption hello carrying 0-RTT early data.
+    ///
+    /// Packets carrying such a message should set [`PacketFlags::EARLY_DATA`](super::PacketFlags::EARLY_DATA).
+    #[must_use]
+    pub fn carries_early_data(&self) -> bool {
+        self.kind == HandshakeMessageKind::InitiatorHello && !self.payload.is_empty()
+    }
+}
+
+/// Responder-side disposition of 0-RTT early data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyDataStatus {
+    /// The initiator did not send early data.
+    NotOffered,
+    /// Early data decrypted under a known ticket; released once the handshake completes.
+    Accepted,
+    /// Early data was discarded (unknown/expired ticket, replay, or authentication failure).
+    Rejected,
+}
+
+fn seal_early_data(
+    ticket: &SessionTicket,
+    ephemeral: &PublicKey,
+    data: &[u8],
+) -> Result<Vec<u8>, HandshakeError> {
+    if data.len() > MAX_EARLY_DATA_LEN {
+        return Err(HandshakeError::EarlyDataTooLarge { len: data.len() });
+    }
+    let key = derive_early_data_key(ticket.secret(), ephemeral)?;
+    let nonce = AeadNonce::random();
+    let (ciphertext, tag) = encrypt(&key, &nonce, data, ticket.id());
+
+    let mut payload = Vec::with_capacity(EARLY_DATA_HEADER_LEN + ciphertext.len() + AEAD_TAG_LEN);
+    payload.extend_from_slice(ticket.sealed());
+    payload.extend_from_slice(nonce.as_bytes());
+    payload.extend_from_slice(&ciphertext);
+    payload.extend_from_slice(tag.as_bytes());
+    Ok(payload)
 }
 
 fn mix_static_prologue(
@@ -145,20 +362,189 @@ fn mix_static_prologue(
     Ok(())
 }
 
+/// Panic unless `versions` fits a handshake message's non-zero, one-byte version count.
+fn check_version_count(versions: &[u8]) {
+    assert!(
+        (1..=usize::from(u8::MAX)).contains(&versions.len()),
+        "a handshake message carries 1 to 255 versions, got {}",
+        versions.len()
+    );
+}
+
+/// Highest version present in both lists.
+fn negotiate_version(offered: &[u8], supported: &[u8]) -> Result<u8, HandshakeError> {
+    offered
+        .iter()
+        .copied()
+        .filter(|version| supported.contains(version))
+        .max()
+        .ok_or_else(|| HandshakeError::VersionMismatch {
+            offered: offered.to_vec(),
+            supported: supported.to_vec(),
+        })
+}
+
+fn identity_message(context: &[u8], transcript: &[u8; TRANSCRIPT_HASH_LEN]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(context.len() + TRANSCRIPT_HASH_LEN);
+    message.extend_from_slice(context);
+    message.extend_from_slice(transcript);
+    message
+}
+
+/// Append an identity proof: `0` when anonymous, otherwise `1`, the identity key, and its
+/// signature over `context || transcript`.
+fn push_identity_proof(
+    out: &mut Vec<u8>,
+    identity: Option<&Identity>,
+    context: &[u8],
+    transcript: &[u8; TRANSCRIPT_HASH_LEN],
+) {
+    let Some(identity) = identity else {
+        out.push(0);
+        return;
+    };
+    let signature = identity.sign(&identity_message(context, transcript));
+    out.push(1);
+    out.extend_from_slice(identity.public_key().as_bytes());
+    out.extend_from_slice(signature.as_bytes());
+}
+
+/// Split the identity proof off the front of `plaintext` and check it, returning the
+/// verified key (if any) and the remaining bytes.
+///
+/// A proof is mandatory when `required` is set; a presented key must satisfy `trusted`.
+fn open_identity_proof<'a>(
+    plaintext: &'a [u8],
+    context: &[u8],
+    transcript: &[u8; TRANSCRIPT_HASH_LEN],
+    required: bool,
+    trusted: impl Fn(&IdentityKey) -> bool,
+) -> Result<(Option<IdentityKey>, &'a [u8]), HandshakeError> {
+    let (&present, rest) = plaintext
+        .split_first()
+        .ok_or(HandshakeError::MalformedMessage)?;
+    match present {
+        0 if required => Err(HandshakeError::UntrustedIdentity),
+        0 => Ok((None, rest)),
+        1 => {
+            if rest.len() < IDENTITY_KEY_LEN + SIGNATURE_LEN {
+                return Err(HandshakeError::MalformedMessage);
+            }
+            let (key, rest) = rest.split_at(IDENTITY_KEY_LEN);
+            let (signature, rest) = rest.split_at(SIGNATURE_LEN);
+            let key = IdentityKey::from_bytes(key)?;
+            if !trusted(&key) {
+                return Err(HandshakeError::UntrustedIdentity);
+            }
+            key.verify(
+                &identity_message(context, transcript),
+                &Signature::from_bytes(signature)?,
+            )?;
+            Ok((Some(key), rest))
+        }
+        _ => Err(HandshakeError::MalformedMessage),
+    }
+}
+
+/// Timer configuration governing handshake stage deadlines and retransmission.
+///
+/// The wait doubles after every retransmission, so with the defaults a silent peer is given
+/// up on 1 + 2 + 4 + 8 = 15 seconds after the first flight.
+#[derive(Debug, Clone)]
+pub struct HandshakeTimeoutConfig {
+    /// Time to wait for the peer's next flight before first retransmitting our last one.
+    pub retransmit_timeout: Duration,
+    /// Number of retransmissions attempted before the handshake fails.
+    pub max_retransmits: u32,
+}
+
+impl Default for HandshakeTimeoutConfig {
+    fn default() -> Self {
+        Self {
+            retransmit_timeout: Duration::from_secs(1),
+            max_retransmits: 3,
+        }
+    }
+}
+
+/// Result of polling a flight timer.
+enum FlightTimeout {
+    Pending,
+    Retransmit(HandshakeMessage),
+    Expired,
+}
+
+/// Tracks the deadline for the current stage and the flight to retransmit.
+#[derive(Debug, Clone)]
+struct FlightTimer {
+    config: HandshakeTimeoutConfig,
+    deadline: Option<SystemTime>,
+    retransmits: u32,
+    last_flight: Option<HandshakeMessage>,
+}
+
+impl FlightTimer {
+    fn new(config: HandshakeTimeoutConfig) -> Self {
+        Self {
+            config,
+            deadline: None,
+            retransmits: 0,
+            last_flight: None,
+        }
+    }
+
+    fn arm(&mut self, flight: &HandshakeMessage, now: SystemTime) {
+        self.deadline = Some(now + self.config.retransmit_timeout);
+        self.retransmits = 0;
+        self.last_flight = Some(flight.clone());
+    }
+
+    fn disarm(&mut self) {
+        self.deadline = None;
+        self.last_flight = None;
+    }
+
+    /// Wait before the next retransmission, doubling with each one already sent.
+    fn backoff(&self) -> Duration {
+        self.config
+            .retransmit_timeout
+            .saturating_mul(2_u32.saturating_pow(self.retransmits))
+    }
+
+    fn poll(&mut self, now: SystemTime) -> FlightTimeout {
+        match self.deadline {
+            Some(deadline) if deadline <= now => {}
+            _ => return FlightTimeout::Pending,
+        }
+        match self.last_flight.clone() {
+            Some(flight) if self.retransmits < self.config.max_retransmits => {
+                self.retransmits += 1;
+                self.deadline = Some(now + self.backoff());
+                FlightTimeout::Retransmit(flight)
+            }
+            _ => {
+                self.disarm();
+                FlightTimeout::Expired
+            }
+        }
+    }
+}
+
 /// Stages of the initiator handshake.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum InitiatorStage {
     Ready,
     AwaitingResponse,
     Complete,
+    Failed,
 }
 
 /// Stages of the responder handshake.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ResponderStage {
-    Ready,
     AwaitingFinal,
     Complete,
+    Failed,
 }
 
 /// Represents the initiator side of the handshake.
@@ -168,24 +554,208 @@ pub struct Initiator {
     stage: InitiatorStage,
     remote_static: PublicKey,
     anti_replay: AntiReplayStore,
+    early_data_offered: bool,
+    early_data_accepted: Option<bool>,
+    timer: FlightTimer,
+    retry_token: Option<Vec<u8>>,
+    transport_parameters: TransportParameters,
+    negotiated_parameters: Option<TransportParameters>,
+    identity: Option<Identity>,
+    expected_peer_identity: Option<IdentityKey>,
+    peer_identity: Option<IdentityKey>,
+    versions: Vec<u8>,
+    negotiated_version: Option<u8>,
 }
 
 impl Initiator {
     /// Create a new initiator.
     #[must_use]
     pub fn new(local_static: PrivateKey, remote_static: PublicKey) -> Self {
-        let mut state = HandshakeState::new(local_static);
+        Self::with_hash_algorithm(local_static, remote_static, HashAlgorithm::default())
+    }
+
+    /// Create a new initiator whose transcript and key schedule use `hash`.
+    ///
+    /// The responder must be configured with the same algorithm.
+    #[must_use]
+    pub fn with_hash_algorithm(
+        local_static: PrivateKey,
+        remote_static: PublicKey,
+        hash: HashAlgorithm,
+    ) -> Self {
+        let mut state = HandshakeState::with_hash_algorithm(local_static, hash);
         state.set_remote_static(remote_static.clone());
         Self {
             state,
             stage: InitiatorStage::Ready,
             remote_static,
             anti_replay: AntiReplayStore::new(512, Duration::from_secs(60)),
+            early_data_offered: false,
+            early_data_accepted: None,
+            timer: FlightTimer::new(HandshakeTimeoutConfig::default()),
+            retry_token: None,
+            transport_parameters: TransportParameters::default(),
+            negotiated_parameters: None,
+            identity: None,
+            expected_peer_identity: None,
+            peer_identity: None,
+            versions: SUPPORTED_VERSIONS.to_vec(),
+            negotiated_version: None,
+        }
+    }
+
+    /// Advertise `params` to the responder in the finish message.
+    #[must_use]
+    pub fn with_transport_parameters(mut self, params: TransportParameters) -> Self {
+        self.transport_parameters = params;
+        self
+    }
+
+    /// Offer `versions` instead of [`SUPPORTED_VERSIONS`]; the responder picks the highest one
+    /// it also supports.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `versions` holds 1 to 255 entries, as a hello must offer at least one
+    /// and counts them in a single byte.
+    #[must_use]
+    pub fn with_versions(mut self, versions: Vec<u8>) -> Self {
+        check_version_count(&versions);
+        self.versions = versions;
+        self
+    }
+
+    /// Version selected by the responder (`None` until its hello is processed).
+    #[must_use]
+    pub const fn negotiated_version(&self) -> Option<u8> {
+        self.negotiated_version
+    }
+
+    /// Sign the handshake transcript with `identity` so the responder can authenticate us.
+    #[must_use]
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Require the responder to prove possession of `key`; any other or missing identity
+    /// fails the handshake with [`HandshakeError::UntrustedIdentity`].
+    #[must_use]
+    pub fn with_peer_identity(mut self, key: IdentityKey) -> Self {
+        self.expected_peer_identity = Some(key);
+        self
+    }
+
+    /// Identity key the responder proved possession of (`None` if it sent none).
+    #[must_use]
+    pub const fn peer_identity(&self) -> Option<&IdentityKey> {
+        self.peer_identity.as_ref()
+    }
+
+    /// Parameters agreed with the responder (`None` until its hello is processed).
+    #[must_use]
+    pub const fn negotiated_parameters(&self) -> Option<&TransportParameters> {
+        self.negotiated_parameters.as_ref()
+    }
+
+    /// Override the stage deadline and retransmission policy.
+    #[must_use]
+    pub fn with_timeouts(mut self, config: HandshakeTimeoutConfig) -> Self {
+        self.timer = FlightTimer::new(config);
+        self
+    }
+
+    /// Deadline for the current stage, if one is armed.
+    #[must_use]
+    pub const fn deadline(&self) -> Option<SystemTime> {
+        self.timer.deadline
+    }
+
+    /// Whether the handshake has failed (e.g. timed out).
+    #[must_use]
+    pub fn is_failed(&self) -> bool {
+        self.stage == InitiatorStage::Failed
+    }
+
+    /// Drive stage timers: returns the hello to retransmit when the deadline passes, or
+    /// fails the handshake with [`HandshakeError::Timeout`] once retransmissions are exhausted.
+    pub fn poll_timeout(
+        &mut self,
+        now: SystemTime,
+    ) -> Result<Option<HandshakeMessage>, HandshakeError> {
+        if self.stage != InitiatorStage::AwaitingResponse {
+            return Ok(None);
+        }
+        match self.timer.poll(now) {
+            FlightTimeout::Pending => Ok(None),
+            FlightTimeout::Retransmit(flight) => Ok(Some(flight)),
+            FlightTimeout::Expired => {
+                self.stage = InitiatorStage::Failed;
+                Err(HandshakeError::Timeout)
+            }
+        }
+    }
+
+    /// Initiate the handshake by sending the first message at `now`.
+    pub fn initiate(&mut self, now: SystemTime) -> Result<HandshakeMessage, HandshakeError> {
+        self.start(None, now)
+    }
+
+    /// Initiate a resumed handshake whose first flight carries 0-RTT early data.
+    ///
+    /// The early data is sealed under a key derived from the ticket secret; the responder
+    /// only releases it once the handshake completes. Early data longer than
+    /// [`MAX_EARLY_DATA_LEN`] fails with [`HandshakeError::EarlyDataTooLarge`].
+    pub fn initiate_with_early_data(
+        &mut self,
+        ticket: &SessionTicket,
+        early_data: &[u8],
+        now: SystemTime,
+    ) -> Result<HandshakeMessage, HandshakeError> {
+        if !ticket.is_valid_at(now) {
+            return Err(HandshakeError::InvalidTicket);
+        }
+        self.start(Some((ticket, early_data)), now)
+    }
+
+    /// Initiate a handshake to this responder, sending `early_data` as 0-RTT if `cache` holds
+    /// a ticket for it and falling back to a full handshake otherwise.
+    ///
+    /// Returns the hello and whether early data was sent. Unless
+    /// [`early_data_accepted`](Self::early_data_accepted) reports `Some(true)` once the
+    /// responder hello arrives, the caller must send the data again after the handshake.
+    ///
+    /// Early data is not protected against replay across responders: each responder keeps
+    /// its own in-memory replay store, so an attacker can replay a captured hello to another
+    /// server sharing the ticket keys, or to the same server after a restart. Only send
+    /// idempotent messages as early data: an `Event` delivered twice is harmless, a `Call`
+    /// with side effects may execute twice.
+    pub fn initiate_0rtt(
+        &mut self,
+        cache: &mut ClientTicketCache,
+        early_data: &[u8],
+        now: SystemTime,
+    ) -> Result<(HandshakeMessage, bool), HandshakeError> {
+        match cache.take_at(&self.remote_static, now) {
+            Some(ticket) => Ok((
+                self.initiate_with_early_data(&ticket, early_data, now)?,
+                true,
+            )),
+            None => Ok((self.initiate(now)?, false)),
         }
     }
 
-    /// Initiate the handshake by sending the first message.
-    pub fn initiate(&mut self) -> Result<HandshakeMessage, HandshakeError> {
+    /// Whether the responder accepted our early data (`None` until its hello arrives).
+    #[must_use]
+    pub const fn early_data_accepted(&self) -> Option<bool> {
+        self.early_data_accepted
+    }
+
+    fn start(
+        &mut self,
+        early: Option<(&SessionTicket, &[u8])>,
+        now: SystemTime,
+    ) -> Result<HandshakeMessage, HandshakeError> {
         let local_ephemeral = self.state.local_static().derive_ephemeral(0x11);
         self.state.set_local_ephemeral(local_ephemeral.clone());
         let public_ephemeral = local_ephemeral.public_key();
@@ -193,170 +763,717 @@ impl Initiator {
         let local_public = self.state.local_static().public_key();
         mix_static_prologue(&mut self.state, &local_public, &self.remote_static)?;
 
+        let payload = match early {
+            Some((ticket, data)) => {
+                self.early_data_offered = true;
+                seal_early_data(ticket, &public_ephemeral, data)?
+            }
+            None => Vec::new(),
+        };
+
+        Ok(self.send_hello(public_ephemeral, payload, now))
+    }
+
+    fn send_hello(
+        &mut self,
+        ephemeral: PublicKey,
+        payload: Vec<u8>,
+        now: SystemTime,
+    ) -> HandshakeMessage {
+        let mut hello =
+            HandshakeMessage::new(HandshakeMessageKind::InitiatorHello, ephemeral, payload)
+                .with_versions(self.versions.clone());
+        if let Some(token) = &self.retry_token {
+            hello = hello
+                .with_retry_token(token.clone())
+                .expect("checked by handle_retry");
+        }
+        self.state.mix_hash(&hello.encode());
+
+        self.timer.arm(&hello, now);
         self.stage = InitiatorStage::AwaitingResponse;
-        Ok(HandshakeMessage::new(
-            HandshakeMessageKind::InitiatorHello,
-            public_ephemeral,
-            Vec::new(),
-        ))
+        hello
+    }
+
+    /// Process a responder retry and produce a new hello echoing its token.
+    ///
+    /// The handshake restarts from scratch; only one retry is honoured per handshake. A
+    /// token longer than [`MAX_RETRY_TOKEN_LEN`] fails with
+    /// [`HandshakeError::RetryTokenTooLong`] and leaves the handshake waiting for a response.
+    pub fn handle_retry(
+        &mut self,
+        message: &HandshakeMessage,
+        now: SystemTime,
+    ) -> Result<HandshakeMessage, HandshakeError> {
+        if self.stage != InitiatorStage::AwaitingResponse
+            || message.kind() != HandshakeMessageKind::Retry
+            || self.retry_token.is_some()
+        {
+            return Err(HandshakeError::UnexpectedMessage);
+        }
+        let previous = self
+            .timer
+            .last_flight
+            .clone()
+            .ok_or(HandshakeError::MissingKeyMaterial)?;
+        let token = message.payload();
+        if token.len() > MAX_RETRY_TOKEN_LEN {
+            return Err(HandshakeError::RetryTokenTooLong { len: token.len() });
+        }
+        self.retry_token = Some(token.to_vec());
+
+        let local_static = self.state.local_static().clone();
+        let local_ephemeral = self
+            .state
+            .local_ephemeral()
+            .cloned()
+            .ok_or(HandshakeError::MissingKeyMaterial)?;
+        self.state = HandshakeState::with_hash_algorithm(local_static, self.state.hash_algorithm());
+        self.state.set_remote_static(self.remote_static.clone());
+        self.state.set_local_ephemeral(local_ephemeral);
+
+        let local_public = self.state.local_static().public_key();
+        mix_static_prologue(&mut self.state, &local_public, &self.remote_static)?;
+        Ok(self.send_hello(previous.ephemeral, previous.payload, now))
     }
 
     /// Process the responder hello and produce the final message along with session keys.
     pub fn handle_response(
         &mut self,
         message: &HandshakeMessage,
+        now: SystemTime,
     ) -> Result<(HandshakeMessage, SessionKeys), HandshakeError> {
+        if self.stage == InitiatorStage::AwaitingResponse
+            && message.kind() == HandshakeMessageKind::Refused
+        {
+            return Err(match message.payload() {
+                [reason] => Refusal::from_byte(*reason)
+                    .map_or(HandshakeError::MalformedMessage, HandshakeError::Refused),
+                _ => HandshakeError::MalformedMessage,
+            });
+        }
         if self.stage != InitiatorStage::AwaitingResponse
             || message.kind() != HandshakeMessageKind::ResponderHello
         {
             return Err(HandshakeError::UnexpectedMessage);
         }
+        let version = match message.versions() {
+            [version] if self.versions.contains(version) => *version,
+            selected => {
+                return Err(HandshakeError::VersionMismatch {
+                    offered: self.versions.clone(),
+                    supported: selected.to_vec(),
+                });
+            }
+        };
 
-        self.anti_replay.record(message.payload())?;
-
+        // Work on a copy so a forged hello leaves us able to accept the genuine one.
+        let mut state = self.state.clone();
         let remote_ephemeral = message.ephemeral().clone();
-        self.state.set_remote_ephemeral(remote_ephemeral.clone());
+        state.set_remote_ephemeral(remote_ephemeral.clone());
 
-        let local_ephemeral = self
-            .state
+        let local_ephemeral = state
             .local_ephemeral()
             .cloned()
             .ok_or(HandshakeError::MissingKeyMaterial)?;
 
         let shared = x25519_diffie_hellman(&local_ephemeral, &remote_ephemeral)?;
-        self.state.mix_key(shared.as_bytes())?;
-
-        let session_keys = derive_session_keys(&self.state, true)?;
-
-        // Incorporate payload into a chaining key as confirmation data.
-        let payload_clone = message.payload().to_vec();
-        self.state.mix_key(&payload_clone)?;
-
-        let confirmation = self.make_confirmation_payload();
+        state.mix_key(shared.as_bytes())?;
+        let transcript = *state.handshake_hash();
+        let plaintext = state.decrypt_payload(message.payload())?;
+
+        let (early_status, rest) = if self.early_data_offered {
+            let (status, rest) = plaintext
+                .split_first()
+                .ok_or(HandshakeError::MalformedMessage)?;
+            (Some(*status == 1), rest)
+        } else {
+            (None, &plaintext[..])
+        };
+        let expected = self.expected_peer_identity;
+        let (peer_identity, encoded_params) = open_identity_proof(
+            rest,
+            RESPONDER_IDENTITY_CONTEXT,
+            &transcript,
+            expected.is_some(),
+            |key| expected.is_none_or(|expected| expected == *key),
+        )?;
+        let peer_params = TransportParameters::decode(encoded_params)
+            .map_err(|_| HandshakeError::MalformedMessage)?;
+        check_peer_parameters(&self.transport_parameters, &peer_params)?;
+
+        self.anti_replay.record_at(message.payload(), now)?;
+        self.state = state;
+        self.state.mix_hash(&message.encode());
+        self.early_data_accepted = early_status;
+        self.peer_identity = peer_identity;
+        self.negotiated_version = Some(version);
+        self.negotiated_parameters = Some(self.transport_parameters.negotiate(&peer_params));
+
+        let session_keys = derive_session_keys(
+            self.state.hash_algorithm(),
+            self.state.chaining_key(),
+            self.state.handshake_hash(),
+            true,
+        )?;
+
+        // Key confirmation: our identity proof and transport parameters sealed over the full
+        // transcript.
+        let mut plaintext = Vec::new();
+        push_identity_proof(
+            &mut plaintext,
+            self.identity.as_ref(),
+            INITIATOR_IDENTITY_CONTEXT,
+            self.state.handshake_hash(),
+        );
+        plaintext.extend_from_slice(&self.transport_parameters.encode());
+        let confirmation = self.state.encrypt_payload(&plaintext);
         let final_message = HandshakeMessage::new(
             HandshakeMessageKind::InitiatorFinish,
             local_ephemeral.public_key(),
             confirmation,
-        );
+        )
+        .with_versions(vec![version]);
+        self.state.mix_hash(&final_message.encode());
 
+        self.timer.disarm();
         self.stage = InitiatorStage::Complete;
         Ok((final_message, session_keys))
     }
-
-    fn make_confirmation_payload(&self) -> Vec<u8> {
-        let chaining = self.state.chaining_key();
-        chaining.iter().copied().take(16).collect()
-    }
 }
 
-/// Represents the responder side of the handshake.
+/// Server side of the handshake: long-lived state shared by every connection it accepts.
+///
+/// Owns the static key, session ticket manager, and anti-replay stores. Each initiator hello
+/// is handed to [`accept`](Self::accept), which returns a [`PendingHandshake`] holding that
+/// connection's stage and handshake keys, so any number of handshakes can be in flight at once.
 #[derive(Debug, Clone)]
-pub struct Responder {
-    state: HandshakeState,
-    stage: ResponderStage,
+pub struct HandshakeServer {
+    local_static: PrivateKey,
+    hash: HashAlgorithm,
     anti_replay: AntiReplayStore,
+    early_data_replay: AntiReplayStore,
     tickets: SessionTicketManager,
+    timeouts: HandshakeTimeoutConfig,
+    retry: Option<RetryConfig>,
+    admission: Option<AdmissionControl>,
+    transport_parameters: TransportParameters,
+    identity: Option<Identity>,
+    trusted_identities: Option<HashSet<IdentityKey>>,
+    versions: Vec<u8>,
 }
 
-impl Responder {
-    /// Create a new responder with its static key and optional peer static key.
-    pub fn new(
-        local_static: PrivateKey,
-        remote_static: Option<PublicKey>,
-    ) -> Result<Self, HandshakeError> {
-        let mut state = HandshakeState::new(local_static);
-        if let Some(peer) = remote_static {
-            let local_public = state.local_static().public_key();
-            mix_static_prologue(&mut state, &local_public, &peer)?;
-            state.set_remote_static(peer);
+impl HandshakeServer {
+    /// Create a server with its static key.
+    #[must_use]
+    pub fn new(local_static: PrivateKey) -> Self {
+        Self::with_hash_algorithm(local_static, HashAlgorithm::default())
+    }
+
+    /// Create a server whose transcripts and key schedules use `hash`.
+    ///
+    /// A peer using a different algorithm cannot open the responder hello, so the handshake
+    /// fails with [`HandshakeError::Crypto`].
+    #[must_use]
+    pub fn with_hash_algorithm(local_static: PrivateKey, hash: HashAlgorithm) -> Self {
+        Self {
+            local_static,
+            hash,
+            anti_replay: AntiReplayStore::new(
+                DEFAULT_ANTI_REPLAY_CAPACITY,
+                Duration::from_secs(60),
+            ),
+            early_data_replay: AntiReplayStore::new(
+                DEFAULT_ANTI_REPLAY_CAPACITY,
+                Duration::from_secs(600),
+            ),
+            tickets: SessionTicketManager::new(Duration::from_secs(600)),
+            timeouts: HandshakeTimeoutConfig::default(),
+            retry: None,
+            admission: None,
+            transport_parameters: TransportParameters::default(),
+            identity: None,
+            trusted_identities: None,
+            versions: SUPPORTED_VERSIONS.to_vec(),
         }
+    }
 
-        Ok(Self {
-            state,
-            stage: ResponderStage::Ready,
-            anti_replay: AntiReplayStore::new(512, Duration::from_secs(60)),
-            tickets: SessionTicketManager::new(Duration::from_secs(600), 1024),
-        })
+    /// Advertise `params` to initiators in the responder hello.
+    #[must_use]
+    pub fn with_transport_parameters(mut self, params: TransportParameters) -> Self {
+        self.transport_parameters = params;
+        self
     }
 
-    /// Process the initiator hello and produce responder hello.
-    pub fn handle_initiator_hello(
-        &mut self,
-        message: &HandshakeMessage,
-    ) -> Result<HandshakeMessage, HandshakeError> {
-        if self.stage != ResponderStage::Ready
-            || message.kind() != HandshakeMessageKind::InitiatorHello
-        {
-            return Err(HandshakeError::UnexpectedMessage);
-        }
+    /// Accept `versions` instead of [`SUPPORTED_VERSIONS`]. Hellos sharing none of them fail
+    /// with [`HandshakeError::VersionMismatch`].
+    #[must_use]
+    pub fn with_supported_versions(mut self, versions: Vec<u8>) -> Self {
+        self.versions = versions;
+        self
+    }
 
-        let encoded = message.encode();
-        self.anti_replay.record(&encoded)?;
+    /// Sign each responder hello's transcript with `identity`.
+    #[must_use]
+    pub fn with_identity(mut self, identity: Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
 
-        self.state.set_remote_ephemeral(message.ephemeral().clone());
+    /// Only complete handshakes with initiators proving possession of one of `keys`; others
+    /// fail with [`HandshakeError::UntrustedIdentity`]. By default any identity (or none) is
+    /// accepted and reported in [`ResponderOutcome::peer_identity`].
+    #[must_use]
+    pub fn with_trusted_identities(mut self, keys: impl IntoIterator<Item = IdentityKey>) -> Self {
+        self.trusted_identities = Some(keys.into_iter().collect());
+        self
+    }
 
-        let local_ephemeral = self.state.local_static().derive_ephemeral(0x22);
-        self.state.set_local_ephemeral(local_ephemeral.clone());
+    /// Require initiators to echo a retry token bound to their address before a handshake
+    /// proceeds; hellos must then be passed to [`accept_from`](Self::accept_from).
+    ///
+    /// Once a hello with a valid token is accepted the peer address is validated and the
+    /// caller may lift its [`AntiAmplificationGuard`](super::AntiAmplificationGuard) limit.
+    #[must_use]
+    pub fn with_retry(mut self, config: RetryConfig) -> Self {
+        self.retry = Some(config);
+        self
+    }
 
-        let shared = x25519_diffie_hellman(&local_ephemeral, message.ephemeral())?;
-        self.state.mix_key(shared.as_bytes())?;
+    /// Consult `limits` before processing each initiator hello; hellos must then be passed
+    /// to [`accept_from`](Self::accept_from).
+    ///
+    /// A hello over the limits fails with [`HandshakeError::Refused`] before any handshake
+    /// state is created; answer it with [`refuse`](Self::refuse). An accepted hello holds a
+    /// connection slot for its address until released through
+    /// [`admission_mut`](Self::admission_mut) when the handshake fails or the connection ends.
+    #[must_use]
+    pub fn with_admission(mut self, limits: ConnectionLimits) -> Self {
+        self.admission = Some(AdmissionControl::new(limits));
+        self
+    }
+
+    /// Admission control in use, e.g. to release slots or charge received bytes.
+    pub fn admission_mut(&mut self) -> Option<&mut AdmissionControl> {
+        self.admission.as_mut()
+    }
+
+    /// Build the stateless reply to a hello refused with `refusal`.
+    #[must_use]
+    pub fn refuse(&self, refusal: Refusal) -> HandshakeMessage {
+        HandshakeMessage::new(
+            HandshakeMessageKind::Refused,
+            self.local_static.public_key(),
+            vec![refusal as u8],
+        )
+    }
+
+    /// Override the stage deadline and retransmission policy of accepted handshakes.
+    #[must_use]
+    pub fn with_timeouts(mut self, config: HandshakeTimeoutConfig) -> Self {
+        self.timeouts = config;
+        self
+    }
+
+    /// Use a ticket manager carried over from earlier sessions, enabling resumption.
+    #[must_use]
+    pub fn with_tickets(mut self, tickets: SessionTicketManager) -> Self {
+        self.tickets = tickets;
+        self
+    }
 
-        let mut payload = Vec::with_capacity(SHARED_SECRET_LEN);
-        payload.extend_from_slice(self.state.temp_key());
+    /// Borrow the ticket manager (e.g. to share issued tickets with another server).
+    #[must_use]
+    pub fn tickets(&self) -> &SessionTicketManager {
+        &self.tickets
+    }
+
+    /// Mutable access to the ticket manager, e.g. to [`rotate`](SessionTicketManager::rotate)
+    /// its key.
+    pub const fn tickets_mut(&mut self) -> &mut SessionTicketManager {
+        &mut self.tickets
+    }
 
-        self.stage = ResponderStage::AwaitingFinal;
+    /// Build a retry message carrying a fresh token for the initiator at `peer`.
+    pub fn issue_retry(
+        &self,
+        peer: SocketAddr,
+        now: SystemTime,
+    ) -> Result<HandshakeMessage, HandshakeError> {
+        let config = self
+            .retry
+            .as_ref()
+            .ok_or(HandshakeError::MissingKeyMaterial)?;
+        let token = RetryToken::issue(&config.secret, &peer, now);
         Ok(HandshakeMessage::new(
-            HandshakeMessageKind::ResponderHello,
-            local_ephemeral.public_key(),
-            payload,
+            HandshakeMessageKind::Retry,
+            self.local_static.public_key(),
+            token.to_bytes().to_vec(),
         ))
     }
 
-    /// Process the initiator finish message and finalize the handshake.
-    pub fn handle_initiator_finish(
+    /// Start a handshake with the initiator holding `peer_static`, producing its responder
+    /// hello (see [`PendingHandshake::hello`]).
+    ///
+    /// When retry or admission limits are configured the peer address is needed, so this
+    /// fails with [`HandshakeError::RetryRequired`]; use [`accept_from`](Self::accept_from).
+    pub fn accept(
+        &mut self,
+        hello: &HandshakeMessage,
+        peer_static: &PublicKey,
+        now: SystemTime,
+    ) -> Result<PendingHandshake, HandshakeError> {
+        self.accept_inner(hello, peer_static, None, now)
+    }
+
+    /// Start a handshake with the initiator holding `peer_static` at address `peer`.
+    ///
+    /// When retry is configured, a hello without a token fails with
+    /// [`HandshakeError::RetryRequired`] and one with a stale or mismatched token with
+    /// [`HandshakeError::InvalidRetryToken`]; neither changes server state. Hellos over the
+    /// admission limits fail first, with [`HandshakeError::Refused`].
+    pub fn accept_from(
+        &mut self,
+        hello: &HandshakeMessage,
+        peer_static: &PublicKey,
+        peer: SocketAddr,
+        now: SystemTime,
+    ) -> Result<PendingHandshake, HandshakeError> {
+        self.accept_inner(hello, peer_static, Some(peer), now)
+    }
+
+    fn accept_inner(
         &mut self,
         message: &HandshakeMessage,
-    ) -> Result<ResponderOutcome, HandshakeError> {
-        if self.stage != ResponderStage::AwaitingFinal
-            || message.kind() != HandshakeMessageKind::InitiatorFinish
-        {
+        peer_static: &PublicKey,
+        peer: Option<SocketAddr>,
+        now: SystemTime,
+    ) -> Result<PendingHandshake, HandshakeError> {
+        if message.kind() != HandshakeMessageKind::InitiatorHello {
             return Err(HandshakeError::UnexpectedMessage);
         }
+        let Some(admission) = &mut self.admission else {
+            return self.start_handshake(message, peer_static, peer, now);
+        };
+        let peer = peer.ok_or(HandshakeError::RetryRequired)?;
+        admission
+            .admit_handshake(peer.ip(), now)
+            .map_err(HandshakeError::Refused)?;
+        let started = self.start_handshake(message, peer_static, Some(peer), now);
+        if started.is_err() {
+            if let Some(admission) = &mut self.admission {
+                admission.connection_closed(peer.ip());
+            }
+        }
+        started
+    }
 
-        self.anti_replay.record(message.payload())?;
+    fn start_handshake(
+        &mut self,
+        message: &HandshakeMessage,
+        peer_static: &PublicKey,
+        peer: Option<SocketAddr>,
+        now: SystemTime,
+    ) -> Result<PendingHandshake, HandshakeError> {
+        let version = negotiate_version(message.versions(), &self.versions)?;
+        self.validate_retry_token(message, peer, now)?;
 
-        // Remote ephemeral was already set during InitiatorHello; do not overwrite.
-        let session_keys = derive_session_keys(&self.state, false)?;
+        let encoded = message.encode();
+        self.anti_replay.record_at(&encoded, now)?;
 
-        let payload_clone = message.payload().to_vec();
-        self.state.mix_key(&payload_clone)?;
+        let mut state = HandshakeState::with_hash_algorithm(self.local_static.clone(), self.hash);
+        let local_public = self.local_static.public_key();
+        mix_static_prologue(&mut state, &local_public, peer_static)?;
+        state.set_remote_static(peer_static.clone());
+        state.mix_hash(&encoded);
 
-        let ticket = self.tickets.issue(self.state.chaining_key());
+        state.set_remote_ephemeral(message.ephemeral().clone());
 
-        self.stage = ResponderStage::Complete;
-        Ok(ResponderOutcome {
-            session_keys,
-            session_ticket: ticket,
-        })
+        let local_ephemeral = self.local_static.derive_ephemeral(0x22);
+        state.set_local_ephemeral(local_ephemeral.clone());
+
+        let shared = x25519_diffie_hellman(&local_ephemeral, message.ephemeral())?;
+        state.mix_key(shared.as_bytes())?;
+        let transcript = *state.handshake_hash();
+
+        let (early_data_status, early_data) = if message.carries_early_data() {
+            self.open_early_data(message, now)
+        } else {
+            (EarlyDataStatus::NotOffered, None)
+        };
+
+        let mut plaintext = match early_data_status {
+            EarlyDataStatus::NotOffered => Vec::new(),
+            EarlyDataStatus::Accepted => vec![1],
+            EarlyDataStatus::Rejected => vec![0],
+        };
+        push_identity_proof(
+            &mut plaintext,
+            self.identity.as_ref(),
+            RESPONDER_IDENTITY_CONTEXT,
+            &transcript,
+        );
+        plaintext.extend_from_slice(&self.transport_parameters.encode());
+        let payload = state.encrypt_payload(&plaintext);
+
+        let hello = HandshakeMessage::new(
+            HandshakeMessageKind::ResponderHello,
+            local_ephemeral.public_key(),
+            payload,
+        )
+        .with_versions(vec![version]);
+        state.mix_hash(&hello.encode());
+
+        let mut timer = FlightTimer::new(self.timeouts.clone());
+        timer.arm(&hello, now);
+        Ok(PendingHandshake {
+            state,
+            stage: ResponderStage::AwaitingFinal,
+            version,
+            hello,
+            early_data,
+            early_data_status,
+            timer,
+            transport_parameters: self.transport_parameters.clone(),
+        })
+    }
+
+    fn validate_retry_token(
+        &self,
+        message: &HandshakeMessage,
+        peer: Option<SocketAddr>,
+        now: SystemTime,
+    ) -> Result<(), HandshakeError> {
+        let Some(config) = &self.retry else {
+            return Ok(());
+        };
+        let token = message.retry_token().ok_or(HandshakeError::RetryRequired)?;
+        let peer = peer.ok_or(HandshakeError::RetryRequired)?;
+        let valid =
+            RetryToken::from_bytes(token).is_some_and(|token| token.validate(config, &peer, now));
+        if valid {
+            Ok(())
+        } else {
+            Err(HandshakeError::InvalidRetryToken)
+        }
+    }
+
+    fn open_early_data(
+        &mut self,
+        message: &HandshakeMessage,
+        now: SystemTime,
+    ) -> (EarlyDataStatus, Option<Vec<u8>>) {
+        let payload = message.payload();
+        if payload.len() < EARLY_DATA_HEADER_LEN + AEAD_TAG_LEN {
+            return (EarlyDataStatus::Rejected, None);
+        }
+        let (sealed_ticket, rest) = payload.split_at(SEALED_TICKET_LEN);
+        let ticket_id = &sealed_ticket[..TICKET_ID_LEN];
+        let (nonce_bytes, sealed) = rest.split_at(AEAD_NONCE_LEN);
+        let (ciphertext, tag_bytes) = sealed.split_at(sealed.len() - AEAD_TAG_LEN);
+
+        // Each ticket may carry early data at most once.
+        if self.early_data_replay.record_at(ticket_id, now).is_err() {
+            return (EarlyDataStatus::Rejected, None);
+        }
+        let Some(ticket) = self.tickets.resume_at(sealed_ticket, now) else {
+            return (EarlyDataStatus::Rejected, None);
+        };
+
+        let opened = derive_early_data_key(ticket.secret(), message.ephemeral()).and_then(|key| {
+            let nonce = AeadNonce::from_bytes(nonce_bytes)?;
+            let tag = AeadTag::from_bytes(tag_bytes)?;
+            decrypt(&key, &nonce, ciphertext, ticket_id, &tag)
+        });
+        match opened {
+            Ok(plaintext) => (EarlyDataStatus::Accepted, Some(plaintext)),
+            Err(_) => (EarlyDataStatus::Rejected, None),
+        }
     }
 }
 
-/// Simple anti-replay store using a hash set and queue for eviction.
+/// Responder side of one in-flight handshake, created by [`HandshakeServer::accept`].
+#[derive(Debug, Clone)]
+pub struct PendingHandshake {
+    state: HandshakeState,
+    stage: ResponderStage,
+    version: u8,
+    hello: HandshakeMessage,
+    early_data: Option<Vec<u8>>,
+    early_data_status: EarlyDataStatus,
+    timer: FlightTimer,
+    transport_parameters: TransportParameters,
+}
+
+impl PendingHandshake {
+    /// Responder hello to send to the initiator.
+    #[must_use]
+    pub const fn hello(&self) -> &HandshakeMessage {
+        &self.hello
+    }
+
+    /// Protocol version selected for this handshake.
+    #[must_use]
+    pub const fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Disposition of early data offered in the initiator hello.
+    #[must_use]
+    pub const fn early_data_status(&self) -> EarlyDataStatus {
+        self.early_data_status
+    }
+
+    /// Deadline for the current stage, if one is armed.
+    #[must_use]
+    pub const fn deadline(&self) -> Option<SystemTime> {
+        self.timer.deadline
+    }
+
+    /// Whether the handshake has failed (e.g. timed out).
+    #[must_use]
+    pub fn is_failed(&self) -> bool {
+        self.stage == ResponderStage::Failed
+    }
+
+    /// Drive stage timers: returns the hello to retransmit when the deadline passes, or
+    /// fails the handshake with [`HandshakeError::Timeout`] once retransmissions are exhausted.
+    ///
+    /// On failure all per-handshake key material and buffered early data are dropped.
+    pub fn poll_timeout(
+        &mut self,
+        now: SystemTime,
+    ) -> Result<Option<HandshakeMessage>, HandshakeError> {
+        if self.stage != ResponderStage::AwaitingFinal {
+            return Ok(None);
+        }
+        match self.timer.poll(now) {
+            FlightTimeout::Pending => Ok(None),
+            FlightTimeout::Retransmit(flight) => Ok(Some(flight)),
+            FlightTimeout::Expired => {
+                self.state = HandshakeState::with_hash_algorithm(
+                    self.state.local_static().clone(),
+                    self.state.hash_algorithm(),
+                );
+                self.early_data = None;
+                self.stage = ResponderStage::Failed;
+                Err(HandshakeError::Timeout)
+            }
+        }
+    }
+
+    /// Process the initiator finish message and finalize the handshake, issuing a session
+    /// ticket from `server`.
+    pub fn handle_initiator_finish(
+        &mut self,
+        server: &mut HandshakeServer,
+        message: &HandshakeMessage,
+        now: SystemTime,
+    ) -> Result<ResponderOutcome, HandshakeError> {
+        if self.stage != ResponderStage::AwaitingFinal
+            || message.kind() != HandshakeMessageKind::InitiatorFinish
+        {
+            return Err(HandshakeError::UnexpectedMessage);
+        }
+        if message.versions() != [self.version] {
+            return Err(HandshakeError::VersionMismatch {
+                offered: message.versions().to_vec(),
+                supported: vec![self.version],
+            });
+        }
+
+        // Verify key confirmation on a copy so a forged finish does not consume the nonce.
+        let mut state = self.state.clone();
+        let transcript = *state.handshake_hash();
+        let plaintext = state.decrypt_payload(message.payload())?;
+        let trusted = server.trusted_identities.as_ref();
+        let (peer_identity, encoded_params) = open_identity_proof(
+            &plaintext,
+            INITIATOR_IDENTITY_CONTEXT,
+            &transcript,
+            trusted.is_some(),
+            |key| trusted.is_none_or(|trusted| trusted.contains(key)),
+        )?;
+        let peer_params = TransportParameters::decode(encoded_params)
+            .map_err(|_| HandshakeError::MalformedMessage)?;
+        check_peer_parameters(&self.transport_parameters, &peer_params)?;
+        server.anti_replay.record_at(message.payload(), now)?;
+        self.state = state;
+
+        // Remote ephemeral was already set during InitiatorHello; do not overwrite.
+        let session_keys = derive_session_keys(
+            self.state.hash_algorithm(),
+            self.state.chaining_key(),
+            self.state.handshake_hash(),
+            false,
+        )?;
+
+        self.state.mix_hash(&message.encode());
+
+        let ticket = server.tickets.issue_at(self.state.chaining_key(), now);
+
+        self.timer.disarm();
+        self.stage = ResponderStage::Complete;
+        Ok(ResponderOutcome {
+            session_keys,
+            session_ticket: ticket,
+            early_data: self.early_data.take(),
+            transport_parameters: self.transport_parameters.negotiate(&peer_params),
+            peer_identity,
+            version: self.version,
+        })
+    }
+}
+
+/// Fail unless the peer speaks the local packet wire version and shares an AEAD cipher
+/// suite.
+fn check_peer_parameters(
+    local: &TransportParameters,
+    peer: &TransportParameters,
+) -> Result<(), HandshakeError> {
+    if local.wire_version != peer.wire_version {
+        return Err(HandshakeError::WireVersionMismatch {
+            local: local.wire_version,
+            peer: peer.wire_version,
+        });
+    }
+    if local
+        .aead_ciphers
+        .intersection(peer.aead_ciphers)
+        .is_empty()
+    {
+        return Err(HandshakeError::NoCommonCipherSuite {
+            local: local.aead_ciphers,
+            peer: peer.aead_ciphers,
+        });
+    }
+    Ok(())
+}
+
+/// Anti-replay store keeping truncated digests in two rotating time windows.
+///
+/// Each entry is a 16-byte truncated SHA-256 digest of the recorded payload, recorded into
+/// the current window. When a window of `ttl` ends it becomes the previous window and the
+/// old previous window is discarded wholesale, so entries are remembered for between `ttl`
+/// and `2 * ttl` and memory stays bounded by two windows of `capacity` digests. A window
+/// that fills up early is rotated immediately.
 #[derive(Debug, Clone)]
 pub struct AntiReplayStore {
-    seen: HashSet<Vec<u8>>,
-    order: VecDeque<(Vec<u8>, SystemTime)>,
+    current: HashSet<[u8; REPLAY_DIGEST_LEN]>,
+    previous: HashSet<[u8; REPLAY_DIGEST_LEN]>,
+    window_start: Option<SystemTime>,
     capacity: usize,
     ttl: Duration,
 }
 
 impl AntiReplayStore {
-    /// Create a new anti-replay store.
+    /// Create a new anti-replay store holding up to `capacity` entries per `ttl` window.
     #[must_use]
     pub fn new(capacity: usize, ttl: Duration) -> Self {
         Self {
-            seen: HashSet::new(),
-            order: VecDeque::new(),
+            current: HashSet::new(),
+            previous: HashSet::new(),
+            window_start: None,
             capacity,
             ttl,
         }
@@ -364,41 +1481,58 @@ impl AntiReplayStore {
 
     /// Record a message payload; returns error if replay detected.
     pub fn record(&mut self, payload: &[u8]) -> Result<(), HandshakeError> {
-        self.evict_expired();
-        let entry = payload.to_vec();
-        if self.seen.contains(&entry) {
+        self.record_at(payload, SystemTime::now())
+    }
+
+    /// Record a message payload observed at `now`; returns error if replay detected.
+    pub fn record_at(&mut self, payload: &[u8], now: SystemTime) -> Result<(), HandshakeError> {
+        self.rotate(now);
+        let mut digest = [0u8; REPLAY_DIGEST_LEN];
+        digest.copy_from_slice(&sha256(payload)[..REPLAY_DIGEST_LEN]);
+        if self.current.contains(&digest) || self.previous.contains(&digest) {
             return Err(HandshakeError::ReplayDetected);
         }
-        if self.order.len() >= self.capacity {
-            if let Some((old, _)) = self.order.pop_front() {
-                self.seen.remove(&old);
-            }
+        if self.current.len() >= self.capacity {
+            self.advance_window();
+            self.window_start = Some(now);
         }
-        self.seen.insert(entry.clone());
-        self.order.push_back((entry, SystemTime::now()));
+        self.current.insert(digest);
         Ok(())
     }
 
-    fn evict_expired(&mut self) {
-        while let Some((_, timestamp)) = self.order.front() {
-            if timestamp.elapsed().unwrap_or_default() > self.ttl {
-                let (entry, _) = self.order.pop_front().unwrap();
-                self.seen.remove(&entry);
-            } else {
-                break;
-            }
+    /// Number of digests currently remembered across both windows.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.current.len() + self.previous.len()
+    }
+
+    /// Determine whether no digests are remembered.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.current.is_empty() && self.previous.is_empty()
+    }
+
+    fn rotate(&mut self, now: SystemTime) {
+        let start = *self.window_start.get_or_insert(now);
+        let elapsed = now.duration_since(start).unwrap_or_default();
+        if elapsed < self.ttl {
+            return;
+        }
+        if elapsed < self.ttl * 2 {
+            self.advance_window();
+            self.window_start = Some(start + self.ttl);
+        } else {
+            self.previous.clear();
+            self.current.clear();
+            self.window_start = Some(now);
         }
     }
-}
 
-/// Utility function to derive nonce from packet numbers.
-#[must_use]
-pub fn nonce_from_packet_number(packet_number: u64) -> AeadNonce {
-    let mut bytes = [0u8; AEAD_NONCE_LEN];
-    for (idx, byte) in bytes.iter_mut().enumerate() {
-        *byte = packet_number.to_le_bytes()[idx % 8].wrapping_add((idx * 17) as u8);
+    /// Make the current window the previous one, discarding the old previous window.
+    fn advance_window(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.previous);
+        self.current.clear();
     }
-    AeadNonce::from_array(bytes)
 }
 
 /// Outcome of a responder-side handshake.
@@ -408,13 +1542,20 @@ pub struct ResponderOutcome {
     pub session_keys: SessionKeys,
     /// Ticket for future resumption attempts.
     pub session_ticket: SessionTicket,
+    /// 0-RTT early data accepted during this handshake, if any.
+    pub early_data: Option<Vec<u8>>,
+    /// Transport parameters agreed with the initiator.
+    pub transport_parameters: TransportParameters,
+    /// Identity key the initiator proved possession of, if it presented one.
+    pub peer_identity: Option<IdentityKey>,
+    /// Protocol version negotiated with the initiator.
+    pub version: u8,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::transport::crypto::AeadKey;
-    use crate::transport::{AEAD_KEY_LEN, PRIVATE_KEY_LEN};
+    use crate::transport::{PRIVATE_KEY_LEN, RateLimit, SHARED_SECRET_LEN, WIRE_VERSION};
     fn fixed_private(seed: u8) -> PrivateKey {
         let mut bytes = [0u8; PRIVATE_KEY_LEN];
         for (idx, byte) in bytes.iter_mut().enumerate() {
@@ -431,18 +1572,19 @@ mod tests {
         let responder_public = responder_static.public_key();
 
         let mut initiator = Initiator::new(initiator_static.clone(), responder_public.clone());
-        let mut responder = Responder::new(responder_static, Some(initiator_public.clone()))
-            .expect("responder init");
+        let mut server = HandshakeServer::new(responder_static);
 
-        let msg_init = initiator.initiate().expect("initiator hello");
-        let msg_resp = responder
-            .handle_initiator_hello(&msg_init)
+        let msg_init = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let mut pending = server
+            .accept(&msg_init, &initiator_public, SystemTime::now())
             .expect("responder hello");
         let (msg_final, initiator_keys) = initiator
-            .handle_response(&msg_resp)
+            .handle_response(pending.hello(), SystemTime::now())
             .expect("initiator finish");
-        let outcome = responder
-            .handle_initiator_finish(&msg_final)
+        let outcome = pending
+            .handle_initiator_finish(&mut server, &msg_final, SystemTime::now())
             .expect("responder finish");
 
         assert_eq!(
@@ -453,10 +1595,1131 @@ mod tests {
             initiator_keys.receive().as_bytes(),
             outcome.session_keys.send().as_bytes()
         );
+        assert_eq!(initiator_keys.send_hp(), outcome.session_keys.receive_hp());
+        assert_eq!(initiator_keys.receive_hp(), outcome.session_keys.send_hp());
+        assert_eq!(initiator_keys.send_iv(), outcome.session_keys.receive_iv());
+        assert_ne!(initiator_keys.send(), initiator_keys.receive());
         assert!(outcome.session_ticket.is_valid());
         assert!(outcome.session_ticket.issued_at() <= outcome.session_ticket.expires_at());
     }
 
+    #[test]
+    fn interleaved_handshakes_against_one_server() {
+        let responder_static = fixed_private(0x4C);
+        let responder_public = responder_static.public_key();
+        let alice_static = fixed_private(0x1C);
+        let bob_static = fixed_private(0x2C);
+
+        let mut server = HandshakeServer::new(responder_static);
+        let mut alice = Initiator::new(alice_static.clone(), responder_public.clone());
+        let mut bob = Initiator::new(bob_static.clone(), responder_public);
+
+        // Both hellos arrive before either initiator finishes.
+        let alice_hello = alice.initiate(SystemTime::now()).expect("alice hello");
+        let bob_hello = bob.initiate(SystemTime::now()).expect("bob hello");
+        let mut alice_pending = server
+            .accept(&alice_hello, &alice_static.public_key(), SystemTime::now())
+            .expect("accept alice");
+        let mut bob_pending = server
+            .accept(&bob_hello, &bob_static.public_key(), SystemTime::now())
+            .expect("accept bob");
+
+        let (bob_finish, bob_keys) = bob
+            .handle_response(bob_pending.hello(), SystemTime::now())
+            .expect("bob finish");
+        let (alice_finish, alice_keys) = alice
+            .handle_response(alice_pending.hello(), SystemTime::now())
+            .expect("alice finish");
+
+        // A finish delivered to the wrong handshake is rejected without disturbing it.
+        assert!(matches!(
+            alice_pending.handle_initiator_finish(&mut server, &bob_finish, SystemTime::now()),
+            Err(HandshakeError::Crypto(CryptoError::AuthenticationFailed))
+        ));
+
+        let bob_outcome = bob_pending
+            .handle_initiator_finish(&mut server, &bob_finish, SystemTime::now())
+            .expect("bob outcome");
+        let alice_outcome = alice_pending
+            .handle_initiator_finish(&mut server, &alice_finish, SystemTime::now())
+            .expect("alice outcome");
+
+        assert_eq!(alice_keys.send(), alice_outcome.session_keys.receive());
+        assert_eq!(bob_keys.send(), bob_outcome.session_keys.receive());
+        assert_ne!(alice_keys.send(), bob_keys.send());
+        assert_ne!(
+            alice_outcome.session_ticket.id(),
+            bob_outcome.session_ticket.id()
+        );
+    }
+
+    #[test]
+    fn blake3_handshake_derives_distinct_matching_keys() {
+        let initiator_static = fixed_private(0x11);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x41);
+        let responder_public = responder_static.public_key();
+
+        let run = |hash: HashAlgorithm| {
+            let mut initiator = Initiator::with_hash_algorithm(
+                initiator_static.clone(),
+                responder_public.clone(),
+                hash,
+            );
+            let mut server = HandshakeServer::with_hash_algorithm(responder_static.clone(), hash);
+            let mut pending = server
+                .accept(
+                    &initiator
+                        .initiate(SystemTime::now())
+                        .expect("initiator hello"),
+                    &initiator_public,
+                    SystemTime::now(),
+                )
+                .expect("responder hello");
+            let (finish, keys) = initiator
+                .handle_response(pending.hello(), SystemTime::now())
+                .expect("initiator finish");
+            let outcome = pending
+                .handle_initiator_finish(&mut server, &finish, SystemTime::now())
+                .expect("responder finish");
+            assert_eq!(keys.send(), outcome.session_keys.receive());
+            assert_eq!(keys.receive_hp(), outcome.session_keys.send_hp());
+            keys
+        };
+
+        let sha_keys = run(HashAlgorithm::Sha256);
+        let blake_keys = run(HashAlgorithm::Blake3);
+        assert_ne!(sha_keys.send(), blake_keys.send());
+
+        let mut initiator = Initiator::with_hash_algorithm(
+            initiator_static,
+            responder_public,
+            HashAlgorithm::Blake3,
+        );
+        let mut server = HandshakeServer::new(responder_static);
+        let pending = server
+            .accept(
+                &initiator
+                    .initiate(SystemTime::now())
+                    .expect("initiator hello"),
+                &initiator_public,
+                SystemTime::now(),
+            )
+            .expect("responder hello");
+        assert!(matches!(
+            initiator.handle_response(pending.hello(), SystemTime::now()),
+            Err(HandshakeError::Crypto(CryptoError::AuthenticationFailed))
+        ));
+    }
+
+    #[test]
+    fn tampered_responder_hello_rejected() {
+        let initiator_static = fixed_private(0x12);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x42);
+        let responder_public = responder_static.public_key();
+
+        let mut initiator = Initiator::new(initiator_static, responder_public);
+        let mut server = HandshakeServer::new(responder_static);
+
+        let msg_init = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let mut pending = server
+            .accept(&msg_init, &initiator_public, SystemTime::now())
+            .expect("responder hello");
+        let msg_resp = pending.hello().clone();
+
+        let mut payload = msg_resp.payload().to_vec();
+        payload[0] ^= 0x01;
+        let tampered = HandshakeMessage::new(
+            HandshakeMessageKind::ResponderHello,
+            msg_resp.ephemeral().clone(),
+            payload,
+        );
+        assert!(matches!(
+            initiator.handle_response(&tampered, SystemTime::now()),
+            Err(HandshakeError::Crypto(CryptoError::AuthenticationFailed))
+        ));
+
+        // The genuine hello is still accepted after the forgery was discarded.
+        let (msg_final, initiator_keys) = initiator
+            .handle_response(&msg_resp, SystemTime::now())
+            .expect("initiator finish");
+
+        let mut confirmation = msg_final.payload().to_vec();
+        confirmation[0] ^= 0x01;
+        let forged_final = HandshakeMessage::new(
+            HandshakeMessageKind::InitiatorFinish,
+            msg_final.ephemeral().clone(),
+            confirmation,
+        );
+        assert!(matches!(
+            pending.handle_initiator_finish(&mut server, &forged_final, SystemTime::now()),
+            Err(HandshakeError::Crypto(CryptoError::AuthenticationFailed))
+        ));
+
+        let outcome = pending
+            .handle_initiator_finish(&mut server, &msg_final, SystemTime::now())
+            .expect("responder finish");
+        assert_eq!(
+            initiator_keys.send().as_bytes(),
+            outcome.session_keys.receive().as_bytes()
+        );
+    }
+
+    #[test]
+    fn handshake_payloads_do_not_expose_temp_key() {
+        let initiator_static = fixed_private(0x13);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x43);
+        let responder_public = responder_static.public_key();
+
+        let mut initiator = Initiator::new(initiator_static, responder_public);
+        let mut server = HandshakeServer::new(responder_static);
+
+        let msg_init = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let pending = server
+            .accept(&msg_init, &initiator_public, SystemTime::now())
+            .expect("responder hello");
+        let msg_resp = pending.hello();
+        let temp_key = pending.state.temp_key();
+
+        assert_eq!(
+            msg_resp.payload().len(),
+            // Anonymous identity proof byte, then the parameters.
+            1 + TransportParameters::default().encode().len() + AEAD_TAG_LEN
+        );
+        assert!(
+            !msg_resp
+                .payload()
+                .windows(AEAD_TAG_LEN)
+                .any(|window| temp_key.windows(AEAD_TAG_LEN).any(|key| key == window))
+        );
+    }
+
+    fn complete_handshake(
+        initiator: &mut Initiator,
+        server: &mut HandshakeServer,
+        initiator_public: &PublicKey,
+        hello: &HandshakeMessage,
+    ) -> (SessionKeys, PendingHandshake, ResponderOutcome) {
+        let mut pending = server
+            .accept(hello, initiator_public, SystemTime::now())
+            .expect("responder hello");
+        let (msg_final, initiator_keys) = initiator
+            .handle_response(pending.hello(), SystemTime::now())
+            .expect("initiator finish");
+        let outcome = pending
+            .handle_initiator_finish(server, &msg_final, SystemTime::now())
+            .expect("responder finish");
+        (initiator_keys, pending, outcome)
+    }
+
+    #[test]
+    fn early_data_accepted_on_resumption() {
+        let initiator_static = fixed_private(0x14);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x44);
+        let responder_public = responder_static.public_key();
+
+        let mut initiator = Initiator::new(initiator_static.clone(), responder_public.clone());
+        let mut server = HandshakeServer::new(responder_static);
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let (_, _, first) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+        assert!(first.early_data.is_none());
+
+        let mut initiator = Initiator::new(initiator_static, responder_public);
+        let hello = initiator
+            .initiate_with_early_data(&first.session_ticket, b"early request", SystemTime::now())
+            .expect("resumption hello");
+        assert!(hello.carries_early_data());
+
+        let (initiator_keys, pending, outcome) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+        assert_eq!(pending.early_data_status(), EarlyDataStatus::Accepted);
+        assert_eq!(initiator.early_data_accepted(), Some(true));
+        assert_eq!(outcome.early_data.as_deref(), Some(&b"early request"[..]));
+        assert_eq!(
+            initiator_keys.send().as_bytes(),
+            outcome.session_keys.receive().as_bytes()
+        );
+    }
+
+    #[test]
+    fn tickets_carry_over_to_a_new_server() {
+        let initiator_static = fixed_private(0x19);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x49);
+        let responder_public = responder_static.public_key();
+
+        let mut initiator = Initiator::new(initiator_static.clone(), responder_public.clone());
+        let mut server = HandshakeServer::new(responder_static.clone());
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let (_, _, first) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+
+        let mut restarted =
+            HandshakeServer::new(responder_static).with_tickets(server.tickets().clone());
+        let mut initiator = Initiator::new(initiator_static, responder_public);
+        let hello = initiator
+            .initiate_with_early_data(&first.session_ticket, b"early request", SystemTime::now())
+            .expect("resumption hello");
+        let (_, pending, outcome) =
+            complete_handshake(&mut initiator, &mut restarted, &initiator_public, &hello);
+        assert_eq!(pending.early_data_status(), EarlyDataStatus::Accepted);
+        assert_eq!(outcome.early_data.as_deref(), Some(&b"early request"[..]));
+    }
+
+    #[test]
+    fn second_connection_uses_cached_ticket_for_early_data() {
+        let initiator_static = fixed_private(0x16);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x46);
+        let responder_public = responder_static.public_key();
+        let mut cache = ClientTicketCache::default();
+
+        // No ticket yet: the first connection falls back to a full handshake.
+        let mut initiator = Initiator::new(initiator_static.clone(), responder_public.clone());
+        let mut server = HandshakeServer::new(responder_static);
+        let (hello, sent_early) = initiator
+            .initiate_0rtt(&mut cache, b"first request", SystemTime::now())
+            .expect("initiator hello");
+        assert!(!sent_early);
+        let (_, _, first) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+        assert_eq!(initiator.early_data_accepted(), None);
+        assert!(first.early_data.is_none());
+        cache.insert(&responder_public, first.session_ticket);
+
+        let mut initiator = Initiator::new(initiator_static, responder_public);
+        let (hello, sent_early) = initiator
+            .initiate_0rtt(&mut cache, b"second request", SystemTime::now())
+            .expect("initiator hello");
+        assert!(sent_early);
+        assert!(cache.is_empty(), "tickets are single-use");
+        let (_, _, second) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+        assert_eq!(initiator.early_data_accepted(), Some(true));
+        assert_eq!(second.early_data.as_deref(), Some(&b"second request"[..]));
+    }
+
+    #[test]
+    fn early_data_rejected_on_fresh_handshake() {
+        let initiator_static = fixed_private(0x15);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x45);
+        let responder_public = responder_static.public_key();
+
+        let issuer = SessionTicketManager::new(Duration::from_secs(60));
+        let ticket = issuer.issue(&[0x5Au8; SHARED_SECRET_LEN]);
+
+        let mut initiator = Initiator::new(initiator_static, responder_public);
+        let mut server = HandshakeServer::new(responder_static);
+        let hello = initiator
+            .initiate_with_early_data(&ticket, b"early request", SystemTime::now())
+            .expect("resumption hello");
+
+        let (initiator_keys, pending, outcome) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+        assert_eq!(pending.early_data_status(), EarlyDataStatus::Rejected);
+        assert_eq!(initiator.early_data_accepted(), Some(false));
+        assert!(outcome.early_data.is_none());
+        assert_eq!(
+            initiator_keys.send().as_bytes(),
+            outcome.session_keys.receive().as_bytes()
+        );
+    }
+
+    #[test]
+    fn oversized_early_data_is_refused() {
+        let responder_public = fixed_private(0x47).public_key();
+        let issuer = SessionTicketManager::new(Duration::from_secs(60));
+        let ticket = issuer.issue(&[0x5Bu8; SHARED_SECRET_LEN]);
+
+        let mut initiator = Initiator::new(fixed_private(0x17), responder_public.clone());
+        let hello = initiator
+            .initiate_with_early_data(&ticket, &vec![0xEE; MAX_EARLY_DATA_LEN], SystemTime::now())
+            .expect("largest early data fits");
+        let decoded = HandshakeMessage::decode(&hello.encode()).expect("decode");
+        assert_eq!(decoded.payload(), hello.payload());
+
+        let mut initiator = Initiator::new(fixed_private(0x17), responder_public);
+        assert!(matches!(
+            initiator.initiate_with_early_data(
+                &ticket,
+                &vec![0xEE; MAX_EARLY_DATA_LEN + 1],
+                SystemTime::now()
+            ),
+            Err(HandshakeError::EarlyDataTooLarge { len }) if len == MAX_EARLY_DATA_LEN + 1
+        ));
+    }
+
+    #[test]
+    fn transport_parameters_negotiated_to_smaller_limits() {
+        let initiator_static = fixed_private(0x16);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x46);
+        let responder_public = responder_static.public_key();
+
+        let mut initiator = Initiator::new(initiator_static, responder_public)
+            .with_transport_parameters(TransportParameters {
+                initial_max_data: 4096,
+                max_streams: 8,
+                ..TransportParameters::default()
+            });
+        let mut server =
+            HandshakeServer::new(responder_static).with_transport_parameters(TransportParameters {
+                initial_max_stream_data: 512,
+                ack_delay_exponent: 0,
+                ..TransportParameters::default()
+            });
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let (_, _, outcome) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+
+        let negotiated = initiator.negotiated_parameters().expect("negotiated");
+        assert_eq!(negotiated, &outcome.transport_parameters);
+        assert_eq!(negotiated.initial_max_data, 4096);
+        assert_eq!(negotiated.initial_max_stream_data, 512);
+        assert_eq!(negotiated.max_streams, 8);
+        assert_eq!(negotiated.ack_delay_exponent, 0);
+    }
+
+    #[test]
+    fn cipher_suite_negotiated_or_rejected() {
+        use crate::transport::AeadCipher;
+
+        let aes_only = TransportParameters {
+            aead_ciphers: AeadCipherSet::only(AeadCipher::Aes256Gcm),
+            ..TransportParameters::default()
+        };
+        let initiator_static = fixed_private(0x1A);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x4A);
+
+        // Both sides accept AES-256-GCM, so it is preferred.
+        let mut initiator = Initiator::new(initiator_static.clone(), responder_static.public_key())
+            .with_transport_parameters(aes_only.clone());
+        let mut server = HandshakeServer::new(responder_static.clone()).with_transport_parameters(
+            TransportParameters {
+                aead_ciphers: AeadCipherSet::ALL,
+                ..TransportParameters::default()
+            },
+        );
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let (_, _, outcome) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+        assert_eq!(
+            outcome.transport_parameters.aead_cipher(),
+            AeadCipher::Aes256Gcm
+        );
+        assert_eq!(
+            initiator
+                .negotiated_parameters()
+                .map(TransportParameters::aead_cipher),
+            Some(AeadCipher::Aes256Gcm)
+        );
+
+        // An AES-only initiator and a ChaCha20-only responder share nothing.
+        let mut initiator = Initiator::new(initiator_static, responder_static.public_key())
+            .with_transport_parameters(aes_only);
+        let mut server = HandshakeServer::new(responder_static);
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let pending = server
+            .accept(&hello, &initiator_public, SystemTime::now())
+            .expect("accept");
+        let err = initiator
+            .handle_response(pending.hello(), SystemTime::now())
+            .expect_err("no common suite");
+        assert!(matches!(
+            err,
+            HandshakeError::NoCommonCipherSuite { local, peer }
+                if local == AeadCipherSet::only(AeadCipher::Aes256Gcm)
+                    && peer == AeadCipherSet::default()
+        ));
+        assert!(err.to_string().contains("no common AEAD cipher suite"));
+    }
+
+    #[test]
+    fn wire_version_mismatch_is_rejected() {
+        let older = TransportParameters {
+            wire_version: WIRE_VERSION - 1,
+            ..TransportParameters::default()
+        };
+        let initiator_static = fixed_private(0x1B);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x4B);
+
+        // The initiator rejects a responder on another wire version.
+        let mut initiator = Initiator::new(initiator_static.clone(), responder_static.public_key());
+        let mut server =
+            HandshakeServer::new(responder_static.clone()).with_transport_parameters(older.clone());
+        let hello = initiator.initiate(SystemTime::now()).expect("hello");
+        let pending = server
+            .accept(&hello, &initiator_public, SystemTime::now())
+            .expect("accept");
+        let err = initiator
+            .handle_response(pending.hello(), SystemTime::now())
+            .expect_err("wire version mismatch");
+        assert!(matches!(
+            err,
+            HandshakeError::WireVersionMismatch { local, peer }
+                if local == WIRE_VERSION && peer == WIRE_VERSION - 1
+        ));
+        assert!(err.to_string().contains("wire version mismatch"));
+
+        // The responder runs the same check on the parameters in the initiator's finish,
+        // and a peer that predates the parameter reads as version 0.
+        let current = TransportParameters::default();
+        assert!(matches!(
+            check_peer_parameters(&current, &older),
+            Err(HandshakeError::WireVersionMismatch { local, peer })
+                if local == WIRE_VERSION && peer == WIRE_VERSION - 1
+        ));
+        let unversioned = TransportParameters::decode(&[]).expect("decode");
+        assert!(matches!(
+            check_peer_parameters(&current, &unversioned),
+            Err(HandshakeError::WireVersionMismatch { peer: 0, .. })
+        ));
+        check_peer_parameters(&current, &current).expect("same version");
+    }
+
+    #[test]
+    fn same_version_negotiates() {
+        let initiator_static = fixed_private(0x17);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x47);
+        let mut initiator = Initiator::new(initiator_static, responder_static.public_key());
+        let mut server = HandshakeServer::new(responder_static);
+
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        assert_eq!(hello.versions(), SUPPORTED_VERSIONS);
+        let (_, pending, outcome) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+        assert_eq!(pending.hello().versions(), [PROTOCOL_VERSION]);
+        assert_eq!(outcome.version, PROTOCOL_VERSION);
+        assert_eq!(initiator.negotiated_version(), Some(PROTOCOL_VERSION));
+    }
+
+    #[test]
+    fn disjoint_versions_rejected() {
+        let initiator_static = fixed_private(0x18);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x48);
+        let mut initiator =
+            Initiator::new(initiator_static, responder_static.public_key()).with_versions(vec![7]);
+        let mut server = HandshakeServer::new(responder_static);
+
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        match server.accept(&hello, &initiator_public, SystemTime::now()) {
+            Err(HandshakeError::VersionMismatch { offered, supported }) => {
+                assert_eq!(offered, [7]);
+                assert_eq!(supported, SUPPORTED_VERSIONS);
+            }
+            other => panic!("expected version mismatch, got {other:?}"),
+        }
+        // The rejection consumed nothing: a compatible retry of the same hello is not a replay.
+        let mut server = server.with_supported_versions(vec![7, PROTOCOL_VERSION]);
+        assert!(
+            server
+                .accept(&hello, &initiator_public, SystemTime::now())
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn unknown_higher_version_falls_back_to_known() {
+        let initiator_static = fixed_private(0x19);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x49);
+        let future = PROTOCOL_VERSION + 1;
+        let mut initiator = Initiator::new(initiator_static, responder_static.public_key())
+            .with_versions(vec![future, PROTOCOL_VERSION]);
+        let mut server = HandshakeServer::new(responder_static);
+
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let hello = HandshakeMessage::decode(&hello.encode()).expect("decode hello");
+        assert_eq!(hello.versions(), [future, PROTOCOL_VERSION]);
+        let (keys, _, outcome) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+        assert_eq!(outcome.version, PROTOCOL_VERSION);
+        assert_eq!(initiator.negotiated_version(), Some(PROTOCOL_VERSION));
+        assert_eq!(keys.send(), outcome.session_keys.receive());
+    }
+
+    #[test]
+    fn responder_selecting_unoffered_version_rejected() {
+        let initiator_static = fixed_private(0x1A);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x4A);
+        let mut initiator = Initiator::new(initiator_static, responder_static.public_key());
+        let mut server = HandshakeServer::new(responder_static);
+
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let pending = server
+            .accept(&hello, &initiator_public, SystemTime::now())
+            .expect("accept");
+        let downgraded = pending.hello().clone().with_versions(vec![0]);
+        assert!(matches!(
+            initiator.handle_response(&downgraded, SystemTime::now()),
+            Err(HandshakeError::VersionMismatch { .. })
+        ));
+        initiator
+            .handle_response(pending.hello(), SystemTime::now())
+            .expect("genuine hello still accepted");
+    }
+
+    #[test]
+    fn longest_version_list_roundtrips() {
+        let hello = HandshakeMessage::new(
+            HandshakeMessageKind::InitiatorHello,
+            fixed_private(0x1C).public_key(),
+            Vec::new(),
+        )
+        .with_versions((0..=254).collect());
+        let decoded = HandshakeMessage::decode(&hello.encode()).expect("decode");
+        assert_eq!(decoded.versions(), hello.versions());
+    }
+
+    #[test]
+    #[should_panic(expected = "1 to 255 versions, got 0")]
+    fn empty_version_offer_is_rejected() {
+        let _ = Initiator::new(fixed_private(0x1D), fixed_private(0x4D).public_key())
+            .with_versions(Vec::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "1 to 255 versions, got 256")]
+    fn oversized_version_list_is_rejected() {
+        let _ = HandshakeMessage::new(
+            HandshakeMessageKind::InitiatorHello,
+            fixed_private(0x1E).public_key(),
+            Vec::new(),
+        )
+        .with_versions(vec![PROTOCOL_VERSION; 256]);
+    }
+
+    #[test]
+    fn identity_signatures_authenticate_both_peers() {
+        let initiator_static = fixed_private(0x15);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x45);
+        let responder_public = responder_static.public_key();
+        let client_id = Identity::from_seed([0xC1; 32]);
+        let server_id = Identity::from_seed([0x5E; 32]);
+
+        let mut initiator = Initiator::new(initiator_static, responder_public)
+            .with_identity(client_id.clone())
+            .with_peer_identity(*server_id.public_key());
+        let mut server = HandshakeServer::new(responder_static)
+            .with_identity(server_id.clone())
+            .with_trusted_identities([*client_id.public_key()]);
+
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let (keys, _, outcome) =
+            complete_handshake(&mut initiator, &mut server, &initiator_public, &hello);
+        assert_eq!(keys.send(), outcome.session_keys.receive());
+        assert_eq!(initiator.peer_identity(), Some(server_id.public_key()));
+        assert_eq!(outcome.peer_identity.as_ref(), Some(client_id.public_key()));
+    }
+
+    #[test]
+    fn identities_and_parameters_never_appear_on_the_wire() {
+        let initiator_static = fixed_private(0x18);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x48);
+        let client_id = Identity::from_seed([0xC2; 32]);
+        let server_id = Identity::from_seed([0x5F; 32]);
+        let client_params = TransportParameters {
+            initial_max_data: 0x0123_4567_89AB,
+            ..TransportParameters::default()
+        };
+        let server_params = TransportParameters {
+            max_streams: 0x00DE_C0DE,
+            ..TransportParameters::default()
+        };
+
+        let mut initiator = Initiator::new(initiator_static, responder_static.public_key())
+            .with_identity(client_id.clone())
+            .with_transport_parameters(client_params.clone());
+        let mut server = HandshakeServer::new(responder_static)
+            .with_identity(server_id.clone())
+            .with_transport_parameters(server_params.clone());
+
+        let mut pending = server
+            .accept(
+                &initiator.initiate(SystemTime::now()).expect("hello"),
+                &initiator_public,
+                SystemTime::now(),
+            )
+            .expect("responder hello");
+        let (finish, _) = initiator
+            .handle_response(pending.hello(), SystemTime::now())
+            .expect("initiator finish");
+        let outcome = pending
+            .handle_initiator_finish(&mut server, &finish, SystemTime::now())
+            .expect("responder finish");
+        assert_eq!(initiator.peer_identity(), Some(server_id.public_key()));
+        assert_eq!(outcome.peer_identity.as_ref(), Some(client_id.public_key()));
+
+        // Both sealed payloads hide the identity proofs and transport parameters they carry.
+        let contains = |wire: &[u8], needle: &[u8]| wire.windows(needle.len()).any(|w| w == needle);
+        for (wire, identity, params) in [
+            (pending.hello().encode(), &server_id, &server_params),
+            (finish.encode(), &client_id, &client_params),
+        ] {
+            assert!(!contains(&wire, identity.public_key().as_bytes()));
+            assert!(!contains(&wire, &params.encode()));
+        }
+    }
+
+    #[test]
+    fn mismatched_or_missing_identity_rejected() {
+        let initiator_static = fixed_private(0x16);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x46);
+        let responder_public = responder_static.public_key();
+        let expected = Identity::from_seed([0x01; 32]);
+        let impostor = Identity::from_seed([0x02; 32]);
+
+        // Responder signs with a key other than the one the initiator pinned, or with none.
+        for server_identity in [Some(impostor.clone()), None] {
+            let mut initiator = Initiator::new(initiator_static.clone(), responder_public.clone())
+                .with_peer_identity(*expected.public_key());
+            let mut server = HandshakeServer::new(responder_static.clone());
+            if let Some(identity) = server_identity {
+                server = server.with_identity(identity);
+            }
+            let hello = initiator
+                .initiate(SystemTime::now())
+                .expect("initiator hello");
+            let pending = server
+                .accept(&hello, &initiator_public, SystemTime::now())
+                .expect("accept");
+            assert!(matches!(
+                initiator.handle_response(pending.hello(), SystemTime::now()),
+                Err(HandshakeError::UntrustedIdentity)
+            ));
+            assert!(initiator.peer_identity().is_none());
+        }
+
+        // Initiator signs with a key the server does not trust.
+        let mut initiator =
+            Initiator::new(initiator_static, responder_public).with_identity(impostor);
+        let mut server = HandshakeServer::new(responder_static)
+            .with_trusted_identities([*expected.public_key()]);
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let mut pending = server
+            .accept(&hello, &initiator_public, SystemTime::now())
+            .expect("accept");
+        let (finish, _) = initiator
+            .handle_response(pending.hello(), SystemTime::now())
+            .expect("initiator finish");
+        assert!(matches!(
+            pending.handle_initiator_finish(&mut server, &finish, SystemTime::now()),
+            Err(HandshakeError::UntrustedIdentity)
+        ));
+    }
+
+    #[test]
+    fn forged_identity_proof_rejected() {
+        let genuine = Identity::from_seed([0x0A; 32]);
+        let impostor = Identity::from_seed([0x0B; 32]);
+        let transcript = [0x33; TRANSCRIPT_HASH_LEN];
+        let trust_genuine = |key: &IdentityKey| key == genuine.public_key();
+
+        let mut valid = Vec::new();
+        push_identity_proof(
+            &mut valid,
+            Some(&genuine),
+            RESPONDER_IDENTITY_CONTEXT,
+            &transcript,
+        );
+        valid.extend_from_slice(b"params");
+        let (key, rest) = open_identity_proof(
+            &valid,
+            RESPONDER_IDENTITY_CONTEXT,
+            &transcript,
+            true,
+            trust_genuine,
+        )
+        .expect("valid proof");
+        assert_eq!(key.as_ref(), Some(genuine.public_key()));
+        assert_eq!(rest, b"params");
+
+        // Genuine key paired with a signature from someone else.
+        let mut forged = vec![1];
+        forged.extend_from_slice(genuine.public_key().as_bytes());
+        forged.extend_from_slice(
+            impostor
+                .sign(&identity_message(RESPONDER_IDENTITY_CONTEXT, &transcript))
+                .as_bytes(),
+        );
+        // Valid signature, but over another transcript or in the other role.
+        let replayed_transcript = [0x34; TRANSCRIPT_HASH_LEN];
+        for (proof, context, transcript) in [
+            (&forged, RESPONDER_IDENTITY_CONTEXT, &transcript),
+            (&valid, RESPONDER_IDENTITY_CONTEXT, &replayed_transcript),
+            (&valid, INITIATOR_IDENTITY_CONTEXT, &transcript),
+        ] {
+            assert!(matches!(
+                open_identity_proof(proof, context, transcript, true, trust_genuine),
+                Err(HandshakeError::Crypto(CryptoError::InvalidSignature))
+            ));
+        }
+    }
+
+    #[test]
+    fn admission_refuses_excess_hellos_statelessly() {
+        let responder_static = fixed_private(0x49);
+        let responder_public = responder_static.public_key();
+        let mut server = HandshakeServer::new(responder_static).with_admission(ConnectionLimits {
+            handshake_rate: RateLimit::new(1.0, 2.0),
+            ..ConnectionLimits::default()
+        });
+        let busy: SocketAddr = "198.51.100.7:4000".parse().expect("addr");
+        let quiet: SocketAddr = "203.0.113.5:4000".parse().expect("addr");
+
+        let hello_from = |seed: u8| {
+            let initiator_static = fixed_private(seed);
+            let public = initiator_static.public_key();
+            let mut initiator = Initiator::new(initiator_static, responder_public.clone());
+            let hello = initiator
+                .initiate(SystemTime::now())
+                .expect("initiator hello");
+            (initiator, hello, public)
+        };
+
+        for seed in [0x60, 0x61] {
+            let (_, hello, public) = hello_from(seed);
+            server
+                .accept_from(&hello, &public, busy, SystemTime::now())
+                .expect("within burst");
+        }
+        let (mut refused, hello, public) = hello_from(0x62);
+        let err = server
+            .accept_from(&hello, &public, busy, SystemTime::now())
+            .unwrap_err();
+        assert!(matches!(err, HandshakeError::Refused(Refusal::RateLimited)));
+
+        // The refusal is answered without per-peer state and surfaces at the initiator.
+        let reply = server.refuse(Refusal::RateLimited);
+        let wire = HandshakeMessage::decode(&reply.encode()).expect("decode refusal");
+        assert_eq!(wire.kind(), HandshakeMessageKind::Refused);
+        assert!(matches!(
+            refused.handle_response(&wire, SystemTime::now()),
+            Err(HandshakeError::Refused(Refusal::RateLimited))
+        ));
+
+        // Another address is unaffected; a hello that fails later frees its slot.
+        let (_, hello, public) = hello_from(0x63);
+        server
+            .accept_from(&hello, &public, quiet, SystemTime::now())
+            .expect("other address");
+        assert!(matches!(
+            server.accept_from(&hello, &public, quiet, SystemTime::now()),
+            Err(HandshakeError::ReplayDetected)
+        ));
+        let admission = server.admission_mut().expect("admission configured");
+        assert_eq!(admission.connections_from(busy.ip()), 2);
+        assert_eq!(admission.connections_from(quiet.ip()), 1);
+
+        assert!(matches!(
+            server.accept(&hello, &public, SystemTime::now()),
+            Err(HandshakeError::RetryRequired)
+        ));
+    }
+
+    fn retry_peer() -> SocketAddr {
+        "198.51.100.9:7000".parse().expect("addr")
+    }
+
+    #[test]
+    fn oversized_retry_token_is_refused() {
+        let responder_static = fixed_private(0x49);
+        let mut initiator = Initiator::new(fixed_private(0x19), responder_static.public_key());
+        initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+
+        let oversized = HandshakeMessage::new(
+            HandshakeMessageKind::Retry,
+            responder_static.public_key(),
+            vec![0xAB; MAX_RETRY_TOKEN_LEN + 1],
+        );
+        assert!(matches!(
+            initiator.handle_retry(&oversized, SystemTime::now()),
+            Err(HandshakeError::RetryTokenTooLong { len }) if len == MAX_RETRY_TOKEN_LEN + 1
+        ));
+
+        // The refused retry used up nothing; a well-formed one is still honoured.
+        let longest = HandshakeMessage::new(
+            HandshakeMessageKind::Retry,
+            responder_static.public_key(),
+            vec![0xAB; MAX_RETRY_TOKEN_LEN],
+        );
+        let hello = initiator
+            .handle_retry(&longest, SystemTime::now())
+            .expect("second hello");
+        let decoded = HandshakeMessage::decode(&hello.encode()).expect("decode hello");
+        assert_eq!(
+            decoded.retry_token(),
+            Some(&[0xAB; MAX_RETRY_TOKEN_LEN][..])
+        );
+
+        assert!(matches!(
+            hello.with_retry_token(vec![0; MAX_RETRY_TOKEN_LEN + 1]),
+            Err(HandshakeError::RetryTokenTooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn retry_token_echo_completes_handshake() {
+        let initiator_static = fixed_private(0x18);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x48);
+        let responder_public = responder_static.public_key();
+
+        let mut initiator = Initiator::new(initiator_static, responder_public);
+        let mut server =
+            HandshakeServer::new(responder_static).with_retry(RetryConfig::new([0x3Cu8; 32]));
+
+        let first = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        assert!(matches!(
+            server.accept_from(&first, &initiator_public, retry_peer(), SystemTime::now()),
+            Err(HandshakeError::RetryRequired)
+        ));
+
+        let retry = server
+            .issue_retry(retry_peer(), SystemTime::now())
+            .expect("retry");
+        let wire = HandshakeMessage::decode(&retry.encode()).expect("decode retry");
+        let second = initiator
+            .handle_retry(&wire, SystemTime::now())
+            .expect("second hello");
+        let second = HandshakeMessage::decode(&second.encode()).expect("decode hello");
+        assert!(second.retry_token().is_some());
+        assert!(matches!(
+            initiator.handle_retry(&retry, SystemTime::now()),
+            Err(HandshakeError::UnexpectedMessage)
+        ));
+
+        // Without the peer address the token cannot be validated.
+        assert!(matches!(
+            server.accept(&second, &initiator_public, SystemTime::now()),
+            Err(HandshakeError::RetryRequired)
+        ));
+
+        let mut pending = server
+            .accept_from(&second, &initiator_public, retry_peer(), SystemTime::now())
+            .expect("responder hello");
+        let (msg_final, initiator_keys) = initiator
+            .handle_response(pending.hello(), SystemTime::now())
+            .expect("initiator finish");
+        let outcome = pending
+            .handle_initiator_finish(&mut server, &msg_final, SystemTime::now())
+            .expect("responder finish");
+        assert_eq!(
+            initiator_keys.send().as_bytes(),
+            outcome.session_keys.receive().as_bytes()
+        );
+    }
+
+    #[test]
+    fn tampered_or_expired_retry_token_rejected() {
+        let initiator_static = fixed_private(0x1A);
+        let initiator_public = initiator_static.public_key();
+        let responder_static = fixed_private(0x4A);
+        let config = RetryConfig::new([0x3Cu8; 32]);
+        let mut server = HandshakeServer::new(responder_static.clone()).with_retry(config.clone());
+        let mut initiator = Initiator::new(initiator_static.clone(), responder_static.public_key());
+        initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+
+        let mut token = server
+            .issue_retry(retry_peer(), SystemTime::now())
+            .expect("retry")
+            .payload()
+            .to_vec();
+        token[10] ^= 0x01;
+        let tampered = initiator
+            .handle_retry(
+                &HandshakeMessage::new(
+                    HandshakeMessageKind::Retry,
+                    responder_static.public_key(),
+                    token,
+                ),
+                SystemTime::now(),
+            )
+            .expect("hello");
+        assert!(matches!(
+            server.accept_from(
+                &tampered,
+                &initiator_public,
+                retry_peer(),
+                SystemTime::now()
+            ),
+            Err(HandshakeError::InvalidRetryToken)
+        ));
+
+        let stale = SystemTime::now() - config.lifetime - Duration::from_secs(5);
+        let token = RetryToken::issue(&config.secret, &retry_peer(), stale);
+        let mut initiator = Initiator::new(initiator_static, responder_static.public_key());
+        initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let expired = initiator
+            .handle_retry(
+                &HandshakeMessage::new(
+                    HandshakeMessageKind::Retry,
+                    responder_static.public_key(),
+                    token.to_bytes().to_vec(),
+                ),
+                SystemTime::now(),
+            )
+            .expect("hello");
+        assert!(matches!(
+            server.accept_from(&expired, &initiator_public, retry_peer(), SystemTime::now()),
+            Err(HandshakeError::InvalidRetryToken)
+        ));
+    }
+
+    #[test]
+    fn initiator_times_out_after_retransmits_exhausted() {
+        let responder_public = fixed_private(0x46).public_key();
+        let mut initiator = Initiator::new(fixed_private(0x16), responder_public).with_timeouts(
+            HandshakeTimeoutConfig {
+                retransmit_timeout: Duration::from_millis(100),
+                max_retransmits: 0,
+            },
+        );
+
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let deadline = initiator.deadline().expect("deadline armed");
+        assert!(matches!(
+            initiator.poll_timeout(deadline - Duration::from_millis(1)),
+            Ok(None)
+        ));
+
+        let err = initiator
+            .poll_timeout(deadline)
+            .expect_err("deadline exceeded");
+        assert!(matches!(err, HandshakeError::Timeout));
+        assert!(initiator.is_failed());
+        assert!(initiator.deadline().is_none());
+
+        let bogus = HandshakeMessage::new(
+            HandshakeMessageKind::ResponderHello,
+            hello.ephemeral().clone(),
+            Vec::new(),
+        );
+        assert!(matches!(
+            initiator.handle_response(&bogus, SystemTime::now()),
+            Err(HandshakeError::UnexpectedMessage)
+        ));
+    }
+
+    #[test]
+    fn initiator_backs_off_exponentially_between_retransmits() {
+        let responder_public = fixed_private(0x48).public_key();
+        let timeout = Duration::from_millis(100);
+        let mut initiator = Initiator::new(fixed_private(0x18), responder_public).with_timeouts(
+            HandshakeTimeoutConfig {
+                retransmit_timeout: timeout,
+                max_retransmits: 3,
+            },
+        );
+
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let mut deadline = initiator.deadline().expect("deadline armed");
+        for factor in [2, 4, 8] {
+            let resent = initiator
+                .poll_timeout(deadline)
+                .expect("retransmit")
+                .expect("flight resent");
+            assert_eq!(resent.encode(), hello.encode());
+            let next = initiator.deadline().expect("deadline re-armed");
+            assert_eq!(next, deadline + timeout * factor);
+            deadline = next;
+        }
+
+        assert!(matches!(
+            initiator.poll_timeout(deadline),
+            Err(HandshakeError::Timeout)
+        ));
+        assert!(initiator.is_failed());
+    }
+
+    #[test]
+    fn responder_retransmit_resets_deadline_then_fails() {
+        let initiator_static = fixed_private(0x17);
+        let responder_static = fixed_private(0x47);
+        let timeout = Duration::from_millis(100);
+
+        let mut initiator = Initiator::new(initiator_static.clone(), responder_static.public_key());
+        let mut server =
+            HandshakeServer::new(responder_static).with_timeouts(HandshakeTimeoutConfig {
+                retransmit_timeout: timeout,
+                max_retransmits: 1,
+            });
+
+        let hello = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let mut pending = server
+            .accept(&hello, &initiator_static.public_key(), SystemTime::now())
+            .expect("responder hello");
+        let first_deadline = pending.deadline().expect("deadline armed");
+
+        let retransmitted = pending
+            .poll_timeout(first_deadline)
+            .expect("retransmit")
+            .expect("flight resent");
+        assert_eq!(retransmitted.encode(), pending.hello().encode());
+        let second_deadline = pending.deadline().expect("deadline re-armed");
+        assert_eq!(second_deadline, first_deadline + timeout * 2);
+        assert!(matches!(pending.poll_timeout(first_deadline), Ok(None)));
+
+        let err = pending
+            .poll_timeout(second_deadline)
+            .expect_err("retransmits exhausted");
+        assert!(matches!(err, HandshakeError::Timeout));
+        assert!(pending.is_failed());
+    }
+
     #[test]
     fn initiator_rejects_wrong_message_kind() {
         let initiator_static = fixed_private(0x21);
@@ -464,13 +2727,15 @@ mod tests {
         let responder_public = responder_static.public_key();
 
         let mut initiator = Initiator::new(initiator_static.clone(), responder_public.clone());
-        let mut responder = Responder::new(responder_static, Some(initiator_static.public_key()))
-            .expect("responder init");
+        let mut server = HandshakeServer::new(responder_static);
 
-        let msg_init = initiator.initiate().expect("initiator hello");
-        let msg_resp = responder
-            .handle_initiator_hello(&msg_init)
+        let msg_init = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let pending = server
+            .accept(&msg_init, &initiator_static.public_key(), SystemTime::now())
             .expect("responder hello");
+        let msg_resp = pending.hello();
 
         let bogus = HandshakeMessage::new(
             HandshakeMessageKind::InitiatorFinish,
@@ -479,7 +2744,7 @@ mod tests {
         );
 
         let err = initiator
-            .handle_response(&bogus)
+            .handle_response(&bogus, SystemTime::now())
             .expect_err("unexpected message should fail");
         assert!(matches!(err, HandshakeError::UnexpectedMessage));
     }
@@ -492,15 +2757,16 @@ mod tests {
         let responder_public = responder_static.public_key();
 
         let mut initiator = Initiator::new(initiator_static, responder_public);
-        let mut responder =
-            Responder::new(responder_static, Some(initiator_public)).expect("responder init");
+        let mut server = HandshakeServer::new(responder_static);
 
-        let msg_init = initiator.initiate().expect("initiator hello");
-        let msg_resp = responder
-            .handle_initiator_hello(&msg_init)
+        let msg_init = initiator
+            .initiate(SystemTime::now())
+            .expect("initiator hello");
+        let mut pending = server
+            .accept(&msg_init, &initiator_public, SystemTime::now())
             .expect("responder hello");
         let (msg_final, _) = initiator
-            .handle_response(&msg_resp)
+            .handle_response(pending.hello(), SystemTime::now())
             .expect("initiator finish");
 
         let bogus = HandshakeMessage::new(
@@ -509,10 +2775,14 @@ mod tests {
             msg_final.payload().to_vec(),
         );
 
-        let err = responder
-            .handle_initiator_finish(&bogus)
+        let err = pending
+            .handle_initiator_finish(&mut server, &bogus, SystemTime::now())
             .expect_err("unexpected finish should fail");
         assert!(matches!(err, HandshakeError::UnexpectedMessage));
+        assert!(matches!(
+            server.accept(&bogus, &initiator_public, SystemTime::now()),
+            Err(HandshakeError::UnexpectedMessage)
+        ));
     }
 
     #[test]
@@ -525,6 +2795,48 @@ mod tests {
         assert!(matches!(err, HandshakeError::ReplayDetected));
     }
 
+    #[test]
+    fn anti_replay_store_remembers_across_window_boundary() {
+        let ttl = Duration::from_secs(10);
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut store = AntiReplayStore::new(8, ttl);
+        store.record_at(b"first", start).expect("first insert ok");
+
+        // Recorded late in window N, replayed early in window N+1.
+        let late = start + ttl - Duration::from_millis(1);
+        store.record_at(b"late", late).expect("late insert ok");
+        let early_next = start + ttl + Duration::from_millis(1);
+        assert!(matches!(
+            store.record_at(b"late", early_next),
+            Err(HandshakeError::ReplayDetected)
+        ));
+        assert!(store.record_at(b"first", early_next).is_err());
+
+        // Two windows later both are forgotten.
+        let after = start + ttl * 2 + Duration::from_millis(1);
+        store
+            .record_at(b"first", after)
+            .expect("expired entry accepted");
+        assert!(store.record_at(b"late", after + ttl * 2).is_ok());
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn anti_replay_store_memory_is_bounded() {
+        let capacity = 1 << 14;
+        let mut store = AntiReplayStore::new(capacity, Duration::from_secs(60));
+        let now = SystemTime::now();
+        for idx in 0..(capacity * 3) as u64 {
+            store
+                .record_at(&idx.to_le_bytes(), now)
+                .expect("unique entry accepted");
+            assert!(store.len() <= capacity * 2);
+        }
+        // The most recent window is still protected.
+        let last = (capacity * 3 - 1) as u64;
+        assert!(store.record_at(&last.to_le_bytes(), now).is_err());
+    }
+
     struct FuzzRng(u64);
 
     impl FuzzRng {
@@ -578,7 +2890,7 @@ mod tests {
         let responder_public = responder_static.public_key();
 
         let mut initiator = Initiator::new(initiator_static, responder_public);
-        let hello = initiator.initiate().expect("hello");
+        let hello = initiator.initiate(SystemTime::now()).expect("hello");
         let base = hello.encode();
         let mut rng = FuzzRng::new(0xDEAD_D00Du64);
 
@@ -607,30 +2919,15 @@ mod tests {
 
     #[test]
     fn responder_session_resumption_validates_secret() {
-        let mut manager = SessionTicketManager::new(Duration::from_secs(60), 4);
+        let manager = SessionTicketManager::new(Duration::from_secs(60));
         let seed = [0xAAu8; SHARED_SECRET_LEN];
         let ticket = manager.issue(&seed);
 
         let resume = manager
-            .resume(ticket.id(), &seed)
+            .resume(ticket.sealed())
             .expect("ticket should resume");
 
         assert_eq!(resume.id(), ticket.id());
         assert_eq!(resume.secret(), ticket.secret());
     }
-
-    #[test]
-    fn nonce_derivation_varies_with_packet_number() {
-        let nonce_a = nonce_from_packet_number(1);
-        let nonce_b = nonce_from_packet_number(2);
-        assert_ne!(nonce_a.as_bytes(), nonce_b.as_bytes());
-
-        // Basic sanity that derived nonce size matches AEAD requirements.
-        let key = AeadKey::from_array([0x11u8; AEAD_KEY_LEN]);
-        let plaintext = [0x22u8; 8];
-        let (cipher, tag) = super::super::crypto::encrypt(&key, &nonce_a, &plaintext, &[]);
-        let decrypted =
-            super::super::crypto::decrypt(&key, &nonce_a, &cipher, &[], &tag).expect("decrypt");
-        assert_eq!(plaintext.to_vec(), decrypted);
-    }
 }
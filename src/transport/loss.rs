@@ -1,8 +1,10 @@
 //! Sent packet tracking, RTT estimation, and loss detection for MXP transport.
 
 use crate::transport::ack::AckFrame;
+#[cfg(feature = "qlog")]
+use crate::transport::qlog::{LossTrigger, QlogEvent, SharedEventLogger};
 use core::cmp::Ordering;
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::{Duration, SystemTime};
 use tracing::{debug, trace};
 
@@ -66,6 +68,11 @@ pub struct AckOutcome {
     pub lost: Vec<SentPacketInfo>,
     /// Latest RTT sample derived from the ACK delay.
     pub rtt_sample: Option<Duration>,
+    /// Increase in the peer's reported CE count since the previous ACK frame.
+    ///
+    /// A non-zero value is a congestion signal: controllers react as they would to a
+    /// loss, but none of the acknowledged packets need retransmission.
+    pub ecn_ce_increase: u64,
 }
 
 /// Configurable parameters driving the loss detector.
@@ -95,22 +102,35 @@ impl Default for LossConfig {
     }
 }
 
+#[cfg(feature = "qlog")]
+fn log_event(logger: Option<&SharedEventLogger>, now: SystemTime, event: &QlogEvent) {
+    if let Some(logger) = logger {
+        logger.log(now, event);
+    }
+}
+
 /// Tracks outstanding packets and estimates RTT/loss timers.
+///
+/// Outstanding packets are keyed by packet number, so ACK ranges and the packet-threshold
+/// check touch only the packets they cover, and indexed by send time, so the time-threshold
+/// check only visits packets older than the loss delay.
 #[derive(Debug)]
 pub struct LossManager {
     config: LossConfig,
-    outstanding: VecDeque<SentPacketInternal>,
+    outstanding: BTreeMap<u64, SentPacketInfo>,
+    /// `(time_sent, packet_number)` for every outstanding packet.
+    by_time: BTreeSet<(SystemTime, u64)>,
+    /// Sum of the sizes of outstanding packets.
+    bytes_in_flight: usize,
     largest_acked: Option<u64>,
     latest_rtt: Option<Duration>,
     smoothed_rtt: Option<Duration>,
     rtt_var: Option<Duration>,
     min_rtt: Option<Duration>,
     loss_time: Option<SystemTime>,
-}
-
-#[derive(Debug, Clone)]
-struct SentPacketInternal {
-    info: SentPacketInfo,
+    peer_ecn_ce: u64,
+    #[cfg(feature = "qlog")]
+    event_logger: Option<SharedEventLogger>,
 }
 
 impl LossManager {
@@ -119,13 +139,28 @@ impl LossManager {
     pub fn new(config: LossConfig) -> Self {
         Self {
             config,
-            outstanding: VecDeque::new(),
+            outstanding: BTreeMap::new(),
+            by_time: BTreeSet::new(),
+            bytes_in_flight: 0,
             largest_acked: None,
             latest_rtt: None,
             smoothed_rtt: None,
             rtt_var: None,
             min_rtt: None,
             loss_time: None,
+            peer_ecn_ce: 0,
+            #[cfg(feature = "qlog")]
+            event_logger: None,
+        }
+    }
+
+    /// Create a manager that reports ACKs and losses to `logger`.
+    #[cfg(feature = "qlog")]
+    #[must_use]
+    pub fn with_event_logger(config: LossConfig, logger: SharedEventLogger) -> Self {
+        Self {
+            event_logger: Some(logger),
+            ..Self::new(config)
         }
     }
 
@@ -142,7 +177,12 @@ impl LossManager {
             size, ack_eliciting, "loss tracker observe sent packet"
         );
         let info = SentPacketInfo::new(packet_number, time_sent, size, ack_eliciting);
-        self.outstanding.push_back(SentPacketInternal { info });
+        if let Some(previous) = self.outstanding.insert(packet_number, info) {
+            self.by_time.remove(&(previous.time_sent, packet_number));
+            self.bytes_in_flight -= previous.size;
+        }
+        self.bytes_in_flight += size;
+        self.by_time.insert((time_sent, packet_number));
         if ack_eliciting {
             self.update_loss_time(time_sent);
         }
@@ -156,30 +196,31 @@ impl LossManager {
         );
         let mut outcome = AckOutcome::default();
 
-        let mut retained = VecDeque::with_capacity(self.outstanding.len());
-        let mut acknowledged_largest: Option<SentPacketInfo> = None;
-
-        for entry in self.outstanding.drain(..) {
-            if ack_contains(frame, entry.info.packet_number) {
-                if acknowledged_largest
-                    .as_ref()
-                    .is_none_or(|pkt| pkt.packet_number < entry.info.packet_number)
-                {
-                    acknowledged_largest = Some(entry.info.clone());
-                }
-                outcome.acknowledged.push(entry.info.clone());
-            } else {
-                retained.push_back(entry);
+        // Ranges are sorted descending; walk them lowest first so packets come out in order.
+        let mut acked = Vec::new();
+        for range in frame.ranges().iter().rev() {
+            if range.start() > frame.largest() {
+                continue;
+            }
+            let end = range.end().min(frame.largest());
+            acked.extend(
+                self.outstanding
+                    .range(range.start()..=end)
+                    .map(|(pn, _)| *pn),
+            );
+        }
+        for packet_number in acked {
+            if let Some(info) = self.remove(packet_number) {
+                outcome.acknowledged.push(info);
             }
         }
 
-        self.outstanding = retained;
-
-        if let Some(largest) = acknowledged_largest {
-            self.largest_acked = Some(largest.packet_number);
+        if let Some(largest) = outcome.acknowledged.last() {
             let ack_delay = Duration::from_micros(frame.ack_delay_micros());
             let ack_delay = ack_delay.min(self.config.max_ack_delay);
-            if let Ok(mut latest) = now.duration_since(largest.time_sent) {
+            let largest_time_sent = largest.time_sent;
+            self.largest_acked = Some(largest.packet_number);
+            if let Ok(mut latest) = now.duration_since(largest_time_sent) {
                 // Subtract acknowledged ACK delay if it does not underflow.
                 if latest > ack_delay {
                     latest -= ack_delay;
@@ -189,6 +230,28 @@ impl LossManager {
             }
         }
 
+        #[cfg(feature = "qlog")]
+        log_event(
+            self.event_logger.as_ref(),
+            now,
+            &QlogEvent::AckReceived {
+                ranges: frame.ranges().to_vec(),
+                rtt: outcome.rtt_sample,
+            },
+        );
+
+        if let Some(ecn) = frame.ecn_counts() {
+            // Counts are cumulative; a reordered ACK with a stale count is not a signal.
+            if ecn.ce > self.peer_ecn_ce {
+                outcome.ecn_ce_increase = ecn.ce - self.peer_ecn_ce;
+                self.peer_ecn_ce = ecn.ce;
+                debug!(
+                    increase = outcome.ecn_ce_increase,
+                    "peer reported congestion experienced"
+                );
+            }
+        }
+
         let lost = self.detect_losses(frame.largest(), now);
         outcome.lost.extend(lost);
 
@@ -214,32 +277,37 @@ impl LossManager {
             return Vec::new();
         };
 
-        let mut lost = Vec::new();
-        let mut retained = VecDeque::with_capacity(self.outstanding.len());
-
-        for entry in self.outstanding.drain(..) {
-            if !entry.info.ack_eliciting {
-                retained.push_back(entry);
-                continue;
-            }
-
-            let elapsed = now.duration_since(entry.info.time_sent).unwrap_or_default();
-            if elapsed >= delay {
-                debug!(
-                    packet_number = entry.info.packet_number(),
-                    "loss via explicit timeout"
-                );
-                lost.push(entry.info.clone());
-            } else {
-                retained.push_back(entry);
-            }
+        let mut expired: Vec<u64> = self
+            .sent_before(now, delay)
+            .filter(|pn| self.outstanding[pn].ack_eliciting)
+            .collect();
+        expired.sort_unstable();
+
+        let mut lost = Vec::with_capacity(expired.len());
+        for packet_number in expired {
+            debug!(packet_number, "loss via explicit timeout");
+            #[cfg(feature = "qlog")]
+            log_event(
+                self.event_logger.as_ref(),
+                now,
+                &QlogEvent::PacketLost {
+                    packet_number,
+                    trigger: LossTrigger::TimeThreshold,
+                },
+            );
+            lost.extend(self.remove(packet_number));
         }
 
-        self.outstanding = retained;
         self.recalculate_loss_time(now);
         lost
     }
 
+    /// Largest packet number the peer has acknowledged.
+    #[must_use]
+    pub const fn largest_acked(&self) -> Option<u64> {
+        self.largest_acked
+    }
+
     /// Latest RTT sample observed.
     #[must_use]
     pub const fn latest_rtt(&self) -> Option<Duration> {
@@ -261,7 +329,35 @@ impl LossManager {
     /// Remaining outstanding packet references (for diagnostics).
     #[must_use]
     pub fn outstanding(&self) -> impl Iterator<Item = &SentPacketInfo> {
-        self.outstanding.iter().map(|entry| &entry.info)
+        self.outstanding.values()
+    }
+
+    /// Bytes sent in packets neither acknowledged nor declared lost.
+    #[must_use]
+    pub const fn bytes_in_flight(&self) -> usize {
+        self.bytes_in_flight
+    }
+
+    /// Number of packets neither acknowledged nor declared lost.
+    #[must_use]
+    pub fn outstanding_count(&self) -> usize {
+        self.outstanding.len()
+    }
+
+    fn remove(&mut self, packet_number: u64) -> Option<SentPacketInfo> {
+        let info = self.outstanding.remove(&packet_number)?;
+        self.by_time.remove(&(info.time_sent, packet_number));
+        self.bytes_in_flight -= info.size;
+        Some(info)
+    }
+
+    /// Packet numbers sent at least `delay` before `now`, oldest first.
+    fn sent_before(&self, now: SystemTime, delay: Duration) -> impl Iterator<Item = u64> + '_ {
+        let cutoff = now.checked_sub(delay);
+        self.by_time
+            .iter()
+            .take_while(move |(sent, _)| cutoff.is_some_and(|cutoff| *sent <= cutoff))
+            .map(|(_, packet_number)| *packet_number)
     }
 
     fn update_rtt_estimates(&mut self, latest: Duration) {
@@ -284,38 +380,49 @@ impl LossManager {
     }
 
     fn detect_losses(&mut self, largest_acked: u64, now: SystemTime) -> Vec<SentPacketInfo> {
-        let mut lost = Vec::new();
-        let mut retained = VecDeque::with_capacity(self.outstanding.len());
         let threshold = self.config.packet_threshold;
-        let loss_delay = self.time_threshold();
-
-        for entry in self.outstanding.drain(..) {
-            if largest_acked >= entry.info.packet_number
-                && largest_acked - entry.info.packet_number >= threshold
-            {
-                debug!(
-                    packet_number = entry.info.packet_number(),
-                    "loss via packet threshold"
+        let by_threshold = largest_acked.checked_sub(threshold);
+        let mut candidates: Vec<u64> = by_threshold
+            .map(|limit| {
+                self.outstanding
+                    .range(..=limit)
+                    .map(|(pn, _)| *pn)
+                    .collect()
+            })
+            .unwrap_or_default();
+        if let Some(delay) = self.time_threshold() {
+            candidates.extend(self.sent_before(now, delay));
+        }
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        let mut lost = Vec::with_capacity(candidates.len());
+        for packet_number in candidates {
+            if by_threshold.is_some_and(|limit| packet_number <= limit) {
+                debug!(packet_number, "loss via packet threshold");
+                #[cfg(feature = "qlog")]
+                log_event(
+                    self.event_logger.as_ref(),
+                    now,
+                    &QlogEvent::PacketLost {
+                        packet_number,
+                        trigger: LossTrigger::ReorderingThreshold,
+                    },
+                );
+            } else {
+                debug!(packet_number, "loss via time threshold");
+                #[cfg(feature = "qlog")]
+                log_event(
+                    self.event_logger.as_ref(),
+                    now,
+                    &QlogEvent::PacketLost {
+                        packet_number,
+                        trigger: LossTrigger::TimeThreshold,
+                    },
                 );
-                lost.push(entry.info.clone());
-                continue;
-            }
-
-            if let Some(delay) = loss_delay {
-                if now.duration_since(entry.info.time_sent).unwrap_or_default() >= delay {
-                    debug!(
-                        packet_number = entry.info.packet_number(),
-                        "loss via time threshold"
-                    );
-                    lost.push(entry.info.clone());
-                    continue;
-                }
             }
-
-            retained.push_back(entry);
+            lost.extend(self.remove(packet_number));
         }
-
-        self.outstanding = retained;
         lost
     }
 
@@ -340,19 +447,14 @@ impl LossManager {
     }
 
     fn recalculate_loss_time(&mut self, now: SystemTime) {
-        self.loss_time = None;
-        for entry in &self.outstanding {
-            if !entry.info.ack_eliciting {
-                continue;
-            }
-            if let Some(delay) = self.time_threshold() {
-                let candidate = entry.info.time_sent + delay;
-                self.loss_time = match self.loss_time {
-                    Some(current) if current <= candidate => Some(current),
-                    _ => Some(candidate),
-                };
-            }
-        }
+        let earliest = self
+            .by_time
+            .iter()
+            .find(|(_, pn)| self.outstanding[pn].ack_eliciting)
+            .map(|(sent, _)| *sent);
+        self.loss_time = earliest
+            .zip(self.time_threshold())
+            .map(|(sent, delay)| sent + delay);
 
         if self.loss_time.is_some() {
             return;
@@ -364,13 +466,6 @@ impl LossManager {
     }
 }
 
-fn ack_contains(frame: &AckFrame, packet_number: u64) -> bool {
-    frame
-        .ranges()
-        .iter()
-        .any(|range| packet_number >= range.start() && packet_number <= range.end())
-}
-
 fn abs_duration_diff(a: Duration, b: Duration) -> Duration {
     match a.cmp(&b) {
         Ordering::Less => b - a,
@@ -392,6 +487,12 @@ fn scale_duration(base: Duration, numerator: u32, denominator: u32) -> Duration
 mod tests {
     use super::*;
     use crate::transport::ack::AckRange;
+    use crate::transport::ecn::EcnCounts;
+    use crate::transport::time::{Clock, MockClock, TimerWheel};
+
+    fn clock() -> MockClock {
+        MockClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000))
+    }
 
     fn ack_frame_from_ranges(largest: u64, ack_delay: Duration, ranges: &[(u64, u64)]) -> AckFrame {
         let range_structs: Vec<AckRange> = ranges
@@ -404,16 +505,15 @@ mod tests {
     #[test]
     fn ack_marks_packets_acked_and_updates_rtt() {
         let mut mgr = LossManager::new(LossConfig::default());
-        let send_time = SystemTime::now();
-        mgr.on_packet_sent(10, send_time, 1200, true);
-        let ack_time = send_time + Duration::from_millis(50);
+        let clock = clock();
+        mgr.on_packet_sent(10, clock.now(), 1200, true);
+        clock.advance(Duration::from_millis(50));
         let frame = ack_frame_from_ranges(10, Duration::from_millis(10), &[(10, 10)]);
-        let outcome = mgr.on_ack_frame(&frame, ack_time);
+        let outcome = mgr.on_ack_frame(&frame, clock.now());
         assert_eq!(outcome.acknowledged.len(), 1);
         assert!(outcome.lost.is_empty());
         let sample = outcome.rtt_sample.expect("sample");
-        assert!(sample >= Duration::from_millis(39));
-        assert!(sample <= Duration::from_millis(40));
+        assert_eq!(sample, Duration::from_millis(40));
         assert!(mgr.latest_rtt().is_some());
     }
 
@@ -424,15 +524,14 @@ mod tests {
             ..Default::default()
         };
         let mut mgr = LossManager::new(config);
-        let base = SystemTime::now();
-        mgr.on_packet_sent(1, base, 1000, true);
-        mgr.on_packet_sent(2, base, 1000, true);
-        mgr.on_packet_sent(3, base, 1000, true);
-        mgr.on_packet_sent(4, base, 1000, true);
+        let clock = clock();
+        for packet_number in 1..=4 {
+            mgr.on_packet_sent(packet_number, clock.now(), 1000, true);
+        }
 
-        let ack_time = base + Duration::from_millis(5);
+        clock.advance(Duration::from_millis(5));
         let frame = ack_frame_from_ranges(4, Duration::from_micros(0), &[(4, 4)]);
-        let outcome = mgr.on_ack_frame(&frame, ack_time);
+        let outcome = mgr.on_ack_frame(&frame, clock.now());
 
         assert_eq!(outcome.acknowledged.len(), 1);
         assert_eq!(outcome.lost.len(), 2);
@@ -447,23 +546,166 @@ mod tests {
             ..Default::default()
         };
         let mut mgr = LossManager::new(config);
-        let base = SystemTime::now();
-        mgr.on_packet_sent(5, base, 900, true);
+        let clock = clock();
+        mgr.on_packet_sent(5, clock.now(), 900, true);
 
-        let ack_time = base + Duration::from_millis(50);
+        clock.advance(Duration::from_millis(50));
         let frame = ack_frame_from_ranges(6, Duration::from_millis(0), &[(6, 6)]);
-        let outcome = mgr.on_ack_frame(&frame, ack_time);
+        let outcome = mgr.on_ack_frame(&frame, clock.now());
         assert!(!outcome.lost.is_empty());
     }
 
     #[test]
     fn loss_time_updates_on_send_and_ack() {
         let mut mgr = LossManager::new(LossConfig::default());
-        let now = SystemTime::now();
-        mgr.on_packet_sent(1, now, 1200, true);
+        let clock = clock();
+        mgr.on_packet_sent(1, clock.now(), 1200, true);
         assert!(mgr.loss_time().is_some());
+        clock.advance(Duration::from_millis(30));
         let frame = ack_frame_from_ranges(1, Duration::from_millis(0), &[(1, 1)]);
-        mgr.on_ack_frame(&frame, now + Duration::from_millis(30));
+        mgr.on_ack_frame(&frame, clock.now());
         assert!(mgr.loss_time().is_some());
     }
+
+    /// Send a flight, acknowledge part of it, and let the loss timer fire, returning every
+    /// observable outcome with the time it happened.
+    fn run_timer_driven_scenario() -> Vec<(SystemTime, Vec<u64>, Vec<u64>)> {
+        let clock = clock();
+        let mut wheel = TimerWheel::new(clock.now(), Duration::from_millis(1));
+        let mut mgr = LossManager::new(LossConfig::default());
+        let mut log = Vec::new();
+
+        for packet_number in 0..6 {
+            mgr.on_packet_sent(packet_number, clock.now(), 1200, true);
+            clock.advance(Duration::from_millis(1));
+        }
+        clock.advance(Duration::from_millis(29));
+        // Packets 1, 3 and 4 never arrive; the peer acknowledges the rest.
+        let frame = ack_frame_from_ranges(5, Duration::ZERO, &[(5, 5), (2, 2), (0, 0)]);
+        let outcome = mgr.on_ack_frame(&frame, clock.now());
+        log.push((
+            clock.now(),
+            outcome
+                .acknowledged
+                .iter()
+                .map(SentPacketInfo::packet_number)
+                .collect(),
+            outcome
+                .lost
+                .iter()
+                .map(SentPacketInfo::packet_number)
+                .collect(),
+        ));
+
+        for _ in 0..1_000 {
+            if let Some(deadline) = mgr.loss_time() {
+                wheel.insert("loss", deadline);
+            }
+            clock.advance(Duration::from_millis(1));
+            if wheel.advance(clock.now()).contains(&"loss") {
+                let lost = mgr.on_loss_timeout(clock.now());
+                log.push((
+                    clock.now(),
+                    Vec::new(),
+                    lost.iter().map(SentPacketInfo::packet_number).collect(),
+                ));
+            }
+            if mgr.outstanding().next().is_none() {
+                break;
+            }
+        }
+        log
+    }
+
+    #[test]
+    fn ack_reports_ce_increase_without_losses() {
+        let mut mgr = LossManager::new(LossConfig::default());
+        let clock = clock();
+        for pn in 0..3 {
+            mgr.on_packet_sent(pn, clock.now(), 1200, true);
+        }
+        clock.advance(Duration::from_millis(20));
+        let with_ce = |largest, ce| {
+            ack_frame_from_ranges(largest, Duration::ZERO, &[(0, largest)]).with_ecn_counts(
+                EcnCounts {
+                    ect0: largest + 1 - ce,
+                    ect1: 0,
+                    ce,
+                },
+            )
+        };
+
+        let outcome = mgr.on_ack_frame(&with_ce(1, 1), clock.now());
+        assert_eq!(outcome.ecn_ce_increase, 1);
+        assert_eq!(outcome.acknowledged.len(), 2);
+        assert!(outcome.lost.is_empty());
+
+        // A reordered ACK carrying an older count is not a new signal.
+        let outcome = mgr.on_ack_frame(&with_ce(0, 0), clock.now());
+        assert_eq!(outcome.ecn_ce_increase, 0);
+
+        let outcome = mgr.on_ack_frame(&with_ce(2, 3), clock.now());
+        assert_eq!(outcome.ecn_ce_increase, 2);
+        assert_eq!(mgr.outstanding().count(), 0);
+    }
+
+    #[test]
+    fn loss_detection_is_deterministic_under_mock_clock() {
+        let first = run_timer_driven_scenario();
+        assert_eq!(first, run_timer_driven_scenario());
+
+        let start = clock().now();
+        // Packet 1 is far enough behind the largest ACK to be declared lost immediately;
+        // 3 and 4 go once the time threshold (9/8 of the 30 ms RTT) passes for each.
+        assert_eq!(
+            first,
+            [
+                (start + Duration::from_millis(35), vec![0, 2, 5], vec![1]),
+                (start + Duration::from_millis(37), Vec::new(), vec![3]),
+                (start + Duration::from_millis(38), Vec::new(), vec![4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn bytes_in_flight_tracks_outstanding_sizes() {
+        fn assert_consistent(mgr: &LossManager) {
+            let sum: usize = mgr.outstanding().map(SentPacketInfo::size).sum();
+            assert_eq!(mgr.bytes_in_flight(), sum);
+            assert_eq!(mgr.outstanding_count(), mgr.outstanding().count());
+        }
+
+        let mut mgr = LossManager::new(LossConfig::default());
+        let clock = clock();
+        for (packet_number, size) in (0..8).zip((100..).step_by(100)) {
+            mgr.on_packet_sent(packet_number, clock.now(), size, true);
+            assert_consistent(&mgr);
+        }
+        assert_eq!(mgr.bytes_in_flight(), 3_600);
+        // Recording a packet number again replaces the earlier packet.
+        mgr.on_packet_sent(7, clock.now(), 50, false);
+        assert_consistent(&mgr);
+        assert_eq!(mgr.outstanding_count(), 8);
+
+        clock.advance(Duration::from_millis(10));
+        // 6 is acknowledged; 0..=3 fall behind the packet threshold.
+        let frame = ack_frame_from_ranges(6, Duration::ZERO, &[(6, 6)]);
+        let outcome = mgr.on_ack_frame(&frame, clock.now());
+        assert_eq!(outcome.acknowledged.len(), 1);
+        assert_eq!(outcome.lost.len(), 4);
+        assert_consistent(&mgr);
+        assert_eq!(mgr.bytes_in_flight(), 500 + 600 + 50);
+
+        clock.advance(Duration::from_secs(1));
+        let lost = mgr.on_loss_timeout(clock.now());
+        assert_eq!(lost.len(), 2);
+        assert_consistent(&mgr);
+        // Only the non-ack-eliciting packet 7 remains.
+        assert_eq!((mgr.bytes_in_flight(), mgr.outstanding_count()), (50, 1));
+
+        let frame = ack_frame_from_ranges(7, Duration::ZERO, &[(7, 7)]);
+        mgr.on_ack_frame(&frame, clock.now());
+        assert_consistent(&mgr);
+        assert_eq!((mgr.bytes_in_flight(), mgr.outstanding_count()), (0, 0));
+    }
 }
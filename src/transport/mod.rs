@@ -1,58 +1,134 @@
 //! MXP custom transport (work in progress)
 
 mod ack;
+mod admission;
 mod anti_amplification;
+mod batch;
 mod buffer;
 mod congestion;
+mod connection;
 mod crypto;
+mod cubic;
 mod datagram;
+mod demux;
+mod ecn;
 mod error;
 mod flow;
+mod fragment;
 mod handshake;
 mod loss;
+mod mtu;
+mod pacer;
 mod packet;
 mod packet_crypto;
+mod params;
+mod retry;
 mod scheduler;
 mod session;
 mod socket;
 mod stream;
+mod time;
 mod transport;
 
+#[cfg(feature = "tokio")]
+mod async_connection;
 #[cfg(feature = "debug-tools")]
 mod debug;
+#[cfg(feature = "qlog")]
+mod qlog;
 
-pub use ack::{AckError, AckFrame, AckRange, DEFAULT_MAX_ACK_RANGES, ReceiveHistory};
+pub use ack::{
+    AckError, AckFrame, AckFrequencyConfig, AckRange, DEFAULT_ACK_ELICITING_THRESHOLD,
+    DEFAULT_MAX_ACK_RANGES, ReceiveHistory,
+};
+pub use admission::{
+    AdmissionControl, ConnectionLimits, DEFAULT_BYTE_BURST, DEFAULT_BYTE_RATE,
+    DEFAULT_HANDSHAKE_BURST, DEFAULT_HANDSHAKE_RATE, DEFAULT_MAX_CONNECTIONS,
+    DEFAULT_MAX_CONNECTIONS_PER_IP, DEFAULT_MAX_TRACKED_PEERS, RateLimit, RateLimiter, Refusal,
+};
 pub use anti_amplification::{
     AmplificationConfig, AntiAmplificationGuard, DEFAULT_AMPLIFICATION_FACTOR,
+    DEFAULT_MAX_TRACKED_PATHS, PerPathAmplificationTracker,
+};
+#[cfg(feature = "tokio")]
+pub use async_connection::AsyncConnection;
+pub use batch::{BatchedReceiver, MessageSink};
+pub use buffer::{
+    Buffer, BufferPool, DEFAULT_BUFFERS_PER_CLASS, DEFAULT_SIZE_CLASSES, SizeClassedPool,
+};
+pub use congestion::{
+    CongestionAlgorithm, CongestionConfig, CongestionControl, CongestionController,
+};
+pub use connection::{
+    Connection, ConnectionClose, ConnectionConfig, ConnectionError, ConnectionState,
+    DEFAULT_MAX_DATAGRAM_SIZE, DEFAULT_MAX_IDLE_TIMEOUT, IDLE_TIMEOUT_REASON,
 };
-pub use buffer::{Buffer, BufferPool};
-pub use congestion::{CongestionConfig, CongestionController};
 pub use crypto::{
-    AEAD_KEY_LEN, AEAD_NONCE_LEN, AEAD_TAG_LEN, AeadKey, AeadNonce, AeadTag, CryptoError,
-    HEADER_PROTECTION_KEY_LEN, HEADER_PROTECTION_MASK_LEN, HEADER_PROTECTION_SAMPLE_LEN,
-    HandshakeState, HeaderProtectionKey, PRIVATE_KEY_LEN, PUBLIC_KEY_LEN, PrivateKey, PublicKey,
-    SHARED_SECRET_LEN, SessionKeys, SharedSecret, decrypt, encrypt, header_protection_mask,
+    AEAD_KEY_LEN, AEAD_NONCE_LEN, AEAD_TAG_LEN, AeadCipher, AeadCipherSet, AeadDecryptor,
+    AeadEncryptor, AeadKey, AeadNonce, AeadTag, CryptoError, HEADER_PROTECTION_KEY_LEN,
+    HEADER_PROTECTION_MASK_LEN, HEADER_PROTECTION_SAMPLE_LEN, HKDF_PRK_LEN, HandshakeState,
+    HashAlgorithm, HeaderProtectionKey, IDENTITY_KEY_LEN, IDENTITY_SEED_LEN, Identity, IdentityKey,
+    PRIVATE_KEY_LEN, PUBLIC_KEY_LEN, PrivateKey, PublicKey, SHARED_SECRET_LEN, SIGNATURE_LEN,
+    SessionKeys, SharedSecret, Signature, TRANSCRIPT_HASH_LEN, chacha20_poly1305_open,
+    chacha20_poly1305_seal, decrypt, derive_header_protection_key, encrypt, header_protection_mask,
+    hkdf_expand, hkdf_extract, hmac_sha256,
 };
+pub use cubic::{CUBIC_BETA, CubicController};
 pub use datagram::{
-    DEFAULT_DATAGRAM_MAX_PAYLOAD, DEFAULT_DATAGRAM_QUEUE, DatagramConfig, DatagramError,
-    DatagramQueue,
+    DEFAULT_DATAGRAM_MAX_PAYLOAD, DEFAULT_DATAGRAM_QUEUE, DatagramConfig, DatagramDropPolicy,
+    DatagramError, DatagramQueue, DatagramReceiveQueue,
 };
+pub use demux::{ConnectionHandle, ConnectionTable};
+pub use ecn::{EcnCodepoint, EcnCounts};
 pub use error::TransportError;
 pub use flow::{FlowControlError, FlowController, FlowWindow};
+pub use fragment::{
+    DEFAULT_MAX_PENDING_DATAGRAMS, DEFAULT_MAX_REASSEMBLED_DATAGRAM, DEFAULT_REASSEMBLY_BUFFER,
+    DEFAULT_REASSEMBLY_TIMEOUT, DatagramFragmenter, DatagramReassembler, FRAGMENT_HEADER_LEN,
+    ReassemblyConfig,
+};
 pub use handshake::{
-    AntiReplayStore, HandshakeError, HandshakeMessage, HandshakeMessageKind, Initiator, Responder,
-    ResponderOutcome, nonce_from_packet_number,
+    AntiReplayStore, DEFAULT_ANTI_REPLAY_CAPACITY, EarlyDataStatus, HandshakeError,
+    HandshakeMessage, HandshakeMessageKind, HandshakeServer, HandshakeTimeoutConfig, Initiator,
+    MAX_EARLY_DATA_LEN, MAX_RETRY_TOKEN_LEN, PROTOCOL_VERSION, PendingHandshake, ResponderOutcome,
+    SUPPORTED_VERSIONS,
 };
 pub use loss::{AckOutcome, LossConfig, LossManager, SentPacketInfo};
-pub use packet::{Frame, FrameType, HEADER_SIZE, PacketFlags, PacketHeader};
+pub use mtu::{BASE_PLPMTU, DEFAULT_MAX_PLPMTU, PmtuConfig, PmtuDiscovery};
+pub use pacer::{DEFAULT_BURST_INTERVAL, DEFAULT_BURST_PACKETS, Pacer, PacerConfig};
+pub use packet::{
+    ConnectionId, FRAME_HEADER_LEN, Frame, FrameError, FrameType, MAX_CONN_ID_LEN, MAX_HEADER_SIZE,
+    MAX_PACKET_NUMBER_LEN, MAX_STREAM_DATA_LEN, MAX_VARINT, MIN_HEADER_SIZE, PacketError,
+    PacketFlags, PacketHeader, STREAM_DATA_OVERHEAD, WIRE_VERSION, decode_packet_number,
+    encoded_packet_number_len,
+};
 pub use packet_crypto::{DecryptedPacket, PacketCipher};
-pub use scheduler::{PriorityClass, Scheduler};
-pub use session::{SessionTicket, SessionTicketManager, TICKET_ID_LEN, TICKET_SECRET_LEN};
-pub use socket::{SocketBinding, SocketError};
+pub use params::{
+    MAX_ACK_DELAY_EXPONENT, MIN_UDP_PAYLOAD_SIZE, TransportParameters, TransportParametersError,
+};
+pub use retry::{RETRY_SECRET_LEN, RETRY_TOKEN_LEN, RetryConfig, RetryToken};
+pub use scheduler::{PriorityClass, Scheduler, SchedulerConfig};
+pub use session::{
+    ClientTicketCache, DEFAULT_CLIENT_TICKET_CACHE, SEALED_TICKET_LEN, SessionTicket,
+    SessionTicketManager, TICKET_ID_LEN, TICKET_KEY_LEN, TICKET_SECRET_LEN,
+};
+#[cfg(feature = "tokio")]
+pub use socket::AsyncSocketBinding;
+pub use socket::{DatagramSocket, RecvMeta, SocketBinding, SocketError};
 pub use stream::{
-    EndpointRole, SendChunk, Stream, StreamError, StreamId, StreamKind, StreamManager,
+    BackpressureState, DEFAULT_MAX_BUFFERED_PER_STREAM, DEFAULT_MAX_BUFFERED_TOTAL,
+    DEFAULT_MAX_CONCURRENT_BIDI_STREAMS, DEFAULT_MAX_CONCURRENT_UNI_STREAMS, EndpointRole,
+    RecvBufferLimits, SendChunk, SendState, Stream, StreamAccess, StreamCountLimits, StreamError,
+    StreamId, StreamKind, StreamManager,
 };
-pub use transport::{Transport, TransportConfig, TransportHandle};
+pub use time::{Clock, DEFAULT_TIMER_TICK, MockClock, SystemClock, TimerWheel};
+#[cfg(feature = "tokio")]
+pub use transport::AsyncTransportHandle;
+pub use transport::{PeerFilter, Transport, TransportConfig, TransportHandle};
 
 #[cfg(feature = "debug-tools")]
 pub use debug::PcapRecorder;
+#[cfg(feature = "qlog")]
+pub use qlog::{
+    EventLogger, JsonLinesLogger, LossTrigger, QLOG_VERSION, QlogEvent, SharedEventLogger,
+};
@@ -0,0 +1,210 @@
+//! Datagram Packetization Layer Path MTU Discovery (DPLPMTUD).
+//!
+//! The search starts from the minimum payload every path must carry and binary-searches
+//! upward with padded probe packets. An acknowledged probe raises the usable payload size;
+//! a probe size that is lost `max_probes` times in a row is treated as too large.
+
+use tracing::debug;
+
+use super::packet::Frame;
+use super::params::MIN_UDP_PAYLOAD_SIZE;
+
+/// Payload size every path is assumed to support.
+pub const BASE_PLPMTU: usize = 1200;
+/// Default upper bound for the search (Ethernet MTU minus IPv6 and UDP headers).
+pub const DEFAULT_MAX_PLPMTU: usize = 1452;
+/// Searching stops once the candidate range is narrower than this many bytes.
+const SEARCH_GRANULARITY: usize = 8;
+
+/// Configuration for path MTU discovery.
+#[derive(Debug, Clone)]
+pub struct PmtuConfig {
+    /// Payload size assumed to work before any probe succeeds.
+    pub base_mtu: usize,
+    /// Largest payload size to probe (e.g. the peer's `max_udp_payload_size`).
+    pub max_mtu: usize,
+    /// Consecutive losses of one probe size before it is considered too large.
+    pub max_probes: u32,
+}
+
+impl Default for PmtuConfig {
+    fn default() -> Self {
+        Self {
+            base_mtu: BASE_PLPMTU,
+            max_mtu: DEFAULT_MAX_PLPMTU,
+            max_probes: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct InFlightProbe {
+    packet_number: u64,
+    size: usize,
+}
+
+/// Per-path MTU search state.
+#[derive(Debug, Clone)]
+pub struct PmtuDiscovery {
+    config: PmtuConfig,
+    current: usize,
+    ceiling: usize,
+    probe: Option<InFlightProbe>,
+    probe_losses: u32,
+}
+
+impl PmtuDiscovery {
+    /// Create a search bounded by `config`.
+    #[must_use]
+    pub fn new(config: PmtuConfig) -> Self {
+        let base = config
+            .base_mtu
+            .max(usize::try_from(MIN_UDP_PAYLOAD_SIZE).unwrap_or(BASE_PLPMTU));
+        let ceiling = config.max_mtu.max(base);
+        Self {
+            config,
+            current: base,
+            ceiling,
+            probe: None,
+            probe_losses: 0,
+        }
+    }
+
+    /// Largest payload size confirmed to reach the peer.
+    #[must_use]
+    pub const fn current_mtu(&self) -> usize {
+        self.current
+    }
+
+    /// Whether the search has converged.
+    #[must_use]
+    pub const fn is_complete(&self) -> bool {
+        self.ceiling - self.current < SEARCH_GRANULARITY
+    }
+
+    /// Size of the next probe to send, or `None` while a probe is in flight or the
+    /// search has converged.
+    #[must_use]
+    pub fn next_probe_size(&self) -> Option<usize> {
+        if self.probe.is_some() || self.is_complete() {
+            return None;
+        }
+        Some(self.current + (self.ceiling - self.current).div_ceil(2))
+    }
+
+    /// Record that a probe of `size` bytes went out as `packet_number`.
+    pub fn on_probe_sent(&mut self, packet_number: u64, size: usize) {
+        self.probe = Some(InFlightProbe {
+            packet_number,
+            size,
+        });
+    }
+
+    /// Handle an acknowledgement; returns `true` if it confirmed the in-flight probe.
+    pub fn on_packet_acked(&mut self, packet_number: u64) -> bool {
+        let Some(probe) = self.take_probe(packet_number) else {
+            return false;
+        };
+        self.current = self.current.max(probe.size);
+        self.probe_losses = 0;
+        debug!(mtu = self.current, "path MTU probe acknowledged");
+        true
+    }
+
+    /// Handle a loss; returns `true` if it was the in-flight probe.
+    pub fn on_packet_lost(&mut self, packet_number: u64) -> bool {
+        let Some(probe) = self.take_probe(packet_number) else {
+            return false;
+        };
+        self.probe_losses += 1;
+        if self.probe_losses >= self.config.max_probes {
+            self.ceiling = probe.size - 1;
+            self.probe_losses = 0;
+            debug!(
+                size = probe.size,
+                ceiling = self.ceiling,
+                "path MTU probe size abandoned"
+            );
+        }
+        true
+    }
+
+    /// Fall back to the base size after a suspected black hole and restart the search.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config.clone());
+    }
+
+    /// Build a PADDING frame that brings a packet with `overhead` bytes of header and
+    /// authentication tag up to `probe_size`.
+    #[must_use]
+    pub fn probe_frame(probe_size: usize, overhead: usize) -> Frame {
+        Frame::padding(probe_size.saturating_sub(overhead))
+    }
+
+    fn take_probe(&mut self, packet_number: u64) -> Option<InFlightProbe> {
+        self.probe
+            .take_if(|probe| probe.packet_number == packet_number)
+    }
+}
+
+impl Default for PmtuDiscovery {
+    fn default() -> Self {
+        Self::new(PmtuConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::FrameType;
+
+    /// Drive probes over a link that drops anything larger than `threshold`.
+    fn run_search(pmtu: &mut PmtuDiscovery, threshold: usize) -> u64 {
+        let mut packet_number = 0;
+        while let Some(size) = pmtu.next_probe_size() {
+            packet_number += 1;
+            pmtu.on_probe_sent(packet_number, size);
+            if size <= threshold {
+                assert!(pmtu.on_packet_acked(packet_number));
+            } else {
+                assert!(pmtu.on_packet_lost(packet_number));
+            }
+        }
+        packet_number
+    }
+
+    #[test]
+    fn search_converges_below_link_threshold() {
+        let mut pmtu = PmtuDiscovery::new(PmtuConfig {
+            max_mtu: 9000,
+            ..PmtuConfig::default()
+        });
+        let probes = run_search(&mut pmtu, 1400);
+        assert!(pmtu.is_complete());
+        assert!(pmtu.current_mtu() <= 1400);
+        assert!(pmtu.current_mtu() > 1400 - SEARCH_GRANULARITY);
+        assert!(probes < 64);
+    }
+
+    #[test]
+    fn single_probe_loss_retries_same_size() {
+        let mut pmtu = PmtuDiscovery::default();
+        let size = pmtu.next_probe_size().expect("probe");
+        pmtu.on_probe_sent(1, size);
+        assert!(!pmtu.on_packet_lost(99));
+        assert!(pmtu.on_packet_lost(1));
+        assert_eq!(pmtu.next_probe_size(), Some(size));
+        assert_eq!(pmtu.current_mtu(), BASE_PLPMTU);
+    }
+
+    #[test]
+    fn black_hole_link_keeps_base_mtu() {
+        let mut pmtu = PmtuDiscovery::default();
+        run_search(&mut pmtu, BASE_PLPMTU);
+        assert_eq!(pmtu.current_mtu(), BASE_PLPMTU);
+
+        let frame = PmtuDiscovery::probe_frame(1300, 40);
+        assert_eq!(frame.frame_type(), FrameType::Padding);
+        assert_eq!(frame.payload().len(), 1260);
+    }
+}
@@ -0,0 +1,293 @@
+//! Token-bucket pacing driven by [`CongestionControl::pacing_rate`].
+//!
+//! The congestion window bounds how much may be in flight, not how quickly it leaves. A
+//! [`Pacer`] spreads sends over time instead: tokens (bytes) accrue at the pacing rate up to
+//! a burst allowance, each send spends tokens, and when the bucket runs dry
+//! [`Pacer::delay_until_next_send`] reports how long to wait. Drivers arm a timer for that
+//! delay rather than spinning.
+//!
+//! [`CongestionControl::pacing_rate`]: super::CongestionControl::pacing_rate
+
+use std::time::{Duration, SystemTime};
+
+use crate::transport::congestion::duration_to_secs;
+
+/// Default number of full-size packets allowed in a single burst.
+pub const DEFAULT_BURST_PACKETS: usize = 10;
+/// Default burst window: a burst may also carry this much time's worth of the rate.
+pub const DEFAULT_BURST_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Configuration for a [`Pacer`].
+#[derive(Debug, Clone)]
+pub struct PacerConfig {
+    /// Packets of `max_packet_size` that may be sent back to back.
+    pub burst_packets: usize,
+    /// Time's worth of the pacing rate that may be sent back to back.
+    pub burst_interval: Duration,
+    /// Packet size used to turn `burst_packets` into bytes.
+    pub max_packet_size: usize,
+}
+
+impl Default for PacerConfig {
+    fn default() -> Self {
+        Self {
+            burst_packets: DEFAULT_BURST_PACKETS,
+            burst_interval: DEFAULT_BURST_INTERVAL,
+            max_packet_size: 1200,
+        }
+    }
+}
+
+/// Token bucket spacing sends according to a pacing rate.
+///
+/// The bucket holds up to [`Pacer::burst_capacity`] bytes, the larger of
+/// `burst_packets` full packets and `burst_interval` worth of the rate, and starts full.
+/// Until [`Pacer::set_rate`] is called the pacer imposes no delay.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    config: PacerConfig,
+    /// Bytes per second; `None` leaves sends unpaced.
+    rate: Option<f64>,
+    /// Available bytes; negative when a send overdrew the bucket.
+    tokens: f64,
+    last_update: Option<SystemTime>,
+}
+
+impl Pacer {
+    /// Create an unpaced pacer with a full bucket.
+    #[must_use]
+    pub fn new(config: PacerConfig) -> Self {
+        let mut pacer = Self {
+            config,
+            rate: None,
+            tokens: 0.0,
+            last_update: None,
+        };
+        pacer.tokens = pacer.capacity();
+        pacer
+    }
+
+    /// Current pacing rate in bytes per second, if one has been set.
+    #[must_use]
+    pub fn rate(&self) -> Option<f64> {
+        self.rate
+    }
+
+    /// Bytes that may be sent back to back at the current rate.
+    #[must_use]
+    pub fn burst_capacity(&self) -> usize {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        // saturating, never negative
+        let capacity = self.capacity() as usize;
+        capacity
+    }
+
+    /// Bytes currently available to send without delay at `now`.
+    #[must_use]
+    pub fn available(&self, now: SystemTime) -> f64 {
+        self.tokens_at(now)
+    }
+
+    /// Adopt a new pacing rate (bytes per second), typically
+    /// [`CongestionControl::pacing_rate`](super::CongestionControl::pacing_rate).
+    ///
+    /// Tokens accrued before `now` are credited at the previous rate. Non-positive or
+    /// non-finite rates disable pacing.
+    pub fn set_rate(&mut self, rate: f64, now: SystemTime) {
+        self.refill(now);
+        self.rate = (rate.is_finite() && rate > 0.0).then_some(rate);
+        self.tokens = self.tokens.min(self.capacity());
+    }
+
+    /// Record that `bytes` were sent at `now`.
+    ///
+    /// Sending while [`Pacer::delay_until_next_send`] reports a delay overdraws the
+    /// bucket; the debt is repaid before later sends are released.
+    pub fn on_packet_sent(&mut self, bytes: usize, now: SystemTime) {
+        self.refill(now);
+        if self.rate.is_some() {
+            self.tokens -= as_f64(bytes);
+        }
+    }
+
+    /// How long to wait before sending `bytes` at `now`, or `None` to send immediately.
+    ///
+    /// Packets larger than the burst capacity only wait for a full bucket.
+    #[must_use]
+    pub fn delay_until_next_send(&self, bytes: usize, now: SystemTime) -> Option<Duration> {
+        let rate = self.rate?;
+        let needed = as_f64(bytes).min(self.capacity());
+        let deficit = needed - self.tokens_at(now);
+        if deficit <= 0.0 {
+            return None;
+        }
+        // Round up so the caller does not wake a hair early and find the bucket short.
+        Some(Duration::from_secs_f64(deficit / rate) + Duration::from_nanos(1))
+    }
+
+    /// Earliest time `bytes` may be sent, or `None` if they may be sent at `now`.
+    #[must_use]
+    pub fn next_send_time(&self, bytes: usize, now: SystemTime) -> Option<SystemTime> {
+        self.delay_until_next_send(bytes, now)
+            .map(|delay| now + delay)
+    }
+
+    fn capacity(&self) -> f64 {
+        let packets = as_f64(self.config.burst_packets * self.config.max_packet_size);
+        let interval = self.rate.map_or(0.0, |rate| {
+            rate * duration_to_secs(self.config.burst_interval)
+        });
+        packets.max(interval)
+    }
+
+    fn tokens_at(&self, now: SystemTime) -> f64 {
+        let (Some(rate), Some(last)) = (self.rate, self.last_update) else {
+            return self.tokens;
+        };
+        let elapsed = duration_to_secs(now.duration_since(last).unwrap_or_default());
+        (self.tokens + elapsed * rate).min(self.capacity())
+    }
+
+    fn refill(&mut self, now: SystemTime) {
+        self.tokens = self.tokens_at(now);
+        if self.last_update.is_none_or(|last| now > last) {
+            self.last_update = Some(now);
+        }
+    }
+}
+
+#[allow(clippy::cast_precision_loss)] // byte counts are far below 2^52
+fn as_f64(bytes: usize) -> f64 {
+    bytes as f64
+}
+
+impl Default for Pacer {
+    fn default() -> Self {
+        Self::new(PacerConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    fn start() -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_000)
+    }
+
+    #[test]
+    fn unpaced_until_rate_is_set() {
+        let mut pacer = Pacer::default();
+        for _ in 0..100 {
+            assert_eq!(pacer.delay_until_next_send(1200, start()), None);
+            pacer.on_packet_sent(1200, start());
+        }
+        assert_eq!(pacer.rate(), None);
+    }
+
+    #[test]
+    fn tokens_accumulate_at_the_pacing_rate() {
+        let mut pacer = Pacer::default();
+        // 1.2 MB/s: one 1200-byte packet per millisecond.
+        pacer.set_rate(1_200_000.0, start());
+        for _ in 0..10 {
+            assert_eq!(pacer.delay_until_next_send(1200, start()), None);
+            pacer.on_packet_sent(1200, start());
+        }
+        let delay = pacer.delay_until_next_send(1200, start()).unwrap();
+        assert!(delay >= Duration::from_millis(1) && delay < Duration::from_micros(1_010));
+
+        let later = start() + Duration::from_micros(500);
+        assert!(pacer.available(later) > 590.0 && pacer.available(later) < 610.0);
+        let delay = pacer.delay_until_next_send(1200, later).unwrap();
+        assert!(delay >= Duration::from_micros(500) && delay < Duration::from_micros(510));
+        assert_eq!(
+            pacer.delay_until_next_send(1200, start() + Duration::from_micros(1_001)),
+            None
+        );
+    }
+
+    #[test]
+    fn burst_is_capped_after_idle_periods() {
+        let mut pacer = Pacer::default();
+        pacer.set_rate(1_200_000.0, start());
+        assert_eq!(pacer.burst_capacity(), 12_000);
+
+        // A long idle period refills the bucket only up to the burst allowance.
+        let idle = start() + Duration::from_secs(10);
+        assert!((pacer.available(idle) - 12_000.0).abs() < f64::EPSILON);
+        let mut sent = 0;
+        while pacer.delay_until_next_send(1200, idle).is_none() {
+            pacer.on_packet_sent(1200, idle);
+            sent += 1;
+        }
+        assert_eq!(sent, 10);
+
+        // At high rates the burst widens to the configured interval's worth.
+        pacer.set_rate(100_000_000.0, idle);
+        assert_eq!(pacer.burst_capacity(), 200_000);
+
+        // Oversized packets wait only for a full bucket rather than forever.
+        let mut pacer = Pacer::default();
+        pacer.set_rate(1_200_000.0, start());
+        assert_eq!(pacer.delay_until_next_send(64 * 1024, start()), None);
+    }
+
+    #[test]
+    fn rate_changes_apply_mid_stream() {
+        let mut pacer = Pacer::default();
+        pacer.set_rate(1_200_000.0, start());
+        for _ in 0..10 {
+            pacer.on_packet_sent(1200, start());
+        }
+
+        // Half a millisecond accrues 600 bytes at the old rate before the switch.
+        let switch = start() + Duration::from_micros(500);
+        pacer.set_rate(120_000.0, switch);
+        assert!((pacer.available(switch) - 600.0).abs() < 1.0);
+        let delay = pacer.delay_until_next_send(1200, switch).unwrap();
+        assert!(delay >= Duration::from_millis(5) && delay < Duration::from_micros(5_010));
+
+        // Speeding back up shortens the wait for the same deficit.
+        pacer.set_rate(2_400_000.0, switch);
+        let delay = pacer.delay_until_next_send(1200, switch).unwrap();
+        assert!(delay >= Duration::from_micros(250) && delay < Duration::from_micros(260));
+
+        // Shrinking the rate clamps a full bucket to the smaller allowance.
+        let mut pacer = Pacer::default();
+        pacer.set_rate(100_000_000.0, start());
+        assert!((pacer.available(start()) - 12_000.0).abs() < f64::EPSILON);
+        let idle = start() + Duration::from_secs(1);
+        pacer.set_rate(1_000.0, idle);
+        assert!((pacer.available(idle) - 12_000.0).abs() < f64::EPSILON);
+
+        // Disabling pacing releases sends immediately.
+        pacer.set_rate(0.0, idle);
+        assert_eq!(pacer.delay_until_next_send(1_000_000, idle), None);
+    }
+
+    #[test]
+    fn sends_at_next_send_time_are_spaced_at_the_rate() {
+        let mut pacer = Pacer::default();
+        // 600 kB/s: one 1200-byte packet every 2 ms once the burst is spent.
+        pacer.set_rate(600_000.0, start());
+        let mut now = start();
+        let mut sent = Vec::new();
+        while sent.len() < 40 {
+            if let Some(next) = pacer.next_send_time(1200, now) {
+                assert!(next > now);
+                now = next;
+                continue;
+            }
+            pacer.on_packet_sent(1200, now);
+            sent.push(now);
+        }
+
+        assert!(sent[..10].iter().all(|at| *at == start()));
+        for pair in sent[10..].windows(2) {
+            let gap = pair[1].duration_since(pair[0]).unwrap();
+            assert!(gap >= Duration::from_millis(2) && gap < Duration::from_micros(2_001));
+        }
+    }
+}
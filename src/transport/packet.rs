@@ -4,17 +4,51 @@ use std::convert::TryInto;
 use std::fmt;
 
 use super::ack::{AckError, AckFrame};
-use super::stream::StreamId;
+use super::stream::{SendChunk, StreamId, StreamKind};
 
-/// Size of an encoded packet header in bytes.
-pub const HEADER_SIZE: usize = 32;
+/// Packet wire format version.
+///
+/// Version 1 used a fixed 32-byte header with a `u64` connection ID; version 2 prefixes the
+/// header with a 1-byte connection ID length followed by up to [`MAX_CONN_ID_LEN`] bytes.
+/// Version 3 drops the 12-byte nonce; receivers rebuild it from the packet number.
+/// Version 4 truncates the packet number to 1-4 bytes at the end of the header, its length
+/// carried in the protected flags byte.
+/// Version 5 encodes `STREAM_DATA`, `STREAM_OPEN` and `STREAM_FIN` fields as varints.
+///
+/// Endpoints advertise it in the `wire_version` transport parameter, and a handshake with a
+/// peer on another version fails with
+/// [`HandshakeError::WireVersionMismatch`](super::HandshakeError::WireVersionMismatch).
+pub const WIRE_VERSION: u8 = 5;
+
+/// Maximum length of a connection ID in bytes.
+pub const MAX_CONN_ID_LEN: usize = 16;
+
+/// Maximum length of an encoded packet number in bytes.
+pub const MAX_PACKET_NUMBER_LEN: usize = 4;
+
+/// Size of the header fields following the connection ID, excluding the packet number
+/// (spin, payload length, flags).
+const FIXED_FIELDS_SIZE: usize = 1 + 2 + 1;
+
+/// Spin bit within the byte following the connection ID; its other bits are reserved.
+const SPIN_BIT: u8 = 1 << 0;
+
+/// Flag bits holding the packet number length minus one.
+const PACKET_NUMBER_LEN_MASK: u8 = 0b1100_0000;
+const PACKET_NUMBER_LEN_SHIFT: u32 = PACKET_NUMBER_LEN_MASK.trailing_zeros();
+
+/// Size of an encoded header with a zero-length connection ID and a 1-byte packet number.
+pub const MIN_HEADER_SIZE: usize = 1 + FIXED_FIELDS_SIZE + 1;
+
+/// Size of an encoded header with a maximum-length connection ID and packet number.
+pub const MAX_HEADER_SIZE: usize = 1 + FIXED_FIELDS_SIZE + MAX_PACKET_NUMBER_LEN + MAX_CONN_ID_LEN;
 
 // Size of the AEAD authentication tag in bytes.
 // pub const AUTH_TAG_SIZE: usize = 16;
-/// Size of the nonce carried in the header (12 bytes for ChaCha20/AES).
-pub const NONCE_SIZE: usize = 12;
 
 /// Flags describing packet semantics.
+///
+/// Bits 6 and 7 are reserved: the encoded header stores the packet number length there.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub struct PacketFlags(u8);
 
@@ -29,6 +63,8 @@ impl PacketFlags {
     pub const KEY_PHASE: u8 = 1 << 3;
     /// Packet contains probe/keepalive data.
     pub const PROBE: u8 = 1 << 4;
+    /// Packet carries 0-RTT early data (discard if the handshake fails).
+    pub const EARLY_DATA: u8 = 1 << 5;
 
     /// Create a new flag set from raw bits.
     #[must_use]
@@ -63,11 +99,30 @@ impl PacketFlags {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PacketError {
     /// Input buffer does not contain enough bytes.
-    BufferTooSmall { expected: usize, actual: usize },
+    BufferTooSmall {
+        /// Bytes required.
+        expected: usize,
+        /// Bytes available.
+        actual: usize,
+    },
     /// Payload length exceeds self-imposed limits.
-    PayloadTooLarge { len: usize, max: usize },
+    PayloadTooLarge {
+        /// Payload length.
+        len: usize,
+        /// Maximum allowed length.
+        max: usize,
+    },
     /// Reserved bits set unexpectedly.
     ReservedBitsSet(u8),
+    /// Connection ID longer than [`MAX_CONN_ID_LEN`].
+    ConnectionIdTooLong {
+        /// Connection ID length.
+        len: usize,
+        /// Maximum allowed length.
+        max: usize,
+    },
+    /// Frame type byte not defined by this wire version.
+    UnknownFrameType(u8),
 }
 
 impl fmt::Display for PacketError {
@@ -82,96 +137,392 @@ impl fmt::Display for PacketError {
             Self::ReservedBitsSet(bits) => {
                 write!(f, "reserved bits set in packet flags: {bits:#010b}")
             }
+            Self::ConnectionIdTooLong { len, max } => {
+                write!(f, "connection id too long: {len} bytes (max {max})")
+            }
+            Self::UnknownFrameType(byte) => write!(f, "unknown frame type {byte:#04x}"),
         }
     }
 }
 
 impl std::error::Error for PacketError {}
 
+/// Errors produced when decoding the payload of a non-ACK frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame is of a different type than the decoder expects.
+    UnexpectedFrameType {
+        /// Type the decoder handles.
+        expected: FrameType,
+        /// Type of the frame.
+        actual: FrameType,
+    },
+    /// The payload ended in the middle of a field.
+    Truncated,
+    /// Bytes left over after the last field.
+    TrailingBytes(usize),
+    /// The declared data length differs from the data present.
+    LengthMismatch {
+        /// Length declared in the frame.
+        declared: u64,
+        /// Bytes actually following the header.
+        actual: usize,
+    },
+    /// A field holds a value outside its range.
+    InvalidField(&'static str),
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnexpectedFrameType { expected, actual } => {
+                write!(f, "expected a {expected:?} frame, got {actual:?}")
+            }
+            Self::Truncated => write!(f, "frame payload truncated"),
+            Self::TrailingBytes(len) => write!(f, "{len} trailing bytes after frame fields"),
+            Self::LengthMismatch { declared, actual } => write!(
+                f,
+                "frame declares {declared} data bytes but carries {actual}"
+            ),
+            Self::InvalidField(field) => write!(f, "invalid {field} in frame"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Variable-length connection identifier (0 to [`MAX_CONN_ID_LEN`] bytes).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ConnectionId {
+    len: u8,
+    bytes: [u8; MAX_CONN_ID_LEN],
+}
+
+impl ConnectionId {
+    /// Create a connection ID from raw bytes.
+    pub fn new(bytes: &[u8]) -> Result<Self, PacketError> {
+        let too_long = PacketError::ConnectionIdTooLong {
+            len: bytes.len(),
+            max: MAX_CONN_ID_LEN,
+        };
+        if bytes.len() > MAX_CONN_ID_LEN {
+            return Err(too_long);
+        }
+        let mut id = Self {
+            len: u8::try_from(bytes.len()).map_err(|_| too_long)?,
+            ..Self::default()
+        };
+        id.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(id)
+    }
+
+    /// Create an 8-byte connection ID from a `u64` (wire version 1 behaviour).
+    #[must_use]
+    pub fn from_u64(value: u64) -> Self {
+        let mut id = Self {
+            len: 8,
+            ..Self::default()
+        };
+        id.bytes[..8].copy_from_slice(&value.to_le_bytes());
+        id
+    }
+
+    /// Interpret an 8-byte connection ID as a `u64`.
+    #[must_use]
+    pub fn to_u64(&self) -> Option<u64> {
+        self.as_bytes().try_into().ok().map(u64::from_le_bytes)
+    }
+
+    /// Borrow the connection ID bytes.
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len()]
+    }
+
+    /// Length of the connection ID in bytes.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Check whether the connection ID is empty.
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl From<u64> for ConnectionId {
+    fn from(value: u64) -> Self {
+        Self::from_u64(value)
+    }
+}
+
+/// Bytes needed to encode `packet_number` so a receiver that has seen `largest_acked` can
+/// reconstruct it, per RFC 9000 Appendix A.2.
+///
+/// Capped at [`MAX_PACKET_NUMBER_LEN`]; with more than 2^31 packets unacknowledged the
+/// receiver may reconstruct the wrong number. Without an acknowledgement the distance is
+/// counted from zero.
+#[must_use]
+pub const fn encoded_packet_number_len(packet_number: u64, largest_acked: Option<u64>) -> usize {
+    let unacked = match largest_acked {
+        Some(largest) => packet_number.saturating_sub(largest),
+        None => packet_number.saturating_add(1),
+    };
+    // Twice the distance must fit, so the receiver's window is centred on it.
+    let bits = u64::BITS - unacked.leading_zeros() + 1;
+    let len = bits.div_ceil(8) as usize;
+    if len > MAX_PACKET_NUMBER_LEN {
+        MAX_PACKET_NUMBER_LEN
+    } else {
+        len
+    }
+}
+
+/// Reconstruct a packet number from its low `len` bytes, choosing the candidate closest to
+/// the one after `largest_received`, per RFC 9000 Appendix A.3.
+#[must_use]
+pub const fn decode_packet_number(
+    largest_received: Option<u64>,
+    truncated: u64,
+    len: usize,
+) -> u64 {
+    let expected = match largest_received {
+        Some(largest) => largest.wrapping_add(1),
+        None => 0,
+    };
+    let window = 1u64 << (len * 8);
+    let half_window = window / 2;
+    let candidate = (expected & !(window - 1)) | (truncated & (window - 1));
+    if candidate.saturating_add(half_window) <= expected && candidate <= u64::MAX - window {
+        candidate + window
+    } else if candidate > expected.saturating_add(half_window) && candidate >= window {
+        candidate - window
+    } else {
+        candidate
+    }
+}
+
 /// High-level packet header used by the transport.
+///
+/// Wire layout: `[cid_len (1)][cid (cid_len)][spin (1)][payload_len (2)][flags (1)]`
+/// `[packet_number (1-4)]`.
+///
+/// The packet number is truncated to its low bytes (little-endian); the top two bits of the
+/// flags byte hold its length minus one. Header protection masks the flags byte and the
+/// packet number, so the length is only readable once the mask is removed. Only the lowest
+/// bit of the spin byte is defined; it stays readable so on-path observers can measure the
+/// round-trip time.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PacketHeader {
-    conn_id: u64,
+    conn_id: ConnectionId,
     packet_number: u64,
+    packet_number_len: usize,
     flags: PacketFlags,
     payload_len: u16,
-    reserved: u8,
-    nonce: [u8; NONCE_SIZE],
+    spin: bool,
 }
 
 impl PacketHeader {
-    /// Create a new packet header.
+    /// Create a new packet header with an 8-byte connection ID derived from `conn_id`.
     #[must_use]
     pub fn new(conn_id: u64, packet_number: u64, payload_len: u16, flags: PacketFlags) -> Self {
+        Self::with_connection_id(
+            ConnectionId::from_u64(conn_id),
+            packet_number,
+            payload_len,
+            flags,
+        )
+    }
+
+    /// Create a new packet header with an arbitrary connection ID.
+    ///
+    /// The packet number is encoded in [`MAX_PACKET_NUMBER_LEN`] bytes until
+    /// [`with_packet_number_len`](Self::with_packet_number_len) says otherwise.
+    #[must_use]
+    pub fn with_connection_id(
+        conn_id: ConnectionId,
+        packet_number: u64,
+        payload_len: u16,
+        flags: PacketFlags,
+    ) -> Self {
         Self {
             conn_id,
             packet_number,
+            packet_number_len: MAX_PACKET_NUMBER_LEN,
             flags,
             payload_len,
-            reserved: 0,
-            nonce: [0u8; NONCE_SIZE],
+            spin: false,
         }
     }
 
-    /// Set the nonce associated with the packet.
-    pub fn set_nonce(&mut self, nonce: [u8; NONCE_SIZE]) {
-        self.nonce = nonce;
+    /// Encode the packet number in `len` bytes, clamped to 1..=[`MAX_PACKET_NUMBER_LEN`];
+    /// normally [`encoded_packet_number_len`].
+    #[must_use]
+    pub const fn with_packet_number_len(mut self, len: usize) -> Self {
+        self.packet_number_len = if len == 0 {
+            1
+        } else if len > MAX_PACKET_NUMBER_LEN {
+            MAX_PACKET_NUMBER_LEN
+        } else {
+            len
+        };
+        self
+    }
+
+    /// Encoded size of this header in bytes.
+    #[must_use]
+    #[allow(clippy::len_without_is_empty)]
+    pub const fn len(&self) -> usize {
+        Self::flags_offset(self.conn_id.len()) + 1 + self.packet_number_len
+    }
+
+    /// Largest encoded size of a header with a `cid_len`-byte ID.
+    #[must_use]
+    pub(crate) const fn max_len(cid_len: usize) -> usize {
+        Self::flags_offset(cid_len) + 1 + MAX_PACKET_NUMBER_LEN
     }
 
-    /// Encode the header into the provided buffer (must be at least 32 bytes).
+    /// Offset of the protected flags byte, followed by the packet number, within an encoded
+    /// header with a `cid_len`-byte ID.
+    #[must_use]
+    pub(crate) const fn flags_offset(cid_len: usize) -> usize {
+        1 + cid_len + 3
+    }
+
+    /// Packet number length encoded in an unprotected flags byte.
+    #[must_use]
+    pub(crate) const fn packet_number_len_from_flags(byte: u8) -> usize {
+        (byte >> PACKET_NUMBER_LEN_SHIFT) as usize + 1
+    }
+
+    /// Encode the header into the provided buffer (must be at least [`len`](Self::len) bytes).
     pub fn encode(&self, out: &mut [u8]) -> Result<(), PacketError> {
-        if out.len() < HEADER_SIZE {
+        let len = self.len();
+        if out.len() < len {
             return Err(PacketError::BufferTooSmall {
-                expected: HEADER_SIZE,
+                expected: len,
                 actual: out.len(),
             });
         }
+        let flags = self.flags.bits();
+        if flags >> PACKET_NUMBER_LEN_SHIFT != 0 {
+            return Err(PacketError::ReservedBitsSet(flags));
+        }
 
-        out.fill(0);
-        out[0..8].copy_from_slice(&self.conn_id.to_le_bytes());
-        out[8..16].copy_from_slice(&self.packet_number.to_le_bytes());
-        out[16] = self.flags.bits();
-        out[17] = self.reserved;
-        out[18..20].copy_from_slice(&self.payload_len.to_le_bytes());
-        out[20..32].copy_from_slice(&self.nonce);
+        let out = &mut out[..len];
+        let cid_len = self.conn_id.len();
+        out[0] = self.conn_id.len;
+        out[1..=cid_len].copy_from_slice(self.conn_id.as_bytes());
+
+        let fields = &mut out[1 + cid_len..];
+        fields[0] = if self.spin { SPIN_BIT } else { 0 };
+        fields[1..3].copy_from_slice(&self.payload_len.to_le_bytes());
+        let pn_len = self.packet_number_len;
+        #[allow(clippy::cast_possible_truncation)] // at most 3
+        let len_bits = ((pn_len - 1) as u8) << PACKET_NUMBER_LEN_SHIFT;
+        fields[3] = flags | len_bits;
+        fields[4..4 + pn_len].copy_from_slice(&self.packet_number.to_le_bytes()[..pn_len]);
         Ok(())
     }
 
-    /// Decode a packet header from raw bytes.
-    #[must_use]
+    /// Decode a packet header from raw bytes, reconstructing the packet number as the first
+    /// packet of a connection.
     pub fn decode(buf: &[u8]) -> Result<Self, PacketError> {
-        if buf.len() < HEADER_SIZE {
+        Self::decode_after(buf, None)
+    }
+
+    /// Decode a packet header, reconstructing the packet number relative to the largest one
+    /// received so far.
+    pub fn decode_after(buf: &[u8], largest_received: Option<u64>) -> Result<Self, PacketError> {
+        let cid_len = usize::from(Self::peek_conn_id_len(buf)?);
+        let flags_offset = Self::flags_offset(cid_len);
+        let &flags_byte = buf.get(flags_offset).ok_or(PacketError::BufferTooSmall {
+            expected: flags_offset + 2,
+            actual: buf.len(),
+        })?;
+        let pn_len = Self::packet_number_len_from_flags(flags_byte);
+        let len = flags_offset + 1 + pn_len;
+        if buf.len() < len {
             return Err(PacketError::BufferTooSmall {
-                expected: HEADER_SIZE,
+                expected: len,
                 actual: buf.len(),
             });
         }
+        let conn_id = ConnectionId::new(&buf[1..=cid_len])?;
 
-        let flags = PacketFlags::from_bits(buf[16]);
-        let reserved = buf[17];
-        if reserved != 0 {
-            return Err(PacketError::ReservedBitsSet(reserved));
+        let fields = &buf[1 + cid_len..len];
+        let spin = fields[0];
+        if spin & !SPIN_BIT != 0 {
+            return Err(PacketError::ReservedBitsSet(spin));
         }
-
-        let payload_len = u16::from_le_bytes([buf[18], buf[19]]);
-
-        let mut nonce = [0u8; NONCE_SIZE];
-        nonce.copy_from_slice(&buf[20..32]);
+        let payload_len = u16::from_le_bytes([fields[1], fields[2]]);
+        let mut truncated = [0u8; 8];
+        truncated[..pn_len].copy_from_slice(&fields[4..]);
 
         Ok(Self {
-            conn_id: u64::from_le_bytes(buf[0..8].try_into().unwrap()),
-            packet_number: u64::from_le_bytes(buf[8..16].try_into().unwrap()),
-            flags,
+            conn_id,
+            packet_number: decode_packet_number(
+                largest_received,
+                u64::from_le_bytes(truncated),
+                pn_len,
+            ),
+            packet_number_len: pn_len,
+            flags: PacketFlags::from_bits(flags_byte & !PACKET_NUMBER_LEN_MASK),
             payload_len,
-            reserved,
-            nonce,
+            spin: spin & SPIN_BIT != 0,
         })
     }
 
+    /// Read the connection ID length prefix without decoding the rest of the header.
+    pub fn peek_conn_id_len(buf: &[u8]) -> Result<u8, PacketError> {
+        let &cid_len = buf.first().ok_or(PacketError::BufferTooSmall {
+            expected: MIN_HEADER_SIZE,
+            actual: 0,
+        })?;
+        if usize::from(cid_len) > MAX_CONN_ID_LEN {
+            return Err(PacketError::ConnectionIdTooLong {
+                len: usize::from(cid_len),
+                max: MAX_CONN_ID_LEN,
+            });
+        }
+        Ok(cid_len)
+    }
+
+    /// Read the spin bit of an encoded header, protected or not.
+    pub fn peek_spin(buf: &[u8]) -> Result<bool, PacketError> {
+        let offset = 1 + usize::from(Self::peek_conn_id_len(buf)?);
+        let &byte = buf.get(offset).ok_or(PacketError::BufferTooSmall {
+            expected: offset + 1,
+            actual: buf.len(),
+        })?;
+        Ok(byte & SPIN_BIT != 0)
+    }
+
+    /// Set the spin bit.
+    #[must_use]
+    pub const fn with_spin(mut self, spin: bool) -> Self {
+        self.spin = spin;
+        self
+    }
+
+    /// Spin bit accessor.
+    #[must_use]
+    pub const fn spin(&self) -> bool {
+        self.spin
+    }
+
     /// Connection identifier accessor.
     #[must_use]
-    pub const fn conn_id(&self) -> u64 {
-        self.conn_id
+    pub const fn connection_id(&self) -> &ConnectionId {
+        &self.conn_id
+    }
+
+    /// Connection identifier as a `u64`, for 8-byte IDs created via [`new`](Self::new).
+    #[must_use]
+    pub fn conn_id(&self) -> Option<u64> {
+        self.conn_id.to_u64()
     }
 
     /// Packet number accessor.
@@ -180,6 +531,12 @@ impl PacketHeader {
         self.packet_number
     }
 
+    /// Number of bytes the packet number occupies on the wire.
+    #[must_use]
+    pub const fn packet_number_len(&self) -> usize {
+        self.packet_number_len
+    }
+
     /// Payload length accessor.
     #[must_use]
     pub const fn payload_len(&self) -> u16 {
@@ -191,12 +548,6 @@ impl PacketHeader {
     pub const fn flags(&self) -> PacketFlags {
         self.flags
     }
-
-    /// Nonce accessor.
-    #[must_use]
-    pub const fn nonce(&self) -> &[u8; NONCE_SIZE] {
-        &self.nonce
-    }
 }
 
 /// Enumerates available frame kinds.
@@ -210,6 +561,8 @@ pub enum FrameType {
     StreamFin,
     /// Unreliable datagram payload.
     Datagram,
+    /// Fragment of a datagram too large for one packet.
+    DatagramFragment,
     /// Acknowledgement data.
     Ack,
     /// Handshake/crypto data.
@@ -220,6 +573,159 @@ pub enum FrameType {
     StreamMaxData,
     /// Connection-level `MAX_DATA` credit.
     ConnectionMaxData,
+    /// Receiver asks the peer to stop sending on a stream (`STOP_SENDING` equivalent).
+    StopSending,
+    /// Zero bytes used to pad packets (e.g. path MTU probes).
+    Padding,
+    /// Sender wants to open more streams than the concurrent limit allows
+    /// (`STREAMS_BLOCKED` equivalent).
+    StreamsBlocked,
+    /// Sender abandoned stream data below an offset; the receiver skips to it.
+    StreamExpired,
+    /// Sender is closing the connection, with an error code and reason
+    /// (`CONNECTION_CLOSE` equivalent).
+    ConnectionClose,
+    /// Empty ack-eliciting frame keeping an idle connection alive (`PING` equivalent).
+    Ping,
+}
+
+impl FrameType {
+    const fn to_byte(self) -> u8 {
+        match self {
+            Self::StreamOpen => 0x01,
+            Self::StreamData => 0x02,
+            Self::StreamFin => 0x03,
+            Self::Datagram => 0x04,
+            Self::DatagramFragment => 0x05,
+            Self::Ack => 0x06,
+            Self::Crypto => 0x07,
+            Self::Control => 0x08,
+            Self::StreamMaxData => 0x09,
+            Self::ConnectionMaxData => 0x0A,
+            Self::StopSending => 0x0B,
+            Self::Padding => 0x0C,
+            Self::StreamsBlocked => 0x0D,
+            Self::StreamExpired => 0x0E,
+            Self::ConnectionClose => 0x0F,
+            Self::Ping => 0x10,
+        }
+    }
+
+    const fn from_byte(value: u8) -> Option<Self> {
+        Some(match value {
+            0x01 => Self::StreamOpen,
+            0x02 => Self::StreamData,
+            0x03 => Self::StreamFin,
+            0x04 => Self::Datagram,
+            0x05 => Self::DatagramFragment,
+            0x06 => Self::Ack,
+            0x07 => Self::Crypto,
+            0x08 => Self::Control,
+            0x09 => Self::StreamMaxData,
+            0x0A => Self::ConnectionMaxData,
+            0x0B => Self::StopSending,
+            0x0C => Self::Padding,
+            0x0D => Self::StreamsBlocked,
+            0x0E => Self::StreamExpired,
+            0x0F => Self::ConnectionClose,
+            0x10 => Self::Ping,
+            _ => return None,
+        })
+    }
+}
+
+/// Bytes a frame adds in front of its payload: type byte and `u16` length.
+pub const FRAME_HEADER_LEN: usize = 1 + 2;
+
+/// Largest value a variable-length integer can hold (2^62 - 1).
+pub const MAX_VARINT: u64 = (1 << 62) - 1;
+
+/// Most bytes a `STREAM_DATA` frame payload adds in front of the data: stream id and
+/// offset varints (8 bytes each at most), a length varint (4 bytes for any length that
+/// fits a frame) and the FIN byte.
+pub const STREAM_DATA_OVERHEAD: usize = 8 + 8 + 4 + 1;
+
+/// Most data a `STREAM_DATA` frame carries whatever its stream id and offset, keeping the
+/// frame payload within its `u16` length.
+pub const MAX_STREAM_DATA_LEN: usize = u16::MAX as usize - STREAM_DATA_OVERHEAD;
+
+/// Bytes [`put_varint`] uses for `value`: 1, 2, 4 or 8, as in RFC 9000 Section 16.
+const fn varint_len(value: u64) -> usize {
+    if value < 1 << 6 {
+        1
+    } else if value < 1 << 14 {
+        2
+    } else if value < 1 << 30 {
+        4
+    } else {
+        8
+    }
+}
+
+/// Append `value` as a variable-length integer: big-endian, with the top two bits of the
+/// first byte giving the length.
+///
+/// `value` must not exceed [`MAX_VARINT`]: stream ids are checked when a [`StreamId`] is
+/// built and stream offsets when data is queued, so no caller can reach it.
+fn put_varint(out: &mut Vec<u8>, value: u64) {
+    debug_assert!(value <= MAX_VARINT, "varint value {value} out of range");
+    let len = varint_len(value);
+    let tag = u64::from(len.trailing_zeros()) << (len * 8 - 2);
+    out.extend_from_slice(&(value | tag).to_be_bytes()[8 - len..]);
+}
+
+/// Cursor over a frame payload being decoded.
+struct FrameReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> FrameReader<'a> {
+    const fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn varint(&mut self) -> Result<u64, FrameError> {
+        let first = *self.bytes.first().ok_or(FrameError::Truncated)?;
+        let len = 1 << (first >> 6);
+        let bytes = self.take(len)?;
+        let mut value = u64::from(first & 0x3F);
+        for byte in &bytes[1..] {
+            value = (value << 8) | u64::from(*byte);
+        }
+        Ok(value)
+    }
+
+    fn u8(&mut self) -> Result<u8, FrameError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u64_le(&mut self) -> Result<u64, FrameError> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().expect("took 8 bytes")))
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], FrameError> {
+        if self.bytes.len() < len {
+            return Err(FrameError::Truncated);
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(head)
+    }
+
+    const fn rest(&mut self) -> &'a [u8] {
+        let rest = self.bytes;
+        self.bytes = &[];
+        rest
+    }
+
+    const fn finish(self) -> Result<(), FrameError> {
+        if self.bytes.is_empty() {
+            Ok(())
+        } else {
+            Err(FrameError::TrailingBytes(self.bytes.len()))
+        }
+    }
 }
 
 /// Transport frame abstraction.
@@ -265,6 +771,94 @@ impl Frame {
         )
     }
 
+    /// Create a `STOP_SENDING` frame asking the peer to abandon its send side of `stream`.
+    #[must_use]
+    pub fn stop_sending(stream: StreamId, error_code: u64) -> Self {
+        let mut payload = Vec::with_capacity(8 + 8);
+        payload.extend_from_slice(&stream.as_u64().to_le_bytes());
+        payload.extend_from_slice(&error_code.to_le_bytes());
+        Self::new(FrameType::StopSending, payload)
+    }
+
+    /// Create a `STREAM_EXPIRED` frame telling the receiver to skip `stream` to `offset`.
+    #[must_use]
+    pub fn stream_expired(stream: StreamId, offset: u64) -> Self {
+        let mut payload = Vec::with_capacity(8 + 8);
+        payload.extend_from_slice(&stream.as_u64().to_le_bytes());
+        payload.extend_from_slice(&offset.to_le_bytes());
+        Self::new(FrameType::StreamExpired, payload)
+    }
+
+    /// Create a `STREAMS_BLOCKED` frame reporting that opening another `kind` stream would
+    /// exceed `limit` concurrent streams.
+    #[must_use]
+    pub fn streams_blocked(kind: StreamKind, limit: u64) -> Self {
+        let mut payload = Vec::with_capacity(1 + 8);
+        payload.push(kind.as_u8());
+        payload.extend_from_slice(&limit.to_le_bytes());
+        Self::new(FrameType::StreamsBlocked, payload)
+    }
+
+    /// Create a `STREAM_DATA` frame carrying `chunk` of `stream`.
+    ///
+    /// The payload is `stream id | offset | data length` as varints, a FIN byte (0 or 1),
+    /// then the data. Chunks up to [`MAX_STREAM_DATA_LEN`] bytes always fit a frame.
+    #[must_use]
+    pub fn stream_data(stream: StreamId, chunk: &SendChunk) -> Self {
+        let data = &chunk.payload;
+        let mut payload = Vec::with_capacity(STREAM_DATA_OVERHEAD + data.len());
+        put_varint(&mut payload, stream.as_u64());
+        put_varint(&mut payload, chunk.offset);
+        put_varint(&mut payload, data.len() as u64);
+        payload.push(u8::from(chunk.fin));
+        payload.extend_from_slice(data);
+        Self::new(FrameType::StreamData, payload)
+    }
+
+    /// Create a `STREAM_OPEN` frame announcing `stream`; the payload is its id as a varint.
+    #[must_use]
+    pub fn stream_open(stream: StreamId) -> Self {
+        let mut payload = Vec::with_capacity(8);
+        put_varint(&mut payload, stream.as_u64());
+        Self::new(FrameType::StreamOpen, payload)
+    }
+
+    /// Create a `STREAM_FIN` frame ending `stream` at `final_offset`, without data.
+    #[must_use]
+    pub fn stream_fin(stream: StreamId, final_offset: u64) -> Self {
+        let mut payload = Vec::with_capacity(8 + 8);
+        put_varint(&mut payload, stream.as_u64());
+        put_varint(&mut payload, final_offset);
+        Self::new(FrameType::StreamFin, payload)
+    }
+
+    /// Create a `CONNECTION_CLOSE` frame carrying an application `error_code` and `reason`.
+    #[must_use]
+    pub fn connection_close(error_code: u64, reason: &str) -> Self {
+        let mut payload = Vec::with_capacity(8 + reason.len());
+        payload.extend_from_slice(&error_code.to_le_bytes());
+        payload.extend_from_slice(reason.as_bytes());
+        Self::new(FrameType::ConnectionClose, payload)
+    }
+
+    /// Create a `PING` frame; it carries nothing but makes the peer acknowledge the packet.
+    #[must_use]
+    pub fn ping() -> Self {
+        Self::new(FrameType::Ping, Vec::new())
+    }
+
+    /// Create an unreliable DATAGRAM frame.
+    #[must_use]
+    pub fn datagram(payload: Vec<u8>) -> Self {
+        Self::new(FrameType::Datagram, payload)
+    }
+
+    /// Create a PADDING frame of `len` zero bytes.
+    #[must_use]
+    pub fn padding(len: usize) -> Self {
+        Self::new(FrameType::Padding, vec![0; len])
+    }
+
     /// Frame type accessor.
     #[must_use]
     pub const fn frame_type(&self) -> FrameType {
@@ -283,6 +877,61 @@ impl Frame {
         self.payload
     }
 
+    /// Length of the frame on the wire, header included.
+    #[must_use]
+    pub fn encoded_len(&self) -> usize {
+        FRAME_HEADER_LEN + self.payload.len()
+    }
+
+    /// Append the frame as `type (u8) | length (u16 LE) | payload`.
+    pub fn encode(&self, out: &mut Vec<u8>) -> Result<(), PacketError> {
+        let len = u16::try_from(self.payload.len()).map_err(|_| PacketError::PayloadTooLarge {
+            len: self.payload.len(),
+            max: u16::MAX as usize,
+        })?;
+        out.push(self.frame_type.to_byte());
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&self.payload);
+        Ok(())
+    }
+
+    /// Decode every frame of a packet payload written by [`encode`](Self::encode).
+    pub fn decode_all(mut bytes: &[u8]) -> Result<Vec<Self>, PacketError> {
+        let mut frames = Vec::new();
+        while !bytes.is_empty() {
+            if bytes.len() < FRAME_HEADER_LEN {
+                return Err(PacketError::BufferTooSmall {
+                    expected: FRAME_HEADER_LEN,
+                    actual: bytes.len(),
+                });
+            }
+            let frame_type =
+                FrameType::from_byte(bytes[0]).ok_or(PacketError::UnknownFrameType(bytes[0]))?;
+            let len = usize::from(u16::from_le_bytes([bytes[1], bytes[2]]));
+            let rest = &bytes[FRAME_HEADER_LEN..];
+            if rest.len() < len {
+                return Err(PacketError::BufferTooSmall {
+                    expected: len,
+                    actual: rest.len(),
+                });
+            }
+            frames.push(Self::new(frame_type, rest[..len].to_vec()));
+            bytes = &rest[len..];
+        }
+        Ok(frames)
+    }
+
+    /// Reader over the payload, provided the frame is of type `expected`.
+    fn reader(&self, expected: FrameType) -> Result<FrameReader<'_>, FrameError> {
+        if self.frame_type != expected {
+            return Err(FrameError::UnexpectedFrameType {
+                expected,
+                actual: self.frame_type,
+            });
+        }
+        Ok(FrameReader::new(&self.payload))
+    }
+
     /// Attempt to decode the payload as an ACK frame.
     pub fn decode_ack(&self) -> Result<AckFrame, AckError> {
         if self.frame_type != FrameType::Ack {
@@ -292,27 +941,110 @@ impl Frame {
     }
 
     /// Decode a stream `MAX_DATA` frame payload.
-    pub fn decode_stream_max_data(&self) -> Result<(StreamId, u64), AckError> {
-        if self.frame_type != FrameType::StreamMaxData {
-            return Err(AckError::UnexpectedFrameType);
-        }
-        if self.payload.len() != 16 {
-            return Err(AckError::UnexpectedFrameType);
-        }
-        let stream = StreamId::from_raw(u64::from_le_bytes(self.payload[0..8].try_into().unwrap()));
-        let limit = u64::from_le_bytes(self.payload[8..16].try_into().unwrap());
+    pub fn decode_stream_max_data(&self) -> Result<(StreamId, u64), FrameError> {
+        let mut reader = self.reader(FrameType::StreamMaxData)?;
+        let stream = StreamId::try_from_raw(reader.u64_le()?)
+            .ok_or(FrameError::InvalidField("stream id"))?;
+        let limit = reader.u64_le()?;
+        reader.finish()?;
         Ok((stream, limit))
     }
 
-    /// Decode a connection `MAX_DATA` frame payload.
-    pub fn decode_connection_max_data(&self) -> Result<u64, AckError> {
-        if self.frame_type != FrameType::ConnectionMaxData {
-            return Err(AckError::UnexpectedFrameType);
+    /// Decode a `STREAM_DATA` frame payload into the stream, offset, data and FIN flag.
+    pub fn decode_stream_data(&self) -> Result<(StreamId, u64, &[u8], bool), FrameError> {
+        let mut reader = self.reader(FrameType::StreamData)?;
+        let stream = StreamId::try_from_raw(reader.varint()?)
+            .ok_or(FrameError::InvalidField("stream id"))?;
+        let offset = reader.varint()?;
+        let declared = reader.varint()?;
+        let fin = match reader.u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(FrameError::InvalidField("FIN flag")),
+        };
+        let data = reader.rest();
+        if declared != data.len() as u64 {
+            return Err(FrameError::LengthMismatch {
+                declared,
+                actual: data.len(),
+            });
         }
-        if self.payload.len() != 8 {
-            return Err(AckError::UnexpectedFrameType);
+        if offset
+            .checked_add(declared)
+            .is_none_or(|end| end > MAX_VARINT)
+        {
+            return Err(FrameError::InvalidField("stream offset"));
         }
-        Ok(u64::from_le_bytes(self.payload[0..8].try_into().unwrap()))
+        Ok((stream, offset, data, fin))
+    }
+
+    /// Decode a `STREAM_OPEN` frame payload into the stream it announces.
+    pub fn decode_stream_open(&self) -> Result<StreamId, FrameError> {
+        let mut reader = self.reader(FrameType::StreamOpen)?;
+        let stream = StreamId::try_from_raw(reader.varint()?)
+            .ok_or(FrameError::InvalidField("stream id"))?;
+        reader.finish()?;
+        Ok(stream)
+    }
+
+    /// Decode a `STREAM_FIN` frame payload into the stream and its final offset.
+    pub fn decode_stream_fin(&self) -> Result<(StreamId, u64), FrameError> {
+        let mut reader = self.reader(FrameType::StreamFin)?;
+        let stream = StreamId::try_from_raw(reader.varint()?)
+            .ok_or(FrameError::InvalidField("stream id"))?;
+        let final_offset = reader.varint()?;
+        reader.finish()?;
+        Ok((stream, final_offset))
+    }
+
+    /// Decode a `CONNECTION_CLOSE` frame payload into the error code and reason.
+    pub fn decode_connection_close(&self) -> Result<(u64, &str), FrameError> {
+        let mut reader = self.reader(FrameType::ConnectionClose)?;
+        let code = reader.u64_le()?;
+        let reason = std::str::from_utf8(reader.rest())
+            .map_err(|_| FrameError::InvalidField("close reason"))?;
+        Ok((code, reason))
+    }
+
+    /// Decode a `STOP_SENDING` frame payload into the stream and application error code.
+    pub fn decode_stop_sending(&self) -> Result<(StreamId, u64), FrameError> {
+        let mut reader = self.reader(FrameType::StopSending)?;
+        let stream = StreamId::try_from_raw(reader.u64_le()?)
+            .ok_or(FrameError::InvalidField("stream id"))?;
+        let code = reader.u64_le()?;
+        reader.finish()?;
+        Ok((stream, code))
+    }
+
+    /// Decode a `STREAM_EXPIRED` frame payload into the stream and the offset to skip to.
+    pub fn decode_stream_expired(&self) -> Result<(StreamId, u64), FrameError> {
+        let mut reader = self.reader(FrameType::StreamExpired)?;
+        let stream = StreamId::try_from_raw(reader.u64_le()?)
+            .ok_or(FrameError::InvalidField("stream id"))?;
+        let offset = reader.u64_le()?;
+        reader.finish()?;
+        Ok((stream, offset))
+    }
+
+    /// Decode a `STREAMS_BLOCKED` frame payload into the stream kind and the limit hit.
+    pub fn decode_streams_blocked(&self) -> Result<(StreamKind, u64), FrameError> {
+        let mut reader = self.reader(FrameType::StreamsBlocked)?;
+        let kind = match reader.u8()? {
+            0 => StreamKind::Bidirectional,
+            1 => StreamKind::Unidirectional,
+            _ => return Err(FrameError::InvalidField("stream kind")),
+        };
+        let limit = reader.u64_le()?;
+        reader.finish()?;
+        Ok((kind, limit))
+    }
+
+    /// Decode a connection `MAX_DATA` frame payload.
+    pub fn decode_connection_max_data(&self) -> Result<u64, FrameError> {
+        let mut reader = self.reader(FrameType::ConnectionMaxData)?;
+        let limit = reader.u64_le()?;
+        reader.finish()?;
+        Ok(limit)
     }
 }
 
@@ -321,6 +1053,264 @@ mod tests {
     use super::*;
     use crate::transport::stream::{EndpointRole, StreamKind};
 
+    fn chunk(offset: u64, payload: &[u8], fin: bool) -> SendChunk {
+        SendChunk {
+            offset,
+            payload: payload.to_vec(),
+            fin,
+            retransmission: false,
+        }
+    }
+
+    fn varint_bytes(value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_varint(&mut out, value);
+        out
+    }
+
+    fn header_roundtrip(conn_id: ConnectionId) {
+        let packet_number = 0x0102_0304_0506_0708;
+        for pn_len in 1..=MAX_PACKET_NUMBER_LEN {
+            let header = PacketHeader::with_connection_id(
+                conn_id,
+                packet_number,
+                1200,
+                PacketFlags::from_bits(PacketFlags::ACK_ELICITING | PacketFlags::KEY_PHASE),
+            )
+            .with_packet_number_len(pn_len);
+
+            let mut buf = [0xFFu8; MAX_HEADER_SIZE + 4];
+            header.encode(&mut buf).expect("encode");
+            assert_eq!(header.len(), MIN_HEADER_SIZE + conn_id.len() + pn_len - 1);
+            assert_eq!(usize::from(buf[0]), conn_id.len());
+
+            let decoded = PacketHeader::decode_after(&buf[..header.len()], Some(packet_number - 1))
+                .expect("decode");
+            assert_eq!(decoded, header);
+            assert_eq!(decoded.connection_id().as_bytes(), conn_id.as_bytes());
+            assert_eq!(
+                PacketHeader::decode(&buf[..header.len() - 1]),
+                Err(PacketError::BufferTooSmall {
+                    expected: header.len(),
+                    actual: header.len() - 1,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn packet_number_length_covers_twice_the_unacked_distance() {
+        // RFC 9000 Appendix A.2.
+        assert_eq!(encoded_packet_number_len(0x00ac_5c02, Some(0x00ab_e8b3)), 2);
+        assert_eq!(encoded_packet_number_len(0x00ac_e8fe, Some(0x00ab_e8b3)), 3);
+        assert_eq!(encoded_packet_number_len(0, None), 1);
+        assert_eq!(encoded_packet_number_len(126, None), 1);
+        assert_eq!(encoded_packet_number_len(127, None), 2);
+        assert_eq!(
+            encoded_packet_number_len(1 << 40, Some(0)),
+            MAX_PACKET_NUMBER_LEN
+        );
+    }
+
+    #[test]
+    fn packet_number_decoding_matches_rfc_example() {
+        // RFC 9000 Appendix A.3.
+        assert_eq!(
+            decode_packet_number(Some(0xa82f_30ea), 0x9b32, 2),
+            0xa82f_9b32
+        );
+    }
+
+    #[test]
+    fn packet_numbers_reconstruct_across_wraparound() {
+        // The truncated bytes wrap while the full number keeps counting.
+        assert_eq!(decode_packet_number(Some(0x1fe), 0x03, 1), 0x203);
+        assert_eq!(decode_packet_number(Some(0x1ff), 0x7f, 1), 0x27f);
+        assert_eq!(
+            decode_packet_number(Some(0xffff_fff0), 0x05, 4),
+            0x1_0000_0005
+        );
+        // A late packet from before the wrap still resolves below it.
+        assert_eq!(decode_packet_number(Some(0x203), 0xfe, 1), 0x1fe);
+        // Nothing resolves below zero or above u64::MAX.
+        assert_eq!(decode_packet_number(None, 0xff, 1), 0xff);
+        assert_eq!(
+            decode_packet_number(Some(u64::MAX - 1), 0x00, 1),
+            u64::MAX - 0xff
+        );
+
+        for packet_number in [
+            0x7f,
+            0xff,
+            0x100,
+            0xffff,
+            0x1_0000,
+            0xffff_ffff,
+            0x1_0000_0000,
+        ] {
+            let largest_acked = packet_number - 1;
+            let len = encoded_packet_number_len(packet_number, Some(largest_acked));
+            let header = PacketHeader::new(1, packet_number, 0, PacketFlags::default())
+                .with_packet_number_len(len);
+            let mut buf = [0u8; MAX_HEADER_SIZE];
+            header.encode(&mut buf).expect("encode");
+            let decoded = PacketHeader::decode_after(&buf, Some(largest_acked)).expect("decode");
+            assert_eq!(decoded.packet_number(), packet_number);
+            assert_eq!(decoded.packet_number_len(), len);
+        }
+    }
+
+    #[test]
+    fn packet_numbers_reconstruct_within_the_reordering_window() {
+        // With 1 byte the window spans 256 numbers centred on the next expected one.
+        let largest = 1000;
+        for packet_number in 874..=1129u64 {
+            assert_eq!(
+                decode_packet_number(Some(largest), packet_number % 256, 1),
+                packet_number
+            );
+        }
+        assert_eq!(decode_packet_number(Some(largest), 873 % 256, 1), 1129);
+        assert_eq!(decode_packet_number(Some(largest), 1130 % 256, 1), 874);
+
+        // A late packet encoded against an older ack still resolves after newer arrivals.
+        let late = 990;
+        let len = encoded_packet_number_len(late, Some(980));
+        assert_eq!(len, 1);
+        assert_eq!(decode_packet_number(Some(1010), late % 256, len), late);
+    }
+
+    #[test]
+    fn packet_number_length_bits_are_not_flags() {
+        let header = PacketHeader::new(1, 2, 0, PacketFlags::from_bits(PacketFlags::PROBE))
+            .with_packet_number_len(3);
+        let mut buf = [0u8; MAX_HEADER_SIZE];
+        header.encode(&mut buf).expect("encode");
+        let decoded = PacketHeader::decode(&buf).expect("decode");
+        assert_eq!(decoded.flags(), PacketFlags::from_bits(PacketFlags::PROBE));
+        assert_eq!(decoded.packet_number_len(), 3);
+
+        let clashing = PacketHeader::new(1, 2, 0, PacketFlags::from_bits(0x80));
+        assert_eq!(
+            clashing.encode(&mut buf),
+            Err(PacketError::ReservedBitsSet(0x80))
+        );
+    }
+
+    #[test]
+    fn header_roundtrip_all_conn_id_lengths() {
+        let bytes: Vec<u8> = (1..=16).collect();
+        for len in 0..=MAX_CONN_ID_LEN {
+            header_roundtrip(ConnectionId::new(&bytes[..len]).expect("cid"));
+        }
+    }
+
+    #[test]
+    fn spin_bit_roundtrips_and_other_bits_stay_reserved() {
+        let header = PacketHeader::new(1, 2, 0, PacketFlags::default()).with_spin(true);
+        let mut buf = [0u8; MAX_HEADER_SIZE];
+        header.encode(&mut buf).expect("encode");
+        assert_eq!(PacketHeader::peek_spin(&buf), Ok(true));
+        let decoded = PacketHeader::decode(&buf[..header.len()]).expect("decode");
+        assert!(decoded.spin());
+        assert_eq!(decoded, header);
+
+        buf[1 + 8] = 0b10;
+        assert_eq!(
+            PacketHeader::decode(&buf[..header.len()]),
+            Err(PacketError::ReservedBitsSet(0b10))
+        );
+    }
+
+    #[test]
+    fn zero_length_conn_id_uses_minimum_header() {
+        let header =
+            PacketHeader::with_connection_id(ConnectionId::default(), 7, 0, PacketFlags::default())
+                .with_packet_number_len(1);
+        assert_eq!(header.len(), MIN_HEADER_SIZE);
+        assert_eq!(header.conn_id(), None);
+        let mut buf = [0u8; MIN_HEADER_SIZE];
+        header.encode(&mut buf).expect("encode");
+        assert_eq!(PacketHeader::decode(&buf).expect("decode"), header);
+    }
+
+    #[test]
+    fn u64_conn_id_compatibility() {
+        let header = PacketHeader::new(0xDEAD_BEEF, 1, 0, PacketFlags::default());
+        assert_eq!(header.connection_id().len(), 8);
+        assert_eq!(header.conn_id(), Some(0xDEAD_BEEF));
+        assert_eq!(ConnectionId::from(0xDEAD_BEEF), *header.connection_id());
+    }
+
+    #[test]
+    fn oversized_conn_id_rejected() {
+        assert_eq!(
+            ConnectionId::new(&[0u8; MAX_CONN_ID_LEN + 1]),
+            Err(PacketError::ConnectionIdTooLong {
+                len: MAX_CONN_ID_LEN + 1,
+                max: MAX_CONN_ID_LEN,
+            })
+        );
+        let mut buf = [0u8; MAX_HEADER_SIZE + 1];
+        buf[0] = 17;
+        assert!(matches!(
+            PacketHeader::decode(&buf),
+            Err(PacketError::ConnectionIdTooLong { .. })
+        ));
+        assert!(matches!(
+            PacketHeader::decode(&[]),
+            Err(PacketError::BufferTooSmall { .. })
+        ));
+    }
+
+    #[test]
+    fn frames_roundtrip_through_packet_payload() {
+        let stream = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        let frames = [
+            Frame::stream_data(stream, &chunk(4096, b"hello", true)),
+            Frame::connection_max_data(1 << 20),
+            Frame::connection_close(7, "bye"),
+            Frame::padding(0),
+            Frame::ping(),
+        ];
+        let mut payload = Vec::new();
+        for frame in &frames {
+            frame.encode(&mut payload).expect("encode");
+        }
+        assert_eq!(
+            payload.len(),
+            frames.iter().map(Frame::encoded_len).sum::<usize>()
+        );
+
+        let decoded = Frame::decode_all(&payload).expect("decode");
+        assert_eq!(decoded.len(), frames.len());
+        let (id, offset, data, fin) = decoded[0].decode_stream_data().expect("stream data");
+        assert_eq!((id, offset, data, fin), (stream, 4096, &b"hello"[..], true));
+        assert_eq!(
+            decoded[1].decode_connection_max_data().expect("max data"),
+            1 << 20
+        );
+        assert_eq!(
+            decoded[2].decode_connection_close().expect("close"),
+            (7, "bye")
+        );
+        assert_eq!(decoded[3].frame_type(), FrameType::Padding);
+        assert_eq!(decoded[4].frame_type(), FrameType::Ping);
+        assert!(decoded[4].payload().is_empty());
+
+        assert!(matches!(
+            Frame::decode_all(&payload[..payload.len() - 1]),
+            Err(PacketError::BufferTooSmall {
+                expected: FRAME_HEADER_LEN,
+                actual: 2,
+            })
+        ));
+        assert!(matches!(
+            Frame::decode_all(&[0xFF, 0, 0]),
+            Err(PacketError::UnknownFrameType(0xFF))
+        ));
+    }
+
     #[test]
     fn stream_max_data_roundtrip() {
         let stream = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 3);
@@ -331,6 +1321,49 @@ mod tests {
         assert_eq!(limit, 512);
     }
 
+    #[test]
+    fn stop_sending_roundtrip() {
+        let stream = StreamId::new(EndpointRole::Server, StreamKind::Unidirectional, 1);
+        let frame = Frame::stop_sending(stream, 0x42);
+        assert_eq!(frame.frame_type(), FrameType::StopSending);
+        assert_eq!(frame.decode_stop_sending().expect("decode"), (stream, 0x42));
+        assert!(Frame::connection_max_data(1).decode_stop_sending().is_err());
+
+        let mut oversized = (MAX_VARINT + 1).to_le_bytes().to_vec();
+        oversized.extend_from_slice(&0_u64.to_le_bytes());
+        assert_eq!(
+            Frame::new(FrameType::StopSending, oversized).decode_stop_sending(),
+            Err(FrameError::InvalidField("stream id"))
+        );
+    }
+
+    #[test]
+    fn stream_expired_roundtrip() {
+        let stream = StreamId::new(EndpointRole::Client, StreamKind::Unidirectional, 4);
+        let frame = Frame::stream_expired(stream, 4096);
+        assert_eq!(frame.frame_type(), FrameType::StreamExpired);
+        assert_eq!(
+            frame.decode_stream_expired().expect("decode"),
+            (stream, 4096)
+        );
+        assert!(
+            Frame::stop_sending(stream, 1)
+                .decode_stream_expired()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn streams_blocked_roundtrip() {
+        let frame = Frame::streams_blocked(StreamKind::Unidirectional, 12);
+        assert_eq!(frame.frame_type(), FrameType::StreamsBlocked);
+        assert_eq!(
+            frame.decode_streams_blocked().expect("decode"),
+            (StreamKind::Unidirectional, 12)
+        );
+        assert!(Frame::padding(9).decode_streams_blocked().is_err());
+    }
+
     #[test]
     fn connection_max_data_roundtrip() {
         let frame = Frame::connection_max_data(2048);
@@ -338,4 +1371,172 @@ mod tests {
         let limit = frame.decode_connection_max_data().expect("decode");
         assert_eq!(limit, 2048);
     }
+
+    #[test]
+    fn varints_switch_length_at_each_threshold() {
+        for (value, len) in [
+            (0, 1),
+            ((1 << 6) - 1, 1),
+            (1 << 6, 2),
+            ((1 << 14) - 1, 2),
+            (1 << 14, 4),
+            ((1 << 30) - 1, 4),
+            (1 << 30, 8),
+            (MAX_VARINT, 8),
+        ] {
+            let bytes = varint_bytes(value);
+            assert_eq!(bytes.len(), len, "value {value}");
+            assert_eq!(varint_len(value), len);
+            let mut reader = FrameReader::new(&bytes);
+            assert_eq!(reader.varint(), Ok(value));
+            assert_eq!(reader.finish(), Ok(()));
+        }
+        // RFC 9000 Appendix A.1 examples.
+        assert_eq!(varint_bytes(37), [0x25]);
+        assert_eq!(varint_bytes(15_293), [0x7b, 0xbd]);
+        assert_eq!(varint_bytes(494_878_333), [0x9d, 0x7f, 0x3e, 0x7d]);
+        assert_eq!(
+            varint_bytes(151_288_809_941_952_652),
+            [0xc2, 0x19, 0x7c, 0x5e, 0xff, 0x14, 0xe8, 0x8c]
+        );
+        assert_eq!(
+            FrameReader::new(&[0x9d, 0x7f, 0x3e]).varint(),
+            Err(FrameError::Truncated)
+        );
+    }
+
+    #[test]
+    fn stream_data_offsets_cross_varint_thresholds() {
+        let stream = StreamId::new(EndpointRole::Server, StreamKind::Bidirectional, 2);
+        for (offset, header_len) in [
+            ((1 << 14) - 1, 1 + 2 + 1 + 1),
+            (1 << 14, 1 + 4 + 1 + 1),
+            ((1 << 30) - 1, 1 + 4 + 1 + 1),
+            (1 << 30, 1 + 8 + 1 + 1),
+        ] {
+            let frame = Frame::stream_data(stream, &chunk(offset, b"abc", false));
+            assert_eq!(frame.payload().len(), header_len + 3, "offset {offset}");
+            assert_eq!(
+                frame.decode_stream_data(),
+                Ok((stream, offset, &b"abc"[..], false))
+            );
+        }
+        for len in [
+            (1 << 6) - 1,
+            1 << 6,
+            (1 << 14) - 1,
+            1 << 14,
+            MAX_STREAM_DATA_LEN,
+        ] {
+            let data = vec![0xA5; len];
+            let frame = Frame::stream_data(stream, &chunk(1 << 40, &data, true));
+            let mut out = Vec::new();
+            frame.encode(&mut out).expect("fits a frame");
+            let decoded = &Frame::decode_all(&out).expect("decode")[0];
+            let (_, offset, decoded_data, fin) = decoded.decode_stream_data().expect("decode");
+            assert_eq!((offset, decoded_data.len(), fin), (1 << 40, len, true));
+        }
+    }
+
+    #[test]
+    fn stream_data_rejects_malformed_payloads() {
+        let stream = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        let frame = Frame::stream_data(stream, &chunk(10, b"data", false));
+        let mut payload = frame.payload().to_vec();
+
+        payload.push(0);
+        assert_eq!(
+            Frame::new(FrameType::StreamData, payload.clone()).decode_stream_data(),
+            Err(FrameError::LengthMismatch {
+                declared: 4,
+                actual: 5,
+            })
+        );
+        payload.truncate(2);
+        assert_eq!(
+            Frame::new(FrameType::StreamData, payload.clone()).decode_stream_data(),
+            Err(FrameError::Truncated)
+        );
+        payload.extend_from_slice(&[4, 2]);
+        assert_eq!(
+            Frame::new(FrameType::StreamData, payload).decode_stream_data(),
+            Err(FrameError::InvalidField("FIN flag"))
+        );
+
+        let mut overflowing = Vec::new();
+        put_varint(&mut overflowing, stream.as_u64());
+        put_varint(&mut overflowing, MAX_VARINT);
+        put_varint(&mut overflowing, 1);
+        overflowing.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            Frame::new(FrameType::StreamData, overflowing).decode_stream_data(),
+            Err(FrameError::InvalidField("stream offset"))
+        );
+
+        assert_eq!(
+            Frame::ping().decode_stream_data(),
+            Err(FrameError::UnexpectedFrameType {
+                expected: FrameType::StreamData,
+                actual: FrameType::Ping,
+            })
+        );
+    }
+
+    #[test]
+    fn stream_open_and_fin_roundtrip() {
+        let stream = StreamId::new(EndpointRole::Client, StreamKind::Unidirectional, 70);
+        let open = Frame::stream_open(stream);
+        assert_eq!(open.frame_type(), FrameType::StreamOpen);
+        assert_eq!(open.decode_stream_open(), Ok(stream));
+
+        let fin = Frame::stream_fin(stream, 1 << 30);
+        assert_eq!(fin.frame_type(), FrameType::StreamFin);
+        assert_eq!(fin.decode_stream_fin(), Ok((stream, 1 << 30)));
+
+        let mut padded = open.payload().to_vec();
+        padded.push(0);
+        assert_eq!(
+            Frame::new(FrameType::StreamOpen, padded).decode_stream_open(),
+            Err(FrameError::TrailingBytes(1))
+        );
+        assert!(open.decode_stream_fin().is_err());
+    }
+
+    mod proptests {
+        use super::*;
+        use proptest::prelude::*;
+
+        proptest! {
+            #[test]
+            fn stream_data_roundtrips(
+                index in 0u64..(1 << 40),
+                offset in prop_oneof![0u64..(1 << 14), 0u64..(1 << 30), 0u64..(1 << 61)],
+                data in prop::collection::vec(any::<u8>(), 0..2048),
+                fin in any::<bool>(),
+            ) {
+                let stream = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, index);
+                let frame = Frame::stream_data(stream, &chunk(offset, &data, fin));
+                prop_assert!(frame.payload().len() <= STREAM_DATA_OVERHEAD + data.len());
+
+                let mut out = Vec::new();
+                frame.encode(&mut out).expect("encode");
+                let decoded = Frame::decode_all(&out).expect("decode");
+                prop_assert_eq!(decoded.len(), 1);
+                let (id, decoded_offset, decoded_data, decoded_fin) =
+                    decoded[0].decode_stream_data().expect("stream data");
+                prop_assert_eq!(id, stream);
+                prop_assert_eq!(decoded_offset, offset);
+                prop_assert_eq!(decoded_data, &data[..]);
+                prop_assert_eq!(decoded_fin, fin);
+            }
+
+            #[test]
+            fn varints_roundtrip(value in 0..=MAX_VARINT) {
+                let bytes = varint_bytes(value);
+                prop_assert_eq!(bytes.len(), varint_len(value));
+                let mut reader = FrameReader::new(&bytes);
+                prop_assert_eq!(reader.varint(), Ok(value));
+            }
+        }
+    }
 }
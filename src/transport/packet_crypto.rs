@@ -1,13 +1,18 @@
-//! Packet sealing and opening using ChaCha20-Poly1305 session keys.
+//! Packet sealing and opening with session keys under the negotiated AEAD suite.
 
 use super::crypto::{
-    AEAD_TAG_LEN, AeadKey, AeadNonce, AeadTag, HEADER_PROTECTION_MASK_LEN,
-    HEADER_PROTECTION_SAMPLE_LEN, HeaderProtectionKey, SessionKeys, decrypt, encrypt,
-    header_protection_mask,
+    AEAD_TAG_LEN, AeadCipher, AeadKey, AeadNonce, AeadTag, HEADER_PROTECTION_MASK_LEN,
+    HEADER_PROTECTION_SAMPLE_LEN, HeaderProtectionKey, SessionKeys, header_protection_mask,
 };
 use super::error::TransportError;
-use super::handshake::nonce_from_packet_number;
-use super::packet::{HEADER_SIZE, PacketError, PacketFlags, PacketHeader};
+use super::packet::{
+    ConnectionId, MAX_HEADER_SIZE, MAX_PACKET_NUMBER_LEN, PacketError, PacketFlags, PacketHeader,
+    encoded_packet_number_len,
+};
+#[cfg(feature = "qlog")]
+use super::qlog::{QlogEvent, SharedEventLogger};
+#[cfg(feature = "qlog")]
+use std::time::SystemTime;
 use tracing::{debug, instrument, trace};
 
 /// Result of decrypting an inbound packet.
@@ -37,16 +42,37 @@ impl DecryptedPacket {
     }
 }
 
-fn build_header_sample(body: &[u8]) -> [u8; HEADER_PROTECTION_SAMPLE_LEN] {
-    let mut sample = [0u8; HEADER_PROTECTION_SAMPLE_LEN];
-    let take = body.len().min(HEADER_PROTECTION_SAMPLE_LEN);
-    sample[..take].copy_from_slice(&body[..take]);
-    sample
+/// Offset of the header protection sample within a packet with a `cid_len`-byte ID.
+///
+/// As in RFC 9001 the sample starts [`MAX_PACKET_NUMBER_LEN`] bytes after the packet number
+/// begins, so the receiver can find it before learning the packet number length; senders
+/// lengthen the packet number of tiny payloads so the sample fits.
+const fn sample_offset(cid_len: usize) -> usize {
+    PacketHeader::flags_offset(cid_len) + 1 + MAX_PACKET_NUMBER_LEN
+}
+
+/// Header protection sample of a packet at least `sample_offset + 16` bytes long.
+fn header_sample(packet: &[u8], cid_len: usize) -> &[u8; HEADER_PROTECTION_SAMPLE_LEN] {
+    packet[sample_offset(cid_len)..][..HEADER_PROTECTION_SAMPLE_LEN]
+        .try_into()
+        .expect("sample length")
 }
 
-fn apply_header_mask(bytes: &mut [u8], mask: &[u8; HEADER_PROTECTION_MASK_LEN]) {
-    bytes[16] ^= mask[0];
-    for (idx, slot) in bytes[8..16].iter_mut().enumerate() {
+/// Mask or unmask the flags and packet number; the connection ID stays readable for routing
+/// and the spin bit for passive RTT measurement.
+///
+/// The packet number length comes from the flags byte once unmasked: before masking when
+/// `masking`, after when not.
+fn apply_header_mask(bytes: &mut [u8], mask: &[u8; HEADER_PROTECTION_MASK_LEN], masking: bool) {
+    let flags_offset = PacketHeader::flags_offset(usize::from(bytes[0]));
+    let plain_flags = if masking {
+        bytes[flags_offset]
+    } else {
+        bytes[flags_offset] ^ mask[0]
+    };
+    bytes[flags_offset] ^= mask[0];
+    let pn_len = PacketHeader::packet_number_len_from_flags(plain_flags);
+    for (idx, slot) in bytes[flags_offset + 1..][..pn_len].iter_mut().enumerate() {
         *slot ^= mask[1 + idx];
     }
 }
@@ -58,12 +84,22 @@ pub struct PacketCipher {
     receive_key: AeadKey,
     send_hp: HeaderProtectionKey,
     receive_hp: HeaderProtectionKey,
+    send_iv: AeadNonce,
+    receive_iv: AeadNonce,
     send_packet_number: u64,
     highest_received: Option<u64>,
+    /// Largest packet number the peer acknowledged, bounding how far packet numbers are
+    /// truncated.
+    largest_acked: Option<u64>,
+    aead: AeadCipher,
+    spin: bool,
+    #[cfg(feature = "qlog")]
+    event_logger: Option<SharedEventLogger>,
 }
 
 impl PacketCipher {
-    /// Create a cipher instance from negotiated session keys.
+    /// Create a cipher instance from negotiated session keys, protecting payloads with
+    /// ChaCha20-Poly1305.
     #[must_use]
     pub fn new(keys: SessionKeys) -> Self {
         Self {
@@ -71,11 +107,57 @@ impl PacketCipher {
             receive_key: keys.receive().clone(),
             send_hp: keys.send_hp().clone(),
             receive_hp: keys.receive_hp().clone(),
+            send_iv: keys.send_iv().clone(),
+            receive_iv: keys.receive_iv().clone(),
             send_packet_number: 0,
             highest_received: None,
+            largest_acked: None,
+            aead: AeadCipher::ChaCha20Poly1305,
+            spin: false,
+            #[cfg(feature = "qlog")]
+            event_logger: None,
+        }
+    }
+
+    /// Create a cipher that reports every sealed and opened packet to `logger`.
+    ///
+    /// Events are stamped with the wall-clock time of the seal or open call.
+    #[cfg(feature = "qlog")]
+    #[must_use]
+    pub fn with_event_logger(keys: SessionKeys, logger: SharedEventLogger) -> Self {
+        Self {
+            event_logger: Some(logger),
+            ..Self::new(keys)
         }
     }
 
+    /// Protect payloads with `aead`, normally
+    /// [`TransportParameters::aead_cipher`](super::TransportParameters::aead_cipher) of the
+    /// negotiated parameters. Header protection is unaffected.
+    #[must_use]
+    pub fn with_aead_cipher(mut self, aead: AeadCipher) -> Self {
+        self.aead = aead;
+        self
+    }
+
+    /// AEAD suite protecting payloads.
+    #[must_use]
+    pub const fn aead_cipher(&self) -> AeadCipher {
+        self.aead
+    }
+
+    /// Spin bit to stamp on packets sealed from now on.
+    pub const fn set_spin(&mut self, spin: bool) {
+        self.spin = spin;
+    }
+
+    /// Largest packet number the peer has acknowledged; later packets encode only enough of
+    /// their number for the peer to reconstruct it. Until set, packet numbers are truncated
+    /// as if nothing was acknowledged.
+    pub const fn set_largest_acked(&mut self, largest_acked: Option<u64>) {
+        self.largest_acked = largest_acked;
+    }
+
     /// Set the initial packet numbers for send and receive directions.
     #[must_use]
     pub fn with_initial_numbers(mut self, send: u64, highest_received: Option<u64>) -> Self {
@@ -90,7 +172,7 @@ impl PacketCipher {
     #[instrument(level = "trace", skip(self, payload, buffer))]
     pub fn seal_into(
         &mut self,
-        conn_id: u64,
+        conn_id: &ConnectionId,
         flags: PacketFlags,
         payload: &[u8],
         buffer: &mut [u8],
@@ -103,7 +185,21 @@ impl PacketCipher {
             });
         }
 
-        let total_len = HEADER_SIZE + payload.len() + AEAD_TAG_LEN;
+        let packet_number = self.send_packet_number;
+        // The sample needs MAX_PACKET_NUMBER_LEN + 16 bytes after the packet number starts.
+        let pn_len = encoded_packet_number_len(packet_number, self.largest_acked)
+            .max(MAX_PACKET_NUMBER_LEN.saturating_sub(payload.len()));
+        let header = PacketHeader::with_connection_id(
+            *conn_id,
+            packet_number,
+            (payload.len() + AEAD_TAG_LEN) as u16,
+            flags,
+        )
+        .with_packet_number_len(pn_len)
+        .with_spin(self.spin);
+
+        let header_len = header.len();
+        let total_len = header_len + payload.len() + AEAD_TAG_LEN;
         if buffer.len() < total_len {
             return Err(TransportError::BufferTooSmall {
                 required: total_len,
@@ -111,63 +207,64 @@ impl PacketCipher {
             });
         }
 
-        let packet_number = self.send_packet_number;
         self.send_packet_number = self.send_packet_number.wrapping_add(1);
+        let nonce = self.send_iv.for_packet(packet_number);
 
-        let nonce = nonce_from_packet_number(packet_number);
-
-        let mut header = PacketHeader::new(
-            conn_id,
-            packet_number,
-            (payload.len() + AEAD_TAG_LEN) as u16,
-            flags,
-        );
-        header.set_nonce(*nonce.as_bytes());
-
-        let (head, rest) = buffer.split_at_mut(HEADER_SIZE);
+        let (head, rest) = buffer.split_at_mut(header_len);
         header.encode(head).map_err(TransportError::from)?;
 
-        let (ciphertext, tag) = encrypt(&self.send_key, &nonce, payload, head);
+        let (ciphertext, tag) = self.aead.seal(&self.send_key, &nonce, payload, head);
 
         let (cipher_slice, tag_slice) = rest.split_at_mut(ciphertext.len());
         cipher_slice.copy_from_slice(&ciphertext);
         tag_slice[..AEAD_TAG_LEN].copy_from_slice(tag.as_bytes());
 
-        let body_len = ciphertext.len() + AEAD_TAG_LEN;
-        let sample = build_header_sample(&rest[..body_len]);
-        let mask = header_protection_mask(&self.send_hp, &sample);
-        apply_header_mask(head, &mask);
+        let mask = header_protection_mask(
+            &self.send_hp,
+            header_sample(&buffer[..total_len], conn_id.len()),
+        );
+        apply_header_mask(&mut buffer[..header_len], &mask, true);
 
         debug!(packet_number, len = payload.len(), "sealed packet");
+        #[cfg(feature = "qlog")]
+        if let Some(logger) = &self.event_logger {
+            logger.log(
+                SystemTime::now(),
+                &QlogEvent::PacketSent {
+                    packet_number,
+                    size: total_len,
+                    flags,
+                },
+            );
+        }
         Ok((packet_number, total_len))
     }
 
     /// Try to open an inbound packet, returning the header and plaintext payload.
     #[instrument(level = "trace", skip(self, packet))]
     pub fn open(&mut self, packet: &[u8]) -> Result<DecryptedPacket, TransportError> {
-        if packet.len() < HEADER_SIZE + AEAD_TAG_LEN {
+        let cid_len =
+            usize::from(PacketHeader::peek_conn_id_len(packet).map_err(TransportError::from)?);
+        let min_len = sample_offset(cid_len) + HEADER_PROTECTION_SAMPLE_LEN;
+        if packet.len() < min_len {
             return Err(TransportError::Packet(PacketError::BufferTooSmall {
-                expected: HEADER_SIZE + AEAD_TAG_LEN,
+                expected: min_len,
                 actual: packet.len(),
             }));
         }
 
-        let (header_bytes, body) = packet.split_at(HEADER_SIZE);
-        if body.len() < HEADER_PROTECTION_SAMPLE_LEN {
-            return Err(TransportError::BufferTooSmall {
-                required: HEADER_PROTECTION_SAMPLE_LEN,
-                available: body.len(),
-            });
-        }
+        let mask = header_protection_mask(&self.receive_hp, header_sample(packet, cid_len));
 
-        let sample = build_header_sample(body);
-        let mask = header_protection_mask(&self.receive_hp, &sample);
+        let mut header_buf = [0u8; MAX_HEADER_SIZE];
+        let max_header_len = PacketHeader::max_len(cid_len);
+        header_buf[..max_header_len].copy_from_slice(&packet[..max_header_len]);
+        apply_header_mask(&mut header_buf[..max_header_len], &mask, false);
 
-        let mut unmasked_header = [0u8; HEADER_SIZE];
-        unmasked_header.copy_from_slice(header_bytes);
-        apply_header_mask(&mut unmasked_header, &mask);
-
-        let header = PacketHeader::decode(&unmasked_header).map_err(TransportError::from)?;
+        let header = PacketHeader::decode_after(&header_buf, self.highest_received)
+            .map_err(TransportError::from)?;
+        let header_len = header.len();
+        let unmasked_header = &header_buf[..header_len];
+        let body = &packet[header_len..];
         let payload_len = header.payload_len() as usize;
 
         if payload_len < AEAD_TAG_LEN {
@@ -189,7 +286,7 @@ impl PacketCipher {
         let tag_bytes = &body[cipher_len..cipher_len + AEAD_TAG_LEN];
 
         let tag = AeadTag::from_bytes(tag_bytes).map_err(TransportError::from)?;
-        let nonce = AeadNonce::from_array(*header.nonce());
+        let nonce = self.receive_iv.for_packet(header.packet_number());
 
         if let Some(highest) = self.highest_received {
             if header.packet_number() <= highest {
@@ -200,13 +297,9 @@ impl PacketCipher {
             }
         }
 
-        let plaintext = decrypt(
-            &self.receive_key,
-            &nonce,
-            ciphertext,
-            &unmasked_header,
-            &tag,
-        )?;
+        let plaintext =
+            self.aead
+                .open(&self.receive_key, &nonce, ciphertext, unmasked_header, &tag)?;
         let new_highest = match self.highest_received {
             Some(prev) => prev.max(header.packet_number()),
             None => header.packet_number(),
@@ -218,6 +311,17 @@ impl PacketCipher {
             len = plaintext.len(),
             "opened packet"
         );
+        #[cfg(feature = "qlog")]
+        if let Some(logger) = &self.event_logger {
+            logger.log(
+                SystemTime::now(),
+                &QlogEvent::PacketReceived {
+                    packet_number: header.packet_number(),
+                    size: header_len + payload_len,
+                    flags: header.flags(),
+                },
+            );
+        }
         Ok(DecryptedPacket {
             header,
             payload: plaintext,
@@ -229,8 +333,11 @@ impl PacketCipher {
 mod tests {
     use super::*;
     use crate::transport::crypto::{
-        AEAD_KEY_LEN, AeadKey, HEADER_PROTECTION_KEY_LEN, HeaderProtectionKey,
+        AEAD_KEY_LEN, AEAD_NONCE_LEN, AeadCipherSet, AeadKey, CryptoError,
+        HEADER_PROTECTION_KEY_LEN, HashAlgorithm, HeaderProtectionKey, derive_session_keys,
     };
+    use crate::transport::packet::{MAX_CONN_ID_LEN, MIN_HEADER_SIZE};
+    use crate::transport::params::TransportParameters;
 
     #[test]
     fn seal_and_open_roundtrip() {
@@ -239,12 +346,16 @@ mod tests {
             AeadKey::from_array([0x22u8; AEAD_KEY_LEN]),
             HeaderProtectionKey::from_array([0x33u8; HEADER_PROTECTION_KEY_LEN]),
             HeaderProtectionKey::from_array([0x44u8; HEADER_PROTECTION_KEY_LEN]),
+            AeadNonce::from_array([0x11u8; AEAD_NONCE_LEN]),
+            AeadNonce::from_array([0x22u8; AEAD_NONCE_LEN]),
         );
         let server_keys = SessionKeys::new(
             AeadKey::from_array([0x22u8; AEAD_KEY_LEN]),
             AeadKey::from_array([0x11u8; AEAD_KEY_LEN]),
             HeaderProtectionKey::from_array([0x44u8; HEADER_PROTECTION_KEY_LEN]),
             HeaderProtectionKey::from_array([0x33u8; HEADER_PROTECTION_KEY_LEN]),
+            AeadNonce::from_array([0x22u8; AEAD_NONCE_LEN]),
+            AeadNonce::from_array([0x11u8; AEAD_NONCE_LEN]),
         );
 
         let mut send_cipher = PacketCipher::new(client_keys);
@@ -253,13 +364,18 @@ mod tests {
         let mut buffer = vec![0u8; 2048];
         let payload = b"hello secure world";
         let (pn, len) = send_cipher
-            .seal_into(0xAA55, PacketFlags::from_bits(0), payload, &mut buffer)
+            .seal_into(
+                &ConnectionId::from_u64(0xAA55),
+                PacketFlags::from_bits(0),
+                payload,
+                &mut buffer,
+            )
             .expect("seal");
         assert_eq!(pn, 0);
 
         let packet = &buffer[..len];
         let decrypted = recv_cipher.open(packet).expect("open");
-        assert_eq!(decrypted.header().conn_id(), 0xAA55);
+        assert_eq!(decrypted.header().conn_id(), Some(0xAA55));
         assert_eq!(decrypted.payload(), payload);
 
         // Replay should fail.
@@ -277,12 +393,16 @@ mod tests {
             AeadKey::from_array([0xBB; AEAD_KEY_LEN]),
             HeaderProtectionKey::from_array([0xCC; HEADER_PROTECTION_KEY_LEN]),
             HeaderProtectionKey::from_array([0xDD; HEADER_PROTECTION_KEY_LEN]),
+            AeadNonce::from_array([0xAA; AEAD_NONCE_LEN]),
+            AeadNonce::from_array([0xBB; AEAD_NONCE_LEN]),
         );
         let server_keys = SessionKeys::new(
             AeadKey::from_array([0xBB; AEAD_KEY_LEN]),
             AeadKey::from_array([0xAA; AEAD_KEY_LEN]),
             HeaderProtectionKey::from_array([0xDD; HEADER_PROTECTION_KEY_LEN]),
             HeaderProtectionKey::from_array([0xCC; HEADER_PROTECTION_KEY_LEN]),
+            AeadNonce::from_array([0xBB; AEAD_NONCE_LEN]),
+            AeadNonce::from_array([0xAA; AEAD_NONCE_LEN]),
         );
 
         let mut send_cipher = PacketCipher::new(client_keys);
@@ -292,7 +412,7 @@ mod tests {
         let payload = b"hp";
         let (pn, len) = send_cipher
             .seal_into(
-                0xABCD,
+                &ConnectionId::from_u64(0xABCD),
                 PacketFlags::from_bits(PacketFlags::ACK_ELICITING),
                 payload,
                 &mut buffer,
@@ -300,28 +420,106 @@ mod tests {
             .expect("seal");
         assert_eq!(pn, 0);
 
-        let header_on_wire = &buffer[..HEADER_SIZE];
-
-        let mut expected_header = PacketHeader::new(
+        // Packet 0 fits one byte, but the 2-byte payload needs 2 to leave room for the sample.
+        let expected_header = PacketHeader::new(
             0xABCD,
             0,
             (payload.len() + AEAD_TAG_LEN) as u16,
             PacketFlags::from_bits(PacketFlags::ACK_ELICITING),
-        );
-        let nonce = nonce_from_packet_number(0);
-        expected_header.set_nonce(*nonce.as_bytes());
-        let mut expected_bytes = [0u8; HEADER_SIZE];
+        )
+        .with_packet_number_len(2);
+        let header_len = expected_header.len();
+        let mut expected_bytes = [0u8; MAX_HEADER_SIZE];
         expected_header.encode(&mut expected_bytes).unwrap();
 
-        assert_ne!(header_on_wire, expected_bytes);
+        assert_eq!(len, header_len + payload.len() + AEAD_TAG_LEN);
+        assert_ne!(buffer[..header_len], expected_bytes[..header_len]);
 
         let packet = &buffer[..len];
         let decrypted = recv_cipher.open(packet).expect("open");
-        assert_eq!(decrypted.header().conn_id(), 0xABCD);
+        assert_eq!(decrypted.header().conn_id(), Some(0xABCD));
         assert_eq!(decrypted.header().packet_number(), 0);
+        assert_eq!(decrypted.header().packet_number_len(), 2);
         assert_eq!(decrypted.payload(), payload);
     }
 
+    #[test]
+    fn header_protection_mask_matches_rfc9001_chacha20_vector() {
+        // RFC 9001 Appendix A.5: the first five mask bytes.
+        let key = HeaderProtectionKey::from_array([
+            0x25, 0xa2, 0x82, 0xb9, 0xe8, 0x2f, 0x06, 0xf2, 0x1f, 0x48, 0x89, 0x17, 0xa4, 0xfc,
+            0x8f, 0x1b, 0x73, 0x57, 0x36, 0x85, 0x60, 0x85, 0x97, 0xd0, 0xef, 0xcb, 0x07, 0x6b,
+            0x0a, 0xb7, 0xa7, 0xa4,
+        ]);
+        let sample = [
+            0x5e, 0x5c, 0xd5, 0x5c, 0x41, 0xf6, 0x90, 0x80, 0x57, 0x5d, 0x79, 0x99, 0xc2, 0x5a,
+            0x5b, 0xfb,
+        ];
+        let mask = header_protection_mask(&key, &sample);
+        assert_eq!(mask[..5], [0xae, 0xfe, 0xfe, 0x7d, 0x03]);
+    }
+
+    #[test]
+    fn protected_header_restores_exactly_across_packet_numbers() {
+        let chaining_key = [0x5Au8; 32];
+        let transcript = [0xA5u8; 32];
+        let client_keys =
+            derive_session_keys(HashAlgorithm::Sha256, &chaining_key, &transcript, true)
+                .expect("keys");
+        let server_keys =
+            derive_session_keys(HashAlgorithm::Sha256, &chaining_key, &transcript, false)
+                .expect("keys");
+        assert_ne!(client_keys.send_hp(), client_keys.receive_hp());
+        assert_eq!(client_keys.send_hp(), server_keys.receive_hp());
+
+        let conn_id = ConnectionId::from_u64(0x0102_0304);
+        let flags = PacketFlags::from_bits(PacketFlags::ACK_ELICITING);
+        let flags_offset = PacketHeader::flags_offset(conn_id.len());
+        for start in [0u64, 1, 0xFF, 0x1_0000, u64::from(u32::MAX) + 7] {
+            let mut send_cipher =
+                PacketCipher::new(client_keys.clone()).with_initial_numbers(start, None);
+            let mut recv_cipher = PacketCipher::new(server_keys.clone())
+                .with_initial_numbers(0, start.checked_sub(1));
+            let spin = start % 2 == 1;
+            send_cipher.set_spin(spin);
+            let mut buffer = vec![0u8; 128];
+            let (pn, len) = send_cipher
+                .seal_into(&conn_id, flags, b"header protection", &mut buffer)
+                .expect("seal");
+            assert_eq!(pn, start);
+
+            let mut plain = [0u8; MAX_HEADER_SIZE];
+            let header = PacketHeader::with_connection_id(
+                conn_id,
+                pn,
+                u16::try_from(17 + AEAD_TAG_LEN).unwrap(),
+                flags,
+            )
+            .with_packet_number_len(encoded_packet_number_len(pn, None))
+            .with_spin(spin);
+            let header_len = header.len();
+            header.encode(&mut plain[..header_len]).unwrap();
+            let wire = &buffer[..header_len];
+            // Connection ID, spin bit and length stay readable; flags and packet number are
+            // masked.
+            assert_eq!(wire[..flags_offset], plain[..flags_offset]);
+            assert_ne!(
+                wire[flags_offset..header_len],
+                plain[flags_offset..header_len]
+            );
+            assert_eq!(PacketHeader::peek_spin(wire), Ok(spin));
+
+            let decrypted = recv_cipher.open(&buffer[..len]).expect("open");
+            let mut restored = [0u8; MAX_HEADER_SIZE];
+            decrypted
+                .header()
+                .encode(&mut restored[..header_len])
+                .unwrap();
+            assert_eq!(restored[..header_len], plain[..header_len]);
+            assert_eq!(decrypted.payload(), b"header protection");
+        }
+    }
+
     #[test]
     fn empty_payload_uses_tag_for_sample() {
         let client_keys = SessionKeys::new(
@@ -329,12 +527,16 @@ mod tests {
             AeadKey::from_array([0x02; AEAD_KEY_LEN]),
             HeaderProtectionKey::from_array([0x03; HEADER_PROTECTION_KEY_LEN]),
             HeaderProtectionKey::from_array([0x04; HEADER_PROTECTION_KEY_LEN]),
+            AeadNonce::from_array([0x01; AEAD_NONCE_LEN]),
+            AeadNonce::from_array([0x02; AEAD_NONCE_LEN]),
         );
         let server_keys = SessionKeys::new(
             AeadKey::from_array([0x02; AEAD_KEY_LEN]),
             AeadKey::from_array([0x01; AEAD_KEY_LEN]),
             HeaderProtectionKey::from_array([0x04; HEADER_PROTECTION_KEY_LEN]),
             HeaderProtectionKey::from_array([0x03; HEADER_PROTECTION_KEY_LEN]),
+            AeadNonce::from_array([0x02; AEAD_NONCE_LEN]),
+            AeadNonce::from_array([0x01; AEAD_NONCE_LEN]),
         );
 
         let mut send_cipher = PacketCipher::new(client_keys);
@@ -342,7 +544,12 @@ mod tests {
 
         let mut buffer = vec![0u8; 128];
         let (pn, len) = send_cipher
-            .seal_into(0xCAFE, PacketFlags::from_bits(0), &[], &mut buffer)
+            .seal_into(
+                &ConnectionId::from_u64(0xCAFE),
+                PacketFlags::from_bits(0),
+                &[],
+                &mut buffer,
+            )
             .expect("seal");
         assert_eq!(pn, 0);
 
@@ -350,4 +557,166 @@ mod tests {
         let decrypted = recv_cipher.open(packet).expect("open");
         assert!(decrypted.payload().is_empty());
     }
+
+    #[test]
+    fn variable_length_conn_ids_roundtrip() {
+        let keys = |a: u8, b: u8| {
+            SessionKeys::new(
+                AeadKey::from_array([a; AEAD_KEY_LEN]),
+                AeadKey::from_array([b; AEAD_KEY_LEN]),
+                HeaderProtectionKey::from_array([a ^ 0xF0; HEADER_PROTECTION_KEY_LEN]),
+                HeaderProtectionKey::from_array([b ^ 0xF0; HEADER_PROTECTION_KEY_LEN]),
+                AeadNonce::from_array([a; AEAD_NONCE_LEN]),
+                AeadNonce::from_array([b; AEAD_NONCE_LEN]),
+            )
+        };
+        let mut send_cipher = PacketCipher::new(keys(0x05, 0x06));
+        let mut recv_cipher = PacketCipher::new(keys(0x06, 0x05));
+
+        let cid_bytes = [0xC1u8; MAX_CONN_ID_LEN];
+        for len in [0, 1, 8, MAX_CONN_ID_LEN] {
+            let conn_id = ConnectionId::new(&cid_bytes[..len]).expect("cid");
+            let mut buffer = vec![0u8; 256];
+            let (pn, written) = send_cipher
+                .seal_into(&conn_id, PacketFlags::default(), b"cid", &mut buffer)
+                .expect("seal");
+            assert_eq!(written, MIN_HEADER_SIZE + len + 3 + AEAD_TAG_LEN);
+            // The connection ID is not masked so it can be used for routing.
+            assert_eq!(&buffer[1..=len], conn_id.as_bytes());
+
+            let decrypted = recv_cipher.open(&buffer[..written]).expect("open");
+            assert_eq!(decrypted.header().connection_id(), &conn_id);
+            assert_eq!(decrypted.header().packet_number(), pn);
+            assert_eq!(decrypted.payload(), b"cid");
+        }
+    }
+
+    #[test]
+    fn acknowledgements_shorten_packet_numbers() {
+        let keys = |a: u8, b: u8| {
+            SessionKeys::new(
+                AeadKey::from_array([a; AEAD_KEY_LEN]),
+                AeadKey::from_array([b; AEAD_KEY_LEN]),
+                HeaderProtectionKey::from_array([a ^ 0x0F; HEADER_PROTECTION_KEY_LEN]),
+                HeaderProtectionKey::from_array([b ^ 0x0F; HEADER_PROTECTION_KEY_LEN]),
+                AeadNonce::from_array([a; AEAD_NONCE_LEN]),
+                AeadNonce::from_array([b; AEAD_NONCE_LEN]),
+            )
+        };
+        let start = 1_000_000;
+        let mut send_cipher = PacketCipher::new(keys(0x07, 0x08)).with_initial_numbers(start, None);
+        let mut recv_cipher =
+            PacketCipher::new(keys(0x08, 0x07)).with_initial_numbers(0, Some(start - 1));
+        let conn_id = ConnectionId::from_u64(9);
+        let seal = |cipher: &mut PacketCipher| {
+            let mut buffer = vec![0u8; 128];
+            let (_, len) = cipher
+                .seal_into(&conn_id, PacketFlags::default(), b"numbered", &mut buffer)
+                .expect("seal");
+            buffer.truncate(len);
+            buffer
+        };
+
+        // Nothing acknowledged: a million packets in flight need 3 bytes; one needs 1.
+        let unacked = seal(&mut send_cipher);
+        send_cipher.set_largest_acked(Some(start - 1));
+        let acked = seal(&mut send_cipher);
+        assert_eq!(unacked.len(), acked.len() + 2);
+
+        for (packet, packet_number) in [(unacked, start), (acked, start + 1)] {
+            let decrypted = recv_cipher.open(&packet).expect("open");
+            assert_eq!(decrypted.header().packet_number(), packet_number);
+            assert_eq!(decrypted.payload(), b"numbered");
+        }
+    }
+
+    #[test]
+    fn send_and_receive_nonces_differ_for_same_packet_number() {
+        let chaining_key = [0x42u8; 32];
+        let transcript = [0x24u8; 32];
+        let initiator =
+            derive_session_keys(HashAlgorithm::Sha256, &chaining_key, &transcript, true)
+                .expect("keys");
+        let responder =
+            derive_session_keys(HashAlgorithm::Sha256, &chaining_key, &transcript, false)
+                .expect("keys");
+
+        assert_ne!(initiator.send_iv(), initiator.receive_iv());
+        assert_eq!(initiator.send_iv(), responder.receive_iv());
+        assert_eq!(initiator.receive_iv(), responder.send_iv());
+
+        for packet_number in [0, 1, 0xFFFF, u64::MAX] {
+            let send = initiator.send_iv().for_packet(packet_number);
+            let receive = initiator.receive_iv().for_packet(packet_number);
+            assert_ne!(send, receive);
+            assert_eq!(send, responder.receive_iv().for_packet(packet_number));
+        }
+        assert_ne!(
+            initiator.send_iv().for_packet(1),
+            initiator.send_iv().for_packet(2)
+        );
+
+        // Both directions start at packet number 0 and still interoperate.
+        let mut client = PacketCipher::new(initiator);
+        let mut server = PacketCipher::new(responder);
+        let mut buffer = vec![0u8; 128];
+        let cid = ConnectionId::from_u64(7);
+        let (_, len) = client
+            .seal_into(&cid, PacketFlags::default(), b"ping", &mut buffer)
+            .expect("seal");
+        assert_eq!(
+            server.open(&buffer[..len]).expect("open").payload(),
+            b"ping"
+        );
+        let (_, len) = server
+            .seal_into(&cid, PacketFlags::default(), b"pong", &mut buffer)
+            .expect("seal");
+        assert_eq!(
+            client.open(&buffer[..len]).expect("open").payload(),
+            b"pong"
+        );
+    }
+
+    #[test]
+    fn aes_gcm_suite_roundtrip_and_mismatch() {
+        let aes = TransportParameters {
+            aead_ciphers: AeadCipherSet::default().with(AeadCipher::Aes256Gcm),
+            ..TransportParameters::default()
+        };
+        let suite = aes.negotiate(&aes).aead_cipher();
+        assert_eq!(suite, AeadCipher::Aes256Gcm);
+
+        let chaining_key = [0x17u8; 32];
+        let transcript = [0x71u8; 32];
+        let initiator =
+            derive_session_keys(HashAlgorithm::Sha256, &chaining_key, &transcript, true)
+                .expect("keys");
+        let responder =
+            derive_session_keys(HashAlgorithm::Sha256, &chaining_key, &transcript, false)
+                .expect("keys");
+
+        let mut client = PacketCipher::new(initiator).with_aead_cipher(suite);
+        let mut server = PacketCipher::new(responder.clone()).with_aead_cipher(suite);
+        let cid = ConnectionId::from_u64(9);
+        let mut buffer = vec![0u8; 128];
+        for payload in [&b"hello over gcm"[..], &[0xEE; 40][..]] {
+            let (pn, len) = client
+                .seal_into(&cid, PacketFlags::default(), payload, &mut buffer)
+                .expect("seal");
+            let opened = server.open(&buffer[..len]).expect("open");
+            assert_eq!(opened.header().packet_number(), pn);
+            assert_eq!(opened.payload(), payload);
+        }
+
+        // A peer still on ChaCha20-Poly1305 cannot open AES-GCM packets.
+        let (_, len) = client
+            .seal_into(&cid, PacketFlags::default(), b"mismatch", &mut buffer)
+            .expect("seal");
+        let mut chacha = PacketCipher::new(responder).with_initial_numbers(0, Some(1));
+        assert_eq!(chacha.aead_cipher(), AeadCipher::ChaCha20Poly1305);
+        assert!(matches!(
+            chacha.open(&buffer[..len]),
+            Err(TransportError::Crypto(CryptoError::AuthenticationFailed))
+        ));
+    }
 }
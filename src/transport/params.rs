@@ -0,0 +1,426 @@
+//! Transport parameters exchanged during the handshake.
+
+use std::fmt;
+use std::time::Duration;
+
+use super::crypto::{AeadCipher, AeadCipherSet};
+use super::packet::WIRE_VERSION;
+use crate::protocol;
+
+const ID_INITIAL_MAX_DATA: u8 = 0x01;
+const ID_INITIAL_MAX_STREAM_DATA: u8 = 0x02;
+const ID_MAX_STREAMS: u8 = 0x03;
+const ID_MAX_UDP_PAYLOAD_SIZE: u8 = 0x04;
+const ID_ACK_DELAY_EXPONENT: u8 = 0x05;
+const ID_MAX_IDLE_TIMEOUT: u8 = 0x06;
+const ID_AEAD_CIPHERS: u8 = 0x07;
+const ID_WIRE_VERSION: u8 = 0x08;
+
+/// Smallest UDP payload size a peer may advertise.
+pub const MIN_UDP_PAYLOAD_SIZE: u64 = 1200;
+/// Largest permitted ACK delay exponent.
+pub const MAX_ACK_DELAY_EXPONENT: u8 = 20;
+
+/// Errors produced when decoding transport parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransportParametersError {
+    /// Encoding ended in the middle of a parameter.
+    Truncated,
+    /// The same parameter appeared twice.
+    Duplicate(u8),
+    /// Parameter value is out of range.
+    InvalidValue(u8),
+}
+
+impl fmt::Display for TransportParametersError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => write!(f, "transport parameters truncated"),
+            Self::Duplicate(id) => write!(f, "duplicate transport parameter {id:#04x}"),
+            Self::InvalidValue(id) => write!(f, "invalid value for transport parameter {id:#04x}"),
+        }
+    }
+}
+
+impl std::error::Error for TransportParametersError {}
+
+/// Limits each endpoint advertises to its peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportParameters {
+    /// Connection-wide flow control limit in bytes.
+    pub initial_max_data: u64,
+    /// Per-stream flow control limit in bytes.
+    pub initial_max_stream_data: u64,
+    /// Maximum number of concurrently open streams.
+    pub max_streams: u64,
+    /// Largest UDP payload the endpoint is willing to receive.
+    pub max_udp_payload_size: u64,
+    /// Exponent applied to ACK delay values (`delay_micros >> exponent` on the wire).
+    pub ack_delay_exponent: u8,
+    /// Idle period after which the connection is closed.
+    pub max_idle_timeout: Duration,
+    /// AEAD cipher suites the endpoint accepts for packet protection.
+    pub aead_ciphers: AeadCipherSet,
+    /// Packet wire format the endpoint speaks, [`WIRE_VERSION`] for this build. Peers must
+    /// match exactly; one that omits the parameter predates it and reads as version 0.
+    pub wire_version: u8,
+}
+
+impl Default for TransportParameters {
+    fn default() -> Self {
+        Self {
+            initial_max_data: 1024 * 1024,
+            initial_max_stream_data: 256 * 1024,
+            max_streams: 100,
+            max_udp_payload_size: 1452,
+            ack_delay_exponent: 3,
+            max_idle_timeout: Duration::from_secs(30),
+            aead_ciphers: AeadCipherSet::default(),
+            wire_version: WIRE_VERSION,
+        }
+    }
+}
+
+impl TransportParameters {
+    /// Encode as a sequence of `[id (1)][len (1)][value (len bytes, LE)]` entries.
+    #[must_use]
+    pub fn encode(&self) -> Vec<u8> {
+        let idle_millis = u64::try_from(self.max_idle_timeout.as_millis()).unwrap_or(u64::MAX);
+        let mut out = Vec::with_capacity(8 * 10);
+        for (id, value) in [
+            (ID_INITIAL_MAX_DATA, self.initial_max_data),
+            (ID_INITIAL_MAX_STREAM_DATA, self.initial_max_stream_data),
+            (ID_MAX_STREAMS, self.max_streams),
+            (ID_MAX_UDP_PAYLOAD_SIZE, self.max_udp_payload_size),
+            (ID_ACK_DELAY_EXPONENT, u64::from(self.ack_delay_exponent)),
+            (ID_MAX_IDLE_TIMEOUT, idle_millis),
+            (ID_AEAD_CIPHERS, u64::from(self.aead_ciphers.bits())),
+            (ID_WIRE_VERSION, u64::from(self.wire_version)),
+        ] {
+            let len = value
+                .to_le_bytes()
+                .iter()
+                .rposition(|b| *b != 0)
+                .map_or(0, |i| i + 1);
+            out.push(id);
+            out.push(u8::try_from(len).expect("at most 8 value bytes"));
+            out.extend_from_slice(&value.to_le_bytes()[..len]);
+        }
+        out
+    }
+
+    /// Decode parameters; absent entries keep their defaults, except `wire_version`, which
+    /// reads as 0, and unknown IDs are skipped.
+    pub fn decode(mut bytes: &[u8]) -> Result<Self, TransportParametersError> {
+        let mut params = Self {
+            wire_version: 0,
+            ..Self::default()
+        };
+        let mut seen = [0u64; 4];
+        while !bytes.is_empty() {
+            let [id, len, rest @ ..] = bytes else {
+                return Err(TransportParametersError::Truncated);
+            };
+            let len = usize::from(*len);
+            if rest.len() < len {
+                return Err(TransportParametersError::Truncated);
+            }
+            let (value_bytes, tail) = rest.split_at(len);
+            bytes = tail;
+
+            let (word, bit) = (usize::from(id / 64), 1u64 << (id % 64));
+            if seen[word] & bit != 0 {
+                return Err(TransportParametersError::Duplicate(*id));
+            }
+            seen[word] |= bit;
+
+            let known = (ID_INITIAL_MAX_DATA..=ID_WIRE_VERSION).contains(id);
+            if !known {
+                continue;
+            }
+            if len > 8 {
+                return Err(TransportParametersError::InvalidValue(*id));
+            }
+            let mut raw = [0u8; 8];
+            raw[..len].copy_from_slice(value_bytes);
+            let value = u64::from_le_bytes(raw);
+
+            match *id {
+                ID_INITIAL_MAX_DATA => params.initial_max_data = value,
+                ID_INITIAL_MAX_STREAM_DATA => params.initial_max_stream_data = value,
+                ID_MAX_STREAMS => params.max_streams = value,
+                ID_MAX_UDP_PAYLOAD_SIZE => {
+                    if value < MIN_UDP_PAYLOAD_SIZE {
+                        return Err(TransportParametersError::InvalidValue(*id));
+                    }
+                    params.max_udp_payload_size = value;
+                }
+                ID_ACK_DELAY_EXPONENT => {
+                    params.ack_delay_exponent = u8::try_from(value)
+                        .ok()
+                        .filter(|exp| *exp <= MAX_ACK_DELAY_EXPONENT)
+                        .ok_or(TransportParametersError::InvalidValue(*id))?;
+                }
+                ID_AEAD_CIPHERS => {
+                    // Bits for suites this build does not know are ignored.
+                    params.aead_ciphers = AeadCipherSet::from_bits(value.to_le_bytes()[0]);
+                }
+                ID_WIRE_VERSION => {
+                    params.wire_version = u8::try_from(value)
+                        .map_err(|_| TransportParametersError::InvalidValue(*id))?;
+                }
+                _ => params.max_idle_timeout = Duration::from_millis(value),
+            }
+        }
+        Ok(params)
+    }
+
+    /// Check locally configured parameters before advertising them.
+    ///
+    /// Rejects zero flow-control windows, zero stream or idle limits, a UDP payload below
+    /// [`MIN_UDP_PAYLOAD_SIZE`] and an ACK delay exponent above [`MAX_ACK_DELAY_EXPONENT`].
+    /// [`decode`](Self::decode) stays lenient about zero limits a peer advertises.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`protocol::Error::Connection`] naming the first offending parameter.
+    pub fn validate(&self) -> protocol::Result<()> {
+        let invalid = |what: &str| {
+            Err(protocol::Error::Connection(format!(
+                "invalid transport parameter: {what}"
+            )))
+        };
+        if self.initial_max_data == 0 {
+            return invalid("initial_max_data must be non-zero");
+        }
+        if self.initial_max_stream_data == 0 {
+            return invalid("initial_max_stream_data must be non-zero");
+        }
+        if self.max_streams == 0 {
+            return invalid("max_streams must be non-zero");
+        }
+        if self.max_udp_payload_size < MIN_UDP_PAYLOAD_SIZE {
+            return invalid("max_udp_payload_size is below 1200 bytes");
+        }
+        if self.ack_delay_exponent > MAX_ACK_DELAY_EXPONENT {
+            return invalid("ack_delay_exponent exceeds 20");
+        }
+        if self.max_idle_timeout.is_zero() {
+            return invalid("max_idle_timeout must be non-zero");
+        }
+        if self.aead_ciphers.is_empty() {
+            return invalid("aead_ciphers must name at least one suite");
+        }
+        Ok(())
+    }
+
+    /// Combine local and peer parameters, taking the smaller of each limit and the cipher
+    /// suites both sides support. The handshake has already rejected a peer whose
+    /// `wire_version` differs, so the local one is kept.
+    #[must_use]
+    pub fn negotiate(&self, peer: &Self) -> Self {
+        Self {
+            initial_max_data: self.initial_max_data.min(peer.initial_max_data),
+            initial_max_stream_data: self
+                .initial_max_stream_data
+                .min(peer.initial_max_stream_data),
+            max_streams: self.max_streams.min(peer.max_streams),
+            max_udp_payload_size: self.max_udp_payload_size.min(peer.max_udp_payload_size),
+            ack_delay_exponent: self.ack_delay_exponent.min(peer.ack_delay_exponent),
+            max_idle_timeout: self.max_idle_timeout.min(peer.max_idle_timeout),
+            aead_ciphers: self.aead_ciphers.intersection(peer.aead_ciphers),
+            wire_version: self.wire_version,
+        }
+    }
+
+    /// AEAD suite to protect packets with once these parameters have been negotiated.
+    #[must_use]
+    pub const fn aead_cipher(&self) -> AeadCipher {
+        self.aead_ciphers.preferred()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let params = TransportParameters {
+            initial_max_data: u64::MAX,
+            initial_max_stream_data: 0,
+            max_streams: 7,
+            max_udp_payload_size: 65_527,
+            ack_delay_exponent: MAX_ACK_DELAY_EXPONENT,
+            max_idle_timeout: Duration::from_millis(1500),
+            aead_ciphers: AeadCipherSet::ALL,
+            wire_version: WIRE_VERSION,
+        };
+        let decoded = TransportParameters::decode(&params.encode()).expect("decode");
+        assert_eq!(decoded, params);
+        assert_eq!(
+            TransportParameters::decode(&[]).expect("empty"),
+            TransportParameters {
+                wire_version: 0,
+                ..TransportParameters::default()
+            }
+        );
+    }
+
+    #[test]
+    fn decode_skips_unknown_and_rejects_malformed() {
+        let mut bytes = vec![0x7F, 2, 0xAA, 0xBB];
+        bytes.extend_from_slice(&[ID_MAX_STREAMS, 1, 9]);
+        let decoded = TransportParameters::decode(&bytes).expect("decode");
+        assert_eq!(decoded.max_streams, 9);
+
+        // IDs 64 apart are distinct parameters, not duplicates.
+        let decoded = TransportParameters::decode(&[
+            ID_INITIAL_MAX_DATA,
+            1,
+            7,
+            ID_INITIAL_MAX_DATA + 0x40,
+            1,
+            1,
+            ID_INITIAL_MAX_DATA + 0x80,
+            0,
+        ])
+        .expect("decode");
+        assert_eq!(decoded.initial_max_data, 7);
+
+        assert_eq!(
+            TransportParameters::decode(&[ID_MAX_STREAMS, 2, 1]),
+            Err(TransportParametersError::Truncated)
+        );
+        assert_eq!(
+            TransportParameters::decode(&[ID_MAX_STREAMS, 1, 1, ID_MAX_STREAMS, 1, 2]),
+            Err(TransportParametersError::Duplicate(ID_MAX_STREAMS))
+        );
+        assert_eq!(
+            TransportParameters::decode(&[ID_ACK_DELAY_EXPONENT, 1, 21]),
+            Err(TransportParametersError::InvalidValue(
+                ID_ACK_DELAY_EXPONENT
+            ))
+        );
+        assert_eq!(
+            TransportParameters::decode(&[ID_MAX_UDP_PAYLOAD_SIZE, 1, 100]),
+            Err(TransportParametersError::InvalidValue(
+                ID_MAX_UDP_PAYLOAD_SIZE
+            ))
+        );
+        assert_eq!(
+            TransportParameters::decode(&[ID_WIRE_VERSION, 2, 0x05, 0x01]),
+            Err(TransportParametersError::InvalidValue(ID_WIRE_VERSION))
+        );
+    }
+
+    #[test]
+    fn validate_rejects_nonsensical_local_limits() {
+        TransportParameters::default()
+            .validate()
+            .expect("defaults are valid");
+        let custom = TransportParameters {
+            initial_max_data: 8 * 1024 * 1024,
+            initial_max_stream_data: 1024 * 1024,
+            max_streams: 1_000,
+            max_idle_timeout: Duration::from_secs(120),
+            ..TransportParameters::default()
+        };
+        custom.validate().expect("custom limits are valid");
+
+        let broken = [
+            TransportParameters {
+                initial_max_data: 0,
+                ..custom.clone()
+            },
+            TransportParameters {
+                initial_max_stream_data: 0,
+                ..custom.clone()
+            },
+            TransportParameters {
+                max_streams: 0,
+                ..custom.clone()
+            },
+            TransportParameters {
+                max_udp_payload_size: MIN_UDP_PAYLOAD_SIZE - 1,
+                ..custom.clone()
+            },
+            TransportParameters {
+                ack_delay_exponent: MAX_ACK_DELAY_EXPONENT + 1,
+                ..custom.clone()
+            },
+            TransportParameters {
+                max_idle_timeout: Duration::ZERO,
+                ..custom.clone()
+            },
+        ];
+        for params in broken {
+            assert!(
+                matches!(params.validate(), Err(protocol::Error::Connection(_))),
+                "{params:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn negotiate_takes_smaller_limits() {
+        let local = TransportParameters {
+            initial_max_data: 10,
+            max_streams: 50,
+            ack_delay_exponent: 2,
+            ..TransportParameters::default()
+        };
+        let peer = TransportParameters {
+            initial_max_stream_data: 5,
+            max_udp_payload_size: 1200,
+            max_idle_timeout: Duration::from_secs(5),
+            ..TransportParameters::default()
+        };
+        let negotiated = local.negotiate(&peer);
+        assert_eq!(negotiated, peer.negotiate(&local));
+        assert_eq!(negotiated.initial_max_data, 10);
+        assert_eq!(negotiated.initial_max_stream_data, 5);
+        assert_eq!(negotiated.max_streams, 50);
+        assert_eq!(negotiated.max_udp_payload_size, 1200);
+        assert_eq!(negotiated.ack_delay_exponent, 2);
+        assert_eq!(negotiated.max_idle_timeout, Duration::from_secs(5));
+        assert_eq!(negotiated.aead_cipher(), AeadCipher::ChaCha20Poly1305);
+    }
+
+    #[test]
+    fn aead_cipher_negotiated_from_common_suites() {
+        let aes = TransportParameters {
+            aead_ciphers: AeadCipherSet::default().with(AeadCipher::Aes256Gcm),
+            ..TransportParameters::default()
+        };
+        let chacha_only = TransportParameters::default();
+        assert_eq!(aes.negotiate(&aes).aead_cipher(), AeadCipher::Aes256Gcm);
+        assert_eq!(
+            aes.negotiate(&chacha_only).aead_cipher(),
+            AeadCipher::ChaCha20Poly1305
+        );
+
+        // Unknown suite bits are dropped; ChaCha20-Poly1305 is not implied.
+        let decoded = TransportParameters::decode(&[ID_AEAD_CIPHERS, 1, 0xF2]).expect("decode");
+        assert_eq!(
+            decoded.aead_ciphers,
+            AeadCipherSet::only(AeadCipher::Aes256Gcm)
+        );
+        let decoded = TransportParameters::decode(&[ID_AEAD_CIPHERS, 1, 0xF3]).expect("decode");
+        assert_eq!(decoded.aead_ciphers, AeadCipherSet::ALL);
+
+        let aes_only = TransportParameters {
+            aead_ciphers: AeadCipherSet::only(AeadCipher::Aes256Gcm),
+            ..TransportParameters::default()
+        };
+        assert!(aes_only.negotiate(&chacha_only).aead_ciphers.is_empty());
+        assert!(
+            TransportParameters {
+                aead_ciphers: AeadCipherSet::from_bits(0),
+                ..TransportParameters::default()
+            }
+            .validate()
+            .is_err()
+        );
+    }
+}
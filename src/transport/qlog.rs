@@ -0,0 +1,327 @@
+//! Structured transport event logging in a qlog-like JSON-lines format.
+//!
+//! Components that accept a [`SharedEventLogger`] report packet, recovery, and flow control
+//! events to it; nothing is logged globally. [`JsonLinesLogger`] writes one JSON object per
+//! line: a header record carrying the reference time, then events of the form
+//! `{"time": <ms since reference>, "name": "<category>:<event>", "data": {...}}`. Wrapping
+//! the events in a qlog `traces[0].events` array is enough for qvis to load the file.
+
+use std::fmt::{self, Write as _};
+use std::io::Write;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::debug;
+
+use super::ack::AckRange;
+use super::congestion::CongestionControl;
+use super::loss::AckOutcome;
+use super::packet::PacketFlags;
+
+/// qlog version advertised in the header record.
+pub const QLOG_VERSION: &str = "0.3";
+
+/// Event logger shared between the components of one connection.
+pub type SharedEventLogger = Arc<dyn EventLogger>;
+
+/// Why a packet was declared lost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LossTrigger {
+    /// Enough later packets were acknowledged.
+    ReorderingThreshold,
+    /// The packet was outstanding longer than the time threshold.
+    TimeThreshold,
+}
+
+impl LossTrigger {
+    /// qlog name of the trigger.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::ReorderingThreshold => "reordering_threshold",
+            Self::TimeThreshold => "time_threshold",
+        }
+    }
+}
+
+/// Transport event reported to an [`EventLogger`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum QlogEvent {
+    /// A packet was sealed for transmission.
+    PacketSent {
+        /// Packet number.
+        packet_number: u64,
+        /// Encoded size in bytes.
+        size: usize,
+        /// Packet header flags.
+        flags: PacketFlags,
+    },
+    /// A packet was opened successfully.
+    PacketReceived {
+        /// Packet number.
+        packet_number: u64,
+        /// Encoded size in bytes.
+        size: usize,
+        /// Packet header flags.
+        flags: PacketFlags,
+    },
+    /// A sent packet was declared lost.
+    PacketLost {
+        /// Packet number.
+        packet_number: u64,
+        /// Detection mechanism that declared the loss.
+        trigger: LossTrigger,
+    },
+    /// An ACK frame was processed.
+    AckReceived {
+        /// Acknowledged packet number ranges.
+        ranges: Vec<AckRange>,
+        /// RTT sample taken from the ACK, if any.
+        rtt: Option<Duration>,
+    },
+    /// The congestion window changed.
+    CwndUpdated {
+        /// New congestion window in bytes.
+        congestion_window: usize,
+        /// Bytes in flight after the update.
+        bytes_in_flight: usize,
+    },
+    /// A send was refused by flow control.
+    FlowBlocked {
+        /// Stream whose send was refused.
+        stream_id: u64,
+        /// Bytes the window still allowed.
+        available: u64,
+        /// Bytes the caller tried to send.
+        attempted: u64,
+    },
+}
+
+impl QlogEvent {
+    /// qlog event name, `<category>:<event>`.
+    #[must_use]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::PacketSent { .. } => "transport:packet_sent",
+            Self::PacketReceived { .. } => "transport:packet_received",
+            Self::PacketLost { .. } => "recovery:packet_lost",
+            Self::AckReceived { .. } => "recovery:ack_received",
+            Self::CwndUpdated { .. } => "recovery:metrics_updated",
+            Self::FlowBlocked { .. } => "transport:flow_blocked",
+        }
+    }
+
+    /// Render the event's `data` object as JSON.
+    #[must_use]
+    pub fn data_json(&self) -> String {
+        match self {
+            Self::PacketSent {
+                packet_number,
+                size,
+                flags,
+            }
+            | Self::PacketReceived {
+                packet_number,
+                size,
+                flags,
+            } => format!(
+                r#"{{"header":{{"packet_number":{packet_number},"flags":{}}},"raw":{{"length":{size}}}}}"#,
+                flags.bits()
+            ),
+            Self::PacketLost {
+                packet_number,
+                trigger,
+            } => format!(
+                r#"{{"header":{{"packet_number":{packet_number}}},"trigger":"{}"}}"#,
+                trigger.as_str()
+            ),
+            Self::AckReceived { ranges, rtt } => {
+                let mut out = String::from(r#"{"acked_ranges":["#);
+                for (idx, range) in ranges.iter().enumerate() {
+                    if idx > 0 {
+                        out.push(',');
+                    }
+                    let _ = write!(out, "[{},{}]", range.start(), range.end());
+                }
+                out.push(']');
+                if let Some(rtt) = rtt {
+                    let _ = write!(out, r#","latest_rtt":{}"#, millis(*rtt));
+                }
+                out.push('}');
+                out
+            }
+            Self::CwndUpdated {
+                congestion_window,
+                bytes_in_flight,
+            } => format!(
+                r#"{{"congestion_window":{congestion_window},"bytes_in_flight":{bytes_in_flight}}}"#
+            ),
+            Self::FlowBlocked {
+                stream_id,
+                available,
+                attempted,
+            } => format!(
+                r#"{{"stream_id":{stream_id},"available":{available},"attempted":{attempted}}}"#
+            ),
+        }
+    }
+}
+
+/// Milliseconds with microsecond precision, as qlog expects.
+fn millis(duration: Duration) -> String {
+    format!(
+        "{}.{:03}",
+        duration.as_millis(),
+        duration.subsec_micros() % 1000
+    )
+}
+
+/// Sink for structured transport events.
+pub trait EventLogger: fmt::Debug + Send + Sync {
+    /// Record `event`, which happened at `time`.
+    fn log(&self, time: SystemTime, event: &QlogEvent);
+}
+
+#[derive(Debug)]
+struct JsonLinesState<W> {
+    writer: W,
+    reference: Option<SystemTime>,
+}
+
+/// [`EventLogger`] writing newline-delimited JSON records.
+#[derive(Debug)]
+pub struct JsonLinesLogger<W> {
+    state: Mutex<JsonLinesState<W>>,
+}
+
+impl<W: Write> JsonLinesLogger<W> {
+    /// Log to `writer`; the header record is written with the first event.
+    pub fn new(writer: W) -> Self {
+        Self {
+            state: Mutex::new(JsonLinesState {
+                writer,
+                reference: None,
+            }),
+        }
+    }
+
+    /// Return the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.state
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .writer
+    }
+}
+
+impl<W: Write + fmt::Debug + Send> EventLogger for JsonLinesLogger<W> {
+    fn log(&self, time: SystemTime, event: &QlogEvent) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let reference = if let Some(reference) = state.reference {
+            reference
+        } else {
+            let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let header = format!(
+                r#"{{"qlog_version":"{QLOG_VERSION}","qlog_format":"JSON-SEQ","trace":{{"common_fields":{{"reference_time":{}}}}}}}"#,
+                millis(since_epoch)
+            );
+            if let Err(err) = writeln!(state.writer, "{header}") {
+                debug!(error = %err, "failed to write qlog header");
+            }
+            state.reference = Some(time);
+            time
+        };
+        let elapsed = time.duration_since(reference).unwrap_or_default();
+        if let Err(err) = writeln!(
+            state.writer,
+            r#"{{"time":{},"name":"{}","data":{}}}"#,
+            millis(elapsed),
+            event.name(),
+            event.data_json()
+        ) {
+            debug!(error = %err, "failed to write qlog event");
+        }
+    }
+}
+
+/// Congestion controller wrapper reporting window changes after each ACK outcome.
+#[derive(Debug)]
+pub(crate) struct LoggedCongestionControl {
+    inner: Box<dyn CongestionControl>,
+    logger: SharedEventLogger,
+}
+
+impl LoggedCongestionControl {
+    pub(crate) fn new(inner: Box<dyn CongestionControl>, logger: SharedEventLogger) -> Self {
+        Self { inner, logger }
+    }
+}
+
+impl CongestionControl for LoggedCongestionControl {
+    fn on_packet_sent(&mut self, size: usize) {
+        self.inner.on_packet_sent(size);
+    }
+
+    fn on_ack_outcome(&mut self, outcome: &AckOutcome, now: SystemTime) {
+        let before = self.inner.window();
+        self.inner.on_ack_outcome(outcome, now);
+        let congestion_window = self.inner.window();
+        if congestion_window != before {
+            self.logger.log(
+                now,
+                &QlogEvent::CwndUpdated {
+                    congestion_window,
+                    bytes_in_flight: self.inner.bytes_in_flight(),
+                },
+            );
+        }
+    }
+
+    fn window(&self) -> usize {
+        self.inner.window()
+    }
+
+    fn pacing_rate(&self) -> f64 {
+        self.inner.pacing_rate()
+    }
+
+    fn bytes_in_flight(&self) -> usize {
+        self.inner.bytes_in_flight()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_lines_start_with_header_and_relative_times() {
+        let logger = JsonLinesLogger::new(Vec::new());
+        let start = UNIX_EPOCH + Duration::from_secs(5);
+        logger.log(
+            start,
+            &QlogEvent::PacketSent {
+                packet_number: 1,
+                size: 1200,
+                flags: PacketFlags::from_bits(PacketFlags::ACK_ELICITING),
+            },
+        );
+        logger.log(
+            start + Duration::from_micros(2_500),
+            &QlogEvent::AckReceived {
+                ranges: vec![AckRange::new(0, 1).unwrap()],
+                rtt: Some(Duration::from_millis(20)),
+            },
+        );
+
+        let output = String::from_utf8(logger.into_inner()).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains(r#""reference_time":5000.000"#));
+        assert!(lines[1].starts_with(r#"{"time":0.000,"name":"transport:packet_sent""#));
+        assert_eq!(
+            lines[2],
+            r#"{"time":2.500,"name":"recovery:ack_received","data":{"acked_ranges":[[0,1]],"latest_rtt":20.000}}"#
+        );
+    }
+}
@@ -0,0 +1,152 @@
+//! Stateless retry tokens used to validate a peer's address before the handshake proceeds.
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::crypto::hmac_sha256;
+
+/// Length of the retry token MAC in bytes.
+const RETRY_MAC_LEN: usize = 32;
+/// Length of encoded retry tokens in bytes: issue timestamp followed by the MAC.
+pub const RETRY_TOKEN_LEN: usize = 8 + RETRY_MAC_LEN;
+/// Length of the responder's retry secret in bytes.
+pub const RETRY_SECRET_LEN: usize = 32;
+
+/// Responder configuration for address validation via retry tokens.
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Secret keying the token MAC; never leaves the responder.
+    pub secret: [u8; RETRY_SECRET_LEN],
+    /// Maximum age of a token that is still accepted.
+    pub lifetime: Duration,
+}
+
+impl RetryConfig {
+    /// Create a configuration with the default ten second token lifetime.
+    #[must_use]
+    pub fn new(secret: [u8; RETRY_SECRET_LEN]) -> Self {
+        Self {
+            secret,
+            lifetime: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Token proving the initiator can receive traffic at its claimed address.
+///
+/// The responder keeps no per-token state: the token is a MAC over the peer address and the
+/// issue time, so validation only needs the responder secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryToken {
+    issued_at: u64,
+    mac: [u8; RETRY_MAC_LEN],
+}
+
+impl RetryToken {
+    /// Issue a token binding `peer` to the issue time `now`.
+    #[must_use]
+    pub fn issue(secret: &[u8; RETRY_SECRET_LEN], peer: &SocketAddr, now: SystemTime) -> Self {
+        let issued_at = unix_seconds(now);
+        Self {
+            issued_at,
+            mac: compute_mac(secret, peer, issued_at),
+        }
+    }
+
+    /// Encode the token. Format: [timestamp (u64 LE)][mac (32)].
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; RETRY_TOKEN_LEN] {
+        let mut out = [0u8; RETRY_TOKEN_LEN];
+        out[..8].copy_from_slice(&self.issued_at.to_le_bytes());
+        out[8..].copy_from_slice(&self.mac);
+        out
+    }
+
+    /// Decode a token, returning `None` when the length is wrong.
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != RETRY_TOKEN_LEN {
+            return None;
+        }
+        let mut issued_at = [0u8; 8];
+        issued_at.copy_from_slice(&bytes[..8]);
+        let mut mac = [0u8; RETRY_MAC_LEN];
+        mac.copy_from_slice(&bytes[8..]);
+        Some(Self {
+            issued_at: u64::from_le_bytes(issued_at),
+            mac,
+        })
+    }
+
+    /// Check the token was issued for `peer` under `config` and has not expired at `now`.
+    #[must_use]
+    pub fn validate(&self, config: &RetryConfig, peer: &SocketAddr, now: SystemTime) -> bool {
+        let now = unix_seconds(now);
+        if self.issued_at > now || now - self.issued_at > config.lifetime.as_secs() {
+            return false;
+        }
+        let expected = compute_mac(&config.secret, peer, self.issued_at);
+        constant_time_eq(&expected, &self.mac)
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+fn compute_mac(secret: &[u8], peer: &SocketAddr, issued_at: u64) -> [u8; RETRY_MAC_LEN] {
+    let mut data = Vec::with_capacity(16 + 2 + 8);
+    match peer.ip() {
+        IpAddr::V4(ip) => data.extend_from_slice(&ip.octets()),
+        IpAddr::V6(ip) => data.extend_from_slice(&ip.octets()),
+    }
+    data.extend_from_slice(&peer.port().to_be_bytes());
+    data.extend_from_slice(&issued_at.to_le_bytes());
+    hmac_sha256(secret, &data)
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> SocketAddr {
+        "192.0.2.7:4433".parse().expect("addr")
+    }
+
+    #[test]
+    fn token_roundtrip_validates() {
+        let config = RetryConfig::new([0x5Au8; RETRY_SECRET_LEN]);
+        let now = SystemTime::now();
+        let token = RetryToken::issue(&config.secret, &peer(), now);
+        let decoded = RetryToken::from_bytes(&token.to_bytes()).expect("decode");
+        assert_eq!(decoded, token);
+        assert!(decoded.validate(&config, &peer(), now));
+    }
+
+    #[test]
+    fn token_rejected_for_other_address_secret_or_age() {
+        let config = RetryConfig::new([0x5Au8; RETRY_SECRET_LEN]);
+        let now = SystemTime::now();
+        let token = RetryToken::issue(&config.secret, &peer(), now);
+
+        let other_peer: SocketAddr = "192.0.2.8:4433".parse().expect("addr");
+        assert!(!token.validate(&config, &other_peer, now));
+
+        let other_secret = RetryConfig::new([0xA5u8; RETRY_SECRET_LEN]);
+        assert!(!token.validate(&other_secret, &peer(), now));
+
+        let later = now + config.lifetime + Duration::from_secs(1);
+        assert!(!token.validate(&config, &peer(), later));
+
+        let mut bytes = token.to_bytes();
+        bytes[RETRY_TOKEN_LEN - 1] ^= 0x01;
+        let tampered = RetryToken::from_bytes(&bytes).expect("decode");
+        assert!(!tampered.validate(&config, &peer(), now));
+    }
+}
@@ -1,13 +1,23 @@
 //! Priority-aware scheduling for streams and datagrams.
+//!
+//! [`Scheduler::pop_stream`] serves streams by [`PriorityClass`], with a stream's effective
+//! weight growing the longer it waits ([`SchedulerConfig::aging_rate`]) so a steady flow of
+//! higher-priority traffic cannot starve lower classes forever;
+//! [`Scheduler::pop_stream_bytes`] instead shares bytes between classes in proportion to their
+//! weights (weighted fair queueing), so no class can starve another. Within a class the stream
+//! that has sent the fewest bytes ([`Scheduler::record_sent`]) goes first, so a stream that
+//! keeps re-queueing cannot starve its peers; streams that never report bytes rotate FIFO.
 
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, VecDeque};
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::time::SystemTime;
 
 use super::stream::StreamId;
 
 #[cfg(test)]
 use super::stream::{EndpointRole, StreamKind};
 use crate::protocol::metrics::{self, SchedulerPriority};
+use crate::protocol::{Message, Priority};
 use tracing::trace;
 
 /// Priority class for outbound transmissions.
@@ -21,28 +31,85 @@ pub enum PriorityClass {
     Bulk,
 }
 
+/// Classes in strict priority order.
+const CLASSES: [PriorityClass; 3] = [
+    PriorityClass::Control,
+    PriorityClass::Interactive,
+    PriorityClass::Bulk,
+];
+
+/// Virtual time a class advances per byte is `VTIME_SCALE / weight`.
+const VTIME_SCALE: u64 = 100;
+
+/// Scheduler tuning.
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    /// Weight a queued stream gains for every stream dequeued ahead of it.
+    ///
+    /// With the default of 1, a waiting Bulk stream (weight 10) overtakes fresh Control
+    /// streams (weight 100) after 90 dequeues. Zero disables aging (strict priority).
+    pub aging_rate: u32,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self { aging_rate: 1 }
+    }
+}
+
 impl PriorityClass {
-    const fn weight(self) -> u32 {
+    /// Relative share of bandwidth under weighted fair queueing.
+    #[must_use]
+    pub const fn weight(self) -> u32 {
         match self {
             Self::Control => 100,
             Self::Interactive => 50,
             Self::Bulk => 10,
         }
     }
+
+    /// Position in strict priority order (0 is served first).
+    pub(crate) const fn index(self) -> usize {
+        match self {
+            Self::Control => 0,
+            Self::Interactive => 1,
+            Self::Bulk => 2,
+        }
+    }
+
+    /// Virtual time consumed by sending `bytes` in this class.
+    fn vtime_cost(self, bytes: u64) -> u64 {
+        bytes.saturating_mul(VTIME_SCALE) / u64::from(self.weight())
+    }
 }
 
 /// Queue entry representing a stream ready to transmit.
 #[derive(Debug)]
 struct StreamEntry {
-    weight: u32,
+    /// Bytes the stream had been credited with when queued.
+    served: u64,
     sequence: u64,
+    /// Dequeue count when the stream was queued, used for aging.
+    enqueued_at: u64,
     id: StreamId,
     priority: PriorityClass,
+    deadline: Option<SystemTime>,
+}
+
+/// Queued datagram payload with an optional deadline.
+#[derive(Debug)]
+struct DatagramEntry {
+    payload: Vec<u8>,
+    deadline: Option<SystemTime>,
+}
+
+fn is_expired(deadline: Option<SystemTime>, now: SystemTime) -> bool {
+    deadline.is_some_and(|deadline| deadline <= now)
 }
 
 impl PartialEq for StreamEntry {
     fn eq(&self, other: &Self) -> bool {
-        self.weight == other.weight && self.sequence == other.sequence && self.id == other.id
+        self.served == other.served && self.sequence == other.sequence && self.id == other.id
     }
 }
 
@@ -56,19 +123,35 @@ impl PartialOrd for StreamEntry {
 
 impl Ord for StreamEntry {
     fn cmp(&self, other: &Self) -> Ordering {
-        match self.weight.cmp(&other.weight) {
-            Ordering::Equal => self.sequence.cmp(&other.sequence).reverse(),
-            ordering => ordering,
-        }
+        self.served
+            .cmp(&other.served)
+            .reverse()
+            .then_with(|| self.sequence.cmp(&other.sequence).reverse())
     }
 }
 
 /// Scheduler tracking active streams and datagram queue.
 #[derive(Debug)]
 pub struct Scheduler {
-    streams: BinaryHeap<StreamEntry>,
-    datagrams: VecDeque<Vec<u8>>,
+    config: SchedulerConfig,
+    /// Ready streams, one queue per class indexed by [`PriorityClass::index`].
+    streams: [BinaryHeap<StreamEntry>; 3],
+    datagrams: VecDeque<DatagramEntry>,
     sequence: u64,
+    /// Streams dequeued so far; the clock for aging.
+    dequeues: u64,
+    expired_drops: u64,
+    /// Bytes sent per stream and the class they were charged to.
+    served: HashMap<StreamId, (u64, PriorityClass)>,
+    /// Per-class `served` value of the last stream dequeued; newly active streams start here
+    /// so time spent idle does not bank credit.
+    class_floor: [u64; 3],
+    /// Bytes sent per class.
+    class_bytes: [u64; 3],
+    /// Weighted virtual time per class; the class furthest behind is served next.
+    class_vtime: [u64; 3],
+    /// Virtual time of the last class dequeued; classes becoming active start here.
+    virtual_time: u64,
 }
 
 impl Default for Scheduler {
@@ -81,15 +164,48 @@ impl Scheduler {
     /// Construct an empty scheduler.
     #[must_use]
     pub fn new() -> Self {
+        Self::with_config(SchedulerConfig::default())
+    }
+
+    /// Construct an empty scheduler with custom tuning.
+    #[must_use]
+    pub fn with_config(config: SchedulerConfig) -> Self {
         Self {
-            streams: BinaryHeap::new(),
+            config,
+            streams: Default::default(),
             datagrams: VecDeque::new(),
             sequence: 0,
+            dequeues: 0,
+            expired_drops: 0,
+            served: HashMap::new(),
+            class_floor: [0; 3],
+            class_bytes: [0; 3],
+            class_vtime: [0; 3],
+            virtual_time: 0,
         }
     }
 
     /// Register a stream ready to send.
     pub fn push_stream(&mut self, id: StreamId, priority: PriorityClass) {
+        self.enqueue_stream(id, priority, None);
+    }
+
+    /// Register a stream whose pending send is abandoned once `deadline` passes.
+    pub fn push_stream_with_deadline(
+        &mut self,
+        id: StreamId,
+        priority: PriorityClass,
+        deadline: SystemTime,
+    ) {
+        self.enqueue_stream(id, priority, Some(deadline));
+    }
+
+    fn enqueue_stream(
+        &mut self,
+        id: StreamId,
+        priority: PriorityClass,
+        deadline: Option<SystemTime>,
+    ) {
         self.sequence = self.sequence.wrapping_add(1);
         trace!(
             stream = id.as_u64(),
@@ -97,32 +213,162 @@ impl Scheduler {
             "enqueue stream for scheduling"
         );
         metrics::Metrics::record_scheduler_enqueue(priority.into());
-        self.streams.push(StreamEntry {
+        let class = priority.index();
+        if self.streams[class].is_empty() {
+            self.class_vtime[class] = self.class_vtime[class].max(self.virtual_time);
+        }
+        let floor = self.class_floor[class];
+        let (served, charged) = self.served.entry(id).or_insert((0, priority));
+        *served = (*served).max(floor);
+        *charged = priority;
+        self.streams[class].push(StreamEntry {
             priority,
-            weight: priority.weight(),
+            served: *served,
             sequence: self.sequence,
+            enqueued_at: self.dequeues,
             id,
+            deadline,
         });
     }
 
+    /// Register a stream carrying `message`, using the message's header priority and deadline.
+    pub fn push_message(&mut self, id: StreamId, message: &Message) {
+        self.enqueue_stream(id, message.priority().into(), message.deadline());
+    }
+
     /// Register an outbound datagram payload.
     pub fn push_datagram(&mut self, payload: Vec<u8>) {
         trace!(len = payload.len(), "enqueue datagram");
-        self.datagrams.push_back(payload);
+        self.datagrams.push_back(DatagramEntry {
+            payload,
+            deadline: None,
+        });
+    }
+
+    /// Register an outbound datagram payload that is dropped once `deadline` passes.
+    pub fn push_datagram_with_deadline(&mut self, payload: Vec<u8>, deadline: SystemTime) {
+        trace!(len = payload.len(), "enqueue datagram with deadline");
+        self.datagrams.push_back(DatagramEntry {
+            payload,
+            deadline: Some(deadline),
+        });
+    }
+
+    /// Drop every queued stream and datagram whose deadline has passed.
+    ///
+    /// Returns the number of entries removed.
+    pub fn drop_expired(&mut self, now: SystemTime) -> usize {
+        let before = self.queued_streams() + self.datagrams.len();
+        for queue in &mut self.streams {
+            queue.retain(|entry| !is_expired(entry.deadline, now));
+        }
+        self.datagrams
+            .retain(|entry| !is_expired(entry.deadline, now));
+        let dropped = before - (self.queued_streams() + self.datagrams.len());
+        if dropped > 0 {
+            trace!(dropped, "dropped expired sends");
+            self.expired_drops = self.expired_drops.saturating_add(dropped as u64);
+        }
+        dropped
     }
 
-    /// Pop the highest priority stream, if any.
+    /// Total number of sends dropped because their deadline passed.
+    #[must_use]
+    pub const fn expired_drops(&self) -> u64 {
+        self.expired_drops
+    }
+
+    fn queued_streams(&self) -> usize {
+        self.streams.iter().map(BinaryHeap::len).sum()
+    }
+
+    /// Pop the highest priority stream, if any; within a class, the least-served stream.
+    ///
+    /// Classes are compared by the effective weight of their next stream: its class weight
+    /// plus [`SchedulerConfig::aging_rate`] for every dequeue since it was queued. Ties go to
+    /// the higher class.
     pub fn pop_stream(&mut self) -> Option<(StreamId, PriorityClass)> {
-        self.streams.pop().map(|entry| {
+        let mut best: Option<(PriorityClass, u64)> = None;
+        for class in CLASSES {
+            let Some(head) = self.streams[class.index()].peek() else {
+                continue;
+            };
+            let weight = self.effective_weight(head);
+            if best.is_none_or(|(_, best_weight)| weight > best_weight) {
+                best = Some((class, weight));
+            }
+        }
+        let (class, _) = best?;
+        self.pop_from_class(class)
+    }
+
+    fn effective_weight(&self, entry: &StreamEntry) -> u64 {
+        let waited = self.dequeues - entry.enqueued_at;
+        u64::from(entry.priority.weight())
+            .saturating_add(u64::from(self.config.aging_rate).saturating_mul(waited))
+    }
+
+    /// Pop a stream about to send up to `max_bytes`, sharing bytes between classes by weight.
+    ///
+    /// The class whose weighted byte count would finish earliest after sending `max_bytes` is
+    /// chosen; within it, the least-served stream. Report the bytes actually transmitted with
+    /// [`record_sent`](Self::record_sent) so the class is charged for them.
+    pub fn pop_stream_bytes(&mut self, max_bytes: usize) -> Option<(StreamId, PriorityClass)> {
+        let class = CLASSES
+            .into_iter()
+            .filter(|class| !self.streams[class.index()].is_empty())
+            .min_by_key(|class| {
+                self.class_vtime[class.index()].saturating_add(class.vtime_cost(max_bytes as u64))
+            })?;
+        self.virtual_time = self.class_vtime[class.index()];
+        self.pop_from_class(class)
+    }
+
+    fn pop_from_class(&mut self, class: PriorityClass) -> Option<(StreamId, PriorityClass)> {
+        let queue = &mut self.streams[class.index()];
+        loop {
+            let mut entry = queue.pop()?;
+            let served = self.served.get(&entry.id).map_or(0, |(served, _)| *served);
+            if entry.served < served {
+                // Queued more than once and has sent since: requeue at its current share.
+                entry.served = served;
+                queue.push(entry);
+                continue;
+            }
+            self.class_floor[class.index()] = entry.served;
+            self.dequeues += 1;
             trace!(stream = entry.id.as_u64(), ?entry.priority, "dequeue stream for transmit");
             metrics::Metrics::record_scheduler_dequeue(entry.priority.into());
-            (entry.id, entry.priority)
-        })
+            return Some((entry.id, entry.priority));
+        }
+    }
+
+    /// Charge `bytes` sent on stream `id` against its share of its priority class.
+    ///
+    /// Streams never queued are charged to [`PriorityClass::Bulk`].
+    pub fn record_sent(&mut self, id: StreamId, bytes: usize) {
+        let bytes = bytes as u64;
+        let (served, class) = self.served.entry(id).or_insert((0, PriorityClass::Bulk));
+        *served = served.saturating_add(bytes);
+        let index = class.index();
+        self.class_bytes[index] = self.class_bytes[index].saturating_add(bytes);
+        self.class_vtime[index] = self.class_vtime[index].saturating_add(class.vtime_cost(bytes));
+    }
+
+    /// Total bytes reported via [`record_sent`](Self::record_sent) for streams in `priority`.
+    #[must_use]
+    pub const fn class_bytes(&self, priority: PriorityClass) -> u64 {
+        self.class_bytes[priority.index()]
+    }
+
+    /// Forget fairness state for a closed stream.
+    pub fn remove_stream(&mut self, id: StreamId) {
+        self.served.remove(&id);
     }
 
     /// Pop the oldest datagram payload.
     pub fn pop_datagram(&mut self) -> Option<Vec<u8>> {
-        let datagram = self.datagrams.pop_front();
+        let datagram = self.datagrams.pop_front().map(|entry| entry.payload);
         if let Some(ref payload) = datagram {
             trace!(len = payload.len(), "dequeue datagram");
         }
@@ -132,7 +378,7 @@ impl Scheduler {
     /// Check whether any streams are queued.
     #[must_use]
     pub fn has_streams(&self) -> bool {
-        !self.streams.is_empty()
+        self.streams.iter().any(|queue| !queue.is_empty())
     }
 
     /// Check whether datagrams are waiting to send.
@@ -142,6 +388,16 @@ impl Scheduler {
     }
 }
 
+impl From<Priority> for PriorityClass {
+    fn from(value: Priority) -> Self {
+        match value {
+            Priority::Control => Self::Control,
+            Priority::Interactive => Self::Interactive,
+            Priority::Bulk => Self::Bulk,
+        }
+    }
+}
+
 impl From<PriorityClass> for SchedulerPriority {
     fn from(value: PriorityClass) -> Self {
         match value {
@@ -170,6 +426,200 @@ mod tests {
         assert_eq!(second.0, stream_a);
     }
 
+    #[test]
+    fn scheduler_orders_by_message_priority() {
+        use crate::protocol::MessageType;
+
+        let mut scheduler = Scheduler::new();
+        let messages = [
+            (MessageType::StreamChunk, Priority::Bulk),
+            (MessageType::Call, Priority::Interactive),
+            (MessageType::AgentHeartbeat, Priority::Control),
+        ];
+        for (index, (msg_type, priority)) in (0u64..).zip(messages) {
+            let message = Message::builder(msg_type).priority(priority).build();
+            let id = StreamId::new(EndpointRole::Client, StreamKind::Unidirectional, index);
+            scheduler.push_message(id, &message);
+        }
+
+        let order: Vec<_> = std::iter::from_fn(|| scheduler.pop_stream())
+            .map(|(_, priority)| priority)
+            .collect();
+        assert_eq!(
+            order,
+            [
+                PriorityClass::Control,
+                PriorityClass::Interactive,
+                PriorityClass::Bulk
+            ]
+        );
+    }
+
+    #[test]
+    fn stream_open_priorities_are_honored_on_both_sides() {
+        use crate::protocol::StreamOpen;
+        use crate::transport::stream::StreamManager;
+
+        let bulk = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        let control = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        let opens = [
+            StreamOpen::new(bulk.as_u64(), Priority::Bulk, "file").unwrap(),
+            StreamOpen::new(control.as_u64(), Priority::Control, "control").unwrap(),
+        ];
+
+        let mut sender = StreamManager::new(EndpointRole::Client);
+        let mut receiver = StreamManager::new(EndpointRole::Server);
+        let mut scheduler = Scheduler::new();
+        for open in &opens {
+            let id = sender.open_with_priority(open).unwrap();
+            sender.queue_send(id, b"payload").unwrap();
+            scheduler.push_stream(id, sender.priority(id));
+
+            let announced = StreamOpen::from_message(&open.to_message()).unwrap();
+            receiver.open_with_priority(&announced).unwrap();
+        }
+
+        // The sender serves the control stream first even though it was opened second...
+        let order: Vec<_> = std::iter::from_fn(|| scheduler.pop_stream())
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(order, [control, bulk]);
+
+        // ...and the receiver delivers it first regardless of arrival order.
+        for id in [bulk, control] {
+            receiver.ingest(id, 0, b"payload", false).unwrap();
+        }
+        assert_eq!(receiver.priority(control), PriorityClass::Control);
+        assert_eq!(receiver.readable_streams(), [control, bulk]);
+        receiver.read(control, usize::MAX).unwrap();
+        assert_eq!(receiver.readable_streams(), [bulk]);
+    }
+
+    #[test]
+    fn equal_priority_streams_share_bytes_fairly() {
+        let mut scheduler = Scheduler::new();
+        let large = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        let small = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 2);
+        let burst = |id| if id == large { 4000 } else { 1000 };
+        scheduler.push_stream(large, PriorityClass::Bulk);
+        scheduler.push_stream(small, PriorityClass::Bulk);
+
+        let mut sent = HashMap::new();
+        for _ in 0..100 {
+            let (id, _) = scheduler.pop_stream().expect("stream ready");
+            scheduler.record_sent(id, burst(id));
+            *sent.entry(id).or_insert(0) += burst(id);
+            // Both streams always have more to send.
+            scheduler.push_stream(id, PriorityClass::Bulk);
+        }
+        assert!(sent[&large].abs_diff(sent[&small]) <= 4000, "{sent:?}");
+    }
+
+    #[test]
+    fn repeatedly_queued_stream_does_not_monopolize() {
+        let mut scheduler = Scheduler::new();
+        let eager = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        let patient = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 2);
+        for _ in 0..5 {
+            scheduler.push_stream(eager, PriorityClass::Bulk);
+        }
+        scheduler.push_stream(patient, PriorityClass::Bulk);
+
+        let mut order = Vec::new();
+        for _ in 0..4 {
+            let (id, _) = scheduler.pop_stream().expect("stream ready");
+            scheduler.record_sent(id, 1200);
+            order.push(id);
+            if id == patient {
+                scheduler.push_stream(patient, PriorityClass::Bulk);
+            }
+        }
+        assert_eq!(order, [eager, patient, eager, patient]);
+    }
+
+    #[test]
+    fn byte_mode_shares_bandwidth_by_class_weight() {
+        let mut scheduler = Scheduler::new();
+        let streams: Vec<_> = CLASSES
+            .into_iter()
+            .zip(0u64..)
+            .map(|(class, index)| {
+                let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, index);
+                scheduler.push_stream(id, class);
+                (id, class)
+            })
+            .collect();
+
+        for _ in 0..3200 {
+            let (id, class) = scheduler.pop_stream_bytes(1200).expect("stream ready");
+            scheduler.record_sent(id, 1200);
+            // Every class stays backlogged.
+            scheduler.push_stream(id, class);
+        }
+
+        let total: u64 = CLASSES.iter().map(|c| scheduler.class_bytes(*c)).sum();
+        let weights: u64 = CLASSES.iter().map(|c| u64::from(c.weight())).sum();
+        assert_eq!(total, 3200 * 1200);
+        for (_, class) in streams {
+            let sent = scheduler.class_bytes(class);
+            let expected = total * u64::from(class.weight()) / weights;
+            // Within 1% of the total.
+            assert!(
+                sent.abs_diff(expected) < total / 100,
+                "{class:?}: sent {sent}, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn byte_mode_does_not_let_idle_class_bank_credit() {
+        let mut scheduler = Scheduler::new();
+        let bulk = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        let control = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 2);
+        scheduler.push_stream(bulk, PriorityClass::Bulk);
+        for _ in 0..100 {
+            let (id, class) = scheduler.pop_stream_bytes(1200).expect("bulk ready");
+            scheduler.record_sent(id, 1200);
+            scheduler.push_stream(id, class);
+        }
+
+        // Control joins late; it gets ten turns per Bulk turn but must not lock Bulk out
+        // while catching up on the time it was idle.
+        scheduler.push_stream(control, PriorityClass::Control);
+        let mut bulk_turns = 0;
+        for _ in 0..33 {
+            let (id, class) = scheduler.pop_stream_bytes(1200).expect("stream ready");
+            scheduler.record_sent(id, 1200);
+            scheduler.push_stream(id, class);
+            bulk_turns += usize::from(id == bulk);
+        }
+        assert_eq!(bulk_turns, 2);
+    }
+
+    #[test]
+    fn waiting_bulk_stream_overtakes_new_control_streams() {
+        let run = |aging_rate| {
+            let mut scheduler = Scheduler::with_config(SchedulerConfig { aging_rate });
+            let bulk = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+            scheduler.push_stream(bulk, PriorityClass::Bulk);
+            for index in 1..=200 {
+                // A fresh Control stream arrives before every dequeue.
+                let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, index);
+                scheduler.push_stream(id, PriorityClass::Control);
+                let (popped, _) = scheduler.pop_stream().expect("stream ready");
+                if popped == bulk {
+                    return Some(index);
+                }
+            }
+            None
+        };
+
+        // Weight 10 + 1 per dequeue passes Control's 100 after 91 dequeues.
+        assert_eq!(run(1), Some(92));
+        assert_eq!(run(10), Some(11));
+        assert_eq!(run(0), None);
+    }
+
     #[test]
     fn datagram_queue_is_fifo() {
         let mut scheduler = Scheduler::new();
@@ -178,4 +628,24 @@ mod tests {
         assert_eq!(scheduler.pop_datagram().unwrap(), vec![1]);
         assert_eq!(scheduler.pop_datagram().unwrap(), vec![2]);
     }
+
+    #[test]
+    fn expired_entries_are_dropped_and_counted() {
+        let now = SystemTime::now();
+        let later = now + std::time::Duration::from_secs(1);
+        let mut scheduler = Scheduler::new();
+        let stale = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        let live = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 2);
+        scheduler.push_stream_with_deadline(stale, PriorityClass::Control, now);
+        scheduler.push_stream_with_deadline(live, PriorityClass::Bulk, later);
+        scheduler.push_datagram_with_deadline(vec![1], now);
+        scheduler.push_datagram(vec![2]);
+
+        assert_eq!(scheduler.drop_expired(now), 2);
+        assert_eq!(scheduler.expired_drops(), 2);
+        assert_eq!(scheduler.pop_stream().expect("live").0, live);
+        assert!(!scheduler.has_streams());
+        assert_eq!(scheduler.pop_datagram().unwrap(), vec![2]);
+        assert_eq!(scheduler.drop_expired(later), 0);
+    }
 }
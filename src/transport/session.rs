@@ -1,12 +1,42 @@
 //! Session ticket issuance and resumption primitives for MXP transport.
+//!
+//! Tickets are self-contained: the responder seals the resumption secret and lifetime under
+//! a ticket-protection key, so it keeps no per-ticket state and validates a presented ticket
+//! by opening it. Layout of a sealed ticket:
+//!
+//! ```text
+//! [ticket id (16)][ChaCha20-Poly1305({secret (32)}{issued_at ms (u64 LE)}{ttl ms (u64 LE)})][tag (16)]
+//! ```
+//!
+//! The first 12 bytes of the random ticket id are the nonce and the whole id is the
+//! associated data.
 
 use std::collections::{HashMap, VecDeque};
 use std::time::{Duration, SystemTime};
 
+use uuid::Uuid;
+
+use super::crypto::{
+    AEAD_KEY_LEN, AEAD_NONCE_LEN, AEAD_TAG_LEN, AeadKey, AeadNonce, PUBLIC_KEY_LEN, PublicKey,
+    chacha20_poly1305_open, chacha20_poly1305_seal, hkdf_expand, hkdf_extract,
+};
+
+/// HKDF salt whitening random bytes into a ticket-protection key.
+const TICKET_KEY_SALT: &[u8] = b"mxp ticket key";
+/// HKDF info label for ticket secrets.
+const TICKET_SECRET_INFO: &[u8] = b"mxp ticket";
+
 /// Length of ticket identifiers in bytes.
 pub const TICKET_ID_LEN: usize = 16;
 /// Length of ticket secrets in bytes.
 pub const TICKET_SECRET_LEN: usize = 32;
+/// Length of the ticket-protection key in bytes.
+pub const TICKET_KEY_LEN: usize = AEAD_KEY_LEN;
+/// Length of the sealed ticket a client presents to resume.
+pub const SEALED_TICKET_LEN: usize = TICKET_ID_LEN + TICKET_PLAINTEXT_LEN + AEAD_TAG_LEN;
+
+/// Sealed fields: resumption secret, issue time, and lifetime.
+const TICKET_PLAINTEXT_LEN: usize = TICKET_SECRET_LEN + 8 + 8;
 
 /// Session resumption ticket issued after successful handshakes.
 #[derive(Debug, Clone)]
@@ -15,22 +45,10 @@ pub struct SessionTicket {
     secret: [u8; TICKET_SECRET_LEN],
     issued_at: SystemTime,
     expires_at: SystemTime,
+    sealed: [u8; SEALED_TICKET_LEN],
 }
 
 impl SessionTicket {
-    /// Create a new ticket from components.
-    #[must_use]
-    pub fn new(id: [u8; TICKET_ID_LEN], secret: [u8; TICKET_SECRET_LEN], ttl: Duration) -> Self {
-        let issued_at = SystemTime::now();
-        let expires_at = issued_at + ttl;
-        Self {
-            id,
-            secret,
-            issued_at,
-            expires_at,
-        }
-    }
-
     /// Ticket identifier accessor.
     #[must_use]
     pub fn id(&self) -> &[u8; TICKET_ID_LEN] {
@@ -43,6 +61,12 @@ impl SessionTicket {
         &self.secret
     }
 
+    /// Sealed form presented to the responder when resuming.
+    #[must_use]
+    pub fn sealed(&self) -> &[u8; SEALED_TICKET_LEN] {
+        &self.sealed
+    }
+
     /// Issued-at timestamp accessor.
     #[must_use]
     pub fn issued_at(&self) -> SystemTime {
@@ -58,110 +82,371 @@ impl SessionTicket {
     /// Determine whether the ticket is still valid.
     #[must_use]
     pub fn is_valid(&self) -> bool {
-        self.expires_at > SystemTime::now()
+        self.is_valid_at(SystemTime::now())
+    }
+
+    /// Determine whether the ticket is still valid at `now`.
+    #[must_use]
+    pub fn is_valid_at(&self, now: SystemTime) -> bool {
+        self.expires_at > now
     }
 }
 
-/// Manages issuance and storage of session tickets.
+/// Issues sealed session tickets and opens them on resumption.
+///
+/// Tickets sealed under the key in use before the last [`rotate`](Self::rotate) still
+/// open; a second rotation retires them.
 #[derive(Debug, Clone)]
 pub struct SessionTicketManager {
     ttl: Duration,
-    max_entries: usize,
-    counter: u64,
-    tickets: HashMap<[u8; TICKET_ID_LEN], SessionTicket>,
-    order: VecDeque<[u8; TICKET_ID_LEN]>,
+    key: [u8; TICKET_KEY_LEN],
+    previous_key: Option<[u8; TICKET_KEY_LEN]>,
 }
 
 impl SessionTicketManager {
-    /// Construct a manager with the provided TTL and capacity.
+    /// Construct a manager issuing tickets valid for `ttl` under a random key.
+    #[must_use]
+    pub fn new(ttl: Duration) -> Self {
+        Self::with_key(ttl, random_ticket_key())
+    }
+
+    /// Construct a manager with an explicit ticket-protection key, e.g. one shared by
+    /// several responders so each resumes tickets issued by the others.
     #[must_use]
-    pub fn new(ttl: Duration, max_entries: usize) -> Self {
+    pub const fn with_key(ttl: Duration, key: [u8; TICKET_KEY_LEN]) -> Self {
         Self {
             ttl,
-            max_entries: max_entries.max(1),
-            counter: 0,
-            tickets: HashMap::new(),
-            order: VecDeque::new(),
+            key,
+            previous_key: None,
         }
     }
 
-    /// Issue a new ticket seeded by the provided chaining key.
-    pub fn issue(&mut self, seed: &[u8]) -> SessionTicket {
-        self.prune_expired();
-        self.counter = self.counter.wrapping_add(1);
+    /// Replace the ticket-protection key with a random one, keeping the current key to
+    /// open tickets already issued.
+    pub fn rotate(&mut self) {
+        self.rotate_to(random_ticket_key());
+    }
+
+    /// Replace the ticket-protection key with `key`, keeping the current key to open
+    /// tickets already issued.
+    pub fn rotate_to(&mut self, key: [u8; TICKET_KEY_LEN]) {
+        self.previous_key = Some(core::mem::replace(&mut self.key, key));
+    }
 
-        let (id, secret) = self.derive_material(seed);
-        let ticket = SessionTicket::new(id, secret, self.ttl);
+    /// Issue a new ticket whose secret is bound to the provided chaining key.
+    #[must_use]
+    pub fn issue(&self, seed: &[u8]) -> SessionTicket {
+        self.issue_at(seed, SystemTime::now())
+    }
 
-        self.store(ticket.clone());
-        ticket
+    /// Issue a new ticket at `issued_at` whose secret is bound to the provided chaining key.
+    #[must_use]
+    pub fn issue_at(&self, seed: &[u8], issued_at: SystemTime) -> SessionTicket {
+        let id = random_ticket_id();
+        let secret = derive_secret(&id, seed);
+
+        let mut plaintext = Vec::with_capacity(TICKET_PLAINTEXT_LEN);
+        plaintext.extend_from_slice(&secret);
+        plaintext.extend_from_slice(&unix_millis(issued_at).to_le_bytes());
+        plaintext.extend_from_slice(&duration_millis(self.ttl).to_le_bytes());
+        let ciphertext = chacha20_poly1305_seal(
+            &AeadKey::from_array(self.key),
+            &ticket_nonce(&id),
+            &plaintext,
+            &id,
+        );
+
+        let mut sealed = [0u8; SEALED_TICKET_LEN];
+        sealed[..TICKET_ID_LEN].copy_from_slice(&id);
+        sealed[TICKET_ID_LEN..].copy_from_slice(&ciphertext);
+        SessionTicket {
+            id,
+            secret,
+            issued_at,
+            expires_at: issued_at + self.ttl,
+            sealed,
+        }
+    }
+
+    /// Open a sealed ticket presented for resumption.
+    ///
+    /// Returns `None` if the ticket is malformed, was not sealed under the current or
+    /// previous key, or has expired.
+    #[must_use]
+    pub fn resume(&self, sealed: &[u8]) -> Option<SessionTicket> {
+        self.resume_at(sealed, SystemTime::now())
     }
 
-    /// Attempt to resume a session given an identifier and expected seed.
+    /// [`resume`](Self::resume), checking expiry against `now`.
     #[must_use]
-    pub fn resume(&mut self, id: &[u8], seed: &[u8]) -> Option<SessionTicket> {
-        if id.len() != TICKET_ID_LEN {
+    pub fn resume_at(&self, sealed: &[u8], now: SystemTime) -> Option<SessionTicket> {
+        if sealed.len() != SEALED_TICKET_LEN {
             return None;
         }
+        let (id, ciphertext) = sealed.split_at(TICKET_ID_LEN);
         let mut id_array = [0u8; TICKET_ID_LEN];
         id_array.copy_from_slice(id);
+        let nonce = ticket_nonce(&id_array);
 
-        self.prune_expired();
+        let plaintext = [Some(self.key), self.previous_key]
+            .into_iter()
+            .flatten()
+            .find_map(|key| {
+                chacha20_poly1305_open(&AeadKey::from_array(key), &nonce, ciphertext, id).ok()
+            })?;
 
-        if let Some(ticket) = self.tickets.get(&id_array) {
-            if ticket.is_valid() {
-                let (_, expected_secret) = self.derive_material(seed);
-                if ticket.secret() == &expected_secret {
-                    return Some(ticket.clone());
-                }
-            }
-        }
+        let (secret_bytes, times) = plaintext.split_at(TICKET_SECRET_LEN);
+        let mut secret = [0u8; TICKET_SECRET_LEN];
+        secret.copy_from_slice(secret_bytes);
+        let issued_at = SystemTime::UNIX_EPOCH
+            + Duration::from_millis(u64::from_le_bytes(times[..8].try_into().ok()?));
+        let ttl = Duration::from_millis(u64::from_le_bytes(times[8..].try_into().ok()?));
 
-        None
+        let mut sealed_array = [0u8; SEALED_TICKET_LEN];
+        sealed_array.copy_from_slice(sealed);
+        let ticket = SessionTicket {
+            id: id_array,
+            secret,
+            issued_at,
+            expires_at: issued_at.checked_add(ttl)?,
+            sealed: sealed_array,
+        };
+        ticket.is_valid_at(now).then_some(ticket)
     }
+}
 
-    fn derive_material(&self, seed: &[u8]) -> ([u8; TICKET_ID_LEN], [u8; TICKET_SECRET_LEN]) {
-        let mut id = [0u8; TICKET_ID_LEN];
-        let mut secret = [0u8; TICKET_SECRET_LEN];
+/// Ticket secret as `HKDF(salt = id, ikm = seed)`.
+fn derive_secret(id: &[u8; TICKET_ID_LEN], seed: &[u8]) -> [u8; TICKET_SECRET_LEN] {
+    let prk = hkdf_extract(id, seed);
+    let okm =
+        hkdf_expand(&prk, TICKET_SECRET_INFO, TICKET_SECRET_LEN).expect("ticket secret length");
+    let mut secret = [0u8; TICKET_SECRET_LEN];
+    secret.copy_from_slice(&okm);
+    secret
+}
 
-        let counter_bytes = self.counter.to_le_bytes();
-        for (idx, byte) in id.iter_mut().enumerate() {
-            let seed_byte = seed[idx % seed.len()];
-            let counter_byte = counter_bytes[idx % counter_bytes.len()];
-            *byte = seed_byte ^ counter_byte.rotate_left((idx % 8) as u32);
-        }
+/// A ticket id whose first [`AEAD_NONCE_LEN`] bytes, the nonce sealing the ticket, are 96
+/// random bits.
+fn random_ticket_id() -> [u8; TICKET_ID_LEN] {
+    let mut id = [0u8; TICKET_ID_LEN];
+    id[..AEAD_NONCE_LEN].copy_from_slice(AeadNonce::random().as_bytes());
+    id[AEAD_NONCE_LEN..]
+        .copy_from_slice(&Uuid::new_v4().as_bytes()[..TICKET_ID_LEN - AEAD_NONCE_LEN]);
+    id
+}
 
-        for (idx, byte) in secret.iter_mut().enumerate() {
-            let seed_byte = seed[idx % seed.len()];
-            let id_byte = id[idx % TICKET_ID_LEN];
-            *byte = seed_byte
-                .wrapping_add(id_byte)
-                .rotate_left(((idx & 7) + 1) as u32);
-        }
+fn ticket_nonce(id: &[u8; TICKET_ID_LEN]) -> AeadNonce {
+    let mut nonce = [0u8; AEAD_NONCE_LEN];
+    nonce.copy_from_slice(&id[..AEAD_NONCE_LEN]);
+    AeadNonce::from_array(nonce)
+}
 
-        (id, secret)
+fn random_ticket_key() -> [u8; TICKET_KEY_LEN] {
+    let mut random = [0u8; 32];
+    random[..16].copy_from_slice(Uuid::new_v4().as_bytes());
+    random[16..].copy_from_slice(Uuid::new_v4().as_bytes());
+    hkdf_extract(TICKET_KEY_SALT, &random)
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, duration_millis)
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+/// Default number of servers a [`ClientTicketCache`] remembers tickets for.
+pub const DEFAULT_CLIENT_TICKET_CACHE: usize = 256;
+
+/// Initiator-side store of resumption tickets, keyed by the server's static public key.
+///
+/// Tickets are handed out once: a responder accepts early data under a given ticket only
+/// once, so reusing one would always be rejected.
+#[derive(Debug, Clone)]
+pub struct ClientTicketCache {
+    max_servers: usize,
+    tickets: HashMap<[u8; PUBLIC_KEY_LEN], SessionTicket>,
+    order: VecDeque<[u8; PUBLIC_KEY_LEN]>,
+}
+
+impl ClientTicketCache {
+    /// Create a cache remembering the latest ticket for up to `max_servers` servers.
+    #[must_use]
+    pub fn new(max_servers: usize) -> Self {
+        Self {
+            max_servers: max_servers.max(1),
+            tickets: HashMap::new(),
+            order: VecDeque::new(),
+        }
     }
 
-    fn store(&mut self, ticket: SessionTicket) {
-        if self.order.len() >= self.max_entries {
+    /// Remember `ticket` for `server`, replacing any older ticket for it.
+    pub fn insert(&mut self, server: &PublicKey, ticket: SessionTicket) {
+        let key = *server.as_bytes();
+        if self.tickets.insert(key, ticket).is_some() {
+            self.order.retain(|entry| *entry != key);
+        } else if self.order.len() >= self.max_servers {
             if let Some(oldest) = self.order.pop_front() {
                 self.tickets.remove(&oldest);
             }
         }
+        self.order.push_back(key);
+    }
+
+    /// Remove and return the ticket for `server` if it is still valid.
+    pub fn take(&mut self, server: &PublicKey) -> Option<SessionTicket> {
+        self.take_at(server, SystemTime::now())
+    }
+
+    /// Remove and return the ticket for `server` if it is still valid at `now`.
+    pub fn take_at(&mut self, server: &PublicKey, now: SystemTime) -> Option<SessionTicket> {
+        let key = server.as_bytes();
+        let ticket = self.tickets.remove(key)?;
+        self.order.retain(|entry| entry != key);
+        ticket.is_valid_at(now).then_some(ticket)
+    }
+
+    /// Number of servers with a cached ticket.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.tickets.len()
+    }
 
-        self.order.push_back(*ticket.id());
-        self.tickets.insert(*ticket.id(), ticket);
+    /// Determine whether no tickets are cached.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.tickets.is_empty()
     }
+}
 
-    fn prune_expired(&mut self) {
-        while let Some(id) = self.order.front() {
-            if let Some(ticket) = self.tickets.get(id) {
-                if ticket.is_valid() {
-                    break;
-                }
+impl Default for ClientTicketCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CLIENT_TICKET_CACHE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticket_nonce_has_no_fixed_bits() {
+        let manager = SessionTicketManager::new(Duration::from_secs(60));
+        let mut seen_ones = [0u8; AEAD_NONCE_LEN];
+        let mut seen_zeros = [0u8; AEAD_NONCE_LEN];
+        for _ in 0..64 {
+            let ticket = manager.issue(b"seed");
+            for (i, byte) in ticket_nonce(ticket.id()).as_bytes().iter().enumerate() {
+                seen_ones[i] |= byte;
+                seen_zeros[i] |= !byte;
             }
-            let removed = self.order.pop_front().expect("entry available");
-            self.tickets.remove(&removed);
         }
+        assert_eq!(seen_ones, [0xff; AEAD_NONCE_LEN]);
+        assert_eq!(seen_zeros, [0xff; AEAD_NONCE_LEN]);
+    }
+
+    #[test]
+    fn earlier_ticket_resumes_after_later_issues() {
+        let manager = SessionTicketManager::new(Duration::from_secs(60));
+        let first = manager.issue(b"first seed");
+        let second = manager.issue(b"second seed");
+        assert_ne!(first.id(), second.id());
+        assert_ne!(first.secret(), second.secret());
+
+        let resumed = manager.resume(first.sealed()).expect("resume");
+        assert_eq!(resumed.id(), first.id());
+        assert_eq!(resumed.secret(), first.secret());
+        assert_eq!(
+            resumed.expires_at(),
+            resumed.issued_at() + Duration::from_secs(60)
+        );
+        assert_eq!(
+            manager.resume(second.sealed()).expect("resume").secret(),
+            second.secret()
+        );
+    }
+
+    #[test]
+    fn forged_tickets_are_rejected() {
+        let manager = SessionTicketManager::new(Duration::from_secs(60));
+        let ticket = manager.issue(b"seed");
+
+        // Any flipped bit, in the id, the sealed fields, or the tag, fails authentication.
+        for idx in [0, TICKET_ID_LEN, SEALED_TICKET_LEN - 1] {
+            let mut forged = *ticket.sealed();
+            forged[idx] ^= 0x01;
+            assert!(manager.resume(&forged).is_none(), "byte {idx}");
+        }
+        // Swapping in another ticket's id breaks the nonce and associated data.
+        let other = manager.issue(b"seed");
+        let mut spliced = *ticket.sealed();
+        spliced[..TICKET_ID_LEN].copy_from_slice(other.id());
+        assert!(manager.resume(&spliced).is_none());
+
+        // Knowing the scheme and the seed is not enough without the key.
+        let attacker = SessionTicketManager::new(Duration::from_secs(3600));
+        assert!(manager.resume(attacker.issue(b"seed").sealed()).is_none());
+        assert!(manager.resume(&ticket.sealed()[1..]).is_none());
+        assert!(manager.resume(&[0u8; SEALED_TICKET_LEN]).is_none());
+    }
+
+    #[test]
+    fn tickets_survive_one_key_rotation() {
+        let mut manager = SessionTicketManager::with_key(Duration::from_secs(60), [7; 32]);
+        let old = manager.issue(b"old");
+
+        manager.rotate();
+        let current = manager.issue(b"current");
+        assert!(manager.resume(old.sealed()).is_some());
+        assert!(manager.resume(current.sealed()).is_some());
+
+        manager.rotate();
+        assert!(manager.resume(old.sealed()).is_none());
+        assert!(manager.resume(current.sealed()).is_some());
+
+        // A responder sharing the original key no longer matches after the rotations.
+        let peer = SessionTicketManager::with_key(Duration::from_secs(60), [7; 32]);
+        assert!(peer.resume(old.sealed()).is_some());
+        assert!(peer.resume(current.sealed()).is_none());
+    }
+
+    #[test]
+    fn expired_tickets_are_rejected() {
+        let manager = SessionTicketManager::new(Duration::ZERO);
+        let ticket = manager.issue(b"seed");
+        assert!(!ticket.is_valid());
+        assert!(manager.resume(ticket.sealed()).is_none());
+    }
+
+    #[test]
+    fn client_cache_hands_out_each_ticket_once() {
+        let server_a = PublicKey::from_array([1; PUBLIC_KEY_LEN]);
+        let server_b = PublicKey::from_array([2; PUBLIC_KEY_LEN]);
+        let server_c = PublicKey::from_array([3; PUBLIC_KEY_LEN]);
+        let issuer = SessionTicketManager::new(Duration::from_secs(60));
+        let mut cache = ClientTicketCache::new(2);
+
+        cache.insert(&server_a, issuer.issue(b"a"));
+        cache.insert(&server_b, issuer.issue(b"b"));
+        let latest_a = issuer.issue(b"a again");
+        cache.insert(&server_a, latest_a.clone());
+        assert_eq!(cache.len(), 2);
+
+        // Server B is now the least recently stored entry and makes room for C.
+        cache.insert(&server_c, issuer.issue(b"c"));
+        assert!(cache.take(&server_b).is_none());
+        assert_eq!(cache.take(&server_a).unwrap().id(), latest_a.id());
+        assert!(cache.take(&server_a).is_none());
+
+        cache.insert(
+            &server_a,
+            SessionTicketManager::new(Duration::ZERO).issue(b"expired"),
+        );
+        assert!(
+            cache.take(&server_a).is_none(),
+            "expired tickets are not returned"
+        );
     }
 }
@@ -1,10 +1,14 @@
 //! Minimal UDP socket wrapper for MXP transport.
 
-use std::io;
+use std::fmt;
+use std::io::{self, IoSlice};
 use std::net::{SocketAddr, UdpSocket};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Duration;
 
+use super::ecn::EcnCodepoint;
+
 /// Error type for socket operations.
 #[derive(Debug)]
 pub enum SocketError {
@@ -18,20 +22,92 @@ impl From<io::Error> for SocketError {
     }
 }
 
+/// Metadata for one received datagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvMeta {
+    /// Number of bytes written into the receive buffer.
+    pub len: usize,
+    /// Address the datagram came from.
+    pub addr: SocketAddr,
+    /// ECN codepoint from the IP header ([`EcnCodepoint::NotEct`] when ECN is off or
+    /// unsupported).
+    pub ecn: EcnCodepoint,
+}
+
+impl RecvMeta {
+    fn from_parts((len, addr, ecn): (usize, SocketAddr, EcnCodepoint)) -> Self {
+        Self { len, addr, ecn }
+    }
+}
+
 /// Binding for a UDP socket.
 #[derive(Debug, Clone)]
 pub struct SocketBinding {
     socket: Arc<UdpSocket>,
+    segmentation_offload: Arc<AtomicBool>,
+    nonblocking: Arc<AtomicBool>,
+    ecn: Arc<AtomicBool>,
 }
 
 impl SocketBinding {
     /// Bind to the provided address.
+    ///
+    /// On Linux the socket marks outgoing packets ECT(0) and reports the ECN codepoint of
+    /// received packets; if the kernel refuses either option the socket works without ECN.
     pub fn bind(addr: SocketAddr) -> Result<Self, SocketError> {
         let socket = UdpSocket::bind(addr)?;
         socket.set_nonblocking(false)?;
-        Ok(Self {
+        let binding = Self {
             socket: Arc::new(socket),
-        })
+            segmentation_offload: Arc::new(AtomicBool::new(cfg!(all(
+                target_os = "linux",
+                target_pointer_width = "64"
+            )))),
+            nonblocking: Arc::new(AtomicBool::new(false)),
+            ecn: Arc::new(AtomicBool::new(false)),
+        };
+        binding.set_ecn(true);
+        Ok(binding)
+    }
+
+    /// Enable or disable ECN, returning whether it is now in effect.
+    ///
+    /// Enabling marks outgoing packets ECT(0) and asks the kernel to report the codepoint of
+    /// received packets. Only Linux is supported; elsewhere, or if the kernel rejects the
+    /// socket options, the binding keeps working without ECN and this returns `false`.
+    pub fn set_ecn(&self, enabled: bool) -> bool {
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        let active = match self
+            .socket
+            .local_addr()
+            .and_then(|addr| sys::set_ecn(&self.socket, addr.is_ipv6(), enabled))
+        {
+            Ok(()) => enabled,
+            Err(err) => {
+                tracing::debug!(error = %err, enabled, "ECN socket options unavailable");
+                // A failed enable may have applied half the options; clear them again.
+                if enabled {
+                    let _ = self
+                        .socket
+                        .local_addr()
+                        .and_then(|addr| sys::set_ecn(&self.socket, addr.is_ipv6(), false));
+                }
+                false
+            }
+        };
+        #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+        let active = {
+            let _ = enabled;
+            false
+        };
+        self.ecn.store(active, Ordering::Relaxed);
+        active
+    }
+
+    /// Whether outgoing packets are ECN-marked and received codepoints are reported.
+    #[must_use]
+    pub fn ecn_enabled(&self) -> bool {
+        self.ecn.load(Ordering::Relaxed)
     }
 
     /// Set socket read timeout.
@@ -49,17 +125,365 @@ impl SocketBinding {
     /// Adjust the non-blocking mode.
     pub fn set_nonblocking(&self, nonblocking: bool) -> Result<(), SocketError> {
         self.socket.set_nonblocking(nonblocking)?;
+        self.nonblocking.store(nonblocking, Ordering::Relaxed);
         Ok(())
     }
 
+    /// Restrict the socket to `addr`: sends without an address go there and the kernel
+    /// discards datagrams from anyone else.
+    ///
+    /// ICMP errors for the peer may then surface as errors from later sends and receives.
+    pub fn connect(&self, addr: SocketAddr) -> Result<(), SocketError> {
+        self.socket.connect(addr)?;
+        Ok(())
+    }
+
+    /// Address passed to [`connect`](Self::connect), if the socket is connected.
+    pub fn peer_addr(&self) -> Result<SocketAddr, SocketError> {
+        Ok(self.socket.peer_addr()?)
+    }
+
+    /// Send bytes to the connected peer.
+    pub fn send(&self, buf: &[u8]) -> Result<usize, SocketError> {
+        Ok(self.socket.send(buf)?)
+    }
+
+    /// Receive a datagram from the connected peer; see [`recv_from`](Self::recv_from).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<RecvMeta, SocketError> {
+        self.recv_from(buf)
+    }
+
     /// Send bytes to a remote address.
     pub fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, SocketError> {
         Ok(self.socket.send_to(buf, addr)?)
     }
 
-    /// Receive bytes into the provided buffer.
-    pub fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr), SocketError> {
-        Ok(self.socket.recv_from(buf)?)
+    /// Send bytes to a remote address with an explicit ECN codepoint.
+    ///
+    /// The codepoint is only applied on Linux; elsewhere this is equivalent to
+    /// [`send_to`](Self::send_to).
+    pub fn send_to_ecn(
+        &self,
+        buf: &[u8],
+        addr: SocketAddr,
+        ecn: EcnCodepoint,
+    ) -> Result<usize, SocketError> {
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        return Ok(sys::send_ecn(&self.socket, buf, addr, ecn)?);
+        #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+        {
+            let _ = ecn;
+            self.send_to(buf, addr)
+        }
+    }
+
+    /// Send several datagrams to `addr`, returning the total bytes sent.
+    ///
+    /// On Linux, packets of equal size (the last may be shorter) are coalesced into a single
+    /// `sendmsg` using UDP GSO (`UDP_SEGMENT`); elsewhere, or if the kernel rejects GSO, each
+    /// packet is sent with its own `send_to`. When the kernel rejects GSO partway through a
+    /// large batch, only the packets it has not sent yet go out one by one.
+    pub fn send_batch(
+        &self,
+        packets: &[IoSlice<'_>],
+        addr: SocketAddr,
+    ) -> Result<usize, SocketError> {
+        let (mut sent, done) = self.send_segmented(packets, addr)?;
+        for packet in &packets[done..] {
+            sent += self.socket.send_to(packet, addr)?;
+        }
+        Ok(sent)
+    }
+
+    /// Send as much of `packets` as UDP GSO takes, returning the bytes and the number of
+    /// packets that went out; the rest are left to `send_to`.
+    fn send_segmented(
+        &self,
+        packets: &[IoSlice<'_>],
+        addr: SocketAddr,
+    ) -> Result<(usize, usize), SocketError> {
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        if self.segmentation_offload() && packets.len() > 1 && sys::is_segmentable(packets) {
+            return match sys::send(&self.socket, packets, addr) {
+                Ok(sent) => Ok((sent, packets.len())),
+                Err(partial) if sys::is_unsupported(&partial.error) => {
+                    tracing::debug!(
+                        error = %partial.error,
+                        already_sent = partial.packets,
+                        "UDP GSO unavailable; falling back to send_to"
+                    );
+                    self.set_segmentation_offload(false);
+                    Ok((partial.bytes, partial.packets))
+                }
+                Err(partial) => Err(partial.error.into()),
+            };
+        }
+        #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+        let _ = (packets, addr);
+        Ok((0, 0))
+    }
+
+    /// Whether [`send_batch`](Self::send_batch) attempts UDP GSO.
+    #[must_use]
+    pub fn segmentation_offload(&self) -> bool {
+        self.segmentation_offload.load(Ordering::Relaxed)
+    }
+
+    /// Enable or disable UDP GSO for batched sends (it has no effect off Linux).
+    pub fn set_segmentation_offload(&self, enabled: bool) {
+        self.segmentation_offload.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Receive bytes into the provided buffer, returning the length, sender and the packet's
+    /// ECN codepoint (always [`EcnCodepoint::NotEct`] off Linux).
+    pub fn recv_from(&self, buf: &mut [u8]) -> Result<RecvMeta, SocketError> {
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        return Ok(RecvMeta::from_parts(sys::recv_ecn(&self.socket, buf)?));
+        #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+        {
+            let (len, addr) = self.socket.recv_from(buf)?;
+            Ok(RecvMeta::from_parts((len, addr, EcnCodepoint::NotEct)))
+        }
+    }
+
+    /// Receive up to `buffers.len()` datagrams, returning the length and sender of each.
+    ///
+    /// Blocks (subject to the read timeout) until at least one datagram arrives, then drains
+    /// whatever else is already queued without waiting. On Linux a single `recvmmsg` fills
+    /// the batch; elsewhere datagrams are read one `recv_from` at a time.
+    pub fn recv_batch(
+        &self,
+        buffers: &mut [&mut [u8]],
+    ) -> Result<Vec<(usize, SocketAddr)>, SocketError> {
+        if buffers.is_empty() {
+            return Ok(Vec::new());
+        }
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        match sys::recv(&self.socket, buffers) {
+            Err(err) if err.raw_os_error() == Some(38) => {} // ENOSYS: fall back below
+            result => return Ok(result?),
+        }
+        self.recv_batch_fallback(buffers)
+    }
+
+    fn recv_batch_fallback(
+        &self,
+        buffers: &mut [&mut [u8]],
+    ) -> Result<Vec<(usize, SocketAddr)>, SocketError> {
+        let Some((first, rest)) = buffers.split_first_mut() else {
+            return Ok(Vec::new());
+        };
+        let mut received = vec![self.socket.recv_from(first)?];
+        if rest.is_empty() {
+            return Ok(received);
+        }
+
+        // Drain already-queued datagrams without blocking.
+        #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+        let _restore = self.nonblocking_for_drain()?;
+        for buf in rest {
+            match self.try_recv_from(buf) {
+                Ok(datagram) => received.push(datagram),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(received)
+    }
+
+    /// Receive a datagram that is already queued, failing with `WouldBlock` otherwise.
+    ///
+    /// On Linux `MSG_DONTWAIT` applies to this call alone; elsewhere the socket must already
+    /// be non-blocking (see [`nonblocking_for_drain`](Self::nonblocking_for_drain)).
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        return sys::try_recv(&self.socket, buf);
+        #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+        self.socket.recv_from(buf)
+    }
+
+    /// Switch a blocking socket to non-blocking until the returned guard drops, so no early
+    /// return can leave it non-blocking for other users of the handle.
+    #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+    fn nonblocking_for_drain(&self) -> io::Result<Option<BlockingRestore<'_>>> {
+        if self.nonblocking.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        self.socket.set_nonblocking(true)?;
+        Ok(Some(BlockingRestore(&self.socket)))
+    }
+
+    /// Access the local address for this binding.
+    pub fn local_addr(&self) -> Result<SocketAddr, SocketError> {
+        Ok(self.socket.local_addr()?)
+    }
+}
+
+/// Puts a socket back into blocking mode when dropped.
+#[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+struct BlockingRestore<'a>(&'a UdpSocket);
+
+#[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+impl Drop for BlockingRestore<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = self.0.set_nonblocking(false) {
+            tracing::warn!(error = %err, "failed to restore blocking mode after draining");
+        }
+    }
+}
+
+/// Blocking datagram socket a [`TransportHandle`](super::TransportHandle) sends and receives
+/// through.
+///
+/// [`SocketBinding`] is the UDP implementation; the `testing` feature adds an in-memory one
+/// for simulated networks. Methods mirror the [`SocketBinding`] methods of the same name.
+pub trait DatagramSocket: fmt::Debug + Send + Sync {
+    /// Send bytes to a remote address.
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, SocketError>;
+
+    /// Send several datagrams to `addr`, returning the total bytes sent.
+    fn send_batch(&self, packets: &[IoSlice<'_>], addr: SocketAddr) -> Result<usize, SocketError> {
+        let mut sent = 0;
+        for packet in packets {
+            sent += self.send_to(packet, addr)?;
+        }
+        Ok(sent)
+    }
+
+    /// Receive one datagram into `buf`.
+    fn recv_from(&self, buf: &mut [u8]) -> Result<RecvMeta, SocketError>;
+
+    /// Receive up to `buffers.len()` datagrams, returning the length and sender of each.
+    ///
+    /// The default receives a single datagram.
+    fn recv_batch(
+        &self,
+        buffers: &mut [&mut [u8]],
+    ) -> Result<Vec<(usize, SocketAddr)>, SocketError> {
+        let Some(first) = buffers.first_mut() else {
+            return Ok(Vec::new());
+        };
+        let meta = self.recv_from(first)?;
+        Ok(vec![(meta.len, meta.addr)])
+    }
+
+    /// Restrict the socket to a single peer.
+    fn connect(&self, addr: SocketAddr) -> Result<(), SocketError>;
+
+    /// Address passed to [`connect`](Self::connect).
+    fn peer_addr(&self) -> Result<SocketAddr, SocketError>;
+
+    /// Send bytes to the connected peer.
+    fn send(&self, buf: &[u8]) -> Result<usize, SocketError> {
+        self.send_to(buf, self.peer_addr()?)
+    }
+
+    /// Local address of the socket.
+    fn local_addr(&self) -> Result<SocketAddr, SocketError>;
+}
+
+impl DatagramSocket for SocketBinding {
+    fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, SocketError> {
+        Self::send_to(self, buf, addr)
+    }
+
+    fn send_batch(&self, packets: &[IoSlice<'_>], addr: SocketAddr) -> Result<usize, SocketError> {
+        Self::send_batch(self, packets, addr)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<RecvMeta, SocketError> {
+        Self::recv_from(self, buf)
+    }
+
+    fn recv_batch(
+        &self,
+        buffers: &mut [&mut [u8]],
+    ) -> Result<Vec<(usize, SocketAddr)>, SocketError> {
+        Self::recv_batch(self, buffers)
+    }
+
+    fn connect(&self, addr: SocketAddr) -> Result<(), SocketError> {
+        Self::connect(self, addr)
+    }
+
+    fn peer_addr(&self) -> Result<SocketAddr, SocketError> {
+        Self::peer_addr(self)
+    }
+
+    fn send(&self, buf: &[u8]) -> Result<usize, SocketError> {
+        Self::send(self, buf)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr, SocketError> {
+        Self::local_addr(self)
+    }
+}
+
+/// Asynchronous UDP socket binding driven by the tokio reactor.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone)]
+pub struct AsyncSocketBinding {
+    socket: Arc<tokio::net::UdpSocket>,
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncSocketBinding {
+    /// Bind to the provided address; must be called within a tokio runtime.
+    ///
+    /// ECN is configured as for [`SocketBinding::bind`].
+    pub fn bind(addr: SocketAddr) -> Result<Self, SocketError> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        if let Err(err) = sys::set_ecn(&socket, addr.is_ipv6(), true) {
+            tracing::debug!(error = %err, "ECN socket options unavailable");
+        }
+        Ok(Self {
+            socket: Arc::new(tokio::net::UdpSocket::from_std(socket)?),
+        })
+    }
+
+    /// Send bytes to a remote address.
+    pub async fn send_to(&self, buf: &[u8], addr: SocketAddr) -> Result<usize, SocketError> {
+        Ok(self.socket.send_to(buf, addr).await?)
+    }
+
+    /// Send bytes to a remote address with an explicit ECN codepoint (applied on Linux only).
+    pub async fn send_to_ecn(
+        &self,
+        buf: &[u8],
+        addr: SocketAddr,
+        ecn: EcnCodepoint,
+    ) -> Result<usize, SocketError> {
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        return Ok(self
+            .socket
+            .async_io(tokio::io::Interest::WRITABLE, || {
+                sys::send_ecn(&*self.socket, buf, addr, ecn)
+            })
+            .await?);
+        #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+        {
+            let _ = ecn;
+            self.send_to(buf, addr).await
+        }
+    }
+
+    /// Wait for a datagram, returning its length, sender and ECN codepoint.
+    pub async fn recv_from(&self, buf: &mut [u8]) -> Result<RecvMeta, SocketError> {
+        #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+        return Ok(RecvMeta::from_parts(
+            self.socket
+                .async_io(tokio::io::Interest::READABLE, || {
+                    sys::recv_ecn(&*self.socket, buf)
+                })
+                .await?,
+        ));
+        #[cfg(not(all(target_os = "linux", target_pointer_width = "64")))]
+        {
+            let (len, addr) = self.socket.recv_from(buf).await?;
+            Ok(RecvMeta::from_parts((len, addr, EcnCodepoint::NotEct)))
+        }
     }
 
     /// Access the local address for this binding.
@@ -67,3 +491,709 @@ impl SocketBinding {
         Ok(self.socket.local_addr()?)
     }
 }
+
+/// Raw `sendmsg` (UDP GSO, ECN), `recvmsg` (ECN) and `recvmmsg` for batched I/O
+/// (64-bit Linux layouts).
+#[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+mod sys {
+    use std::io::{self, IoSlice, IoSliceMut};
+    use std::mem::size_of;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
+    use std::os::fd::AsRawFd;
+    use std::os::raw::{c_int, c_void};
+    use std::ptr;
+
+    use super::EcnCodepoint;
+
+    const SOL_UDP: c_int = 17;
+    const UDP_SEGMENT: c_int = 103;
+    const AF_INET: u16 = 2;
+    const AF_INET6: u16 = 10;
+    /// Kernel limit on segments per GSO send (`UDP_MAX_SEGMENTS`).
+    const MAX_SEGMENTS: usize = 64;
+    /// Largest UDP payload a single GSO send may carry.
+    const MAX_GSO_PAYLOAD: usize = 65_507;
+    /// Block until one datagram arrives, then return whatever else is queued.
+    const MSG_WAITFORONE: c_int = 0x1_0000;
+    /// Fail with `EAGAIN` instead of blocking, for this call only.
+    const MSG_DONTWAIT: c_int = 0x40;
+    /// Kernel limit on messages per `recvmmsg` call (`UIO_MAXIOV`).
+    const MAX_BATCH: usize = 1024;
+    const IPPROTO_IP: c_int = 0;
+    const IP_TOS: c_int = 1;
+    const IP_RECVTOS: c_int = 13;
+    const IPPROTO_IPV6: c_int = 41;
+    const IPV6_RECVTCLASS: c_int = 66;
+    const IPV6_TCLASS: c_int = 67;
+
+    #[repr(C)]
+    struct MsgHdr {
+        name: *mut c_void,
+        name_len: u32,
+        iov: *mut c_void,
+        iov_len: usize,
+        control: *mut c_void,
+        control_len: usize,
+        flags: c_int,
+    }
+
+    #[repr(C)]
+    struct MmsgHdr {
+        hdr: MsgHdr,
+        len: u32,
+    }
+
+    #[derive(Clone, Copy)]
+    #[repr(C, align(8))]
+    struct SockAddrStorage {
+        family: u16,
+        data: [u8; 126],
+    }
+
+    #[repr(C)]
+    struct CmsgHdr {
+        len: usize,
+        level: c_int,
+        kind: c_int,
+    }
+
+    #[repr(C, align(8))]
+    struct SegmentControl {
+        header: CmsgHdr,
+        segment_size: u16,
+        padding: [u8; 6],
+    }
+
+    #[repr(C, align(8))]
+    struct IntControl {
+        header: CmsgHdr,
+        value: c_int,
+        padding: [u8; 4],
+    }
+
+    /// Room for the TOS and traffic class control messages on receive.
+    #[repr(C, align(8))]
+    struct RecvControl([u8; 64]);
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct SockAddrIn {
+        family: u16,
+        port: [u8; 2],
+        addr: [u8; 4],
+        zero: [u8; 8],
+    }
+
+    #[derive(Clone, Copy)]
+    #[repr(C)]
+    struct SockAddrIn6 {
+        family: u16,
+        port: [u8; 2],
+        flow_info: [u8; 4],
+        addr: [u8; 16],
+        scope_id: u32,
+    }
+
+    #[repr(C)]
+    union SockAddr {
+        v4: SockAddrIn,
+        v6: SockAddrIn6,
+    }
+
+    unsafe extern "C" {
+        fn setsockopt(
+            fd: c_int,
+            level: c_int,
+            name: c_int,
+            value: *const c_void,
+            len: u32,
+        ) -> c_int;
+        fn sendmsg(fd: c_int, msg: *const MsgHdr, flags: c_int) -> isize;
+        fn recvmsg(fd: c_int, msg: *mut MsgHdr, flags: c_int) -> isize;
+        fn recvmmsg(
+            fd: c_int,
+            msgs: *mut MmsgHdr,
+            len: u32,
+            flags: c_int,
+            timeout: *mut c_void,
+        ) -> c_int;
+    }
+
+    fn set_int_option(
+        socket: &UdpSocket,
+        level: c_int,
+        name: c_int,
+        value: c_int,
+    ) -> io::Result<()> {
+        // SAFETY: `value` is a live `c_int` and the length passed matches it.
+        let result = unsafe {
+            setsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                (&raw const value).cast(),
+                socklen::<c_int>(),
+            )
+        };
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Mark outgoing packets ECT(0) and report received codepoints, or undo both.
+    pub(super) fn set_ecn(socket: &UdpSocket, ipv6: bool, enabled: bool) -> io::Result<()> {
+        let (tos, report) = if enabled {
+            (c_int::from(EcnCodepoint::Ect0.bits()), 1)
+        } else {
+            (c_int::from(EcnCodepoint::NotEct.bits()), 0)
+        };
+        if ipv6 {
+            set_int_option(socket, IPPROTO_IPV6, IPV6_TCLASS, tos)?;
+            set_int_option(socket, IPPROTO_IPV6, IPV6_RECVTCLASS, report)?;
+            // Dual-stack sockets also carry IPv4-mapped traffic; best effort only.
+            let _ = set_int_option(socket, IPPROTO_IP, IP_TOS, tos);
+            let _ = set_int_option(socket, IPPROTO_IP, IP_RECVTOS, report);
+            Ok(())
+        } else {
+            set_int_option(socket, IPPROTO_IP, IP_TOS, tos)?;
+            set_int_option(socket, IPPROTO_IP, IP_RECVTOS, report)
+        }
+    }
+
+    /// Send one datagram whose TOS / traffic class carries `ecn`.
+    pub(super) fn send_ecn(
+        socket: &impl AsRawFd,
+        buf: &[u8],
+        addr: SocketAddr,
+        ecn: EcnCodepoint,
+    ) -> io::Result<usize> {
+        let (mut name, name_len) = sockaddr(addr);
+        let (level, kind) = if addr.is_ipv6() {
+            (IPPROTO_IPV6, IPV6_TCLASS)
+        } else {
+            (IPPROTO_IP, IP_TOS)
+        };
+        let mut control = IntControl {
+            header: CmsgHdr {
+                len: size_of::<CmsgHdr>() + size_of::<c_int>(),
+                level,
+                kind,
+            },
+            value: c_int::from(ecn.bits()),
+            padding: [0; 4],
+        };
+        let mut iov = IoSlice::new(buf);
+        let msg = MsgHdr {
+            name: (&raw mut name).cast(),
+            name_len,
+            // `IoSlice` is ABI-compatible with `struct iovec` on Unix.
+            iov: (&raw mut iov).cast(),
+            iov_len: 1,
+            control: (&raw mut control).cast(),
+            control_len: size_of::<IntControl>(),
+            flags: 0,
+        };
+        // SAFETY: every pointer in `msg` refers to a live local that outlives the call, and the
+        // structs match the kernel's 64-bit `msghdr`/`cmsghdr` layout.
+        let sent = unsafe { sendmsg(socket.as_raw_fd(), &raw const msg, 0) };
+        usize::try_from(sent).map_err(|_| io::Error::last_os_error())
+    }
+
+    /// Receive one datagram along with the ECN bits of its TOS / traffic class.
+    pub(super) fn recv_ecn(
+        socket: &impl AsRawFd,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr, EcnCodepoint)> {
+        recv_msg(socket, buf, 0)
+    }
+
+    /// Receive one already-queued datagram, failing with `WouldBlock` rather than waiting,
+    /// whatever the socket's blocking mode.
+    pub(super) fn try_recv(
+        socket: &impl AsRawFd,
+        buf: &mut [u8],
+    ) -> io::Result<(usize, SocketAddr)> {
+        let (len, addr, _) = recv_msg(socket, buf, MSG_DONTWAIT)?;
+        Ok((len, addr))
+    }
+
+    fn recv_msg(
+        socket: &impl AsRawFd,
+        buf: &mut [u8],
+        flags: c_int,
+    ) -> io::Result<(usize, SocketAddr, EcnCodepoint)> {
+        let mut name = SockAddrStorage {
+            family: 0,
+            data: [0; 126],
+        };
+        let mut control = RecvControl([0; 64]);
+        let mut iov = IoSliceMut::new(buf);
+        let mut msg = MsgHdr {
+            name: (&raw mut name).cast(),
+            name_len: socklen::<SockAddrStorage>(),
+            // `IoSliceMut` is ABI-compatible with `struct iovec` on Unix.
+            iov: (&raw mut iov).cast(),
+            iov_len: 1,
+            control: (&raw mut control).cast(),
+            control_len: size_of::<RecvControl>(),
+            flags: 0,
+        };
+        // SAFETY: every pointer in `msg` refers to a live local that outlives the call, and the
+        // structs match the kernel's 64-bit `msghdr` layout.
+        let received = unsafe { recvmsg(socket.as_raw_fd(), &raw mut msg, flags) };
+        let len = usize::try_from(received).map_err(|_| io::Error::last_os_error())?;
+        let control_len = msg.control_len.min(size_of::<RecvControl>());
+        let ecn = received_ecn(&control.0[..control_len]);
+        Ok((len, decode_sockaddr(&name)?, ecn))
+    }
+
+    /// Walk the control messages for an `IP_TOS` or `IPV6_TCLASS` entry.
+    fn received_ecn(control: &[u8]) -> EcnCodepoint {
+        let header_len = size_of::<CmsgHdr>();
+        let mut offset = 0;
+        while offset + header_len <= control.len() {
+            let field = |at: usize, len: usize| &control[offset + at..offset + at + len];
+            let len = usize::from_ne_bytes(field(0, 8).try_into().expect("8 bytes"));
+            let level = c_int::from_ne_bytes(field(8, 4).try_into().expect("4 bytes"));
+            let kind = c_int::from_ne_bytes(field(12, 4).try_into().expect("4 bytes"));
+            if len < header_len || offset + len > control.len() {
+                break;
+            }
+            let data = &control[offset + header_len..offset + len];
+            match (level, kind) {
+                // IPv4 delivers the TOS as a single byte.
+                (IPPROTO_IP, IP_TOS) if !data.is_empty() => {
+                    return EcnCodepoint::from_tos(data[0]);
+                }
+                (IPPROTO_IPV6, IPV6_TCLASS) if data.len() >= 4 => {
+                    let tclass = c_int::from_ne_bytes(data[..4].try_into().expect("4 bytes"));
+                    return EcnCodepoint::from_tos(u8::try_from(tclass & 0xFF).unwrap_or(0));
+                }
+                _ => {}
+            }
+            offset += len.next_multiple_of(8);
+        }
+        EcnCodepoint::NotEct
+    }
+
+    /// GSO needs every segment but the last to share one size, and the last no larger.
+    pub(super) fn is_segmentable(packets: &[IoSlice<'_>]) -> bool {
+        let size = packets[0].len();
+        let (last, body) = packets.split_last().expect("non-empty batch");
+        size > 0
+            && u16::try_from(size).is_ok()
+            && body.iter().all(|packet| packet.len() == size)
+            && last.len() <= size
+    }
+
+    /// Errors meaning the kernel or device cannot offload segmentation.
+    pub(super) fn is_unsupported(err: &io::Error) -> bool {
+        // EIO, EINVAL, ENOPROTOOPT, EOPNOTSUPP
+        matches!(err.raw_os_error(), Some(5 | 22 | 92 | 95))
+    }
+
+    /// A GSO send that failed after its first `packets` datagrams, `bytes` in all, went out.
+    #[derive(Debug)]
+    pub(super) struct PartialSend {
+        pub(super) packets: usize,
+        pub(super) bytes: usize,
+        pub(super) error: io::Error,
+    }
+
+    pub(super) fn send(
+        socket: &UdpSocket,
+        packets: &[IoSlice<'_>],
+        addr: SocketAddr,
+    ) -> Result<usize, PartialSend> {
+        let segment_size = packets[0].len();
+        send_chunked(packets, segment_size, |batch| {
+            send_segments(socket, batch, segment_size, addr)
+        })
+    }
+
+    /// Hand `packets` to `send_chunk` as many at a time as one GSO send carries, stopping at
+    /// the first failure.
+    pub(super) fn send_chunked(
+        packets: &[IoSlice<'_>],
+        segment_size: usize,
+        mut send_chunk: impl FnMut(&[IoSlice<'_>]) -> io::Result<usize>,
+    ) -> Result<usize, PartialSend> {
+        let per_send = (MAX_GSO_PAYLOAD / segment_size).clamp(1, MAX_SEGMENTS);
+        let mut sent = 0;
+        for (index, batch) in packets.chunks(per_send).enumerate() {
+            match send_chunk(batch) {
+                Ok(bytes) => sent += bytes,
+                Err(error) => {
+                    return Err(PartialSend {
+                        packets: index * per_send,
+                        bytes: sent,
+                        error,
+                    });
+                }
+            }
+        }
+        Ok(sent)
+    }
+
+    fn send_segments(
+        socket: &UdpSocket,
+        segments: &[IoSlice<'_>],
+        segment_size: usize,
+        addr: SocketAddr,
+    ) -> io::Result<usize> {
+        let (mut name, name_len) = sockaddr(addr);
+        let mut control = SegmentControl {
+            header: CmsgHdr {
+                len: size_of::<CmsgHdr>() + size_of::<u16>(),
+                level: SOL_UDP,
+                kind: UDP_SEGMENT,
+            },
+            segment_size: u16::try_from(segment_size).expect("checked by is_segmentable"),
+            padding: [0; 6],
+        };
+        let msg = MsgHdr {
+            name: (&raw mut name).cast(),
+            name_len,
+            // `IoSlice` is ABI-compatible with `struct iovec` on Unix.
+            iov: segments.as_ptr().cast_mut().cast(),
+            iov_len: segments.len(),
+            control: (&raw mut control).cast(),
+            control_len: size_of::<SegmentControl>(),
+            flags: 0,
+        };
+        // SAFETY: every pointer in `msg` refers to a live local or to `segments`, all of which
+        // outlive the call, and the structs match the kernel's 64-bit `msghdr`/`cmsghdr` layout.
+        let sent = unsafe { sendmsg(socket.as_raw_fd(), &raw const msg, 0) };
+        usize::try_from(sent).map_err(|_| io::Error::last_os_error())
+    }
+
+    /// Receive up to `buffers.len()` datagrams with one `recvmmsg`, blocking for the first.
+    pub(super) fn recv(
+        socket: &UdpSocket,
+        buffers: &mut [&mut [u8]],
+    ) -> io::Result<Vec<(usize, SocketAddr)>> {
+        let count = buffers.len().min(MAX_BATCH);
+        let mut iovecs: Vec<IoSliceMut<'_>> = buffers[..count]
+            .iter_mut()
+            .map(|buf| IoSliceMut::new(buf))
+            .collect();
+        let mut names = vec![
+            SockAddrStorage {
+                family: 0,
+                data: [0; 126],
+            };
+            count
+        ];
+        let mut headers: Vec<MmsgHdr> = iovecs
+            .iter_mut()
+            .zip(names.iter_mut())
+            .map(|(iov, name)| MmsgHdr {
+                hdr: MsgHdr {
+                    name: ptr::from_mut(name).cast(),
+                    name_len: socklen::<SockAddrStorage>(),
+                    // `IoSliceMut` is ABI-compatible with `struct iovec` on Unix.
+                    iov: ptr::from_mut(iov).cast(),
+                    iov_len: 1,
+                    control: ptr::null_mut(),
+                    control_len: 0,
+                    flags: 0,
+                },
+                len: 0,
+            })
+            .collect();
+
+        let len = u32::try_from(count).expect("batch bounded by MAX_BATCH");
+        // SAFETY: each header points at a live iovec and sockaddr storage owned by this frame,
+        // and the structs match the kernel's 64-bit `mmsghdr` layout.
+        let received = unsafe {
+            recvmmsg(
+                socket.as_raw_fd(),
+                headers.as_mut_ptr(),
+                len,
+                MSG_WAITFORONE,
+                ptr::null_mut(),
+            )
+        };
+        let received = usize::try_from(received).map_err(|_| io::Error::last_os_error())?;
+        headers[..received]
+            .iter()
+            .zip(&names)
+            .map(|(header, name)| Ok((header.len as usize, decode_sockaddr(name)?)))
+            .collect()
+    }
+
+    fn decode_sockaddr(name: &SockAddrStorage) -> io::Result<SocketAddr> {
+        let data = &name.data;
+        let port = u16::from_be_bytes([data[0], data[1]]);
+        match name.family {
+            AF_INET => {
+                let ip = Ipv4Addr::new(data[2], data[3], data[4], data[5]);
+                Ok(SocketAddr::V4(SocketAddrV4::new(ip, port)))
+            }
+            AF_INET6 => {
+                let flow_info = u32::from_be_bytes(data[2..6].try_into().expect("4 bytes"));
+                let octets: [u8; 16] = data[6..22].try_into().expect("16 bytes");
+                let scope_id = u32::from_ne_bytes(data[22..26].try_into().expect("4 bytes"));
+                Ok(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(octets),
+                    port,
+                    flow_info,
+                    scope_id,
+                )))
+            }
+            family => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected address family {family}"),
+            )),
+        }
+    }
+
+    fn socklen<T>() -> u32 {
+        u32::try_from(size_of::<T>()).expect("sockaddr size fits socklen_t")
+    }
+
+    fn sockaddr(addr: SocketAddr) -> (SockAddr, u32) {
+        match addr {
+            SocketAddr::V4(v4) => (
+                SockAddr {
+                    v4: SockAddrIn {
+                        family: AF_INET,
+                        port: v4.port().to_be_bytes(),
+                        addr: v4.ip().octets(),
+                        zero: [0; 8],
+                    },
+                },
+                socklen::<SockAddrIn>(),
+            ),
+            SocketAddr::V6(v6) => (
+                SockAddr {
+                    v6: SockAddrIn6 {
+                        family: AF_INET6,
+                        port: v6.port().to_be_bytes(),
+                        flow_info: v6.flowinfo().to_be_bytes(),
+                        addr: v6.ip().octets(),
+                        scope_id: v6.scope_id(),
+                    },
+                },
+                socklen::<SockAddrIn6>(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair() -> (SocketBinding, SocketBinding) {
+        let localhost: SocketAddr = "127.0.0.1:0".parse().expect("addr");
+        let sender = SocketBinding::bind(localhost).expect("bind sender");
+        let receiver = SocketBinding::bind(localhost).expect("bind receiver");
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(2)))
+            .expect("timeout");
+        (sender, receiver)
+    }
+
+    fn receive_sizes(receiver: &SocketBinding, count: usize) -> Vec<usize> {
+        let mut buf = [0u8; 2048];
+        (0..count)
+            .map(|_| receiver.recv_from(&mut buf).expect("datagram").len)
+            .collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn gso_batch_received_as_separate_datagrams() {
+        let (sender, receiver) = pair();
+        let full = [0xABu8; 1200];
+        let tail = [0xCDu8; 300];
+        let packets = [
+            IoSlice::new(&full),
+            IoSlice::new(&full),
+            IoSlice::new(&full),
+            IoSlice::new(&tail),
+        ];
+        let target = receiver.local_addr().expect("addr");
+        assert_eq!(sender.send_batch(&packets, target).expect("send"), 3900);
+        assert_eq!(receive_sizes(&receiver, 4), vec![1200, 1200, 1200, 300]);
+    }
+
+    #[cfg(all(target_os = "linux", target_pointer_width = "64"))]
+    #[test]
+    fn failed_gso_chunk_reports_what_already_went_out() {
+        let packet = [0u8; 1200];
+        let packets = [IoSlice::new(&packet); 120];
+        let mut chunks = Vec::new();
+        let partial = sys::send_chunked(&packets, packet.len(), |batch| {
+            chunks.push(batch.len());
+            if chunks.len() == 2 {
+                Err(io::Error::from_raw_os_error(5))
+            } else {
+                Ok(batch.iter().map(|packet| packet.len()).sum())
+            }
+        })
+        .expect_err("second chunk fails");
+
+        // 54 segments of 1200 bytes fill one GSO send; only the first chunk went out, so
+        // only the remaining 66 packets may be sent again.
+        assert_eq!(chunks, [54, 54]);
+        assert_eq!((partial.packets, partial.bytes), (54, 54 * 1200));
+        assert!(sys::is_unsupported(&partial.error));
+    }
+
+    fn send_datagrams(sender: &SocketBinding, receiver: &SocketBinding, sizes: &[usize]) {
+        let target = receiver.local_addr().expect("addr");
+        for &size in sizes {
+            sender.send_to(&vec![0x42; size], target).expect("send");
+        }
+    }
+
+    #[test]
+    fn recv_batch_drains_queued_datagrams() {
+        let (sender, receiver) = pair();
+        send_datagrams(&sender, &receiver, &[100, 200, 300]);
+
+        let mut storage = vec![[0u8; 2048]; 8];
+        let mut buffers: Vec<&mut [u8]> = storage.iter_mut().map(|buf| &mut buf[..]).collect();
+        let datagrams = receiver.recv_batch(&mut buffers).expect("batch");
+        let sizes: Vec<usize> = datagrams.iter().map(|(len, _)| *len).collect();
+        assert_eq!(sizes, vec![100, 200, 300]);
+        let source = sender.local_addr().expect("addr");
+        assert!(datagrams.iter().all(|(_, from)| *from == source));
+    }
+
+    #[test]
+    fn recv_batch_fallback_drains_queued_datagrams() {
+        let (sender, receiver) = pair();
+        send_datagrams(&sender, &receiver, &[10, 20, 30]);
+
+        let mut storage = [[0u8; 64]; 2];
+        let mut buffers: Vec<&mut [u8]> = storage.iter_mut().map(|buf| &mut buf[..]).collect();
+        let first = receiver.recv_batch_fallback(&mut buffers).expect("batch");
+        assert_eq!(
+            first.iter().map(|(len, _)| *len).collect::<Vec<_>>(),
+            vec![10, 20]
+        );
+        let second = receiver.recv_batch_fallback(&mut buffers).expect("batch");
+        assert_eq!(
+            second.iter().map(|(len, _)| *len).collect::<Vec<_>>(),
+            vec![30]
+        );
+        assert!(!receiver.nonblocking.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn recv_batch_fallback_leaves_the_socket_blocking() {
+        let (sender, receiver) = pair();
+        send_datagrams(&sender, &receiver, &[10]);
+
+        // Draining runs into an empty queue after the first datagram.
+        let mut storage = [[0u8; 64]; 3];
+        let mut buffers: Vec<&mut [u8]> = storage.iter_mut().map(|buf| &mut buf[..]).collect();
+        assert_eq!(
+            receiver
+                .recv_batch_fallback(&mut buffers)
+                .expect("batch")
+                .len(),
+            1
+        );
+
+        // A blocking socket waits out its read timeout instead of failing at once.
+        let timeout = Duration::from_millis(50);
+        receiver.set_read_timeout(Some(timeout)).expect("timeout");
+        let started = std::time::Instant::now();
+        let mut buf = [0u8; 64];
+        assert!(receiver.recv_from(&mut buf).is_err());
+        assert!(started.elapsed() >= timeout / 2);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn ecn_codepoints_roundtrip_over_loopback() {
+        let (sender, receiver) = pair();
+        let target = receiver.local_addr().expect("addr");
+        let mut buf = [0u8; 64];
+
+        sender.send_to(b"default", target).expect("send");
+        let meta = receiver.recv_from(&mut buf).expect("recv");
+        assert_eq!(
+            meta,
+            RecvMeta {
+                len: 7,
+                addr: sender.local_addr().expect("addr"),
+                ecn: EcnCodepoint::Ect0,
+            }
+        );
+
+        for codepoint in [
+            EcnCodepoint::NotEct,
+            EcnCodepoint::Ect1,
+            EcnCodepoint::Ect0,
+            EcnCodepoint::Ce,
+        ] {
+            assert_eq!(
+                sender
+                    .send_to_ecn(b"marked", target, codepoint)
+                    .expect("send"),
+                6
+            );
+            let meta = receiver.recv_from(&mut buf).expect("recv");
+            assert_eq!(meta.len, 6);
+            assert_eq!(meta.ecn, codepoint);
+        }
+    }
+
+    #[cfg_attr(
+        not(all(target_os = "linux", target_pointer_width = "64")),
+        ignore = "ECN socket options are only wired up on 64-bit Linux"
+    )]
+    #[test]
+    fn set_ecn_toggles_marking_over_loopback() {
+        let (sender, receiver) = pair();
+        let target = receiver.local_addr().expect("addr");
+        let mut buf = [0u8; 64];
+        assert!(sender.ecn_enabled());
+
+        assert!(!sender.set_ecn(false));
+        assert!(!sender.ecn_enabled());
+        sender.send_to(b"plain", target).expect("send");
+        assert_eq!(
+            receiver.recv_from(&mut buf).expect("recv").ecn,
+            EcnCodepoint::NotEct
+        );
+
+        assert!(sender.set_ecn(true));
+        sender.send_to(b"marked", target).expect("send");
+        assert_eq!(
+            receiver.recv_from(&mut buf).expect("recv").ecn,
+            EcnCodepoint::Ect0
+        );
+
+        // With reporting off the receiver no longer sees the marks.
+        assert!(!receiver.set_ecn(false));
+        sender.send_to(b"unseen", target).expect("send");
+        assert_eq!(
+            receiver.recv_from(&mut buf).expect("recv").ecn,
+            EcnCodepoint::NotEct
+        );
+    }
+
+    #[test]
+    fn fallback_sends_each_packet() {
+        let (sender, receiver) = pair();
+        sender.set_segmentation_offload(false);
+        assert!(!sender.segmentation_offload());
+        let packets = [
+            IoSlice::new(b"short"),
+            IoSlice::new(&[1u8; 700]),
+            IoSlice::new(b"tail"),
+        ];
+        let target = receiver.local_addr().expect("addr");
+        assert_eq!(sender.send_batch(&packets, target).expect("send"), 709);
+        assert_eq!(receive_sizes(&receiver, 3), vec![5, 700, 4]);
+        assert_eq!(sender.send_batch(&[], target).expect("empty"), 0);
+    }
+}
@@ -1,11 +1,30 @@
 //! Reliable stream state machines and buffering for MXP transport.
 
 use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
 
+use bytes::{Buf, Bytes};
+
+use crate::protocol::StreamOpen;
 use crate::protocol::metrics::Metrics;
 use tracing::{debug, instrument, trace};
 
+use super::batch::{BatchedReceiver, MessageSink};
 use super::flow::{FlowControlError, FlowController};
+use super::packet::{Frame, MAX_VARINT};
+use super::params::TransportParameters;
+use super::scheduler::PriorityClass;
+
+/// Default cap on unread bytes buffered for one stream before its credit is withheld.
+pub const DEFAULT_MAX_BUFFERED_PER_STREAM: u64 = 1 << 20;
+/// Default cap on unread bytes buffered across all streams before all credit is withheld.
+pub const DEFAULT_MAX_BUFFERED_TOTAL: u64 = 16 << 20;
+
+/// Default cap on concurrently open bidirectional streams per initiator.
+pub const DEFAULT_MAX_CONCURRENT_BIDI_STREAMS: u64 = 100;
+
+/// Default cap on concurrently open unidirectional streams per initiator.
+pub const DEFAULT_MAX_CONCURRENT_UNI_STREAMS: u64 = 100;
 
 /// Direction of stream initiation relative to the local endpoint.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -47,6 +66,14 @@ impl StreamKind {
         }
     }
 
+    /// Wire encoding used by `STREAMS_BLOCKED` frames.
+    pub(crate) const fn as_u8(self) -> u8 {
+        match self {
+            Self::Bidirectional => 0,
+            Self::Unidirectional => 1,
+        }
+    }
+
     const fn from_bit(bit: u64) -> Self {
         if bit == 0 {
             Self::Bidirectional
@@ -57,22 +84,48 @@ impl StreamKind {
 }
 
 /// Monotonically increasing identifier for streams.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct StreamId(u64);
 
 impl StreamId {
     /// Compose a stream identifier from role, kind, and sequence number.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the identifier would exceed [`MAX_VARINT`], i.e. `index` is above
+    /// `MAX_VARINT >> 2`.
     #[must_use]
     pub const fn new(role: EndpointRole, kind: StreamKind, index: u64) -> Self {
+        assert!(
+            index <= MAX_VARINT >> 2,
+            "stream index exceeds the varint range"
+        );
         Self((index << 2) | (role.bit() << 1) | kind.bit())
     }
 
     /// Parse a raw identifier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `raw` is above [`MAX_VARINT`]; use [`try_from_raw`](Self::try_from_raw)
+    /// for identifiers read from the network.
     #[must_use]
     pub const fn from_raw(raw: u64) -> Self {
+        assert!(raw <= MAX_VARINT, "stream id exceeds the varint range");
         Self(raw)
     }
 
+    /// Parse a raw identifier, or `None` if it is above [`MAX_VARINT`] and so cannot be
+    /// carried in a frame.
+    #[must_use]
+    pub const fn try_from_raw(raw: u64) -> Option<Self> {
+        if raw <= MAX_VARINT {
+            Some(Self(raw))
+        } else {
+            None
+        }
+    }
+
     /// Return the raw numeric value.
     #[must_use]
     pub const fn as_u64(self) -> u64 {
@@ -102,6 +155,41 @@ impl StreamId {
     pub fn is_local_initiated(self, local: EndpointRole) -> bool {
         self.role() == local
     }
+
+    /// Whether `local` may send on the stream: any bidirectional stream, or a
+    /// unidirectional one it opened.
+    #[must_use]
+    pub fn can_send(self, local: EndpointRole) -> bool {
+        self.kind() == StreamKind::Bidirectional || self.is_local_initiated(local)
+    }
+
+    /// Whether `local` may receive on the stream: any bidirectional stream, or a
+    /// unidirectional one the peer opened.
+    #[must_use]
+    pub fn can_receive(self, local: EndpointRole) -> bool {
+        self.kind() == StreamKind::Bidirectional || !self.is_local_initiated(local)
+    }
+}
+
+/// Operation on a stream that its identifier may not permit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamAccess {
+    /// Opening the stream locally; only the initiator's role may do this.
+    Open,
+    /// Sending data or a FIN.
+    Send,
+    /// Receiving data.
+    Receive,
+}
+
+impl fmt::Display for StreamAccess {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Open => "open",
+            Self::Send => "send on",
+            Self::Receive => "receive on",
+        })
+    }
 }
 
 /// Error conditions for stream operations.
@@ -122,6 +210,46 @@ pub enum StreamError {
     /// Stream doesn't present in the manager.
     #[error("unknown stream id")]
     UnknownStream,
+    /// Opening the stream would exceed the negotiated stream limit.
+    #[error("stream limit of {limit} reached")]
+    StreamLimitExceeded {
+        /// Negotiated maximum number of streams.
+        limit: u64,
+    },
+    /// Opening the stream would exceed the concurrent limit for its kind and initiator.
+    #[error("concurrent {kind:?} stream limit of {limit} reached")]
+    ConcurrencyLimitExceeded {
+        /// Kind of stream that was refused.
+        kind: StreamKind,
+        /// Maximum number of concurrently open streams of that kind.
+        limit: u64,
+    },
+    /// The manager is draining and accepts no new streams.
+    #[error("stream manager is draining")]
+    Draining,
+    /// The stream's initiator and direction forbid the operation at this endpoint.
+    #[error("local endpoint may not {access} stream {id}")]
+    IllegalAccess {
+        /// Raw stream identifier.
+        id: u64,
+        /// Operation that was refused.
+        access: StreamAccess,
+    },
+    /// The stream id is above [`MAX_VARINT`] and cannot be carried in a frame.
+    #[error("stream id {id} exceeds the varint range")]
+    InvalidStreamId {
+        /// Raw stream identifier.
+        id: u64,
+    },
+    /// Queuing the data would move the stream's send offset past [`MAX_VARINT`].
+    #[error("stream send offset would exceed 2^62 - 1")]
+    OffsetOverflow,
+    /// The peer sent `STOP_SENDING`; the send side was reset and accepts no more data.
+    #[error("peer stopped the stream with code {code}")]
+    SendStopped {
+        /// Application error code from the peer's `STOP_SENDING`.
+        code: u64,
+    },
 }
 
 /// Chunk of data ready for transmission.
@@ -133,26 +261,194 @@ pub struct SendChunk {
     pub payload: Vec<u8>,
     /// Whether this chunk carries the final FIN flag.
     pub fin: bool,
+    /// Whether this chunk resends data declared lost; such bytes consume no flow credit.
+    pub retransmission: bool,
+}
+
+/// Stream bytes (and possibly the FIN) carried by one sent packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SentRange {
+    offset: u64,
+    len: u64,
+    fin: bool,
+}
+
+/// Progress of a stream's send side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendState {
+    /// Data or a FIN is still waiting to be sent (or no FIN was queued yet).
+    Sending,
+    /// Every byte and the FIN were sent; some are not yet acknowledged.
+    Sent,
+    /// The peer acknowledged every byte and the FIN; resources may be released.
+    DataAcked,
+    /// The send side was abandoned after the peer sent `STOP_SENDING` with `code`.
+    Reset {
+        /// Application error code carried by the peer's `STOP_SENDING`.
+        code: u64,
+    },
+}
+
+/// Bytes addressed by stream offset, stored as the chunks they arrived in.
+///
+/// Appending a chunk moves it in without copying, and bytes are dropped from the front by
+/// slicing the first chunk, so large transfers never touch bytes one at a time.
+#[derive(Debug, Default)]
+struct ChunkQueue {
+    /// Chunks in stream order, each with the offset of its first byte.
+    chunks: VecDeque<(u64, Bytes)>,
+    /// Offset of the first byte held.
+    start: u64,
+    /// Offset just past the last byte held.
+    end: u64,
+}
+
+impl ChunkQueue {
+    fn starting_at(offset: u64) -> Self {
+        Self {
+            chunks: VecDeque::new(),
+            start: offset,
+            end: offset,
+        }
+    }
+
+    const fn start(&self) -> u64 {
+        self.start
+    }
+
+    const fn end(&self) -> u64 {
+        self.end
+    }
+
+    fn len(&self) -> usize {
+        offset_in(self.start, self.end)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    fn push(&mut self, bytes: Bytes) {
+        if bytes.is_empty() {
+            return;
+        }
+        let len = bytes.len() as u64;
+        self.chunks.push_back((self.end, bytes));
+        self.end += len;
+    }
+
+    /// The bytes at `from..to`, which must lie within the queue, one slice per chunk.
+    fn slices(&self, from: u64, to: u64) -> impl Iterator<Item = &[u8]> {
+        let first = self
+            .chunks
+            .partition_point(|(chunk_start, _)| *chunk_start <= from)
+            .saturating_sub(1);
+        self.chunks
+            .range(first..)
+            .take_while(move |(chunk_start, _)| *chunk_start < to)
+            .map(move |(chunk_start, chunk)| {
+                let chunk_end = chunk_start + chunk.len() as u64;
+                let lo = offset_in(*chunk_start, from.max(*chunk_start));
+                let hi = offset_in(*chunk_start, to.min(chunk_end));
+                &chunk[lo..hi]
+            })
+    }
+
+    /// Copy the bytes at `from..to`, which must lie within the queue.
+    fn copy_range(&self, from: u64, to: u64) -> Vec<u8> {
+        let mut out = Vec::with_capacity(offset_in(from, to));
+        for slice in self.slices(from, to) {
+            out.extend_from_slice(slice);
+        }
+        out
+    }
+
+    /// Remove up to `max_len` bytes from the front, limited to the first chunk so no copy
+    /// is made; a chunk taken whole is handed over as is.
+    fn take_front(&mut self, max_len: usize) -> Bytes {
+        let Some((chunk_start, chunk)) = self.chunks.front_mut() else {
+            return Bytes::new();
+        };
+        let taken = if chunk.len() <= max_len {
+            self.chunks
+                .pop_front()
+                .map(|(_, chunk)| chunk)
+                .unwrap_or_default()
+        } else {
+            *chunk_start += max_len as u64;
+            chunk.split_to(max_len)
+        };
+        self.start += taken.len() as u64;
+        taken
+    }
+
+    /// Drop every byte below `offset`.
+    fn advance_to(&mut self, offset: u64) {
+        let offset = offset.clamp(self.start, self.end);
+        while let Some((chunk_start, chunk)) = self.chunks.front_mut() {
+            let chunk_end = *chunk_start + chunk.len() as u64;
+            if chunk_end <= offset {
+                self.chunks.pop_front();
+                continue;
+            }
+            if *chunk_start < offset {
+                chunk.advance(offset_in(*chunk_start, offset));
+                *chunk_start = offset;
+            }
+            break;
+        }
+        self.start = offset;
+    }
 }
 
+/// Outgoing bytes, kept from the first unacknowledged offset onwards.
 #[derive(Debug, Default)]
+#[allow(clippy::struct_excessive_bools)] // FIN lifecycle: queued, sent, lost, acked
 struct SendBuffer {
-    buffer: VecDeque<u8>,
+    /// Sent-but-unacknowledged bytes followed by bytes not yet sent, starting at
+    /// `acked_offset`.
+    buffer: ChunkQueue,
+    /// Every byte below this offset was acknowledged.
+    acked_offset: u64,
+    /// Acknowledged ranges above `acked_offset`, as start -> end (exclusive).
+    acked_ranges: BTreeMap<u64, u64>,
+    /// Ranges carried by each unacknowledged packet, keyed by packet number.
+    in_flight: BTreeMap<u64, Vec<SentRange>>,
+    /// Ranges declared lost and awaiting retransmission, as start -> end (exclusive).
+    lost: BTreeMap<u64, u64>,
     fin_queued: bool,
     fin_sent: bool,
+    fin_acked: bool,
+    /// The packet carrying the FIN was lost and the FIN must be sent again.
+    fin_lost: bool,
     next_offset: u64,
+    reset: Option<u64>,
 }
 
 impl SendBuffer {
-    fn queue(&mut self, data: &[u8]) -> Result<(), StreamError> {
+    fn queue(&mut self, data: Bytes) -> Result<(), StreamError> {
+        if let Some(code) = self.reset {
+            return Err(StreamError::SendStopped { code });
+        }
         if self.fin_queued {
             return Err(StreamError::AlreadyFinished);
         }
-        self.buffer.extend(data);
+        if self
+            .buffer
+            .end()
+            .checked_add(data.len() as u64)
+            .is_none_or(|end| end > MAX_VARINT)
+        {
+            return Err(StreamError::OffsetOverflow);
+        }
+        self.buffer.push(data);
         Ok(())
     }
 
     fn queue_fin(&mut self) -> Result<(), StreamError> {
+        if let Some(code) = self.reset {
+            return Err(StreamError::SendStopped { code });
+        }
         if self.fin_queued {
             Err(StreamError::AlreadyFinished)
         } else {
@@ -161,20 +457,32 @@ impl SendBuffer {
         }
     }
 
+    fn unsent_len(&self) -> usize {
+        offset_in(self.next_offset, self.buffer.end())
+    }
+
+    /// Whether lost bytes or a lost FIN are waiting to be resent.
+    fn has_lost(&self) -> bool {
+        !self.lost.is_empty() || self.fin_lost
+    }
+
     fn next_chunk(&mut self, max_len: usize) -> Option<SendChunk> {
-        if (self.fin_sent || !self.fin_queued) && self.buffer.is_empty() {
+        if self.reset.is_some() {
             return None;
         }
-
-        let take = self.buffer.len().min(max_len);
-        let mut payload = Vec::with_capacity(take);
-        for _ in 0..take {
-            if let Some(byte) = self.buffer.pop_front() {
-                payload.push(byte);
-            }
+        if self.has_lost() {
+            return self.next_retransmission(max_len);
+        }
+        if (self.fin_sent || !self.fin_queued) && self.unsent_len() == 0 {
+            return None;
         }
 
-        let fin = self.buffer.is_empty() && self.fin_queued && !self.fin_sent;
+        let take = self.unsent_len().min(max_len);
+        let payload = self
+            .buffer
+            .copy_range(self.next_offset, self.next_offset + take as u64);
+
+        let fin = self.unsent_len() == take && self.fin_queued && !self.fin_sent;
         if fin {
             self.fin_sent = true;
         }
@@ -185,24 +493,225 @@ impl SendBuffer {
             offset,
             payload,
             fin,
+            retransmission: false,
+        })
+    }
+
+    /// Resend the lowest lost range, skipping bytes acknowledged since it was lost.
+    fn next_retransmission(&mut self, max_len: usize) -> Option<SendChunk> {
+        if max_len == 0 {
+            return None;
+        }
+        while let Some((start, end)) = self.lost.pop_first() {
+            let mut start = start.max(self.acked_offset);
+            if let Some((_, acked_end)) = self
+                .acked_ranges
+                .range(..=start)
+                .next_back()
+                .filter(|(_, acked_end)| **acked_end > start)
+            {
+                start = *acked_end;
+            }
+            if start >= end {
+                continue;
+            }
+            let mut stop = end.min(start.saturating_add(max_len as u64));
+            if let Some((acked_start, _)) = self.acked_ranges.range(start..stop).next() {
+                stop = *acked_start;
+            }
+            if stop < end {
+                self.lost.insert(stop, end);
+            }
+            let fin = self.fin_lost && stop == self.next_offset;
+            if fin {
+                self.fin_lost = false;
+            }
+            return Some(SendChunk {
+                offset: start,
+                payload: self.buffer.copy_range(start, stop),
+                fin,
+                retransmission: true,
+            });
+        }
+        if !self.fin_lost {
+            return None;
+        }
+        self.fin_lost = false;
+        Some(SendChunk {
+            offset: self.next_offset,
+            payload: Vec::new(),
+            fin: true,
+            retransmission: true,
         })
     }
 
+    /// Remember that packet `packet_number` carried `chunk`.
+    fn on_sent(&mut self, chunk: &SendChunk, packet_number: u64) {
+        if self.reset.is_some() {
+            return;
+        }
+        self.in_flight
+            .entry(packet_number)
+            .or_default()
+            .push(SentRange {
+                offset: chunk.offset,
+                len: chunk.payload.len() as u64,
+                fin: chunk.fin,
+            });
+    }
+
+    /// Acknowledge every range packet `packet_number` carried.
+    fn on_packet_acked(&mut self, packet_number: u64) {
+        for range in self.in_flight.remove(&packet_number).unwrap_or_default() {
+            self.on_acked(range.offset, range.len, range.fin);
+        }
+    }
+
+    /// Queue the ranges packet `packet_number` carried for retransmission, except bytes
+    /// already acknowledged through another packet.
+    fn on_packet_lost(&mut self, packet_number: u64) {
+        for range in self.in_flight.remove(&packet_number).unwrap_or_default() {
+            if range.fin && self.fin_sent && !self.fin_acked {
+                self.fin_lost = true;
+            }
+            let start = range.offset.max(self.acked_offset);
+            let end = range.offset.saturating_add(range.len).min(self.next_offset);
+            if start < end {
+                insert_merged(&mut self.lost, start, end);
+            }
+        }
+    }
+
+    /// Record that the chunk at `offset..offset + len` (and the FIN, if `fin`) was
+    /// acknowledged, releasing bytes once everything before them is acknowledged too.
+    fn on_acked(&mut self, offset: u64, len: u64, fin: bool) {
+        if self.reset.is_some() {
+            return;
+        }
+        let end = offset.saturating_add(len).min(self.next_offset);
+        if fin && self.fin_sent {
+            self.fin_acked = true;
+            self.fin_lost = false;
+        }
+        let start = offset.max(self.acked_offset);
+        if start >= end {
+            return;
+        }
+
+        let merged = insert_merged(&mut self.acked_ranges, start, end);
+        if merged.0 <= self.acked_offset {
+            self.acked_ranges.remove(&merged.0);
+            self.buffer.advance_to(merged.1);
+            self.acked_offset = merged.1;
+        }
+    }
+
+    /// Give up on every byte below `before`, sent or not, as if the peer had acknowledged
+    /// it, so none of it is sent or retransmitted again.
+    ///
+    /// Returns the offset the peer should skip to, or `None` if nothing new expired.
+    fn expire(&mut self, before: u64) -> Option<u64> {
+        let before = before.min(self.buffer.end());
+        if self.reset.is_some() || before <= self.acked_offset {
+            return None;
+        }
+        self.buffer.advance_to(before);
+        self.acked_offset = before;
+        self.next_offset = self.next_offset.max(before);
+        trim_below(&mut self.lost, before);
+        trim_below(&mut self.acked_ranges, before);
+        if let Some(entry) = self.acked_ranges.first_entry() {
+            if *entry.key() <= self.acked_offset {
+                let end = entry.remove();
+                self.buffer.advance_to(end);
+                self.acked_offset = end;
+            }
+        }
+        Some(before)
+    }
+
+    fn reset(&mut self, code: u64) {
+        if self.reset.is_none() && self.state() != SendState::DataAcked {
+            self.reset = Some(code);
+            self.acked_offset = self.next_offset;
+            self.buffer = ChunkQueue::starting_at(self.next_offset);
+            self.acked_ranges.clear();
+            self.in_flight.clear();
+            self.lost.clear();
+            self.fin_lost = false;
+        }
+    }
+
+    fn state(&self) -> SendState {
+        if let Some(code) = self.reset {
+            SendState::Reset { code }
+        } else if !self.fin_sent || self.unsent_len() > 0 || self.has_lost() {
+            SendState::Sending
+        } else if self.fin_acked && self.buffer.is_empty() {
+            SendState::DataAcked
+        } else {
+            SendState::Sent
+        }
+    }
+
     fn is_drained(&self) -> bool {
-        self.buffer.is_empty() && (!self.fin_queued || self.fin_sent)
+        self.reset.is_some()
+            || (self.unsent_len() == 0 && !self.has_lost() && (!self.fin_queued || self.fin_sent))
+    }
+
+    fn is_closed(&self) -> bool {
+        self.reset.is_some() || (self.unsent_len() == 0 && !self.has_lost() && self.fin_sent)
+    }
+}
+
+/// Insert `start..end` into a map of disjoint ranges (start -> end), merging it with any
+/// overlapping or adjacent ranges, and return the merged range.
+fn insert_merged(ranges: &mut BTreeMap<u64, u64>, start: u64, end: u64) -> (u64, u64) {
+    let mut merged = (start, end);
+    let touching: Vec<u64> = ranges
+        .range(..=end)
+        .filter(|(_, range_end)| **range_end >= start)
+        .map(|(range_start, _)| *range_start)
+        .collect();
+    for key in touching {
+        let range_end = ranges.remove(&key).expect("listed above");
+        merged = (merged.0.min(key), merged.1.max(range_end));
+    }
+    ranges.insert(merged.0, merged.1);
+    merged
+}
+
+/// Drop the parts of the ranges in `ranges` (start -> end) that lie below `offset`.
+fn trim_below(ranges: &mut BTreeMap<u64, u64>, offset: u64) {
+    while let Some(entry) = ranges.first_entry() {
+        if *entry.key() >= offset {
+            break;
+        }
+        let end = entry.remove();
+        if end > offset {
+            ranges.insert(offset, end);
+            break;
+        }
     }
 }
 
 #[derive(Debug, Default)]
 struct RecvBuffer {
-    delivered_offset: u64,
-    ready: VecDeque<u8>,
+    /// Contiguous bytes not yet read, starting at the delivered offset.
+    ready: ChunkQueue,
     pending: BTreeMap<u64, Vec<u8>>,
+    /// Total length of the ranges in `pending`.
+    pending_bytes: usize,
     final_offset: Option<u64>,
+    /// Error code sent in `STOP_SENDING`; later data is discarded.
+    stopped: Option<u64>,
 }
 
 impl RecvBuffer {
     fn ingest(&mut self, offset: u64, data: &[u8], fin: bool) -> Result<(), StreamError> {
+        if self.stopped.is_some() {
+            return Ok(());
+        }
         if let Some(final_offset) = self.final_offset {
             let incoming_end = offset.saturating_add(data.len() as u64);
             if incoming_end > final_offset {
@@ -214,12 +723,7 @@ impl RecvBuffer {
             return Ok(());
         }
 
-        let entry = self.pending.entry(offset).or_default();
-        if entry.is_empty() {
-            entry.extend_from_slice(data);
-        } else if entry.as_slice() != data {
-            return Err(StreamError::ConflictingData { offset });
-        }
+        self.insert_range(offset, data)?;
 
         if fin {
             let end = offset.saturating_add(data.len() as u64);
@@ -230,9 +734,76 @@ impl RecvBuffer {
         Ok(())
     }
 
+    /// Offset just past the contiguous data received so far.
+    fn contiguous_end(&self) -> u64 {
+        self.ready.end()
+    }
+
+    /// Merge `data` at `offset` into `pending`, coalescing overlapping and adjacent ranges.
+    ///
+    /// Bytes already received contiguously are dropped; bytes overlapping a pending range must
+    /// match it exactly. State is left untouched when a conflict is found.
+    fn insert_range(&mut self, offset: u64, data: &[u8]) -> Result<(), StreamError> {
+        let contiguous = self.contiguous_end();
+        let end = offset.saturating_add(data.len() as u64);
+        if data.is_empty() || end <= contiguous {
+            return Ok(());
+        }
+        let skip = usize::try_from(contiguous.saturating_sub(offset)).unwrap_or(usize::MAX);
+        let (start, data) = (offset.max(contiguous), &data[skip.min(data.len())..]);
+
+        // Ranges touching [start, end]: any starting at or before `end` that ends at or after
+        // `start`. Pending ranges never overlap each other, so scanning backwards can stop at
+        // the first one that ends before `start`.
+        let mut touching = Vec::new();
+        for (&entry_start, entry) in self.pending.range(..=end).rev() {
+            let entry_end = entry_start + entry.len() as u64;
+            if entry_end < start {
+                break;
+            }
+            let overlap_start = start.max(entry_start);
+            let overlap_end = end.min(entry_end);
+            let incoming = &data[offset_in(start, overlap_start)..offset_in(start, overlap_end)];
+            let existing =
+                &entry[offset_in(entry_start, overlap_start)..offset_in(entry_start, overlap_end)];
+            if let Some(pos) = incoming.iter().zip(existing).position(|(a, b)| a != b) {
+                return Err(StreamError::ConflictingData {
+                    offset: overlap_start + pos as u64,
+                });
+            }
+            touching.push(entry_start);
+        }
+
+        let merged_start = touching.last().map_or(start, |&first| first.min(start));
+        let mut merged = Vec::new();
+        let mut merged_end = merged_start;
+        for entry_start in touching.into_iter().rev() {
+            let entry = self.pending.remove(&entry_start).expect("exists");
+            self.pending_bytes -= entry.len();
+            if entry_start > merged_end {
+                // Gap before this entry is covered by the incoming data.
+                merged.extend_from_slice(
+                    &data[offset_in(start, merged_end)..offset_in(start, entry_start)],
+                );
+                merged_end = entry_start;
+            }
+            let entry_end = entry_start + entry.len() as u64;
+            if entry_end > merged_end {
+                merged.extend_from_slice(&entry[offset_in(entry_start, merged_end)..]);
+                merged_end = entry_end;
+            }
+        }
+        if end > merged_end {
+            merged.extend_from_slice(&data[offset_in(start, merged_end)..]);
+        }
+        self.pending_bytes += merged.len();
+        self.pending.insert(merged_start, merged);
+        Ok(())
+    }
+
     fn promote_pending(&mut self) {
         loop {
-            let next_offset = self.delivered_offset + self.ready.len() as u64;
+            let next_offset = self.contiguous_end();
             let Some((&offset, _)) = self.pending.first_key_value() else {
                 break;
             };
@@ -240,28 +811,100 @@ impl RecvBuffer {
                 break;
             }
             let chunk = self.pending.remove(&offset).expect("exists");
-            self.ready.extend(chunk);
+            self.pending_bytes -= chunk.len();
+            self.ready.push(Bytes::from(chunk));
         }
     }
 
     fn read(&mut self, max_len: usize) -> Vec<u8> {
-        let take = self.ready.len().min(max_len);
-        let mut out = Vec::with_capacity(take);
-        for _ in 0..take {
-            if let Some(byte) = self.ready.pop_front() {
-                out.push(byte);
-            }
-        }
-        self.delivered_offset = self.delivered_offset.saturating_add(out.len() as u64);
+        let from = self.ready.start();
+        let to = from + self.ready.len().min(max_len) as u64;
+        let out = self.ready.copy_range(from, to);
+        self.ready.advance_to(to);
         out
     }
 
+    fn read_into(&mut self, out: &mut [u8]) -> usize {
+        let from = self.ready.start();
+        let to = from + self.ready.len().min(out.len()) as u64;
+        let mut written = 0;
+        for slice in self.ready.slices(from, to) {
+            out[written..written + slice.len()].copy_from_slice(slice);
+            written += slice.len();
+        }
+        self.ready.advance_to(to);
+        written
+    }
+
+    fn read_bytes(&mut self, max_len: usize) -> Bytes {
+        self.ready.take_front(max_len)
+    }
+
+    /// Bytes received but not yet read: contiguous data plus out-of-order ranges.
+    fn buffered_bytes(&self) -> u64 {
+        (self.ready.len() + self.pending_bytes) as u64
+    }
+
     fn received_fin(&self) -> bool {
         self.final_offset
-            .is_some_and(|offset| self.delivered_offset + self.ready.len() as u64 >= offset)
+            .is_some_and(|offset| self.ready.end() >= offset)
+    }
+
+    /// Treat every byte below `offset` as delivered after the sender expired it: unread
+    /// and buffered bytes below it are dropped and reading resumes at `offset`.
+    fn expire(&mut self, offset: u64) -> Result<(), StreamError> {
+        if self.stopped.is_some() || offset <= self.ready.start() {
+            return Ok(());
+        }
+        if self
+            .final_offset
+            .is_some_and(|final_offset| offset > final_offset)
+        {
+            return Err(StreamError::DataBeyondFinalOffset);
+        }
+        if offset >= self.ready.end() {
+            self.ready = ChunkQueue::starting_at(offset);
+        } else {
+            self.ready.advance_to(offset);
+        }
+        while let Some(entry) = self.pending.first_entry() {
+            if *entry.key() >= offset {
+                break;
+            }
+            let start = *entry.key();
+            let chunk = entry.remove();
+            self.pending_bytes -= chunk.len();
+            let end = start + chunk.len() as u64;
+            if end > offset {
+                let kept = chunk[offset_in(start, offset)..].to_vec();
+                self.pending_bytes += kept.len();
+                self.pending.insert(offset, kept);
+                break;
+            }
+        }
+        self.promote_pending();
+        Ok(())
+    }
+
+    fn stop(&mut self, code: u64) {
+        self.stopped.get_or_insert(code);
+        self.ready.advance_to(self.ready.end());
+        self.pending.clear();
+        self.pending_bytes = 0;
+    }
+
+    /// Whether nothing more will be delivered: the FIN arrived and everything was read,
+    /// or the application stopped reading.
+    fn is_closed(&self) -> bool {
+        self.stopped.is_some() || (self.received_fin() && self.ready.is_empty())
     }
 }
 
+/// Index of stream offset `offset` within a range starting at `base`.
+fn offset_in(base: u64, offset: u64) -> usize {
+    usize::try_from(offset - base).expect("range fits in memory")
+}
+
 /// Combined stream state machine.
 #[derive(Debug)]
 pub struct Stream {
@@ -283,6 +926,12 @@ impl Stream {
     /// Queue application data for transmission.
     #[instrument(level = "trace", skip(self, data))]
     pub fn queue_send(&mut self, data: &[u8]) -> Result<(), StreamError> {
+        self.send.queue(Bytes::copy_from_slice(data))
+    }
+
+    /// Queue an owned buffer for transmission without copying it.
+    #[instrument(level = "trace", skip(self, data))]
+    pub fn queue_send_bytes(&mut self, data: Bytes) -> Result<(), StreamError> {
         self.send.queue(data)
     }
 
@@ -309,6 +958,20 @@ impl Stream {
         self.recv.read(max_len)
     }
 
+    /// Copy contiguous received data into `out`, returning how many bytes were written.
+    pub fn read_into(&mut self, out: &mut [u8]) -> usize {
+        self.recv.read_into(out)
+    }
+
+    /// Like [`read`](Self::read), but hands out a view of the received chunk instead of
+    /// copying.
+    ///
+    /// At most one received chunk is returned per call, so this may yield fewer bytes than
+    /// are readable; call it until it returns an empty buffer to drain the stream.
+    pub fn read_bytes(&mut self, max_len: usize) -> Bytes {
+        self.recv.read_bytes(max_len)
+    }
+
     /// Determine whether the receive side reached EOF.
     #[must_use]
     pub fn is_receive_finished(&self) -> bool {
@@ -320,14 +983,164 @@ impl Stream {
     pub fn is_send_drained(&self) -> bool {
         self.send.is_drained()
     }
+
+    /// Bytes received but not yet read by the application, including out-of-order data.
+    #[must_use]
+    pub fn buffered_bytes(&self) -> u64 {
+        self.recv.buffered_bytes()
+    }
+
+    /// Check whether received data is waiting to be read.
+    #[must_use]
+    pub fn has_readable_data(&self) -> bool {
+        !self.recv.ready.is_empty()
+    }
+
+    /// Check whether the send side still has data or a FIN to transmit.
+    #[must_use]
+    pub fn has_pending_data(&self) -> bool {
+        !self.send.is_drained()
+    }
+
+    /// Progress of the send side, from queued data through to acknowledgement.
+    #[must_use]
+    pub fn send_state(&self) -> SendState {
+        self.send.state()
+    }
+
+    /// Record that the peer acknowledged the chunk at `offset..offset + len`, and the FIN
+    /// if `fin` is set.
+    pub fn on_chunk_acked(&mut self, offset: u64, len: u64, fin: bool) {
+        self.send.on_acked(offset, len, fin);
+    }
+
+    /// Record that `chunk` went out in packet `packet_number`, so the packet's fate can
+    /// later be reported with [`on_packet_acked`](Self::on_packet_acked) or
+    /// [`on_packet_lost`](Self::on_packet_lost).
+    pub fn on_chunk_sent(&mut self, chunk: &SendChunk, packet_number: u64) {
+        self.send.on_sent(chunk, packet_number);
+    }
+
+    /// Acknowledge every chunk sent in packet `packet_number`.
+    pub fn on_packet_acked(&mut self, packet_number: u64) {
+        self.send.on_packet_acked(packet_number);
+    }
+
+    /// Queue the chunks sent in packet `packet_number` for retransmission.
+    ///
+    /// Only bytes not acknowledged through another packet are resent; they come out of
+    /// [`next_send_chunk`](Self::next_send_chunk) ahead of new data, marked
+    /// [`SendChunk::retransmission`].
+    pub fn on_packet_lost(&mut self, packet_number: u64) {
+        self.send.on_packet_lost(packet_number);
+    }
+
+    /// Whether bytes or a FIN declared lost are waiting to be resent.
+    #[must_use]
+    pub fn has_lost_data(&self) -> bool {
+        self.send.has_lost()
+    }
+
+    /// Whether the peer acknowledged every byte and the FIN.
+    #[must_use]
+    pub fn is_fully_acked(&self) -> bool {
+        self.send.state() == SendState::DataAcked
+    }
+
+    /// Whether every direction this stream uses is closed: the FIN was sent (or the send
+    /// side reset), and/or the peer's FIN arrived and all its data has been read (or
+    /// reading was stopped).
+    fn is_complete(&self, sends: bool, receives: bool) -> bool {
+        (!sends || self.send.is_closed()) && (!receives || self.recv.is_closed())
+    }
+}
+
+/// Limits on received data buffered while the application is not reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvBufferLimits {
+    /// Unread bytes one stream may hold before its credit is withheld.
+    pub max_buffered_per_stream: u64,
+    /// Unread bytes all streams together may hold before all credit is withheld.
+    pub max_buffered_total: u64,
+}
+
+impl Default for RecvBufferLimits {
+    fn default() -> Self {
+        Self {
+            max_buffered_per_stream: DEFAULT_MAX_BUFFERED_PER_STREAM,
+            max_buffered_total: DEFAULT_MAX_BUFFERED_TOTAL,
+        }
+    }
+}
+
+/// Caps on streams open at the same time, counted separately for each initiator.
+///
+/// A stream stops counting once it has completed in every direction it carries, so
+/// finishing streams makes room for new ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamCountLimits {
+    /// Bidirectional streams each side may have open at once.
+    pub max_concurrent_bidi: u64,
+    /// Unidirectional streams each side may have open at once.
+    pub max_concurrent_uni: u64,
+}
+
+impl StreamCountLimits {
+    /// Limit that applies to streams of `kind`.
+    #[must_use]
+    pub const fn for_kind(&self, kind: StreamKind) -> u64 {
+        match kind {
+            StreamKind::Bidirectional => self.max_concurrent_bidi,
+            StreamKind::Unidirectional => self.max_concurrent_uni,
+        }
+    }
+}
+
+impl Default for StreamCountLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrent_bidi: DEFAULT_MAX_CONCURRENT_BIDI_STREAMS,
+            max_concurrent_uni: DEFAULT_MAX_CONCURRENT_UNI_STREAMS,
+        }
+    }
+}
+
+/// Receive-side pressure reported by [`StreamManager::backpressure`], most severe last.
+///
+/// Data arriving within an already advertised window is always accepted; these states only
+/// tell the transport when to stop extending windows and, in the worst case, stop reading
+/// from the socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BackpressureState {
+    /// Every buffer is under its limit; issue credit as usual.
+    Clear,
+    /// Some streams reached the per-stream limit; withhold their credit
+    /// (see [`StreamManager::may_issue_credit`]).
+    StreamLimited,
+    /// The aggregate limit is reached; withhold all credit until the application reads.
+    ConnectionLimited,
+    /// Buffered data reached twice the aggregate limit; stop reading from the socket.
+    Saturated,
 }
 
 /// Manager for all streams owned by an endpoint.
 #[derive(Debug)]
 pub struct StreamManager {
-    _role: EndpointRole,
+    role: EndpointRole,
     streams: HashMap<StreamId, Stream>,
+    /// Priorities announced by `StreamOpen`; other streams are Interactive.
+    priorities: HashMap<StreamId, PriorityClass>,
     flow: FlowController,
+    max_streams: u64,
+    next_bidi_index: u64,
+    next_uni_index: u64,
+    /// Stream most recently served by [`poll_any_send_chunk`](Self::poll_any_send_chunk).
+    last_served: Option<StreamId>,
+    recv_limits: RecvBufferLimits,
+    count_limits: StreamCountLimits,
+    /// `STREAMS_BLOCKED` limit to report per stream kind, and whether it was already polled.
+    streams_blocked: [Option<(u64, bool)>; 2],
+    draining: bool,
 }
 
 impl StreamManager {
@@ -335,20 +1148,91 @@ impl StreamManager {
     #[must_use]
     pub fn new(role: EndpointRole) -> Self {
         Self {
-            _role: role,
+            role,
             streams: HashMap::new(),
+            priorities: HashMap::new(),
             flow: FlowController::new(u64::MAX),
+            max_streams: u64::MAX,
+            next_bidi_index: 0,
+            next_uni_index: 0,
+            last_served: None,
+            recv_limits: RecvBufferLimits::default(),
+            count_limits: StreamCountLimits::default(),
+            streams_blocked: [None; 2],
+            draining: false,
         }
     }
 
-    /// Configure the connection-level send window (`MAX_DATA` from peer).
-    pub fn set_connection_limit(&mut self, limit: u64) {
-        self.flow.update_connection_limit(limit);
+    /// Use `limits` for receive-side backpressure instead of the defaults.
+    #[must_use]
+    pub fn with_recv_buffer_limits(mut self, limits: RecvBufferLimits) -> Self {
+        self.recv_limits = limits;
+        self
     }
 
-    /// Configure a stream-specific send window (per-stream `MAX_DATA` from peer).
-    pub fn set_stream_limit(&mut self, id: StreamId, limit: u64) {
-        self.flow.update_stream_limit(id, limit);
+    /// Receive-side buffering limits in effect.
+    #[must_use]
+    pub const fn recv_buffer_limits(&self) -> RecvBufferLimits {
+        self.recv_limits
+    }
+
+    /// Use `limits` for concurrently open streams instead of the defaults.
+    #[must_use]
+    pub fn with_stream_count_limits(mut self, limits: StreamCountLimits) -> Self {
+        self.count_limits = limits;
+        self
+    }
+
+    /// Concurrent stream limits in effect.
+    #[must_use]
+    pub const fn stream_count_limits(&self) -> StreamCountLimits {
+        self.count_limits
+    }
+
+    /// Streams of `kind` opened by `initiator` that have not yet completed.
+    #[must_use]
+    pub fn open_streams(&self, initiator: EndpointRole, kind: StreamKind) -> u64 {
+        self.streams
+            .iter()
+            .filter(|(id, stream)| {
+                id.role() == initiator
+                    && id.kind() == kind
+                    && !self.is_stream_complete(**id, stream)
+            })
+            .count() as u64
+    }
+
+    /// Take the `STREAMS_BLOCKED` frame owed to the peer, if a local open was refused by
+    /// the concurrent stream limit.
+    ///
+    /// Each kind is reported once per blocked episode; a successful open of that kind
+    /// clears it.
+    pub fn poll_streams_blocked(&mut self) -> Option<Frame> {
+        for kind in [StreamKind::Bidirectional, StreamKind::Unidirectional] {
+            if let Some((limit, reported)) = &mut self.streams_blocked[usize::from(kind.as_u8())] {
+                if !*reported {
+                    *reported = true;
+                    return Some(Frame::streams_blocked(kind, *limit));
+                }
+            }
+        }
+        None
+    }
+
+    /// Apply limits negotiated during the handshake (flow windows and stream count).
+    pub fn apply_transport_parameters(&mut self, params: &TransportParameters) {
+        self.flow.apply_transport_parameters(params);
+        self.max_streams = params.max_streams;
+    }
+
+    /// Configure the connection-level send window (`MAX_DATA` from peer).
+    pub fn set_connection_limit(&mut self, limit: u64) {
+        self.flow.update_connection_limit(limit);
+    }
+
+    /// Configure a stream-specific send window (per-stream `MAX_DATA` from peer).
+    pub fn set_stream_limit(&mut self, id: StreamId, limit: u64) {
+        self.flow.update_stream_limit(id, limit);
     }
 
     /// Compute the remaining bytes that may be sent for the stream respecting connection limits.
@@ -358,6 +1242,10 @@ impl StreamManager {
     }
 
     /// Obtain a mutable reference to a stream, creating it if required.
+    ///
+    /// No limits or stream-id rules are checked; use
+    /// [`try_get_or_create`](Self::try_get_or_create) to open streams on behalf of the
+    /// application.
     pub fn get_or_create(&mut self, id: StreamId) -> &mut Stream {
         if !self.streams.contains_key(&id) {
             Metrics::record_stream_open();
@@ -365,61 +1253,328 @@ impl StreamManager {
         self.streams.entry(id).or_insert_with(|| Stream::new(id))
     }
 
+    /// Like [`get_or_create`](Self::get_or_create), but refuses to exceed the stream limit,
+    /// to create streams while [draining](Self::begin_drain), or to open a stream whose id
+    /// belongs to the peer.
+    pub fn try_get_or_create(&mut self, id: StreamId) -> Result<&mut Stream, StreamError> {
+        if !self.streams.contains_key(&id) {
+            self.check_access(id, StreamAccess::Open)?;
+        }
+        self.admit(id)
+    }
+
+    /// Look up or create a stream opened by either side, subject to the stream limit and
+    /// draining.
+    fn admit(&mut self, id: StreamId) -> Result<&mut Stream, StreamError> {
+        if !self.streams.contains_key(&id) {
+            if self.draining {
+                return Err(StreamError::Draining);
+            }
+            if self.streams.len() as u64 >= self.max_streams {
+                return Err(StreamError::StreamLimitExceeded {
+                    limit: self.max_streams,
+                });
+            }
+            let kind = id.kind();
+            let limit = self.count_limits.for_kind(kind);
+            let slot = usize::from(kind.as_u8());
+            let local = id.is_local_initiated(self.role);
+            if self.open_streams(id.role(), kind) >= limit {
+                if local && self.streams_blocked[slot].is_none() {
+                    debug!(
+                        ?kind,
+                        limit, "local stream open blocked by concurrency limit"
+                    );
+                    self.streams_blocked[slot] = Some((limit, false));
+                }
+                return Err(StreamError::ConcurrencyLimitExceeded { kind, limit });
+            }
+            if local {
+                self.streams_blocked[slot] = None;
+            }
+        }
+        Ok(self.get_or_create(id))
+    }
+
+    /// Stop accepting new streams, local or remote, while existing ones run to completion.
+    ///
+    /// Opening or ingesting data for an unknown stream fails with
+    /// [`StreamError::Draining`]; poll [`is_drained`](Self::is_drained) to learn when the
+    /// connection can be closed without cutting off in-flight exchanges.
+    pub fn begin_drain(&mut self) {
+        if !self.draining {
+            debug!(active = self.active_streams(), "stream manager draining");
+        }
+        self.draining = true;
+    }
+
+    /// Whether [`begin_drain`](Self::begin_drain) has been called.
+    #[must_use]
+    pub const fn is_draining(&self) -> bool {
+        self.draining
+    }
+
+    /// Streams that still have data to send, a FIN outstanding, or unread received data.
+    #[must_use]
+    pub fn active_streams(&self) -> usize {
+        self.streams
+            .iter()
+            .filter(|(id, stream)| !self.is_stream_complete(**id, stream))
+            .count()
+    }
+
+    /// Whether the manager is draining and every stream has completed.
+    #[must_use]
+    pub fn is_drained(&self) -> bool {
+        self.draining && self.active_streams() == 0
+    }
+
+    fn is_stream_complete(&self, id: StreamId, stream: &Stream) -> bool {
+        let local = id.is_local_initiated(self.role);
+        match id.kind() {
+            StreamKind::Bidirectional => stream.is_complete(true, true),
+            StreamKind::Unidirectional => stream.is_complete(local, !local),
+        }
+    }
+
+    /// Create the stream announced by a `StreamOpen` body and record its priority.
+    ///
+    /// Both sides call this: the opener before sending the message, the peer on receipt.
+    pub fn open_with_priority(&mut self, open: &StreamOpen) -> Result<StreamId, StreamError> {
+        let id = StreamId::try_from_raw(open.stream_id()).ok_or(StreamError::InvalidStreamId {
+            id: open.stream_id(),
+        })?;
+        self.admit(id)?;
+        self.priorities.insert(id, open.priority().into());
+        Ok(id)
+    }
+
+    /// Scheduling class of a stream (Interactive unless announced otherwise).
+    #[must_use]
+    pub fn priority(&self, id: StreamId) -> PriorityClass {
+        self.priorities
+            .get(&id)
+            .copied()
+            .unwrap_or(PriorityClass::Interactive)
+    }
+
+    /// Open the next locally initiated bidirectional stream.
+    pub fn open_bidirectional(&mut self) -> Result<StreamId, StreamError> {
+        let id = StreamId::new(self.role, StreamKind::Bidirectional, self.next_bidi_index);
+        self.try_get_or_create(id)?;
+        self.next_bidi_index += 1;
+        Ok(id)
+    }
+
+    /// Open the next locally initiated unidirectional stream.
+    pub fn open_unidirectional(&mut self) -> Result<StreamId, StreamError> {
+        let id = StreamId::new(self.role, StreamKind::Unidirectional, self.next_uni_index);
+        self.try_get_or_create(id)?;
+        self.next_uni_index += 1;
+        Ok(id)
+    }
+
+    /// Open a unidirectional stream that carries many MXP messages back-to-back.
+    pub fn open_message_sink(&mut self) -> Result<MessageSink, StreamError> {
+        self.open_unidirectional().map(MessageSink::new)
+    }
+
+    /// Accept a peer's batched message stream, yielding messages as they decode.
+    pub fn recv_batched(&mut self, id: StreamId) -> Result<BatchedReceiver, StreamError> {
+        self.check_access(id, StreamAccess::Receive)?;
+        self.admit(id)?;
+        Ok(BatchedReceiver::new(id))
+    }
+
+    fn check_access(&self, id: StreamId, access: StreamAccess) -> Result<(), StreamError> {
+        let allowed = match access {
+            StreamAccess::Open => id.is_local_initiated(self.role),
+            StreamAccess::Send => id.can_send(self.role),
+            StreamAccess::Receive => id.can_receive(self.role),
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(StreamError::IllegalAccess {
+                id: id.as_u64(),
+                access,
+            })
+        }
+    }
+
+    /// Existing stream the local endpoint may send on.
+    fn send_stream(&mut self, id: StreamId) -> Result<&mut Stream, StreamError> {
+        self.check_access(id, StreamAccess::Send)?;
+        self.streams.get_mut(&id).ok_or(StreamError::UnknownStream)
+    }
+
     /// Queue application data on a particular stream.
     #[instrument(level = "debug", skip(self, data))]
     pub fn queue_send(&mut self, id: StreamId, data: &[u8]) -> Result<(), StreamError> {
-        self.streams
-            .get_mut(&id)
-            .ok_or(StreamError::UnknownStream)?
-            .queue_send(data)
+        self.send_stream(id)?.queue_send(data)
+    }
+
+    /// Queue an owned buffer on the stream without copying it.
+    #[instrument(level = "debug", skip(self, data))]
+    pub fn queue_send_bytes(&mut self, id: StreamId, data: Bytes) -> Result<(), StreamError> {
+        self.send_stream(id)?.queue_send_bytes(data)
     }
 
     /// Queue a FIN marker on the stream.
     #[instrument(level = "debug", skip(self))]
     pub fn finish(&mut self, id: StreamId) -> Result<(), StreamError> {
-        self.streams
-            .get_mut(&id)
-            .ok_or(StreamError::UnknownStream)?
-            .finish()
+        self.send_stream(id)?.finish()
     }
 
     /// Pull the next send chunk from a stream.
+    ///
+    /// Data declared lost is resent first; it was already charged against flow control, so
+    /// it goes out even when the windows are exhausted.
     pub fn poll_send_chunk(
         &mut self,
         id: StreamId,
         max_len: usize,
     ) -> Result<Option<SendChunk>, FlowControlError> {
-        let allowance = self.flow.stream_available(id);
-        if allowance == 0 {
+        let Some(stream) = self.streams.get_mut(&id) else {
             return Ok(None);
-        }
-        let limit = allowance
-            .min(self.flow.connection_available())
-            .min(max_len as u64) as usize;
+        };
+        let limit = if stream.has_lost_data() {
+            max_len
+        } else {
+            #[allow(clippy::cast_possible_truncation)] // bounded by max_len
+            let limit = self
+                .flow
+                .stream_available(id)
+                .min(self.flow.connection_available())
+                .min(max_len as u64) as usize;
+            limit
+        };
         if limit == 0 {
             return Ok(None);
         }
 
-        let Some(stream) = self.streams.get_mut(&id) else {
-            return Ok(None);
-        };
-
         let chunk = stream.next_send_chunk(limit);
         if let Some(ref chunk) = chunk {
-            if !chunk.payload.is_empty() {
+            if !chunk.retransmission && !chunk.payload.is_empty() {
                 self.flow.consume(id, chunk.payload.len() as u64)?;
             }
             debug!(
                 stream = id.as_u64(),
+                offset = chunk.offset,
                 len = chunk.payload.len(),
                 fin = chunk.fin,
+                retransmission = chunk.retransmission,
                 "emit stream chunk"
             );
         }
         Ok(chunk)
     }
 
+    /// Record that `chunk` of stream `id` went out in packet `packet_number`.
+    pub fn on_chunk_sent(
+        &mut self,
+        id: StreamId,
+        chunk: &SendChunk,
+        packet_number: u64,
+    ) -> Result<(), StreamError> {
+        self.streams
+            .get_mut(&id)
+            .ok_or(StreamError::UnknownStream)?
+            .on_chunk_sent(chunk, packet_number);
+        Ok(())
+    }
+
+    /// Acknowledge the stream chunks every stream sent in packet `packet_number`.
+    pub fn on_packet_acked(&mut self, packet_number: u64) {
+        for stream in self.streams.values_mut() {
+            stream.on_packet_acked(packet_number);
+        }
+    }
+
+    /// Queue the stream chunks sent in packet `packet_number` for retransmission.
+    pub fn on_packet_lost(&mut self, packet_number: u64) {
+        for stream in self.streams.values_mut() {
+            stream.on_packet_lost(packet_number);
+        }
+    }
+
+    /// Pull the next send chunk from any stream, rotating fairly across streams with data.
+    ///
+    /// Streams are visited in ID order starting after the one served last; a stream blocked
+    /// by its flow-control window is skipped rather than ending the search.
+    pub fn poll_any_send_chunk(
+        &mut self,
+        max_len: usize,
+    ) -> Result<Option<(StreamId, SendChunk)>, FlowControlError> {
+        let mut pending = self.pending_streams();
+        let start = self
+            .last_served
+            .map_or(0, |last| pending.partition_point(|id| *id <= last));
+        pending.rotate_left(start);
+
+        for id in pending {
+            if let Some(chunk) = self.poll_send_chunk(id, max_len)? {
+                self.last_served = Some(id);
+                return Ok(Some((id, chunk)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Check whether any stream has data or a FIN waiting to be sent.
+    #[must_use]
+    pub fn has_pending_data(&self) -> bool {
+        self.streams.values().any(Stream::has_pending_data)
+    }
+
+    /// Streams with pending data that flow control currently allows to send, or with lost
+    /// data to resend, in ID order.
+    #[must_use]
+    pub fn ready_streams(&self) -> Vec<StreamId> {
+        let connection_open = self.flow.connection_available() > 0;
+        let mut ready = self.pending_streams();
+        ready.retain(|id| {
+            self.streams[id].has_lost_data()
+                || (connection_open && self.flow.stream_available(*id) > 0)
+        });
+        ready
+    }
+
+    /// Streams with received data waiting to be read, highest priority first.
+    ///
+    /// Receivers should deliver data and return flow-control credit in this order so a bulk
+    /// transfer cannot hold back a latency-critical stream.
+    #[must_use]
+    pub fn readable_streams(&self) -> Vec<StreamId> {
+        let mut readable: Vec<StreamId> = self
+            .streams
+            .iter()
+            .filter(|(_, stream)| stream.has_readable_data())
+            .map(|(id, _)| *id)
+            .collect();
+        readable.sort_unstable_by_key(|id| (self.priority(*id).index(), *id));
+        readable
+    }
+
+    fn pending_streams(&self) -> Vec<StreamId> {
+        let mut pending: Vec<StreamId> = self
+            .streams
+            .iter()
+            .filter(|(_, stream)| stream.has_pending_data())
+            .map(|(id, _)| *id)
+            .collect();
+        pending.sort_unstable();
+        pending
+    }
+
     /// Ingest remote data for the specified stream.
+    ///
+    /// Data for a unidirectional stream the local endpoint opened is refused with
+    /// [`StreamError::IllegalAccess`].
+    ///
+    /// Buffering limits never reject data here: the peer was promised its window, so limits
+    /// act only through [`backpressure`](Self::backpressure) and
+    /// [`may_issue_credit`](Self::may_issue_credit).
     pub fn ingest(
         &mut self,
         id: StreamId,
@@ -428,7 +1583,53 @@ impl StreamManager {
         fin: bool,
     ) -> Result<(), StreamError> {
         trace!(stream = id.as_u64(), offset, fin, "ingesting stream data");
-        self.get_or_create(id).ingest(offset, data, fin)
+        self.check_access(id, StreamAccess::Receive)?;
+        self.admit(id)?.ingest(offset, data, fin)
+    }
+
+    /// Unread bytes buffered across all streams.
+    #[must_use]
+    pub fn buffered_bytes(&self) -> u64 {
+        self.streams.values().map(Stream::buffered_bytes).sum()
+    }
+
+    /// Unread bytes buffered for one stream.
+    pub fn stream_buffered_bytes(&self, id: StreamId) -> Result<u64, StreamError> {
+        self.streams
+            .get(&id)
+            .ok_or(StreamError::UnknownStream)
+            .map(Stream::buffered_bytes)
+    }
+
+    /// Current receive-side pressure against the configured [`RecvBufferLimits`].
+    #[must_use]
+    pub fn backpressure(&self) -> BackpressureState {
+        let total = self.buffered_bytes();
+        let limits = self.recv_limits;
+        if total >= limits.max_buffered_total.saturating_mul(2) {
+            BackpressureState::Saturated
+        } else if total >= limits.max_buffered_total {
+            BackpressureState::ConnectionLimited
+        } else if self
+            .streams
+            .values()
+            .any(|stream| stream.buffered_bytes() >= limits.max_buffered_per_stream)
+        {
+            BackpressureState::StreamLimited
+        } else {
+            BackpressureState::Clear
+        }
+    }
+
+    /// Whether the transport may extend the peer's send window for `id`.
+    ///
+    /// False while the stream or the connection is at its buffering limit; credit resumes
+    /// once the application reads enough to drop back below it.
+    #[must_use]
+    pub fn may_issue_credit(&self, id: StreamId) -> bool {
+        let stream_buffered = self.streams.get(&id).map_or(0, Stream::buffered_bytes);
+        stream_buffered < self.recv_limits.max_buffered_per_stream
+            && self.buffered_bytes() < self.recv_limits.max_buffered_total
     }
 
     /// Read fully contiguous data from the receive buffer.
@@ -440,6 +1641,22 @@ impl StreamManager {
             .map(|stream| stream.read(max_len))
     }
 
+    /// Read contiguous data from the stream into a caller-provided buffer.
+    pub fn read_into(&mut self, id: StreamId, out: &mut [u8]) -> Result<usize, StreamError> {
+        self.streams
+            .get_mut(&id)
+            .ok_or(StreamError::UnknownStream)
+            .map(|stream| stream.read_into(out))
+    }
+
+    /// Read contiguous data from the stream as a view of the received chunk.
+    pub fn read_bytes(&mut self, id: StreamId, max_len: usize) -> Result<Bytes, StreamError> {
+        self.streams
+            .get_mut(&id)
+            .ok_or(StreamError::UnknownStream)
+            .map(|stream| stream.read_bytes(max_len))
+    }
+
     /// Check whether the stream send side is fully drained.
     pub fn is_send_drained(&self, id: StreamId) -> Result<bool, StreamError> {
         self.streams
@@ -455,6 +1672,96 @@ impl StreamManager {
             .ok_or(StreamError::UnknownStream)
             .map(Stream::is_receive_finished)
     }
+
+    /// Record that a packet carrying the chunk at `offset..offset + len` (and the FIN, if
+    /// `fin`) was acknowledged.
+    ///
+    /// The transport calls this for each stream chunk in an acknowledged packet; the send
+    /// buffer releases bytes once every earlier byte is acknowledged as well.
+    pub fn on_chunk_acked(
+        &mut self,
+        id: StreamId,
+        offset: u64,
+        len: u64,
+        fin: bool,
+    ) -> Result<(), StreamError> {
+        self.streams
+            .get_mut(&id)
+            .ok_or(StreamError::UnknownStream)?
+            .on_chunk_acked(offset, len, fin);
+        Ok(())
+    }
+
+    /// Progress of a stream's send side.
+    pub fn send_state(&self, id: StreamId) -> Result<SendState, StreamError> {
+        self.streams
+            .get(&id)
+            .ok_or(StreamError::UnknownStream)
+            .map(Stream::send_state)
+    }
+
+    /// Check whether the peer acknowledged all of the stream's data and its FIN, so the
+    /// application may release resources tied to it.
+    pub fn is_fully_acked(&self, id: StreamId) -> Result<bool, StreamError> {
+        self.streams
+            .get(&id)
+            .ok_or(StreamError::UnknownStream)
+            .map(Stream::is_fully_acked)
+    }
+
+    /// Stop reading `id`: discard buffered and future data, and return the
+    /// `STOP_SENDING` frame asking the peer to reset its send side with `code`.
+    pub fn stop_sending(&mut self, id: StreamId, code: u64) -> Result<Frame, StreamError> {
+        self.streams
+            .get_mut(&id)
+            .ok_or(StreamError::UnknownStream)?
+            .recv
+            .stop(code);
+        debug!(stream = id.as_u64(), code, "stop sending");
+        Ok(Frame::stop_sending(id, code))
+    }
+
+    /// Abandon un-acknowledged and unsent bytes below `before` on a stream carrying data
+    /// that goes stale, such as real-time telemetry.
+    ///
+    /// Expired bytes are never sent or retransmitted again. Returns the `STREAM_EXPIRED`
+    /// frame telling the peer to skip to the new offset, or `None` if everything below
+    /// `before` was already acknowledged or expired. `before` is capped at the end of the
+    /// queued data.
+    pub fn expire_send(&mut self, id: StreamId, before: u64) -> Result<Option<Frame>, StreamError> {
+        let Some(offset) = self.send_stream(id)?.send.expire(before) else {
+            return Ok(None);
+        };
+        debug!(stream = id.as_u64(), offset, "expired send data");
+        Ok(Some(Frame::stream_expired(id, offset)))
+    }
+
+    /// Handle a peer's `STREAM_EXPIRED`: skip the receive side to `offset`, treating the
+    /// gap as delivered.
+    ///
+    /// Unread bytes below `offset` are discarded along with the gap; reads resume with the
+    /// first byte at or after it.
+    pub fn on_stream_expired(&mut self, id: StreamId, offset: u64) -> Result<(), StreamError> {
+        self.check_access(id, StreamAccess::Receive)?;
+        self.admit(id)?.recv.expire(offset)?;
+        debug!(stream = id.as_u64(), offset, "peer expired stream data");
+        Ok(())
+    }
+
+    /// Handle a peer's `STOP_SENDING`: reset the send side, dropping unsent and
+    /// unacknowledged data so no further chunks are emitted.
+    ///
+    /// Later writes fail with [`StreamError::SendStopped`]. A stream whose data was already
+    /// fully acknowledged is left alone.
+    pub fn on_stop_sending(&mut self, id: StreamId, code: u64) -> Result<(), StreamError> {
+        self.streams
+            .get_mut(&id)
+            .ok_or(StreamError::UnknownStream)?
+            .send
+            .reset(code);
+        debug!(stream = id.as_u64(), code, "peer stopped stream");
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -473,6 +1780,41 @@ mod tests {
         let raw = id.as_u64();
         let parsed = StreamId::from_raw(raw);
         assert_eq!(parsed, id);
+
+        assert_eq!(
+            StreamId::try_from_raw(MAX_VARINT),
+            Some(StreamId::from_raw(MAX_VARINT))
+        );
+        assert_eq!(StreamId::try_from_raw(MAX_VARINT + 1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "stream id exceeds the varint range")]
+    fn oversized_raw_stream_id_is_rejected() {
+        let _ = StreamId::from_raw(MAX_VARINT + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "stream index exceeds the varint range")]
+    fn oversized_stream_index_is_rejected() {
+        let _ = StreamId::new(
+            EndpointRole::Client,
+            StreamKind::Bidirectional,
+            (MAX_VARINT >> 2) + 1,
+        );
+    }
+
+    #[test]
+    fn send_offset_stops_at_the_varint_limit() {
+        let mut stream = Stream::new(StreamId::from_raw(0));
+        stream.send.buffer = ChunkQueue::starting_at(MAX_VARINT - 4);
+        stream.send.next_offset = MAX_VARINT - 4;
+
+        stream.queue_send(b"abcd").unwrap();
+        assert_eq!(stream.queue_send(b"e"), Err(StreamError::OffsetOverflow));
+        let chunk = stream.next_send_chunk(16).expect("chunk");
+        assert_eq!(chunk.offset, MAX_VARINT - 4);
+        assert_eq!(chunk.payload, b"abcd");
     }
 
     #[test]
@@ -507,6 +1849,39 @@ mod tests {
         assert!(stream.is_receive_finished());
     }
 
+    #[test]
+    fn recv_buffer_merges_overlapping_retransmits() {
+        let mut stream = Stream::new(StreamId::from_raw(0));
+        stream.ingest(6, b"world", true).expect("ingest tail");
+        stream
+            .ingest(3, b"lo wo", false)
+            .expect("ingest overlapping middle");
+        stream
+            .ingest(0, b"hello ", false)
+            .expect("ingest overlapping head");
+        assert_eq!(stream.read(4), b"hell");
+
+        // A retransmit spanning already-read and buffered data is accepted and trimmed.
+        stream
+            .ingest(0, b"hello world", true)
+            .expect("ingest full retransmit");
+        assert_eq!(stream.read(usize::MAX), b"o world");
+        assert!(stream.is_receive_finished());
+    }
+
+    #[test]
+    fn recv_buffer_rejects_conflicting_overlap() {
+        let mut stream = Stream::new(StreamId::from_raw(0));
+        stream.ingest(4, b"abcd", false).expect("ingest");
+        assert_eq!(
+            stream.ingest(2, b"xxabXd", false),
+            Err(StreamError::ConflictingData { offset: 6 })
+        );
+        // The rejected chunk left the buffer untouched.
+        stream.ingest(0, b"0123", false).expect("ingest head");
+        assert_eq!(stream.read(usize::MAX), b"0123abcd");
+    }
+
     #[test]
     fn manager_queues_and_reads_streams() {
         let mut manager = StreamManager::new(EndpointRole::Client);
@@ -526,6 +1901,483 @@ mod tests {
         assert_eq!(read, b"xyz");
     }
 
+    #[test]
+    fn chunked_buffers_span_chunk_boundaries() {
+        let mut manager = StreamManager::new(EndpointRole::Client);
+        let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        manager.get_or_create(id);
+        manager.queue_send(id, b"abc").unwrap();
+        manager
+            .queue_send_bytes(id, Bytes::from_static(b""))
+            .unwrap();
+        manager
+            .queue_send_bytes(id, Bytes::from_static(b"defgh"))
+            .unwrap();
+        manager.queue_send(id, b"ij").unwrap();
+
+        let first = manager.poll_send_chunk(id, 4).unwrap().unwrap();
+        let second = manager.poll_send_chunk(id, 16).unwrap().unwrap();
+        assert_eq!(first.payload, b"abcd");
+        assert_eq!(
+            (second.offset, second.payload.as_slice()),
+            (4, &b"efghij"[..])
+        );
+
+        // Acknowledging part of a chunk keeps its tail for retransmission.
+        manager.on_chunk_acked(id, 0, 4, false).unwrap();
+        assert_eq!(manager.streams[&id].send.buffer.len(), 6);
+
+        manager.ingest(id, 0, b"abc", false).expect("ingest");
+        manager.ingest(id, 3, b"def", false).expect("ingest");
+        manager.ingest(id, 6, b"ghij", false).expect("ingest");
+
+        // Views never span received chunks and never copy.
+        assert_eq!(manager.read_bytes(id, 2).unwrap(), &b"ab"[..]);
+        assert_eq!(manager.read_bytes(id, 16).unwrap(), &b"c"[..]);
+        assert_eq!(manager.read(id, 5).unwrap(), b"defgh");
+        assert_eq!(manager.read_bytes(id, 16).unwrap(), &b"ij"[..]);
+        assert!(manager.read_bytes(id, 16).unwrap().is_empty());
+        assert_eq!(
+            manager.read_bytes(StreamId::from_raw(99), 1),
+            Err(StreamError::UnknownStream)
+        );
+    }
+
+    #[test]
+    fn zero_copy_reads_return_the_ingested_bytes() {
+        let mut manager = StreamManager::new(EndpointRole::Server);
+        let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        let data: Vec<u8> = (0..=255).cycle().take(5_000).collect();
+        // Out of order, overlapping and in uneven pieces.
+        for (start, end) in [(1_200, 3_000), (0, 1_500), (4_000, 5_000), (2_900, 4_100)] {
+            manager
+                .ingest(id, start as u64, &data[start..end], false)
+                .unwrap();
+        }
+
+        let mut read = Vec::new();
+        loop {
+            let bytes = manager.read_bytes(id, 700).unwrap();
+            if bytes.is_empty() {
+                break;
+            }
+            assert!(bytes.len() <= 700);
+            read.extend_from_slice(&bytes);
+            let mut scratch = [0u8; 333];
+            let len = manager.read_into(id, &mut scratch).unwrap();
+            read.extend_from_slice(&scratch[..len]);
+        }
+        assert_eq!(read, data);
+        assert_eq!(manager.read_into(id, &mut [0u8; 8]), Ok(0));
+        assert_eq!(
+            manager.read_into(StreamId::from_raw(99), &mut []),
+            Err(StreamError::UnknownStream)
+        );
+    }
+
+    #[test]
+    fn fully_acked_only_after_every_chunk_and_fin() {
+        let mut manager = StreamManager::new(EndpointRole::Client);
+        let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        manager.get_or_create(id);
+        manager.queue_send(id, b"0123456789").unwrap();
+        manager.finish(id).unwrap();
+
+        let mut chunks = Vec::new();
+        while let Some(chunk) = manager.poll_send_chunk(id, 4).unwrap() {
+            chunks.push(chunk);
+            if chunks.len() < 3 {
+                assert_eq!(manager.send_state(id), Ok(SendState::Sending));
+            }
+        }
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks[2].fin);
+        assert_eq!(manager.send_state(id), Ok(SendState::Sent));
+
+        // Out-of-order and duplicate ACKs: the first chunk is still missing.
+        let ack = |manager: &mut StreamManager, chunk: &SendChunk| {
+            manager
+                .on_chunk_acked(id, chunk.offset, chunk.payload.len() as u64, chunk.fin)
+                .unwrap();
+        };
+        ack(&mut manager, &chunks[1]);
+        ack(&mut manager, &chunks[2]);
+        ack(&mut manager, &chunks[1]);
+        assert_eq!(manager.is_fully_acked(id), Ok(false));
+        assert_eq!(manager.send_state(id), Ok(SendState::Sent));
+        // Unacknowledged bytes are retained until the gap closes.
+        assert_eq!(manager.streams[&id].send.buffer.len(), 10);
+
+        ack(&mut manager, &chunks[0]);
+        assert_eq!(manager.is_fully_acked(id), Ok(true));
+        assert_eq!(manager.send_state(id), Ok(SendState::DataAcked));
+        assert!(manager.streams[&id].send.buffer.is_empty());
+        assert_eq!(
+            manager.is_fully_acked(StreamId::from_raw(99)),
+            Err(StreamError::UnknownStream)
+        );
+
+        // Data acknowledged without the FIN is not enough.
+        let other = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        manager.get_or_create(other);
+        manager.queue_send(other, b"abc").unwrap();
+        manager.finish(other).unwrap();
+        let chunk = manager.poll_send_chunk(other, 16).unwrap().unwrap();
+        manager.on_chunk_acked(other, 0, 3, false).unwrap();
+        assert_eq!(manager.is_fully_acked(other), Ok(false));
+        manager
+            .on_chunk_acked(other, chunk.offset, 3, true)
+            .unwrap();
+        assert_eq!(manager.is_fully_acked(other), Ok(true));
+    }
+
+    #[test]
+    fn lost_chunks_are_resent_and_acked_ones_are_not() {
+        let mut manager = StreamManager::new(EndpointRole::Client);
+        let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        manager.get_or_create(id);
+        let data: Vec<u8> = (0..30).collect();
+        manager.queue_send(id, &data).unwrap();
+        manager.finish(id).unwrap();
+
+        // One chunk per packet: 0..10 in 1, 10..20 in 2, 20..30 + FIN in 3.
+        for packet_number in 1..=3 {
+            let chunk = manager.poll_send_chunk(id, 10).unwrap().unwrap();
+            assert!(!chunk.retransmission);
+            manager.on_chunk_sent(id, &chunk, packet_number).unwrap();
+        }
+        assert!(manager.poll_send_chunk(id, 10).unwrap().is_none());
+
+        manager.on_packet_acked(1);
+        manager.on_packet_lost(2);
+        manager.on_packet_acked(3);
+        assert_eq!(manager.send_state(id), Ok(SendState::Sending));
+
+        // Exactly the lost range comes back, split to fit, and nothing else.
+        let first = manager.poll_send_chunk(id, 6).unwrap().unwrap();
+        assert_eq!(
+            (first.offset, first.payload.as_slice()),
+            (10, &data[10..16])
+        );
+        assert!(first.retransmission && !first.fin);
+        manager.on_chunk_sent(id, &first, 4).unwrap();
+        let second = manager.poll_send_chunk(id, 6).unwrap().unwrap();
+        assert_eq!(
+            (second.offset, second.payload.as_slice()),
+            (16, &data[16..20])
+        );
+        manager.on_chunk_sent(id, &second, 5).unwrap();
+        assert!(manager.poll_send_chunk(id, 10).unwrap().is_none());
+
+        // A lost FIN is resent on its own once the data is covered.
+        manager.on_packet_acked(4);
+        manager.on_packet_acked(5);
+        assert_eq!(manager.is_fully_acked(id), Ok(true));
+
+        let other = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        manager.get_or_create(other);
+        manager.queue_send(other, b"abcd").unwrap();
+        manager.finish(other).unwrap();
+        let chunk = manager.poll_send_chunk(other, 16).unwrap().unwrap();
+        assert!(chunk.fin);
+        manager.on_chunk_sent(other, &chunk, 6).unwrap();
+        // The bytes arrive through another path before the loss is declared.
+        manager.on_chunk_acked(other, 0, 4, false).unwrap();
+        manager.on_packet_lost(6);
+        let fin = manager.poll_send_chunk(other, 16).unwrap().unwrap();
+        assert_eq!((fin.offset, fin.payload.len(), fin.fin), (4, 0, true));
+        assert!(fin.retransmission);
+        manager.on_chunk_sent(other, &fin, 7).unwrap();
+        manager.on_packet_acked(7);
+        assert_eq!(manager.is_fully_acked(other), Ok(true));
+    }
+
+    #[test]
+    fn retransmissions_bypass_exhausted_flow_windows() {
+        let mut manager = StreamManager::new(EndpointRole::Client);
+        let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        manager.get_or_create(id);
+        manager.set_connection_limit(8);
+        manager.set_stream_limit(id, 8);
+        manager.queue_send(id, b"0123456789").unwrap();
+
+        let chunk = manager.poll_send_chunk(id, 16).unwrap().unwrap();
+        assert_eq!(chunk.payload, b"01234567");
+        manager.on_chunk_sent(id, &chunk, 1).unwrap();
+        assert!(manager.ready_streams().is_empty());
+
+        manager.on_packet_lost(1);
+        assert_eq!(manager.ready_streams(), vec![id]);
+        let resent = manager.poll_send_chunk(id, 16).unwrap().unwrap();
+        assert_eq!(resent.payload, b"01234567");
+        assert!(resent.retransmission);
+        // The resend consumed no credit and the window is still closed to new data.
+        assert_eq!(manager.stream_send_allowance(id), 0);
+        assert!(manager.poll_send_chunk(id, 16).unwrap().is_none());
+    }
+
+    #[test]
+    fn stream_ids_enforce_initiator_and_direction() {
+        let mut server = StreamManager::new(EndpointRole::Server);
+        let peer_bidi = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        let peer_uni = StreamId::new(EndpointRole::Client, StreamKind::Unidirectional, 0);
+        let local_uni = StreamId::new(EndpointRole::Server, StreamKind::Unidirectional, 0);
+        let illegal = |id: StreamId, access| StreamError::IllegalAccess {
+            id: id.as_u64(),
+            access,
+        };
+
+        // Peer-initiated ids cannot be opened locally, but arrive through ingest.
+        assert_eq!(
+            server.try_get_or_create(peer_bidi).err(),
+            Some(illegal(peer_bidi, StreamAccess::Open))
+        );
+        assert_eq!(
+            server.try_get_or_create(peer_uni).err(),
+            Some(illegal(peer_uni, StreamAccess::Open))
+        );
+        assert_eq!(server.active_streams(), 0);
+        server.ingest(peer_bidi, 0, b"ping", false).unwrap();
+        server.queue_send(peer_bidi, b"pong").unwrap();
+        assert!(server.try_get_or_create(peer_bidi).is_ok());
+
+        // A peer's unidirectional stream is receive-only here.
+        server.ingest(peer_uni, 0, b"one way", true).unwrap();
+        assert_eq!(
+            server.queue_send(peer_uni, b"back"),
+            Err(illegal(peer_uni, StreamAccess::Send))
+        );
+        assert_eq!(
+            server.queue_send_bytes(peer_uni, Bytes::from_static(b"back")),
+            Err(illegal(peer_uni, StreamAccess::Send))
+        );
+        assert_eq!(
+            server.finish(peer_uni),
+            Err(illegal(peer_uni, StreamAccess::Send))
+        );
+
+        // Our own unidirectional stream is send-only.
+        assert_eq!(server.open_unidirectional(), Ok(local_uni));
+        server.queue_send(local_uni, b"out").unwrap();
+        assert_eq!(
+            server.ingest(local_uni, 0, b"in", false),
+            Err(illegal(local_uni, StreamAccess::Receive))
+        );
+        assert!(matches!(
+            server.recv_batched(local_uni),
+            Err(StreamError::IllegalAccess {
+                access: StreamAccess::Receive,
+                ..
+            })
+        ));
+        assert_eq!(
+            illegal(local_uni, StreamAccess::Receive).to_string(),
+            "local endpoint may not receive on stream 3"
+        );
+    }
+
+    #[test]
+    fn stop_sending_resets_peer_send_side() {
+        let mut client = StreamManager::new(EndpointRole::Client);
+        let mut server = StreamManager::new(EndpointRole::Server);
+        let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        client.get_or_create(id);
+        client.queue_send(id, &[7u8; 64]).unwrap();
+
+        let chunk = client.poll_send_chunk(id, 16).unwrap().unwrap();
+        server
+            .ingest(id, chunk.offset, &chunk.payload, false)
+            .unwrap();
+        assert_eq!(server.stream_buffered_bytes(id), Ok(16));
+
+        let frame = server.stop_sending(id, 0x2a).unwrap();
+        assert_eq!(server.stream_buffered_bytes(id), Ok(0));
+        let (stopped, code) = frame.decode_stop_sending().unwrap();
+        client.on_stop_sending(stopped, code).unwrap();
+
+        assert_eq!(client.send_state(id), Ok(SendState::Reset { code: 0x2a }));
+        assert!(client.poll_send_chunk(id, 16).unwrap().is_none());
+        assert!(!client.has_pending_data());
+        assert_eq!(
+            client.queue_send(id, b"more"),
+            Err(StreamError::SendStopped { code: 0x2a })
+        );
+        assert_eq!(
+            client.finish(id),
+            Err(StreamError::SendStopped { code: 0x2a })
+        );
+
+        // Chunks already in flight when the server stopped are discarded on arrival.
+        server.ingest(id, 16, &[7u8; 16], false).unwrap();
+        assert_eq!(server.stream_buffered_bytes(id), Ok(0));
+        assert!(server.read(id, 64).unwrap().is_empty());
+    }
+
+    #[test]
+    fn manager_applies_transport_parameters() {
+        let mut manager = StreamManager::new(EndpointRole::Server);
+        manager.apply_transport_parameters(&TransportParameters {
+            initial_max_data: 64,
+            initial_max_stream_data: 4,
+            max_streams: 1,
+            ..TransportParameters::default()
+        });
+
+        let first = StreamId::new(EndpointRole::Server, StreamKind::Bidirectional, 0);
+        let second = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        manager.try_get_or_create(first).expect("within limit");
+        assert_eq!(manager.stream_send_allowance(first), 4);
+        assert_eq!(
+            manager.ingest(second, 0, b"x", false),
+            Err(StreamError::StreamLimitExceeded { limit: 1 })
+        );
+    }
+
+    #[test]
+    fn concurrent_stream_limits_block_and_relax() {
+        let limits = StreamCountLimits {
+            max_concurrent_bidi: 2,
+            max_concurrent_uni: 1,
+        };
+        let mut client = StreamManager::new(EndpointRole::Client).with_stream_count_limits(limits);
+        let bidi = |index| StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, index);
+
+        client.try_get_or_create(bidi(0)).unwrap();
+        client.try_get_or_create(bidi(1)).unwrap();
+        assert_eq!(
+            client.try_get_or_create(bidi(2)).err(),
+            Some(StreamError::ConcurrencyLimitExceeded {
+                kind: StreamKind::Bidirectional,
+                limit: 2,
+            })
+        );
+        // The refusal is reported to the peer once, however often the open is retried.
+        assert!(client.try_get_or_create(bidi(2)).is_err());
+        let blocked = client.poll_streams_blocked().expect("STREAMS_BLOCKED");
+        assert_eq!(
+            blocked.decode_streams_blocked().unwrap(),
+            (StreamKind::Bidirectional, 2)
+        );
+        assert!(client.poll_streams_blocked().is_none());
+
+        // Kinds and initiators are counted separately.
+        let uni = client.open_unidirectional().unwrap();
+        assert!(client.open_unidirectional().is_err());
+        let peer = StreamId::new(EndpointRole::Server, StreamKind::Bidirectional, 0);
+        client.ingest(peer, 0, b"hi", false).unwrap();
+        assert_eq!(
+            client.open_streams(EndpointRole::Client, StreamKind::Bidirectional),
+            2
+        );
+        assert_eq!(
+            client.open_streams(EndpointRole::Server, StreamKind::Bidirectional),
+            1
+        );
+
+        // Completing a stream in both directions frees its slot.
+        client.finish(bidi(0)).unwrap();
+        let fin = client.poll_send_chunk(bidi(0), 64).unwrap().unwrap();
+        client.on_chunk_acked(bidi(0), fin.offset, 0, true).unwrap();
+        assert!(client.try_get_or_create(bidi(2)).is_err());
+        client.ingest(bidi(0), 0, b"", true).unwrap();
+        client.try_get_or_create(bidi(2)).unwrap();
+
+        // Only the unidirectional refusal is still owed.
+        let blocked = client.poll_streams_blocked().expect("uni STREAMS_BLOCKED");
+        assert_eq!(
+            blocked.decode_streams_blocked().unwrap(),
+            (StreamKind::Unidirectional, 1)
+        );
+        client.finish(uni).unwrap();
+        let fin = client.poll_send_chunk(uni, 64).unwrap().unwrap();
+        client.on_chunk_acked(uni, fin.offset, 0, true).unwrap();
+        client.open_unidirectional().unwrap();
+    }
+
+    #[test]
+    fn peer_streams_beyond_limit_are_refused() {
+        let limits = StreamCountLimits {
+            max_concurrent_bidi: 1,
+            ..StreamCountLimits::default()
+        };
+        let mut server = StreamManager::new(EndpointRole::Server).with_stream_count_limits(limits);
+        let first = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        let second = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        server.ingest(first, 0, b"a", false).unwrap();
+        assert_eq!(
+            server.ingest(second, 0, b"b", false),
+            Err(StreamError::ConcurrencyLimitExceeded {
+                kind: StreamKind::Bidirectional,
+                limit: 1,
+            })
+        );
+        // Refusing a peer's stream is not something to report as blocked.
+        assert!(server.poll_streams_blocked().is_none());
+    }
+
+    #[test]
+    fn expired_send_data_is_not_retransmitted() {
+        let mut client = StreamManager::new(EndpointRole::Client);
+        let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        client.try_get_or_create(id).unwrap();
+        let data: Vec<u8> = (0..=255).collect();
+        client.queue_send(id, &data).unwrap();
+        for packet in 1..=2 {
+            let chunk = client.poll_send_chunk(id, 100).unwrap().unwrap();
+            client.on_chunk_sent(id, &chunk, packet).unwrap();
+        }
+        client.on_packet_lost(1);
+
+        let frame = client.expire_send(id, 150).unwrap().expect("frame");
+        assert_eq!(frame.decode_stream_expired().unwrap(), (id, 150));
+        assert!(client.expire_send(id, 120).unwrap().is_none());
+
+        // Packet 1's range now lies wholly below the expiry offset; packet 2 is resent
+        // only from 150.
+        client.on_packet_lost(2);
+        let resent = client.poll_send_chunk(id, 1_000).unwrap().unwrap();
+        assert!(resent.retransmission);
+        assert_eq!(resent.offset, 150);
+        assert_eq!(resent.payload, data[150..200]);
+        let fresh = client.poll_send_chunk(id, 1_000).unwrap().unwrap();
+        assert_eq!((fresh.offset, fresh.payload.len()), (200, 56));
+
+        // Expiring past everything queued skips unsent data too and is capped at its end.
+        client.queue_send(id, &[1; 10]).unwrap();
+        let frame = client.expire_send(id, u64::MAX).unwrap().expect("frame");
+        assert_eq!(frame.decode_stream_expired().unwrap(), (id, 266));
+        client.finish(id).unwrap();
+        let fin = client.poll_send_chunk(id, 1_000).unwrap().unwrap();
+        assert_eq!((fin.offset, fin.payload.len(), fin.fin), (266, 0, true));
+    }
+
+    #[test]
+    fn receiver_treats_expired_gap_as_delivered() {
+        let mut server = StreamManager::new(EndpointRole::Server);
+        let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        server.ingest(id, 0, b"abcd", false).unwrap();
+        server.ingest(id, 12, b"mnop", false).unwrap();
+        server.ingest(id, 20, b"uv", false).unwrap();
+
+        // Unread bytes and the gap below 14 are skipped; the pending range is trimmed.
+        server.on_stream_expired(id, 14).unwrap();
+        assert_eq!(server.read(id, 64).unwrap(), b"op");
+        assert_eq!(server.stream_buffered_bytes(id), Ok(2));
+        server.ingest(id, 16, b"qrst", true).unwrap();
+        assert_eq!(server.read(id, 64).unwrap(), b"qrstuv");
+        assert_eq!(server.is_receive_finished(id), Ok(true));
+
+        // Stale offsets are ignored and skipping past the final size is an error.
+        let other = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        server.ingest(other, 0, b"xy", true).unwrap();
+        server.on_stream_expired(other, 1).unwrap();
+        assert_eq!(
+            server.on_stream_expired(other, 3),
+            Err(StreamError::DataBeyondFinalOffset)
+        );
+        assert_eq!(server.read(other, 64).unwrap(), b"y");
+    }
+
     #[test]
     fn manager_respects_flow_limits() {
         let mut manager = StreamManager::new(EndpointRole::Client);
@@ -543,4 +2395,223 @@ mod tests {
         assert_eq!(manager.stream_send_allowance(stream_id), 0);
         assert!(manager.poll_send_chunk(stream_id, 10).unwrap().is_none());
     }
+
+    #[test]
+    fn buffered_bytes_cover_ready_and_out_of_order_data() {
+        let mut manager = StreamManager::new(EndpointRole::Server);
+        let first = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        let second = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+
+        manager.ingest(first, 0, b"0123", false).unwrap();
+        manager.ingest(first, 8, b"89", false).unwrap();
+        manager.ingest(first, 6, b"6789", false).unwrap();
+        manager.ingest(second, 0, b"abc", false).unwrap();
+        assert_eq!(manager.stream_buffered_bytes(first), Ok(8));
+        assert_eq!(manager.buffered_bytes(), 11);
+
+        // Filling the gap moves pending bytes to ready without changing the total.
+        manager.ingest(first, 4, b"45", false).unwrap();
+        assert_eq!(manager.stream_buffered_bytes(first), Ok(10));
+        manager.read(first, 7).unwrap();
+        assert_eq!(manager.stream_buffered_bytes(first), Ok(3));
+        assert_eq!(manager.buffered_bytes(), 6);
+        assert_eq!(
+            manager.stream_buffered_bytes(StreamId::from_raw(99)),
+            Err(StreamError::UnknownStream)
+        );
+    }
+
+    #[test]
+    fn slow_reader_plateaus_buffered_bytes() {
+        const CHUNK: usize = 512;
+        const CREDIT: u64 = 1024;
+        let limits = RecvBufferLimits {
+            max_buffered_per_stream: 4096,
+            max_buffered_total: 1 << 20,
+        };
+        let mut manager = StreamManager::new(EndpointRole::Server).with_recv_buffer_limits(limits);
+        let id = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+
+        // The peer sends whenever it has window; the reader drains far slower.
+        let (mut sent, mut window) = (0u64, 2048u64);
+        let mut history = Vec::new();
+        for _ in 0..400 {
+            while sent + CHUNK as u64 <= window {
+                manager.ingest(id, sent, &[0x5A; CHUNK], false).unwrap();
+                sent += CHUNK as u64;
+            }
+            if manager.may_issue_credit(id) {
+                window += CREDIT;
+            }
+            manager.read(id, 64).unwrap();
+            history.push(manager.buffered_bytes());
+        }
+
+        let peak = history.iter().copied().max().unwrap();
+        assert!(
+            peak <= limits.max_buffered_per_stream + CREDIT,
+            "peak {peak}"
+        );
+        let late = &history[200..];
+        assert!(late.iter().all(|&buffered| buffered + CREDIT >= peak));
+        assert_eq!(manager.backpressure(), BackpressureState::StreamLimited);
+
+        manager.read(id, usize::MAX).unwrap();
+        assert!(manager.may_issue_credit(id));
+        assert_eq!(manager.backpressure(), BackpressureState::Clear);
+    }
+
+    #[test]
+    fn aggregate_limit_withholds_all_credit_and_saturates() {
+        let mut manager =
+            StreamManager::new(EndpointRole::Server).with_recv_buffer_limits(RecvBufferLimits {
+                max_buffered_per_stream: 1000,
+                max_buffered_total: 100,
+            });
+        let busy = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        let idle = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        manager.get_or_create(idle);
+
+        manager.ingest(busy, 0, &[1; 100], false).unwrap();
+        assert_eq!(manager.backpressure(), BackpressureState::ConnectionLimited);
+        assert!(!manager.may_issue_credit(idle));
+
+        // Data within a window already granted is still accepted.
+        manager.ingest(busy, 100, &[1; 100], false).unwrap();
+        assert_eq!(manager.backpressure(), BackpressureState::Saturated);
+
+        manager.read(busy, 150).unwrap();
+        assert_eq!(manager.backpressure(), BackpressureState::Clear);
+        assert!(manager.may_issue_credit(idle));
+    }
+
+    #[test]
+    fn draining_refuses_new_streams_until_in_flight_ones_finish() {
+        let mut client = StreamManager::new(EndpointRole::Client);
+        let call = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        client.try_get_or_create(call).unwrap();
+        client.queue_send(call, b"request").unwrap();
+
+        client.begin_drain();
+        assert!(client.is_draining());
+        assert!(!client.is_drained());
+        assert_eq!(client.open_unidirectional(), Err(StreamError::Draining));
+        let peer_stream = StreamId::new(EndpointRole::Server, StreamKind::Unidirectional, 0);
+        assert_eq!(
+            client.ingest(peer_stream, 0, b"late", true),
+            Err(StreamError::Draining)
+        );
+
+        // The call opened before the drain still runs to completion.
+        client.finish(call).unwrap();
+        let chunk = client.poll_send_chunk(call, 1024).unwrap().unwrap();
+        assert!(chunk.fin);
+        assert_eq!(client.active_streams(), 1);
+        client.ingest(call, 0, b"response", true).unwrap();
+        assert!(!client.is_drained());
+        assert_eq!(client.read(call, 1024).unwrap(), b"response");
+        assert_eq!(client.active_streams(), 0);
+        assert!(client.is_drained());
+    }
+
+    #[test]
+    fn poll_any_interleaves_streams_under_tight_connection_window() {
+        let mut manager = StreamManager::new(EndpointRole::Client);
+        let first = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        let second = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        for id in [first, second] {
+            manager.get_or_create(id).queue_send(&[0xAB; 1000]).unwrap();
+        }
+        manager.set_connection_limit(600);
+        assert_eq!(manager.ready_streams(), [first, second]);
+
+        let mut order = Vec::new();
+        while let Some((id, chunk)) = manager.poll_any_send_chunk(100).unwrap() {
+            assert_eq!(chunk.payload.len(), 100);
+            order.push(id);
+        }
+        assert_eq!(order, [first, second].repeat(3));
+        assert!(manager.has_pending_data());
+        assert!(manager.ready_streams().is_empty());
+    }
+
+    #[test]
+    fn poll_any_skips_flow_blocked_stream() {
+        let mut manager = StreamManager::new(EndpointRole::Client);
+        let blocked = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 0);
+        let open = StreamId::new(EndpointRole::Client, StreamKind::Bidirectional, 1);
+        manager.get_or_create(blocked).queue_send(b"stuck").unwrap();
+        manager.get_or_create(open).queue_send(b"flowing").unwrap();
+        manager.set_stream_limit(blocked, 0);
+        assert_eq!(manager.ready_streams(), [open]);
+
+        let (id, chunk) = manager.poll_any_send_chunk(4).unwrap().expect("chunk");
+        assert_eq!((id, chunk.payload.as_slice()), (open, &b"flow"[..]));
+        let (id, chunk) = manager.poll_any_send_chunk(4).unwrap().expect("chunk");
+        assert_eq!((id, chunk.payload.as_slice()), (open, &b"ing"[..]));
+        assert!(manager.poll_any_send_chunk(4).unwrap().is_none());
+
+        manager.set_stream_limit(blocked, 16);
+        let (id, _) = manager.poll_any_send_chunk(16).unwrap().expect("unblocked");
+        assert_eq!(id, blocked);
+        assert!(!manager.has_pending_data());
+    }
+
+    mod proptests {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// Random data plus chunk ranges that tile it completely and overlap arbitrarily.
+        fn fragments_strategy() -> impl Strategy<Value = (Vec<u8>, Vec<(usize, usize)>)> {
+            prop::collection::vec(any::<u8>(), 1..=512).prop_flat_map(|data| {
+                let len = data.len();
+                let tiling = prop::collection::vec(1..=64usize, 1..=16).prop_map(move |sizes| {
+                    let mut ranges = Vec::new();
+                    let mut start = 0;
+                    for size in sizes.iter().cycle() {
+                        if start >= len {
+                            break;
+                        }
+                        let end = (start + size).min(len);
+                        ranges.push((start, end));
+                        start = end;
+                    }
+                    ranges
+                });
+                let extra = prop::collection::vec((0..len, 0..len), 0..=16).prop_map(|pairs| {
+                    pairs
+                        .into_iter()
+                        .map(|(a, b)| (a.min(b), a.max(b) + 1))
+                        .collect::<Vec<_>>()
+                });
+                (Just(data), tiling, extra).prop_flat_map(|(data, mut ranges, extra)| {
+                    ranges.extend(extra);
+                    (Just(data), Just(ranges).prop_shuffle())
+                })
+            })
+        }
+
+        proptest! {
+            /// Property: overlapping, duplicated and reordered chunks reassemble the original bytes
+            #[test]
+            fn prop_overlapping_chunks_reassemble(
+                (data, ranges) in fragments_strategy(),
+                read_size in 1usize..=128,
+            ) {
+                let mut stream = Stream::new(StreamId::from_raw(0));
+                let mut received = Vec::new();
+                for (start, end) in ranges {
+                    let fin = end == data.len();
+                    stream
+                        .ingest(start as u64, &data[start..end], fin)
+                        .expect("consistent chunks are accepted");
+                    received.extend(stream.read(read_size));
+                }
+                received.extend(stream.read(usize::MAX));
+
+                prop_assert_eq!(received, data);
+                prop_assert!(stream.is_receive_finished());
+            }
+        }
+    }
 }
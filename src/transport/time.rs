@@ -0,0 +1,458 @@
+//! Clocks and a hierarchical timer wheel for transport timers.
+//!
+//! Transport state machines ([`LossManager`](super::LossManager),
+//! [`ReceiveHistory`](super::ReceiveHistory), ...) never read the clock themselves: every
+//! operation takes the current time as a `now` argument and exposes its next deadline. A
+//! driver reads `now` from a [`Clock`], feeds the deadlines into a [`TimerWheel`], and calls
+//! back into the state machine when [`TimerWheel::advance`] reports a key as expired. Tests
+//! substitute [`MockClock`] and advance it explicitly instead of sleeping.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::mem;
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+/// Source of the current time.
+pub trait Clock: fmt::Debug + Send + Sync {
+    /// Current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// [`Clock`] reading the system wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// [`Clock`] that only moves when told to, for deterministic tests.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<SystemTime>,
+}
+
+impl MockClock {
+    /// Create a clock reading `start`.
+    #[must_use]
+    pub fn new(start: SystemTime) -> Self {
+        Self {
+            now: Mutex::new(start),
+        }
+    }
+
+    /// Move the clock forward by `by`.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+
+    /// Set the clock to `now`.
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner) = now;
+    }
+}
+
+impl Default for MockClock {
+    /// A clock starting at the unix epoch.
+    fn default() -> Self {
+        Self::new(SystemTime::UNIX_EPOCH)
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Slots per wheel level (one 6-bit digit of the tick count).
+const SLOTS: usize = 64;
+const SLOT_BITS: u32 = SLOTS.trailing_zeros();
+/// Number of levels; together they span `64^6` ticks (about two years at 1 ms ticks).
+const LEVELS: usize = 6;
+/// Bits of the tick count covered by the levels; later deadlines wait in the overflow list.
+const WHEEL_BITS: usize = SLOT_BITS as usize * LEVELS;
+
+/// Default timer resolution.
+pub const DEFAULT_TIMER_TICK: Duration = Duration::from_millis(1);
+
+#[derive(Debug)]
+struct Entry<K> {
+    key: K,
+    tick: u64,
+    id: u64,
+}
+
+/// Hierarchical timing wheel keyed by `K`.
+///
+/// Deadlines are rounded up to whole ticks, so a timer never fires early. Insertion and
+/// cancellation are O(1); [`advance`](Self::advance) touches each timer at most once per
+/// level on its way down, so the amortized cost per timer is constant regardless of how many
+/// are pending. Each key has at most one timer: inserting an existing key reschedules it.
+#[derive(Debug)]
+pub struct TimerWheel<K> {
+    start: SystemTime,
+    tick: Duration,
+    /// Ticks since `start` already processed.
+    elapsed: u64,
+    levels: Vec<Vec<Vec<Entry<K>>>>,
+    /// Bit `s` of `occupied[level]` is set when slot `s` holds entries (possibly stale).
+    occupied: [u64; LEVELS],
+    /// Timers inserted with a deadline that had already passed.
+    due: Vec<Entry<K>>,
+    /// Timers beyond the span of the levels, re-placed when the span rolls over.
+    overflow: Vec<Entry<K>>,
+    /// Live timer ID and deadline tick per key; entries with another ID are stale.
+    active: HashMap<K, (u64, u64)>,
+    next_id: u64,
+}
+
+impl<K: Clone + Eq + Hash> TimerWheel<K> {
+    /// Create a wheel starting at `start` with the given resolution.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tick` is zero.
+    #[must_use]
+    pub fn new(start: SystemTime, tick: Duration) -> Self {
+        assert!(!tick.is_zero(), "tick must be positive");
+        Self {
+            start,
+            tick,
+            elapsed: 0,
+            levels: (0..LEVELS)
+                .map(|_| (0..SLOTS).map(|_| Vec::new()).collect())
+                .collect(),
+            occupied: [0; LEVELS],
+            due: Vec::new(),
+            overflow: Vec::new(),
+            active: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Schedule `key` to expire at `deadline`, replacing any timer it already has.
+    pub fn insert(&mut self, key: K, deadline: SystemTime) {
+        let tick = self.tick_ceil(deadline);
+        let id = self.next_id;
+        self.next_id += 1;
+        self.active.insert(key.clone(), (id, tick));
+        self.place(Entry { key, tick, id });
+    }
+
+    /// Cancel the timer for `key`; returns whether one was pending.
+    pub fn cancel(&mut self, key: &K) -> bool {
+        self.active.remove(key).is_some()
+    }
+
+    /// Deadline of the timer for `key`, rounded up to the wheel's resolution.
+    #[must_use]
+    pub fn deadline(&self, key: &K) -> Option<SystemTime> {
+        self.active.get(key).map(|(_, tick)| self.time_of(*tick))
+    }
+
+    /// Number of pending timers.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.active.len()
+    }
+
+    /// Check whether no timers are pending.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.active.is_empty()
+    }
+
+    /// Earliest time at which [`advance`](Self::advance) may return a key.
+    ///
+    /// This is a lower bound: timers far in the future are tracked at coarse resolution, and
+    /// cancelled timers are only purged lazily.
+    #[must_use]
+    pub fn next_expiration(&self) -> Option<SystemTime> {
+        if !self.due.is_empty() {
+            return Some(self.time_of(self.elapsed));
+        }
+        self.next_slot().map(|(_, _, tick)| self.time_of(tick))
+    }
+
+    /// Move the wheel to `now`, returning the keys whose deadlines passed, earliest first.
+    pub fn advance(&mut self, now: SystemTime) -> Vec<K> {
+        let target = self.tick_floor(now);
+        let mut expired = Vec::new();
+        for entry in mem::take(&mut self.due) {
+            self.fire(entry, &mut expired);
+        }
+
+        while let Some((level, slot, tick)) = self.next_slot() {
+            if tick > target {
+                break;
+            }
+            self.elapsed = tick;
+            let entries = if level == LEVELS {
+                mem::take(&mut self.overflow)
+            } else {
+                self.occupied[level] &= !(1 << slot);
+                mem::take(&mut self.levels[level][slot])
+            };
+            for entry in entries {
+                if !self.is_live(&entry) {
+                    continue;
+                }
+                if entry.tick <= self.elapsed {
+                    self.fire(entry, &mut expired);
+                } else {
+                    // Cascade into a finer level now that the slot's range has begun.
+                    self.place(entry);
+                }
+            }
+        }
+        self.elapsed = self.elapsed.max(target);
+        expired
+    }
+
+    fn fire(&mut self, entry: Entry<K>, expired: &mut Vec<K>) {
+        if self.is_live(&entry) {
+            self.active.remove(&entry.key);
+            expired.push(entry.key);
+        }
+    }
+
+    fn is_live(&self, entry: &Entry<K>) -> bool {
+        self.active
+            .get(&entry.key)
+            .is_some_and(|(id, _)| *id == entry.id)
+    }
+
+    fn place(&mut self, entry: Entry<K>) {
+        if entry.tick <= self.elapsed {
+            self.due.push(entry);
+            return;
+        }
+        // The highest digit in which the deadline differs from the current tick picks the
+        // level; everything above it matches, so the slot is reached before it wraps.
+        let level = ((entry.tick ^ self.elapsed).ilog2() / SLOT_BITS) as usize;
+        if level >= LEVELS {
+            self.overflow.push(entry);
+            return;
+        }
+        let slot = Self::digit(entry.tick, level);
+        self.levels[level][slot].push(entry);
+        self.occupied[level] |= 1 << slot;
+    }
+
+    /// Earliest occupied slot after the current tick, as `(level, slot, first tick)`.
+    ///
+    /// Level [`LEVELS`] stands for the overflow list, due when the levels' span rolls over.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        let in_levels = (0..LEVELS).find_map(|level| {
+            let position = Self::digit(self.elapsed, level);
+            let ahead = self.occupied[level] & (u64::MAX << position) & !(1 << position);
+            if ahead == 0 {
+                return None;
+            }
+            let slot = ahead.trailing_zeros() as usize;
+            let shift = SLOT_BITS as usize * (level + 1);
+            let base = self.elapsed >> shift << shift;
+            Some((
+                level,
+                slot,
+                base | ((slot as u64) << (SLOT_BITS as usize * level)),
+            ))
+        });
+        if in_levels.is_some() || self.overflow.is_empty() {
+            return in_levels;
+        }
+        let rollover = ((self.elapsed >> WHEEL_BITS) + 1).checked_mul(1 << WHEEL_BITS)?;
+        Some((LEVELS, 0, rollover))
+    }
+
+    #[allow(clippy::cast_possible_truncation)] // masked to a slot index
+    const fn digit(tick: u64, level: usize) -> usize {
+        ((tick >> (SLOT_BITS as usize * level)) & (SLOTS as u64 - 1)) as usize
+    }
+
+    fn time_of(&self, tick: u64) -> SystemTime {
+        let nanos = self.tick.as_nanos().saturating_mul(u128::from(tick));
+        self.start + Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+    }
+
+    fn ticks_since_start(&self, time: SystemTime) -> (u128, bool) {
+        let since = time.duration_since(self.start).unwrap_or_default();
+        let tick = self.tick.as_nanos();
+        (since.as_nanos() / tick, since.as_nanos() % tick != 0)
+    }
+
+    fn tick_floor(&self, time: SystemTime) -> u64 {
+        u64::try_from(self.ticks_since_start(time).0).unwrap_or(u64::MAX)
+    }
+
+    fn tick_ceil(&self, time: SystemTime) -> u64 {
+        let (ticks, partial) = self.ticks_since_start(time);
+        u64::try_from(ticks + u128::from(partial)).unwrap_or(u64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(1);
+
+    fn wheel() -> (TimerWheel<u32>, SystemTime) {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        (TimerWheel::new(start, TICK), start)
+    }
+
+    #[test]
+    fn timers_fire_in_deadline_order_and_not_early() {
+        let (mut wheel, start) = wheel();
+        wheel.insert(3, start + Duration::from_millis(30));
+        wheel.insert(1, start + Duration::from_millis(10));
+        wheel.insert(2, start + Duration::from_micros(10_500));
+
+        assert!(wheel.advance(start + Duration::from_millis(9)).is_empty());
+        assert_eq!(wheel.advance(start + Duration::from_millis(10)), [1]);
+        // 10.5 ms rounds up to the 11 ms tick.
+        assert!(
+            wheel
+                .advance(start + Duration::from_micros(10_900))
+                .is_empty()
+        );
+        assert_eq!(wheel.advance(start + Duration::from_secs(1)), [2, 3]);
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn cancel_and_reschedule_replace_pending_timer() {
+        let (mut wheel, start) = wheel();
+        wheel.insert(1, start + Duration::from_millis(5));
+        wheel.insert(2, start + Duration::from_millis(5));
+        assert!(wheel.cancel(&1));
+        assert!(!wheel.cancel(&1));
+        wheel.insert(2, start + Duration::from_millis(50));
+        assert_eq!(wheel.len(), 1);
+        assert_eq!(wheel.deadline(&2), Some(start + Duration::from_millis(50)));
+
+        assert!(wheel.advance(start + Duration::from_millis(49)).is_empty());
+        assert_eq!(wheel.advance(start + Duration::from_millis(50)), [2]);
+    }
+
+    #[test]
+    fn timers_cascade_across_levels() {
+        let (mut wheel, start) = wheel();
+        // One deadline per level boundary, plus neighbours on either side.
+        let mut deadlines: Vec<u64> = (1..LEVELS)
+            .flat_map(|level| {
+                let boundary = 1u64 << (SLOT_BITS as usize * level);
+                [boundary - 1, boundary, boundary + 1]
+            })
+            .collect();
+        deadlines.extend([1, 63, 4_095 * 7, 200_000, 123_456_789]);
+        for (key, ticks) in (0u32..).zip(&deadlines) {
+            wheel.insert(key, start + TICK * u32::try_from(*ticks).unwrap());
+        }
+
+        let mut fired = Vec::new();
+        let mut now = 0;
+        // Step in irregular increments so slot starts are skipped over as well as hit.
+        for step in [1, 62, 1, 1, 4_000, 97, 1_000_000, 3, 64u64.pow(4)]
+            .iter()
+            .cycle()
+        {
+            now += step;
+            for key in wheel.advance(start + TICK * u32::try_from(now).unwrap()) {
+                let ticks = deadlines[key as usize];
+                assert!(ticks <= now, "timer at {ticks} fired early at {now}");
+                fired.push(ticks);
+            }
+            if now > 64u64.pow(5) + 1 {
+                break;
+            }
+        }
+        let mut expected = deadlines.clone();
+        expected.sort_unstable();
+        assert_eq!(fired, expected);
+    }
+
+    #[test]
+    fn each_timer_fires_at_its_exact_tick() {
+        let (mut wheel, start) = wheel();
+        let deadlines: Vec<u64> = (0..5_000u64).map(|i| (i * 7_919) % 300_000 + 1).collect();
+        for (key, ticks) in (0u32..).zip(&deadlines) {
+            wheel.insert(key, start + TICK * u32::try_from(*ticks).unwrap());
+        }
+        assert_eq!(wheel.len(), deadlines.len());
+        for now in 1..=300_000u64 {
+            for key in wheel.advance(start + TICK * u32::try_from(now).unwrap()) {
+                assert_eq!(deadlines[key as usize], now);
+            }
+        }
+        assert!(wheel.is_empty());
+    }
+
+    #[test]
+    fn timers_beyond_wheel_span_survive_rollover() {
+        // With nanosecond ticks the levels span about 69 seconds.
+        let start = SystemTime::UNIX_EPOCH;
+        let mut wheel = TimerWheel::new(start, Duration::from_nanos(1));
+        let span = Duration::from_nanos(1 << WHEEL_BITS);
+        wheel.insert(1, start + span * 3 + Duration::from_nanos(5));
+        wheel.insert(2, start + span);
+
+        assert!(
+            wheel
+                .advance(start + span - Duration::from_nanos(1))
+                .is_empty()
+        );
+        assert_eq!(wheel.advance(start + span), [2]);
+        assert!(wheel.advance(start + span * 3).is_empty());
+        assert!(
+            wheel
+                .advance(start + span * 3 + Duration::from_nanos(4))
+                .is_empty()
+        );
+        assert_eq!(
+            wheel.advance(start + span * 3 + Duration::from_nanos(5)),
+            [1]
+        );
+    }
+
+    #[test]
+    fn overdue_timers_fire_on_next_advance() {
+        let (mut wheel, start) = wheel();
+        wheel.advance(start + Duration::from_secs(10));
+        wheel.insert(1, start);
+        assert_eq!(
+            wheel.next_expiration(),
+            Some(start + Duration::from_secs(10))
+        );
+        assert_eq!(wheel.advance(start + Duration::from_secs(10)), [1]);
+    }
+
+    #[test]
+    fn next_expiration_is_a_lower_bound() {
+        let (mut wheel, start) = wheel();
+        assert_eq!(wheel.next_expiration(), None);
+        wheel.insert(1, start + Duration::from_secs(5));
+        let bound = wheel.next_expiration().unwrap();
+        assert!(bound <= start + Duration::from_secs(5));
+        assert!(bound > start);
+    }
+
+    #[test]
+    fn mock_clock_only_moves_when_told() {
+        let clock = MockClock::default();
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH);
+        clock.advance(Duration::from_millis(3));
+        assert_eq!(
+            clock.now(),
+            SystemTime::UNIX_EPOCH + Duration::from_millis(3)
+        );
+        clock.set(SystemTime::UNIX_EPOCH);
+        assert_eq!(clock.now(), SystemTime::UNIX_EPOCH);
+    }
+}
@@ -1,8 +1,11 @@
 //! High-level transport facade built on the MXP custom transport stack.
 
+use std::collections::HashSet;
+use std::fmt;
+use std::io::IoSlice;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 #[cfg(feature = "debug-tools")]
 use std::path::PathBuf;
@@ -11,12 +14,17 @@ use crate::protocol::metrics::Metrics;
 use tracing::{debug, instrument};
 
 use super::buffer::{Buffer, BufferPool};
+use super::connection::ConnectionError;
 #[cfg(feature = "debug-tools")]
 use super::debug::PcapRecorder;
+use super::demux::{ConnectionHandle, ConnectionTable};
+use super::ecn::EcnCodepoint;
 use super::error::TransportError;
-use super::packet::PacketFlags;
+use super::packet::{ConnectionId, PacketFlags};
 use super::packet_crypto::{DecryptedPacket, PacketCipher};
-use super::socket::{SocketBinding, SocketError};
+#[cfg(feature = "tokio")]
+use super::socket::AsyncSocketBinding;
+use super::socket::{DatagramSocket, SocketBinding, SocketError};
 
 /// Transport configuration options.
 #[derive(Debug, Clone)]
@@ -29,6 +37,10 @@ pub struct TransportConfig {
     pub read_timeout: Option<Duration>,
     /// Optional write timeout for sockets.
     pub write_timeout: Option<Duration>,
+    /// Zero pooled buffers on release; they hold decrypted payloads.
+    pub zero_buffers_on_release: bool,
+    /// Drop datagrams from peers the filter rejects before any decoding or decryption.
+    pub peer_filter: Option<PeerFilter>,
     /// Optional PCAP capture path for outbound packets (debug builds only).
     #[cfg(feature = "debug-tools")]
     pub pcap_send_path: Option<PathBuf>,
@@ -44,6 +56,8 @@ impl Default for TransportConfig {
             max_buffers: 1024,
             read_timeout: None,
             write_timeout: None,
+            zero_buffers_on_release: true,
+            peer_filter: None,
             #[cfg(feature = "debug-tools")]
             pcap_send_path: None,
             #[cfg(feature = "debug-tools")]
@@ -52,23 +66,125 @@ impl Default for TransportConfig {
     }
 }
 
+/// Decides which peers an endpoint accepts datagrams from.
+///
+/// Rejected datagrams are dropped as soon as they are read and counted in the
+/// `foreign_datagrams_dropped` metric. A client talking to a single server can instead
+/// [`connect`](TransportHandle::connect) its handle and let the kernel do the filtering.
+#[derive(Clone)]
+pub enum PeerFilter {
+    /// Accept only these addresses.
+    Allow(HashSet<SocketAddr>),
+    /// Accept the addresses for which the callback returns `true`.
+    Custom(Arc<dyn Fn(SocketAddr) -> bool + Send + Sync>),
+}
+
+impl PeerFilter {
+    /// Accept only the given addresses.
+    pub fn allow(addrs: impl IntoIterator<Item = SocketAddr>) -> Self {
+        Self::Allow(addrs.into_iter().collect())
+    }
+
+    /// Accept the addresses for which `accept` returns `true`.
+    pub fn custom(accept: impl Fn(SocketAddr) -> bool + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(accept))
+    }
+
+    /// Whether datagrams from `addr` are accepted.
+    #[must_use]
+    pub fn accepts(&self, addr: SocketAddr) -> bool {
+        match self {
+            Self::Allow(addrs) => addrs.contains(&addr),
+            Self::Custom(accept) => accept(addr),
+        }
+    }
+}
+
+impl fmt::Debug for PeerFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Allow(addrs) => f.debug_tuple("Allow").field(addrs).finish(),
+            Self::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
 /// Handle used by callers to interact with the transport.
+///
+/// Handles from [`Transport::bind`] use UDP; [`Transport::bind_socket`] accepts any
+/// [`DatagramSocket`].
+#[derive(Debug)]
+pub struct TransportHandle<S = SocketBinding> {
+    inner: Arc<TransportInner<S>>,
+}
+
+impl<S> Clone for TransportHandle<S> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+/// Asynchronous handle for driving the transport from a tokio runtime.
+#[cfg(feature = "tokio")]
 #[derive(Clone, Debug)]
-pub struct TransportHandle {
-    inner: Arc<TransportInner>,
+pub struct AsyncTransportHandle {
+    inner: Arc<TransportInner<AsyncSocketBinding>>,
 }
 
 #[derive(Debug)]
-struct TransportInner {
-    socket: SocketBinding,
+struct TransportInner<S> {
+    socket: S,
     buffers: BufferPool,
+    peer_filter: Option<PeerFilter>,
     #[cfg(feature = "debug-tools")]
     pcap_send: Option<PcapRecorder>,
     #[cfg(feature = "debug-tools")]
     pcap_recv: Option<PcapRecorder>,
 }
 
-impl TransportHandle {
+impl<S> TransportInner<S> {
+    /// Whether a datagram from `addr` passes the peer filter; rejections are counted.
+    fn admits(&self, addr: SocketAddr) -> bool {
+        if self
+            .peer_filter
+            .as_ref()
+            .is_none_or(|filter| filter.accepts(addr))
+        {
+            return true;
+        }
+        debug!(%addr, "dropping datagram from filtered peer");
+        Metrics::record_foreign_datagram_dropped();
+        false
+    }
+
+    #[cfg_attr(not(feature = "debug-tools"), allow(clippy::unused_self))]
+    fn record_outbound(&self, packet: &[u8]) {
+        #[cfg(feature = "debug-tools")]
+        if let Some(recorder) = &self.pcap_send {
+            if let Err(err) = recorder.record(packet) {
+                debug!(error = ?err, "failed to record outbound packet");
+            }
+        }
+        #[cfg(not(feature = "debug-tools"))]
+        let _ = packet;
+    }
+
+    #[cfg_attr(not(feature = "debug-tools"), allow(clippy::unused_self))]
+    fn record_inbound(&self, packet: &[u8]) {
+        #[cfg(feature = "debug-tools")]
+        if let Some(recorder) = &self.pcap_recv {
+            if let Err(err) = recorder.record(packet) {
+                debug!(error = ?err, "failed to record inbound packet");
+            }
+        }
+        #[cfg(not(feature = "debug-tools"))]
+        let _ = packet;
+    }
+}
+
+impl<S: DatagramSocket> TransportHandle<S> {
     /// Acquire a reusable buffer for outbound or inbound data.
     #[must_use]
     pub fn acquire_buffer(&self) -> Buffer {
@@ -81,13 +197,89 @@ impl TransportHandle {
         self.inner.socket.send_to(buffer, addr)
     }
 
+    /// Send several already-sealed packets at once (coalesced with UDP GSO where available).
+    #[instrument(level = "trace", skip(self, packets), fields(count = packets.len()))]
+    pub fn send_batch(
+        &self,
+        packets: &[IoSlice<'_>],
+        addr: SocketAddr,
+    ) -> Result<usize, SocketError> {
+        let sent = self.inner.socket.send_batch(packets, addr)?;
+        for packet in packets {
+            self.inner.record_outbound(packet);
+        }
+        Ok(sent)
+    }
+
     /// Receive data into the provided buffer (blocking call).
+    ///
+    /// Datagrams rejected by the [`PeerFilter`] are skipped. [`Buffer::freeze`] then hands
+    /// the datagram to [`Message::decode_bytes`](crate::Message::decode_bytes) without
+    /// another copy.
     #[instrument(level = "trace", skip(self, buffer))]
     pub fn receive(&self, buffer: &mut Buffer) -> Result<(usize, SocketAddr), SocketError> {
-        let raw = buffer.as_mut_slice();
-        let (len, addr) = self.inner.socket.recv_from(raw)?;
-        buffer.set_len(len);
-        Ok((len, addr))
+        loop {
+            let meta = self.inner.socket.recv_from(buffer.as_mut_slice())?;
+            if self.inner.admits(meta.addr) {
+                buffer.set_len(meta.len);
+                return Ok((meta.len, meta.addr));
+            }
+        }
+    }
+
+    /// Restrict the handle to `addr`; see [`SocketBinding::connect`] for UDP sockets.
+    ///
+    /// Use [`send_connected`](Self::send_connected) and
+    /// [`receive_connected`](Self::receive_connected) afterwards.
+    #[instrument(level = "debug", skip(self))]
+    pub fn connect(&self, addr: SocketAddr) -> Result<(), SocketError> {
+        self.inner.socket.connect(addr)
+    }
+
+    /// Address the handle is connected to.
+    pub fn peer_addr(&self) -> Result<SocketAddr, SocketError> {
+        self.inner.socket.peer_addr()
+    }
+
+    /// Send data to the connected peer.
+    #[instrument(level = "trace", skip(self, buffer))]
+    pub fn send_connected(&self, buffer: &[u8]) -> Result<usize, SocketError> {
+        self.inner.socket.send(buffer)
+    }
+
+    /// Receive a datagram from the connected peer (blocking call).
+    #[instrument(level = "trace", skip(self, buffer))]
+    pub fn receive_connected(&self, buffer: &mut Buffer) -> Result<usize, SocketError> {
+        self.receive(buffer).map(|(len, _)| len)
+    }
+
+    /// Receive several datagrams in one call, filling `buffers` in order.
+    ///
+    /// Returns the sender of each filled buffer; buffers past the returned count are left empty.
+    /// Datagrams rejected by the [`PeerFilter`] are dropped, so this may return fewer senders
+    /// than datagrams were read, or none at all.
+    #[instrument(level = "trace", skip(self, buffers), fields(capacity = buffers.len()))]
+    pub fn receive_batch(&self, buffers: &mut [Buffer]) -> Result<Vec<SocketAddr>, SocketError> {
+        for buffer in buffers.iter_mut() {
+            buffer.reset();
+        }
+        let received = {
+            let mut raw: Vec<&mut [u8]> = buffers.iter_mut().map(Buffer::as_mut_slice).collect();
+            self.inner.socket.recv_batch(&mut raw)?
+        };
+        let mut senders = Vec::with_capacity(received.len());
+        for (index, (len, addr)) in received.into_iter().enumerate() {
+            if !self.inner.admits(addr) {
+                continue;
+            }
+            // Move accepted datagrams down over the dropped ones.
+            let slot = senders.len();
+            buffers.swap(slot, index);
+            buffers[slot].set_len(len);
+            self.inner.record_inbound(buffers[slot].as_slice());
+            senders.push(addr);
+        }
+        Ok(senders)
     }
 
     /// Seal and send an encrypted packet using the provided cipher state.
@@ -95,7 +287,7 @@ impl TransportHandle {
     pub fn send_packet(
         &self,
         cipher: &mut PacketCipher,
-        conn_id: u64,
+        conn_id: &ConnectionId,
         flags: PacketFlags,
         payload: &[u8],
         addr: SocketAddr,
@@ -109,38 +301,179 @@ impl TransportHandle {
             .socket
             .send_to(buffer.as_slice(), addr)
             .map_err(TransportError::from)?;
-        #[cfg(feature = "debug-tools")]
-        if let Some(recorder) = &self.inner.pcap_send {
-            if let Err(err) = recorder.record(buffer.as_slice()) {
-                debug!(error = ?err, "failed to record outbound packet");
-            }
-        }
+        self.inner.record_outbound(buffer.as_slice());
         Ok(packet_number)
     }
 
     /// Receive and decrypt a packet into plaintext payload using the provided cipher.
+    ///
+    /// Packets rejected by the [`PeerFilter`] are skipped before decryption. The packet's ECN
+    /// codepoint is returned for the caller's [`ReceiveHistory`](super::ReceiveHistory).
     #[instrument(level = "debug", skip(self, cipher, buffer))]
     pub fn receive_packet(
         &self,
         cipher: &mut PacketCipher,
         buffer: &mut Buffer,
-    ) -> Result<(DecryptedPacket, SocketAddr), TransportError> {
+    ) -> Result<(DecryptedPacket, SocketAddr, EcnCodepoint), TransportError> {
         buffer.reset();
-        let (len, addr) = self
-            .inner
-            .socket
-            .recv_from(buffer.as_mut_slice())
-            .map_err(TransportError::from)?;
-        buffer.set_len(len);
+        let meta = loop {
+            let meta = self
+                .inner
+                .socket
+                .recv_from(buffer.as_mut_slice())
+                .map_err(TransportError::from)?;
+            if self.inner.admits(meta.addr) {
+                break meta;
+            }
+        };
+        buffer.set_len(meta.len);
         let packet = buffer.as_slice();
-        #[cfg(feature = "debug-tools")]
-        if let Some(recorder) = &self.inner.pcap_recv {
-            if let Err(err) = recorder.record(packet) {
-                debug!(error = ?err, "failed to record inbound packet");
+        self.inner.record_inbound(packet);
+        let decrypted = cipher.open(packet)?;
+        Ok((decrypted, meta.addr, meta.ecn))
+    }
+
+    /// Receive one datagram and route it to its connection in `table`.
+    ///
+    /// Datagrams rejected by the [`PeerFilter`] are skipped. Returns the connection the
+    /// datagram reached, or `None` if the table dropped it; see
+    /// [`ConnectionTable::handle_datagram`].
+    pub fn accept_datagram(
+        &self,
+        table: &mut ConnectionTable,
+        buffer: &mut Buffer,
+        now: SystemTime,
+    ) -> Result<Option<ConnectionHandle>, ConnectionError> {
+        buffer.reset();
+        let meta = loop {
+            let meta = self
+                .inner
+                .socket
+                .recv_from(buffer.as_mut_slice())
+                .map_err(ConnectionError::Socket)?;
+            if self.inner.admits(meta.addr) {
+                break meta;
             }
+        };
+        buffer.set_len(meta.len);
+        self.inner.record_inbound(buffer.as_slice());
+        table.handle_datagram(buffer.as_slice(), meta.addr, now)
+    }
+
+    /// Send every datagram the connections in `table` have ready, returning how many went
+    /// out.
+    pub fn flush_connections(
+        &self,
+        table: &mut ConnectionTable,
+        now: SystemTime,
+    ) -> Result<usize, SocketError> {
+        let mut sent = 0;
+        while let Some((addr, datagram)) = table.poll_transmit(now) {
+            self.inner.socket.send_to(&datagram, addr)?;
+            self.inner.record_outbound(&datagram);
+            sent += 1;
         }
+        Ok(sent)
+    }
+
+    /// Expose the local socket address.
+    pub fn local_addr(&self) -> Result<SocketAddr, SocketError> {
+        self.inner.socket.local_addr()
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl AsyncTransportHandle {
+    /// Acquire a reusable buffer for outbound or inbound data.
+    #[must_use]
+    pub fn acquire_buffer(&self) -> Buffer {
+        self.inner.buffers.acquire()
+    }
+
+    /// Send data to the specified remote address.
+    #[instrument(level = "trace", skip(self, buffer))]
+    pub async fn send(&self, buffer: &[u8], addr: SocketAddr) -> Result<usize, SocketError> {
+        self.inner.socket.send_to(buffer, addr).await
+    }
+
+    /// Wait for a datagram and copy it into the provided buffer.
+    ///
+    /// Datagrams rejected by the [`PeerFilter`] are skipped.
+    #[instrument(level = "trace", skip(self, buffer))]
+    pub async fn receive(&self, buffer: &mut Buffer) -> Result<(usize, SocketAddr), SocketError> {
+        loop {
+            let meta = self.inner.socket.recv_from(buffer.as_mut_slice()).await?;
+            if self.inner.admits(meta.addr) {
+                buffer.set_len(meta.len);
+                return Ok((meta.len, meta.addr));
+            }
+        }
+    }
+
+    /// Seal and send an encrypted packet using the provided cipher state.
+    #[instrument(level = "debug", skip(self, cipher, payload, buffer))]
+    pub async fn send_packet(
+        &self,
+        cipher: &mut PacketCipher,
+        conn_id: &ConnectionId,
+        flags: PacketFlags,
+        payload: &[u8],
+        addr: SocketAddr,
+        buffer: &mut Buffer,
+    ) -> Result<u64, TransportError> {
+        buffer.reset();
+        let (packet_number, total_len) =
+            cipher.seal_into(conn_id, flags, payload, buffer.as_mut_slice())?;
+        buffer.set_len(total_len);
+        self.inner.socket.send_to(buffer.as_slice(), addr).await?;
+        self.inner.record_outbound(buffer.as_slice());
+        Ok(packet_number)
+    }
+
+    /// Wait for a packet and decrypt it using the provided cipher.
+    ///
+    /// Packets rejected by the [`PeerFilter`] are skipped before decryption.
+    #[instrument(level = "debug", skip(self, cipher, buffer))]
+    pub async fn receive_packet(
+        &self,
+        cipher: &mut PacketCipher,
+        buffer: &mut Buffer,
+    ) -> Result<(DecryptedPacket, SocketAddr, EcnCodepoint), TransportError> {
+        buffer.reset();
+        let meta = loop {
+            let meta = self.inner.socket.recv_from(buffer.as_mut_slice()).await?;
+            if self.inner.admits(meta.addr) {
+                break meta;
+            }
+        };
+        buffer.set_len(meta.len);
+        let packet = buffer.as_slice();
+        self.inner.record_inbound(packet);
         let decrypted = cipher.open(packet)?;
-        Ok((decrypted, addr))
+        Ok((decrypted, meta.addr, meta.ecn))
+    }
+
+    /// Wait for a packet until `deadline` (typically the earlier of
+    /// [`LossManager::loss_time`](super::LossManager::loss_time) and the ACK delay timer).
+    ///
+    /// Returns `Ok(None)` once the deadline passes so the caller can run its timer handling;
+    /// `None` waits indefinitely.
+    pub async fn receive_packet_until(
+        &self,
+        cipher: &mut PacketCipher,
+        buffer: &mut Buffer,
+        deadline: Option<SystemTime>,
+    ) -> Result<Option<(DecryptedPacket, SocketAddr, EcnCodepoint)>, TransportError> {
+        let Some(deadline) = deadline else {
+            return self.receive_packet(cipher, buffer).await.map(Some);
+        };
+        let remaining = deadline
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        match tokio::time::timeout(remaining, self.receive_packet(cipher, buffer)).await {
+            Ok(result) => result.map(Some),
+            Err(_) => Ok(None),
+        }
     }
 
     /// Expose the local socket address.
@@ -160,7 +493,11 @@ impl Transport {
     /// Create a new transport with the given configuration.
     #[must_use]
     pub fn new(config: TransportConfig) -> Self {
-        let pool = BufferPool::new(config.buffer_size, config.max_buffers);
+        let pool = if config.zero_buffers_on_release {
+            BufferPool::with_zero_on_release(config.buffer_size, config.max_buffers)
+        } else {
+            BufferPool::new(config.buffer_size, config.max_buffers)
+        };
         Self { config, pool }
     }
 
@@ -175,10 +512,42 @@ impl Transport {
             socket.set_write_timeout(Some(timeout))?;
         }
         Metrics::record_connection_open();
-        self.build_handle(socket)
+        Ok(TransportHandle {
+            inner: Arc::new(self.build_inner(socket)?),
+        })
+    }
+
+    /// Create an endpoint on an already bound socket, such as an in-memory one.
+    ///
+    /// Read and write timeouts are left to the socket.
+    #[instrument(level = "info", skip(self, socket))]
+    pub fn bind_socket<S: DatagramSocket>(
+        &self,
+        socket: S,
+    ) -> Result<TransportHandle<S>, SocketError> {
+        let inner = self.build_inner(socket)?;
+        Metrics::record_connection_open();
+        Ok(TransportHandle {
+            inner: Arc::new(inner),
+        })
     }
 
-    fn build_handle(&self, socket: SocketBinding) -> Result<TransportHandle, SocketError> {
+    /// Bind an asynchronous endpoint on the provided address; must be called within a tokio
+    /// runtime.
+    ///
+    /// Read and write timeouts do not apply; use
+    /// [`AsyncTransportHandle::receive_packet_until`] to bound waits.
+    #[cfg(feature = "tokio")]
+    #[instrument(level = "info", skip(self))]
+    pub fn bind_async(&self, addr: SocketAddr) -> Result<AsyncTransportHandle, SocketError> {
+        let socket = AsyncSocketBinding::bind(addr)?;
+        Metrics::record_connection_open();
+        Ok(AsyncTransportHandle {
+            inner: Arc::new(self.build_inner(socket)?),
+        })
+    }
+
+    fn build_inner<S>(&self, socket: S) -> Result<TransportInner<S>, SocketError> {
         let buffers = self.pool.clone();
         #[cfg(feature = "debug-tools")]
         let pcap_send = match &self.config.pcap_send_path {
@@ -191,15 +560,14 @@ impl Transport {
             None => None,
         };
 
-        Ok(TransportHandle {
-            inner: Arc::new(TransportInner {
-                socket,
-                buffers,
-                #[cfg(feature = "debug-tools")]
-                pcap_send,
-                #[cfg(feature = "debug-tools")]
-                pcap_recv,
-            }),
+        Ok(TransportInner {
+            socket,
+            buffers,
+            peer_filter: self.config.peer_filter.clone(),
+            #[cfg(feature = "debug-tools")]
+            pcap_send,
+            #[cfg(feature = "debug-tools")]
+            pcap_recv,
         })
     }
 }
@@ -210,9 +578,111 @@ impl Default for Transport {
     }
 }
 
-impl Drop for TransportInner {
+impl<S> Drop for TransportInner<S> {
     fn drop(&mut self) {
         debug!("transport handle dropped; metrics connection close");
         Metrics::record_connection_close();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::net::UdpSocket;
+
+    fn localhost() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 0))
+    }
+
+    fn bind(peer_filter: Option<PeerFilter>) -> TransportHandle {
+        let config = TransportConfig {
+            read_timeout: Some(Duration::from_millis(200)),
+            peer_filter,
+            ..TransportConfig::default()
+        };
+        Transport::new(config).bind(localhost()).expect("bind")
+    }
+
+    fn is_timeout(err: SocketError) -> bool {
+        let SocketError::Io(err) = err;
+        matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        )
+    }
+
+    #[test]
+    fn connected_handle_ignores_third_parties() {
+        let client = bind(None);
+        let server = UdpSocket::bind(localhost()).expect("bind server");
+        let stranger = UdpSocket::bind(localhost()).expect("bind stranger");
+        let server_addr = server.local_addr().unwrap();
+        let client_addr = client.local_addr().unwrap();
+
+        client.connect(server_addr).expect("connect");
+        assert_eq!(client.peer_addr().unwrap(), server_addr);
+        stranger.send_to(b"noise", client_addr).unwrap();
+        server.send_to(b"hello", client_addr).unwrap();
+
+        let mut buffer = client.acquire_buffer();
+        let len = client.receive_connected(&mut buffer).expect("receive");
+        assert_eq!(&buffer.as_slice()[..len], b"hello");
+        // The stranger's datagram never reached the socket.
+        assert!(is_timeout(
+            client.receive_connected(&mut buffer).unwrap_err()
+        ));
+
+        client.send_connected(b"reply").expect("send");
+        let mut reply = [0u8; 16];
+        let (len, from) = server.recv_from(&mut reply).unwrap();
+        assert_eq!((&reply[..len], from), (&b"reply"[..], client_addr));
+    }
+
+    #[test]
+    fn peer_filter_drops_and_counts_unexpected_sources() {
+        let server = UdpSocket::bind(localhost()).expect("bind server");
+        let stranger = UdpSocket::bind(localhost()).expect("bind stranger");
+        let server_addr = server.local_addr().unwrap();
+        let client = bind(Some(PeerFilter::allow([server_addr])));
+        let client_addr = client.local_addr().unwrap();
+        let before = Metrics::totals().foreign_datagrams_dropped;
+
+        stranger.send_to(b"noise", client_addr).unwrap();
+        server.send_to(b"hello", client_addr).unwrap();
+        let mut buffer = client.acquire_buffer();
+        let (len, from) = client.receive(&mut buffer).expect("receive");
+        assert_eq!(
+            (&buffer.as_slice()[..len], from),
+            (&b"hello"[..], server_addr)
+        );
+        assert!(Metrics::totals().foreign_datagrams_dropped > before);
+
+        // Batches keep the accepted datagrams in order at the front.
+        stranger.send_to(b"noise", client_addr).unwrap();
+        server.send_to(b"one", client_addr).unwrap();
+        stranger.send_to(b"noise", client_addr).unwrap();
+        server.send_to(b"two", client_addr).unwrap();
+        let mut buffers: Vec<Buffer> = (0..4).map(|_| client.acquire_buffer()).collect();
+        let mut received = Vec::new();
+        while received.len() < 2 {
+            let senders = client.receive_batch(&mut buffers).expect("receive batch");
+            assert!(senders.iter().all(|addr| *addr == server_addr));
+            received.extend(
+                buffers[..senders.len()]
+                    .iter()
+                    .map(|b| b.as_slice().to_vec()),
+            );
+        }
+        assert_eq!(received, [b"one".to_vec(), b"two".to_vec()]);
+        assert!(Metrics::totals().foreign_datagrams_dropped >= before + 3);
+    }
+
+    #[test]
+    fn custom_peer_filter_consults_the_callback() {
+        let filter = PeerFilter::custom(|addr| addr.ip().is_loopback());
+        assert!(filter.accepts(SocketAddr::from(([127, 0, 0, 1], 9))));
+        assert!(!filter.accepts(SocketAddr::from(([192, 0, 2, 1], 9))));
+        assert_eq!(format!("{filter:?}"), "Custom(..)");
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::ErrorKind;
+
+use mxp::protocol::{Error, Flags, MAX_PAYLOAD_SIZE, Message, MessageHeader, MessageType};
+use tokio::io::{AsyncWriteExt, DuplexStream, duplex};
+
+/// Write `bytes` in `chunk`-sized pieces, yielding between them, then close the stream.
+async fn trickle(mut writer: DuplexStream, bytes: Vec<u8>, chunk: usize) {
+    for piece in bytes.chunks(chunk) {
+        writer.write_all(piece).await.expect("write");
+        tokio::task::yield_now().await;
+    }
+}
+
+fn eof_message(err: Error) -> String {
+    match err {
+        Error::Io(err) => {
+            assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+            err.to_string()
+        }
+        other => panic!("expected an EOF error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn reads_consecutive_messages_delivered_in_small_chunks() {
+    let first = Message::with_ids(MessageType::Call, 1, 10, b"first payload".to_vec());
+    let second = Message::builder(MessageType::Event)
+        .message_id(2)
+        .trace_id(20)
+        .flags(Flags::new().with(Flags::REQUIRES_ACK))
+        .payload(vec![0xAB; 3000])
+        .build();
+    let mut wire = first.encode();
+    wire.extend_from_slice(&second.encode());
+
+    // A small pipe plus 7-byte writes split both the header and the payload across reads.
+    let (writer, mut reader) = duplex(16);
+    let sender = tokio::spawn(trickle(writer, wire, 7));
+
+    let read = Message::read_async(&mut reader)
+        .await
+        .expect("first message");
+    assert_eq!(read.message_type(), Some(MessageType::Call));
+    assert_eq!((read.message_id(), read.trace_id()), (1, 10));
+    assert_eq!(read.payload(), first.payload());
+
+    let read = Message::read_async(&mut reader)
+        .await
+        .expect("second message");
+    assert_eq!(read.message_id(), 2);
+    assert!(read.flags().requires_ack());
+    assert_eq!(read.payload(), second.payload());
+
+    sender.await.unwrap();
+    let closed = eof_message(Message::read_async(&mut reader).await.unwrap_err());
+    assert!(closed.contains("before a message"), "{closed}");
+}
+
+#[tokio::test]
+async fn eof_mid_message_is_reported_with_progress() {
+    let wire = Message::new(MessageType::Call, vec![1; 100]).encode();
+    let total = wire.len();
+
+    for cut in [10, 32, 90] {
+        let (writer, mut reader) = duplex(8);
+        tokio::spawn(trickle(writer, wire[..cut].to_vec(), 3));
+        let err = eof_message(Message::read_async(&mut reader).await.unwrap_err());
+        assert!(
+            err.contains(&format!("after {cut} of")),
+            "cut at {cut}: {err}"
+        );
+        if cut >= 32 {
+            assert!(err.contains(&format!("of {total} bytes")), "{err}");
+        }
+    }
+}
+
+#[tokio::test]
+async fn invalid_header_fails_before_reading_the_payload() {
+    // The header claims more than MAX_PAYLOAD_SIZE and the stream stays open: the reader
+    // must reject it from the header alone rather than wait for (or allocate) the body.
+    let header = MessageHeader::new(MessageType::Call, 1, 1, MAX_PAYLOAD_SIZE as u64 + 1);
+    let (mut writer, mut reader) = duplex(64);
+    writer.write_all(&header.to_bytes()).await.unwrap();
+
+    let err = Message::read_async(&mut reader).await.unwrap_err();
+    assert!(matches!(err, Error::PayloadTooLarge { .. }), "{err:?}");
+
+    let mut corrupt = Message::new(MessageType::Call, b"checked").encode();
+    let last = corrupt.len() - 1;
+    corrupt[last] ^= 0xFF;
+    writer.write_all(&corrupt).await.unwrap();
+    let err = Message::read_async(&mut reader).await.unwrap_err();
+    assert!(matches!(err, Error::ChecksumMismatch { .. }), "{err:?}");
+}
@@ -0,0 +1,260 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use mxp::protocol::{HandlerRegistry, Message, MessageType, heartbeat_ack};
+use mxp::transport::{
+    AEAD_KEY_LEN, AEAD_NONCE_LEN, AeadKey, AeadNonce, AsyncConnection, Connection,
+    ConnectionConfig, ConnectionError, ConnectionId, HEADER_PROTECTION_KEY_LEN, HandshakeServer,
+    HeaderProtectionKey, Initiator, PRIVATE_KEY_LEN, PacketCipher, PacketFlags, PrivateKey,
+    SessionKeys, Transport, TransportConfig,
+};
+
+fn make_session_keys(send_key: u8, recv_key: u8, send_hp: u8, recv_hp: u8) -> SessionKeys {
+    SessionKeys::new(
+        AeadKey::from_array([send_key; AEAD_KEY_LEN]),
+        AeadKey::from_array([recv_key; AEAD_KEY_LEN]),
+        HeaderProtectionKey::from_array([send_hp; HEADER_PROTECTION_KEY_LEN]),
+        HeaderProtectionKey::from_array([recv_hp; HEADER_PROTECTION_KEY_LEN]),
+        AeadNonce::from_array([send_key; AEAD_NONCE_LEN]),
+        AeadNonce::from_array([recv_key; AEAD_NONCE_LEN]),
+    )
+}
+
+fn localhost() -> SocketAddr {
+    "127.0.0.1:0".parse().expect("addr")
+}
+
+#[tokio::test]
+async fn async_endpoints_exchange_packets() {
+    let transport = Transport::new(TransportConfig::default());
+    let client = transport.bind_async(localhost()).expect("bind client");
+    let server = transport.bind_async(localhost()).expect("bind server");
+    let server_addr = server.local_addr().expect("server addr");
+    let client_addr = client.local_addr().expect("client addr");
+
+    let mut client_cipher = PacketCipher::new(make_session_keys(1, 2, 3, 4));
+    let mut server_cipher = PacketCipher::new(make_session_keys(2, 1, 4, 3));
+    let conn_id = ConnectionId::from_u64(7);
+
+    let receiver = tokio::spawn({
+        let server = server.clone();
+        async move {
+            let mut buffer = server.acquire_buffer();
+            let (packet, from, _) = server
+                .receive_packet(&mut server_cipher, &mut buffer)
+                .await
+                .expect("receive");
+            (packet.payload().to_vec(), from)
+        }
+    });
+
+    let mut buffer = client.acquire_buffer();
+    client
+        .send_packet(
+            &mut client_cipher,
+            &conn_id,
+            PacketFlags::from_bits(PacketFlags::ACK_ELICITING),
+            b"hello over tokio",
+            server_addr,
+            &mut buffer,
+        )
+        .await
+        .expect("send");
+
+    let (payload, from) = tokio::time::timeout(Duration::from_secs(5), receiver)
+        .await
+        .expect("packet delivered")
+        .expect("receiver task");
+    assert_eq!(payload, b"hello over tokio");
+    assert_eq!(from, client_addr);
+}
+
+#[tokio::test]
+async fn receive_until_returns_none_at_timer_deadline() {
+    let transport = Transport::default();
+    let endpoint = transport.bind_async(localhost()).expect("bind");
+    let mut cipher = PacketCipher::new(make_session_keys(1, 2, 3, 4));
+    let mut buffer = endpoint.acquire_buffer();
+
+    let deadline = SystemTime::now() + Duration::from_millis(20);
+    let outcome = endpoint
+        .receive_packet_until(&mut cipher, &mut buffer, Some(deadline))
+        .await
+        .expect("receive");
+    assert!(outcome.is_none());
+    assert!(SystemTime::now() >= deadline);
+}
+
+fn custom(byte: u8) -> MessageType {
+    MessageType::custom(byte).expect("custom range")
+}
+
+/// Client and server connections over loopback; the handshake completes as they are driven.
+async fn connected_pair() -> (AsyncConnection, AsyncConnection) {
+    let client_key = PrivateKey::from_array([0x21; PRIVATE_KEY_LEN]);
+    let server_key = PrivateKey::from_array([0x42; PRIVATE_KEY_LEN]);
+    let transport = Transport::default();
+    let client_socket = transport.bind_async(localhost()).expect("bind client");
+    let server_socket = transport.bind_async(localhost()).expect("bind server");
+    let server_addr = server_socket.local_addr().expect("server addr");
+
+    let initiator = Initiator::new(client_key.clone(), server_key.public_key());
+    let connection = Connection::connect(ConnectionConfig::default(), initiator, SystemTime::now())
+        .expect("connect");
+    let mut client = AsyncConnection::new(connection, client_socket, server_addr);
+    client.flush().await.expect("send hello");
+
+    let mut buffer = server_socket.acquire_buffer();
+    let (_, from) = server_socket.receive(&mut buffer).await.expect("hello");
+    let connection = Connection::accept(
+        ConnectionConfig::default(),
+        Arc::new(Mutex::new(HandshakeServer::new(server_key))),
+        &client_key.public_key(),
+        from,
+        buffer.as_slice(),
+        SystemTime::now(),
+    )
+    .expect("accept");
+    (
+        client,
+        AsyncConnection::new(connection, server_socket, from),
+    )
+}
+
+#[tokio::test]
+async fn recv_ends_cleanly_when_the_peer_closes() {
+    let (mut client, mut server) = connected_pair().await;
+    let server = tokio::spawn(async move {
+        let request = server.recv().await.expect("request").expect("open");
+        let reply = Message::new(MessageType::Response, request.payload().to_vec());
+        server.connection_mut().send_message(&reply).expect("reply");
+        let end = server.recv().await.expect("clean close");
+        (end, server.closed().await.expect("closed"))
+    });
+
+    client
+        .connection_mut()
+        .send_message(&Message::new(MessageType::Call, b"ping".to_vec()))
+        .expect("send");
+    let reply = tokio::time::timeout(Duration::from_secs(5), client.recv())
+        .await
+        .expect("reply in time")
+        .expect("reply")
+        .expect("open");
+    assert_eq!(reply.payload().as_ref(), b"ping");
+    client.connection_mut().close(0, "done", SystemTime::now());
+    let local = client.closed().await.expect("closed");
+    assert!(!local.remote);
+
+    let (end, remote) = server.await.expect("server task");
+    assert!(end.is_none());
+    assert_eq!((remote.error_code, remote.reason.as_str()), (0, "done"));
+    assert!(remote.remote);
+}
+
+#[tokio::test]
+async fn serve_answers_built_in_and_custom_types_with_registered_handlers() {
+    let (mut client, mut server) = connected_pair().await;
+    let server = tokio::spawn(async move {
+        let mut handlers = HandlerRegistry::new();
+        handlers
+            .register(custom(0x90), |request| {
+                let mut payload = request.payload().to_vec();
+                payload.reverse();
+                Some(Message::with_ids(
+                    custom(0x91),
+                    request.message_id(),
+                    request.trace_id(),
+                    payload,
+                ))
+            })
+            .register(MessageType::AgentHeartbeat, heartbeat_ack);
+        server.serve(&handlers).await
+    });
+
+    // The unhandled event is dropped; the replies come back in order.
+    let requests = [
+        Message::new(MessageType::Event, b"ignored".to_vec()),
+        Message::new(custom(0x90), b"abc".to_vec()),
+        Message::new(MessageType::AgentHeartbeat, Vec::new()),
+    ];
+    for request in &requests {
+        client.connection_mut().send_message(request).expect("send");
+    }
+    let reply = client
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("custom reply")
+        .expect("open");
+    assert_eq!(reply.message_type(), Some(custom(0x91)));
+    assert_eq!(reply.message_id(), requests[1].message_id());
+    assert_eq!(reply.payload().as_ref(), b"cba");
+    let ack = client
+        .recv_timeout(Duration::from_secs(5))
+        .await
+        .expect("heartbeat ack")
+        .expect("open");
+    assert_eq!(ack.message_type(), Some(MessageType::Ack));
+    assert_eq!(ack.message_id(), requests[2].message_id());
+
+    client.connection_mut().close(0, "done", SystemTime::now());
+    client.closed().await.expect("closed");
+    server
+        .await
+        .expect("server task")
+        .expect("serve ends cleanly");
+}
+
+#[tokio::test]
+async fn recv_reports_an_error_close() {
+    let (mut client, mut server) = connected_pair().await;
+    let server = tokio::spawn(async move {
+        let request = server.recv().await.expect("request").expect("open");
+        server
+            .connection_mut()
+            .send_message(&request)
+            .expect("echo");
+        server.recv().await
+    });
+
+    client
+        .connection_mut()
+        .send_message(&Message::new(MessageType::Call, b"ping".to_vec()))
+        .expect("send");
+    tokio::time::timeout(Duration::from_secs(5), client.recv())
+        .await
+        .expect("echo in time")
+        .expect("echo");
+    client
+        .connection_mut()
+        .close(7, "bad request", SystemTime::now());
+    match client.recv().await {
+        Err(ConnectionError::Closed(close)) => assert!(!close.remote),
+        other => panic!("expected a local error close, got {other:?}"),
+    }
+
+    match server.await.expect("server task") {
+        Err(ConnectionError::Closed(close)) => {
+            assert_eq!(
+                (close.error_code, close.reason.as_str()),
+                (7, "bad request")
+            );
+            assert!(close.remote);
+        }
+        other => panic!("expected the peer's error close, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn recv_timeout_is_distinct_from_connection_errors() {
+    let (mut client, _server) = connected_pair().await;
+    let started = SystemTime::now();
+    let outcome = client.recv_timeout(Duration::from_millis(50)).await;
+    assert!(
+        matches!(outcome, Err(ConnectionError::Timeout)),
+        "{outcome:?}"
+    );
+    assert!(SystemTime::now().duration_since(started).unwrap() >= Duration::from_millis(50));
+    assert_eq!(client.connection().close_reason(), None);
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use mxp::transport::BufferPool;
+
+const THREADS: usize = 8;
+const ITERATIONS: usize = 20_000;
+
+/// Hammer `pool` from several threads, checking no buffer is ever leased twice at once.
+fn stress(pool: &BufferPool) {
+    let failed = Arc::new(AtomicBool::new(false));
+    let workers: Vec<_> = (0..THREADS)
+        .map(|thread_index| {
+            let pool = pool.clone();
+            let failed = Arc::clone(&failed);
+            thread::spawn(move || {
+                let tag = u8::try_from(thread_index + 1).unwrap();
+                for iteration in 0..ITERATIONS {
+                    let mut held: Vec<_> = (0..1 + iteration % 3).map(|_| pool.acquire()).collect();
+                    for buffer in &mut held {
+                        buffer.as_mut_slice().fill(tag);
+                        buffer.set_len(buffer.capacity());
+                    }
+                    thread::yield_now();
+                    if held
+                        .iter()
+                        .any(|buffer| buffer.as_slice().iter().any(|byte| *byte != tag))
+                    {
+                        failed.store(true, Ordering::Relaxed);
+                    }
+                }
+            })
+        })
+        .collect();
+    for worker in workers {
+        worker.join().expect("worker panicked");
+    }
+    assert!(
+        !failed.load(Ordering::Relaxed),
+        "buffer shared between holders"
+    );
+    assert_eq!(pool.outstanding(), 0);
+    assert!(pool.high_water_mark() <= THREADS * 3);
+}
+
+#[test]
+fn shared_pool_survives_contention() {
+    stress(&BufferPool::new(256, 16));
+}
+
+#[test]
+fn locally_cached_pool_survives_contention() {
+    stress(&BufferPool::new(256, 16).with_local_cache(4));
+}
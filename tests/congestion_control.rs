@@ -0,0 +1,99 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use mxp::transport::{AckOutcome, CongestionAlgorithm, CongestionConfig, SentPacketInfo};
+
+const PACKET_SIZE: usize = 1200;
+const BASE_RTT: Duration = Duration::from_millis(20);
+
+/// Bottleneck link: `capacity` packets per RTT plus a drop-tail queue of `buffer` packets.
+struct Bottleneck {
+    capacity: usize,
+    buffer: usize,
+}
+
+struct RunSummary {
+    elapsed: Duration,
+    delivered: usize,
+    lost: usize,
+    peak_window: usize,
+}
+
+/// Send one window per round trip through the bottleneck and feed the outcome back.
+fn simulate(algorithm: CongestionAlgorithm, link: &Bottleneck, rounds: usize) -> RunSummary {
+    let mut cc = CongestionConfig {
+        algorithm,
+        initial_window: 10 * PACKET_SIZE,
+        min_window: 2 * PACKET_SIZE,
+        ..CongestionConfig::default()
+    }
+    .build();
+
+    let start = UNIX_EPOCH + Duration::from_secs(1_000);
+    let mut now = start;
+    let mut packet_number = 0u64;
+    let mut summary = RunSummary {
+        elapsed: Duration::ZERO,
+        delivered: 0,
+        lost: 0,
+        peak_window: 0,
+    };
+    for _ in 0..rounds {
+        let sent_at: SystemTime = now;
+        let mut packets = Vec::new();
+        while cc.can_send(PACKET_SIZE) {
+            packet_number += 1;
+            cc.on_packet_sent(PACKET_SIZE);
+            packets.push(SentPacketInfo::new(
+                packet_number,
+                sent_at,
+                PACKET_SIZE,
+                true,
+            ));
+        }
+
+        let accepted = packets.len().min(link.capacity + link.buffer);
+        let queued = accepted.saturating_sub(link.capacity);
+        let rtt = BASE_RTT
+            + BASE_RTT * u32::try_from(queued).unwrap() / u32::try_from(link.capacity).unwrap();
+        now += rtt;
+
+        let lost = packets.split_off(accepted);
+        summary.delivered += packets.len();
+        summary.lost += lost.len();
+        cc.on_ack_outcome(
+            &AckOutcome {
+                acknowledged: packets,
+                lost,
+                rtt_sample: Some(rtt),
+                ecn_ce_increase: 0,
+            },
+            now,
+        );
+        summary.peak_window = summary.peak_window.max(cc.window());
+    }
+    summary.elapsed = now.duration_since(start).unwrap();
+    summary
+}
+
+#[test]
+fn bbr_and_cubic_share_a_bottleneck_link() {
+    let link = Bottleneck {
+        capacity: 40,
+        buffer: 20,
+    };
+    let rounds = 400;
+    for algorithm in [CongestionAlgorithm::Bbr, CongestionAlgorithm::Cubic] {
+        let summary = simulate(algorithm, &link, rounds);
+        let link_rounds = summary.elapsed.as_secs_f64() / BASE_RTT.as_secs_f64();
+        let utilization = summary.delivered as f64 / (link.capacity as f64 * link_rounds);
+        let loss_rate = summary.lost as f64 / (summary.delivered + summary.lost) as f64;
+        println!(
+            "{algorithm:?}: utilization {utilization:.2}, loss {loss_rate:.3}, peak window {}",
+            summary.peak_window
+        );
+
+        assert!(utilization > 0.6, "{algorithm:?} underutilizes the link");
+        assert!(utilization <= 1.0 + 1e-9);
+        assert!(loss_rate < 0.05, "{algorithm:?} overruns the queue");
+    }
+}
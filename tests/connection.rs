@@ -0,0 +1,896 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use mxp::protocol::{self, Message, MessageType};
+use mxp::testing::{Latency, LinkConfig, MemorySocket, SimClock, SimNetwork};
+use mxp::transport::{
+    BASE_PLPMTU, Clock, CongestionConfig, Connection, ConnectionClose, ConnectionConfig,
+    ConnectionError, ConnectionId, ConnectionLimits, ConnectionState, ConnectionTable,
+    DatagramSocket, HandshakeError, HandshakeServer, HandshakeTimeoutConfig, IDLE_TIMEOUT_REASON,
+    Initiator, PRIVATE_KEY_LEN, PmtuConfig, PrivateKey, Refusal, StreamError, StreamId, Transport,
+};
+
+/// Simulation step; endpoints act once per step.
+const STEP: Duration = Duration::from_millis(5);
+
+fn client_static() -> PrivateKey {
+    PrivateKey::from_array([0x21; PRIVATE_KEY_LEN])
+}
+
+/// 2001-09-09: fixed, so timers and admission must run off the simulated clock alone.
+fn epoch() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000)
+}
+
+fn server_static() -> PrivateKey {
+    PrivateKey::from_array([0x42; PRIVATE_KEY_LEN])
+}
+
+/// Network whose clock starts at a fixed epoch, far from the wall clock, so any timer or
+/// admission check that read `SystemTime::now()` instead of the passed-in time would misfire.
+fn network(seed: u64, loss_percent: u32) -> SimNetwork {
+    let network = SimNetwork::with_clock(seed, SimClock::new(epoch()));
+    network.set_default_link(LinkConfig {
+        loss_rate: f64::from(loss_percent) / 100.0,
+        latency: Latency::Uniform {
+            min: STEP,
+            max: STEP * 3,
+        },
+        ..LinkConfig::default()
+    });
+    network
+}
+
+struct Peers {
+    client: Connection,
+    client_socket: MemorySocket,
+    server: Option<Connection>,
+    server_socket: MemorySocket,
+    handshake_server: Arc<Mutex<HandshakeServer>>,
+    config: ConnectionConfig,
+    /// Spin bit of each packet from the client, as seen by an observer at the server.
+    client_spins: Vec<(SystemTime, bool)>,
+    /// Arrival time and length of each packet from the client.
+    client_packets: Vec<(SystemTime, usize)>,
+}
+
+impl Peers {
+    fn new(network: &SimNetwork) -> Self {
+        Self::with_config(network, ConnectionConfig::default())
+    }
+
+    /// Peers whose connections both use `config`.
+    fn with_config(network: &SimNetwork, config: ConnectionConfig) -> Self {
+        let (client_socket, server_socket) = network.socket_pair();
+        let initiator = Initiator::new(client_static(), server_static().public_key());
+        Self {
+            client: Connection::connect(config.clone(), initiator, network.clock().now())
+                .expect("connect"),
+            client_socket,
+            server: None,
+            server_socket,
+            handshake_server: Arc::new(Mutex::new(HandshakeServer::new(server_static()))),
+            config,
+            client_spins: Vec::new(),
+            client_packets: Vec::new(),
+        }
+    }
+
+    /// Run timers, deliver datagrams, and flush both connections once.
+    fn step(&mut self, now: SystemTime) {
+        let client_addr = self.client_socket.local_addr().expect("client addr");
+        let server_addr = self.server_socket.local_addr().expect("server addr");
+        let mut buffer = [0u8; 2048];
+
+        while let Ok(meta) = self.server_socket.recv_from(&mut buffer) {
+            let datagram = &buffer[..meta.len];
+            if let Some(spin) = Connection::observed_spin(datagram) {
+                self.client_spins.push((now, spin));
+                self.client_packets.push((now, datagram.len()));
+            }
+            match &mut self.server {
+                Some(server) => {
+                    let _ = server.handle_datagram(datagram, now);
+                }
+                None => {
+                    self.server = Connection::accept(
+                        self.config.clone(),
+                        Arc::clone(&self.handshake_server),
+                        &client_static().public_key(),
+                        meta.addr,
+                        datagram,
+                        now,
+                    )
+                    .ok();
+                }
+            }
+        }
+        while let Ok(meta) = self.client_socket.recv_from(&mut buffer) {
+            let _ = self.client.handle_datagram(&buffer[..meta.len], now);
+        }
+
+        flush(&mut self.client, &self.client_socket, server_addr, now);
+        if let Some(server) = &mut self.server {
+            flush(server, &self.server_socket, client_addr, now);
+        }
+    }
+
+    fn server(&mut self) -> &mut Connection {
+        self.server.as_mut().expect("server accepted")
+    }
+}
+
+fn flush(connection: &mut Connection, socket: &MemorySocket, peer: SocketAddr, now: SystemTime) {
+    if connection
+        .poll_timeout()
+        .is_some_and(|deadline| deadline <= now)
+    {
+        connection.handle_timeout(now).expect("timers");
+    }
+    while let Some(datagram) = connection.poll_transmit(now) {
+        socket.send_to(&datagram, peer).expect("send");
+    }
+}
+
+/// Read everything `connection` has received, appending it per stream.
+fn drain(connection: &mut Connection, received: &mut HashMap<StreamId, Vec<u8>>) {
+    for id in connection.streams().readable_streams() {
+        let data = connection.read(id, usize::MAX).expect("read");
+        received.entry(id).or_default().extend(data);
+    }
+}
+
+#[test]
+fn connections_handshake_and_exchange_stream_data() {
+    let network = network(1, 0);
+    let clock = network.clock().clone();
+    let mut peers = Peers::new(&network);
+
+    let stream = peers.client.open_stream().expect("open");
+    peers.client.send(stream, b"ping").expect("send");
+    peers.client.finish(stream).expect("finish");
+
+    let mut server_received = HashMap::new();
+    let mut client_received = HashMap::new();
+    let mut replied = false;
+    for _ in 0..200 {
+        peers.step(clock.now());
+        if let Some(server) = &mut peers.server {
+            drain(server, &mut server_received);
+            if !replied && server.streams().is_receive_finished(stream) == Ok(true) {
+                server.send(stream, b"pong").expect("reply");
+                server.finish(stream).expect("finish reply");
+                replied = true;
+            }
+        }
+        drain(&mut peers.client, &mut client_received);
+        if peers.client.streams().is_receive_finished(stream) == Ok(true) {
+            break;
+        }
+        clock.advance(STEP);
+    }
+
+    assert!(peers.client.is_established());
+    assert!(peers.server().is_established());
+    assert_eq!(
+        peers
+            .client
+            .negotiated_parameters()
+            .map(|p| p.aead_cipher()),
+        peers
+            .server()
+            .negotiated_parameters()
+            .map(|p| p.aead_cipher())
+    );
+    assert_eq!(server_received[&stream], b"ping");
+    assert_eq!(client_received[&stream], b"pong");
+}
+
+#[test]
+fn connections_transfer_streams_over_lossy_network() {
+    for seed in [3, 17, 99] {
+        let network = network(seed, 5);
+        let clock = network.clock().clone();
+        let mut peers = Peers::new(&network);
+
+        let payloads: Vec<Vec<u8>> = (0..3u8)
+            .map(|idx| (0..40_000u32).map(|byte| (byte as u8) ^ idx).collect())
+            .collect();
+        let mut streams = Vec::new();
+        for payload in &payloads {
+            let stream = peers.client.open_stream().expect("open");
+            peers.client.send(stream, payload).expect("send");
+            peers.client.finish(stream).expect("finish");
+            streams.push(stream);
+        }
+
+        let mut received = HashMap::new();
+        let mut done = false;
+        // Handshake flights retransmit after a second, so allow a few of them.
+        for _ in 0..5_000 {
+            peers.step(clock.now());
+            if let Some(server) = &mut peers.server {
+                drain(server, &mut received);
+            }
+            done = streams.iter().all(|id| {
+                peers.client.streams().is_fully_acked(*id) == Ok(true)
+                    && peers
+                        .server
+                        .as_ref()
+                        .is_some_and(|server| server.streams().is_receive_finished(*id) == Ok(true))
+            });
+            if done {
+                break;
+            }
+            clock.advance(STEP);
+        }
+
+        assert!(done, "seed {seed}: transfer did not complete");
+        for (stream, payload) in streams.iter().zip(&payloads) {
+            assert_eq!(&received[stream], payload, "seed {seed}");
+        }
+        assert!(peers.client.loss().outstanding().next().is_none());
+    }
+}
+
+/// Step until both sides are established.
+fn establish(peers: &mut Peers, clock: &SimClock) {
+    for _ in 0..50 {
+        peers.step(clock.now());
+        if peers
+            .server
+            .as_ref()
+            .is_some_and(Connection::is_established)
+        {
+            return;
+        }
+        clock.advance(STEP);
+    }
+    panic!("handshake did not complete");
+}
+
+#[test]
+fn lost_handshake_flights_are_retransmitted() {
+    let network = network(31, 0);
+    let clock = network.clock().clone();
+    let mut peers = Peers::new(&network);
+    let client_addr = peers.client_socket.local_addr().expect("client addr");
+    let server_addr = peers.server_socket.local_addr().expect("server addr");
+    // Lose the client's first hello and the server's first answer.
+    network.drop_next(client_addr, server_addr, 1);
+    network.drop_next(server_addr, client_addr, 1);
+    let start = clock.now();
+
+    for _ in 0..2_000 {
+        peers.step(clock.now());
+        if peers.client.is_established()
+            && peers
+                .server
+                .as_ref()
+                .is_some_and(Connection::is_established)
+        {
+            break;
+        }
+        clock.advance(STEP);
+    }
+
+    assert!(peers.client.is_established());
+    assert!(peers.server().is_established());
+    let elapsed = clock
+        .now()
+        .duration_since(start)
+        .expect("time moves forward");
+    assert!(elapsed >= HandshakeTimeoutConfig::default().retransmit_timeout);
+}
+
+#[test]
+fn handshake_fails_after_retransmits_are_exhausted() {
+    let network = network(32, 0);
+    let clock = network.clock().clone();
+    let (client_socket, server_socket) = network.socket_pair();
+    let server_addr = server_socket.local_addr().expect("server addr");
+    network.drop_next(
+        client_socket.local_addr().expect("client addr"),
+        server_addr,
+        u64::MAX,
+    );
+    let timeout = Duration::from_millis(100);
+    let initiator = Initiator::new(client_static(), server_static().public_key()).with_timeouts(
+        HandshakeTimeoutConfig {
+            retransmit_timeout: timeout,
+            max_retransmits: 2,
+        },
+    );
+    let mut client =
+        Connection::connect(ConnectionConfig::default(), initiator, clock.now()).expect("connect");
+
+    let mut sent = Vec::new();
+    let mut failed_at = None;
+    for _ in 0..1_000 {
+        let now = clock.now();
+        if client
+            .poll_timeout()
+            .is_some_and(|deadline| deadline <= now)
+        {
+            match client.handle_timeout(now) {
+                Ok(()) => {}
+                Err(ConnectionError::Handshake(HandshakeError::Timeout)) => {
+                    failed_at = Some(now);
+                    break;
+                }
+                Err(err) => panic!("unexpected error: {err}"),
+            }
+        }
+        while let Some(datagram) = client.poll_transmit(now) {
+            client_socket.send_to(&datagram, server_addr).expect("send");
+            sent.push(now);
+        }
+        clock.advance(STEP);
+    }
+
+    // The hello and two retransmissions, each wait twice the one before.
+    let failed_at = failed_at.expect("handshake timed out");
+    assert_eq!(sent.len(), 3);
+    let waits = [
+        sent[1].duration_since(sent[0]).expect("ordered"),
+        sent[2].duration_since(sent[1]).expect("ordered"),
+        failed_at.duration_since(sent[2]).expect("ordered"),
+    ];
+    for (wait, expected) in waits.into_iter().zip([timeout, timeout * 2, timeout * 4]) {
+        assert!(
+            wait >= expected && wait <= expected + STEP,
+            "waited {wait:?}, expected {expected:?}"
+        );
+    }
+    assert!(client.poll_timeout().is_none());
+    assert!(!client.is_established());
+}
+
+#[test]
+fn flagged_event_is_acked_without_the_application() {
+    let network = network(11, 0);
+    let clock = network.clock().clone();
+    let mut peers = Peers::new(&network);
+    establish(&mut peers, &clock);
+
+    let event = Message::new(MessageType::Event, b"deployed".to_vec());
+    let message_id = peers
+        .client
+        .send_acked(event, Duration::from_secs(5), clock.now())
+        .expect("send");
+    let call = Message::new(MessageType::Call, b"status".to_vec());
+    let call_id = peers
+        .client
+        .send_acked(call, Duration::from_millis(200), clock.now())
+        .expect("send call");
+
+    let mut acked = false;
+    for _ in 0..100 {
+        clock.advance(STEP);
+        peers.step(clock.now());
+        if peers
+            .client
+            .poll_ack(message_id, clock.now())
+            .expect("ack pending or received")
+        {
+            acked = true;
+            break;
+        }
+    }
+    assert!(acked);
+
+    // The server saw both messages, but only the event was acknowledged for it.
+    let received: Vec<Message> = std::iter::from_fn(|| peers.server().recv_message()).collect();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0].message_id(), message_id);
+    assert_eq!(received[0].payload().as_ref(), b"deployed");
+    assert!(received[0].flags().requires_ack());
+    assert_eq!(received[1].message_type(), Some(MessageType::Call));
+    assert!(peers.client.recv_message().is_none());
+
+    // A repeated ack for the same message is ignored rather than delivered.
+    let ack = protocol::delivery_ack(&received[0]).expect("ack");
+    peers.server().send_message(&ack).expect("duplicate ack");
+    for _ in 0..10 {
+        clock.advance(STEP);
+        peers.step(clock.now());
+    }
+    assert!(peers.client.recv_message().is_none());
+    assert!(matches!(
+        peers.client.poll_ack(message_id, clock.now()),
+        Err(ConnectionError::NotAwaitingAck(id)) if id == message_id
+    ));
+
+    clock.advance(Duration::from_millis(200));
+    assert!(matches!(
+        peers.client.poll_ack(call_id, clock.now()),
+        Err(ConnectionError::Message(protocol::Error::DeadlineExceeded { message_id })) if message_id == call_id
+    ));
+}
+
+#[test]
+fn send_acked_times_out_when_the_peer_never_acks() {
+    let network = network(13, 0);
+    let clock = network.clock().clone();
+    let mut peers = Peers::new(&network);
+    establish(&mut peers, &clock);
+
+    // Cut the server off so the event never arrives and no ack comes back.
+    let server_addr = peers.server_socket.local_addr().expect("server addr");
+    network.set_link(
+        peers.client_socket.local_addr().expect("client addr"),
+        server_addr,
+        LinkConfig {
+            loss_rate: 1.0,
+            ..LinkConfig::default()
+        },
+    );
+
+    let event = Message::new(MessageType::Event, b"lost".to_vec());
+    let timeout = Duration::from_millis(100);
+    let message_id = peers
+        .client
+        .send_acked(event, timeout, clock.now())
+        .expect("send");
+
+    let started = clock.now();
+    let outcome = loop {
+        clock.advance(STEP);
+        peers.step(clock.now());
+        match peers.client.poll_ack(message_id, clock.now()) {
+            Ok(false) => {}
+            outcome => break outcome,
+        }
+    };
+    assert!(matches!(
+        outcome,
+        Err(ConnectionError::Message(
+            protocol::Error::DeadlineExceeded { .. }
+        ))
+    ));
+    assert!(
+        clock
+            .now()
+            .duration_since(started)
+            .expect("time moves forward")
+            >= timeout
+    );
+    assert!(peers.server().recv_message().is_none());
+}
+
+#[test]
+fn closing_one_side_is_observed_by_the_peer() {
+    let network = network(7, 0);
+    let clock = network.clock().clone();
+    let mut peers = Peers::new(&network);
+
+    let stream = peers.client.open_stream().expect("open");
+    peers.client.send(stream, b"hello").expect("send");
+    establish(&mut peers, &clock);
+    assert_eq!(peers.client.state(), ConnectionState::Established);
+
+    peers.client.close(42, "maintenance", clock.now());
+    assert_eq!(peers.client.state(), ConnectionState::Draining);
+    assert!(matches!(
+        peers.client.open_stream(),
+        Err(StreamError::Draining)
+    ));
+
+    for _ in 0..1_000 {
+        clock.advance(STEP);
+        peers.step(clock.now());
+        if peers.client.state() == ConnectionState::Closed
+            && peers.server().state() == ConnectionState::Closed
+        {
+            break;
+        }
+    }
+
+    assert_eq!(peers.client.state(), ConnectionState::Closed);
+    assert_eq!(peers.server().state(), ConnectionState::Closed);
+    assert_eq!(
+        peers.server().close_reason(),
+        Some(&ConnectionClose {
+            error_code: 42,
+            reason: "maintenance".to_owned(),
+            remote: true,
+        })
+    );
+    assert!(
+        peers
+            .client
+            .close_reason()
+            .is_some_and(|close| !close.remote)
+    );
+    assert!(peers.server().poll_transmit(clock.now()).is_none());
+}
+
+#[test]
+fn idle_connections_time_out() {
+    let network = network(21, 0);
+    let clock = network.clock().clone();
+    let idle_timeout = Duration::from_secs(2);
+    let mut peers = Peers::with_config(
+        &network,
+        ConnectionConfig {
+            max_idle_timeout: Some(idle_timeout),
+            ..ConnectionConfig::default()
+        },
+    );
+    establish(&mut peers, &clock);
+    let last_activity = clock.now();
+
+    for _ in 0..2_000 {
+        clock.advance(STEP);
+        peers.step(clock.now());
+        if peers.client.state() == ConnectionState::Closed
+            && peers.server().state() == ConnectionState::Closed
+        {
+            break;
+        }
+    }
+
+    assert_eq!(peers.client.state(), ConnectionState::Closed);
+    assert_eq!(peers.server().state(), ConnectionState::Closed);
+    assert!(
+        clock
+            .now()
+            .duration_since(last_activity)
+            .expect("time moves forward")
+            >= idle_timeout
+    );
+    for connection in [&peers.client, peers.server.as_ref().expect("server")] {
+        let close = connection.close_reason().expect("closed by the idle timer");
+        assert_eq!(close.reason, IDLE_TIMEOUT_REASON);
+        assert!(!close.remote);
+    }
+}
+
+#[test]
+fn keepalives_hold_an_idle_connection_open() {
+    let network = network(23, 0);
+    let clock = network.clock().clone();
+    let mut peers = Peers::with_config(
+        &network,
+        ConnectionConfig {
+            max_idle_timeout: Some(Duration::from_secs(2)),
+            keep_alive_interval: Some(Duration::from_millis(500)),
+            ..ConnectionConfig::default()
+        },
+    );
+    establish(&mut peers, &clock);
+
+    // Five idle timeouts pass without any application traffic.
+    for _ in 0..2_000 {
+        clock.advance(STEP);
+        peers.step(clock.now());
+    }
+
+    assert_eq!(peers.client.state(), ConnectionState::Established);
+    assert_eq!(peers.server().state(), ConnectionState::Established);
+    assert!(peers.client.close_reason().is_none());
+}
+
+#[test]
+fn clients_sharing_a_server_socket_keep_their_streams_apart() {
+    serve_two_clients([1, 2]);
+}
+
+#[test]
+fn clients_choosing_the_same_connection_id_are_routed_by_address() {
+    serve_two_clients([7, 7]);
+}
+
+/// Two clients with connection IDs `ids` exchange a stream each with one server socket;
+/// every datagram the server receives, forged ones included, must route without an error.
+fn serve_two_clients(ids: [u64; 2]) {
+    let network = network(9, 0);
+    let clock = network.clock().clone();
+    let loopback = SocketAddr::from(([127, 0, 0, 1], 0));
+    let server = Transport::default()
+        .bind_socket(network.bind(loopback).expect("bind server"))
+        .expect("server endpoint");
+    let server_addr = server.local_addr().expect("server addr");
+
+    let keys = [
+        PrivateKey::from_array([0x21; PRIVATE_KEY_LEN]),
+        PrivateKey::from_array([0x31; PRIVATE_KEY_LEN]),
+    ];
+    let mut clients: Vec<(Connection, MemorySocket)> = keys
+        .iter()
+        .zip(ids)
+        .map(|(key, id)| {
+            let config = ConnectionConfig {
+                connection_id: ConnectionId::from_u64(id),
+                ..ConnectionConfig::default()
+            };
+            let initiator = Initiator::new(key.clone(), server_static().public_key());
+            let connection =
+                Connection::connect(config, initiator, network.clock().now()).expect("connect");
+            (connection, network.bind(loopback).expect("bind client"))
+        })
+        .collect();
+    let known: HashMap<SocketAddr, _> = clients
+        .iter()
+        .zip(&keys)
+        .map(|((_, socket), key)| (socket.local_addr().unwrap(), key.public_key()))
+        .collect();
+    let mut table = ConnectionTable::new(
+        ConnectionConfig::default(),
+        Arc::new(Mutex::new(HandshakeServer::new(server_static()))),
+        move |addr| known.get(&addr).cloned(),
+    );
+
+    let payloads: [&[u8]; 2] = [b"from the first client", b"from the second client"];
+    for ((client, _), payload) in clients.iter_mut().zip(payloads) {
+        let stream = client.open_stream().expect("open");
+        client.send(stream, payload).expect("send");
+        client.finish(stream).expect("finish");
+    }
+
+    let mut buffer = server.acquire_buffer();
+    let mut received: HashMap<SocketAddr, HashMap<StreamId, Vec<u8>>> = HashMap::new();
+    for _ in 0..200 {
+        let now = clock.now();
+        loop {
+            match server.accept_datagram(&mut table, &mut buffer, now) {
+                Ok(_) => {}
+                Err(ConnectionError::Socket(_)) => break,
+                Err(err) => panic!("server connection failed: {err}"),
+            }
+        }
+        let handles: Vec<_> = table.handles().collect();
+        for handle in handles {
+            let peer = table.peer_addr(handle).expect("peer");
+            drain(
+                table.get_mut(handle).expect("connection"),
+                received.entry(peer).or_default(),
+            );
+        }
+        table.handle_timeout(now);
+        server.flush_connections(&mut table, now).expect("flush");
+
+        let mut datagram = [0u8; 2048];
+        for (client, socket) in &mut clients {
+            while let Ok(meta) = socket.recv_from(&mut datagram) {
+                let _ = client.handle_datagram(&datagram[..meta.len], now);
+            }
+            flush(client, socket, server_addr, now);
+        }
+        if received.values().map(HashMap::len).sum::<usize>() == 2
+            && received
+                .values()
+                .flat_map(HashMap::values)
+                .all(|data| payloads.contains(&data.as_slice()))
+        {
+            break;
+        }
+        clock.advance(STEP);
+    }
+
+    assert_eq!(table.len(), 2);
+    for ((client, socket), payload) in clients.iter().zip(payloads) {
+        assert!(client.is_established());
+        let streams = &received[&socket.local_addr().unwrap()];
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams.values().next().unwrap(), payload);
+    }
+
+    // A tampered packet carrying a known ID is dropped, from a stranger or the client.
+    let (client, socket) = &mut clients[1];
+    client.ping();
+    let mut forged = client.poll_transmit(clock.now()).expect("ping packet");
+    *forged.last_mut().unwrap() ^= 0x01;
+    let stranger = SocketAddr::from(([192, 0, 2, 1], 4000));
+    for from in [stranger, socket.local_addr().unwrap()] {
+        assert!(matches!(
+            table.handle_datagram(&forged, from, clock.now()),
+            Ok(None)
+        ));
+    }
+    assert_eq!(table.len(), 2);
+}
+
+#[test]
+fn refused_hellos_are_answered_through_the_table() {
+    let now = epoch();
+    let server = HandshakeServer::new(server_static()).with_admission(ConnectionLimits {
+        max_connections: 0,
+        ..ConnectionLimits::default()
+    });
+    let mut table = ConnectionTable::new(
+        ConnectionConfig::default(),
+        Arc::new(Mutex::new(server)),
+        |_| Some(client_static().public_key()),
+    );
+    let mut client = Connection::connect(
+        ConnectionConfig::default(),
+        Initiator::new(client_static(), server_static().public_key()),
+        now,
+    )
+    .expect("connect");
+    let hello = client.poll_transmit(now).expect("hello");
+    let client_addr = SocketAddr::from(([127, 0, 0, 1], 5000));
+
+    assert!(matches!(
+        table.handle_datagram(&hello, client_addr, now),
+        Ok(None)
+    ));
+    assert!(table.is_empty());
+    let (to, reply) = table.poll_transmit(now).expect("refusal");
+    assert_eq!(to, client_addr);
+    assert!(table.poll_transmit(now).is_none());
+    assert!(matches!(
+        client.handle_datagram(&reply, now),
+        Err(ConnectionError::Handshake(HandshakeError::Refused(
+            Refusal::ServerFull
+        )))
+    ));
+}
+
+#[test]
+fn spin_bit_flips_once_per_round_trip() {
+    let network = SimNetwork::with_clock(29, SimClock::new(epoch()));
+    let one_way = STEP * 4;
+    network.set_default_link(LinkConfig {
+        latency: Latency::Fixed(one_way),
+        ..LinkConfig::default()
+    });
+    let clock = network.clock().clone();
+    let mut peers = Peers::new(&network);
+    establish(&mut peers, &clock);
+
+    // Both sides send every step, so each reflects the spin bit as soon as it arrives.
+    let stream = peers.client.open_stream().expect("open");
+    for _ in 0..100 {
+        clock.advance(STEP);
+        peers.client.send(stream, b"tick").expect("client send");
+        if peers
+            .server()
+            .streams()
+            .readable_streams()
+            .contains(&stream)
+        {
+            peers.server().read(stream, usize::MAX).expect("read");
+            peers.server().send(stream, b"tock").expect("server send");
+        }
+        peers.step(clock.now());
+    }
+
+    let flips: Vec<SystemTime> = peers
+        .client_spins
+        .windows(2)
+        .filter(|pair| pair[0].1 != pair[1].1)
+        .map(|pair| pair[1].0)
+        .collect();
+    let periods: Vec<Duration> = flips
+        .windows(2)
+        .map(|pair| pair[1].duration_since(pair[0]).expect("ordered"))
+        .collect();
+    assert!(periods.len() >= 5, "spin bit barely moved: {periods:?}");
+    let rtt = one_way * 2;
+    assert!(
+        periods.iter().all(|&period| period == rtt),
+        "expected a {rtt:?} period, got {periods:?}"
+    );
+}
+
+#[test]
+fn packets_leave_at_the_pacing_rate() {
+    let network = SimNetwork::with_clock(37, SimClock::new(epoch()));
+    network.set_default_link(LinkConfig {
+        latency: Latency::Fixed(STEP * 2),
+        ..LinkConfig::default()
+    });
+    let clock = network.clock().clone();
+    // A fixed rate and a window that never binds leave the pacer in charge.
+    let rate = 120_000.0;
+    let config = ConnectionConfig {
+        congestion: CongestionConfig {
+            initial_window: 1 << 20,
+            min_pacing_rate: rate,
+            max_pacing_rate: rate,
+            ..CongestionConfig::default()
+        },
+        ..ConnectionConfig::default()
+    };
+    let mut peers = Peers::with_config(&network, config);
+    establish(&mut peers, &clock);
+
+    let stream = peers.client.open_stream().expect("open");
+    peers.client.send(stream, &[7; 60_000]).expect("send");
+    peers.client.finish(stream).expect("finish");
+    peers.client_packets.clear();
+    for _ in 0..10_000 {
+        peers.step(clock.now());
+        if peers.client.streams().is_fully_acked(stream) == Ok(true) {
+            break;
+        }
+        // Wake exactly when something is due, as an event-driven driver would.
+        let next = [
+            peers.client.poll_timeout(),
+            peers.server().poll_timeout(),
+            network.next_delivery(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .expect("work pending");
+        let wait = next.duration_since(clock.now()).unwrap_or_default();
+        clock.advance(wait.max(Duration::from_micros(100)));
+    }
+    assert_eq!(peers.client.streams().is_fully_acked(stream), Ok(true));
+
+    // The first burst goes back to back; after it every full packet waits for the tokens
+    // the one before it spent, one per byte after the datagram type byte.
+    let packets = &peers.client_packets;
+    let burst = peers.config.pacing.as_ref().expect("pacing").burst_packets;
+    assert!(packets[..burst].iter().all(|(at, _)| *at == packets[0].0));
+    let mut paced = 0;
+    for pair in packets[burst..].windows(2) {
+        let [(sent, len), (next, next_len)] = [pair[0], pair[1]];
+        if len < 1_000 || next_len < 1_000 {
+            continue;
+        }
+        let tokens = f64::from(u32::try_from(len - 1).expect("datagram length"));
+        let expected = Duration::from_secs_f64(tokens / rate);
+        let measured = next.duration_since(sent).expect("ordered");
+        assert!(
+            measured >= expected.mul_f64(0.99) && measured <= expected.mul_f64(1.01),
+            "gap {measured:?}, expected about {expected:?}"
+        );
+        paced += 1;
+    }
+    assert!(paced >= 40, "only {paced} paced packets");
+}
+
+#[test]
+fn path_mtu_discovery_grows_datagrams_up_to_the_link_limit() {
+    let network = network(43, 0);
+    let link_mtu = 1_400;
+    network.set_default_link(LinkConfig {
+        latency: Latency::Fixed(STEP),
+        max_datagram_size: Some(link_mtu),
+        ..LinkConfig::default()
+    });
+    let clock = network.clock().clone();
+    let config = ConnectionConfig {
+        pmtu: Some(PmtuConfig::default()),
+        ..ConnectionConfig::default()
+    };
+    let mut peers = Peers::with_config(&network, config);
+    establish(&mut peers, &clock);
+    assert_eq!(peers.client.current_mtu(), BASE_PLPMTU);
+
+    // Probes above the link limit vanish; steady data lets their loss be detected.
+    let payload: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    let stream = peers.client.open_stream().expect("open");
+    peers.client.send(stream, &payload).expect("send");
+    peers.client.finish(stream).expect("finish");
+    let mut received = HashMap::new();
+    for _ in 0..2_000 {
+        peers.step(clock.now());
+        drain(peers.server(), &mut received);
+        if peers.server().streams().is_receive_finished(stream) == Ok(true) {
+            break;
+        }
+        clock.advance(STEP);
+    }
+
+    assert_eq!(received[&stream], payload);
+    let mtu = peers.client.current_mtu();
+    assert!(mtu <= link_mtu && mtu > link_mtu - 8, "settled on {mtu}");
+    // Stream data fills the grown datagrams, not just the probe that confirmed the size.
+    let grown = peers
+        .client_packets
+        .iter()
+        .filter(|(_, len)| *len > BASE_PLPMTU)
+        .count();
+    assert!(
+        grown > 10,
+        "only {grown} datagrams above {BASE_PLPMTU} bytes"
+    );
+    assert!(peers.client_packets.iter().all(|(_, len)| *len <= mtu));
+}
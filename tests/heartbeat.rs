@@ -0,0 +1,49 @@
+use std::time::Duration;
+
+use mxp::protocol::{HeartbeatConfig, HeartbeatHandle, Message, answer_heartbeats};
+use tokio::sync::mpsc;
+
+fn config() -> HeartbeatConfig {
+    HeartbeatConfig {
+        interval: Duration::from_secs(1),
+        timeout: Duration::from_millis(500),
+        max_misses: 2,
+    }
+}
+
+#[tokio::test(start_paused = true)]
+async fn answered_heartbeats_report_rtt() {
+    let (to_peer, peer_inbound) = mpsc::channel::<Message>(8);
+    let (peer_outbound, mut from_peer) = mpsc::channel::<Message>(8);
+    let _responder = answer_heartbeats(peer_inbound, peer_outbound);
+    let heartbeat = HeartbeatHandle::start(config(), to_peer);
+
+    for _ in 0..3 {
+        let ack = from_peer.recv().await.expect("ack");
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(heartbeat.on_message(&ack));
+    }
+    assert!(heartbeat.is_healthy());
+    assert_eq!(heartbeat.last_rtt(), Some(Duration::from_millis(30)));
+}
+
+#[tokio::test(start_paused = true)]
+async fn missed_heartbeats_flip_health() {
+    let (to_peer, mut peer_inbound) = mpsc::channel::<Message>(8);
+    let heartbeat = HeartbeatHandle::start(config(), to_peer);
+    let mut health = heartbeat.health();
+
+    // Drop the first two heartbeats on the floor.
+    peer_inbound.recv().await.expect("first heartbeat");
+    peer_inbound.recv().await.expect("second heartbeat");
+    health.changed().await.expect("health update");
+    assert!(!*health.borrow_and_update());
+    assert!(!heartbeat.is_healthy());
+
+    // Answering the next one restores health.
+    let third = peer_inbound.recv().await.expect("third heartbeat");
+    assert!(heartbeat.on_message(&mxp::protocol::heartbeat_ack(&third).expect("ack")));
+    health.changed().await.expect("health update");
+    assert!(*health.borrow());
+    assert_eq!(heartbeat.last_rtt(), Some(Duration::ZERO));
+}
@@ -1,79 +1,40 @@
 use std::collections::{HashMap, VecDeque};
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
 
+use mxp::testing::{Latency, LinkConfig, MemorySocket, SimNetwork};
 use mxp::transport::{
-    AEAD_KEY_LEN, AEAD_TAG_LEN, AckFrame, AeadKey, AmplificationConfig, AntiAmplificationGuard,
-    CongestionConfig, CongestionController, DEFAULT_MAX_ACK_RANGES, HEADER_PROTECTION_KEY_LEN,
-    HEADER_SIZE, HeaderProtectionKey, LossConfig, LossManager, PacketCipher, PacketFlags,
-    ReceiveHistory, SessionKeys, TransportError,
+    AEAD_KEY_LEN, AEAD_NONCE_LEN, AEAD_TAG_LEN, AckFrame, AeadKey, AeadNonce, AmplificationConfig,
+    AntiAmplificationGuard, Clock, CongestionAlgorithm, CongestionConfig, CongestionControl,
+    ConnectionId, DEFAULT_MAX_ACK_RANGES, DatagramConfig, DatagramQueue, DatagramSocket,
+    HEADER_PROTECTION_KEY_LEN, HeaderProtectionKey, LossConfig, LossManager, MAX_HEADER_SIZE,
+    Pacer, PacketCipher, PacketFlags, ReceiveHistory, SessionKeys, Transport, TransportError,
 };
-
-#[derive(Default)]
-struct Lcg(u64);
-
-impl Lcg {
-    fn next(&mut self) -> u64 {
-        const A: u64 = 6364136223846793005;
-        const C: u64 = 1442695040888963407;
-        self.0 = self.0.wrapping_mul(A).wrapping_add(C);
-        self.0
-    }
-}
-
-struct SimPacket {
-    to: usize,
-    bytes: Vec<u8>,
-    deliver_at: SystemTime,
-}
-
-struct SimLink {
-    in_flight: Vec<SimPacket>,
-    rng: Lcg,
-    drop_rate: u64,
-    delay_steps: u64,
-    step_duration: Duration,
-}
-
-impl SimLink {
-    fn new(seed: u64, drop_rate: u64, delay_steps: u64, step_duration: Duration) -> Self {
-        Self {
-            in_flight: Vec::new(),
-            rng: Lcg(seed),
-            drop_rate,
-            delay_steps,
-            step_duration,
-        }
-    }
-
-    fn send(&mut self, now: SystemTime, packet: SimPacket) {
-        if self.rng.next() % 100 < self.drop_rate {
-            return;
+#[cfg(feature = "qlog")]
+use mxp::transport::{JsonLinesLogger, SharedEventLogger};
+#[cfg(feature = "qlog")]
+use std::sync::Arc;
+
+/// Simulation step; endpoints act once per step.
+const STEP: Duration = Duration::from_millis(5);
+
+/// Network whose links lose `loss_percent` of datagrams and take one to `delay_steps` steps.
+fn lossy_network(seed: u64, loss_percent: u32, delay_steps: u32) -> SimNetwork {
+    let network = SimNetwork::new(seed);
+    let latency = if delay_steps <= 1 {
+        Latency::Fixed(STEP)
+    } else {
+        Latency::Uniform {
+            min: STEP,
+            max: STEP * delay_steps,
         }
-        let jitter = (self.rng.next() % self.delay_steps.max(1)) + 1;
-        let mut packet = packet;
-        packet.deliver_at = now + self.step_duration * (jitter as u32);
-        self.in_flight.push(packet);
-    }
-
-    fn deliver<F>(&mut self, now: SystemTime, mut handler: F)
-    where
-        F: FnMut(usize, Vec<u8>),
-    {
-        let mut ready = Vec::new();
-        let mut remaining = Vec::new();
-        for packet in self.in_flight.drain(..) {
-            if packet.deliver_at <= now {
-                ready.push(packet);
-            } else {
-                remaining.push(packet);
-            }
-        }
-        self.in_flight = remaining;
-        ready.sort_by_key(|_| self.rng.next());
-        for packet in ready {
-            handler(packet.to, packet.bytes);
-        }
-    }
+    };
+    network.set_default_link(LinkConfig {
+        loss_rate: f64::from(loss_percent) / 100.0,
+        latency,
+        ..LinkConfig::default()
+    });
+    network
 }
 
 #[derive(Clone)]
@@ -86,31 +47,73 @@ struct Endpoint {
     cipher: PacketCipher,
     recv_history: ReceiveHistory,
     loss: LossManager,
-    cc: CongestionController,
+    cc: Box<dyn CongestionControl>,
     amp: AntiAmplificationGuard,
+    pacer: Pacer,
+    /// Pacing timer: no ack-eliciting sends before this time.
+    next_send: Option<SystemTime>,
+    /// Send time and size of every ack-eliciting packet.
+    sent_log: Vec<(SystemTime, usize)>,
     outbound: VecDeque<OutboundPacket>,
     outstanding: HashMap<u64, OutboundPacket>,
+    datagrams: DatagramQueue,
     received: Vec<Vec<u8>>,
-    conn_id: u64,
+    received_datagrams: Vec<Vec<u8>>,
+    conn_id: ConnectionId,
+    socket: MemorySocket,
+    peer: Option<SocketAddr>,
 }
 
 impl Endpoint {
-    fn new(keys: SessionKeys, conn_id: u64) -> Self {
+    fn new(
+        network: &SimNetwork,
+        keys: SessionKeys,
+        conn_id: u64,
+        congestion: CongestionConfig,
+    ) -> Self {
         let mut amp = AntiAmplificationGuard::new(AmplificationConfig::default());
         amp.mark_verified();
         Self {
             cipher: PacketCipher::new(keys),
             recv_history: ReceiveHistory::new(DEFAULT_MAX_ACK_RANGES, Duration::from_millis(0)),
             loss: LossManager::new(LossConfig::default()),
-            cc: CongestionController::new(CongestionConfig::default()),
+            cc: congestion.build(),
             amp,
+            pacer: Pacer::default(),
+            next_send: None,
+            sent_log: Vec::new(),
             outbound: VecDeque::new(),
             outstanding: HashMap::new(),
+            datagrams: DatagramQueue::new(DatagramConfig::default()),
             received: Vec::new(),
-            conn_id,
+            received_datagrams: Vec::new(),
+            conn_id: ConnectionId::from_u64(conn_id),
+            socket: network
+                .bind("127.0.0.1:0".parse().expect("addr"))
+                .expect("bind"),
+            peer: None,
         }
     }
 
+    fn addr(&self) -> SocketAddr {
+        self.socket.local_addr().expect("local addr")
+    }
+
+    #[cfg(feature = "qlog")]
+    fn with_event_logger(
+        network: &SimNetwork,
+        keys: SessionKeys,
+        conn_id: u64,
+        congestion: CongestionConfig,
+        logger: SharedEventLogger,
+    ) -> Self {
+        let mut endpoint = Self::new(network, keys.clone(), conn_id, congestion.clone());
+        endpoint.cipher = PacketCipher::with_event_logger(keys, logger.clone());
+        endpoint.loss = LossManager::with_event_logger(LossConfig::default(), logger.clone());
+        endpoint.cc = congestion.build_with_event_logger(logger);
+        endpoint
+    }
+
     fn enqueue_message(&mut self, data: Vec<u8>) {
         self.outbound.push_back(OutboundPacket {
             payload: into_data_payload(data),
@@ -118,6 +121,16 @@ impl Endpoint {
         });
     }
 
+    /// Process every datagram the network has delivered by `now`.
+    fn receive_all(&mut self, now: SystemTime) {
+        let mut buffer = [0u8; 2048];
+        while let Ok(meta) = self.socket.recv_from(&mut buffer) {
+            if let Some(ack_pkt) = self.on_receive(now, buffer[..meta.len].to_vec()) {
+                self.outbound.push_back(ack_pkt);
+            }
+        }
+    }
+
     fn on_receive(&mut self, now: SystemTime, bytes: Vec<u8>) -> Option<OutboundPacket> {
         self.amp.on_receive(bytes.len());
         let packet = match self.cipher.open(&bytes) {
@@ -127,7 +140,7 @@ impl Endpoint {
         };
         let payload = packet.payload();
         match payload.first().copied() {
-            Some(0) => self.handle_data(now, payload, packet.header().packet_number()),
+            Some(0 | 2) => self.handle_data(now, payload, packet.header().packet_number()),
             Some(1) => self.handle_ack(now, payload),
             _ => None,
         }
@@ -139,7 +152,11 @@ impl Endpoint {
         payload: &[u8],
         packet_number: u64,
     ) -> Option<OutboundPacket> {
-        self.received.push(payload[1..].to_vec());
+        if payload[0] == 2 {
+            self.received_datagrams.push(payload[1..].to_vec());
+        } else {
+            self.received.push(payload[1..].to_vec());
+        }
         let immediate = self.recv_history.record(packet_number, true, now);
         if immediate {
             if let Some(frame) = self.recv_history.build_frame(now).unwrap() {
@@ -165,16 +182,19 @@ impl Endpoint {
                 self.outbound.push_front(pkt.clone());
             }
         }
+        self.datagrams.on_ack_outcome(&outcome);
         self.cc.on_ack_outcome(&outcome, now);
         None
     }
 
-    fn tick(&mut self, now: SystemTime, link: &mut SimLink, peer: usize) {
-        let mut inflight: usize = self.loss.outstanding().map(|pkt| pkt.size()).sum();
-        let window = self.cc.window();
+    fn tick(&mut self, now: SystemTime) {
+        self.pacer.set_rate(self.cc.pacing_rate(), now);
+        let paced = self.next_send.is_some_and(|at| now < at);
 
         while let Some(packet) = self.outbound.front().cloned() {
-            if packet.ack_eliciting && inflight >= window {
+            if packet.ack_eliciting
+                && (paced || self.cc.available_window() == 0 || self.pace(now, &packet.payload))
+            {
                 break;
             }
             let send_len = packet.payload.len();
@@ -182,42 +202,73 @@ impl Endpoint {
                 break;
             }
 
-            let mut buffer = vec![0u8; HEADER_SIZE + send_len + AEAD_TAG_LEN];
-            let flags = if packet.ack_eliciting {
-                PacketFlags::from_bits(PacketFlags::ACK_ELICITING)
-            } else {
-                PacketFlags::from_bits(PacketFlags::ACK)
-            };
-            let (pn, len) = self
-                .cipher
-                .seal_into(self.conn_id, flags, &packet.payload, &mut buffer)
-                .expect("seal");
-            buffer.truncate(len);
-
+            let (pn, _) = self.transmit(now, &packet.payload, packet.ack_eliciting);
             if packet.ack_eliciting {
-                self.loss.on_packet_sent(pn, now, len, true);
-                self.cc.on_packet_sent(len);
-                inflight = inflight.saturating_add(len);
                 if let Some(stored) = self.outstanding.insert(pn, packet.clone()) {
                     self.outbound.push_front(stored);
                 }
             }
-
             self.outbound.pop_front();
-            let sim_packet = SimPacket {
-                to: peer,
-                bytes: buffer,
-                deliver_at: now,
+        }
+
+        while !paced && self.cc.available_window() > 0 {
+            if self.pace(now, &[0; DATAGRAM_PACING_ESTIMATE]) {
+                break;
+            }
+            let Some(data) = self.datagrams.dequeue_with_guard(&mut self.amp) else {
+                break;
             };
-            link.send(now, sim_packet);
+            let mut payload = vec![2u8];
+            payload.extend_from_slice(&data);
+            let (pn, _) = self.transmit(now, &payload, true);
+            self.datagrams.on_packet_sent(pn);
+        }
+    }
 
-            if !packet.ack_eliciting {
-                continue;
+    /// Arm the pacing timer if `payload` cannot leave at `now`.
+    fn pace(&mut self, now: SystemTime, payload: &[u8]) -> bool {
+        let len = MAX_HEADER_SIZE + payload.len() + AEAD_TAG_LEN;
+        match self.pacer.delay_until_next_send(len, now) {
+            Some(delay) => {
+                self.next_send = Some(now + delay);
+                true
+            }
+            None => {
+                self.next_send = None;
+                false
             }
         }
     }
+
+    /// Seal and send one packet, registering ack-eliciting ones with loss and congestion state.
+    fn transmit(&mut self, now: SystemTime, payload: &[u8], ack_eliciting: bool) -> (u64, usize) {
+        let mut buffer = vec![0u8; MAX_HEADER_SIZE + payload.len() + AEAD_TAG_LEN];
+        let flags = if ack_eliciting {
+            PacketFlags::from_bits(PacketFlags::ACK_ELICITING)
+        } else {
+            PacketFlags::from_bits(PacketFlags::ACK)
+        };
+        let (pn, len) = self
+            .cipher
+            .seal_into(&self.conn_id, flags, payload, &mut buffer)
+            .expect("seal");
+        buffer.truncate(len);
+
+        if ack_eliciting {
+            self.loss.on_packet_sent(pn, now, len, true);
+            self.cc.on_packet_sent(len);
+            self.pacer.on_packet_sent(len, now);
+            self.sent_log.push((now, len));
+        }
+        let peer = self.peer.expect("peer set");
+        self.socket.send_to(&buffer, peer).expect("send");
+        (pn, len)
+    }
 }
 
+/// Payload size assumed when pacing a datagram before it is dequeued.
+const DATAGRAM_PACING_ESTIMATE: usize = 64;
+
 fn into_data_payload(mut data: Vec<u8>) -> Vec<u8> {
     let mut payload = Vec::with_capacity(data.len() + 1);
     payload.push(0);
@@ -231,49 +282,71 @@ fn make_session_keys(send_key: u8, recv_key: u8, send_hp: u8, recv_hp: u8) -> Se
         AeadKey::from_array([recv_key; AEAD_KEY_LEN]),
         HeaderProtectionKey::from_array([send_hp; HEADER_PROTECTION_KEY_LEN]),
         HeaderProtectionKey::from_array([recv_hp; HEADER_PROTECTION_KEY_LEN]),
+        AeadNonce::from_array([send_key; AEAD_NONCE_LEN]),
+        AeadNonce::from_array([recv_key; AEAD_NONCE_LEN]),
     )
 }
 
-#[test]
-fn packet_engine_survives_loss_and_reorder() {
-    let base_time = UNIX_EPOCH + Duration::from_secs(1_000); // deterministic baseline
-    let mut link = SimLink::new(0xfeed_beef, 10, 3, Duration::from_millis(5));
+fn client_keys() -> SessionKeys {
+    make_session_keys(0x11, 0x22, 0x33, 0x44)
+}
 
-    let client_keys = make_session_keys(0x11, 0x22, 0x33, 0x44);
-    let server_keys = make_session_keys(0x22, 0x11, 0x44, 0x33);
+fn server_keys() -> SessionKeys {
+    make_session_keys(0x22, 0x11, 0x44, 0x33)
+}
 
-    let mut client = Endpoint::new(client_keys, 0xAAAA);
-    let mut server = Endpoint::new(server_keys, 0xBBBB);
+/// Client and server endpoints bound on `network`.
+fn endpoints(network: &SimNetwork, congestion: &CongestionConfig) -> (Endpoint, Endpoint) {
+    let client = Endpoint::new(network, client_keys(), 0xAAAA, congestion.clone());
+    let server = Endpoint::new(network, server_keys(), 0xBBBB, congestion.clone());
+    (client, server)
+}
 
-    let messages: Vec<Vec<u8>> = vec![
-        b"hello".to_vec(),
-        b"from".to_vec(),
-        b"the".to_vec(),
-        b"packet".to_vec(),
-        b"engine".to_vec(),
-    ];
-    for msg in &messages {
+/// Drive a client-to-server transfer until every message is acknowledged or `max_steps` pass.
+fn run_transfer(
+    network: &SimNetwork,
+    (mut client, server): (Endpoint, Endpoint),
+    messages: &[Vec<u8>],
+    datagrams: &[Vec<u8>],
+    max_steps: usize,
+) -> (Endpoint, Endpoint, usize) {
+    for msg in messages {
         client.enqueue_message(msg.clone());
     }
+    for datagram in datagrams {
+        client
+            .datagrams
+            .enqueue_reliable(datagram.clone())
+            .expect("enqueue datagram");
+    }
+    drive(network, client, server, max_steps)
+}
 
-    let mut now = base_time;
-    for _step in 0..200 {
-        client.tick(now, &mut link, 1);
-        server.tick(now, &mut link, 0);
-
-        link.deliver(now, |idx, bytes| {
-            if idx == 0 {
-                if let Some(ack_pkt) = client.on_receive(now, bytes) {
-                    client.outbound.push_back(ack_pkt);
-                }
-            } else if let Some(ack_pkt) = server.on_receive(now, bytes) {
-                server.outbound.push_back(ack_pkt);
-            }
-        });
+/// Step both endpoints until the client has nothing queued or outstanding.
+fn drive(
+    network: &SimNetwork,
+    mut client: Endpoint,
+    mut server: Endpoint,
+    max_steps: usize,
+) -> (Endpoint, Endpoint, usize) {
+    client.peer = Some(server.addr());
+    server.peer = Some(client.addr());
+    let clock = network.clock();
+
+    let mut steps = 0;
+    while steps < max_steps {
+        steps += 1;
+        let now = clock.now();
+        client.tick(now);
+        server.tick(now);
+        client.receive_all(now);
+        server.receive_all(now);
 
         if let Some(deadline) = client.loss.loss_time() {
             if deadline <= now {
                 let timed_out = client.loss.on_loss_timeout(now);
+                client.datagrams.on_packets_lost(&timed_out);
+                client.cc.on_packets_lost(&timed_out, now);
                 for info in timed_out {
                     if let Some(pkt) = client.outstanding.remove(&info.packet_number()) {
                         client.outbound.push_front(pkt);
@@ -282,17 +355,20 @@ fn packet_engine_survives_loss_and_reorder() {
             }
         }
 
-        if server.received.len() == messages.len()
-            && client.outbound.is_empty()
+        if client.outbound.is_empty()
+            && client.datagrams.is_empty()
             && client.loss.outstanding().next().is_none()
         {
             break;
         }
 
-        now += Duration::from_millis(5);
+        clock.advance(STEP);
     }
+    (client, server, steps)
+}
 
-    let mut received = server.received.clone();
+fn sorted_unique(received: &[Vec<u8>], messages: &[Vec<u8>]) -> Vec<Vec<u8>> {
+    let mut received = received.to_vec();
     received.sort_by_key(|msg| {
         messages
             .iter()
@@ -300,6 +376,195 @@ fn packet_engine_survives_loss_and_reorder() {
             .expect("known message")
     });
     received.dedup();
-    assert_eq!(received, messages);
+    received
+}
+
+#[test]
+fn packet_engine_survives_loss_and_reorder() {
+    let network = lossy_network(0xfeed_beef, 10, 3);
+    let messages: Vec<Vec<u8>> = vec![
+        b"hello".to_vec(),
+        b"from".to_vec(),
+        b"the".to_vec(),
+        b"packet".to_vec(),
+        b"engine".to_vec(),
+    ];
+
+    let pair = endpoints(&network, &CongestionConfig::default());
+    let (client, server, _) = run_transfer(&network, pair, &messages, &[], 200);
+
+    assert_eq!(sorted_unique(&server.received, &messages), messages);
     assert!(client.loss.outstanding().next().is_none());
 }
+
+#[test]
+fn packet_engine_runs_against_each_congestion_algorithm() {
+    let messages: Vec<Vec<u8>> = (0u8..10).map(|idx| vec![idx; 64]).collect();
+    for algorithm in [CongestionAlgorithm::Bbr, CongestionAlgorithm::Cubic] {
+        let config = CongestionConfig {
+            algorithm,
+            ..CongestionConfig::default()
+        };
+        let network = lossy_network(0xfeed_beef, 10, 3);
+        let pair = endpoints(&network, &config);
+        let (client, server, _) = run_transfer(&network, pair, &messages, &[], 400);
+
+        assert_eq!(
+            sorted_unique(&server.received, &messages),
+            messages,
+            "{algorithm:?}"
+        );
+        assert!(client.loss.outstanding().next().is_none());
+        assert!(client.cc.window() >= config.min_window);
+    }
+}
+
+#[test]
+fn packet_engine_retransmits_lost_reliable_datagram() {
+    let network = lossy_network(0xfeed_beef, 0, 3);
+    let pair = endpoints(&network, &CongestionConfig::default());
+    network.drop_next(pair.0.addr(), pair.1.addr(), 1);
+    let datagrams: Vec<Vec<u8>> = vec![b"agent".to_vec(), b"status".to_vec(), b"event".to_vec()];
+
+    let (client, server, _) = run_transfer(&network, pair, &[], &datagrams, 200);
+
+    assert_eq!(
+        sorted_unique(&server.received_datagrams, &datagrams),
+        datagrams
+    );
+    assert!(client.datagrams.retransmitted() >= 1);
+    assert_eq!(client.datagrams.reliable_in_flight(), 0);
+    assert!(client.loss.outstanding().next().is_none());
+}
+
+#[test]
+fn packet_engine_paces_large_bursts() {
+    let config = CongestionConfig {
+        algorithm: CongestionAlgorithm::Cubic,
+        ..CongestionConfig::default()
+    };
+    let messages: Vec<Vec<u8>> = (0u8..40).map(|idx| vec![idx; 1000]).collect();
+    // A FIFO path: pacing is about spacing, and reordering makes the cipher's strict replay
+    // check discard packets.
+    let network = lossy_network(0xfeed_beef, 0, 1);
+    let pair = endpoints(&network, &config);
+    let (client, server, _) = run_transfer(&network, pair, &messages, &[], 400);
+
+    assert_eq!(sorted_unique(&server.received, &messages), messages);
+    let first = client.sent_log[0].0;
+    let initial_burst: usize = client
+        .sent_log
+        .iter()
+        .filter(|(at, _)| *at == first)
+        .map(|(_, len)| len)
+        .sum();
+    // The window admits the whole queue at once; pacing holds the first step to one burst.
+    assert!(initial_burst <= client.pacer.burst_capacity());
+    assert!(initial_burst < config.initial_window / 2);
+
+    let mut send_times: Vec<SystemTime> = client.sent_log.iter().map(|(at, _)| *at).collect();
+    send_times.dedup();
+    // Unpaced, the window would release all but a handful of packets in the first step.
+    assert!(
+        send_times.len() >= 5,
+        "sends bunched into {} steps",
+        send_times.len()
+    );
+    assert_eq!(client.sent_log.len(), messages.len());
+}
+
+#[cfg(feature = "qlog")]
+#[test]
+fn packet_engine_emits_qlog_events() {
+    let logger = Arc::new(JsonLinesLogger::new(Vec::new()));
+    let network = lossy_network(0xfeed_beef, 0, 1);
+
+    let mut client = Endpoint::with_event_logger(
+        &network,
+        client_keys(),
+        0xAAAA,
+        CongestionConfig::default(),
+        logger.clone(),
+    );
+    let server = Endpoint::new(&network, server_keys(), 0xBBBB, CongestionConfig::default());
+    network.drop_next(client.addr(), server.addr(), 1);
+    for idx in 0u8..5 {
+        client.enqueue_message(vec![idx; 32]);
+    }
+    let (client, server, _) = drive(&network, client, server, 200);
+    assert_eq!(server.received.len(), 5);
+    drop(client);
+
+    let output = String::from_utf8(
+        Arc::try_unwrap(logger)
+            .expect("endpoints dropped")
+            .into_inner(),
+    )
+    .expect("utf8");
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines[0].contains(r#""qlog_version""#));
+
+    // Expected order: the dropped packet is sent, later packets are acked, the first one
+    // is declared lost, the window shrinks, and the retransmission goes out.
+    let mut cursor = 1;
+    for needle in [
+        r#""name":"transport:packet_sent","data":{"header":{"packet_number":0,"#,
+        r#""name":"transport:packet_received""#,
+        r#""name":"recovery:ack_received""#,
+        r#""name":"recovery:packet_lost","data":{"header":{"packet_number":0}"#,
+        r#""name":"recovery:metrics_updated""#,
+        r#""name":"transport:packet_sent","data":{"header":{"packet_number":5,"#,
+    ] {
+        let offset = lines[cursor..]
+            .iter()
+            .position(|line| line.contains(needle))
+            .unwrap_or_else(|| panic!("missing {needle} after line {cursor}:\n{output}"));
+        cursor += offset + 1;
+    }
+}
+
+#[test]
+fn sim_network_caps_link_bandwidth() {
+    let network = SimNetwork::new(7);
+    let transport = Transport::default();
+    let (a, b) = network.socket_pair();
+    let (a, b) = (
+        transport.bind_socket(a).expect("bind a"),
+        transport.bind_socket(b).expect("bind b"),
+    );
+    let (a_addr, b_addr) = (a.local_addr().unwrap(), b.local_addr().unwrap());
+    // 100 kB/s: each 1000-byte datagram holds the link for 10 ms before its 10 ms flight.
+    network.set_link(
+        a_addr,
+        b_addr,
+        LinkConfig {
+            latency: Latency::Fixed(Duration::from_millis(10)),
+            bandwidth: Some(100_000),
+            ..LinkConfig::default()
+        },
+    );
+    for idx in 0..10u8 {
+        a.send(&[idx; 1000], b_addr).expect("send");
+    }
+
+    let start = network.clock().now();
+    let mut buffer = b.acquire_buffer();
+    let mut arrivals = Vec::new();
+    while let Some(at) = network.next_delivery() {
+        network.clock().set(at);
+        while let Ok((len, from)) = b.receive(&mut buffer) {
+            assert_eq!((len, from), (1000, a_addr));
+            arrivals.push((at.duration_since(start).unwrap(), buffer.as_slice()[0]));
+        }
+    }
+    let expected: Vec<(Duration, u8)> = (0..10u8)
+        .map(|idx| (Duration::from_millis(20 + 10 * u64::from(idx)), idx))
+        .collect();
+    assert_eq!(arrivals, expected);
+
+    // The cap applies per direction; the reverse link is still the default perfect one.
+    b.send(&[0; 1000], a_addr).expect("send back");
+    assert_eq!(network.next_delivery(), Some(network.clock().now()));
+    let mut buffer = a.acquire_buffer();
+    assert_eq!(a.receive(&mut buffer).expect("receive").0, 1000);
+}
@@ -1,6 +1,9 @@
+use std::time::Instant;
+
 use mxp::transport::{
     EndpointRole, Frame, FrameType, PriorityClass, Scheduler, StreamId, StreamKind, StreamManager,
 };
+use mxp::{Message, MessageType};
 
 #[test]
 fn scheduler_respects_priority_and_flow_limits() {
@@ -61,3 +64,68 @@ fn scheduler_respects_priority_and_flow_limits() {
         .expect("remaining chunk");
     assert_eq!(chunk_low_rest.payload, b"ij");
 }
+
+/// Move every queued chunk from `sender` to `receiver`, returning the number of chunks.
+fn transfer(sender: &mut StreamManager, receiver: &mut StreamManager, id: StreamId) -> usize {
+    let mut chunks = 0;
+    while let Some(chunk) = sender.poll_send_chunk(id, 1200).unwrap() {
+        receiver
+            .ingest(id, chunk.offset, &chunk.payload, chunk.fin)
+            .unwrap();
+        chunks += 1;
+    }
+    chunks
+}
+
+#[test]
+fn message_sink_batches_small_messages_on_one_stream() {
+    const MESSAGES: u64 = 10_000;
+    let messages: Vec<Message> = (0..MESSAGES)
+        .map(|id| Message::with_ids(MessageType::Event, id, 1, b"tick".to_vec()))
+        .collect();
+
+    // Baseline: one unidirectional stream per message.
+    let mut sender = StreamManager::new(EndpointRole::Client);
+    let mut receiver = StreamManager::new(EndpointRole::Server);
+    let started = Instant::now();
+    let mut per_message_chunks = 0;
+    for message in &messages {
+        let id = sender.open_unidirectional().unwrap();
+        sender
+            .queue_send(id, &mxp::protocol::encode(message))
+            .unwrap();
+        sender.finish(id).unwrap();
+        per_message_chunks += transfer(&mut sender, &mut receiver, id);
+        let bytes = receiver.read(id, usize::MAX).unwrap();
+        mxp::protocol::decode(bytes.into()).unwrap();
+    }
+    let per_message_elapsed = started.elapsed();
+
+    // Batched: every message on a single long-lived stream.
+    let mut sender = StreamManager::new(EndpointRole::Client);
+    let mut receiver = StreamManager::new(EndpointRole::Server);
+    let started = Instant::now();
+    let sink = sender.open_message_sink().unwrap();
+    let mut batched = receiver.recv_batched(sink.stream_id()).unwrap();
+    for message in &messages {
+        sink.send(&mut sender, message).unwrap();
+    }
+    sink.finish(&mut sender).unwrap();
+    let batched_chunks = transfer(&mut sender, &mut receiver, sink.stream_id());
+    let received = batched.poll(&mut receiver).unwrap();
+    let batched_elapsed = started.elapsed();
+
+    assert!(batched.is_finished(&receiver).unwrap());
+    assert_eq!(received.len(), messages.len());
+    assert!(
+        received
+            .iter()
+            .zip(0..MESSAGES)
+            .all(|(message, id)| message.message_id() == id)
+    );
+    assert!(batched_chunks * 10 < per_message_chunks);
+    println!(
+        "10k messages: per-message streams {per_message_chunks} chunks in {per_message_elapsed:?}, \
+         sink {batched_chunks} chunks in {batched_elapsed:?}"
+    );
+}